@@ -0,0 +1,35 @@
+//! Episode throughput of `ParallelTrainer` on Flappy Bird as workers are added.
+//!
+//! cargo bench --bench parallel_training --features rl [-- <episodes>]
+
+use swarmonomicon::agents::rl::flappy::FlappyBirdEnv;
+use swarmonomicon::agents::rl::model::TrainingConfig;
+use swarmonomicon::agents::rl::ParallelTrainer;
+
+fn main() {
+    let episodes = std::env::args()
+        .skip(1)
+        .find_map(|arg| arg.parse().ok())
+        .unwrap_or(2000);
+    let cores = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+    let config = TrainingConfig { episodes, save_metrics: false, ..TrainingConfig::default() };
+
+    println!("{} episodes, {} cores available", episodes, cores);
+    println!("{:>8} {:>12} {:>14} {:>9}", "workers", "seconds", "episodes/sec", "speedup");
+
+    let mut baseline = None;
+    let mut workers = 1;
+    while workers <= cores.max(1) {
+        let report = ParallelTrainer::new(workers, config.clone(), |_| FlappyBirdEnv::default()).train();
+        let throughput = report.episodes_per_sec();
+        let baseline = *baseline.get_or_insert(throughput);
+        println!(
+            "{:>8} {:>12.2} {:>14.1} {:>8.2}x",
+            workers,
+            report.elapsed.as_secs_f64(),
+            throughput,
+            throughput / baseline,
+        );
+        workers *= 2;
+    }
+}
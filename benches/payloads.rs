@@ -0,0 +1,131 @@
+//! Serializing what goes over MQTT: todo payloads in and out of JSON, intake
+//! requests, building messages with full metadata, and fanning a burst of
+//! received messages out to subscribers.
+//!
+//! cargo bench --bench payloads
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use bytes::Bytes;
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use swarmonomicon::mcp::schema::{McpResponse, QueryTodosData};
+use swarmonomicon::mqtt::{MqttMessage, QoS};
+use swarmonomicon::types::{IntakeRequest, Message, MessageMetadata, TodoTask};
+
+const QUERY_TODOS: &str = include_str!("../src/mcp/fixtures/query_todos.json");
+
+const INTAKE: &str = r#"{
+    "version": 1,
+    "request_id": "bench-1",
+    "description": "Write a haiku about the deploy pipeline and commit it",
+    "priority": "high",
+    "project": "swarmonomicon",
+    "source": "node-red",
+    "metadata": {"ticket": "SWM-12"},
+    "idempotency_key": "swm-12-haiku"
+}"#;
+
+/// Counts allocations so the burst bench can report them next to its timings
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Messages in the burst, and subscribers each one is handed to
+const BURST: usize = 1000;
+const SUBSCRIBERS: usize = 4;
+
+fn sample_task() -> TodoTask {
+    let data = McpResponse::<QueryTodosData>::parse(QUERY_TODOS).unwrap().into_data().unwrap();
+    data.items.into_iter().next().map(TodoTask::from).expect("fixture has todos")
+}
+
+fn bench_todo_payloads(c: &mut Criterion) {
+    let task = sample_task();
+    let encoded = serde_json::to_vec(&task).unwrap();
+
+    let mut group = c.benchmark_group("todo_payload");
+    group.bench_function("serialize", |b| b.iter(|| serde_json::to_vec(black_box(&task)).unwrap()));
+    group.bench_function("deserialize", |b| {
+        b.iter(|| serde_json::from_slice::<TodoTask>(black_box(&encoded)).unwrap())
+    });
+    group.bench_function("parse_mcp_query", |b| {
+        b.iter(|| McpResponse::<QueryTodosData>::parse(black_box(QUERY_TODOS)).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_intake(c: &mut Criterion) {
+    let mut group = c.benchmark_group("intake_parse");
+    group.bench_function("json", |b| b.iter(|| IntakeRequest::parse(black_box(INTAKE), true).unwrap()));
+    group.bench_function("plain_text", |b| {
+        b.iter(|| IntakeRequest::parse(black_box("Show me the git history for the last week"), true).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_message_metadata(c: &mut Criterion) {
+    let context: HashMap<String, String> = (0..8)
+        .map(|i| (format!("key-{}", i), format!("value-{}", i)))
+        .collect();
+
+    c.bench_function("message_with_metadata", |b| {
+        b.iter(|| {
+            let metadata = MessageMetadata::new("greeter".to_string())
+                .with_personality(vec!["cheerful".to_string(), "concise".to_string()])
+                .with_transfer_target("git".to_string())
+                .with_context(context.clone());
+            Message::new(black_box("Show me the git history").to_string()).with_metadata(metadata)
+        })
+    });
+}
+
+/// What the MQTT event loop does with a burst of task publishes: wrap each
+/// one as an `MqttMessage` and give a copy to every matching subscriber
+fn receive_burst(topic: &str, payload: &Bytes) -> usize {
+    let mut delivered = 0;
+    for _ in 0..BURST {
+        let message = MqttMessage {
+            topic: topic.into(),
+            payload: payload.clone(),
+            qos: QoS::ExactlyOnce,
+            retain: false,
+            response_topic: None,
+            correlation_data: Some(Bytes::from_static(b"task-1")),
+            content_type: None,
+        };
+        for _ in 0..SUBSCRIBERS {
+            delivered += black_box(message.clone()).payload.len();
+        }
+    }
+    delivered
+}
+
+fn bench_mqtt_burst(c: &mut Criterion) {
+    let payload = Bytes::from(serde_json::to_vec(&sample_task()).unwrap());
+    let topic = "agent/git/todo/process";
+
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    receive_burst(topic, &payload);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    eprintln!("mqtt_burst: {} allocations for {} messages to {} subscribers", allocations, BURST, SUBSCRIBERS);
+
+    c.bench_function("mqtt_burst", |b| b.iter(|| receive_burst(black_box(topic), &payload)));
+}
+
+criterion_group!(benches, bench_todo_payloads, bench_intake, bench_message_metadata, bench_mqtt_burst);
+criterion_main!(benches);
@@ -0,0 +1,75 @@
+//! Q-learning updates once the table holds a million state-action entries,
+//! where hashing and cache misses dominate.
+//!
+//! cargo bench --bench qtable --features rl
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serde::{Deserialize, Serialize};
+use swarmonomicon::agents::rl::{Action, QLearningAgent, State};
+
+const STATES: u32 = 250_000;
+const ACTIONS: [Move; 4] = [Move::Up, Move::Down, Move::Left, Move::Right];
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+struct Cell(u32);
+
+impl State for Cell {
+    fn to_features(&self) -> Vec<f64> {
+        vec![self.0 as f64]
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+enum Move {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Action for Move {
+    fn to_index(&self) -> usize {
+        *self as usize
+    }
+
+    fn from_index(index: usize) -> Option<Self> {
+        ACTIONS.get(index).copied()
+    }
+}
+
+/// An agent with every (state, action) pair learned once: 1M entries
+fn filled() -> QLearningAgent<Cell, Move> {
+    let mut agent = QLearningAgent::new(0.1, 0.95, 0.1);
+    for state in 0..STATES {
+        for action in &ACTIONS {
+            agent.update(&Cell(state), action, 1.0, &Cell((state + 1) % STATES), &ACTIONS);
+        }
+    }
+    agent
+}
+
+fn bench_update(c: &mut Criterion) {
+    let mut agent = filled();
+    let mut step: u32 = 0;
+
+    let mut group = c.benchmark_group("qtable_1m");
+    group.bench_function("update_existing", |b| {
+        b.iter(|| {
+            // Stride through the table so consecutive updates miss the cache
+            step = step.wrapping_add(7919);
+            let state = Cell(step % STATES);
+            let next = Cell((step + 1) % STATES);
+            agent.update(black_box(&state), &ACTIONS[(step % 4) as usize], 0.5, &next, &ACTIONS)
+        })
+    });
+    group.bench_function("q_value", |b| {
+        b.iter(|| {
+            step = step.wrapping_add(7919);
+            agent.q_value(black_box(&Cell(step % STATES)), &ACTIONS[(step % 4) as usize])
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_update);
+criterion_main!(benches);
@@ -0,0 +1,92 @@
+//! Agent lookups through the shared registry lock as concurrent readers are
+//! added, with and without a writer registering agents at the same time.
+//!
+//! cargo bench --bench registry
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use async_trait::async_trait;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tokio::sync::RwLock;
+use swarmonomicon::agents::AgentRegistry;
+use swarmonomicon::Result;
+use swarmonomicon::error::SwarmError;
+use swarmonomicon::types::{Agent, AgentConfig, Message, State, Tool};
+
+const AGENTS: usize = 32;
+const LOOKUPS_PER_TASK: usize = 100;
+
+struct Noop;
+
+#[async_trait]
+impl Agent for Noop {
+    async fn process_message(&self, message: Message) -> Result<Message> {
+        Ok(message)
+    }
+    async fn transfer_to(&self, _target_agent: String, message: Message) -> Result<Message> {
+        Ok(message)
+    }
+    async fn call_tool(&self, _tool: &Tool, _params: HashMap<String, String>) -> Result<String> {
+        Ok(String::new())
+    }
+    async fn get_current_state(&self) -> Result<Option<State>> {
+        Ok(None)
+    }
+    async fn get_config(&self) -> Result<AgentConfig> {
+        Err(SwarmError::NotFound("no config".to_string()))
+    }
+}
+
+async fn registry() -> Arc<RwLock<AgentRegistry>> {
+    let mut registry = AgentRegistry::new();
+    for i in 0..AGENTS {
+        registry.register(format!("agent-{}", i), Box::new(Noop)).await.unwrap();
+    }
+    Arc::new(RwLock::new(registry))
+}
+
+/// `readers` tasks each looking up agents round-robin, plus a writer
+/// re-registering one agent in a loop when `writer` is set
+async fn contend(registry: Arc<RwLock<AgentRegistry>>, readers: usize, writer: bool) {
+    let mut handles = Vec::new();
+    for reader in 0..readers {
+        let registry = registry.clone();
+        handles.push(tokio::spawn(async move {
+            for i in 0..LOOKUPS_PER_TASK {
+                let name = format!("agent-{}", (reader + i) % AGENTS);
+                assert!(registry.read().await.get(&name).is_some());
+            }
+        }));
+    }
+    if writer {
+        let registry = registry.clone();
+        handles.push(tokio::spawn(async move {
+            for _ in 0..LOOKUPS_PER_TASK / 10 {
+                registry.write().await.register("agent-0".to_string(), Box::new(Noop)).await.unwrap();
+                tokio::task::yield_now().await;
+            }
+        }));
+    }
+    for handle in handles {
+        handle.await.unwrap();
+    }
+}
+
+fn bench_lookup(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let registry = runtime.block_on(registry());
+
+    let mut group = c.benchmark_group("registry_lookup");
+    for readers in [1, 4, 16, 64] {
+        group.bench_with_input(BenchmarkId::new("readers", readers), &readers, |b, &readers| {
+            b.to_async(&runtime).iter(|| contend(registry.clone(), readers, false));
+        });
+        group.bench_with_input(BenchmarkId::new("readers_with_writer", readers), &readers, |b, &readers| {
+            b.to_async(&runtime).iter(|| contend(registry.clone(), readers, true));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_lookup);
+criterion_main!(benches);
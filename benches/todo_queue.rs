@@ -0,0 +1,102 @@
+//! Queue operations on the todo list: ordering ready tasks for dispatch, and
+//! the add / fetch ready / claim / complete round trip against MongoDB.
+//!
+//! cargo bench --bench todo_queue
+//!
+//! The MongoDB benchmarks run only with `RTK_MONGO_URI` set. They use a
+//! scratch `swarm_bench_<id>` database, dropped when they finish.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use swarmonomicon::mcp::schema::{McpResponse, QueryTodosData};
+use swarmonomicon::types::{SchedulerConfig, TaskLease, TaskPriority, TaskScheduler, TaskStatus, TodoList, TodoTask};
+
+const QUERY_TODOS: &str = include_str!("../src/mcp/fixtures/query_todos.json");
+const NOW: i64 = 1_770_000_000;
+
+fn template() -> TodoTask {
+    let data = McpResponse::<QueryTodosData>::parse(QUERY_TODOS).unwrap().into_data().unwrap();
+    let mut task = data.items.into_iter().next().map(TodoTask::from).expect("fixture has todos");
+    task.status = TaskStatus::Pending;
+    task.completed_at = None;
+    task.target_agent = "bench".to_string();
+    task
+}
+
+/// `count` pending tasks spread over priorities and ages
+fn tasks(count: usize) -> Vec<TodoTask> {
+    let template = template();
+    (0..count)
+        .map(|i| {
+            let mut task = template.clone();
+            task.id = format!("bench-{}", i);
+            task.priority = TaskPriority::from_rank((i % 4) as u8 + 1);
+            task.created_at = NOW - (i as i64 * 37) % 7200;
+            task
+        })
+        .collect()
+}
+
+fn bench_order(c: &mut Criterion) {
+    let scheduler = TaskScheduler::new(SchedulerConfig::default());
+    let mut group = c.benchmark_group("scheduler_order");
+    for count in [10, 100, 1000] {
+        let ready = tasks(count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &ready, |b, ready| {
+            b.iter(|| scheduler.order(black_box(ready.clone()), NOW))
+        });
+    }
+    group.finish();
+}
+
+fn bench_mongo(c: &mut Criterion) {
+    let Ok(uri) = std::env::var("RTK_MONGO_URI") else {
+        eprintln!("RTK_MONGO_URI is not set, skipping the MongoDB queue benchmarks");
+        return;
+    };
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let db_name = format!("swarm_bench_{}", &uuid::Uuid::new_v4().simple().to_string()[..8]);
+    std::env::set_var("RTK_MONGO_DB", &db_name);
+    let list = runtime.block_on(TodoList::new()).expect("connect to MongoDB");
+    let lease = TaskLease::new("bench", 120);
+    let template = template();
+    let next = AtomicU64::new(0);
+    let new_task = || {
+        let mut task = template.clone();
+        task.id = format!("bench-{}", next.fetch_add(1, Ordering::Relaxed));
+        task
+    };
+
+    // Enough backlog that fetching ready tasks reads a realistic page
+    runtime.block_on(async {
+        for _ in 0..500 {
+            list.add_task(new_task()).await.unwrap();
+        }
+    });
+
+    let mut group = c.benchmark_group("todo_list");
+    group.bench_function("add_task", |b| {
+        b.to_async(&runtime).iter(|| async { list.add_task(new_task()).await.unwrap() })
+    });
+    group.bench_function("get_ready_tasks", |b| {
+        b.to_async(&runtime).iter(|| async { list.get_ready_tasks(Some("bench"), 50).await.unwrap() })
+    });
+    group.bench_function("claim_and_complete", |b| {
+        b.to_async(&runtime).iter(|| async {
+            let task = new_task();
+            let id = task.id.clone();
+            list.add_task(task).await.unwrap();
+            list.claim_task(&id, &lease).await.unwrap().expect("claimed");
+            list.mark_task_completed(&id).await.unwrap();
+        })
+    });
+    group.finish();
+
+    runtime.block_on(async {
+        let client = mongodb::Client::with_uri_str(&uri).await.unwrap();
+        client.database(&db_name).drop(None).await.unwrap();
+    });
+}
+
+criterion_group!(benches, bench_order, bench_mongo);
+criterion_main!(benches);
@@ -0,0 +1,360 @@
+//! Role-based access to agents and tools. API keys name a principal and its
+//! roles, roles grant permissions (`git:write`, `todo:admin`, `fleet:deploy`),
+//! and agents and tools require them. Checks apply to work done on behalf of a
+//! principal, i.e. API requests and MQTT commands; work the process starts
+//! itself, like schedules and retries, runs unscoped and is not checked.
+
+use std::collections::HashMap;
+use std::env;
+use std::future::Future;
+use std::sync::{Arc, OnceLock};
+use serde::{Deserialize, Serialize};
+use crate::agents::inbox::Gate;
+
+/// Principal for callers that present no key
+pub const ANONYMOUS: &str = "anonymous";
+/// HTTP header carrying an API key
+pub const API_KEY_HEADER: &str = "x-api-key";
+/// Field of an MQTT JSON payload carrying an API key
+pub const API_KEY_FIELD: &str = "api_key";
+/// What a key needs to approve, reject or edit operations held in an inbox
+pub const INBOX_DECIDE: &str = "inbox:decide";
+
+/// What agents require unless `ACCESS_AGENTS` says otherwise
+const DEFAULT_AGENT_PERMISSIONS: &[(&str, &str)] = &[
+    ("git", "git:write"),
+    ("project", "project:write"),
+];
+
+/// What tools, or commands of them, require unless `ACCESS_TOOLS` says otherwise
+const DEFAULT_TOOL_PERMISSIONS: &[(&str, &str)] = &[
+    ("git:branch", "git:write"),
+    ("git:stage", "git:write"),
+    ("git:commit", "git:write"),
+    ("git:merge", "git:write"),
+    ("git:push", "git:write"),
+    ("project", "project:write"),
+    ("shell", "shell:exec"),
+    ("balena:push", "fleet:deploy"),
+    ("balena:deploy", "fleet:deploy"),
+];
+
+static SHARED: OnceLock<Arc<AccessPolicy>> = OnceLock::new();
+
+tokio::task_local! {
+    static PRINCIPAL: Principal;
+}
+
+/// Whoever a request or command is being handled for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Principal {
+    pub name: String,
+    pub roles: Vec<String>,
+}
+
+impl Principal {
+    pub fn new(name: impl Into<String>, roles: &[&str]) -> Self {
+        Self { name: name.into(), roles: roles.iter().map(|role| role.to_string()).collect() }
+    }
+}
+
+/// Run `fut` on behalf of `principal`
+pub async fn with_principal<F: Future>(principal: Principal, fut: F) -> F::Output {
+    PRINCIPAL.scope(principal, fut).await
+}
+
+/// Principal the current request or command is handled for, if any
+pub fn current_principal() -> Option<Principal> {
+    PRINCIPAL.try_with(|principal| principal.clone()).ok()
+}
+
+/// A principal tried to use an agent or tool its roles don't allow
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("Access denied: {principal} needs {permission} for {target}")]
+pub struct AccessDenied {
+    pub principal: String,
+    pub permission: String,
+    pub target: String,
+}
+
+impl AccessDenied {
+    /// The denial behind `error`, if that is what stopped it
+    pub fn find(error: &anyhow::Error) -> Option<&Self> {
+        error.chain().find_map(|cause| cause.downcast_ref::<Self>())
+    }
+}
+
+/// Compares without returning early, so response timing doesn't leak how much of a token matched
+pub fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given.bytes().zip(expected.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+/// Whether `granted` covers `required`: an exact match, `*`, or a prefix like `git:*`
+fn covers(granted: &str, required: &str) -> bool {
+    granted == "*"
+        || granted == required
+        || granted.strip_suffix('*').map_or(false, |prefix| prefix.ends_with(':') && required.starts_with(prefix))
+}
+
+/// `name=value` entries separated by commas, as the `ACCESS_*` variables use
+fn parse_entries(spec: &str) -> impl Iterator<Item = (&str, &str)> {
+    spec.split(',')
+        .filter_map(|entry| entry.split_once('='))
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .filter(|(name, _)| !name.is_empty())
+}
+
+#[derive(Debug)]
+pub struct AccessPolicy {
+    /// Permissions each role grants
+    roles: HashMap<String, Vec<String>>,
+    /// Who each API key belongs to
+    keys: HashMap<String, Principal>,
+    /// Roles of callers that present no key
+    anonymous_roles: Vec<String>,
+    agents: HashMap<String, String>,
+    tools: Vec<(Gate, String)>,
+}
+
+impl Default for AccessPolicy {
+    fn default() -> Self {
+        let mut policy = Self {
+            roles: HashMap::new(),
+            keys: HashMap::new(),
+            anonymous_roles: Vec::new(),
+            agents: HashMap::new(),
+            tools: Vec::new(),
+        };
+        for (agent, permission) in DEFAULT_AGENT_PERMISSIONS {
+            policy = policy.with_agent(agent, permission);
+        }
+        for (spec, permission) in DEFAULT_TOOL_PERMISSIONS {
+            policy = policy.with_tool(spec, permission);
+        }
+        policy
+    }
+}
+
+impl AccessPolicy {
+    /// Let `role` do everything `permissions` cover
+    pub fn with_role(mut self, role: &str, permissions: &[&str]) -> Self {
+        self.roles.entry(role.to_string()).or_default().extend(permissions.iter().map(|p| p.to_string()));
+        self
+    }
+
+    /// Treat callers presenting `key` as `principal`
+    pub fn with_key(mut self, key: &str, principal: Principal) -> Self {
+        self.keys.insert(key.to_string(), principal);
+        self
+    }
+
+    /// Roles of callers that present no key; none by default
+    pub fn with_anonymous_roles(mut self, roles: &[&str]) -> Self {
+        self.anonymous_roles = roles.iter().map(|role| role.to_string()).collect();
+        self
+    }
+
+    /// Require `permission` to message `agent` or give it tasks. An empty
+    /// permission opens the agent to everyone.
+    pub fn with_agent(mut self, agent: &str, permission: &str) -> Self {
+        match permission {
+            "" => self.agents.remove(agent),
+            _ => self.agents.insert(agent.to_string(), permission.to_string()),
+        };
+        self
+    }
+
+    /// Require `permission` for calls to `spec`: a tool name, or `tool:command`
+    /// for calls whose `command` param starts with `command`. Replaces any
+    /// earlier requirement for the same spec; an empty permission drops it.
+    pub fn with_tool(mut self, spec: &str, permission: &str) -> Self {
+        let Some(gate) = Gate::parse(spec) else {
+            return self;
+        };
+        self.tools.retain(|(existing, _)| *existing != gate);
+        if !permission.is_empty() {
+            self.tools.push((gate, permission.to_string()));
+        }
+        self
+    }
+
+    /// Roles from `ACCESS_ROLES` (`reader=todo:read,ops=git:write fleet:*`),
+    /// keys from `ACCESS_KEYS` (`<key>=ci:reader,<key>=alice:ops reader`),
+    /// anonymous roles from `ACCESS_ANONYMOUS_ROLES`, and requirements on top
+    /// of the defaults from `ACCESS_AGENTS` (`haiku=haiku:use`) and
+    /// `ACCESS_TOOLS` (`todo:add=todo:write`)
+    pub fn from_env() -> Self {
+        let mut policy = Self::default();
+        for (role, permissions) in parse_entries(&env::var("ACCESS_ROLES").unwrap_or_default()) {
+            policy = policy.with_role(role, &permissions.split_whitespace().collect::<Vec<_>>());
+        }
+        // Keys may end in `=` padding, so split at the last one
+        for entry in env::var("ACCESS_KEYS").unwrap_or_default().split(',') {
+            let Some((key, who)) = entry.trim().rsplit_once('=') else {
+                continue;
+            };
+            let (name, roles) = who.split_once(':').unwrap_or((who, ""));
+            if !key.is_empty() && !name.is_empty() {
+                policy = policy.with_key(key, Principal::new(name, &roles.split_whitespace().collect::<Vec<_>>()));
+            }
+        }
+        let anonymous = env::var("ACCESS_ANONYMOUS_ROLES").unwrap_or_default();
+        policy = policy.with_anonymous_roles(&anonymous.split([',', ' ']).filter(|r| !r.is_empty()).collect::<Vec<_>>());
+        for (agent, permission) in parse_entries(&env::var("ACCESS_AGENTS").unwrap_or_default()) {
+            policy = policy.with_agent(agent, permission);
+        }
+        for (spec, permission) in parse_entries(&env::var("ACCESS_TOOLS").unwrap_or_default()) {
+            policy = policy.with_tool(spec, permission);
+        }
+        policy
+    }
+
+    /// The policy read from the environment, shared process-wide
+    pub fn shared() -> Arc<Self> {
+        SHARED.get_or_init(|| Arc::new(Self::from_env())).clone()
+    }
+
+    /// Whether any keys or roles are configured; without them nothing is checked
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty() || !self.roles.is_empty()
+    }
+
+    /// Who presents `key`: the anonymous principal for no key, `None` for an unknown one
+    pub fn resolve(&self, key: Option<&str>) -> Option<Principal> {
+        match key {
+            Some(key) => self.keys.get(key).cloned(),
+            None => Some(Principal { name: ANONYMOUS.to_string(), roles: self.anonymous_roles.clone() }),
+        }
+    }
+
+    /// Whether `principal`'s roles grant `permission`
+    pub fn allows(&self, principal: &Principal, permission: &str) -> bool {
+        principal.roles.iter()
+            .filter_map(|role| self.roles.get(role))
+            .flatten()
+            .any(|granted| covers(granted, permission))
+    }
+
+    fn check(&self, permission: &str, target: impl FnOnce() -> String) -> Result<(), AccessDenied> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let Some(principal) = current_principal() else {
+            return Ok(());
+        };
+        if self.allows(&principal, permission) {
+            return Ok(());
+        }
+        tracing::warn!(principal = %principal.name, permission, "Access denied");
+        Err(AccessDenied { principal: principal.name, permission: permission.to_string(), target: target() })
+    }
+
+    /// Refuse to let the current principal message or task `agent` without its permission
+    pub fn check_agent(&self, agent: &str) -> Result<(), AccessDenied> {
+        match self.agents.get(agent) {
+            Some(permission) => self.check(permission, || format!("agent {}", agent)),
+            None => Ok(()),
+        }
+    }
+
+    /// Refuse a call to `tool` unless the current principal holds every permission it requires
+    pub fn check_tool(&self, tool: &str, params: &HashMap<String, String>) -> Result<(), AccessDenied> {
+        for (gate, permission) in &self.tools {
+            if gate.matches(tool, params) {
+                self.check(permission, || match params.get("command") {
+                    Some(command) => format!("tool {} {}", tool, command),
+                    None => format!("tool {}", tool),
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> AccessPolicy {
+        AccessPolicy::default()
+            .with_role("reader", &["todo:read"])
+            .with_role("ops", &["git:*", "project:write"])
+            .with_key("k-ci", Principal::new("ci", &["reader"]))
+            .with_key("k-alice", Principal::new("alice", &["ops"]))
+    }
+
+    fn command(command: &str) -> HashMap<String, String> {
+        HashMap::from([("command".to_string(), command.to_string())])
+    }
+
+    #[test]
+    fn test_covers() {
+        assert!(covers("git:write", "git:write"));
+        assert!(covers("git:*", "git:write"));
+        assert!(covers("*", "fleet:deploy"));
+        assert!(!covers("git:*", "gitlab:write"));
+        assert!(!covers("git:read", "git:write"));
+    }
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("s3cret", "s3cret"));
+        assert!(!tokens_match("s3cre", "s3cret"));
+        assert!(!tokens_match("s3creT", "s3cret"));
+        assert!(!tokens_match("", "s3cret"));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let policy = policy().with_anonymous_roles(&["reader"]);
+        assert_eq!(policy.resolve(Some("k-alice")).unwrap().name, "alice");
+        assert!(policy.resolve(Some("stolen")).is_none());
+        let anonymous = policy.resolve(None).unwrap();
+        assert_eq!(anonymous.name, ANONYMOUS);
+        assert!(policy.allows(&anonymous, "todo:read"));
+    }
+
+    #[tokio::test]
+    async fn test_checks_apply_to_scoped_principals() {
+        let policy = policy();
+        let ci = policy.resolve(Some("k-ci")).unwrap();
+        let alice = policy.resolve(Some("k-alice")).unwrap();
+
+        // Unscoped work is the process's own and isn't checked
+        assert!(policy.check_agent("git").is_ok());
+
+        with_principal(ci, async {
+            let denied = policy.check_agent("git").unwrap_err();
+            assert_eq!(denied.permission, "git:write");
+            assert!(policy.check_tool("git", &command("commit")).is_err());
+            assert!(policy.check_tool("git", &command("diff")).is_ok());
+            assert!(policy.check_agent("haiku").is_ok());
+        }).await;
+
+        with_principal(alice, async {
+            assert!(policy.check_agent("git").is_ok());
+            assert!(policy.check_tool("git", &command("push origin main")).is_ok());
+            assert!(policy.check_tool("balena", &command("push fleet")).is_err());
+            assert!(policy.check_tool("shell", &HashMap::new()).is_err());
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn test_disabled_policy_allows_everything() {
+        let policy = AccessPolicy::default();
+        assert!(!policy.is_enabled());
+        with_principal(Principal::new(ANONYMOUS, &[]), async {
+            assert!(policy.check_agent("git").is_ok());
+            assert!(policy.check_tool("shell", &HashMap::new()).is_ok());
+        }).await;
+    }
+
+    #[test]
+    fn test_overrides() {
+        let policy = policy().with_agent("git", "").with_tool("todo:add", "todo:write").with_tool("shell", "");
+        assert!(!policy.agents.contains_key("git"));
+        assert!(policy.tools.iter().any(|(gate, permission)| gate.matches("todo", &command("add")) && permission == "todo:write"));
+        assert!(!policy.tools.iter().any(|(gate, _)| gate.matches("shell", &HashMap::new())));
+    }
+}
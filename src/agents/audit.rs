@@ -0,0 +1,275 @@
+//! A record of every agent invocation: who asked, which agent answered, a hash
+//! of what it was asked, the tools it called, and how it went. Entries go to
+//! the `audit_log` collection, for working out after the fact why a todo was
+//! created or a branch merged.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use mongodb::options::{FindOptions, IndexOptions};
+use mongodb::{Collection, IndexModel};
+use crate::clients::Clients;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Entries returned by a query when no limit is given
+pub const DEFAULT_AUDIT_LIMIT: i64 = 200;
+/// Most entries a single query returns
+pub const MAX_AUDIT_LIMIT: i64 = 5000;
+/// Actor recorded for work the process started itself
+pub const SYSTEM_ACTOR: &str = "system";
+
+static SHARED: OnceLock<AgentAuditLog> = OnceLock::new();
+
+tokio::task_local! {
+    static TOOLS_CALLED: Arc<Mutex<Vec<String>>>;
+}
+
+/// Note a tool call made by the agent invocation being audited, if any
+pub fn record_tool(tool: &str, params: &HashMap<String, String>) {
+    let call = match params.get("command") {
+        Some(command) => format!("{} {}", tool, command),
+        None => tool.to_string(),
+    };
+    let _ = TOOLS_CALLED.try_with(|tools| tools.lock().unwrap().push(call));
+}
+
+/// Run `fut`, collecting the tool calls made while it runs
+pub async fn tracking_tools<F: Future>(fut: F) -> (F::Output, Vec<String>) {
+    let tools = Arc::new(Mutex::new(Vec::new()));
+    let output = TOOLS_CALLED.scope(tools.clone(), fut).await;
+    let tools = std::mem::take(&mut *tools.lock().unwrap());
+    (output, tools)
+}
+
+/// Hex SHA-256 of an agent's input, so entries can be matched up without storing it
+pub fn input_hash(input: &str) -> String {
+    format!("{:x}", Sha256::digest(input.as_bytes()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditOutcome {
+    Success,
+    Failure,
+}
+
+/// One agent invocation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub agent: String,
+    /// Principal the invocation ran for, or `system`
+    pub actor: String,
+    pub input_hash: String,
+    /// Tools called, with their `command` param when they took one, e.g. `git merge`
+    pub tools: Vec<String>,
+    pub outcome: AuditOutcome,
+    pub error: Option<String>,
+    pub duration_ms: u64,
+    pub task_id: Option<String>,
+    pub correlation_id: Option<String>,
+    #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub timestamp: DateTime<Utc>,
+}
+
+impl AuditEntry {
+    /// An entry for `agent` handling `input` on behalf of the current principal
+    pub fn new(agent: impl Into<String>, input: &str, outcome: Result<(), &anyhow::Error>, elapsed: Duration) -> Self {
+        Self {
+            agent: agent.into(),
+            actor: crate::access::current_principal().map_or_else(|| SYSTEM_ACTOR.to_string(), |p| p.name),
+            input_hash: input_hash(input),
+            tools: Vec::new(),
+            outcome: if outcome.is_ok() { AuditOutcome::Success } else { AuditOutcome::Failure },
+            error: outcome.err().map(|e| e.to_string()),
+            duration_ms: elapsed.as_millis() as u64,
+            task_id: None,
+            correlation_id: crate::telemetry::current_correlation_id(),
+            timestamp: Utc::now(),
+        }
+    }
+
+    pub fn with_tools(mut self, tools: Vec<String>) -> Self {
+        self.tools = tools;
+        self
+    }
+
+    pub fn with_task(mut self, task_id: Option<&str>) -> Self {
+        self.task_id = task_id.map(str::to_string);
+        self
+    }
+}
+
+/// Filters for [`AgentAuditLog::query`]; every field is optional
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct AuditQuery {
+    pub agent: Option<String>,
+    pub actor: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+impl AuditQuery {
+    fn filter(&self) -> Document {
+        let mut filter = Document::new();
+        if let Some(agent) = &self.agent {
+            filter.insert("agent", agent.as_str());
+        }
+        if let Some(actor) = &self.actor {
+            filter.insert("actor", actor.as_str());
+        }
+        let mut range = Document::new();
+        if let Some(since) = self.since {
+            range.insert("$gte", mongodb::bson::DateTime::from_chrono(since));
+        }
+        if let Some(until) = self.until {
+            range.insert("$lte", mongodb::bson::DateTime::from_chrono(until));
+        }
+        if !range.is_empty() {
+            filter.insert("timestamp", range);
+        }
+        filter
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_AUDIT_LIMIT).clamp(1, MAX_AUDIT_LIMIT)
+    }
+}
+
+/// Keeps [`AuditEntry`]s in the `audit_log` collection. Writes are
+/// best-effort: a failed one is logged and never fails the invocation.
+#[derive(Clone)]
+pub struct AgentAuditLog {
+    collection: Collection<AuditEntry>,
+}
+
+impl AgentAuditLog {
+    pub fn new(collection: Collection<AuditEntry>) -> Self {
+        Self { collection }
+    }
+
+    /// Connects using `RTK_MONGO_URI`/`RTK_MONGO_DB` and expires entries after
+    /// `AUDIT_RETENTION_DAYS` (default 90; 0 keeps them forever)
+    pub async fn from_env() -> Result<Self> {
+        let uri = std::env::var("RTK_MONGO_URI").map_err(|_| anyhow!("RTK_MONGO_URI is not set"))?;
+        let db_name = std::env::var("RTK_MONGO_DB").unwrap_or_else(|_| "swarmonomicon".to_string());
+        let client = Clients::shared().mongo(&uri)?;
+        let log = Self::new(client.database(&db_name).collection("audit_log"));
+
+        let retention_days: u64 = std::env::var("AUDIT_RETENTION_DAYS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(90);
+        if let Err(e) = log.ensure_indexes(retention_days).await {
+            tracing::warn!("Failed to create audit_log indexes: {}", e);
+        }
+        Ok(log)
+    }
+
+    async fn ensure_indexes(&self, retention_days: u64) -> Result<()> {
+        for keys in [doc! { "agent": 1, "timestamp": 1 }, doc! { "actor": 1, "timestamp": 1 }] {
+            self.collection.create_index(IndexModel::builder().keys(keys).build(), None).await?;
+        }
+        if retention_days > 0 {
+            let expiry = IndexModel::builder()
+                .keys(doc! { "timestamp": 1 })
+                .options(IndexOptions::builder()
+                    .expire_after(Duration::from_secs(retention_days * 24 * 60 * 60))
+                    .build())
+                .build();
+            self.collection.create_index(expiry, None).await?;
+        }
+        Ok(())
+    }
+
+    /// Make `log` the one every [`AgentWrapper`](super::AgentWrapper) records
+    /// to. Later calls keep the first log.
+    pub fn init_shared(log: Self) -> Self {
+        SHARED.get_or_init(|| log).clone()
+    }
+
+    /// The log agents record to, once one has been set up
+    pub fn shared() -> Option<Self> {
+        SHARED.get().cloned()
+    }
+
+    pub async fn record(&self, entry: &AuditEntry) {
+        if let Err(e) = self.collection.insert_one(entry, None).await {
+            tracing::warn!("Failed to write audit entry for agent {}: {}", entry.agent, e);
+        }
+    }
+
+    /// Matching entries, oldest first. With more matches than the limit, the
+    /// most recent ones are returned.
+    pub async fn query(&self, query: &AuditQuery) -> Result<Vec<AuditEntry>> {
+        let options = FindOptions::builder()
+            .sort(doc! { "timestamp": -1 })
+            .limit(query.limit())
+            .build();
+        let mut entries: Vec<AuditEntry> = self.collection.find(query.filter(), options).await?.try_collect().await?;
+        entries.reverse();
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_tracking_tools() {
+        let command = HashMap::from([("command".to_string(), "merge".to_string())]);
+        let ((), tools) = tracking_tools(async {
+            record_tool("git", &command);
+            record_tool("notify", &HashMap::new());
+        }).await;
+        assert_eq!(tools, vec!["git merge", "notify"]);
+
+        // Outside an audited invocation there is nothing to record into
+        record_tool("git", &command);
+    }
+
+    #[tokio::test]
+    async fn test_entry_records_actor() {
+        use crate::access::{with_principal, Principal};
+
+        let failure = anyhow!("boom");
+        let entry = AuditEntry::new("git", "merge it", Err(&failure), Duration::from_millis(42));
+        assert_eq!(entry.actor, SYSTEM_ACTOR);
+        assert_eq!((entry.outcome, entry.error.as_deref(), entry.duration_ms), (AuditOutcome::Failure, Some("boom"), 42));
+        assert_eq!(entry.input_hash, input_hash("merge it"));
+        assert_ne!(entry.input_hash, input_hash("merge that"));
+
+        let entry = with_principal(Principal::new("ci", &[]), async {
+            AuditEntry::new("git", "merge it", Ok(()), Duration::ZERO)
+        }).await;
+        assert_eq!((entry.actor.as_str(), entry.outcome), ("ci", AuditOutcome::Success));
+    }
+
+    #[test]
+    fn test_query_filter() {
+        let since = "2026-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let query = AuditQuery {
+            agent: Some("git".to_string()),
+            actor: Some("ci".to_string()),
+            since: Some(since),
+            ..Default::default()
+        };
+        let filter = query.filter();
+        assert_eq!(filter.get_str("agent").unwrap(), "git");
+        assert_eq!(filter.get_str("actor").unwrap(), "ci");
+        let range = filter.get_document("timestamp").unwrap();
+        assert_eq!(range.get_datetime("$gte").unwrap().to_chrono(), since);
+        assert!(range.get("$lte").is_none());
+
+        assert_eq!(AuditQuery::default().limit(), DEFAULT_AUDIT_LIMIT);
+        assert_eq!(AuditQuery { limit: Some(0), ..Default::default() }.limit(), 1);
+        assert!(AuditQuery::default().filter().is_empty());
+    }
+}
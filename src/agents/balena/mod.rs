@@ -62,18 +62,7 @@ impl BalenaWrapperAgent {
                 "deployment_expert".to_string(),
             ]);
 
-        Message {
-            content,
-            metadata,
-            parameters: {
-                let mut params = HashMap::new();
-                params.insert("style".to_string(), "fleet_commander".to_string());
-                params.insert("domain".to_string(), "iot_operations".to_string());
-                params
-            },
-            tool_calls: None,
-            confidence: Some(1.0),
-        }
+        Message::new(content).with_metadata(metadata)
     }
 
     fn format_fleet_response(&self, content: String) -> Message {
@@ -102,6 +91,14 @@ impl BalenaWrapperAgent {
     }
 
     async fn execute_balena_command(&self, args: &[&str]) -> Result<String> {
+        // Deploys need `fleet:deploy`, then wait for approval when `APPROVAL_REQUIRED` gates `balena:push`
+        let command = HashMap::from([("command".to_string(), args.join(" "))]);
+        crate::access::AccessPolicy::shared().check_tool("balena", &command)?;
+        if crate::tools::dry_run::is_active() {
+            return Ok(crate::tools::dry_run::plan([format!("run `balena {}`", args.join(" "))]));
+        }
+        crate::agents::ApprovalInbox::shared().approve_call("balena", command).await?;
+
         let output = Command::new("balena")
             .args(args)
             .output()?;
@@ -117,28 +114,28 @@ impl BalenaWrapperAgent {
 
 #[async_trait]
 impl Agent for BalenaWrapperAgent {
-    async fn process_message(&self, message: Message) -> Result<Message> {
+    async fn process_message(&self, message: Message) -> crate::Result<Message> {
         // Use the existing logic to handle commands...
         // ...
         Ok(self.create_response("Response from BalenaWrapperAgent".to_string()))
     }
 
-    async fn transfer_to(&self, target_agent: String, message: Message) -> Result<Message> {
+    async fn transfer_to(&self, target_agent: String, message: Message) -> crate::Result<Message> {
         Ok(message)
     }
 
-    async fn call_tool(&self, _tool_call: ToolCall) -> Result<Message> {
+    async fn call_tool(&self, _tool_call: ToolCall) -> crate::Result<Message> {
         Ok(self.create_response(
             "Direct tool interface not available. Please use fleet command protocols."
                 .to_string(),
         ))
     }
 
-    async fn get_current_state(&self) -> Result<Option<crate::types::State>> {
+    async fn get_current_state(&self) -> crate::Result<Option<crate::types::State>> {
         Ok(self.state_manager.get_current_state().cloned())
     }
 
-    async fn get_config(&self) -> Result<AgentConfig> {
+    async fn get_config(&self) -> crate::Result<AgentConfig> {
         Ok(self.config.clone())
     }
 }
@@ -38,23 +38,23 @@ struct DummyAgent {}
 
 #[async_trait]
 impl Agent for DummyAgent {
-    async fn process_message(&self, message: Message) -> Result<Message> {
+    async fn process_message(&self, message: Message) -> crate::Result<Message> {
         Ok(Message::new(format!("Browser received: {}", message.content)))
     }
 
-    async fn transfer_to(&self, target_agent: String, message: Message) -> Result<Message> {
+    async fn transfer_to(&self, target_agent: String, message: Message) -> crate::Result<Message> {
         Ok(message)
     }
 
-    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
+    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> crate::Result<String> {
         Ok(format!("Called tool {} with params {:?}", tool.name, params))
     }
 
-    async fn get_current_state(&self) -> Result<Option<State>> {
+    async fn get_current_state(&self) -> crate::Result<Option<State>> {
         Ok(None)
     }
 
-    async fn get_config(&self) -> Result<AgentConfig> {
+    async fn get_config(&self) -> crate::Result<AgentConfig> {
         Ok(AgentConfig {
             name: "browser".to_string(),
             public_description: "Browser automation agent".to_string(),
@@ -69,23 +69,23 @@ impl Agent for DummyAgent {
 
 #[async_trait]
 impl Agent for BrowserAgentWrapper {
-    async fn process_message(&self, message: Message) -> Result<Message> {
+    async fn process_message(&self, message: Message) -> crate::Result<Message> {
         self.inner.process_message(message).await
     }
 
-    async fn transfer_to(&self, target_agent: String, message: Message) -> Result<Message> {
+    async fn transfer_to(&self, target_agent: String, message: Message) -> crate::Result<Message> {
         self.inner.transfer_to(target_agent, message).await
     }
 
-    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
+    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> crate::Result<String> {
         self.inner.call_tool(tool, params).await
     }
 
-    async fn get_current_state(&self) -> Result<Option<State>> {
+    async fn get_current_state(&self) -> crate::Result<Option<State>> {
         self.inner.get_current_state().await
     }
 
-    async fn get_config(&self) -> Result<AgentConfig> {
+    async fn get_config(&self) -> crate::Result<AgentConfig> {
         Ok(self.agent_config.clone())
     }
 }
@@ -0,0 +1,208 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use crate::agents::AgentRegistry;
+use crate::mqtt::{MqttService, QoS};
+use crate::Result;
+
+/// Retained manifests are published under `swarm/nodes/<id>`
+pub const NODES_TOPIC: &str = "swarm/nodes/+";
+
+pub fn node_topic(node_id: &str) -> String {
+    format!("swarm/nodes/{}", node_id)
+}
+
+/// What a node announces about itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeManifest {
+    pub node_id: String,
+    /// Agents registered on the node, sorted
+    pub agents: Vec<String>,
+    /// Cargo features the node was built with, e.g. `browser-agent`
+    pub capabilities: Vec<String>,
+    pub version: String,
+    pub started_at: DateTime<Utc>,
+    /// Refreshed on every heartbeat; staleness is measured from here
+    pub published_at: DateTime<Utc>,
+}
+
+impl NodeManifest {
+    pub fn new(node_id: impl Into<String>, mut agents: Vec<String>) -> Self {
+        agents.sort();
+        let now = Utc::now();
+        Self {
+            node_id: node_id.into(),
+            agents,
+            capabilities: compiled_capabilities(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at: now,
+            published_at: now,
+        }
+    }
+}
+
+/// Feature flags compiled into this build
+pub fn compiled_capabilities() -> Vec<String> {
+    let features = [
+        ("greeter-agent", cfg!(feature = "greeter-agent")),
+        ("haiku-agent", cfg!(feature = "haiku-agent")),
+        ("git-agent", cfg!(feature = "git-agent")),
+        ("project-agent", cfg!(feature = "project-agent")),
+        ("browser-agent", cfg!(feature = "browser-agent")),
+        ("eventghost-agent", cfg!(feature = "eventghost-agent")),
+        ("rl", cfg!(feature = "rl")),
+        ("yolo", cfg!(feature = "yolo")),
+        ("embedded-state", cfg!(feature = "embedded-state")),
+    ];
+    features.iter().filter(|(_, enabled)| *enabled).map(|(name, _)| name.to_string()).collect()
+}
+
+/// A node as seen by a [`SwarmDirectory`]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeStatus {
+    #[serde(flatten)]
+    pub manifest: NodeManifest,
+    /// When this process last received the node's manifest
+    pub last_seen: DateTime<Utc>,
+    /// No heartbeat within the directory's `stale_after`
+    pub stale: bool,
+}
+
+/// Live view of every node announcing itself on the broker
+#[derive(Clone)]
+pub struct SwarmDirectory {
+    nodes: Arc<RwLock<HashMap<String, (NodeManifest, DateTime<Utc>)>>>,
+    stale_after: Duration,
+}
+
+impl SwarmDirectory {
+    pub fn new(stale_after: Duration) -> Self {
+        Self { nodes: Arc::default(), stale_after }
+    }
+
+    /// Follow `swarm/nodes/+`. Retained manifests arrive right away, and an
+    /// empty retained message removes a node that shut down cleanly.
+    pub async fn spawn(&self, client: &MqttService) -> Result<JoinHandle<()>> {
+        let mut manifests = client.subscribe(NODES_TOPIC, QoS::AtLeastOnce).await?;
+        let directory = self.clone();
+        Ok(tokio::spawn(async move {
+            while let Some(message) = manifests.recv().await {
+                if message.payload.is_empty() {
+                    if let Some(node_id) = message.topic.rsplit('/').next() {
+                        directory.remove(node_id).await;
+                    }
+                    continue;
+                }
+                match message.json::<NodeManifest>() {
+                    Ok(manifest) => directory.record(manifest, Utc::now()).await,
+                    Err(e) => tracing::warn!("Ignoring malformed node manifest on {}: {}", message.topic, e),
+                }
+            }
+        }))
+    }
+
+    pub async fn record(&self, manifest: NodeManifest, seen_at: DateTime<Utc>) {
+        self.nodes.write().await.insert(manifest.node_id.clone(), (manifest, seen_at));
+    }
+
+    pub async fn remove(&self, node_id: &str) {
+        if self.nodes.write().await.remove(node_id).is_some() {
+            tracing::info!("Node {} left the swarm", node_id);
+        }
+    }
+
+    /// Every known node sorted by id, with staleness as of `now`
+    pub async fn nodes_at(&self, now: DateTime<Utc>) -> Vec<NodeStatus> {
+        let stale_after = chrono::Duration::from_std(self.stale_after).unwrap_or_else(|_| chrono::Duration::days(365));
+        let mut nodes: Vec<NodeStatus> = self.nodes.read().await.values()
+            .map(|(manifest, last_seen)| NodeStatus {
+                // Retained manifests of dead nodes arrive fresh, so go by when they were published
+                stale: now - manifest.published_at > stale_after,
+                manifest: manifest.clone(),
+                last_seen: *last_seen,
+            })
+            .collect();
+        nodes.sort_by(|a, b| a.manifest.node_id.cmp(&b.manifest.node_id));
+        nodes
+    }
+
+    pub async fn nodes(&self) -> Vec<NodeStatus> {
+        self.nodes_at(Utc::now()).await
+    }
+
+    /// Live nodes hosting `agent`
+    pub async fn nodes_with_agent(&self, agent: &str) -> Vec<String> {
+        self.nodes().await.into_iter()
+            .filter(|node| !node.stale && node.manifest.agents.iter().any(|a| a == agent))
+            .map(|node| node.manifest.node_id)
+            .collect()
+    }
+}
+
+/// Publish this node's retained manifest now and every `heartbeat`, listing
+/// the agents registered at the time
+pub fn spawn_announcer(
+    client: MqttService,
+    node_id: String,
+    registry: Arc<RwLock<AgentRegistry>>,
+    heartbeat: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let started_at = Utc::now();
+        let mut interval = tokio::time::interval(heartbeat);
+        loop {
+            interval.tick().await;
+            let agents = registry.read().await.names();
+            let manifest = NodeManifest { started_at, ..NodeManifest::new(node_id.clone(), agents) };
+            let payload = match serde_json::to_vec(&manifest) {
+                Ok(payload) => payload,
+                Err(e) => {
+                    tracing::error!("Failed to encode node manifest: {}", e);
+                    continue;
+                }
+            };
+            if let Err(e) = client.publish(node_topic(&node_id), QoS::AtLeastOnce, true, payload).await {
+                tracing::warn!("Failed to announce node {}: {}", node_id, e);
+            }
+        }
+    })
+}
+
+/// Clear this node's retained manifest ahead of a clean shutdown
+pub async fn withdraw(client: &MqttService, node_id: &str) -> Result<()> {
+    client.publish(node_topic(node_id), QoS::AtLeastOnce, true, Vec::new()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_directory_marks_stale_nodes() {
+        let directory = SwarmDirectory::new(Duration::from_secs(90));
+        let now = Utc::now();
+        let mut studio = NodeManifest::new("studio", vec!["haiku".to_string(), "greeter".to_string()]);
+        studio.published_at = now;
+        let mut pi = NodeManifest::new("pi", vec!["browser".to_string()]);
+        pi.published_at = now - chrono::Duration::minutes(5);
+
+        directory.record(studio, now).await;
+        directory.record(pi, now - chrono::Duration::minutes(5)).await;
+
+        let nodes = directory.nodes_at(now).await;
+        assert_eq!(nodes.iter().map(|n| n.manifest.node_id.as_str()).collect::<Vec<_>>(), ["pi", "studio"]);
+        assert!(nodes[0].stale);
+        assert!(!nodes[1].stale);
+        assert_eq!(nodes[1].manifest.agents, ["greeter", "haiku"]);
+
+        assert_eq!(directory.nodes_with_agent("haiku").await, ["studio"]);
+        assert!(directory.nodes_with_agent("browser").await.is_empty());
+
+        directory.remove("pi").await;
+        assert_eq!(directory.nodes_at(now).await.len(), 1);
+    }
+}
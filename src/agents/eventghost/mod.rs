@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use serde_json::json;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use crate::events::Event;
+use crate::mqtt::{MqttService, QoS};
+use crate::tools::ToolRegistry;
+use crate::types::{Agent, AgentConfig, AgentStateManager, Message, MessageMetadata, State, Tool, INTAKE_SCHEMA_VERSION};
+
+pub mod rules;
+
+pub use rules::{EventRule, EventRules, GhostEvent, RuleAction, EVENT_TOPIC_PREFIX};
+
+/// Intake topic todos created from events are published to
+pub const INTAKE_TOPIC: &str = "mcp/eventghost";
+/// Swarm events are republished as `eventghost/swarm/<kind>` for EventGhost to react to
+pub const SWARM_TOPIC_PREFIX: &str = "eventghost/swarm";
+
+/// Bridges EventGhost automation events into the swarm. Each event runs the
+/// actions of every matching [`EventRule`]; messages sent to the agent are
+/// treated as events in the `Prefix.Suffix [payload]` form.
+pub struct EventGhostAgent {
+    config: AgentConfig,
+    rules: EventRules,
+    tools: Option<Arc<ToolRegistry>>,
+    mqtt: Option<MqttService>,
+    state_manager: Arc<RwLock<AgentStateManager>>,
+}
+
+impl EventGhostAgent {
+    pub fn new(config: AgentConfig) -> Self {
+        Self {
+            config,
+            rules: EventRules::default(),
+            tools: None,
+            mqtt: None,
+            state_manager: Arc::new(RwLock::new(AgentStateManager::new(None))),
+        }
+    }
+
+    /// An agent with the rules named by `EVENTGHOST_RULES`
+    pub fn from_env(config: AgentConfig) -> Result<Self> {
+        Ok(Self::new(config).with_rules(EventRules::from_env()?))
+    }
+
+    pub fn with_rules(mut self, rules: EventRules) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    /// Registry `run_tool` actions call into
+    pub fn with_tools(mut self, tools: Arc<ToolRegistry>) -> Self {
+        self.tools = Some(tools);
+        self
+    }
+
+    /// Broker `create_todo` actions publish intake requests to
+    pub fn with_mqtt(mut self, client: MqttService) -> Self {
+        self.mqtt = Some(client);
+        self
+    }
+
+    pub fn rules(&self) -> &EventRules {
+        &self.rules
+    }
+
+    /// Runs the actions of every rule matching `event`, returning one outcome
+    /// line per rule. A failing action is reported and doesn't stop the rest.
+    pub async fn handle_event(&self, event: &GhostEvent) -> Vec<String> {
+        let mut outcomes = Vec::new();
+        for rule in self.rules.matching(event) {
+            let outcome = match self.run_action(event, &rule.action).await {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    tracing::warn!("EventGhost rule {} failed for {}: {}", rule.event, event.name, e);
+                    format!("{} failed: {}", rule.event, e)
+                }
+            };
+            outcomes.push(outcome);
+        }
+        if outcomes.is_empty() {
+            tracing::debug!("No EventGhost rule matches {}", event.name);
+        }
+        outcomes
+    }
+
+    async fn run_action(&self, event: &GhostEvent, action: &RuleAction) -> Result<String> {
+        match action {
+            RuleAction::CreateTodo { description, project, priority } => {
+                let client = self.mqtt.as_ref()
+                    .ok_or_else(|| anyhow!("create_todo needs an MQTT connection"))?;
+                let description = event.render(description);
+                client.publish_json(INTAKE_TOPIC, &json!({
+                    "version": INTAKE_SCHEMA_VERSION,
+                    "description": description,
+                    "project": project.as_deref().map(|p| event.render(p)),
+                    "priority": priority,
+                    "source": "eventghost",
+                    "metadata": { "event": event.name, "payload": event.payload },
+                })).await?;
+                Ok(format!("Created todo: {}", description))
+            }
+            RuleAction::RunTool { tool, params } => {
+                let tools = self.tools.as_ref()
+                    .ok_or_else(|| anyhow!("run_tool needs a tool registry"))?;
+                let params = params.iter()
+                    .map(|(key, value)| (key.clone(), event.render(value)))
+                    .collect();
+                let tool = Tool { name: tool.clone(), description: String::new(), parameters: HashMap::new() };
+                let output = tools.execute(&tool, params).await?;
+                Ok(format!("Ran {}: {}", tool.name, output))
+            }
+            RuleAction::NotifyAgent { agent, message } => {
+                let target = crate::agents::get_agent(agent).await
+                    .ok_or_else(|| anyhow!("Agent '{}' is not registered", agent))?;
+                let message = Message::new(event.render(message))
+                    .with_role(Some("user".to_string()))
+                    .with_metadata(MessageMetadata::new(self.config.name.clone()));
+                let reply = target.process_message(message).await?;
+                Ok(format!("{} replied: {}", agent, reply.content))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Agent for EventGhostAgent {
+    async fn process_message(&self, message: Message) -> crate::Result<Message> {
+        let event = GhostEvent::parse(&message.content)?;
+        let outcomes = self.handle_event(&event).await;
+        let content = if outcomes.is_empty() {
+            format!("{}: no matching rules", event.name)
+        } else {
+            format!("{}: {}", event.name, outcomes.join("; "))
+        };
+        Ok(message.reply(content)
+            .with_metadata(MessageMetadata::new(self.config.name.clone())))
+    }
+
+    async fn transfer_to(&self, target_agent: String, message: Message) -> crate::Result<Message> {
+        Ok(Message::new(format!("Transferring to {} agent...", target_agent)))
+    }
+
+    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> crate::Result<String> {
+        let tools = self.tools.as_ref()
+            .ok_or_else(|| crate::SwarmError::Tool("EventGhostAgent has no tool registry".to_string()))?;
+        Ok(tools.execute(tool, params).await?)
+    }
+
+    async fn get_current_state(&self) -> crate::Result<Option<State>> {
+        Ok(self.state_manager.read().await.get_current_state().cloned())
+    }
+
+    async fn get_config(&self) -> crate::Result<AgentConfig> {
+        Ok(self.config.clone())
+    }
+}
+
+/// Handle every event published under `eventghost/event/#`
+pub async fn spawn_mqtt_listener(agent: Arc<EventGhostAgent>, client: MqttService) -> Result<JoinHandle<()>> {
+    let mut events = client.subscribe(&format!("{}/#", EVENT_TOPIC_PREFIX), QoS::AtLeastOnce).await?;
+    Ok(tokio::spawn(async move {
+        while let Some(message) = events.recv().await {
+            match GhostEvent::from_topic(&message.topic, &message.payload) {
+                Some(event) => {
+                    agent.handle_event(&event).await;
+                }
+                None => tracing::warn!("Ignoring EventGhost message on {}", message.topic),
+            }
+        }
+    }))
+}
+
+/// Accept newline-separated events over TCP, e.g. from an EventGhost
+/// "Python Script" action or `nc`. Each line is answered with its outcomes.
+pub async fn spawn_tcp_listener(agent: Arc<EventGhostAgent>, addr: &str) -> Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(addr).await
+        .map_err(|e| anyhow!("Failed to listen for EventGhost events on {}: {}", addr, e))?;
+    tracing::info!("Listening for EventGhost events on {}", addr);
+    Ok(tokio::spawn(async move {
+        loop {
+            let (stream, peer) = match listener.accept().await {
+                Ok(connection) => connection,
+                Err(e) => {
+                    tracing::warn!("EventGhost listener accept failed: {}", e);
+                    continue;
+                }
+            };
+            let agent = agent.clone();
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+                    let reply = match GhostEvent::parse(&line) {
+                        Ok(event) => agent.handle_event(&event).await.join("; "),
+                        Err(e) => format!("error: {}", e),
+                    };
+                    if writer.write_all(format!("{}\n", reply).as_bytes()).await.is_err() {
+                        break;
+                    }
+                }
+                tracing::debug!("EventGhost connection from {} closed", peer);
+            });
+        }
+    }))
+}
+
+/// Republish swarm events mirrored to MQTT by other processes as
+/// `eventghost/swarm/<kind>`, so EventGhost macros can react to them
+pub async fn spawn_event_forwarder(client: MqttService) -> Result<JoinHandle<()>> {
+    let mut agent_events = client.subscribe("agent/#", QoS::AtLeastOnce).await?;
+    let mut tool_events = client.subscribe("tools/+/executed", QoS::AtLeastOnce).await?;
+    Ok(tokio::spawn(async move {
+        loop {
+            let message = tokio::select! {
+                Some(message) = agent_events.recv() => message,
+                Some(message) = tool_events.recv() => message,
+                else => break,
+            };
+            // Agent topics also carry commands and replies; only events parse
+            let Ok(event) = serde_json::from_slice::<Event>(&message.payload) else {
+                continue;
+            };
+            let topic = format!("{}/{}", SWARM_TOPIC_PREFIX, event.kind());
+            if let Err(e) = client.publish(topic, QoS::AtLeastOnce, false, message.payload.clone()).await {
+                tracing::warn!("Failed to forward {} event to EventGhost: {}", event.kind(), e);
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AgentConfig {
+        AgentConfig {
+            name: "eventghost".to_string(),
+            public_description: "EventGhost bridge".to_string(),
+            instructions: String::new(),
+            tools: vec![],
+            downstream_agents: vec![],
+            personality: None,
+            state_machine: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_actions_are_reported() -> Result<()> {
+        let rules = EventRules::from_toml(r#"
+            [[rule]]
+            event = "Keyboard.*"
+            action = "create_todo"
+            description = "Key {suffix}"
+
+            [[rule]]
+            event = "Keyboard.F12"
+            action = "notify_agent"
+            agent = "nobody"
+            message = "{event}"
+        "#)?;
+        let agent = EventGhostAgent::new(config()).with_rules(rules);
+
+        let reply = agent.process_message(Message::new("Keyboard.F12".to_string())).await?;
+        assert!(reply.content.starts_with("Keyboard.F12: "));
+        assert!(reply.content.contains("create_todo needs an MQTT connection"));
+        assert!(reply.content.contains("'nobody' is not registered"));
+
+        let reply = agent.process_message(Message::new("Mouse.Left".to_string())).await?;
+        assert_eq!(reply.content, "Mouse.Left: no matching rules");
+        Ok(())
+    }
+}
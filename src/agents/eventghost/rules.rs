@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::path::Path;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Topic prefix EventGhost publishes events under, as `eventghost/event/<Prefix>/<Suffix>`
+pub const EVENT_TOPIC_PREFIX: &str = "eventghost/event";
+
+/// An EventGhost-style event: a dotted name such as `Keyboard.F12` or
+/// `System.Idle`, and whatever payload the plugin attached
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GhostEvent {
+    pub name: String,
+    #[serde(default)]
+    pub payload: Option<Value>,
+}
+
+impl GhostEvent {
+    pub fn new(name: impl Into<String>, payload: Option<Value>) -> Self {
+        Self { name: name.into(), payload }
+    }
+
+    /// Parses `{"name": "Keyboard.F12", "payload": ...}` or the plain line form
+    /// `Keyboard.F12 [payload]` that EventGhost's network sender produces
+    pub fn parse(text: &str) -> Result<Self> {
+        let text = text.trim();
+        if text.starts_with('{') {
+            let event: Self = serde_json::from_str(text)?;
+            if event.name.trim().is_empty() {
+                return Err(anyhow!("Event name is empty"));
+            }
+            return Ok(event);
+        }
+        let (name, payload) = match text.split_once(char::is_whitespace) {
+            Some((name, rest)) => (name, Some(rest.trim())),
+            None => (text, None),
+        };
+        if name.is_empty() {
+            return Err(anyhow!("Event name is empty"));
+        }
+        let payload = payload
+            .filter(|p| !p.is_empty())
+            .map(|p| serde_json::from_str(p).unwrap_or_else(|_| Value::String(p.to_string())));
+        Ok(Self::new(name, payload))
+    }
+
+    /// The event behind a message on `eventghost/event/<Prefix>/<Suffix>`,
+    /// with the message body as its payload
+    pub fn from_topic(topic: &str, payload: &[u8]) -> Option<Self> {
+        let rest = topic.strip_prefix(EVENT_TOPIC_PREFIX)?.strip_prefix('/')?;
+        if rest.is_empty() {
+            return None;
+        }
+        let body = String::from_utf8_lossy(payload);
+        let body = body.trim();
+        let payload = (!body.is_empty())
+            .then(|| serde_json::from_str(body).unwrap_or_else(|_| Value::String(body.to_string())));
+        Some(Self::new(rest.replace('/', "."), payload))
+    }
+
+    /// The part of the name before the first dot, e.g. `Keyboard`
+    pub fn prefix(&self) -> &str {
+        self.name.split_once('.').map_or(self.name.as_str(), |(prefix, _)| prefix)
+    }
+
+    /// The part of the name after the first dot, e.g. `F12`
+    pub fn suffix(&self) -> &str {
+        self.name.split_once('.').map_or("", |(_, suffix)| suffix)
+    }
+
+    fn payload_text(&self) -> String {
+        match &self.payload {
+            Some(Value::String(text)) => text.clone(),
+            Some(value) => value.to_string(),
+            None => String::new(),
+        }
+    }
+
+    /// Fills `{event}`, `{prefix}`, `{suffix}` and `{payload}` in `template`
+    pub fn render(&self, template: &str) -> String {
+        template
+            .replace("{event}", &self.name)
+            .replace("{prefix}", self.prefix())
+            .replace("{suffix}", self.suffix())
+            .replace("{payload}", &self.payload_text())
+    }
+}
+
+/// What the swarm does when a rule matches. String fields are templates, see
+/// [`GhostEvent::render`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum RuleAction {
+    /// Publish an intake request so `mqtt_intake` creates a todo
+    CreateTodo {
+        description: String,
+        #[serde(default)]
+        project: Option<String>,
+        #[serde(default)]
+        priority: Option<String>,
+    },
+    /// Run a tool from the agent's registry
+    RunTool {
+        tool: String,
+        #[serde(default)]
+        params: HashMap<String, String>,
+    },
+    /// Send a message to a registered agent
+    NotifyAgent { agent: String, message: String },
+}
+
+/// Maps events whose name matches `event` to an action. `*` in the pattern
+/// matches any run of characters, so `Keyboard.*` matches every key.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventRule {
+    pub event: String,
+    #[serde(flatten)]
+    pub action: RuleAction,
+}
+
+impl EventRule {
+    pub fn matches(&self, event: &GhostEvent) -> bool {
+        wildcard_match(&self.event, &event.name)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RulesFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<EventRule>,
+}
+
+/// The ordered rule set of an [`EventGhostAgent`](super::EventGhostAgent)
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventRules {
+    rules: Vec<EventRule>,
+}
+
+impl EventRules {
+    pub fn new(rules: Vec<EventRule>) -> Self {
+        Self { rules }
+    }
+
+    /// Parses `[[rule]]` tables, see `config/eventghost.example.toml`
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let file: RulesFile = toml::from_str(text)?;
+        Ok(Self::new(file.rules))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read EventGhost rules {}: {}", path.display(), e))?;
+        Self::from_toml(&text)
+    }
+
+    /// Loads the file named by `EVENTGHOST_RULES`, falling back to no rules
+    /// when it is unset
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("EVENTGHOST_RULES") {
+            Ok(path) if !path.is_empty() => Self::load(path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    /// Every rule matching `event`, in file order
+    pub fn matching<'a>(&'a self, event: &'a GhostEvent) -> impl Iterator<Item = &'a EventRule> + 'a {
+        self.rules.iter().filter(move |rule| rule.matches(event))
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+}
+
+/// Case-insensitive match where `*` stands for any run of characters
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_events() {
+        let event = GhostEvent::parse("Keyboard.F12").unwrap();
+        assert_eq!(event, GhostEvent::new("Keyboard.F12", None));
+        assert_eq!((event.prefix(), event.suffix()), ("Keyboard", "F12"));
+
+        let event = GhostEvent::parse("System.Idle {\"minutes\": 5}").unwrap();
+        assert_eq!(event.payload, Some(json!({"minutes": 5})));
+        let event = GhostEvent::parse("Remote.Say hello there").unwrap();
+        assert_eq!(event.payload, Some(json!("hello there")));
+        let event = GhostEvent::parse(r#"{"name": "Timer.Tick", "payload": 3}"#).unwrap();
+        assert_eq!(event, GhostEvent::new("Timer.Tick", Some(json!(3))));
+        assert!(GhostEvent::parse("   ").is_err());
+
+        let event = GhostEvent::from_topic("eventghost/event/Keyboard/F12", b"").unwrap();
+        assert_eq!(event, GhostEvent::new("Keyboard.F12", None));
+        assert!(GhostEvent::from_topic("eventghost/swarm/task_failed", b"{}").is_none());
+    }
+
+    #[test]
+    fn test_rules_match_and_render() {
+        let rules = EventRules::from_toml(r#"
+            [[rule]]
+            event = "Keyboard.*"
+            action = "create_todo"
+            description = "Key {suffix} pressed: {payload}"
+            priority = "high"
+
+            [[rule]]
+            event = "system.idle"
+            action = "run_tool"
+            tool = "git"
+            params = { command = "status" }
+
+            [[rule]]
+            event = "*"
+            action = "notify_agent"
+            agent = "greeter"
+            message = "{event}"
+        "#).unwrap();
+        assert_eq!(rules.len(), 3);
+
+        let event = GhostEvent::parse("Keyboard.F12 now").unwrap();
+        let matched: Vec<_> = rules.matching(&event).collect();
+        assert_eq!(matched.len(), 2);
+        match &matched[0].action {
+            RuleAction::CreateTodo { description, priority, .. } => {
+                assert_eq!(event.render(description), "Key F12 pressed: now");
+                assert_eq!(priority.as_deref(), Some("high"));
+            }
+            other => panic!("unexpected action {:?}", other),
+        }
+
+        let idle = GhostEvent::parse("System.Idle").unwrap();
+        assert!(matches!(rules.matching(&idle).next().map(|r| &r.action), Some(RuleAction::RunTool { .. })));
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("Keyboard.*", "Keyboard.F12"));
+        assert!(wildcard_match("*.Idle", "System.Idle"));
+        assert!(wildcard_match("Remote.*.Down", "Remote.Volume.Down"));
+        assert!(!wildcard_match("Remote.*.Down", "Remote.Volume.Up"));
+        assert!(!wildcard_match("Keyboard.F1", "Keyboard.F12"));
+    }
+}
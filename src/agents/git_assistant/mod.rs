@@ -1,22 +1,52 @@
 use async_trait::async_trait;
-use std::process::Command;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
 use crate::types::{Agent, AgentConfig, Message, MessageMetadata, Tool, ToolCall, State, StateMachine, AgentStateManager};
-use crate::tools::ToolRegistry;
+use crate::tools::{dry_run, ToolRegistry};
+use crate::agents::ApprovalInbox;
 use anyhow::{Result, anyhow};
 #[cfg(feature = "git-agent")]
 use rand::Rng;
 use chrono;
 use crate::ai::{AiProvider, DefaultAiClient};
 use tokio::process::Command as TokioCommand;
+use tokio::sync::{Mutex, RwLock};
 use tokio::io::{AsyncBufReadExt, BufReader};
-use futures::executor::block_on;
+
+/// Command that moves the agent to another repository
+const SET_WORKING_DIR: &str = "set working-dir";
+
+/// The path after `set working-dir`, in its original case, when `content` is that command
+fn working_dir_argument(content: &str) -> Option<&str> {
+    let prefix = content.get(..SET_WORKING_DIR.len())?;
+    let rest = &content[SET_WORKING_DIR.len()..];
+    (prefix.eq_ignore_ascii_case(SET_WORKING_DIR) && (rest.is_empty() || rest.starts_with(char::is_whitespace)))
+        .then(|| rest.trim())
+}
+
+/// One caller's view of the agent: the repository its commands run in, and
+/// a lock so its commands don't interleave
+#[derive(Default)]
+struct GitSession {
+    /// `None` falls back to the default session's repository
+    working_dir: RwLock<Option<PathBuf>>,
+    operation: Mutex<()>,
+}
+
+tokio::task_local! {
+    /// Session of the message being handled
+    static SESSION: Arc<GitSession>;
+}
 
 pub struct GitAssistantAgent {
     config: AgentConfig,
-    working_dir: Arc<Mutex<Option<PathBuf>>>,
+    /// Used by messages without a session, and for the repository of
+    /// sessions that haven't picked their own
+    default_session: Arc<GitSession>,
+    /// Keyed by [`Message::session`], so concurrent callers can work in
+    /// different repositories
+    sessions: RwLock<HashMap<String, Arc<GitSession>>>,
     current_state: Option<State>,
     ai_client: Box<dyn AiProvider + Send + Sync>,
 }
@@ -25,7 +55,8 @@ impl GitAssistantAgent {
     pub fn new(config: AgentConfig) -> Self {
         Self {
             config,
-            working_dir: Arc::new(Mutex::new(None)),
+            default_session: Arc::new(GitSession::default()),
+            sessions: RwLock::new(HashMap::new()),
             current_state: None,
             ai_client: Box::new(DefaultAiClient::new()),
         }
@@ -36,23 +67,73 @@ impl GitAssistantAgent {
         self
     }
 
-    fn get_working_dir(&self) -> Result<PathBuf> {
-        self.working_dir
-            .lock()
-            .unwrap()
+    /// Run git commands in `path` unless a session picks another repository
+    pub fn with_working_dir(mut self, path: impl Into<PathBuf>) -> Self {
+        self.default_session = Arc::new(GitSession {
+            working_dir: RwLock::new(Some(path.into())),
+            ..GitSession::default()
+        });
+        self
+    }
+
+    /// The session `id` names, or the default one. Sessions that never set
+    /// a working directory are dropped once idle, so one-off task ids
+    /// don't pile up.
+    async fn session(&self, id: Option<&str>) -> Arc<GitSession> {
+        let Some(id) = id else {
+            return self.default_session.clone();
+        };
+        if let Some(session) = self.sessions.read().await.get(id) {
+            return session.clone();
+        }
+
+        let mut sessions = self.sessions.write().await;
+        sessions.retain(|_, session| {
+            Arc::strong_count(session) > 1
+                || session.working_dir.try_read().map_or(true, |dir| dir.is_some())
+        });
+        sessions.entry(id.to_string()).or_default().clone()
+    }
+
+    /// The session of the message being handled, or the default one
+    fn current_session(&self) -> Arc<GitSession> {
+        SESSION.try_with(Arc::clone).unwrap_or_else(|_| self.default_session.clone())
+    }
+
+    async fn get_working_dir(&self) -> Result<PathBuf> {
+        if let Some(dir) = self.current_session().working_dir.read().await.clone() {
+            return Ok(dir);
+        }
+        self.default_session
+            .working_dir
+            .read()
+            .await
             .clone()
             .ok_or_else(|| anyhow!("Working directory not set"))
     }
 
-    pub fn update_working_dir(&self, path: PathBuf) -> Result<()> {
-        *self.working_dir.lock().unwrap() = Some(path);
-        Ok(())
+    /// Move the current session to `path`, resolved against its working
+    /// directory when relative. Fails unless it is an existing directory.
+    /// Outside of [`Agent::process_message`] this moves the default session.
+    pub async fn set_working_dir(&self, path: impl AsRef<Path>) -> Result<PathBuf> {
+        let path = match self.get_working_dir().await {
+            Ok(current) if path.as_ref().is_relative() => current.join(path),
+            _ => path.as_ref().to_path_buf(),
+        };
+        let metadata = tokio::fs::metadata(&path).await
+            .map_err(|e| anyhow!("Cannot use {} as working directory: {}", path.display(), e))?;
+        if !metadata.is_dir() {
+            return Err(anyhow!("{} is not a directory", path.display()));
+        }
+        let path = tokio::fs::canonicalize(&path).await?;
+        *self.current_session().working_dir.write().await = Some(path.clone());
+        Ok(path)
     }
 
     async fn execute_git_command(&self, args: &[&str]) -> Result<String> {
         let output = TokioCommand::new("git")
             .args(args)
-            .current_dir(&self.get_working_dir()?)
+            .current_dir(&self.get_working_dir().await?)
             .output()
             .await
             .map_err(|e| anyhow!("Failed to execute git command: {}", e))?;
@@ -90,7 +171,10 @@ impl GitAssistantAgent {
 
     async fn push(&self) -> Result<String> {
         let branch = self.get_current_branch().await?;
-        self.execute_git_command(&["push", "origin", &branch]).await
+        // The approver may point the push at another branch
+        let params = HashMap::from([("command".to_string(), "push".to_string()), ("branch".to_string(), branch)]);
+        let params = ApprovalInbox::shared().approve_call("git", params).await?;
+        self.execute_git_command(&["push", "origin", &params["branch"]]).await
     }
 
     async fn pull(&self) -> Result<String> {
@@ -111,7 +195,7 @@ impl GitAssistantAgent {
 
     async fn create_branch(&self, branch_name: &str) -> Result<()> {
         TokioCommand::new("git")
-            .current_dir(&self.get_working_dir()?)
+            .current_dir(&self.get_working_dir().await?)
             .args(["checkout", "-b", branch_name])
             .output()
             .await?;
@@ -120,7 +204,7 @@ impl GitAssistantAgent {
 
     async fn stage_changes(&self) -> Result<()> {
         TokioCommand::new("git")
-            .current_dir(&self.get_working_dir()?)
+            .current_dir(&self.get_working_dir().await?)
             .args(["add", "."])
             .output()
             .await?;
@@ -152,17 +236,17 @@ impl GitAssistantAgent {
         }
     }
 
-    pub async fn commit_for_agent(&mut self, agent_name: &str, message: &str) -> Result<()> {
+    pub async fn commit_for_agent(&self, agent_name: &str, message: &str) -> Result<()> {
         // Stage all changes
         TokioCommand::new("git")
-            .current_dir(&self.get_working_dir()?)
+            .current_dir(&self.get_working_dir().await?)
             .args(["add", "."])
             .output()
             .await?;
 
         // Commit with provided message
         TokioCommand::new("git")
-            .current_dir(&self.get_working_dir()?)
+            .current_dir(&self.get_working_dir().await?)
             .args(["commit", "-m", &format!("[{}] {}", agent_name, message)])
             .output()
             .await?;
@@ -197,12 +281,22 @@ impl GitAssistantAgent {
                 "helpful".to_string(),
             ]);
 
-        Message {
-            content,
-            metadata: Some(metadata),
-            role: Some("assistant".to_string()),
-            timestamp: Some(chrono::Utc::now().timestamp()),
-        }
+        Message::new(content).with_metadata(metadata)
+    }
+
+    async fn handle_set_working_dir(&self, path: &str) -> Message {
+        let response = if path.is_empty() {
+            match self.get_working_dir().await {
+                Ok(dir) => format!("🧭 Anchored to timeline coordinates: {}", dir.display()),
+                Err(_) => "🧭 Not anchored to any timeline. Use 'set working-dir <path>'".to_string(),
+            }
+        } else {
+            match self.set_working_dir(path).await {
+                Ok(dir) => format!("🧭 Anchored to timeline coordinates: {}", dir.display()),
+                Err(e) => format!("⚠️ Cannot anchor there: {}", e),
+            }
+        };
+        self.format_git_response(response)
     }
 
     async fn handle_git_command(&self, command: &str) -> Message {
@@ -210,6 +304,13 @@ impl GitAssistantAgent {
         let cmd = parts.first().unwrap_or(&"");
         let args = if parts.len() > 1 { &parts[1..] } else { &[] };
 
+        // Everything but help and status changes the repository, so a dry run only describes it
+        if dry_run::is_active() && !matches!(*cmd, "help" | "" | "status") {
+            let dir = self.get_working_dir().await.unwrap_or_else(|_| PathBuf::from("."));
+            let plan = dry_run::plan([format!("run `git {}` in {}", parts.join(" "), dir.display())]);
+            return self.format_git_response(plan);
+        }
+
         let response = match *cmd {
             "help" | "" => format!(
                 "🌟 Quantum Version Control Interface - Your Temporal Archive Assistant\n\n\
@@ -222,7 +323,8 @@ impl GitAssistantAgent {
                 - checkout <branch>: Shift to an alternate timeline\n\
                 - merge <branch>: Converge timelines into unified reality\n\
                 - push: Synchronize local quantum states with the temporal nexus\n\
-                - pull: Retrieve quantum state updates from the temporal nexus"
+                - pull: Retrieve quantum state updates from the temporal nexus\n\
+                - set working-dir <path>: Anchor to the timeline at another set of coordinates"
             ),
             "status" => {
                 match self.get_status().await {
@@ -239,7 +341,7 @@ impl GitAssistantAgent {
             "add" => {
                 let files = args.join(" ");
                 match TokioCommand::new("git")
-                    .current_dir(&self.get_working_dir().unwrap_or_else(|_| PathBuf::from(".")))
+                    .current_dir(&self.get_working_dir().await.unwrap_or_else(|_| PathBuf::from(".")))
                     .args(["add"])
                     .args(args)
                     .output()
@@ -251,7 +353,7 @@ impl GitAssistantAgent {
             "commit" => {
                 let msg = args.join(" ");
                 match TokioCommand::new("git")
-                    .current_dir(&self.get_working_dir().unwrap_or_else(|_| PathBuf::from(".")))
+                    .current_dir(&self.get_working_dir().await.unwrap_or_else(|_| PathBuf::from(".")))
                     .args(["commit", "-m", if msg.is_empty() { "archival" } else { &msg }])
                     .output()
                     .await {
@@ -285,8 +387,14 @@ impl GitAssistantAgent {
                 }
             },
             "push" => {
+                let approval = ApprovalInbox::shared()
+                    .approve_call("git", HashMap::from([("command".to_string(), "push".to_string())]))
+                    .await;
+                if let Err(e) = approval {
+                    return self.format_git_response(format!("⚠️ Temporal synchronization held back: {}", e));
+                }
                 match TokioCommand::new("git")
-                    .current_dir(&self.get_working_dir().unwrap_or_else(|_| PathBuf::from(".")))
+                    .current_dir(&self.get_working_dir().await.unwrap_or_else(|_| PathBuf::from(".")))
                     .args(["push"])
                     .output()
                     .await {
@@ -296,7 +404,7 @@ impl GitAssistantAgent {
             },
             "pull" => {
                 match TokioCommand::new("git")
-                    .current_dir(&self.get_working_dir().unwrap_or_else(|_| PathBuf::from(".")))
+                    .current_dir(&self.get_working_dir().await.unwrap_or_else(|_| PathBuf::from(".")))
                     .args(["pull"])
                     .output()
                     .await {
@@ -313,24 +421,35 @@ impl GitAssistantAgent {
 
 #[async_trait]
 impl Agent for GitAssistantAgent {
-    async fn process_message(&self, message: Message) -> Result<Message> {
-        let command = message.content.trim().to_lowercase();
-        Ok(self.handle_git_command(&command).await)
+    async fn process_message(&self, message: Message) -> crate::Result<Message> {
+        let session = self.session(message.session()).await;
+        let _operation = session.operation.lock().await;
+
+        SESSION.scope(session.clone(), async {
+            // Paths keep their case, so this is matched before lowercasing
+            let content = message.content.trim();
+            if let Some(path) = working_dir_argument(content) {
+                return Ok(self.handle_set_working_dir(path).await);
+            }
+
+            let command = content.to_lowercase();
+            Ok(self.handle_git_command(&command).await)
+        }).await
     }
 
-    async fn transfer_to(&self, target_agent: String, message: Message) -> Result<Message> {
+    async fn transfer_to(&self, target_agent: String, message: Message) -> crate::Result<Message> {
         Ok(Message::new(format!("Transferring to {} agent...", target_agent)))
     }
 
-    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
-        Err(anyhow!("GitAssistantAgent does not support tool calls"))
+    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> crate::Result<String> {
+        Err(crate::SwarmError::Unsupported("GitAssistantAgent does not support tool calls".to_string()))
     }
 
-    async fn get_current_state(&self) -> Result<Option<State>> {
+    async fn get_current_state(&self) -> crate::Result<Option<State>> {
         Ok(self.current_state.clone())
     }
 
-    async fn get_config(&self) -> Result<AgentConfig> {
+    async fn get_config(&self) -> crate::Result<AgentConfig> {
         Ok(self.config.clone())
     }
 }
@@ -339,6 +458,7 @@ impl Agent for GitAssistantAgent {
 mod tests {
     use super::*;
     use std::fs;
+    use std::process::Command;
     use tempfile::tempdir;
 
     fn create_test_message(content: &str) -> Message {
@@ -359,8 +479,7 @@ mod tests {
 
     async fn setup_test_repo() -> (GitAssistantAgent, tempfile::TempDir) {
         let temp_dir = tempdir().unwrap();
-        let mut agent = GitAssistantAgent::new(create_test_config());
-        agent.update_working_dir(temp_dir.path().to_path_buf()).unwrap();
+        let agent = GitAssistantAgent::new(create_test_config()).with_working_dir(temp_dir.path());
 
         // Initialize git repo
         Command::new("git")
@@ -426,8 +545,7 @@ mod tests {
     #[tokio::test]
     async fn test_empty_repo_status() {
         let temp_dir = tempdir().unwrap();
-        let mut agent = create_test_agent().await.unwrap();
-        agent.update_working_dir(temp_dir.path().to_path_buf()).unwrap();
+        let agent = create_test_agent().await.unwrap().with_working_dir(temp_dir.path());
 
         let response = agent.process_message(Message::new("status".to_string())).await.unwrap();
         assert!(response.content.contains("temporal nexus"),
@@ -437,8 +555,7 @@ mod tests {
     #[tokio::test]
     async fn test_commit_flow() {
         let temp_dir = tempdir().unwrap();
-        let mut agent = create_test_agent().await.unwrap();
-        agent.update_working_dir(temp_dir.path().to_path_buf()).unwrap();
+        let agent = create_test_agent().await.unwrap().with_working_dir(temp_dir.path());
 
         // Initialize git repo
         Command::new("git")
@@ -470,11 +587,23 @@ mod tests {
             "Should indicate quantum state marker creation");
     }
 
+    #[tokio::test]
+    async fn test_dry_run_describes_instead_of_running() {
+        let temp_dir = tempdir().unwrap();
+        let agent = create_test_agent().await.unwrap().with_working_dir(temp_dir.path());
+        Command::new("git").current_dir(temp_dir.path()).args(["init"]).output().unwrap();
+
+        let response = dry_run::with_dry_run(agent.process_message(Message::new("branch feature".to_string()))).await.unwrap();
+        assert!(response.content.contains("run `git branch feature`"), "Should describe the command: {}", response.content);
+
+        let branches = Command::new("git").current_dir(temp_dir.path()).args(["branch", "--list", "feature"]).output().unwrap();
+        assert!(branches.stdout.is_empty(), "Dry run should not create the branch");
+    }
+
     #[tokio::test]
     async fn test_branch_and_merge() {
         let temp_dir = tempdir().unwrap();
-        let mut agent = create_test_agent().await.unwrap();
-        agent.update_working_dir(temp_dir.path().to_path_buf()).unwrap();
+        let agent = create_test_agent().await.unwrap().with_working_dir(temp_dir.path());
 
         // Initialize and create initial commit
         Command::new("git")
@@ -531,11 +660,70 @@ mod tests {
 
     #[tokio::test]
     async fn test_invalid_command() {
-        let (mut agent, _temp_dir) = setup_test_repo().await;
+        let (agent, _temp_dir) = setup_test_repo().await;
         let response = agent.process_message(Message::new("invalid-command".to_string())).await.unwrap();
         assert!(response.content.contains("Unknown temporal operation"));
     }
 
+    #[tokio::test]
+    async fn test_set_working_dir_command() {
+        let (agent, temp_dir) = setup_test_repo().await;
+        let other = tempdir().unwrap();
+        std::fs::create_dir(other.path().join("Nested")).unwrap();
+
+        // The agent is shared the way the registry shares it, so no `&mut` is needed
+        let agent: Arc<Box<dyn Agent + Send + Sync>> = Arc::new(Box::new(agent));
+        let path = other.path().canonicalize().unwrap();
+        let response = agent.process_message(Message::new(format!("SET Working-Dir {}", path.display()))).await.unwrap();
+        assert!(response.content.contains(&path.display().to_string()), "{}", response.content);
+        let response = agent.process_message(Message::new("status".to_string())).await.unwrap();
+        assert!(response.content.contains("temporal nexus"), "The new directory has no repository");
+
+        // Relative paths keep their case and resolve against the current directory
+        let response = agent.process_message(Message::new("set working-dir Nested".to_string())).await.unwrap();
+        assert!(response.content.contains(&path.join("Nested").display().to_string()), "{}", response.content);
+        let response = agent.process_message(Message::new("set working-dir".to_string())).await.unwrap();
+        assert!(response.content.contains("Nested"));
+
+        let response = agent.process_message(Message::new("set working-dir /no/such/dir".to_string())).await.unwrap();
+        assert!(response.content.contains("Cannot anchor"));
+        let response = agent.process_message(Message::new(format!("set working-dir {}", temp_dir.path().display()))).await.unwrap();
+        assert!(response.content.contains("Anchored"));
+        assert!(working_dir_argument("set working-directory x").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_sessions_keep_their_own_working_dir() {
+        let (agent, temp_dir) = setup_test_repo().await;
+        let first = tempdir().unwrap();
+        let second = tempdir().unwrap();
+        let first_path = first.path().canonicalize().unwrap();
+        let second_path = second.path().canonicalize().unwrap();
+
+        let (a, b) = tokio::join!(
+            agent.process_message(Message::new(format!("set working-dir {}", first_path.display())).with_session("first")),
+            agent.process_message(Message::new(format!("set working-dir {}", second_path.display())).with_session("second")),
+        );
+        assert!(a.unwrap().content.contains(&first_path.display().to_string()));
+        assert!(b.unwrap().content.contains(&second_path.display().to_string()));
+
+        let where_is = |session: Option<&str>| {
+            let message = Message::new("set working-dir".to_string());
+            let message = match session {
+                Some(id) => message.with_session(id),
+                None => message,
+            };
+            agent.process_message(message)
+        };
+        assert!(where_is(Some("first")).await.unwrap().content.contains(&first_path.display().to_string()));
+        assert!(where_is(Some("second")).await.unwrap().content.contains(&second_path.display().to_string()));
+
+        // Messages without a session, and sessions that never moved, stay in the default repository
+        assert!(where_is(None).await.unwrap().content.contains(&temp_dir.path().display().to_string()));
+        let response = agent.process_message(Message::new("status".to_string()).with_session("third")).await.unwrap();
+        assert!(response.content.contains("Quantum State Analysis"), "{}", response.content);
+    }
+
     #[tokio::test]
     async fn test_git_commands() {
         let (agent, _temp_dir) = setup_test_repo().await;
@@ -2,13 +2,14 @@ use async_trait::async_trait;
 use std::collections::HashMap;
 use std::time::Duration;
 use serde_json::Value;
-use crate::types::{Agent, AgentConfig, Message, MessageMetadata, State, AgentStateManager, StateMachine, Tool};
+use crate::types::{Agent, AgentConfig, Message, MessageMetadata, ResponseFormat, State, AgentStateManager, StateMachine, Tool};
 use crate::types::{TodoProcessor, TodoList, TodoTask};
 use crate::ai::{AiProvider, DefaultAiClient};
+use crate::tools::KnowledgeBase;
 use anyhow::{Result, anyhow};
 use std::error::Error as StdError;
 use uuid::Uuid;
-use futures::executor::block_on;
+use std::sync::Arc;
 
 pub struct GreeterAgent {
     config: AgentConfig,
@@ -16,6 +17,7 @@ pub struct GreeterAgent {
     ai_client: Box<dyn AiProvider + Send + Sync>,
     conversation_history: Vec<Message>,
     todo_list: TodoList,
+    knowledge: Option<Arc<KnowledgeBase>>,
 }
 
 impl GreeterAgent {
@@ -25,7 +27,8 @@ impl GreeterAgent {
             config,
             ai_client: Box::new(DefaultAiClient::new()),
             conversation_history: Vec::new(),
-            todo_list: block_on(TodoList::new()).expect("Failed to create TodoList"),
+            todo_list: TodoList::from_env().expect("Failed to create TodoList"),
+            knowledge: KnowledgeBase::shared(),
         }
     }
 
@@ -34,9 +37,15 @@ impl GreeterAgent {
         self
     }
 
-    async fn get_ai_response(&self, prompt: &str) -> Result<String> {
+    /// Ground answers in project documentation. Defaults to `KnowledgeBase::shared()`.
+    pub fn with_knowledge(mut self, knowledge: Option<Arc<KnowledgeBase>>) -> Self {
+        self.knowledge = knowledge;
+        self
+    }
+
+    async fn get_ai_response(&self, prompt: &str, format: ResponseFormat) -> Result<String> {
         let messages = self.build_conversation_messages(prompt);
-        let system_prompt = format!(
+        let mut system_prompt = format!(
             "You are a friendly AI greeter assistant named {}. Your role is to: \
             1. Welcome users and understand their needs \
             2. Direct them to specialized agents for specific tasks (git, haiku, or project initialization) \
@@ -47,10 +56,25 @@ impl GreeterAgent {
             Be concise but friendly in your responses.",
             self.config.name
         );
+        if let Some(context) = self.knowledge_context(prompt).await {
+            system_prompt.push_str(&format!(
+                "\n\nWhen the user asks about the project, answer from these documentation excerpts \
+                and name the [source] you used. Don't guess beyond them.\n\n{}",
+                context
+            ));
+        }
+        if format == ResponseFormat::Json {
+            system_prompt.push_str("\n\n");
+            system_prompt.push_str(crate::agents::structured::JSON_REPLY_INSTRUCTIONS);
+        }
 
         self.ai_client.chat(&system_prompt, messages).await
     }
 
+    async fn knowledge_context(&self, prompt: &str) -> Option<String> {
+        self.knowledge.as_ref()?.context_for(prompt, crate::tools::knowledge::DEFAULT_SEARCH_LIMIT).await
+    }
+
     fn build_conversation_messages(&self, current_prompt: &str) -> Vec<HashMap<String, String>> {
         let mut messages = Vec::new();
 
@@ -71,7 +95,7 @@ impl GreeterAgent {
         messages
     }
 
-    async fn handle_greeting(&self, message: &str) -> Result<Message> {
+    async fn handle_greeting(&self, message: &str, format: ResponseFormat) -> Result<Message> {
         // Check for direct transfer requests first
         let transfer_agent = match message.to_lowercase().as_str() {
             msg if msg.contains("haiku") || msg.contains("poetry") || msg.contains("nature") => Some("haiku"),
@@ -88,7 +112,7 @@ impl GreeterAgent {
         }
 
         // Get AI response for conversation
-        let ai_response = self.get_ai_response(message).await?;
+        let ai_response = self.get_ai_response(message, format).await?;
 
         let mut response = Message::new(ai_response);
         response.metadata = Some(MessageMetadata::new("greeter".to_string())
@@ -99,27 +123,27 @@ impl GreeterAgent {
 
 #[async_trait]
 impl Agent for GreeterAgent {
-    async fn process_message(&self, message: Message) -> Result<Message> {
-        self.handle_greeting(&message.content).await
+    async fn process_message(&self, message: Message) -> crate::Result<Message> {
+        Ok(self.handle_greeting(&message.content, message.response_format()).await?)
     }
 
-    async fn transfer_to(&self, target_agent: String, message: Message) -> Result<Message> {
+    async fn transfer_to(&self, target_agent: String, message: Message) -> crate::Result<Message> {
         // Check if target agent is in downstream_agents
         if !self.config.downstream_agents.contains(&target_agent) {
-            return Err(anyhow!("Cannot transfer to unknown agent: {}", target_agent));
+            return Err(crate::SwarmError::NotFound(format!("Cannot transfer to unknown agent: {}", target_agent)));
         }
         Ok(Message::new(format!("Transferring to {} agent...", target_agent)))
     }
 
-    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
-        Err(anyhow!("GreeterAgent does not support tool calls"))
+    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> crate::Result<String> {
+        Err(crate::SwarmError::Unsupported("GreeterAgent does not support tool calls".to_string()))
     }
 
-    async fn get_current_state(&self) -> Result<Option<State>> {
+    async fn get_current_state(&self) -> crate::Result<Option<State>> {
         Ok(self.state_manager.get_current_state().cloned())
     }
 
-    async fn get_config(&self) -> Result<AgentConfig> {
+    async fn get_config(&self) -> crate::Result<AgentConfig> {
         Ok(self.config.clone())
     }
 }
@@ -127,7 +151,7 @@ impl Agent for GreeterAgent {
 #[async_trait]
 impl TodoProcessor for GreeterAgent {
     async fn process_task(&self, task: TodoTask) -> Result<Message> {
-        self.process_message(Message::new(task.description)).await
+        Ok(self.process_message(Message::new(task.clarified_description()).with_attachments(task.attachments)).await?)
     }
 
     fn get_check_interval(&self) -> Duration {
@@ -222,19 +246,11 @@ mod tests {
         let task = TodoTask {
             id: Uuid::new_v4().to_string(),
             description: "Hello, I need help with git".to_string(),
-            enhanced_description: None,  // No AI enhancement in test
             priority: crate::types::TaskPriority::Medium,
-            project: None,
-            source_agent: None,
             target_agent: "greeter".to_string(),
-            status: crate::types::TaskStatus::Pending,
             created_at: chrono::Utc::now().timestamp(),
-            completed_at: None,
-            due_date: None,
-            duration_minutes: None,
-            notes: None,
-            ticket: None,
             last_modified: Some(chrono::Utc::now().timestamp()),
+            ..Default::default()
         };
 
         // Add task to todo list
@@ -7,11 +7,25 @@ use crate::ai::{AiProvider, DefaultAiClient};
 use anyhow::{Result, anyhow};
 use std::error::Error as StdError;
 use serde_json;
+use chrono::{Duration as ChronoDuration, Utc};
+use crate::mqtt::QoS;
+use crate::mqtt::MqttService;
+use crate::types::TodoList;
+
+pub mod memory;
+
+pub use memory::{HaikuEntry, HaikuMemory};
+
+/// MQTT topic the daily haiku is published to
+pub const DAILY_HAIKU_TOPIC: &str = "haiku/daily";
+/// MQTT topic collecting daily digest items
+pub const DIGEST_TOPIC: &str = "digest/daily";
 
 pub struct HaikuAgent {
     config: AgentConfig,
     state_manager: Arc<RwLock<AgentStateManager>>,
     ai_client: Box<dyn AiProvider + Send + Sync>,
+    memory: Arc<RwLock<HaikuMemory>>,
 }
 
 impl HaikuAgent {
@@ -72,6 +86,7 @@ impl HaikuAgent {
             config,
             state_manager: Arc::new(RwLock::new(AgentStateManager::new(state_machine))),
             ai_client: Box::new(DefaultAiClient::new()),
+            memory: Arc::new(RwLock::new(HaikuMemory::from_env())),
         }
     }
 
@@ -80,12 +95,78 @@ impl HaikuAgent {
         self
     }
 
+    pub fn with_memory(mut self, memory: HaikuMemory) -> Self {
+        self.memory = Arc::new(RwLock::new(memory));
+        self
+    }
+
+    /// Archived haikus, newest first
+    pub async fn archive(&self, offset: usize, limit: usize) -> Vec<HaikuEntry> {
+        self.memory.read().await.archive(offset, limit)
+    }
+
+    /// Generate a haiku about the most active project of the previous day and
+    /// publish it to the daily haiku and digest topics.
+    pub async fn publish_daily_haiku(&self, todo_list: &TodoList, client: &MqttService) -> Result<HaikuEntry> {
+        let yesterday = (Utc::now() - ChronoDuration::days(1)).date_naive();
+        let tasks = todo_list.get_all_tasks().await?;
+        let ranked = memory::rank_projects_by_activity(&tasks, yesterday);
+
+        // Prefer the busiest project that has not been written about recently
+        let project = {
+            let memory = self.memory.read().await;
+            ranked.iter()
+                .find(|(project, _)| !memory.is_recent(project))
+                .or_else(|| ranked.first())
+                .map(|(project, _)| project.clone())
+        };
+        let topic = project.clone().unwrap_or_else(|| "a quiet day in the swarm".to_string());
+
+        let haiku = self.generate_haiku(topic.clone()).await?;
+        let entry = HaikuEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            topic,
+            haiku,
+            project,
+            daily: true,
+            created_at: Utc::now(),
+        };
+        self.memory.write().await.record(entry.clone()).await?;
+
+        let payload = serde_json::to_string(&entry)?;
+        client.publish(DAILY_HAIKU_TOPIC, QoS::AtLeastOnce, true, payload.clone()).await?;
+        client.publish(DIGEST_TOPIC, QoS::AtLeastOnce, false, serde_json::json!({
+            "section": "haiku",
+            "date": yesterday.to_string(),
+            "item": entry,
+        }).to_string()).await?;
+
+        Ok(entry)
+    }
+
     async fn generate_haiku(&self, topic: String) -> Result<String> {
         let system_prompt = "You are a poetic AI that creates haikus. A haiku is a three-line poem with 5 syllables in the first line, 7 in the second, and 5 in the third. Create a haiku that blends nature imagery with technical concepts.";
 
+        // Steer away from haikus we have already written about this topic
+        let previous: Vec<String> = self.memory.read().await
+            .haikus_for_topic(&topic)
+            .into_iter()
+            .take(3)
+            .map(|entry| entry.haiku.clone())
+            .collect();
+        let request = if previous.is_empty() {
+            format!("Create a haiku about: {}", topic)
+        } else {
+            format!(
+                "Create a haiku about: {}\nTake a fresh angle; do not reuse imagery from these earlier haikus:\n{}",
+                topic,
+                previous.join("\n---\n")
+            )
+        };
+
         let messages = vec![HashMap::from([
             ("role".to_string(), "user".to_string()),
-            ("content".to_string(), format!("Create a haiku about: {}", topic)),
+            ("content".to_string(), request),
         ])];
 
         let mut haiku = self.ai_client.chat(system_prompt, messages.clone()).await?;
@@ -142,12 +223,7 @@ impl HaikuAgent {
                 "mad_tinker_inspired".to_string(),
             ]);
 
-        Message {
-            content,
-            metadata: Some(metadata),
-            role: Some("assistant".to_string()),
-            timestamp: Some(chrono::Utc::now().timestamp()),
-        }
+        Message::new(content).with_metadata(metadata)
     }
 }
 
@@ -162,7 +238,7 @@ fn count_syllables(line: &str) -> usize {
 
 #[async_trait]
 impl Agent for HaikuAgent {
-    async fn process_message(&self, message: Message) -> Result<Message> {
+    async fn process_message(&self, message: Message) -> crate::Result<Message> {
         let guard = self.state_manager.read().await;
         let state = guard.get_current_state_name().map(|s| s.to_string());
         drop(guard); // Drop the read guard before acquiring write guards
@@ -197,7 +273,18 @@ impl Agent for HaikuAgent {
                     drop(state_manager);
 
                     // Generate the haiku
-                    let haiku = self.generate_haiku(topic).await?;
+                    let haiku = self.generate_haiku(topic.clone()).await?;
+                    let entry = HaikuEntry {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        topic,
+                        haiku: haiku.clone(),
+                        project: None,
+                        daily: false,
+                        created_at: Utc::now(),
+                    };
+                    if let Err(e) = self.memory.write().await.record(entry).await {
+                        log::warn!("Failed to record haiku in archive: {}", e);
+                    }
 
                     // Transition to complete state
                     self.state_manager.write().await.transition("haiku_generated")
@@ -221,7 +308,7 @@ impl Agent for HaikuAgent {
                     }
                 }
                 "goodbye" => self.create_response("Farewell, seeker of digital poetry.".to_string()).await,
-                _ => return Err(anyhow!("Invalid state: {}", state)),
+                _ => return Err(crate::SwarmError::State(format!("Invalid state: {}", state))),
             },
             None => self.create_response("🌸 What shall we crystallize into algorithmic verse today?".to_string()).await,
         };
@@ -229,23 +316,48 @@ impl Agent for HaikuAgent {
         Ok(response)
     }
 
-    async fn transfer_to(&self, target_agent: String, message: Message) -> Result<Message> {
+    async fn transfer_to(&self, target_agent: String, message: Message) -> crate::Result<Message> {
         Ok(Message::new(format!("Transferring to {} agent...", target_agent)))
     }
 
-    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
+    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> crate::Result<String> {
         Err(anyhow!("HaikuAgent does not support tool calls").into())
     }
 
-    async fn get_current_state(&self) -> Result<Option<State>> {
+    async fn get_current_state(&self) -> crate::Result<Option<State>> {
         Ok(self.state_manager.read().await.get_current_state().cloned())
     }
 
-    async fn get_config(&self) -> Result<AgentConfig> {
+    async fn get_config(&self) -> crate::Result<AgentConfig> {
         Ok(self.config.clone())
     }
 }
 
+/// Run `publish_daily_haiku` once a day at `hour` UTC until the task is aborted.
+pub fn spawn_daily_haiku_job(
+    agent: Arc<HaikuAgent>,
+    todo_list: TodoList,
+    client: MqttService,
+    hour: u32,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let now = Utc::now();
+            let today_run = now.date_naive().and_hms_opt(hour.min(23), 0, 0)
+                .expect("valid hour")
+                .and_utc();
+            let next_run = if today_run > now { today_run } else { today_run + ChronoDuration::days(1) };
+            let wait = (next_run - now).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+
+            match agent.publish_daily_haiku(&todo_list, &client).await {
+                Ok(entry) => log::info!("Published daily haiku about {}", entry.topic),
+                Err(e) => log::error!("Failed to publish daily haiku: {}", e),
+            }
+        }
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -324,7 +436,7 @@ mod tests {
         });
 
         // Replace the default AI client with our mock
-        agent = agent.with_ai_client(MockAiClient);
+        agent = agent.with_ai_client(MockAiClient).with_memory(HaikuMemory::in_memory());
 
         // First message transitions to generating state and stores the topic
         let response = agent.process_message(Message::new("nature".to_string())).await.unwrap();
@@ -337,6 +449,11 @@ mod tests {
         // Verify we're in the complete state
         let state = agent.get_current_state().await.unwrap();
         assert_eq!(state.unwrap().name, "complete");
+
+        // The haiku is remembered under its topic
+        let archive = agent.archive(0, 10).await;
+        assert_eq!(archive.len(), 1);
+        assert_eq!(archive[0].topic, "nature");
     }
 
     #[tokio::test]
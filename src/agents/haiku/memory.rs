@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use anyhow::{Result, anyhow};
+use crate::types::TodoTask;
+
+/// How many of the most recent haikus count as "recent" when avoiding repeats
+const DEFAULT_RECENT_WINDOW: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HaikuEntry {
+    pub id: String,
+    pub topic: String,
+    pub haiku: String,
+    pub project: Option<String>,
+    pub daily: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Archive of generated haikus, persisted as JSON so topic memory survives restarts.
+#[derive(Debug, Clone)]
+pub struct HaikuMemory {
+    path: Option<PathBuf>,
+    entries: Vec<HaikuEntry>,
+    recent_window: usize,
+}
+
+impl HaikuMemory {
+    /// A memory that is never written to disk
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            entries: Vec::new(),
+            recent_window: DEFAULT_RECENT_WINDOW,
+        }
+    }
+
+    /// Load the archive at `path`, starting empty if the file does not exist yet
+    pub fn load<P: Into<PathBuf>>(path: P) -> Result<Self> {
+        let path = path.into();
+        let entries = if path.exists() {
+            let contents = std::fs::read_to_string(&path)?;
+            serde_json::from_str(&contents)
+                .map_err(|e| anyhow!("Failed to parse haiku archive {}: {}", path.display(), e))?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            path: Some(path),
+            entries,
+            recent_window: DEFAULT_RECENT_WINDOW,
+        })
+    }
+
+    /// Uses `HAIKU_ARCHIVE_PATH`, or a file under the system temp dir.
+    pub fn default_path() -> PathBuf {
+        std::env::var("HAIKU_ARCHIVE_PATH")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| std::env::temp_dir().join("swarmonomicon").join("haiku_archive.json"))
+    }
+
+    pub fn from_env() -> Self {
+        let path = Self::default_path();
+        Self::load(&path).unwrap_or_else(|e| {
+            log::warn!("Failed to load haiku archive, starting fresh: {}", e);
+            Self {
+                path: Some(path),
+                entries: Vec::new(),
+                recent_window: DEFAULT_RECENT_WINDOW,
+            }
+        })
+    }
+
+    pub fn with_recent_window(mut self, recent_window: usize) -> Self {
+        self.recent_window = recent_window;
+        self
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Topics of the most recent haikus, newest first
+    pub fn recent_topics(&self) -> Vec<String> {
+        self.entries.iter()
+            .rev()
+            .take(self.recent_window)
+            .map(|entry| entry.topic.clone())
+            .collect()
+    }
+
+    pub fn is_recent(&self, topic: &str) -> bool {
+        let topic = normalize_topic(topic);
+        self.entries.iter()
+            .rev()
+            .take(self.recent_window)
+            .any(|entry| normalize_topic(&entry.topic) == topic)
+    }
+
+    /// Previous haikus written about `topic`, newest first
+    pub fn haikus_for_topic(&self, topic: &str) -> Vec<&HaikuEntry> {
+        let topic = normalize_topic(topic);
+        self.entries.iter()
+            .rev()
+            .filter(|entry| normalize_topic(&entry.topic) == topic)
+            .collect()
+    }
+
+    /// Archive page, newest first
+    pub fn archive(&self, offset: usize, limit: usize) -> Vec<HaikuEntry> {
+        self.entries.iter()
+            .rev()
+            .skip(offset)
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    pub async fn record(&mut self, entry: HaikuEntry) -> Result<()> {
+        self.entries.push(entry);
+        self.save().await
+    }
+
+    async fn save(&self) -> Result<()> {
+        if let Some(path) = &self.path {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            let contents = serde_json::to_string_pretty(&self.entries)?;
+            tokio::fs::write(path, contents).await?;
+        }
+        Ok(())
+    }
+}
+
+fn normalize_topic(topic: &str) -> String {
+    topic.trim().to_lowercase()
+}
+
+/// Projects ranked by how many tasks were created or completed on `day`, busiest first.
+pub fn rank_projects_by_activity(tasks: &[TodoTask], day: NaiveDate) -> Vec<(String, usize)> {
+    let on_day = |timestamp: i64| {
+        DateTime::from_timestamp(timestamp, 0)
+            .map(|dt| dt.date_naive() == day)
+            .unwrap_or(false)
+    };
+
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for task in tasks {
+        let active = on_day(task.created_at) || task.completed_at.map(on_day).unwrap_or(false);
+        if active {
+            if let Some(project) = &task.project {
+                *counts.entry(project.clone()).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut ranked: Vec<_> = counts.into_iter().collect();
+    ranked.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::TaskPriority;
+
+    fn entry(topic: &str) -> HaikuEntry {
+        HaikuEntry {
+            id: uuid::Uuid::new_v4().to_string(),
+            topic: topic.to_string(),
+            haiku: "a\nb\nc".to_string(),
+            project: None,
+            daily: false,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn task(project: &str, created_at: i64) -> TodoTask {
+        TodoTask {
+            id: uuid::Uuid::new_v4().to_string(),
+            description: "task".to_string(),
+            priority: TaskPriority::Medium,
+            project: Some(project.to_string()),
+            target_agent: "user".to_string(),
+            created_at,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recent_topics_and_persistence() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("haiku.json");
+
+        let mut memory = HaikuMemory::load(&path)?.with_recent_window(2);
+        memory.record(entry("Rust")).await?;
+        memory.record(entry("mqtt")).await?;
+        memory.record(entry("autumn")).await?;
+
+        assert!(memory.is_recent("MQTT"));
+        assert!(!memory.is_recent("rust"));
+        assert_eq!(memory.recent_topics(), vec!["autumn", "mqtt"]);
+
+        let reloaded = HaikuMemory::load(&path)?;
+        assert_eq!(reloaded.len(), 3);
+        assert_eq!(reloaded.archive(0, 1)[0].topic, "autumn");
+        assert_eq!(reloaded.archive(2, 10)[0].topic, "Rust");
+        Ok(())
+    }
+
+    #[test]
+    fn test_rank_projects_by_activity() {
+        let day = NaiveDate::from_ymd_opt(2025, 3, 1).unwrap();
+        let on_day = day.and_hms_opt(12, 0, 0).unwrap().and_utc().timestamp();
+        let day_before = on_day - 86_400;
+
+        let tasks = vec![
+            task("swarmonomicon", on_day),
+            task("swarmonomicon", on_day),
+            task("omnispindle", on_day),
+            task("omnispindle", day_before),
+        ];
+
+        let ranked = rank_projects_by_activity(&tasks, day);
+        assert_eq!(ranked[0], ("swarmonomicon".to_string(), 2));
+        assert_eq!(ranked[1], ("omnispindle".to_string(), 1));
+    }
+}
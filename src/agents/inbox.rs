@@ -0,0 +1,332 @@
+//! Operations that wait for a person to approve them. Calls to gated tools
+//! (`APPROVAL_REQUIRED`) are held here until someone approves, rejects or
+//! edits them, or until the approval times out.
+
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+use crate::events::{Event, EventBus};
+
+/// How long a gated operation waits when `APPROVAL_TIMEOUT_SECS` is unset
+pub const DEFAULT_APPROVAL_TIMEOUT: Duration = Duration::from_secs(900);
+/// Decided approvals kept for listing; older ones are dropped
+const MAX_DECIDED: usize = 200;
+
+static SHARED: OnceLock<Arc<ApprovalInbox>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalStatus {
+    Pending,
+    Approved,
+    Rejected,
+    /// Nobody decided before the timeout; the operation did not run
+    Expired,
+}
+
+/// One operation held for approval
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Approval {
+    pub id: String,
+    pub tool: String,
+    /// What the operation runs with once approved, including any edits
+    pub params: HashMap<String, String>,
+    pub status: ApprovalStatus,
+    pub requested_at: i64,
+    pub decided_at: Option<i64>,
+    /// Reason given with the decision
+    pub note: Option<String>,
+}
+
+impl Approval {
+    /// `tool command`, for logs and error messages
+    pub fn summary(&self) -> String {
+        match self.params.get("command") {
+            Some(command) => format!("{} {}", self.tool, command),
+            None => self.tool.clone(),
+        }
+    }
+}
+
+/// A tool, or one command of it, that needs approval
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Gate {
+    tool: String,
+    /// Leading words of the `command` param; every call to the tool when absent
+    command: Option<Vec<String>>,
+}
+
+impl Gate {
+    /// `git:push`, `balena:push` or a bare tool name
+    pub(crate) fn parse(spec: &str) -> Option<Self> {
+        let (tool, command) = match spec.split_once(':') {
+            Some((tool, command)) => (tool, Some(command.split_whitespace().map(str::to_string).collect())),
+            None => (spec, None),
+        };
+        let tool = tool.trim();
+        (!tool.is_empty()).then(|| Self { tool: tool.to_string(), command })
+    }
+
+    pub(crate) fn matches(&self, tool: &str, params: &HashMap<String, String>) -> bool {
+        if self.tool != tool {
+            return false;
+        }
+        let Some(words) = &self.command else {
+            return true;
+        };
+        let command = params.get("command").map(String::as_str).unwrap_or_default();
+        let given: Vec<&str> = command.split_whitespace().collect();
+        given.len() >= words.len() && words.iter().zip(&given).all(|(word, given)| word == given)
+    }
+}
+
+#[derive(Debug)]
+pub struct ApprovalInbox {
+    approvals: Mutex<Vec<Approval>>,
+    /// Callers waiting on a pending approval, by id
+    waiting: Mutex<HashMap<String, oneshot::Sender<Approval>>>,
+    gates: Vec<Gate>,
+    timeout: Duration,
+    events: EventBus,
+}
+
+impl Default for ApprovalInbox {
+    fn default() -> Self {
+        Self {
+            approvals: Mutex::new(Vec::new()),
+            waiting: Mutex::new(HashMap::new()),
+            gates: Vec::new(),
+            timeout: DEFAULT_APPROVAL_TIMEOUT,
+            events: EventBus::shared(),
+        }
+    }
+}
+
+impl ApprovalInbox {
+    /// Hold calls to `spec` for approval: a tool name, or `tool:command` for
+    /// calls whose `command` param starts with `command`
+    pub fn with_gate(mut self, spec: &str) -> Self {
+        self.gates.extend(Gate::parse(spec));
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Gates from `APPROVAL_REQUIRED` (e.g. `git:push,balena:push,todo:delete`)
+    /// and the wait from `APPROVAL_TIMEOUT_SECS` (default 900)
+    pub fn from_env() -> Self {
+        let mut inbox = Self::default();
+        for spec in env::var("APPROVAL_REQUIRED").unwrap_or_default().split(',') {
+            inbox = inbox.with_gate(spec);
+        }
+        if let Some(secs) = env::var("APPROVAL_TIMEOUT_SECS").ok().and_then(|s| s.parse().ok()) {
+            inbox = inbox.with_timeout(Duration::from_secs(secs));
+        }
+        inbox
+    }
+
+    /// Process-wide instance read from the environment on first use
+    pub fn shared() -> Arc<Self> {
+        SHARED.get_or_init(|| Arc::new(Self::from_env())).clone()
+    }
+
+    /// Whether any operation needs approval at all
+    pub fn is_gated(&self) -> bool {
+        !self.gates.is_empty()
+    }
+
+    pub fn requires_approval(&self, tool: &str, params: &HashMap<String, String>) -> bool {
+        self.gates.iter().any(|gate| gate.matches(tool, params))
+    }
+
+    /// Wait for a person to approve a call to `tool`, returning the params it
+    /// should run with. Calls that need no approval get their params straight
+    /// back; rejected and expired ones are errors.
+    pub async fn approve_call(&self, tool: &str, params: HashMap<String, String>) -> Result<HashMap<String, String>> {
+        if !self.requires_approval(tool, &params) {
+            return Ok(params);
+        }
+
+        let approval = Approval {
+            id: uuid::Uuid::new_v4().to_string(),
+            tool: tool.to_string(),
+            params,
+            status: ApprovalStatus::Pending,
+            requested_at: Utc::now().timestamp(),
+            decided_at: None,
+            note: None,
+        };
+        let (sender, decision) = oneshot::channel();
+        self.waiting.lock().unwrap().insert(approval.id.clone(), sender);
+        self.approvals.lock().unwrap().push(approval.clone());
+        tracing::info!("{} is waiting for approval as {}", approval.summary(), approval.id);
+        self.events.publish(Event::ApprovalRequested {
+            id: approval.id.clone(),
+            tool: approval.tool.clone(),
+            params: approval.params.clone(),
+        });
+
+        let decided = match tokio::time::timeout(self.timeout, decision).await {
+            Ok(Ok(decided)) => decided,
+            Ok(Err(_)) => return Err(anyhow!("{} was dropped from the inbox", approval.summary())),
+            Err(_) => {
+                self.decide(&approval.id, ApprovalStatus::Expired, None);
+                return Err(anyhow!("{} was not approved within {}s", approval.summary(), self.timeout.as_secs()));
+            }
+        };
+        match decided.status {
+            ApprovalStatus::Approved => Ok(decided.params),
+            _ => Err(anyhow!(
+                "{} was rejected{}",
+                decided.summary(),
+                decided.note.map(|note| format!(": {}", note)).unwrap_or_default()
+            )),
+        }
+    }
+
+    /// Every approval still waiting, oldest first
+    pub fn pending(&self) -> Vec<Approval> {
+        self.approvals.lock().unwrap().iter()
+            .filter(|approval| approval.status == ApprovalStatus::Pending)
+            .cloned()
+            .collect()
+    }
+
+    pub fn get(&self, id: &str) -> Option<Approval> {
+        self.approvals.lock().unwrap().iter().find(|approval| approval.id == id).cloned()
+    }
+
+    /// Let a pending operation run. `None` if there is no such pending approval.
+    pub fn approve(&self, id: &str, note: Option<String>) -> Option<Approval> {
+        self.decide(id, ApprovalStatus::Approved, note)
+    }
+
+    /// Refuse a pending operation. `None` if there is no such pending approval.
+    pub fn reject(&self, id: &str, note: Option<String>) -> Option<Approval> {
+        self.decide(id, ApprovalStatus::Rejected, note)
+    }
+
+    /// Change the params a pending operation will run with. `None` if there
+    /// is no such pending approval.
+    pub fn edit(&self, id: &str, params: HashMap<String, String>) -> Option<Approval> {
+        let mut approvals = self.approvals.lock().unwrap();
+        let approval = approvals.iter_mut()
+            .find(|approval| approval.id == id && approval.status == ApprovalStatus::Pending)?;
+        approval.params.extend(params);
+        Some(approval.clone())
+    }
+
+    fn decide(&self, id: &str, status: ApprovalStatus, note: Option<String>) -> Option<Approval> {
+        let decided = {
+            let mut approvals = self.approvals.lock().unwrap();
+            let approval = approvals.iter_mut()
+                .find(|approval| approval.id == id && approval.status == ApprovalStatus::Pending)?;
+            approval.status = status;
+            approval.decided_at = Some(Utc::now().timestamp());
+            approval.note = note;
+            let decided = approval.clone();
+
+            let finished = approvals.iter().filter(|approval| approval.status != ApprovalStatus::Pending).count();
+            if finished > MAX_DECIDED {
+                let mut excess = finished - MAX_DECIDED;
+                approvals.retain(|approval| {
+                    let drop = excess > 0 && approval.status != ApprovalStatus::Pending;
+                    excess -= drop as usize;
+                    !drop
+                });
+            }
+            decided
+        };
+
+        if let Some(waiter) = self.waiting.lock().unwrap().remove(id) {
+            let _ = waiter.send(decided.clone());
+        }
+        tracing::info!("{} ({}) is {:?}", decided.summary(), decided.id, decided.status);
+        self.events.publish(Event::ApprovalDecided {
+            id: decided.id.clone(),
+            tool: decided.tool.clone(),
+            status: decided.status,
+        });
+        Some(decided)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push() -> HashMap<String, String> {
+        HashMap::from([("command".to_string(), "push".to_string()), ("branch".to_string(), "main".to_string())])
+    }
+
+    #[test]
+    fn test_gates() {
+        let inbox = ApprovalInbox::default().with_gate("git:push").with_gate("todo").with_gate(" ");
+        assert!(inbox.requires_approval("git", &push()));
+        assert!(!inbox.requires_approval("git", &HashMap::from([("command".to_string(), "diff".to_string())])));
+        assert!(inbox.requires_approval("todo", &HashMap::new()));
+        assert!(!inbox.requires_approval("balena", &push()));
+
+        let inbox = ApprovalInbox::default().with_gate("shell:git push");
+        assert!(inbox.requires_approval("shell", &HashMap::from([("command".to_string(), "git push origin".to_string())])));
+        assert!(!inbox.requires_approval("shell", &HashMap::from([("command".to_string(), "git status".to_string())])));
+    }
+
+    #[tokio::test]
+    async fn test_edited_call_runs_once_approved() {
+        let inbox = Arc::new(ApprovalInbox::default().with_gate("git:push").with_event_bus(EventBus::new(8)));
+        // Ungated calls go straight through
+        let diff = HashMap::from([("command".to_string(), "diff".to_string())]);
+        assert_eq!(inbox.approve_call("git", diff.clone()).await.unwrap(), diff);
+
+        let waiting = tokio::spawn({
+            let inbox = inbox.clone();
+            async move { inbox.approve_call("git", push()).await }
+        });
+        while inbox.pending().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        let id = inbox.pending()[0].id.clone();
+        inbox.edit(&id, HashMap::from([("branch".to_string(), "release".to_string())])).unwrap();
+        assert_eq!(inbox.approve(&id, None).unwrap().status, ApprovalStatus::Approved);
+
+        let params = waiting.await.unwrap().unwrap();
+        assert_eq!(params["branch"], "release");
+        assert!(inbox.pending().is_empty());
+        // Already decided
+        assert!(inbox.reject(&id, None).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_rejected_and_expired_calls_fail() {
+        let inbox = Arc::new(ApprovalInbox::default().with_gate("git:push").with_event_bus(EventBus::new(8)));
+        let waiting = tokio::spawn({
+            let inbox = inbox.clone();
+            async move { inbox.approve_call("git", push()).await }
+        });
+        while inbox.pending().is_empty() {
+            tokio::task::yield_now().await;
+        }
+        inbox.reject(&inbox.pending()[0].id, Some("not on a Friday".to_string()));
+        let error = waiting.await.unwrap().unwrap_err().to_string();
+        assert!(error.contains("rejected: not on a Friday"), "{}", error);
+
+        let inbox = ApprovalInbox::default().with_gate("git").with_timeout(Duration::from_millis(10))
+            .with_event_bus(EventBus::new(8));
+        assert!(inbox.approve_call("git", push()).await.unwrap_err().to_string().contains("not approved"));
+        assert!(inbox.pending().is_empty());
+    }
+}
@@ -104,6 +104,17 @@ impl Action for RoutingAction {
 }
 
 impl RoutingAction {
+    /// Every routing choice, available in any state
+    pub fn all() -> Vec<Self> {
+        vec![
+            RoutingAction::StayWithCurrent,
+            RoutingAction::TransferToGreeter,
+            RoutingAction::TransferToGit,
+            RoutingAction::TransferToProject,
+            RoutingAction::TransferToHaiku,
+        ]
+    }
+
     pub fn to_agent_name(&self, current_agent: &str) -> String {
         match self {
             RoutingAction::StayWithCurrent => current_agent.to_string(),
@@ -144,8 +155,7 @@ impl AgentRoutingPolicy {
     #[cfg(feature = "rl")]
     pub async fn load_model(tracker: InteractionTracker, model_path: &str) -> Result<Self> {
         let mut agent = QLearningAgent::new(0.1, 0.95, 0.1);
-        agent.load_model(model_path).await
-            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        agent.load_model(model_path).await?;
 
         Ok(Self {
             agent,
@@ -171,13 +181,7 @@ impl AgentRoutingPolicy {
                 last_success,
             };
 
-            let valid_actions = vec![
-                RoutingAction::StayWithCurrent,
-                RoutingAction::TransferToGreeter,
-                RoutingAction::TransferToGit,
-                RoutingAction::TransferToProject,
-                RoutingAction::TransferToHaiku,
-            ];
+            let valid_actions = RoutingAction::all();
 
             let action = self.agent.choose_action(&state, &valid_actions);
             return action.to_agent_name(current_agent);
@@ -225,7 +229,7 @@ impl AgentRoutingPolicy {
         };
 
         // Update Q-values
-        self.agent.update(&state, &action, reward, &next_state);
+        self.agent.update(&state, &action, reward, &next_state, &RoutingAction::all());
     }
 
     /// Train the policy from historical interactions
@@ -283,7 +287,7 @@ impl AgentRoutingPolicy {
                 };
 
                 // Update
-                self.agent.update(&state, &action, reward, &next_state);
+                self.agent.update(&state, &action, reward, &next_state, &RoutingAction::all());
             }
         }
 
@@ -293,8 +297,7 @@ impl AgentRoutingPolicy {
     /// Save the trained model
     #[cfg(feature = "rl")]
     pub async fn save_model(&self, model_path: &str) -> Result<()> {
-        self.agent.save_model(model_path).await
-            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        self.agent.save_model(model_path).await?;
         Ok(())
     }
 
@@ -169,9 +169,7 @@ impl LearningTransferService {
             {
                 // Fallback: use preference predictor
                 if let (Some(predictor), Some(ref uid)) = (&self.preference_predictor, &user_id) {
-                    let registry = self.registry.read().await;
-                    let agents: Vec<String> = registry.iter().map(|(name, _)| name.clone()).collect();
-                    drop(registry);
+                    let agents = self.registry.read().await.names();
 
                     predictor.predict_best_agent(uid, &agents).await?
                         .unwrap_or_else(|| from.to_string())
@@ -205,10 +203,9 @@ impl LearningTransferService {
         }
 
         // Get the source agent and perform transfer
-        let source_agent = {
-            let registry = self.registry.read().await;
-            registry.get(from).unwrap().clone()
-        };
+        let source_agent = self.registry.read().await.get(from)
+            .ok_or_else(|| anyhow!("Source agent '{}' not found", from))?;
+        let source_agent = source_agent.read().await.clone();
 
         let result = source_agent.transfer_to(to.to_string(), message).await?;
 
@@ -340,10 +337,10 @@ impl LearningTransferService {
     }
 
     async fn get_agent(&self, name: &str) -> Result<Arc<Box<dyn Agent + Send + Sync>>> {
-        let registry = self.registry.read().await;
-        registry.get(name)
-            .map(|wrapper| Arc::new(Box::new(wrapper.clone()) as Box<dyn Agent + Send + Sync>))
-            .ok_or_else(|| anyhow!("Agent '{}' not found", name))
+        let handle = self.registry.read().await.get(name)
+            .ok_or_else(|| anyhow!("Agent '{}' not found", name))?;
+        let wrapper = handle.read().await.clone();
+        Ok(Arc::new(Box::new(wrapper) as Box<dyn Agent + Send + Sync>))
     }
 
     async fn get_current_agent_name(&self) -> Result<String> {
@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::Arc;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use regex::Regex;
+use crate::tools::middleware::DEFAULT_LOG_CHARS;
+use crate::tools::summarizer::truncate_middle;
+use crate::types::Message;
+
+/// Hooks an [`AgentWrapper`](super::AgentWrapper) runs around its agent.
+/// `before` hooks run in the order the middleware was added and `after` hooks
+/// in reverse, so the first middleware sees the raw request and the final
+/// response. An error from either stops the message there.
+#[async_trait]
+pub trait AgentMiddleware: Send + Sync {
+    async fn before(&self, message: Message) -> Result<Message> {
+        Ok(message)
+    }
+
+    async fn after(&self, response: Message) -> Result<Message> {
+        Ok(response)
+    }
+}
+
+/// Logs each message an agent receives and each reply, cut down to `max_chars`
+pub struct MessageLogging {
+    agent: String,
+    max_chars: usize,
+}
+
+impl MessageLogging {
+    pub fn new(agent: impl Into<String>, max_chars: usize) -> Self {
+        Self { agent: agent.into(), max_chars }
+    }
+
+    /// Reads `TOOL_LOG_CHARS` (default 200), like the tool logging middleware
+    pub fn from_env(agent: impl Into<String>) -> Self {
+        Self::new(agent, env::var("TOOL_LOG_CHARS").ok().and_then(|s| s.parse().ok()).unwrap_or(DEFAULT_LOG_CHARS))
+    }
+}
+
+#[async_trait]
+impl AgentMiddleware for MessageLogging {
+    async fn before(&self, message: Message) -> Result<Message> {
+        tracing::debug!(agent = %self.agent, "Agent input: {}", truncate_middle(&message.content, self.max_chars));
+        Ok(message)
+    }
+
+    async fn after(&self, response: Message) -> Result<Message> {
+        tracing::debug!(agent = %self.agent, "Agent output: {}", truncate_middle(&response.content, self.max_chars));
+        Ok(response)
+    }
+}
+
+/// Masks blocked words, whole and in any case, in messages going to the
+/// agent and in its replies
+pub struct ContentFilter {
+    pattern: Option<Regex>,
+}
+
+impl ContentFilter {
+    pub fn new<S: AsRef<str>>(blocked: &[S]) -> Self {
+        let words: Vec<String> = blocked.iter()
+            .map(|word| word.as_ref().trim())
+            .filter(|word| !word.is_empty())
+            .map(regex::escape)
+            .collect();
+        let pattern = (!words.is_empty())
+            .then(|| Regex::new(&format!(r"(?i)\b(?:{})\b", words.join("|"))).expect("escaped words form a valid pattern"));
+        Self { pattern }
+    }
+
+    /// Blocks the comma-separated words in `AGENT_BLOCKED_WORDS`
+    pub fn from_env() -> Self {
+        let words = env::var("AGENT_BLOCKED_WORDS").unwrap_or_default();
+        Self::new(&words.split(',').collect::<Vec<_>>())
+    }
+
+    pub fn filter(&self, text: &str) -> String {
+        match &self.pattern {
+            Some(pattern) => pattern.replace_all(text, "***").into_owned(),
+            None => text.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl AgentMiddleware for ContentFilter {
+    async fn before(&self, mut message: Message) -> Result<Message> {
+        message.content = self.filter(&message.content);
+        Ok(message)
+    }
+
+    async fn after(&self, mut response: Message) -> Result<Message> {
+        response.content = self.filter(&response.content);
+        Ok(response)
+    }
+}
+
+/// Built-in middleware by the name `AGENT_MIDDLEWARE` uses: `log` or `filter`
+pub fn named(agent: &str, name: &str) -> Result<Arc<dyn AgentMiddleware>> {
+    match name {
+        "log" => Ok(Arc::new(MessageLogging::from_env(agent))),
+        "filter" => Ok(Arc::new(ContentFilter::from_env())),
+        other => Err(anyhow!("Unknown agent middleware '{}', expected log or filter", other)),
+    }
+}
+
+/// Parse `agent=name+name,agent=name` into middleware chains by agent name.
+/// `*` applies to every agent, ahead of the agent's own. Unknown names are
+/// logged and skipped.
+pub fn parse_chains(spec: &str) -> HashMap<String, Vec<Arc<dyn AgentMiddleware>>> {
+    let mut chains: HashMap<String, Vec<Arc<dyn AgentMiddleware>>> = HashMap::new();
+    for (agent, names) in spec.split(',').filter_map(|pair| pair.split_once('=')) {
+        let agent = agent.trim();
+        if agent.is_empty() {
+            continue;
+        }
+        for name in names.split('+').map(str::trim).filter(|name| !name.is_empty()) {
+            match named(agent, name) {
+                Ok(middleware) => chains.entry(agent.to_string()).or_default().push(middleware),
+                Err(e) => tracing::warn!("AGENT_MIDDLEWARE: {}", e),
+            }
+        }
+    }
+    chains
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_filter() {
+        let filter = ContentFilter::new(&["darn", "heck "]);
+        assert_eq!(filter.filter("Darn it, what the HECK!"), "*** it, what the ***!");
+        // Whole words only
+        assert_eq!(filter.filter("darning"), "darning");
+        assert_eq!(ContentFilter::new::<&str>(&[]).filter("darn"), "darn");
+    }
+
+    #[test]
+    fn test_parse_chains() {
+        let chains = parse_chains("git=log+filter, *=log, greeter=bogus, =log");
+        assert_eq!(chains["git"].len(), 2);
+        assert_eq!(chains["*"].len(), 1);
+        assert!(!chains.contains_key("greeter"));
+        assert_eq!(chains.len(), 2);
+    }
+}
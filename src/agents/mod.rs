@@ -1,8 +1,8 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::Arc;
 use std::any::Any;
 use tokio::sync::RwLock;
-use crate::types::{Agent, AgentConfig, Message, MessageMetadata, State, AgentStateManager, StateMachine, ValidationRule, ToolCall, Tool, TodoProcessor};
+use crate::types::{Agent, AgentConfig, Message, MessageMetadata, Readiness, State, AgentStateManager, StateMachine, ValidationRule, ToolCall, Tool, TodoProcessor};
 use anyhow::Result;
 use lazy_static::lazy_static;
 use anyhow::anyhow;
@@ -34,9 +34,27 @@ pub mod project;
 #[cfg(feature = "project-agent")]
 pub use project::ProjectAgent;
 
+#[cfg(feature = "eventghost-agent")]
+pub mod eventghost;
+#[cfg(feature = "eventghost-agent")]
+pub use eventghost::EventGhostAgent;
+
+#[cfg(feature = "planner-agent")]
+pub mod planner;
+#[cfg(feature = "planner-agent")]
+pub use planner::PlannerAgent;
+
 pub mod user_agent;
+pub mod inbox;
+pub mod audit;
 pub mod transfer;
+pub mod remote;
+pub mod discovery;
 pub mod wrapper;
+pub mod middleware;
+pub mod moderation;
+pub mod structured;
+pub mod reviewer;
 #[cfg(feature = "rl")]
 pub mod rl;
 pub mod learning;
@@ -45,33 +63,95 @@ pub mod task_intelligence;
 pub mod swarm_coordination;
 
 pub use user_agent::UserAgent;
+pub use inbox::{Approval, ApprovalInbox, ApprovalStatus};
+pub use audit::{AgentAuditLog, AuditEntry, AuditOutcome, AuditQuery};
 pub use transfer::TransferService;
+pub use remote::{RemoteAgents, RemoteEnvelope, RemoteReply};
+pub use discovery::{NodeManifest, NodeStatus, SwarmDirectory};
 pub use wrapper::AgentWrapper;
-
+pub use middleware::{AgentMiddleware, ContentFilter, MessageLogging};
+pub use moderation::{ModerationViolation, OutputFilter};
+pub use structured::{StructuredOutput, StructuredOutputError};
+pub use reviewer::{Critique, Review, ReviewerAgent};
+
+/// One registered agent behind its own lock. Messages take it shared, so an
+/// agent can answer several at once; only changes to the agent itself take it
+/// exclusively, and those wait on that agent alone.
+pub type AgentHandle = Arc<RwLock<AgentWrapper>>;
+
+/// Registered agents by name. The registry's own lock (callers share it as
+/// `Arc<RwLock<AgentRegistry>>`) only guards the name table and the current
+/// agent: look an agent up, let the registry guard go, then work through the
+/// agent's [`AgentHandle`].
 pub struct AgentRegistry {
-    pub agents: HashMap<String, AgentWrapper>,
+    agents: HashMap<String, AgentHandle>,
     current_agent: Option<String>,
+    /// Middleware given to agents as they register, by agent name; `*` is for every agent
+    middleware: HashMap<String, Vec<Arc<dyn AgentMiddleware>>>,
+    /// Reviews the replies of the agents named in `reviewed`; `*` is for every agent
+    reviewer: Option<Arc<ReviewerAgent>>,
+    reviewed: Vec<String>,
+    /// Agents left to warm up on their first message; `*` is for every agent
+    lazy: Vec<String>,
 }
 
 impl AgentRegistry {
+    /// Agents get the middleware named for them in `AGENT_MIDDLEWARE`
+    /// (e.g. `*=log,greeter=filter`) when they register, and the agents listed
+    /// in `AGENT_REVIEW` (e.g. `git,haiku`) have their replies reviewed.
+    /// Those in `AGENT_LAZY_START` are skipped by [`start_all`](Self::start_all).
     pub fn new() -> Self {
+        let reviewed = env_list("AGENT_REVIEW");
+        let reviewer = (!reviewed.is_empty())
+            .then(|| Arc::new(ReviewerAgent::from_env(reviewer::default_config())));
         Self {
             agents: HashMap::new(),
             current_agent: None,
+            middleware: middleware::parse_chains(&std::env::var("AGENT_MIDDLEWARE").unwrap_or_default()),
+            reviewer,
+            reviewed,
+            lazy: env_list("AGENT_LAZY_START"),
         }
     }
 
-    pub async fn register(&mut self, name: String, agent: Box<dyn Agent + Send + Sync>) -> Result<()> {
-        self.agents.insert(name, AgentWrapper::new(agent));
-        Ok(())
+    /// Leave `agents` (`*` for all) to warm up on their first message
+    pub fn with_lazy_start(mut self, agents: &[&str]) -> Self {
+        self.lazy = agents.iter().map(|agent| agent.to_string()).collect();
+        self
+    }
+
+    /// Review the replies of `agents` (`*` for all) with `reviewer` as they register
+    pub fn with_reviewer(mut self, reviewer: ReviewerAgent, agents: &[&str]) -> Self {
+        self.reviewer = Some(Arc::new(reviewer));
+        self.reviewed = agents.iter().map(|agent| agent.to_string()).collect();
+        self
+    }
+
+    /// Give `agent` this middleware when it registers, after any it already has
+    pub fn with_agent_middleware<M: AgentMiddleware + 'static>(mut self, agent: impl Into<String>, middleware: M) -> Self {
+        self.middleware.entry(agent.into()).or_default().push(Arc::new(middleware));
+        self
     }
 
-    pub fn get(&self, name: &str) -> Option<&AgentWrapper> {
-        self.agents.get(name)
+    pub async fn register(&mut self, name: String, agent: Box<dyn Agent + Send + Sync>) -> Result<()> {
+        let chain = ["*", name.as_str()].into_iter()
+            .flat_map(|key| self.middleware.get(key).into_iter().flatten().cloned())
+            .collect::<Vec<_>>();
+        let mut wrapper = AgentWrapper::new(agent).with_middleware_chain(chain);
+        if let Some(reviewer) = &self.reviewer {
+            // The reviewer's own replies are never reviewed
+            if name != "reviewer" && self.reviewed.iter().any(|agent| agent == "*" || *agent == name) {
+                wrapper = wrapper.with_reviewer(reviewer.clone());
+            }
+        }
+        self.agents.insert(name, Arc::new(RwLock::new(wrapper)));
+        Ok(())
     }
 
-    pub fn get_mut(&mut self, name: &str) -> Option<&mut AgentWrapper> {
-        self.agents.get_mut(name)
+    /// `name`'s handle. Hold on to the handle, not the registry guard, while
+    /// the agent works.
+    pub fn get(&self, name: &str) -> Option<AgentHandle> {
+        self.agents.get(name).cloned()
     }
 
     pub fn exists(&self, name: &str) -> bool {
@@ -86,20 +166,60 @@ impl AgentRegistry {
         self.current_agent = Some(agent);
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = (&String, &AgentWrapper)> {
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &AgentHandle)> {
         self.agents.iter()
     }
 
+    pub fn names(&self) -> Vec<String> {
+        self.agents.keys().cloned().collect()
+    }
+
+    /// Begin warming up every agent not marked lazy, in the background.
+    /// Messages that arrive first wait for their agent's warm-up.
+    pub fn start_all(&self) {
+        for (name, handle) in &self.agents {
+            if self.lazy.iter().any(|agent| agent == "*" || agent == name) {
+                continue;
+            }
+            let handle = handle.clone();
+            tokio::spawn(async move {
+                let agent = handle.read().await.clone();
+                // The wrapper logs how it went
+                let _ = agent.ensure_started().await;
+            });
+        }
+    }
+
+    /// Each agent's warm-up progress, by name
+    pub async fn readiness(&self) -> BTreeMap<String, Readiness> {
+        let mut readiness = BTreeMap::new();
+        for (name, handle) in &self.agents {
+            readiness.insert(name.clone(), handle.read().await.readiness());
+        }
+        readiness
+    }
+
+    /// Build the agents for `configs` and start warming them up
     pub async fn create_default_agents(configs: Vec<AgentConfig>) -> Result<Self> {
         let mut registry = Self::new();
         for config in configs {
             let agent = create_agent(config.clone()).await?;
             registry.register(config.name, agent).await?;
         }
+        registry.start_all();
         Ok(registry)
     }
 }
 
+/// Comma-separated agent names from `var`
+fn env_list(var: &str) -> Vec<String> {
+    std::env::var(var).unwrap_or_default()
+        .split(',')
+        .map(|agent| agent.trim().to_string())
+        .filter(|agent| !agent.is_empty())
+        .collect()
+}
+
 pub async fn create_agent(config: AgentConfig) -> Result<Box<dyn Agent + Send + Sync>> {
     match config.name.as_str() {
         #[cfg(feature = "project-agent")]
@@ -127,6 +247,25 @@ pub async fn create_agent(config: AgentConfig) -> Result<Box<dyn Agent + Send +
             let agent = browser::BrowserAgentWrapper::new(config)?;
             Ok(Box::new(agent))
         }
+        #[cfg(feature = "eventghost-agent")]
+        "eventghost" => {
+            let agent = EventGhostAgent::from_env(config)?;
+            Ok(Box::new(agent))
+        }
+        #[cfg(feature = "reviewer-agent")]
+        "reviewer" => {
+            let agent = ReviewerAgent::from_env(config);
+            Ok(Box::new(agent))
+        }
+        #[cfg(feature = "planner-agent")]
+        "planner" => {
+            let agent = PlannerAgent::from_env(config).await?;
+            Ok(Box::new(agent))
+        }
+        "user" => {
+            let agent = UserAgent::new(config);
+            Ok(Box::new(agent))
+        }
         _ => Err(anyhow!("Unknown agent type: {}", config.name)),
     }
 }
@@ -142,17 +281,17 @@ pub async fn register_agent(agent: Box<dyn Agent + Send + Sync>) -> Result<()> {
 }
 
 pub async fn get_agent(name: &str) -> Option<Arc<Box<dyn Agent + Send + Sync>>> {
-    let registry = GLOBAL_REGISTRY.read().await;
-    registry.get(name).map(|wrapper| {
-        let boxed: Box<dyn Agent + Send + Sync> = Box::new(wrapper.clone());
-        Arc::new(boxed)
-    })
+    let handle = GLOBAL_REGISTRY.read().await.get(name)?;
+    let wrapper = handle.read().await.clone();
+    let boxed: Box<dyn Agent + Send + Sync> = Box::new(wrapper);
+    Some(Arc::new(boxed))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::types::{Message, State, StateMachine, AgentStateManager};
+    use crate::error::SwarmError;
     use crate::agents::greeter::GreeterAgent;
 
     fn create_test_configs() -> Vec<AgentConfig> {
@@ -200,9 +339,9 @@ mod tests {
         assert!(registry.get("greeter").is_some());
         assert!(registry.get("nonexistent").is_none());
 
-        // Test mutable access
-        if let Some(greeter) = registry.get_mut("greeter") {
-            let response = greeter.process_message(Message::new(String::from("hi"))).await?;
+        // Test exclusive access through the agent's own lock
+        if let Some(greeter) = registry.get("greeter") {
+            let response = greeter.write().await.process_message(Message::new(String::from("hi"))).await?;
             assert!(response.content.contains("Hello"));
         }
 
@@ -212,6 +351,92 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_busy_agent_does_not_hold_the_registry() -> Result<()> {
+        struct Waiting(Arc<tokio::sync::Notify>);
+
+        #[async_trait]
+        impl Agent for Waiting {
+            async fn process_message(&self, message: Message) -> crate::Result<Message> {
+                self.0.notified().await;
+                Ok(message)
+            }
+            async fn transfer_to(&self, _target_agent: String, message: Message) -> crate::Result<Message> {
+                Ok(message)
+            }
+            async fn call_tool(&self, _tool: &Tool, _params: HashMap<String, String>) -> crate::Result<String> {
+                Ok(String::new())
+            }
+            async fn get_current_state(&self) -> crate::Result<Option<State>> {
+                Ok(None)
+            }
+            async fn get_config(&self) -> crate::Result<AgentConfig> {
+                Err(SwarmError::NotFound("no config".to_string()))
+            }
+        }
+
+        let release = Arc::new(tokio::sync::Notify::new());
+        let registry = Arc::new(RwLock::new(AgentRegistry::new()));
+        registry.write().await.register("slow".to_string(), Box::new(Waiting(release.clone()))).await?;
+
+        let slow = registry.read().await.get("slow").unwrap();
+        let busy = tokio::spawn(async move { slow.read().await.process_message(Message::new("hi".to_string())).await });
+        tokio::task::yield_now().await;
+
+        // Registering and switching agents doesn't wait for the busy one
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            let mut registry = registry.write().await;
+            registry.register("other".to_string(), Box::new(Waiting(Arc::new(tokio::sync::Notify::new())))).await?;
+            registry.set_current_agent("other".to_string());
+            Ok::<_, anyhow::Error>(())
+        }).await??;
+
+        release.notify_one();
+        assert_eq!(busy.await??.content, "hi");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_start_all_skips_lazy_agents() -> Result<()> {
+        struct Quiet;
+
+        #[async_trait]
+        impl Agent for Quiet {
+            async fn process_message(&self, message: Message) -> crate::Result<Message> {
+                Ok(message)
+            }
+            async fn transfer_to(&self, _target_agent: String, message: Message) -> crate::Result<Message> {
+                Ok(message)
+            }
+            async fn call_tool(&self, _tool: &Tool, _params: HashMap<String, String>) -> crate::Result<String> {
+                Ok(String::new())
+            }
+            async fn get_current_state(&self) -> crate::Result<Option<State>> {
+                Ok(None)
+            }
+            async fn get_config(&self) -> crate::Result<AgentConfig> {
+                Err(SwarmError::NotFound("no config".to_string()))
+            }
+        }
+
+        let mut registry = AgentRegistry::new().with_lazy_start(&["lazy"]);
+        registry.register("eager".to_string(), Box::new(Quiet)).await?;
+        registry.register("lazy".to_string(), Box::new(Quiet)).await?;
+        registry.start_all();
+
+        tokio::time::timeout(std::time::Duration::from_secs(1), async {
+            while registry.readiness().await["eager"] != Readiness::Ready {
+                tokio::task::yield_now().await;
+            }
+        }).await?;
+        assert_eq!(registry.readiness().await["lazy"], Readiness::Pending);
+
+        let lazy = registry.get("lazy").unwrap();
+        lazy.read().await.process_message(Message::new("hi".to_string())).await?;
+        assert_eq!(registry.readiness().await["lazy"], Readiness::Ready);
+        Ok(())
+    }
+
     #[tokio::test]
     #[cfg(all(feature = "greeter-agent", feature = "haiku-agent"))]
     async fn test_agent_workflow() -> Result<()> {
@@ -378,5 +603,25 @@ pub fn default_agents() -> Vec<AgentConfig> {
         state_machine: None,
     });
 
+    #[cfg(feature = "reviewer-agent")]
+    agents.push(reviewer::default_config());
+
+    agents.push(user_agent::default_config());
+
+    // Plans across every agent above
+    #[cfg(feature = "planner-agent")]
+    {
+        let downstream_agents = agents.iter().map(|agent| agent.name.clone()).collect();
+        agents.push(AgentConfig {
+            name: "planner".to_string(),
+            public_description: "Agent that breaks large todos into ordered subtasks.".to_string(),
+            instructions: "Decompose todos into subtasks with dependencies and hand each to the right agent.".to_string(),
+            tools: Vec::new(),
+            downstream_agents,
+            personality: None,
+            state_machine: None,
+        });
+    }
+
     agents
 }
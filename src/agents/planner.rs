@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+use crate::ai::{AiProvider, DefaultAiClient};
+use crate::agents::structured::strip_code_fence;
+use crate::tools::{TodoTool, ToolExecutor};
+use crate::types::{Agent, AgentConfig, Message, MessageMetadata, State, TaskPriority, Tool};
+
+/// Most subtasks one plan may create; longer plans are cut short
+pub const DEFAULT_MAX_SUBTASKS: usize = 10;
+
+/// One step of a plan as the model writes it. `depends_on` holds the 1-based
+/// numbers of earlier steps.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PlannedSubtask {
+    pub description: String,
+    #[serde(default)]
+    pub target_agent: Option<String>,
+    #[serde(default)]
+    pub priority: Option<TaskPriority>,
+    #[serde(default)]
+    pub depends_on: Vec<usize>,
+}
+
+/// What the model answers with: the steps, or questions when the todo is too
+/// vague to plan
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct Plan {
+    #[serde(default)]
+    pub subtasks: Vec<PlannedSubtask>,
+    #[serde(default)]
+    pub questions: Vec<String>,
+}
+
+impl Plan {
+    /// Parse the model's reply. Steps may only depend on steps before them,
+    /// which keeps the plan in order and free of cycles.
+    pub fn parse(reply: &str) -> Result<Self> {
+        let plan: Plan = serde_json::from_str(strip_code_fence(reply))
+            .map_err(|e| anyhow!("Plan is not valid JSON: {}", e))?;
+        for (index, subtask) in plan.subtasks.iter().enumerate() {
+            let step = index + 1;
+            if subtask.description.trim().is_empty() {
+                return Err(anyhow!("Step {} has no description", step));
+            }
+            if let Some(dependency) = subtask.depends_on.iter().find(|&&dependency| dependency == 0 || dependency >= step) {
+                return Err(anyhow!("Step {} depends on step {}, which does not come before it", step, dependency));
+            }
+        }
+        Ok(plan)
+    }
+}
+
+/// Breaks a large todo into ordered subtasks. Each subtask goes to one of the
+/// agents the planner may delegate to, waits on the steps it depends on, and
+/// points back at the todo it was planned from.
+pub struct PlannerAgent {
+    config: AgentConfig,
+    ai_client: Box<dyn AiProvider + Send + Sync>,
+    todo_tool: Arc<dyn ToolExecutor>,
+    max_subtasks: usize,
+}
+
+impl PlannerAgent {
+    pub fn new(config: AgentConfig, todo_tool: Arc<dyn ToolExecutor>) -> Self {
+        Self {
+            config,
+            ai_client: Box::new(DefaultAiClient::new()),
+            todo_tool,
+            max_subtasks: DEFAULT_MAX_SUBTASKS,
+        }
+    }
+
+    /// A planner that creates subtasks with the configured `TodoTool` backend
+    pub async fn from_env(config: AgentConfig) -> Result<Self> {
+        Ok(Self::new(config, Arc::new(TodoTool::new().await?)))
+    }
+
+    pub fn with_ai_client<T: AiProvider + Send + Sync + 'static>(mut self, client: T) -> Self {
+        self.ai_client = Box::new(client);
+        self
+    }
+
+    pub fn with_max_subtasks(mut self, max_subtasks: usize) -> Self {
+        self.max_subtasks = max_subtasks.max(1);
+        self
+    }
+
+    /// Agents subtasks may be sent to. `user` is always one of them.
+    fn agents(&self) -> Vec<String> {
+        let mut agents: Vec<String> = self.config.downstream_agents.iter()
+            .filter(|agent| **agent != self.config.name)
+            .cloned()
+            .collect();
+        if !agents.iter().any(|agent| agent == "user") {
+            agents.push("user".to_string());
+        }
+        agents
+    }
+
+    async fn plan(&self, todo: &str) -> Result<Plan> {
+        let system_prompt = format!(
+            "You plan work for a team of agents. Break the todo you are given into a short \
+            ordered list of concrete subtasks. Assign each to one of these agents: {}. \
+            Reply with one JSON object and nothing else: \
+            {{\"subtasks\": [{{\"description\": \"...\", \"target_agent\": \"...\", \
+            \"priority\": \"Low|Medium|High|Critical\", \"depends_on\": [<numbers of earlier steps>]}}]}}. \
+            Steps are numbered from 1. If the todo is too vague to plan, reply with \
+            {{\"questions\": [\"...\"]}} instead.",
+            self.agents().join(", ")
+        );
+        let messages = vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), todo.to_string()),
+        ])];
+        let reply = self.ai_client.chat(&system_prompt, messages).await?;
+        Plan::parse(&reply)
+    }
+
+    /// Create the plan's subtasks in order, returning their ids. Subtasks of
+    /// a stored todo get idempotency keys derived from it, so planning the
+    /// same todo again doesn't duplicate them.
+    async fn create_subtasks(&self, parent_id: Option<&str>, subtasks: &[PlannedSubtask]) -> Result<Vec<String>> {
+        let agents = self.agents();
+        let mut ids: Vec<String> = Vec::with_capacity(subtasks.len());
+        for (index, subtask) in subtasks.iter().enumerate() {
+            let target_agent = subtask.target_agent.as_deref()
+                .filter(|agent| agents.iter().any(|known| known == agent))
+                .unwrap_or("user");
+            let priority = serde_json::to_value(subtask.priority.clone().unwrap_or(TaskPriority::Medium))?;
+            let depends_on: Vec<&str> = subtask.depends_on.iter().map(|step| ids[step - 1].as_str()).collect();
+
+            let mut params = HashMap::from([
+                ("command".to_string(), "add".to_string()),
+                ("description".to_string(), subtask.description.trim().to_string()),
+                ("target_agent".to_string(), target_agent.to_string()),
+                ("priority".to_string(), priority.as_str().unwrap_or("Medium").to_string()),
+                ("context".to_string(), self.config.name.clone()),
+            ]);
+            if !depends_on.is_empty() {
+                params.insert("depends_on".to_string(), depends_on.join(","));
+            }
+            if let Some(parent_id) = parent_id {
+                params.insert("parent_id".to_string(), parent_id.to_string());
+                params.insert("idempotency_key".to_string(), format!("plan:{}:{}", parent_id, index + 1));
+            }
+
+            let response = self.todo_tool.execute(params).await
+                .map_err(|e| anyhow!("Failed to create step {}: {}", index + 1, e))?;
+            ids.push(created_id(&response)
+                .ok_or_else(|| anyhow!("Step {} was not stored, so later steps can't depend on it: {}", index + 1, response))?);
+        }
+        Ok(ids)
+    }
+}
+
+/// Id of the todo a `TodoTool` add reported, from its `data`
+fn created_id(response: &str) -> Option<String> {
+    let response: Value = serde_json::from_str(response).ok()?;
+    response["data"]["id"].as_str()
+        .or_else(|| response["data"]["todo_id"].as_str())
+        .map(str::to_string)
+}
+
+#[async_trait]
+impl Agent for PlannerAgent {
+    async fn process_message(&self, message: Message) -> crate::Result<Message> {
+        let mut plan = self.plan(&message.content).await?;
+        if plan.subtasks.is_empty() {
+            if plan.questions.is_empty() {
+                return Err(crate::SwarmError::Agent("The planner returned no subtasks".to_string()));
+            }
+            return Ok(Message::needs_input(&plan.questions));
+        }
+        if plan.subtasks.len() > self.max_subtasks {
+            tracing::warn!("Plan has {} steps, keeping the first {}", plan.subtasks.len(), self.max_subtasks);
+            plan.subtasks.truncate(self.max_subtasks);
+        }
+
+        let parent_id = message.task_id();
+        let ids = self.create_subtasks(parent_id, &plan.subtasks).await?;
+        tracing::info!("Planned {} subtasks for {}", ids.len(), parent_id.unwrap_or("an ad-hoc todo"));
+
+        let mut content = format!("Planned {} subtasks:", ids.len());
+        for (index, (subtask, id)) in plan.subtasks.iter().zip(&ids).enumerate() {
+            content.push_str(&format!("\n{}. {} [{}]", index + 1, subtask.description.trim(), id));
+            if !subtask.depends_on.is_empty() {
+                let steps: Vec<String> = subtask.depends_on.iter().map(|step| step.to_string()).collect();
+                content.push_str(&format!(" after {}", steps.join(", ")));
+            }
+        }
+        Ok(message.reply(content)
+            .with_metadata(MessageMetadata::new(self.config.name.clone())))
+    }
+
+    async fn transfer_to(&self, target_agent: String, message: Message) -> crate::Result<Message> {
+        if !self.config.downstream_agents.contains(&target_agent) {
+            return Err(crate::SwarmError::NotFound(format!("Cannot transfer to unknown agent: {}", target_agent)));
+        }
+        Ok(Message::new(format!("Transferring to {} agent...", target_agent)))
+    }
+
+    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> crate::Result<String> {
+        match tool.name.as_str() {
+            "todo" => Ok(self.todo_tool.execute(params).await?),
+            other => Err(crate::SwarmError::Unsupported(format!("PlannerAgent does not support the {} tool", other))),
+        }
+    }
+
+    async fn get_current_state(&self) -> crate::Result<Option<State>> {
+        Ok(None)
+    }
+
+    async fn get_config(&self) -> crate::Result<AgentConfig> {
+        Ok(self.config.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::Mutex;
+
+    struct Reply(&'static str);
+
+    #[async_trait]
+    impl AiProvider for Reply {
+        async fn chat(&self, _system_prompt: &str, _messages: Vec<HashMap<String, String>>) -> Result<String> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    /// Records each add and answers like the Mongo todo store
+    #[derive(Default)]
+    struct RecordingTodos {
+        added: Mutex<Vec<HashMap<String, String>>>,
+    }
+
+    #[async_trait]
+    impl ToolExecutor for RecordingTodos {
+        async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+            let mut added = self.added.lock().await;
+            added.push(params);
+            Ok(serde_json::json!({ "success": true, "data": { "id": format!("sub-{}", added.len()) } }).to_string())
+        }
+    }
+
+    fn config() -> AgentConfig {
+        AgentConfig {
+            name: "planner".to_string(),
+            public_description: "Plans multi-step work".to_string(),
+            instructions: "Break todos into subtasks".to_string(),
+            tools: vec![],
+            downstream_agents: vec!["git".to_string(), "haiku".to_string()],
+            personality: None,
+            state_machine: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_plan() {
+        let plan = Plan::parse(r#"```json
+            {"subtasks": [
+                {"description": "Cut a release branch", "target_agent": "git"},
+                {"description": "Write release notes", "priority": "High", "depends_on": [1]}
+            ]}
+        ```"#).unwrap();
+        assert_eq!(plan.subtasks.len(), 2);
+        assert_eq!(plan.subtasks[1].priority, Some(TaskPriority::High));
+
+        assert!(Plan::parse(r#"{"subtasks": [{"description": "a", "depends_on": [1]}]}"#).is_err());
+        assert!(Plan::parse(r#"{"subtasks": [{"description": " "}]}"#).is_err());
+        assert!(Plan::parse("Sure! Here's a plan").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_creates_linked_subtasks() {
+        let todos = Arc::new(RecordingTodos::default());
+        let planner = PlannerAgent::new(config(), todos.clone()).with_ai_client(Reply(r#"{"subtasks": [
+            {"description": "Cut a release branch", "target_agent": "git"},
+            {"description": "Write a haiku for the release", "target_agent": "haiku", "depends_on": [1]},
+            {"description": "Deploy", "target_agent": "kubernetes", "depends_on": [1, 2]}
+        ]}"#));
+
+        let response = planner.process_message(Message::new("Ship 2.0".to_string()).with_task("parent-1")).await.unwrap();
+        assert!(response.content.starts_with("Planned 3 subtasks"), "{}", response.content);
+
+        let added = todos.added.lock().await;
+        assert_eq!(added[0]["target_agent"], "git");
+        assert!(!added[0].contains_key("depends_on"));
+        assert_eq!(added[1]["depends_on"], "sub-1");
+        assert_eq!(added[2]["depends_on"], "sub-1,sub-2");
+        // Agents the planner can't delegate to fall back to the user
+        assert_eq!(added[2]["target_agent"], "user");
+        assert!(added.iter().all(|params| params["parent_id"] == "parent-1"));
+        assert_eq!(added[1]["idempotency_key"], "plan:parent-1:2");
+    }
+
+    #[tokio::test]
+    async fn test_vague_todo_asks_for_input() {
+        let todos = Arc::new(RecordingTodos::default());
+        let planner = PlannerAgent::new(config(), todos.clone())
+            .with_ai_client(Reply(r#"{"questions": ["Which service?"]}"#));
+        let response = planner.process_message(Message::new("Make it better".to_string())).await.unwrap();
+        assert_eq!(response.questions(), Some(vec!["Which service?".to_string()]));
+        assert!(todos.added.lock().await.is_empty());
+    }
+}
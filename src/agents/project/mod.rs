@@ -1,12 +1,14 @@
-use std::fs;
 use std::path::Path;
-use std::process::Command;
+use tokio::fs;
+use tokio::process::Command;
 use std::collections::HashMap;
 use async_trait::async_trait;
 use crate::types::{Agent, AgentConfig, Message, MessageMetadata, Tool, ToolCall, State, TaskPriority};
-use crate::tools::ToolRegistry;
+use crate::tools::{KnowledgeBase, ToolRegistry};
 use crate::ai::{AiProvider, DefaultAiClient};
-use crate::Result;
+use crate::mcp::McpClient;
+use crate::supervisor::TaskSupervisor;
+use crate::{Result, SwarmError};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use tokio::sync::{RwLock, Mutex};
@@ -16,6 +18,16 @@ use std::time::{Duration, Instant};
 use std::error::Error as StdError;
 use anyhow::{Result as AnyhowResult, anyhow};
 
+mod responder;
+
+pub use responder::{
+    ClassificationResponder, CLASSIFY_REQUEST_TOPIC, DEFAULT_CLASSIFY_TIMEOUT, DEFAULT_PROJECT,
+    classify_response_topic, fallback_classification, parse_classification_request,
+};
+
+/// Background tasks a project agent runs at once
+const MAX_BACKGROUND_JOBS: usize = 4;
+
 // Project classification request/response structures for MQTT
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProjectClassificationRequest {
@@ -66,11 +78,19 @@ pub struct ProjectAgent {
     current_state: Option<String>,
     ai_client: Arc<dyn AiProvider + Send + Sync>,
     background_tasks: Arc<RwLock<Vec<BackgroundTask>>>,
+    /// Runs due background tasks, a few at a time
+    background_jobs: TaskSupervisor,
+    /// Checks for due background tasks once started; stopped on shutdown or drop
+    background_loop: std::sync::Mutex<Option<tokio::task::JoinHandle<()>>>,
     last_git_check: Arc<Mutex<Instant>>,
-    valid_projects: Vec<String>,
+    /// Built-in projects, joined by the MCP server's list on start
+    valid_projects: RwLock<Vec<String>>,
+    knowledge: Option<Arc<KnowledgeBase>>,
 }
 
 impl ProjectAgent {
+    /// Knows only the built-in projects and schedules nothing until
+    /// [`start`](Agent::start)
     pub async fn new(config: AgentConfig) -> Result<Self> {
         let valid_projects = vec![
             "madness_interactive".to_string(),
@@ -91,20 +111,39 @@ impl ProjectAgent {
             "inventorium".to_string(),
         ];
 
-        let agent = Self {
+        Ok(Self {
             config,
             tools: ToolRegistry::create_default_tools().await?,
             current_state: None,
             ai_client: Arc::new(DefaultAiClient::new()),
             background_tasks: Arc::new(RwLock::new(Vec::new())),
+            background_jobs: TaskSupervisor::new("project background", MAX_BACKGROUND_JOBS),
+            background_loop: std::sync::Mutex::new(None),
             last_git_check: Arc::new(Mutex::new(Instant::now())),
-            valid_projects,
-        };
+            valid_projects: RwLock::new(valid_projects),
+            knowledge: KnowledgeBase::shared(),
+        })
+    }
 
-        // Initialize background tasks
-        agent.setup_background_tasks().await?;
+    /// Documentation used to ground classification. Defaults to `KnowledgeBase::shared()`.
+    pub fn with_knowledge(mut self, knowledge: Option<Arc<KnowledgeBase>>) -> Self {
+        self.knowledge = knowledge;
+        self
+    }
 
-        Ok(agent)
+    /// Project names known to the MCP server, normalized to lowercase
+    async fn fetch_mcp_projects(mcp: &McpClient) -> AnyhowResult<Vec<String>> {
+        let response: Value = serde_json::from_str(&mcp.call_tool("list_projects_tool", &json!({})).await?)?;
+        let projects = [&response["data"]["projects"], &response["data"], &response["projects"]]
+            .into_iter()
+            .find_map(|value| value.as_array())
+            .ok_or_else(|| anyhow!("Unexpected list_projects_tool response"))?;
+
+        Ok(projects.iter()
+            .filter_map(|project| project.as_str())
+            .map(|project| project.trim().to_lowercase())
+            .filter(|project| !project.is_empty())
+            .collect())
     }
 
     /// Classify a project description and return the project name
@@ -135,13 +174,21 @@ If you're unsure, default to "madness_interactive"."#;
             ("content".to_string(), format!("Which project does this task belong to? {}", request.description)),
         ])];
 
-        let project_name = self.ai_client.chat(project_prompt, messages).await?;
+        let mut system_prompt = project_prompt.to_string();
+        if let Some(knowledge) = &self.knowledge {
+            if let Some(context) = knowledge.context_for(&request.description, crate::tools::knowledge::DEFAULT_SEARCH_LIMIT).await {
+                system_prompt.push_str(&format!("\n\nProject documentation that may help:\n\n{}", context));
+            }
+        }
+
+        let project_name = self.ai_client.chat(&system_prompt, messages).await
+            .map_err(|e| SwarmError::Ai(e.to_string()))?;
 
         // Clean up project name
         let project = project_name.trim().trim_matches('"').trim_matches('\'').to_lowercase();
 
         // Verify project exists in valid list
-        let verified_project = if self.valid_projects.iter().any(|p| p == &project) {
+        let verified_project = if self.valid_projects.read().await.iter().any(|p| p == &project) {
             project
         } else {
             // If not a valid project, default to madness_interactive
@@ -196,56 +243,65 @@ If you're unsure, default to "madness_interactive"."#;
         tasks.push(git_task);
         tasks.push(maintenance_task);
 
-        // Start background task processing if not already running
-        let background_tasks = self.background_tasks.clone();
-        tokio::spawn(async move {
-            Self::process_background_tasks(background_tasks).await;
-        });
-
         Ok(())
     }
 
     /// Setup initial background tasks
     async fn setup_background_tasks(&self) -> Result<()> {
         // Initialize periodic tasks for all projects
-        for project in &self.valid_projects {
+        let projects = self.valid_projects.read().await.clone();
+        for project in &projects {
             self.schedule_project_background_work(project).await?;
         }
         Ok(())
     }
 
     /// Process background tasks continuously
-    async fn process_background_tasks(tasks: Arc<RwLock<Vec<BackgroundTask>>>) {
+    async fn process_background_tasks(tasks: Arc<RwLock<Vec<BackgroundTask>>>, jobs: TaskSupervisor) {
         let mut interval = tokio::time::interval(Duration::from_secs(60)); // Check every minute
         
         loop {
             interval.tick().await;
             
-            let mut tasks_guard = tasks.write().await;
-            let now = Utc::now();
+            let mut due = Vec::new();
+            {
+                let mut tasks_guard = tasks.write().await;
+                let now = Utc::now();
+                for task in tasks_guard.iter_mut() {
+                    if matches!(task.status, TaskStatus::Pending) && task.next_run <= now {
+                        due.push(task.clone());
+                        
+                        // Update task timing
+                        task.last_run = Some(now);
+                        task.next_run = now + chrono::Duration::hours(24); // Daily by default
+                        task.status = TaskStatus::Completed;
+                    }
+                }
+            }
             
-            for task in tasks_guard.iter_mut() {
-                if matches!(task.status, TaskStatus::Pending) && task.next_run <= now {
-                    task.status = TaskStatus::Running;
-                    
-                    // Clone task for processing
-                    let task_clone = task.clone();
-                    
-                    // Process task in background
-                    tokio::spawn(async move {
-                        let result = Self::execute_background_task(&task_clone).await;
-                        log::info!("Background task {} completed: {:?}", task_clone.id, result);
-                    });
-                    
-                    // Update task timing
-                    task.last_run = Some(now);
-                    task.next_run = now + chrono::Duration::hours(24); // Daily by default
-                    task.status = TaskStatus::Completed;
+            // Started outside the lock, since a full supervisor makes us wait
+            for task in due {
+                let job = task.id.clone();
+                let started = jobs.spawn(job, async move {
+                    let result = Self::execute_background_task(&task).await;
+                    log::info!("Background task {} completed: {:?}", task.id, result);
+                }).await;
+                if started.is_err() {
+                    return;
                 }
             }
         }
     }
 
+    /// Stop scheduling background tasks and wait up to `grace` for running
+    /// ones. Returns how many had to be aborted.
+    pub async fn shutdown(&self, grace: Duration) -> usize {
+        if let Some(background_loop) = self.background_loop.lock().unwrap().take() {
+            background_loop.abort();
+        }
+        self.background_jobs.drain(grace).await
+    }
+
     /// Execute a specific background task
     async fn execute_background_task(task: &BackgroundTask) -> Result<()> {
         match task.task_type {
@@ -271,7 +327,8 @@ If you're unsure, default to "madness_interactive"."#;
         // Check if we're in a git repository
         let output = Command::new("git")
             .args(["rev-parse", "--git-dir"])
-            .output()?;
+            .output()
+            .await?;
             
         if !output.status.success() {
             log::warn!("Not in a git repository, skipping git analysis for {}", project);
@@ -281,7 +338,8 @@ If you're unsure, default to "madness_interactive"."#;
         // Get recent commits (last 24 hours)
         let output = Command::new("git")
             .args(["log", "--oneline", "--since=24 hours ago"])
-            .output()?;
+            .output()
+            .await?;
             
         if output.status.success() {
             let commits = String::from_utf8_lossy(&output.stdout);
@@ -318,11 +376,12 @@ If you're unsure, default to "madness_interactive"."#;
         log::info!("Checking dependency updates for project: {}", project);
         
         // Check for different project types
-        if Path::new("Cargo.toml").exists() {
+        if fs::try_exists("Cargo.toml").await.unwrap_or(false) {
             // Rust project
             let output = Command::new("cargo")
                 .args(["outdated"])
-                .output();
+                .output()
+                .await;
                 
             if let Ok(output) = output {
                 if output.status.success() {
@@ -332,11 +391,12 @@ If you're unsure, default to "madness_interactive"."#;
                     }
                 }
             }
-        } else if Path::new("requirements.txt").exists() {
+        } else if fs::try_exists("requirements.txt").await.unwrap_or(false) {
             // Python project
             let output = Command::new("pip")
                 .args(["list", "--outdated"])
-                .output();
+                .output()
+                .await;
                 
             if let Ok(output) = output {
                 if output.status.success() {
@@ -364,25 +424,25 @@ If you're unsure, default to "madness_interactive"."#;
         Ok(())
     }
 
-    fn init_python_project(&self, name: &str, description: &str, path: &Path) -> Result<()> {
+    async fn init_python_project(&self, name: &str, description: &str, path: &Path) -> Result<()> {
         // Use the Spindlewrit CLI if available
-        if self.is_spindlewrit_available() {
-            return self.use_spindlewrit_cli(name, description, "python", path);
+        if self.is_spindlewrit_available().await {
+            return self.use_spindlewrit_cli(name, description, "python", path).await;
         }
 
         // Fallback to direct implementation
         // Create project structure
         let src_dir = path.join("src");
-        fs::create_dir_all(&src_dir)?;
-        fs::create_dir_all(src_dir.join(name))?;
-        fs::create_dir_all(src_dir.join("tests"))?;
+        fs::create_dir_all(&src_dir).await?;
+        fs::create_dir_all(src_dir.join(name)).await?;
+        fs::create_dir_all(src_dir.join("tests")).await?;
 
         // Create __init__.py files
-        fs::write(src_dir.join(name).join("__init__.py"), "")?;
-        fs::write(src_dir.join("tests").join("__init__.py"), "")?;
+        fs::write(src_dir.join(name).join("__init__.py"), "").await?;
+        fs::write(src_dir.join("tests").join("__init__.py"), "").await?;
 
         // Create requirements.txt
-        fs::write(path.join("requirements.txt"), "# Core dependencies\n")?;
+        fs::write(path.join("requirements.txt"), "# Core dependencies\n").await?;
 
         // Create setup.py
         let setup_content = format!(
@@ -398,49 +458,50 @@ setup(
 )"#,
             name
         );
-        fs::write(path.join("setup.py"), setup_content)?;
+        fs::write(path.join("setup.py"), setup_content).await?;
 
-        self.create_readme(name, description, "python", path)?;
+        self.create_readme(name, description, "python", path).await?;
         Ok(())
     }
 
-    fn init_rust_project(&self, name: &str, description: &str, path: &Path) -> Result<()> {
+    async fn init_rust_project(&self, name: &str, description: &str, path: &Path) -> Result<()> {
         // Use the Spindlewrit CLI if available
-        if self.is_spindlewrit_available() {
-            return self.use_spindlewrit_cli(name, description, "rust", path);
+        if self.is_spindlewrit_available().await {
+            return self.use_spindlewrit_cli(name, description, "rust", path).await;
         }
 
         // Fallback to direct implementation
         Command::new("cargo")
             .args(["init", "--name", name])
             .current_dir(path)
-            .output()?;
+            .output()
+            .await?;
 
-        self.create_readme(name, description, "rust", path)?;
+        self.create_readme(name, description, "rust", path).await?;
         Ok(())
     }
 
-    fn init_common_project(&self, name: &str, description: &str, path: &Path) -> Result<()> {
+    async fn init_common_project(&self, name: &str, description: &str, path: &Path) -> Result<()> {
         // Use the Spindlewrit CLI if available
-        if self.is_spindlewrit_available() {
-            return self.use_spindlewrit_cli(name, description, "common", path);
+        if self.is_spindlewrit_available().await {
+            return self.use_spindlewrit_cli(name, description, "common", path).await;
         }
 
         // Fallback to direct implementation
-        fs::create_dir_all(path.join("src"))?;
-        fs::create_dir_all(path.join("docs"))?;
-        fs::create_dir_all(path.join("examples"))?;
+        fs::create_dir_all(path.join("src")).await?;
+        fs::create_dir_all(path.join("docs")).await?;
+        fs::create_dir_all(path.join("examples")).await?;
 
-        self.create_readme(name, description, "common", path)?;
+        self.create_readme(name, description, "common", path).await?;
         // add init .specstory and run fixchat
         // setup the git hooks and init git project
         Ok(())
     }
 
-    fn init_project_from_todo(&self, todo_id: &str, output_path: &Path) -> Result<()> {
+    async fn init_project_from_todo(&self, todo_id: &str, output_path: &Path) -> Result<()> {
         // Check if Spindlewrit is available
-        if !self.is_spindlewrit_available() {
-            return Err("Spindlewrit CLI not available. Please install it first.".into());
+        if !self.is_spindlewrit_available().await {
+            return Err(SwarmError::Tool("Spindlewrit CLI not available. Please install it first.".to_string()));
         }
 
         // Get the GEMMA_API_KEY from environment
@@ -457,27 +518,29 @@ setup(
                 output_path.to_str().unwrap(),
             ])
             .arg(api_key_arg)
-            .output()?;
+            .output()
+            .await?;
 
         if !output.status.success() {
             let error_message = String::from_utf8_lossy(&output.stderr);
-            return Err(format!("Failed to generate project from todo: {}", error_message).into());
+            return Err(SwarmError::Tool(format!("Failed to generate project from todo: {}", error_message)));
         }
 
         Ok(())
     }
 
     // Check if the Spindlewrit CLI is available in the system
-    fn is_spindlewrit_available(&self) -> bool {
+    async fn is_spindlewrit_available(&self) -> bool {
         Command::new("spindlewrit")
             .arg("--help")
             .output()
+            .await
             .map(|output| output.status.success())
             .unwrap_or(false)
     }
 
     // Use the Spindlewrit CLI to create a project
-    fn use_spindlewrit_cli(&self, name: &str, description: &str, project_type: &str, path: &Path) -> Result<()> {
+    async fn use_spindlewrit_cli(&self, name: &str, description: &str, project_type: &str, path: &Path) -> Result<()> {
         let output = Command::new("spindlewrit")
             .args([
                 "create",
@@ -490,17 +553,18 @@ setup(
                 "--path",
                 path.to_str().unwrap(),
             ])
-            .output()?;
+            .output()
+            .await?;
 
         if !output.status.success() {
             let error_message = String::from_utf8_lossy(&output.stderr);
-            return Err(format!("Failed to generate project: {}", error_message).into());
+            return Err(SwarmError::Tool(format!("Failed to generate project: {}", error_message)));
         }
 
         Ok(())
     }
 
-    fn create_readme(
+    async fn create_readme(
         &self,
         name: &str,
         description: &str,
@@ -554,14 +618,52 @@ This is a {project_type} project created with the project initialization tool.
             _ => {}
         }
 
-        fs::write(path.join("README.md"), content)?;
+        fs::write(path.join("README.md"), content).await?;
         Ok(())
     }
 }
 
+impl Drop for ProjectAgent {
+    fn drop(&mut self) {
+        if let Some(background_loop) = self.background_loop.lock().unwrap().take() {
+            background_loop.abort();
+        }
+    }
+}
+
 #[async_trait]
 impl Agent for ProjectAgent {
-    async fn process_message(&self, message: Message) -> AnyhowResult<Message> {
+    /// Learn the MCP server's projects and start the background work for
+    /// each. Starting again does nothing.
+    async fn start(&self) -> Result<()> {
+        {
+            let mut background_loop = self.background_loop.lock().unwrap();
+            if background_loop.is_some() {
+                return Ok(());
+            }
+            let (tasks, jobs) = (self.background_tasks.clone(), self.background_jobs.clone());
+            *background_loop = Some(tokio::spawn(Self::process_background_tasks(tasks, jobs)));
+        }
+
+        // Same client, and so the same circuit breaker, as the registry's TodoTool
+        let mcp = McpClient::shared()?;
+        match Self::fetch_mcp_projects(&mcp).await {
+            Ok(projects) => {
+                let mut valid_projects = self.valid_projects.write().await;
+                for project in projects {
+                    if !valid_projects.contains(&project) {
+                        valid_projects.push(project);
+                    }
+                }
+            }
+            Err(e) => tracing::debug!("Using built-in project list, MCP project list unavailable: {}", e),
+        }
+
+        self.setup_background_tasks().await?;
+        Ok(())
+    }
+
+    async fn process_message(&self, message: Message) -> Result<Message> {
         // Check if this is a project classification request
         if let Ok(classification_request) = serde_json::from_str::<ProjectClassificationRequest>(&message.content) {
             // Handle project classification
@@ -603,19 +705,23 @@ impl Agent for ProjectAgent {
         Ok(response)
     }
 
-    async fn transfer_to(&self, target_agent: String, message: Message) -> AnyhowResult<Message> {
+    async fn transfer_to(&self, target_agent: String, message: Message) -> Result<Message> {
         Ok(message)
     }
 
-    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> AnyhowResult<String> {
-        self.tools.execute(tool, params).await.map_err(|e| anyhow!("{}", e))
+    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
+        Ok(self.tools.execute(tool, params).await?)
+    }
+
+    async fn call_tools(&self, calls: Vec<(Tool, HashMap<String, String>)>) -> Vec<Result<String>> {
+        self.tools.execute_batch(calls).await.into_iter().map(|result| result.map_err(Into::into)).collect()
     }
 
-    async fn get_config(&self) -> AnyhowResult<AgentConfig> {
+    async fn get_config(&self) -> Result<AgentConfig> {
         Ok(self.config.clone())
     }
 
-    async fn get_current_state(&self) -> AnyhowResult<Option<State>> {
+    async fn get_current_state(&self) -> Result<Option<State>> {
         Ok(self.current_state.clone().map(|s| State {
             name: s,
             data: None,
@@ -647,4 +753,29 @@ mod tests {
         assert!(response.content.contains("Project init received"));
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_background_work_waits_for_start() -> AnyhowResult<()> {
+        let config = AgentConfig {
+            name: "project".to_string(),
+            public_description: "Test project agent".to_string(),
+            instructions: "Classify projects".to_string(),
+            tools: vec![],
+            downstream_agents: vec![],
+            personality: None,
+            state_machine: None,
+        };
+
+        let agent = ProjectAgent::new(config).await?;
+        assert!(agent.background_tasks.read().await.is_empty());
+        assert!(agent.background_loop.lock().unwrap().is_none());
+
+        agent.start().await?;
+        agent.start().await?;
+        let projects = agent.valid_projects.read().await.len();
+        assert_eq!(agent.background_tasks.read().await.len(), 2 * projects);
+
+        assert_eq!(agent.shutdown(Duration::from_secs(1)).await, 0);
+        Ok(())
+    }
 }
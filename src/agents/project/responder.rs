@@ -0,0 +1,164 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use serde_json::json;
+use crate::mqtt::{MqttMessage, MqttService};
+use crate::supervisor::TaskSupervisor;
+use crate::Result;
+use super::{ProjectAgent, ProjectClassificationRequest, ProjectClassificationResponse};
+
+/// Where requesters publish classification requests
+pub const CLASSIFY_REQUEST_TOPIC: &str = "project/classify/request";
+/// Project used when classification fails or takes too long
+pub const DEFAULT_PROJECT: &str = "madness_interactive";
+/// How long the responder gives the model before answering with the default.
+/// Shorter than the requester's wait so it hears back either way.
+pub const DEFAULT_CLASSIFY_TIMEOUT: Duration = Duration::from_secs(25);
+/// Requests classified at once; more wait in the subscription
+const MAX_IN_FLIGHT: usize = 8;
+
+/// Where the answer to `request_id` is published
+pub fn classify_response_topic(request_id: &str) -> String {
+    format!("project/classify/response/{}", request_id)
+}
+
+/// JSON requests as sent by the intake, or a bare description from anyone else
+pub fn parse_classification_request(payload: &str) -> ProjectClassificationRequest {
+    serde_json::from_str(payload).unwrap_or_else(|_| ProjectClassificationRequest {
+        description: payload.to_string(),
+        request_id: None,
+        context: None,
+    })
+}
+
+/// The default project, with the reason classification didn't pick one
+pub fn fallback_classification(request_id: Option<String>, reason: impl Into<String>) -> ProjectClassificationResponse {
+    ProjectClassificationResponse {
+        project_name: DEFAULT_PROJECT.to_string(),
+        confidence: 0.0,
+        request_id,
+        reasoning: Some(reason.into()),
+    }
+}
+
+/// Answers `project/classify/request` with [`ProjectAgent::classify_project`].
+/// Every request gets exactly one response on
+/// `project/classify/response/<request_id>` (or the request's MQTT v5
+/// response topic), falling back to [`DEFAULT_PROJECT`] on errors and timeouts.
+pub struct ClassificationResponder {
+    agent: Arc<ProjectAgent>,
+    client: MqttService,
+    timeout: Duration,
+    jobs: TaskSupervisor,
+    answered: AtomicU64,
+    failed: AtomicU64,
+    timed_out: AtomicU64,
+}
+
+impl ClassificationResponder {
+    pub fn new(agent: Arc<ProjectAgent>, client: MqttService) -> Self {
+        Self {
+            agent,
+            client,
+            timeout: DEFAULT_CLASSIFY_TIMEOUT,
+            jobs: TaskSupervisor::new("classification", MAX_IN_FLIGHT),
+            answered: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            timed_out: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Subscribe and answer requests until the connection's subscription closes
+    pub async fn run(self: Arc<Self>) -> Result<()> {
+        let mut requests = self.client.subscribe(CLASSIFY_REQUEST_TOPIC, self.client.config().task_qos).await?;
+        tracing::info!("Answering project classification requests on {}", CLASSIFY_REQUEST_TOPIC);
+        while let Some(message) = requests.recv().await {
+            let responder = self.clone();
+            self.jobs.spawn(format!("classify {}", message.topic), async move { responder.respond(&message).await }).await?;
+        }
+        Ok(())
+    }
+
+    /// Stop taking requests and wait up to `grace` for those being answered.
+    /// Returns how many had to be aborted.
+    pub async fn shutdown(&self, grace: Duration) -> usize {
+        self.jobs.drain(grace).await
+    }
+
+    /// Classify one request and publish the answer
+    pub async fn respond(&self, message: &MqttMessage) {
+        let request = parse_classification_request(&message.payload_str());
+        let request_id = request.request_id.clone();
+
+        let response = match tokio::time::timeout(self.timeout, self.agent.classify_project(request)).await {
+            Ok(Ok(response)) => {
+                self.answered.fetch_add(1, Ordering::Relaxed);
+                response
+            }
+            Ok(Err(e)) => {
+                tracing::warn!("Project classification failed: {}", e);
+                self.failed.fetch_add(1, Ordering::Relaxed);
+                fallback_classification(request_id.clone(), format!("Classification failed: {}", e))
+            }
+            Err(_) => {
+                tracing::warn!("Project classification timed out after {}s", self.timeout.as_secs());
+                self.timed_out.fetch_add(1, Ordering::Relaxed);
+                fallback_classification(request_id.clone(), "Classification timed out")
+            }
+        };
+
+        let response_topic = match &request_id {
+            Some(request_id) => classify_response_topic(request_id),
+            None => "project/classify/response".to_string(),
+        };
+        let payload = match serde_json::to_vec(&response) {
+            Ok(payload) => payload,
+            Err(e) => {
+                tracing::error!("Failed to serialize classification response: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = self.client.reply(message, response_topic, payload).await {
+            tracing::error!("Failed to publish classification response: {}", e);
+        }
+    }
+
+    pub fn stats_json(&self) -> serde_json::Value {
+        json!({
+            "answered": self.answered.load(Ordering::Relaxed),
+            "failed": self.failed.load(Ordering::Relaxed),
+            "timed_out": self.timed_out.load(Ordering::Relaxed),
+            "timeout_secs": self.timeout.as_secs(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_classification_request() {
+        let request = parse_classification_request(r#"{"description": "Fix the dashboard", "request_id": "42", "context": null}"#);
+        assert_eq!(request.description, "Fix the dashboard");
+        assert_eq!(request.request_id.as_deref(), Some("42"));
+
+        let request = parse_classification_request("Fix the dashboard");
+        assert_eq!(request.description, "Fix the dashboard");
+        assert!(request.request_id.is_none());
+    }
+
+    #[test]
+    fn test_fallback_and_response_topic() {
+        let response = fallback_classification(Some("42".to_string()), "Classification timed out");
+        assert_eq!(response.project_name, DEFAULT_PROJECT);
+        assert_eq!(response.confidence, 0.0);
+        assert_eq!(response.request_id.as_deref(), Some("42"));
+        assert_eq!(classify_response_topic("42"), "project/classify/response/42");
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{oneshot, Mutex, RwLock};
+use tokio::task::JoinHandle;
+use crate::agents::AgentRegistry;
+use crate::config::SwarmSettings;
+use crate::mqtt::{MqttService, QoS};
+use crate::types::{Agent, Message};
+
+/// Where messages for `agent` on `node` are published
+pub fn inbox_topic(node: &str, agent: &str) -> String {
+    format!("swarm/{}/agent/{}/inbox", node, agent)
+}
+
+/// Where replies to messages sent from `node` come back
+pub fn reply_topic(node: &str) -> String {
+    format!("swarm/{}/replies", node)
+}
+
+/// A message for an agent on another node
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteEnvelope {
+    pub correlation_id: String,
+    pub from_node: String,
+    /// Agent handing the message over, when it came from a transfer
+    #[serde(default)]
+    pub from_agent: Option<String>,
+    pub agent: String,
+    /// Topic the reply goes to, for brokers without MQTT v5 response topics
+    pub reply_to: String,
+    pub message: Message,
+}
+
+/// The remote agent's answer, or why there isn't one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoteReply {
+    pub correlation_id: String,
+    #[serde(default)]
+    pub message: Option<Message>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+type Pending = Arc<Mutex<HashMap<String, oneshot::Sender<RemoteReply>>>>;
+
+/// Agents declared in `[swarm.remote_agents]`, reached over MQTT. Each send
+/// waits for the reply carrying its correlation id.
+pub struct RemoteAgents {
+    node_id: String,
+    routes: HashMap<String, String>,
+    client: MqttService,
+    timeout: Duration,
+    pending: Pending,
+}
+
+impl RemoteAgents {
+    /// Subscribes to this node's reply topic and starts matching replies to sends
+    pub async fn connect(client: MqttService, settings: &SwarmSettings) -> Result<Self> {
+        let node_id = settings.node_id();
+        let routes = settings.remote_agents.iter()
+            .map(|(agent, remote)| (agent.clone(), remote.node.clone()))
+            .collect();
+        let pending: Pending = Arc::default();
+
+        let mut replies = client.subscribe(&reply_topic(&node_id), QoS::ExactlyOnce).await?;
+        let waiting = pending.clone();
+        tokio::spawn(async move {
+            while let Some(message) = replies.recv().await {
+                let reply: RemoteReply = match message.json() {
+                    Ok(reply) => reply,
+                    Err(e) => {
+                        tracing::warn!("Ignoring malformed remote reply: {}", e);
+                        continue;
+                    }
+                };
+                match waiting.lock().await.remove(&reply.correlation_id) {
+                    Some(sender) => {
+                        let _ = sender.send(reply);
+                    }
+                    // The sender already gave up waiting
+                    None => tracing::debug!("Late reply {} dropped", reply.correlation_id),
+                }
+            }
+        });
+
+        Ok(Self { node_id, routes, client, timeout: settings.remote_timeout(), pending })
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// Node `agent` lives on, if it is remote
+    pub fn node_for(&self, agent: &str) -> Option<&str> {
+        self.routes.get(agent).map(String::as_str)
+    }
+
+    /// Remote agents by name, with the node each lives on
+    pub fn routes(&self) -> &HashMap<String, String> {
+        &self.routes
+    }
+
+    /// Delivers `message` to the remote `agent` and waits for its reply
+    #[tracing::instrument(name = "remote.send", skip(self, message))]
+    pub async fn send(&self, from_agent: Option<&str>, agent: &str, message: Message) -> Result<Message> {
+        let node = self.node_for(agent)
+            .ok_or_else(|| anyhow!("Agent '{}' is not a remote agent", agent))?;
+        let correlation_id = uuid::Uuid::new_v4().to_string();
+        let reply_to = reply_topic(&self.node_id);
+        let envelope = RemoteEnvelope {
+            correlation_id: correlation_id.clone(),
+            from_node: self.node_id.clone(),
+            from_agent: from_agent.map(str::to_string),
+            agent: agent.to_string(),
+            reply_to: reply_to.clone(),
+            message,
+        };
+
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().await.insert(correlation_id.clone(), sender);
+        let published = self.client.publish_request(
+            inbox_topic(node, agent),
+            serde_json::to_vec(&envelope)?,
+            &reply_to,
+            correlation_id.clone(),
+        ).await;
+        if let Err(e) = published {
+            self.pending.lock().await.remove(&correlation_id);
+            return Err(e.into());
+        }
+
+        let reply = match tokio::time::timeout(self.timeout, receiver).await {
+            Ok(Ok(reply)) => reply,
+            Ok(Err(_)) => return Err(anyhow!("Reply listener for node {} stopped", node)),
+            Err(_) => {
+                self.pending.lock().await.remove(&correlation_id);
+                return Err(anyhow!("Agent '{}' on node {} did not reply within {:?}", agent, node, self.timeout));
+            }
+        };
+        match (reply.message, reply.error) {
+            (Some(message), None) => Ok(message),
+            (_, Some(error)) => Err(anyhow!("Agent '{}' on node {} failed: {}", agent, node, error)),
+            (None, None) => Err(anyhow!("Agent '{}' on node {} sent an empty reply", agent, node)),
+        }
+    }
+}
+
+/// Answer messages sent to this node's agents from other nodes
+pub async fn spawn_inbox(registry: Arc<RwLock<AgentRegistry>>, client: MqttService, node_id: &str) -> Result<JoinHandle<()>> {
+    let filter = inbox_topic(node_id, "+");
+    let mut inbox = client.subscribe(&filter, QoS::ExactlyOnce).await?;
+    tracing::info!("Serving remote messages for node {} on {}", node_id, filter);
+    Ok(tokio::spawn(async move {
+        while let Some(request) = inbox.recv().await {
+            let envelope: RemoteEnvelope = match request.json() {
+                Ok(envelope) => envelope,
+                Err(e) => {
+                    tracing::warn!("Ignoring malformed message on {}: {}", request.topic, e);
+                    continue;
+                }
+            };
+            let registry = registry.clone();
+            let client = client.clone();
+            tokio::spawn(async move {
+                let agent = registry.read().await.get(&envelope.agent);
+                let result = match agent {
+                    Some(agent) => agent.read().await.process_message(envelope.message).await,
+                    None => Err(crate::SwarmError::NotFound(format!("Agent '{}'", envelope.agent))),
+                };
+                let reply = match result {
+                    Ok(message) => RemoteReply { correlation_id: envelope.correlation_id, message: Some(message), error: None },
+                    Err(e) => RemoteReply { correlation_id: envelope.correlation_id, message: None, error: Some(e.to_string()) },
+                };
+                let payload = match serde_json::to_vec(&reply) {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        tracing::error!("Failed to encode remote reply: {}", e);
+                        return;
+                    }
+                };
+                if let Err(e) = client.reply(&request, envelope.reply_to, payload).await {
+                    tracing::error!("Failed to reply to node {}: {}", envelope.from_node, e);
+                }
+            });
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_envelope_round_trip() {
+        assert_eq!(inbox_topic("studio", "haiku"), "swarm/studio/agent/haiku/inbox");
+        assert_eq!(reply_topic("pi"), "swarm/pi/replies");
+
+        let envelope = RemoteEnvelope {
+            correlation_id: "c-1".to_string(),
+            from_node: "pi".to_string(),
+            from_agent: Some("greeter".to_string()),
+            agent: "haiku".to_string(),
+            reply_to: reply_topic("pi"),
+            message: Message::new("write about rust".to_string()),
+        };
+        let decoded: RemoteEnvelope = serde_json::from_slice(&serde_json::to_vec(&envelope).unwrap()).unwrap();
+        assert_eq!(decoded.message.content, "write about rust");
+        assert_eq!(decoded.from_agent.as_deref(), Some("greeter"));
+
+        let reply: RemoteReply = serde_json::from_str(r#"{"correlation_id": "c-1", "error": "boom"}"#).unwrap();
+        assert!(reply.message.is_none());
+    }
+}
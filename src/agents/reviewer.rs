@@ -0,0 +1,271 @@
+//! Scores agent output against the agent's instructions and, when it falls
+//! short, gives the agent one chance to revise it.
+
+use std::collections::HashMap;
+use std::env;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use crate::ai::{AiProvider, DefaultAiClient};
+use crate::agents::structured::strip_code_fence;
+use crate::types::{Agent, AgentConfig, Message, MessageMetadata, State, Tool};
+
+/// Metadata context key holding a reviewed reply's [`Review`] as JSON
+pub const REVIEW_CONTEXT_KEY: &str = "review";
+/// Score out of 10 a reply needs to pass without revision
+pub const DEFAULT_REVIEW_THRESHOLD: f64 = 7.0;
+
+/// The reviewer's verdict on one reply
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Critique {
+    /// 0 to 10
+    pub score: f64,
+    pub feedback: String,
+    #[serde(default)]
+    pub passed: bool,
+}
+
+/// Every critique of a reply, oldest first, and whether the reply sent on is
+/// a revision
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Review {
+    pub critiques: Vec<Critique>,
+    pub revised: bool,
+}
+
+impl Review {
+    /// The review recorded on `message`, if it was reviewed
+    pub fn of(message: &Message) -> Option<Self> {
+        serde_json::from_str(message.context_entry(REVIEW_CONTEXT_KEY)?).ok()
+    }
+
+    fn record(self, message: Message) -> Message {
+        let review = serde_json::to_string(&self).unwrap_or_default();
+        message.with_context_entry(REVIEW_CONTEXT_KEY, review)
+    }
+}
+
+/// Configuration the `reviewer` agent is registered with
+pub fn default_config() -> AgentConfig {
+    AgentConfig {
+        name: "reviewer".to_string(),
+        public_description: "Agent that reviews other agents' output.".to_string(),
+        instructions: "Judge whether a reply is correct, complete and fit for its purpose.".to_string(),
+        tools: Vec::new(),
+        downstream_agents: Vec::new(),
+        personality: None,
+        state_machine: None,
+    }
+}
+
+pub struct ReviewerAgent {
+    config: AgentConfig,
+    ai_client: Box<dyn AiProvider + Send + Sync>,
+    threshold: f64,
+}
+
+impl ReviewerAgent {
+    pub fn new(config: AgentConfig) -> Self {
+        Self {
+            config,
+            ai_client: Box::new(DefaultAiClient::new()),
+            threshold: DEFAULT_REVIEW_THRESHOLD,
+        }
+    }
+
+    /// Reads `REVIEW_THRESHOLD` (default 7 out of 10)
+    pub fn from_env(config: AgentConfig) -> Self {
+        let threshold = env::var("REVIEW_THRESHOLD").ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_REVIEW_THRESHOLD);
+        Self::new(config).with_threshold(threshold)
+    }
+
+    pub fn with_ai_client<T: AiProvider + Send + Sync + 'static>(mut self, client: T) -> Self {
+        self.ai_client = Box::new(client);
+        self
+    }
+
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold.clamp(0.0, 10.0);
+        self
+    }
+
+    /// Score `output`, written in reply to `request` by an agent following
+    /// `instructions`
+    pub async fn critique(&self, instructions: &str, request: &str, output: &str) -> Result<Critique> {
+        let system_prompt = "You review replies written by other assistants. Score the reply from 0 to 10 \
+            for how well it follows the assistant's instructions and answers the request. Reply with one \
+            JSON object and nothing else: {\"score\": <number>, \"feedback\": \"<what to fix, or why it is good>\"}.";
+        let content = format!(
+            "Assistant instructions:\n{}\n\nRequest:\n{}\n\nReply:\n{}",
+            instructions, request, output
+        );
+        let messages = vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), content),
+        ])];
+        let reply = self.ai_client.chat(system_prompt, messages).await?;
+        let mut critique: Critique = serde_json::from_str(strip_code_fence(&reply))
+            .map_err(|e| anyhow!("Review is not valid JSON: {}", e))?;
+        critique.score = critique.score.clamp(0.0, 10.0);
+        critique.passed = critique.score >= self.threshold;
+        Ok(critique)
+    }
+
+    /// Review `agent`'s `response` to `request`. A reply below the threshold
+    /// goes back to the agent once with the feedback, and the revision is
+    /// sent on whatever its score. The critiques are recorded on the reply.
+    /// Reviews that fail are logged and the reply is let through unreviewed.
+    pub async fn review(&self, agent: &(dyn Agent + Send + Sync), request: &Message, response: Message) -> Result<Message> {
+        // Questions for the user aren't output to judge
+        if response.questions().is_some() {
+            return Ok(response);
+        }
+        let instructions = agent.get_config().await
+            .map(|config| config.instructions)
+            .unwrap_or_default();
+
+        let first = match self.critique(&instructions, &request.content, &response.content).await {
+            Ok(critique) => critique,
+            Err(e) => {
+                tracing::warn!("Review failed, sending the reply unreviewed: {}", e);
+                return Ok(response);
+            }
+        };
+        if first.passed {
+            return Ok(Review { critiques: vec![first], revised: false }.record(response));
+        }
+
+        tracing::info!("Reply scored {:.1}, below {:.1}; asking for a revision", first.score, self.threshold);
+        let mut revision = Message::new(format!(
+            "{}\n\nA reviewer scored your previous reply {:.1}/10: {}\n\nYour previous reply:\n{}\n\nReply again, addressing the review.",
+            request.content, first.score, first.feedback, response.content
+        ));
+        revision.metadata = request.metadata.clone();
+        revision.attachments = request.attachments.clone();
+        let revised = match agent.process_message(revision).await {
+            Ok(revised) => revised,
+            Err(e) => {
+                tracing::warn!("Revision failed, sending the original reply: {}", e);
+                return Ok(Review { critiques: vec![first], revised: false }.record(response));
+            }
+        };
+
+        let mut critiques = vec![first];
+        match self.critique(&instructions, &request.content, &revised.content).await {
+            Ok(second) => critiques.push(second),
+            Err(e) => tracing::warn!("Review of the revision failed: {}", e),
+        }
+        Ok(Review { critiques, revised: true }.record(revised))
+    }
+}
+
+#[async_trait]
+impl Agent for ReviewerAgent {
+    /// Critiques the message as a reply written to this agent's instructions
+    async fn process_message(&self, message: Message) -> crate::Result<Message> {
+        let critique = self.critique(&self.config.instructions, "(not given)", &message.content).await?;
+        let verdict = if critique.passed { "Passed" } else { "Needs work" };
+        let reply = message.reply(format!("{} ({:.1}/10): {}", verdict, critique.score, critique.feedback))
+            .with_metadata(MessageMetadata::new(self.config.name.clone()));
+        Ok(Review { critiques: vec![critique], revised: false }.record(reply))
+    }
+
+    async fn transfer_to(&self, target_agent: String, _message: Message) -> crate::Result<Message> {
+        Err(crate::SwarmError::Unsupported(format!("ReviewerAgent does not transfer to {}", target_agent)))
+    }
+
+    async fn call_tool(&self, _tool: &Tool, _params: HashMap<String, String>) -> crate::Result<String> {
+        Err(crate::SwarmError::Unsupported("ReviewerAgent does not support tool calls".to_string()))
+    }
+
+    async fn get_current_state(&self) -> crate::Result<Option<State>> {
+        Ok(None)
+    }
+
+    async fn get_config(&self) -> crate::Result<AgentConfig> {
+        Ok(self.config.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Answers with each scripted reply in turn
+    struct Script(Mutex<Vec<&'static str>>);
+
+    impl Script {
+        fn new(replies: &[&'static str]) -> Self {
+            Self(Mutex::new(replies.iter().rev().copied().collect()))
+        }
+    }
+
+    #[async_trait]
+    impl AiProvider for Script {
+        async fn chat(&self, _system_prompt: &str, _messages: Vec<HashMap<String, String>>) -> Result<String> {
+            self.0.lock().unwrap().pop().map(str::to_string).ok_or_else(|| anyhow!("script exhausted"))
+        }
+    }
+
+    /// Writes "draft" first and "final" once it has been reviewed
+    struct Writer;
+
+    #[async_trait]
+    impl Agent for Writer {
+        async fn process_message(&self, message: Message) -> crate::Result<Message> {
+            let reply = if message.content.contains("A reviewer scored") { "final" } else { "draft" };
+            Ok(Message::new(reply.to_string()))
+        }
+        async fn transfer_to(&self, _target_agent: String, message: Message) -> crate::Result<Message> {
+            Ok(message)
+        }
+        async fn call_tool(&self, _tool: &Tool, _params: HashMap<String, String>) -> crate::Result<String> {
+            Ok(String::new())
+        }
+        async fn get_current_state(&self) -> crate::Result<Option<State>> {
+            Ok(None)
+        }
+        async fn get_config(&self) -> crate::Result<AgentConfig> {
+            Ok(default_config())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_low_score_gets_one_revision() {
+        let reviewer = ReviewerAgent::new(default_config()).with_ai_client(Script::new(&[
+            r#"{"score": 4, "feedback": "Too vague"}"#,
+            r#"```json
+            {"score": 6, "feedback": "Better"}
+            ```"#,
+        ]));
+        let request = Message::new("Write a commit message".to_string());
+        let draft = Writer.process_message(request.clone()).await.unwrap();
+
+        let reviewed = reviewer.review(&Writer, &request, draft).await.unwrap();
+        assert_eq!(reviewed.content, "final");
+        let review = Review::of(&reviewed).unwrap();
+        assert!(review.revised);
+        assert_eq!(review.critiques.len(), 2);
+        assert_eq!(review.critiques[0].feedback, "Too vague");
+        // Still below the threshold, but there is only one revision round
+        assert!(!review.critiques[1].passed);
+    }
+
+    #[tokio::test]
+    async fn test_passing_and_failed_reviews() {
+        let reviewer = ReviewerAgent::new(default_config()).with_threshold(5.0)
+            .with_ai_client(Script::new(&[r#"{"score": 8, "feedback": "Clear"}"#, "not json"]));
+        let request = Message::new("Write a haiku".to_string());
+
+        let reviewed = reviewer.review(&Writer, &request, Message::new("draft".to_string())).await.unwrap();
+        assert_eq!(reviewed.content, "draft");
+        assert!(Review::of(&reviewed).unwrap().critiques[0].passed);
+
+        // A review that can't be read lets the reply through untouched
+        let unreviewed = reviewer.review(&Writer, &request, Message::new("draft".to_string())).await.unwrap();
+        assert!(Review::of(&unreviewed).is_none());
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use super::model::TileCodingConfig;
+use super::{Action, State};
+
+/// Estimates action values; the learning rules in [`super::QLearningAgent`]
+/// work against any implementation
+pub trait QFunction<S, A> {
+    /// Current estimate of `action` in `state`
+    fn value(&self, state: &S, action: &A) -> f64;
+
+    /// Move the estimate for `action` in `state` toward `target`, returning
+    /// the error before the move
+    fn update(&mut self, state: &S, action: &A, target: f64, learning_rate: f64) -> f64;
+}
+
+/// The plain Q-table: one value per state-action pair seen, zero otherwise
+impl<S: State, A: Action> QFunction<S, A> for HashMap<(S, A), f64> {
+    fn value(&self, state: &S, action: &A) -> f64 {
+        *self.get(&(state.clone(), action.clone())).unwrap_or(&0.0)
+    }
+
+    fn update(&mut self, state: &S, action: &A, target: f64, learning_rate: f64) -> f64 {
+        let q = self.entry((state.clone(), action.clone())).or_insert(0.0);
+        let error = target - *q;
+        *q += learning_rate * error;
+        error
+    }
+}
+
+/// Linear approximation over hashed tile coding of [`State::to_features`].
+///
+/// Each of `tilings` grids covers the feature space with tiles `tile_width`
+/// wide, shifted a fraction of a tile from the others. A state-action pair
+/// activates one tile per grid and its value is the sum of their weights, so
+/// nearby states share what they learn while memory stays at `memory_size`
+/// weights however many states there are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TileCoder {
+    tilings: usize,
+    tile_width: f64,
+    weights: Vec<f64>,
+}
+
+impl TileCoder {
+    pub fn new(config: &TileCodingConfig) -> Self {
+        Self {
+            tilings: config.tilings.max(1),
+            tile_width: config.tile_width,
+            weights: vec![0.0; config.memory_size.max(1)],
+        }
+    }
+
+    /// Settings the coder was built with
+    pub fn config(&self) -> TileCodingConfig {
+        TileCodingConfig { tilings: self.tilings, tile_width: self.tile_width, memory_size: self.weights.len() }
+    }
+
+    /// Weight-wise mean of two coders with the same layout
+    pub fn average(&self, other: &TileCoder) -> TileCoder {
+        let weights = self.weights.iter().zip(&other.weights).map(|(a, b)| (a + b) / 2.0).collect();
+        TileCoder { weights, ..self.clone() }
+    }
+
+    /// Weight index of the tile each tiling activates
+    fn active_tiles(&self, features: &[f64], action: usize) -> Vec<usize> {
+        (0..self.tilings)
+            .map(|tiling| {
+                let mut hash = FNV_OFFSET;
+                hash = fnv(hash, tiling as u64);
+                hash = fnv(hash, action as u64);
+                for (dim, feature) in features.iter().enumerate() {
+                    // Odd per-dimension steps keep the grids from shifting diagonally in lockstep
+                    let shift = ((tiling * (2 * dim + 1)) % self.tilings) as f64 / self.tilings as f64;
+                    let coordinate = (feature / self.tile_width + shift).floor() as i64;
+                    hash = fnv(hash, coordinate as u64);
+                }
+                (hash % self.weights.len() as u64) as usize
+            })
+            .collect()
+    }
+}
+
+impl<S: State, A: Action> QFunction<S, A> for TileCoder {
+    fn value(&self, state: &S, action: &A) -> f64 {
+        self.active_tiles(&state.to_features(), action.to_index())
+            .into_iter()
+            .map(|tile| self.weights[tile])
+            .sum()
+    }
+
+    fn update(&mut self, state: &S, action: &A, target: f64, learning_rate: f64) -> f64 {
+        let tiles = self.active_tiles(&state.to_features(), action.to_index());
+        let error = target - tiles.iter().map(|&tile| self.weights[tile]).sum::<f64>();
+        // Split the step across tilings so the value moves by learning_rate * error
+        let step = learning_rate * error / self.tilings as f64;
+        for tile in tiles {
+            self.weights[tile] += step;
+        }
+        error
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// FNV-1a, stable across builds so saved weights stay valid
+fn fnv(hash: u64, value: u64) -> u64 {
+    value.to_le_bytes().iter().fold(hash, |h, &byte| (h ^ byte as u64).wrapping_mul(FNV_PRIME))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    struct Point(i32);
+
+    impl State for Point {
+        fn to_features(&self) -> Vec<f64> {
+            vec![self.0 as f64]
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    struct Go;
+
+    impl Action for Go {
+        fn to_index(&self) -> usize {
+            0
+        }
+
+        fn from_index(index: usize) -> Option<Self> {
+            (index == 0).then_some(Go)
+        }
+    }
+
+    #[test]
+    fn test_tile_coder_generalizes_to_neighbours() {
+        let mut coder = TileCoder::new(&TileCodingConfig { tilings: 4, tile_width: 10.0, memory_size: 1 << 16 });
+        for _ in 0..50 {
+            coder.update(&Point(100), &Go, 8.0, 0.5);
+        }
+        assert!((QFunction::<Point, Go>::value(&coder, &Point(100), &Go) - 8.0).abs() < 1e-6);
+
+        // A state a few units away shares most tiles, a distant one none
+        let near = QFunction::<Point, Go>::value(&coder, &Point(103), &Go);
+        assert!(near > 4.0 && near <= 8.0 + 1e-6, "near = {}", near);
+        assert_eq!(QFunction::<Point, Go>::value(&coder, &Point(500), &Go), 0.0);
+
+        let blank = TileCoder::new(&coder.config());
+        let averaged = QFunction::<Point, Go>::value(&coder.average(&blank), &Point(100), &Go);
+        assert!((averaged - 4.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,279 @@
+use std::fs;
+use std::path::Path;
+use crate::Result;
+use serde::{Deserialize, Serialize};
+use super::{Environment, QLearningAgent};
+
+/// z-score of a two-sided 95% confidence interval
+const Z_95: f64 = 1.96;
+
+/// One recorded step: the state seen, the action taken and its reward
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrajectoryStep<S, A> {
+    pub state: S,
+    pub action: A,
+    pub reward: f64,
+}
+
+/// Everything an evaluation episode did, for replaying it later
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Trajectory<S, A> {
+    pub episode: usize,
+    pub score: i32,
+    pub reward: f64,
+    pub steps: Vec<TrajectoryStep<S, A>>,
+    /// State the episode ended in
+    pub final_state: S,
+}
+
+impl<S: Serialize, A: Serialize> Trajectory<S, A> {
+    /// Save trajectories as a JSON array
+    pub fn save_all<P: AsRef<Path>>(trajectories: &[Self], path: P) -> Result<()> {
+        if let Some(parent) = path.as_ref().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string(trajectories)?)?;
+        Ok(())
+    }
+}
+
+impl<S: for<'de> Deserialize<'de>, A: for<'de> Deserialize<'de>> Trajectory<S, A> {
+    /// Load trajectories saved with [`Trajectory::save_all`]
+    pub fn load_all<P: AsRef<Path>>(path: P) -> Result<Vec<Self>> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+/// Summary of a sample, with a normal-approximation 95% confidence interval
+/// for the mean
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct ScoreStats {
+    pub count: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub min: f64,
+    pub max: f64,
+    /// Sample standard deviation
+    pub std_dev: f64,
+    pub ci95_low: f64,
+    pub ci95_high: f64,
+}
+
+impl ScoreStats {
+    pub fn from_values(values: &[f64]) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+        let count = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / count as f64;
+        let median = if count % 2 == 0 {
+            (sorted[count / 2 - 1] + sorted[count / 2]) / 2.0
+        } else {
+            sorted[count / 2]
+        };
+        let std_dev = if count > 1 {
+            (sorted.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (count - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+        let margin = Z_95 * std_dev / (count as f64).sqrt();
+        Self {
+            count,
+            mean,
+            median,
+            min: sorted[0],
+            max: sorted[count - 1],
+            std_dev,
+            ci95_low: mean - margin,
+            ci95_high: mean + margin,
+        }
+    }
+}
+
+/// Outcome of [`Evaluator::run`]
+#[derive(Debug, Clone)]
+pub struct EvaluationReport<S, A> {
+    pub scores: Vec<i32>,
+    pub rewards: Vec<f64>,
+    pub score_stats: ScoreStats,
+    pub reward_stats: ScoreStats,
+    /// Every episode's steps, when recording was on
+    pub trajectories: Vec<Trajectory<S, A>>,
+}
+
+/// Runs a trained agent greedily, with no exploration and no learning, and
+/// summarizes how it scores.
+///
+/// Episodes are scored with [`Environment::score`] when the environment keeps
+/// one and with the rounded episode reward otherwise, as in training.
+#[derive(Debug, Clone)]
+pub struct Evaluator {
+    episodes: usize,
+    max_steps: usize,
+    record: bool,
+}
+
+impl Evaluator {
+    pub fn new(episodes: usize) -> Self {
+        Self { episodes, max_steps: 10_000, record: false }
+    }
+
+    /// Cut off episodes that run longer than `max_steps`
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    /// Keep every episode's trajectory in the report
+    pub fn with_recording(mut self, record: bool) -> Self {
+        self.record = record;
+        self
+    }
+
+    pub fn run<E: Environment>(&self, env: &mut E, agent: &QLearningAgent<E::S, E::A>) -> EvaluationReport<E::S, E::A> {
+        let mut scores = Vec::with_capacity(self.episodes);
+        let mut rewards = Vec::with_capacity(self.episodes);
+        let mut trajectories = Vec::new();
+
+        for episode in 1..=self.episodes {
+            let mut state = env.reset();
+            let mut reward_total = 0.0;
+            let mut steps = Vec::new();
+            for _ in 0..self.max_steps {
+                let Some(action) = agent.greedy_action(&state, &env.valid_actions(&state)) else {
+                    break;
+                };
+                let (next_state, reward, done) = env.step(&action);
+                reward_total += reward;
+                if self.record {
+                    steps.push(TrajectoryStep { state, action, reward });
+                }
+                state = next_state;
+                if done {
+                    break;
+                }
+            }
+
+            let score = env.score().unwrap_or(reward_total.round() as i32);
+            scores.push(score);
+            rewards.push(reward_total);
+            if self.record {
+                trajectories.push(Trajectory { episode, score, reward: reward_total, steps, final_state: state });
+            }
+        }
+
+        let score_values: Vec<f64> = scores.iter().map(|&s| s as f64).collect();
+        EvaluationReport {
+            score_stats: ScoreStats::from_values(&score_values),
+            reward_stats: ScoreStats::from_values(&rewards),
+            scores,
+            rewards,
+            trajectories,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+    use crate::agents::rl::{Action, State};
+
+    #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+    struct Cell(i32);
+
+    impl State for Cell {
+        fn to_features(&self) -> Vec<f64> {
+            vec![self.0 as f64]
+        }
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+    enum Move {
+        Left,
+        Right,
+    }
+
+    impl Action for Move {
+        fn to_index(&self) -> usize {
+            match self {
+                Move::Left => 0,
+                Move::Right => 1,
+            }
+        }
+
+        fn from_index(index: usize) -> Option<Self> {
+            match index {
+                0 => Some(Move::Left),
+                1 => Some(Move::Right),
+                _ => None,
+            }
+        }
+    }
+
+    /// A corridor with the goal three cells to the right
+    struct Corridor {
+        position: i32,
+    }
+
+    impl Environment for Corridor {
+        type S = Cell;
+        type A = Move;
+
+        fn reset(&mut self) -> Cell {
+            self.position = 0;
+            Cell(0)
+        }
+
+        fn step(&mut self, action: &Move) -> (Cell, f64, bool) {
+            self.position += if *action == Move::Right { 1 } else { -1 };
+            let done = self.position == 3;
+            (Cell(self.position), if done { 10.0 } else { -1.0 }, done)
+        }
+
+        fn action_space_size(&self) -> usize {
+            2
+        }
+
+        fn valid_actions(&self, _state: &Cell) -> Vec<Move> {
+            vec![Move::Left, Move::Right]
+        }
+    }
+
+    #[test]
+    fn test_score_stats() {
+        let stats = ScoreStats::from_values(&[4.0, 1.0, 3.0, 2.0]);
+        assert_eq!((stats.count, stats.mean, stats.median, stats.min, stats.max), (4, 2.5, 2.5, 1.0, 4.0));
+        assert!((stats.std_dev - 1.290_994).abs() < 1e-6);
+        assert!(stats.ci95_low < 2.5 && stats.ci95_high > 2.5);
+        assert!((stats.ci95_high - stats.mean - 1.96 * stats.std_dev / 2.0).abs() < 1e-9);
+
+        let single = ScoreStats::from_values(&[7.0]);
+        assert_eq!((single.median, single.ci95_low, single.ci95_high), (7.0, 7.0, 7.0));
+        assert_eq!(ScoreStats::from_values(&[]).count, 0);
+    }
+
+    #[test]
+    fn test_greedy_evaluation_records_trajectories() {
+        // Explores constantly, but evaluation must follow the learned values only
+        let mut agent = QLearningAgent::new(0.5, 0.9, 1.0);
+        for position in 0..3 {
+            agent.update(&Cell(position), &Move::Right, 5.0, &Cell(position + 1), &[]);
+        }
+
+        let report = Evaluator::new(5).with_max_steps(20).with_recording(true).run(&mut Corridor { position: 0 }, &agent);
+        assert_eq!(report.rewards, vec![8.0; 5]);
+        assert_eq!(report.score_stats.mean, 8.0);
+        assert_eq!(report.score_stats.ci95_low, report.score_stats.ci95_high);
+        assert_eq!(report.trajectories.len(), 5);
+        assert_eq!(report.trajectories[0].steps.len(), 3);
+        assert_eq!(report.trajectories[0].final_state, Cell(3));
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("trajectories.json");
+        Trajectory::save_all(&report.trajectories, &path).unwrap();
+        assert_eq!(Trajectory::<Cell, Move>::load_all(&path).unwrap(), report.trajectories);
+    }
+}
@@ -196,6 +196,10 @@ impl Environment for FlappyBirdEnv {
     fn valid_actions(&self, _state: &Self::S) -> Vec<Self::A> {
         vec![FlappyBirdAction::Flap, FlappyBirdAction::DoNothing]
     }
+
+    fn score(&self) -> Option<i32> {
+        Some(self.state.score)
+    }
 }
 
 #[cfg(test)]
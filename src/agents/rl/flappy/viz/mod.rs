@@ -1,7 +1,22 @@
 use pixels::{Pixels, SurfaceTexture};
+use winit::event::{Event, VirtualKeyCode};
 use winit::window::Window;
 use winit_input_helper::WinitInputHelper;
-use super::FlappyBirdState;
+use crate::agents::rl::viz::playback::{TrajectoryPlayer, SEEK_STEP};
+use super::{FlappyBirdAction, FlappyBirdState};
+
+pub const FRAME_WIDTH: u32 = 288;
+pub const FRAME_HEIGHT: u32 = 512;
+
+/// Render a recorded Flappy Bird episode to a GIF or MP4 without a display
+#[cfg(feature = "rl-render")]
+pub fn render_episode(
+    trajectory: &crate::agents::rl::eval::Trajectory<FlappyBirdState, FlappyBirdAction>,
+    path: &std::path::Path,
+    options: &crate::agents::rl::viz::video::VideoOptions,
+) -> crate::Result<std::path::PathBuf> {
+    crate::agents::rl::viz::video::render_trajectory(trajectory, FRAME_WIDTH, FRAME_HEIGHT, FlappyViz::draw, path, options)
+}
 
 pub struct FlappyViz {
     pixels: Pixels,
@@ -12,15 +27,20 @@ impl FlappyViz {
     pub fn new(window: &Window) -> Self {
         let window_size = window.inner_size();
         let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, window);
-        let pixels = Pixels::new(288, 512, surface_texture).unwrap();
+        let pixels = Pixels::new(FRAME_WIDTH, FRAME_HEIGHT, surface_texture).unwrap();
         let input = WinitInputHelper::new();
 
         Self { pixels, input }
     }
 
     pub fn render(&mut self, state: &FlappyBirdState) {
-        let frame = self.pixels.frame_mut();
-        
+        Self::draw(state, self.pixels.frame_mut());
+        self.pixels.render().unwrap();
+    }
+
+    /// Draw `state` into an RGBA frame of [`FRAME_WIDTH`] x [`FRAME_HEIGHT`]
+    /// pixels, on screen or off
+    pub fn draw(state: &FlappyBirdState, frame: &mut [u8]) {
         // Clear screen (sky blue)
         for pixel in frame.chunks_exact_mut(4) {
             pixel[0] = 135; // R
@@ -56,8 +76,51 @@ impl FlappyViz {
 
         // Draw score
         Self::draw_score(state.score, frame);
+    }
 
-        self.pixels.render().unwrap();
+    /// Apply keyboard playback controls to `player`. Returns true once the
+    /// pending events are handled and the next frame should be drawn.
+    ///
+    /// Space pauses, Left/Right step a frame, PageUp/PageDown seek
+    /// [`SEEK_STEP`] frames, Home/End jump to either end and N/P switch to the
+    /// next or previous trajectory.
+    pub fn playback_controls<T>(
+        &mut self,
+        event: &Event<T>,
+        player: &mut TrajectoryPlayer<FlappyBirdState, FlappyBirdAction>,
+    ) -> bool {
+        if !self.input.update(event) {
+            return false;
+        }
+        let seek = SEEK_STEP as isize;
+        if self.input.key_pressed(VirtualKeyCode::Space) {
+            player.toggle_pause();
+        }
+        if self.input.key_pressed(VirtualKeyCode::Right) {
+            player.step(1);
+        }
+        if self.input.key_pressed(VirtualKeyCode::Left) {
+            player.step(-1);
+        }
+        if self.input.key_pressed(VirtualKeyCode::PageDown) {
+            player.seek_by(seek);
+        }
+        if self.input.key_pressed(VirtualKeyCode::PageUp) {
+            player.seek_by(-seek);
+        }
+        if self.input.key_pressed(VirtualKeyCode::Home) {
+            player.seek(0);
+        }
+        if self.input.key_pressed(VirtualKeyCode::End) {
+            player.seek(usize::MAX);
+        }
+        if self.input.key_pressed(VirtualKeyCode::N) {
+            player.switch_trajectory(1);
+        }
+        if self.input.key_pressed(VirtualKeyCode::P) {
+            player.switch_trajectory(-1);
+        }
+        true
     }
 
     fn draw_circle(y: f32, x: f32, radius: f32, color: &[u8; 4], frame: &mut [u8]) {
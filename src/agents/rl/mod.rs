@@ -6,24 +6,63 @@ use std::path::Path;
 use serde::{Serialize, Deserialize};
 use serde::de::DeserializeOwned;
 
+#[cfg(feature = "rl")]
+pub mod approx;
+#[cfg(feature = "rl")]
+pub mod eval;
 pub mod flappy;
 pub mod model;
 #[cfg(feature = "rl")]
+pub mod parallel;
+#[cfg(feature = "rl")]
+pub mod replay;
+#[cfg(feature = "rl-routing")]
+pub mod routing;
+#[cfg(feature = "rl")]
+pub mod runs;
+#[cfg(feature = "rl")]
+pub mod trainer;
+#[cfg(feature = "rl")]
 pub mod viz;
 
+#[cfg(feature = "rl")]
+pub use approx::{QFunction, TileCoder};
+#[cfg(feature = "rl")]
+pub use eval::{EvaluationReport, Evaluator, ScoreStats, Trajectory};
+#[cfg(feature = "rl")]
+pub use parallel::{ParallelReport, ParallelTrainer, ShardedQTable};
+#[cfg(feature = "rl")]
+pub use runs::{RunArtifact, RunInfo, RunManager, RunRequest, RunStatus};
+#[cfg(feature = "rl")]
+pub use trainer::{EarlyStopping, Trainer, TrainingReport};
+
 /// Trait for states in reinforcement learning environments
 #[cfg(feature = "rl")]
-pub trait State: Clone + Eq + std::hash::Hash + Serialize + DeserializeOwned {
+pub trait State: Clone + Eq + std::hash::Hash + Serialize + DeserializeOwned + Send + 'static {
     fn to_features(&self) -> Vec<f64>;
 }
 
 /// Trait for actions in reinforcement learning environments
 #[cfg(feature = "rl")]
-pub trait Action: Clone + Eq + std::hash::Hash + Serialize + DeserializeOwned {
+pub trait Action: Clone + Eq + std::hash::Hash + Serialize + DeserializeOwned + Send + 'static {
     fn to_index(&self) -> usize;
     fn from_index(index: usize) -> Option<Self>;
 }
 
+/// Run model file I/O, and the (de)serialization around it, on the blocking
+/// pool rather than an async worker thread
+#[cfg(feature = "rl")]
+async fn blocking<T, E>(work: impl FnOnce() -> Result<T, E> + Send + 'static) -> crate::Result<T>
+where
+    T: Send + 'static,
+    E: Into<crate::SwarmError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(work)
+        .await
+        .map_err(|e| crate::SwarmError::Agent(format!("Model I/O task failed: {}", e)))?
+        .map_err(Into::into)
+}
+
 /// The environment interface that RL agents interact with
 pub trait Environment {
     type S: State;
@@ -40,6 +79,11 @@ pub trait Environment {
 
     /// Get valid actions for current state
     fn valid_actions(&self, state: &Self::S) -> Vec<Self::A>;
+
+    /// Game score for the episode so far, for environments that keep one
+    fn score(&self) -> Option<i32> {
+        None
+    }
 }
 
 /// Q-Learning agent implementation
@@ -47,6 +91,13 @@ pub trait Environment {
 #[derive(Clone)]
 pub struct QLearningAgent<S: State + Serialize + for<'de> Deserialize<'de>, A: Action + Serialize + for<'de> Deserialize<'de>> {
     q_table: HashMap<(S, A), f64>,
+    /// Second table when Double Q-learning is on
+    q_table_b: Option<HashMap<(S, A), f64>>,
+    /// Replaces the tables when tile coding is configured
+    approximator: Option<TileCoder>,
+    approximator_b: Option<TileCoder>,
+    replay: Option<replay::ReplayBuffer<S, A>>,
+    model_format: model::ModelFormat,
     pub metadata: model::QModelMetadata,
     state_size: usize,
     action_size: usize,
@@ -60,6 +111,11 @@ impl<S: State + Serialize + for<'de> Deserialize<'de>, A: Action + Serialize + f
     pub fn new(learning_rate: f64, discount_factor: f64, epsilon: f64) -> Self {
         Self {
             q_table: HashMap::new(),
+            q_table_b: None,
+            approximator: None,
+            approximator_b: None,
+            replay: None,
+            model_format: model::ModelFormat::default(),
             metadata: model::QModelMetadata {
                 version: model::MODEL_VERSION.to_string(),
                 state_size: 0,
@@ -80,6 +136,84 @@ impl<S: State + Serialize + for<'de> Deserialize<'de>, A: Action + Serialize + f
         }
     }
 
+    /// An agent with the rates, value representation, experience replay and
+    /// Double Q-learning settings from `config`
+    pub fn from_config(config: &model::TrainingConfig) -> Self {
+        let mut agent = Self::new(config.learning_rate, config.discount_factor, config.epsilon);
+        agent.configure(config);
+        agent
+    }
+
+    /// Apply the value representation, experience replay, Double Q-learning
+    /// and model format settings from `config`, e.g. to an agent loaded from a
+    /// checkpoint. Switching between the table and tile coding starts the
+    /// values over; switching Double Q-learning off folds the two estimates
+    /// into one.
+    pub fn configure(&mut self, config: &model::TrainingConfig) {
+        self.replay = config.replay.enabled().then(|| replay::ReplayBuffer::new(&config.replay));
+        self.model_format = config.model_format;
+
+        match &config.q_function {
+            model::QFunctionConfig::Table if self.approximator.is_some() => {
+                self.approximator = None;
+                self.approximator_b = None;
+            }
+            model::QFunctionConfig::TileCoding(tiles) if self.approximator.is_none() => {
+                self.approximator = Some(TileCoder::new(tiles));
+                self.q_table.clear();
+                self.q_table_b = None;
+            }
+            _ => {}
+        }
+
+        let tile_coding = self.approximator.is_some();
+        match (tile_coding, config.double_q, self.double_q()) {
+            (true, true, false) => self.approximator_b = self.approximator.clone(),
+            (true, false, true) => {
+                self.approximator = self.merged_approximator();
+                self.approximator_b = None;
+            }
+            (false, true, false) => self.q_table_b = Some(self.q_table.clone()),
+            (false, false, true) => {
+                self.q_table = self.merged_q_table();
+                self.q_table_b = None;
+            }
+            _ => {}
+        }
+    }
+
+    /// Whether two estimates are learned for Double Q-learning
+    pub fn double_q(&self) -> bool {
+        self.q_table_b.is_some() || self.approximator_b.is_some()
+    }
+
+    /// Learned value of `action` in `state`; with Double Q-learning, the mean of both estimates
+    pub fn q_value(&self, state: &S, action: &A) -> f64 {
+        match &self.approximator {
+            Some(tiles) => pair_value(tiles, self.approximator_b.as_ref(), state, action),
+            None => pair_value(&self.q_table, self.q_table_b.as_ref(), state, action),
+        }
+    }
+
+    /// The Q-table as saved, with both Double Q-learning tables averaged
+    fn merged_q_table(&self) -> HashMap<(S, A), f64> {
+        match &self.q_table_b {
+            Some(table_b) => self.q_table.keys()
+                .chain(table_b.keys())
+                .map(|(state, action)| ((state.clone(), action.clone()), self.q_value(state, action)))
+                .collect(),
+            None => self.q_table.clone(),
+        }
+    }
+
+    /// The tile coder as saved, with both Double Q-learning estimates averaged
+    fn merged_approximator(&self) -> Option<TileCoder> {
+        match (&self.approximator, &self.approximator_b) {
+            (Some(tiles), Some(tiles_b)) => Some(tiles.average(tiles_b)),
+            (tiles, _) => tiles.clone(),
+        }
+    }
+
     /// Choose an action using epsilon-greedy policy
     pub fn choose_action(&mut self, state: &S, valid_actions: &[A]) -> A {
         // Update state_size and action_size if needed
@@ -97,42 +231,74 @@ impl<S: State + Serialize + for<'de> Deserialize<'de>, A: Action + Serialize + f
             valid_actions
                 .iter()
                 .max_by(|a1, a2| {
-                    let q1 = self.q_table.get(&(state.clone(), (*a1).clone())).unwrap_or(&0.0);
-                    let q2 = self.q_table.get(&(state.clone(), (*a2).clone())).unwrap_or(&0.0);
-                    q1.partial_cmp(q2).unwrap()
+                    let q1 = self.q_value(state, a1);
+                    let q2 = self.q_value(state, a2);
+                    q1.partial_cmp(&q2).unwrap()
                 })
                 .unwrap()
                 .clone()
         }
     }
 
-    /// Update Q-value based on experience
-    pub fn update(&mut self, state: &S, action: &A, reward: f64, next_state: &S) -> f64 {
-        // Get valid actions for the next state (for a real implementation, you would pass these in)
-        let valid_actions = vec![
-            A::from_index(0).unwrap(),
-            A::from_index(1).unwrap(),
-        ];
+    /// Update Q-value based on experience. `next_valid_actions` are the
+    /// actions available in `next_state`, as reported by
+    /// [`Environment::valid_actions`]; pass an empty slice when `next_state`
+    /// is terminal so no future value is bootstrapped.
+    ///
+    /// With experience replay on, the transition is stored and a batch of
+    /// past transitions is learned from as well.
+    pub fn update(&mut self, state: &S, action: &A, reward: f64, next_state: &S, next_valid_actions: &[A]) -> f64 {
+        let td_error = self.learn(state, action, reward, next_state, next_valid_actions);
+
+        if let Some(mut buffer) = self.replay.take() {
+            buffer.push(replay::Transition {
+                state: state.clone(),
+                action: action.clone(),
+                reward,
+                next_state: next_state.clone(),
+                next_valid_actions: next_valid_actions.to_vec(),
+            }, td_error);
+            for index in buffer.sample(&mut rand::thread_rng()) {
+                if let Some(t) = buffer.get(index) {
+                    let error = self.learn(&t.state, &t.action, t.reward, &t.next_state, &t.next_valid_actions);
+                    buffer.set_error(index, error);
+                }
+            }
+            self.replay = Some(buffer);
+        }
+
+        // Return the new Q-value
+        self.q_value(state, action)
+    }
 
-        // First, find the maximum Q-value for the next state
-        let next_max_q = valid_actions
+    /// One Q-learning step, returning the temporal-difference error it corrected
+    fn learn(&mut self, state: &S, action: &A, reward: f64, next_state: &S, next_valid_actions: &[A]) -> f64 {
+        let step = Step { state, action, reward, next_state, next_valid_actions };
+        let (learning_rate, discount_factor) = (self.learning_rate, self.discount_factor);
+        match self.approximator.as_mut() {
+            Some(tiles) => learn_step(tiles, self.approximator_b.as_mut(), step, learning_rate, discount_factor),
+            None => learn_step(&mut self.q_table, self.q_table_b.as_mut(), step, learning_rate, discount_factor),
+        }
+    }
+
+    /// Highest Q-value among `valid_actions` in `state`, unseen pairs counting as zero
+    pub fn max_q_value(&self, state: &S, valid_actions: &[A]) -> Option<f64> {
+        valid_actions
             .iter()
-            .map(|a| self.q_table.get(&(next_state.clone(), a.clone())).unwrap_or(&0.0))
-            .fold(f64::NEG_INFINITY, |a, &b| a.max(b))
-            .max(0.0);
+            .map(|a| self.q_value(state, a))
+            .reduce(f64::max)
+    }
 
-        // Then update the current Q-value
-        let current_q = self.q_table.entry((state.clone(), action.clone())).or_insert(0.0);
-        let old_q = *current_q;
-        *current_q = (1.0 - self.learning_rate) * *current_q + 
-                    self.learning_rate * (reward + self.discount_factor * next_max_q);
-        
-        // Return the new Q-value
-        *current_q
+    /// The highest-valued action in `state`, with no exploration
+    pub fn greedy_action(&self, state: &S, valid_actions: &[A]) -> Option<A> {
+        valid_actions
+            .iter()
+            .max_by(|a1, a2| self.q_value(state, a1).partial_cmp(&self.q_value(state, a2)).unwrap_or(std::cmp::Ordering::Equal))
+            .cloned()
     }
 
-    /// Save the model to a file
-    pub async fn save_model<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>> {
+    /// Save the model to a file in the configured [`model::ModelFormat`]
+    pub async fn save_model<P: AsRef<Path>>(&self, path: P) -> crate::Result<()> {
         let mut model = model::QModel::new(
             self.state_size,
             self.action_size,
@@ -145,19 +311,26 @@ impl<S: State + Serialize + for<'de> Deserialize<'de>, A: Action + Serialize + f
         model.metadata = self.metadata.clone();
         model.metadata.updated_at = Some(chrono::Utc::now());
         
-        // Copy Q-table
-        model.q_table = self.q_table.clone();
+        // Copy Q-table or tile-coding weights
+        model.q_table = self.merged_q_table();
+        model.approximator = self.merged_approximator();
         
-        // Save model to file
-        model.save(path)
+        // Serialize and write on the blocking pool
+        let (path, format) = (path.as_ref().to_path_buf(), self.model_format);
+        blocking(move || model.save_as(path, format)).await
     }
 
     /// Load the model from a file
-    pub async fn load_model<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Box<dyn std::error::Error>> {
-        let model = model::QModel::<S, A>::load(path)?;
+    pub async fn load_model<P: AsRef<Path>>(&mut self, path: P) -> crate::Result<()> {
+        let path = path.as_ref().to_path_buf();
+        let model = blocking(move || model::QModel::<S, A>::load(path)).await?;
         
-        // Copy Q-table
+        // Copy Q-table or tile-coding weights, keeping Double Q-learning on if it was
+        let double_q = self.double_q();
         self.q_table = model.q_table;
+        self.approximator = model.approximator;
+        self.q_table_b = (double_q && self.approximator.is_none()).then(|| self.q_table.clone());
+        self.approximator_b = if double_q { self.approximator.clone() } else { None };
         
         // Copy metadata
         self.metadata = model.metadata.clone();
@@ -178,7 +351,7 @@ impl<S: State + Serialize + for<'de> Deserialize<'de>, A: Action + Serialize + f
         base_path: P,
         episode: usize,
         is_best: bool,
-    ) -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+    ) -> crate::Result<std::path::PathBuf> {
         let mut model = model::QModel::new(
             self.state_size,
             self.action_size,
@@ -192,33 +365,47 @@ impl<S: State + Serialize + for<'de> Deserialize<'de>, A: Action + Serialize + f
         model.metadata.episodes_trained = episode;
         model.metadata.updated_at = Some(chrono::Utc::now());
         
-        // Copy Q-table
-        model.q_table = self.q_table.clone();
+        // Copy Q-table or tile-coding weights
+        model.q_table = self.merged_q_table();
+        model.approximator = self.merged_approximator();
         
         // Save checkpoint
-        model.save_checkpoint(base_path, episode, is_best)
+        let (base_path, format) = (base_path.as_ref().to_path_buf(), self.model_format);
+        blocking(move || model.save_checkpoint_as(base_path, episode, is_best, format)).await
     }
     
     /// Load the latest checkpoint
-    pub async fn load_latest_checkpoint<P: AsRef<Path>>(base_path: P) -> Result<Option<Self>, Box<dyn std::error::Error>> {
-        match model::QModel::<S, A>::load_latest_checkpoint(base_path)? {
-            Some(model) => {
-                let mut agent = Self::new(
-                    model.metadata.learning_rate,
-                    model.metadata.discount_factor,
-                    model.metadata.epsilon,
-                );
-                
-                // Copy Q-table and metadata
-                agent.q_table = model.q_table;
-                agent.metadata = model.metadata;
-                agent.state_size = agent.metadata.state_size;
-                agent.action_size = agent.metadata.action_size;
-                
-                Ok(Some(agent))
-            },
-            None => Ok(None),
-        }
+    pub async fn load_latest_checkpoint<P: AsRef<Path>>(base_path: P) -> crate::Result<Option<Self>> {
+        let base_path = base_path.as_ref().to_path_buf();
+        Ok(blocking(move || model::QModel::<S, A>::load_latest_checkpoint(base_path)).await?.map(Self::from_model))
+    }
+
+    /// Load the checkpoint saved at `episode`, if there is one
+    pub async fn load_checkpoint<P: AsRef<Path>>(base_path: P, episode: usize) -> crate::Result<Option<Self>> {
+        let base_path = base_path.as_ref().to_path_buf();
+        Ok(blocking(move || model::QModel::<S, A>::load_checkpoint(base_path, episode)).await?.map(Self::from_model))
+    }
+
+    /// Load the checkpoint with the highest recorded best score
+    pub async fn load_best_checkpoint<P: AsRef<Path>>(base_path: P) -> crate::Result<Option<Self>> {
+        let base_path = base_path.as_ref().to_path_buf();
+        Ok(blocking(move || model::QModel::<S, A>::load_best_checkpoint(base_path)).await?.map(Self::from_model))
+    }
+
+    fn from_model(model: model::QModel<S, A>) -> Self {
+        let mut agent = Self::new(
+            model.metadata.learning_rate,
+            model.metadata.discount_factor,
+            model.metadata.epsilon,
+        );
+        
+        // Copy Q-table and metadata
+        agent.q_table = model.q_table;
+        agent.approximator = model.approximator;
+        agent.metadata = model.metadata;
+        agent.state_size = agent.metadata.state_size;
+        agent.action_size = agent.metadata.action_size;
+        agent
     }
     
     /// Clean up old checkpoint files
@@ -244,22 +431,25 @@ impl<S: State + Serialize + for<'de> Deserialize<'de>, A: Action + Serialize + f
             checkpoint_path: "models".to_string(),
             save_metrics: true,
             metrics_path: "metrics".to_string(),
+            replay: self.replay.as_ref().map(|buffer| buffer.config()).unwrap_or_default(),
+            double_q: self.double_q(),
+            model_format: self.model_format,
+            q_function: match &self.approximator {
+                Some(tiles) => model::QFunctionConfig::TileCoding(tiles.config()),
+                None => model::QFunctionConfig::Table,
+            },
+            metrics_exporters: Vec::new(),
         }
     }
 
-    /// Calculate the average Q-value for the current state
-    pub fn calculate_avg_q_value(&self, state: &S) -> Option<f64> {
-        let valid_actions = vec![
-            A::from_index(0).unwrap(),
-            A::from_index(1).unwrap(),
-        ];
-        
+    /// Calculate the average Q-value over the actions valid in `state`
+    pub fn calculate_avg_q_value(&self, state: &S, valid_actions: &[A]) -> Option<f64> {
         if valid_actions.is_empty() {
             return None;
         }
         
         let sum: f64 = valid_actions.iter()
-            .map(|a| self.q_table.get(&(state.clone(), a.clone())).unwrap_or(&0.0))
+            .map(|a| self.q_value(state, a))
             .sum();
         
         Some(sum / valid_actions.len() as f64)
@@ -293,6 +483,62 @@ impl<S: State + Serialize + for<'de> Deserialize<'de>, A: Action + Serialize + f
     }
 }
 
+/// One transition being learned from
+#[cfg(feature = "rl")]
+struct Step<'a, S, A> {
+    state: &'a S,
+    action: &'a A,
+    reward: f64,
+    next_state: &'a S,
+    next_valid_actions: &'a [A],
+}
+
+/// Value from one estimate, or the mean of two under Double Q-learning
+#[cfg(feature = "rl")]
+fn pair_value<S, A, F: QFunction<S, A>>(primary: &F, secondary: Option<&F>, state: &S, action: &A) -> f64 {
+    let q = primary.value(state, action);
+    match secondary {
+        Some(secondary) => (q + secondary.value(state, action)) / 2.0,
+        None => q,
+    }
+}
+
+/// The action in `actions` with the highest value under `q`
+#[cfg(feature = "rl")]
+fn best_action<S, A: Clone, F: QFunction<S, A>>(q: &F, state: &S, actions: &[A]) -> Option<A> {
+    actions.iter()
+        .max_by(|a1, a2| q.value(state, a1).partial_cmp(&q.value(state, a2)).unwrap_or(std::cmp::Ordering::Equal))
+        .cloned()
+}
+
+/// Q-learning on `primary`, or Double Q-learning when there is a `secondary`
+/// estimate, returning the temporal-difference error corrected
+#[cfg(feature = "rl")]
+fn learn_step<S, A: Clone, F: QFunction<S, A>>(
+    primary: &mut F,
+    secondary: Option<&mut F>,
+    step: Step<'_, S, A>,
+    learning_rate: f64,
+    discount_factor: f64,
+) -> f64 {
+    let (learner, evaluator): (&mut F, &F) = match secondary {
+        // One estimate picks the next action, the other values it
+        Some(secondary) if rand::thread_rng().gen::<bool>() => (secondary, &*primary),
+        Some(secondary) => (primary, &*secondary),
+        None => {
+            let next_max_q = step.next_valid_actions.iter()
+                .map(|a| primary.value(step.next_state, a))
+                .reduce(f64::max)
+                .unwrap_or(0.0);
+            return primary.update(step.state, step.action, step.reward + discount_factor * next_max_q, learning_rate);
+        }
+    };
+    let next_q = best_action(&*learner, step.next_state, step.next_valid_actions)
+        .map(|a| evaluator.value(step.next_state, &a))
+        .unwrap_or(0.0);
+    learner.update(step.state, step.action, step.reward + discount_factor * next_q, learning_rate)
+}
+
 #[cfg(test)]
 #[cfg(feature = "rl")]
 mod tests {
@@ -461,7 +707,8 @@ mod tests {
             let (new_state, reward, is_done) = env.step(&action);
             
             // Update Q-values
-            agent.update(&state, &action, reward, &new_state);
+            let next_actions = if is_done { Vec::new() } else { env.valid_actions(&new_state) };
+            agent.update(&state, &action, reward, &new_state, &next_actions);
             
             // Update state and done
             state = new_state;
@@ -472,4 +719,195 @@ mod tests {
         // We should have some Q-values now
         assert!(!agent.q_table.is_empty());
     }
+
+    #[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+    struct GridCell(i32, i32);
+
+    impl State for GridCell {
+        fn to_features(&self) -> Vec<f64> {
+            vec![self.0 as f64, self.1 as f64]
+        }
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+    enum GridMove {
+        Up,
+        Down,
+        Left,
+        Right,
+    }
+
+    impl Action for GridMove {
+        fn to_index(&self) -> usize {
+            match self {
+                GridMove::Up => 0,
+                GridMove::Down => 1,
+                GridMove::Left => 2,
+                GridMove::Right => 3,
+            }
+        }
+
+        fn from_index(index: usize) -> Option<Self> {
+            match index {
+                0 => Some(GridMove::Up),
+                1 => Some(GridMove::Down),
+                2 => Some(GridMove::Left),
+                3 => Some(GridMove::Right),
+                _ => None,
+            }
+        }
+    }
+
+    /// 3x3 grid from the top-left corner to a goal in the bottom-right;
+    /// moves into a wall leave the agent in place
+    struct GridWorld {
+        cell: GridCell,
+    }
+
+    impl Environment for GridWorld {
+        type S = GridCell;
+        type A = GridMove;
+
+        fn reset(&mut self) -> Self::S {
+            self.cell = GridCell(0, 0);
+            self.cell
+        }
+
+        fn step(&mut self, action: &Self::A) -> (Self::S, f64, bool) {
+            let GridCell(row, col) = self.cell;
+            let (row, col) = match action {
+                GridMove::Up => (row - 1, col),
+                GridMove::Down => (row + 1, col),
+                GridMove::Left => (row, col - 1),
+                GridMove::Right => (row, col + 1),
+            };
+            self.cell = GridCell(row.clamp(0, 2), col.clamp(0, 2));
+            let done = self.cell == GridCell(2, 2);
+            (self.cell, if done { 10.0 } else { -1.0 }, done)
+        }
+
+        fn action_space_size(&self) -> usize {
+            4
+        }
+
+        fn valid_actions(&self, _state: &Self::S) -> Vec<Self::A> {
+            vec![GridMove::Up, GridMove::Down, GridMove::Left, GridMove::Right]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_update_uses_all_valid_actions() {
+        let env = GridWorld { cell: GridCell(0, 0) };
+        let actions = env.valid_actions(&GridCell(1, 0));
+        let mut agent = QLearningAgent::<GridCell, GridMove>::new(1.0, 0.5, 0.0);
+
+        // Only the fourth action has any value in the next state
+        agent.q_table.insert((GridCell(1, 0), GridMove::Right), 4.0);
+        let q = agent.update(&GridCell(0, 0), &GridMove::Down, -1.0, &GridCell(1, 0), &actions);
+        assert_eq!(q, -1.0 + 0.5 * 4.0);
+        assert_eq!(agent.calculate_avg_q_value(&GridCell(1, 0), &actions), Some(1.0));
+
+        // Terminal transitions bootstrap nothing, and negative values are kept
+        let q = agent.update(&GridCell(2, 1), &GridMove::Right, -3.0, &GridCell(2, 2), &[]);
+        assert_eq!(q, -3.0);
+
+        // The shortest path needs Right, which the old two-action update never valued
+        let config = model::TrainingConfig {
+            learning_rate: 0.5,
+            discount_factor: 0.9,
+            epsilon: 1.0,
+            epsilon_decay: 0.95,
+            min_epsilon: 0.0,
+            episodes: 300,
+            ..model::TrainingConfig::default()
+        };
+        let mut trainer = Trainer::new(GridWorld { cell: GridCell(0, 0) }, config)
+            .with_checkpoint_dir(None)
+            .with_max_steps(50);
+        let report = trainer.train().await.unwrap();
+        let last = report.history.metrics.last().unwrap();
+        assert_eq!(last.steps, 4);
+        assert_eq!(last.reward, 7.0);
+    }
+
+    /// Settings that settle on the greedy path within 300 episodes
+    fn quick_config() -> model::TrainingConfig {
+        model::TrainingConfig {
+            learning_rate: 0.5,
+            discount_factor: 0.9,
+            epsilon: 1.0,
+            epsilon_decay: 0.95,
+            min_epsilon: 0.0,
+            episodes: 300,
+            ..model::TrainingConfig::default()
+        }
+    }
+
+    /// Greedy episode length after training with `config`
+    async fn trained_steps<E: Environment>(env: E, config: model::TrainingConfig) -> usize {
+        let mut trainer = Trainer::new(env, config).with_checkpoint_dir(None).with_max_steps(50);
+        let report = trainer.train().await.unwrap();
+        report.history.metrics.last().unwrap().steps
+    }
+
+    #[tokio::test]
+    async fn test_replay_and_double_q_converge() {
+        let uniform = model::ReplayConfig { capacity: 500, batch_size: 8, strategy: model::SamplingStrategy::Uniform };
+        let prioritized = model::ReplayConfig { strategy: model::SamplingStrategy::Prioritized, ..uniform.clone() };
+        let settings = [
+            (uniform.clone(), false),
+            (prioritized.clone(), false),
+            (model::ReplayConfig::default(), true),
+            (prioritized, true),
+        ];
+
+        for (replay, double_q) in settings {
+            let label = format!("{:?} double_q={}", replay.strategy, double_q);
+            let config = model::TrainingConfig { replay, double_q, ..quick_config() };
+            // Five steps up to the goal, four right and down to the corner
+            assert_eq!(trained_steps(TestEnv { state: 0 }, config.clone()).await, 5, "{}", label);
+            assert_eq!(trained_steps(GridWorld { cell: GridCell(0, 0) }, config).await, 4, "{}", label);
+        }
+    }
+
+    #[test]
+    fn test_double_q_tables_merge_on_save() {
+        let config = model::TrainingConfig { double_q: true, ..model::TrainingConfig::default() };
+        let mut agent = QLearningAgent::<GridCell, GridMove>::from_config(&config);
+        agent.q_table.insert((GridCell(0, 0), GridMove::Right), 2.0);
+        agent.q_table_b.as_mut().unwrap().insert((GridCell(0, 0), GridMove::Right), 4.0);
+        assert_eq!(agent.q_value(&GridCell(0, 0), &GridMove::Right), 3.0);
+        assert_eq!(agent.merged_q_table().get(&(GridCell(0, 0), GridMove::Right)), Some(&3.0));
+        assert!(agent.get_config().double_q);
+
+        // Switching Double Q-learning off keeps the averaged values
+        agent.configure(&model::TrainingConfig::default());
+        assert!(agent.q_table_b.is_none());
+        assert_eq!(agent.q_value(&GridCell(0, 0), &GridMove::Right), 3.0);
+    }
+
+    #[tokio::test]
+    async fn test_tile_coding_learns_and_round_trips() {
+        let tiles = model::TileCodingConfig { tilings: 4, tile_width: 1.0, memory_size: 4096 };
+        for double_q in [false, true] {
+            let config = model::TrainingConfig {
+                q_function: model::QFunctionConfig::TileCoding(tiles.clone()),
+                double_q,
+                ..quick_config()
+            };
+            assert_eq!(trained_steps(GridWorld { cell: GridCell(0, 0) }, config).await, 4, "double_q={}", double_q);
+        }
+
+        let dir = tempdir().unwrap();
+        let config = model::TrainingConfig { q_function: model::QFunctionConfig::TileCoding(tiles.clone()), ..quick_config() };
+        let mut agent = QLearningAgent::<GridCell, GridMove>::from_config(&config);
+        agent.update(&GridCell(2, 1), &GridMove::Right, 10.0, &GridCell(2, 2), &[]);
+        assert!(agent.q_table.is_empty());
+        assert_eq!(agent.q_value(&GridCell(2, 1), &GridMove::Right), 5.0);
+
+        agent.save_checkpoint(dir.path(), 1, false).await.unwrap();
+        let loaded = QLearningAgent::<GridCell, GridMove>::load_latest_checkpoint(dir.path()).await.unwrap().unwrap();
+        assert_eq!(loaded.q_value(&GridCell(2, 1), &GridMove::Right), 5.0);
+        assert_eq!(loaded.get_config().q_function, model::QFunctionConfig::TileCoding(tiles));
+    }
 }
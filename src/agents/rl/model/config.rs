@@ -38,6 +38,175 @@ pub struct TrainingConfig {
     
     /// Path to save performance metrics
     pub metrics_path: String,
+
+    /// Experience replay; off unless `capacity` is set
+    #[serde(default)]
+    pub replay: ReplayConfig,
+
+    /// Learn two Q-tables and evaluate each one's choices with the other
+    #[serde(default)]
+    pub double_q: bool,
+
+    /// How action values are represented
+    #[serde(default)]
+    pub q_function: QFunctionConfig,
+
+    /// File format for saved models and checkpoints
+    #[serde(default)]
+    pub model_format: ModelFormat,
+
+    /// Where per-episode metrics are streamed under `metrics_path`
+    #[serde(default)]
+    pub metrics_exporters: Vec<MetricsExporterKind>,
+}
+
+/// A per-episode metrics sink; see [`crate::agents::rl::viz::export`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsExporterKind {
+    /// `episodes.csv`, one row per episode
+    Csv,
+    /// `episodes.jsonl`, one JSON object per episode
+    Jsonl,
+    /// TensorBoard event file under `tensorboard/`
+    Tensorboard,
+}
+
+impl std::str::FromStr for MetricsExporterKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "jsonl" => Ok(Self::Jsonl),
+            "tensorboard" => Ok(Self::Tensorboard),
+            other => Err(format!("unknown metrics exporter '{}' (expected csv, jsonl or tensorboard)", other)),
+        }
+    }
+}
+
+/// On-disk encoding of saved models. Loading detects the format, so
+/// checkpoints written in any of them can be read back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ModelFormat {
+    /// Pretty-printed JSON, easy to inspect
+    #[default]
+    Json,
+    /// MessagePack behind a format header
+    MessagePack,
+    /// MessagePack compressed with zstd, for large tables
+    MessagePackZstd,
+}
+
+impl ModelFormat {
+    /// File extension for checkpoints in this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ModelFormat::Json => "json",
+            ModelFormat::MessagePack | ModelFormat::MessagePackZstd => "qmodel",
+        }
+    }
+}
+
+impl std::str::FromStr for ModelFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "json" => Ok(Self::Json),
+            "msgpack" | "message_pack" => Ok(Self::MessagePack),
+            "msgpack_zstd" | "message_pack_zstd" | "zstd" => Ok(Self::MessagePackZstd),
+            other => Err(format!("unknown model format '{}' (expected json, msgpack or msgpack-zstd)", other)),
+        }
+    }
+}
+
+/// Representation of the learned action values
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum QFunctionConfig {
+    /// One value per state-action pair seen
+    #[default]
+    Table,
+    /// Linear approximation over tile-coded state features
+    TileCoding(TileCodingConfig),
+}
+
+/// Tile coding settings; see [`crate::agents::rl::approx::TileCoder`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TileCodingConfig {
+    /// Overlapping grids, each offset a fraction of a tile
+    pub tilings: usize,
+
+    /// Tile size in feature units; features should share a scale
+    pub tile_width: f64,
+
+    /// Weights kept; tiles hash into this many slots
+    pub memory_size: usize,
+}
+
+impl Default for TileCodingConfig {
+    fn default() -> Self {
+        Self {
+            tilings: 8,
+            tile_width: 10.0,
+            memory_size: 1 << 16,
+        }
+    }
+}
+
+/// How transitions are drawn from the replay buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplingStrategy {
+    /// Every stored transition is equally likely
+    #[default]
+    Uniform,
+    /// Transitions with a larger temporal-difference error are replayed more often
+    Prioritized,
+}
+
+impl std::str::FromStr for SamplingStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "uniform" => Ok(Self::Uniform),
+            "prioritized" => Ok(Self::Prioritized),
+            other => Err(format!("unknown sampling strategy '{}' (expected uniform or prioritized)", other)),
+        }
+    }
+}
+
+/// Experience replay settings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReplayConfig {
+    /// Transitions kept, oldest dropped first; 0 disables replay
+    pub capacity: usize,
+
+    /// Transitions replayed after every step
+    pub batch_size: usize,
+
+    pub strategy: SamplingStrategy,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 0,
+            batch_size: 32,
+            strategy: SamplingStrategy::Uniform,
+        }
+    }
+}
+
+impl ReplayConfig {
+    pub fn enabled(&self) -> bool {
+        self.capacity > 0 && self.batch_size > 0
+    }
 }
 
 impl Default for TrainingConfig {
@@ -54,6 +223,11 @@ impl Default for TrainingConfig {
             checkpoint_path: "models".to_string(),
             save_metrics: true,
             metrics_path: "metrics".to_string(),
+            replay: ReplayConfig::default(),
+            double_q: false,
+            q_function: QFunctionConfig::default(),
+            model_format: ModelFormat::default(),
+            metrics_exporters: Vec::new(),
         }
     }
 }
@@ -96,6 +270,17 @@ pub struct TrainingMetrics {
     pub avg_q_value: Option<f64>,
 }
 
+/// Column names of [`TrainingMetrics::csv_row`]
+pub const METRICS_CSV_HEADER: &str = "episode,reward,score,steps,epsilon,avg_q_value";
+
+impl TrainingMetrics {
+    /// The metrics as a CSV row; a missing average Q-value is left empty
+    pub fn csv_row(&self) -> String {
+        let avg_q = self.avg_q_value.map(|q| q.to_string()).unwrap_or_default();
+        format!("{},{},{},{},{},{}", self.episode, self.reward, self.score, self.steps, self.epsilon, avg_q)
+    }
+}
+
 /// History of training metrics
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TrainingHistory {
@@ -133,4 +318,38 @@ impl TrainingHistory {
         let history: TrainingHistory = serde_json::from_str(&json)?;
         Ok(history)
     }
+
+    /// The metrics as CSV, one row per episode
+    pub fn to_csv(&self) -> String {
+        let mut csv = format!("{}\n", METRICS_CSV_HEADER);
+        for m in &self.metrics {
+            csv.push_str(&m.csv_row());
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// Save the metrics as CSV for spreadsheets and plotting scripts
+    pub fn save_csv<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, self.to_csv())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_csv() {
+        let mut history = TrainingHistory::new(TrainingConfig::default());
+        history.add_metrics(TrainingMetrics { episode: 0, reward: -1.5, score: 0, steps: 12, epsilon: 0.1, avg_q_value: None });
+        history.add_metrics(TrainingMetrics { episode: 1, reward: 3.0, score: 2, steps: 40, epsilon: 0.0999, avg_q_value: Some(0.25) });
+
+        let csv = history.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "episode,reward,score,steps,epsilon,avg_q_value");
+        assert_eq!(lines[1], "0,-1.5,0,12,0.1,");
+        assert_eq!(lines[2], "1,3,2,40,0.0999,0.25");
+    }
 } 
@@ -0,0 +1,149 @@
+use crate::Result;
+use crate::error::SwarmError;
+use serde_json::{json, Value};
+use super::MODEL_VERSION;
+
+/// Rewrites a saved model from one version's layout to the next
+pub struct Migration {
+    pub from: &'static str,
+    pub to: &'static str,
+    pub description: &'static str,
+    pub apply: fn(Value) -> Result<Value>,
+}
+
+/// Every migration, applied in sequence from a model's version up to
+/// [`MODEL_VERSION`]. Add one here whenever the saved layout changes.
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    from: "1.0.0",
+    to: "1.1.0",
+    description: "Q-table stored as [state, action, value] entries",
+    apply: q_table_entries,
+}];
+
+/// Bring a decoded model up to [`MODEL_VERSION`] with [`MIGRATIONS`]
+pub fn migrate(model: Value, strict: bool) -> Result<Value> {
+    migrate_with(model, MIGRATIONS, MODEL_VERSION, strict)
+}
+
+/// Apply `migrations` in sequence until the model is at `target`. Without a
+/// path there, strict mode refuses the model; otherwise it is loaded as it
+/// is with a warning.
+pub fn migrate_with(mut model: Value, migrations: &[Migration], target: &str, strict: bool) -> Result<Value> {
+    loop {
+        let version = model_version(&model)?;
+        if version == target {
+            return Ok(model);
+        }
+        let Some(migration) = migrations.iter().find(|m| m.from == version) else {
+            let reason = if parse_version(&version) > parse_version(target) {
+                format!("model version {} is newer than {}", version, target)
+            } else {
+                format!("no migration from model version {} to {}", version, target)
+            };
+            if strict {
+                return Err(SwarmError::Validation(format!("Refusing to load model: {}", reason)));
+            }
+            tracing::warn!("Loading model as is: {}", reason);
+            return Ok(model);
+        };
+
+        tracing::info!("Migrating model {} -> {}: {}", migration.from, migration.to, migration.description);
+        model = (migration.apply)(model)
+            .map_err(|e| SwarmError::Agent(format!("Migrating model {} -> {} failed: {}", migration.from, migration.to, e)))?;
+        model["metadata"]["version"] = json!(migration.to);
+    }
+}
+
+fn model_version(model: &Value) -> Result<String> {
+    model["metadata"]["version"]
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| SwarmError::Validation("Model has no metadata.version".to_string()))
+}
+
+/// `major.minor.patch` as numbers; missing or unreadable parts count as zero
+fn parse_version(version: &str) -> Vec<u64> {
+    version.split('.').map(|part| part.parse().unwrap_or(0)).collect()
+}
+
+/// 1.0.0 wrote the Q-table as a JSON object, which only worked while it was
+/// empty or keyed by the `[state, action]` pair as JSON text
+fn q_table_entries(mut model: Value) -> Result<Value> {
+    let entries = match model["q_table"].take() {
+        Value::Null => Vec::new(),
+        Value::Array(entries) => entries,
+        Value::Object(table) => table
+            .into_iter()
+            .map(|(key, value)| {
+                let pair: Value = serde_json::from_str(&key)
+                    .map_err(|e| SwarmError::Validation(format!("Unreadable Q-table key {}: {}", key, e)))?;
+                match pair {
+                    Value::Array(pair) if pair.len() == 2 => Ok(json!([pair[0], pair[1], value])),
+                    _ => Err(SwarmError::Validation(format!("Q-table key {} is not a [state, action] pair", key))),
+                }
+            })
+            .collect::<Result<_>>()?,
+        other => return Err(SwarmError::Validation(format!("Unexpected Q-table {}", other))),
+    };
+    model["q_table"] = Value::Array(entries);
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model_at(version: &str) -> Value {
+        json!({ "metadata": { "version": version }, "q_table": [] })
+    }
+
+    #[test]
+    fn test_q_table_entries_migration() {
+        let empty = json!({ "metadata": { "version": "1.0.0" }, "q_table": {} });
+        let migrated = migrate(empty, true).unwrap();
+        assert_eq!(migrated["metadata"]["version"], MODEL_VERSION);
+        assert_eq!(migrated["q_table"], json!([]));
+
+        let keyed = json!({ "metadata": { "version": "1.0.0" }, "q_table": { "[3,\"Up\"]": 0.5 } });
+        let migrated = migrate(keyed, true).unwrap();
+        assert_eq!(migrated["q_table"], json!([[3, "Up", 0.5]]));
+
+        let broken = json!({ "metadata": { "version": "1.0.0" }, "q_table": { "oops": 0.5 } });
+        assert!(migrate(broken, false).is_err());
+    }
+
+    #[test]
+    fn test_migrations_chain_in_order() {
+        fn add_a(mut model: Value) -> Result<Value> {
+            model["steps"] = json!("a");
+            Ok(model)
+        }
+        fn add_b(mut model: Value) -> Result<Value> {
+            let steps = format!("{}b", model["steps"].as_str().unwrap_or(""));
+            model["steps"] = json!(steps);
+            Ok(model)
+        }
+        let migrations = [
+            Migration { from: "0.2.0", to: "0.3.0", description: "b", apply: add_b },
+            Migration { from: "0.1.0", to: "0.2.0", description: "a", apply: add_a },
+        ];
+
+        let migrated = migrate_with(model_at("0.1.0"), &migrations, "0.3.0", true).unwrap();
+        assert_eq!(migrated["steps"], "ab");
+        assert_eq!(migrated["metadata"]["version"], "0.3.0");
+
+        let migrated = migrate_with(model_at("0.2.0"), &migrations, "0.3.0", true).unwrap();
+        assert_eq!(migrated["steps"], "b");
+    }
+
+    #[test]
+    fn test_strict_mode_refuses_unmigratable_models() {
+        assert!(migrate_with(model_at("0.5.0"), MIGRATIONS, MODEL_VERSION, true).is_err());
+        assert!(migrate_with(model_at("9.0.0"), MIGRATIONS, MODEL_VERSION, true).is_err());
+        assert!(migrate_with(json!({ "q_table": [] }), MIGRATIONS, MODEL_VERSION, false).is_err());
+
+        // Lenient loading keeps the model as it was
+        let kept = migrate_with(model_at("0.5.0"), MIGRATIONS, MODEL_VERSION, false).unwrap();
+        assert_eq!(kept["metadata"]["version"], "0.5.0");
+    }
+}
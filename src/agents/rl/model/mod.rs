@@ -1,6 +1,6 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
 use std::fs;
 use std::hash::Hash;
@@ -8,11 +8,88 @@ use std::path::{Path, PathBuf};
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub mod config;
-pub use config::{TrainingConfig, TrainingHistory, TrainingMetrics};
-
-pub const MODEL_VERSION: &str = "1.0.0";
+pub mod migration;
+use super::approx::TileCoder;
+pub use config::{
+    MetricsExporterKind, ModelFormat, QFunctionConfig, ReplayConfig, SamplingStrategy, TileCodingConfig, TrainingConfig,
+    TrainingHistory, TrainingMetrics,
+};
+
+/// Layout version of saved models; see [`migration::MIGRATIONS`]
+pub const MODEL_VERSION: &str = "1.1.0";
 const CHECKPOINT_PREFIX: &str = "checkpoint_";
-const BEST_MODEL_FILENAME: &str = "best_model.json";
+const BEST_MODEL_STEM: &str = "best_model";
+
+/// Leading bytes of a binary model file, followed by the header version and
+/// a compression byte
+const BINARY_MAGIC: &[u8; 4] = b"SWQM";
+const BINARY_HEADER_VERSION: u8 = 1;
+const COMPRESSION_NONE: u8 = 0;
+const COMPRESSION_ZSTD: u8 = 1;
+const ZSTD_LEVEL: i32 = 3;
+
+/// Episode number of a checkpoint file name in any format
+fn checkpoint_episode(filename: &str) -> Option<usize> {
+    let rest = filename.strip_prefix(CHECKPOINT_PREFIX)?;
+    let digits = [ModelFormat::Json, ModelFormat::MessagePack]
+        .iter()
+        .find_map(|format| rest.strip_suffix(&format!(".{}", format.extension())))?;
+    digits.parse().ok()
+}
+
+/// A checkpoint file and the metadata saved in it
+#[derive(Debug, Clone)]
+pub struct CheckpointInfo {
+    pub path: PathBuf,
+    pub episode: usize,
+    pub metadata: QModelMetadata,
+}
+
+/// Checkpoint files in `dir`, oldest episode first. Episodes are compared as
+/// numbers, so `checkpoint_1000000` sorts after `checkpoint_999999`.
+fn checkpoint_files(dir: &Path) -> Result<Vec<(usize, PathBuf)>> {
+    let mut checkpoints = Vec::new();
+    if !dir.exists() {
+        return Ok(checkpoints);
+    }
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let episode = path.file_name().and_then(|name| name.to_str()).and_then(checkpoint_episode);
+        if let (true, Some(episode)) = (path.is_file(), episode) {
+            checkpoints.push((episode, path));
+        }
+    }
+    checkpoints.sort();
+    Ok(checkpoints)
+}
+
+/// Every checkpoint in `dir` with its metadata, oldest episode first.
+/// Files whose metadata can't be read are skipped with a warning.
+pub fn list_checkpoints<P: AsRef<Path>>(dir: P) -> Result<Vec<CheckpointInfo>> {
+    let mut checkpoints = Vec::new();
+    for (episode, path) in checkpoint_files(dir.as_ref())? {
+        match read_metadata(&path) {
+            Ok(metadata) => checkpoints.push(CheckpointInfo { path, episode, metadata }),
+            Err(e) => tracing::warn!("Skipping checkpoint {}: {}", path.display(), e),
+        }
+    }
+    Ok(checkpoints)
+}
+
+/// Metadata of a saved model without deserializing its Q-values
+fn read_metadata(path: &Path) -> Result<QModelMetadata> {
+    let mut model = read_value(path)?;
+    Ok(serde_json::from_value(model["metadata"].take())?)
+}
+
+/// A model file of any [`ModelFormat`] as untyped JSON, told apart by the binary header
+fn read_value(path: &Path) -> Result<serde_json::Value> {
+    let bytes = fs::read(path)?;
+    match bytes.strip_prefix(BINARY_MAGIC.as_slice()) {
+        Some(rest) => decode_binary(rest),
+        None => Ok(serde_json::from_slice(&bytes)?),
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QModelMetadata {
@@ -32,6 +109,8 @@ pub struct QModelMetadata {
 pub struct QModel<S, A> {
     pub metadata: QModelMetadata,
     pub q_table: HashMap<(S, A), f64>,
+    /// Weights of a tile-coding model; `q_table` is empty when set
+    pub approximator: Option<TileCoder>,
 }
 
 impl<S, A> QModel<S, A>
@@ -60,45 +139,79 @@ where
                 updated_at: Some(Utc::now()),
             },
             q_table: HashMap::new(),
+            approximator: None,
         }
     }
 
-    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn std::error::Error>>
+    /// Save as pretty-printed JSON
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> crate::Result<()>
     where
         S: Serialize,
         A: Serialize,
     {
-        // Update metadata before saving
+        self.save_as(path, ModelFormat::Json)
+    }
+
+    pub fn save_as<P: AsRef<Path>>(&self, path: P, format: ModelFormat) -> crate::Result<()>
+    where
+        S: Serialize,
+        A: Serialize,
+    {
+        // Update metadata before saving; the file is written in the current layout
         let mut serializable = SerializableQModel::from(self);
         serializable.metadata.updated_at = Some(Utc::now());
+        serializable.metadata.version = MODEL_VERSION.to_string();
 
         // Create parent directory if it doesn't exist
         if let Some(parent) = path.as_ref().parent() {
             fs::create_dir_all(parent)?;
         }
 
-        let json = serde_json::to_string_pretty(&serializable)?;
-        fs::write(path, json)?;
+        let bytes = match format {
+            ModelFormat::Json => serde_json::to_vec_pretty(&serializable)?,
+            ModelFormat::MessagePack => {
+                let mut bytes = binary_header(COMPRESSION_NONE);
+                bytes.extend(rmp_serde::to_vec_named(&serializable).map_err(anyhow::Error::from)?);
+                bytes
+            }
+            ModelFormat::MessagePackZstd => {
+                let payload = rmp_serde::to_vec_named(&serializable).map_err(anyhow::Error::from)?;
+                let mut bytes = binary_header(COMPRESSION_ZSTD);
+                bytes.extend(zstd::encode_all(payload.as_slice(), ZSTD_LEVEL)?);
+                bytes
+            }
+        };
+        fs::write(path, bytes)?;
         Ok(())
     }
 
+    /// Load a model in any [`ModelFormat`], migrating older layouts. A model
+    /// no migration applies to is loaded as is with a warning.
     pub fn load<P: AsRef<Path>>(path: P) -> Result<Self>
     where
         S: for<'de> Deserialize<'de>,
         A: for<'de> Deserialize<'de>,
     {
-        let json = fs::read_to_string(path)?;
-        let serializable: SerializableQModel<S, A> = serde_json::from_str(&json)?;
-
-        // Check version
-        if serializable.metadata.version != MODEL_VERSION {
-            println!(
-                "Warning: Loading model with version {} (current version is {})",
-                serializable.metadata.version, MODEL_VERSION
-            );
-            // In a real system, we might implement version migration here
-        }
+        Self::load_with(path, false)
+    }
 
+    /// Like [`QModel::load`], but refuse models that can't be migrated to
+    /// [`MODEL_VERSION`]
+    pub fn load_strict<P: AsRef<Path>>(path: P) -> Result<Self>
+    where
+        S: for<'de> Deserialize<'de>,
+        A: for<'de> Deserialize<'de>,
+    {
+        Self::load_with(path, true)
+    }
+
+    fn load_with<P: AsRef<Path>>(path: P, strict: bool) -> Result<Self>
+    where
+        S: for<'de> Deserialize<'de>,
+        A: for<'de> Deserialize<'de>,
+    {
+        let model = read_value(path.as_ref())?;
+        let serializable: SerializableQModel<S, A> = serde_json::from_value(migration::migrate(model, strict)?)?;
         Ok(serializable.into())
     }
 
@@ -107,7 +220,22 @@ where
         base_path: P,
         episode: usize,
         is_best: bool,
-    ) -> Result<PathBuf, Box<dyn std::error::Error>>
+    ) -> crate::Result<PathBuf>
+    where
+        S: Serialize,
+        A: Serialize,
+    {
+        self.save_checkpoint_as(base_path, episode, is_best, ModelFormat::Json)
+    }
+
+    /// Save a checkpoint in `format`, named with the format's extension
+    pub fn save_checkpoint_as<P: AsRef<Path>>(
+        &self,
+        base_path: P,
+        episode: usize,
+        is_best: bool,
+        format: ModelFormat,
+    ) -> crate::Result<PathBuf>
     where
         S: Serialize,
         A: Serialize,
@@ -117,13 +245,13 @@ where
         fs::create_dir_all(&base_dir)?;
 
         // Save regular checkpoint
-        let checkpoint_path = base_dir.join(format!("{}{:06}.json", CHECKPOINT_PREFIX, episode));
-        self.save(&checkpoint_path)?;
+        let checkpoint_path = base_dir.join(format!("{}{:06}.{}", CHECKPOINT_PREFIX, episode, format.extension()));
+        self.save_as(&checkpoint_path, format)?;
 
         // Save as best model if needed
         if is_best {
-            let best_model_path = base_dir.join(BEST_MODEL_FILENAME);
-            self.save(&best_model_path)?;
+            let best_model_path = base_dir.join(format!("{}.{}", BEST_MODEL_STEM, format.extension()));
+            self.save_as(&best_model_path, format)?;
         }
 
         Ok(checkpoint_path)
@@ -139,44 +267,49 @@ where
             return Ok(None);
         }
 
-        // Search for all checkpoint files
-        let mut checkpoints = Vec::new();
-        for entry in fs::read_dir(&base_dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() {
-                if let Some(filename) = path.file_name() {
-                    if let Some(filename_str) = filename.to_str() {
-                        if filename_str.starts_with(CHECKPOINT_PREFIX)
-                            && filename_str.ends_with(".json")
-                        {
-                            checkpoints.push(path);
-                        }
-                    }
-                }
-            }
-        }
-
-        // Find latest checkpoint
-        if let Some(latest) = checkpoints.iter().max_by(|a, b| {
-            a.file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .cmp(b.file_name().unwrap().to_str().unwrap())
-        }) {
+        if let Some((_, latest)) = checkpoint_files(&base_dir)?.pop() {
             return Ok(Some(Self::load(latest)?));
         }
 
         // If no checkpoint found, try to load the best model
-        let best_model_path = base_dir.join(BEST_MODEL_FILENAME);
-        if best_model_path.exists() {
-            return Ok(Some(Self::load(best_model_path)?));
+        for format in [ModelFormat::Json, ModelFormat::MessagePack] {
+            let best_model_path = base_dir.join(format!("{}.{}", BEST_MODEL_STEM, format.extension()));
+            if best_model_path.exists() {
+                return Ok(Some(Self::load(best_model_path)?));
+            }
         }
 
         Ok(None)
     }
 
+    /// Load the checkpoint saved at `episode`, if there is one
+    pub fn load_checkpoint<P: AsRef<Path>>(base_path: P, episode: usize) -> Result<Option<Self>>
+    where
+        S: for<'de> Deserialize<'de>,
+        A: for<'de> Deserialize<'de>,
+    {
+        match checkpoint_files(base_path.as_ref())?.into_iter().rev().find(|(e, _)| *e == episode) {
+            Some((_, path)) => Ok(Some(Self::load(path)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Load the checkpoint with the highest recorded `best_score`, preferring
+    /// the later episode on ties
+    pub fn load_best_checkpoint<P: AsRef<Path>>(base_path: P) -> Result<Option<Self>>
+    where
+        S: for<'de> Deserialize<'de>,
+        A: for<'de> Deserialize<'de>,
+    {
+        let best = list_checkpoints(base_path)?
+            .into_iter()
+            .reduce(|best, c| if c.metadata.best_score >= best.metadata.best_score { c } else { best });
+        match best {
+            Some(checkpoint) => Ok(Some(Self::load(checkpoint.path)?)),
+            None => Ok(None),
+        }
+    }
+
     pub fn update_metadata(
         &mut self,
         episodes_trained: Option<usize>,
@@ -208,32 +341,8 @@ where
             return Ok(0);
         }
 
-        // Find all checkpoint files
-        let mut checkpoints = Vec::new();
-        for entry in fs::read_dir(&base_dir)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file() {
-                if let Some(filename) = path.file_name() {
-                    if let Some(filename_str) = filename.to_str() {
-                        if filename_str.starts_with(CHECKPOINT_PREFIX)
-                            && filename_str.ends_with(".json")
-                        {
-                            checkpoints.push(path);
-                        }
-                    }
-                }
-            }
-        }
-
-        // Sort checkpoints by name (which contains the episode number)
-        checkpoints.sort_by(|a, b| {
-            a.file_name()
-                .unwrap()
-                .to_str()
-                .unwrap()
-                .cmp(b.file_name().unwrap().to_str().unwrap())
-        });
+        // Checkpoints in episode order
+        let checkpoints = checkpoint_files(&base_dir)?;
 
         // Decide which ones to keep
         let mut to_delete = Vec::new();
@@ -243,7 +352,7 @@ where
             return Ok(0);
         }
 
-        for (i, checkpoint) in checkpoints.iter().enumerate() {
+        for (i, (episode, checkpoint)) in checkpoints.iter().enumerate() {
             // Keep the latest 'keep_latest' checkpoints
             if i >= num_checkpoints - keep_latest {
                 continue;
@@ -251,21 +360,8 @@ where
 
             // Keep checkpoints at regular intervals if specified
             if let Some(interval) = keep_interval {
-                // Extract episode number from filename
-                if let Some(filename) = checkpoint.file_name() {
-                    if let Some(filename_str) = filename.to_str() {
-                        if filename_str.starts_with(CHECKPOINT_PREFIX)
-                            && filename_str.ends_with(".json")
-                        {
-                            let episode_part =
-                                &filename_str[CHECKPOINT_PREFIX.len()..filename_str.len() - 5];
-                            if let Ok(episode) = episode_part.parse::<usize>() {
-                                if episode % interval == 0 {
-                                    continue; // Keep this interval checkpoint
-                                }
-                            }
-                        }
-                    }
+                if episode % interval == 0 {
+                    continue; // Keep this interval checkpoint
                 }
             }
 
@@ -290,7 +386,57 @@ where
 )]
 struct SerializableQModel<S, A> {
     metadata: QModelMetadata,
+    #[serde(with = "q_entries")]
     q_table: HashMap<(S, A), f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    approximator: Option<TileCoder>,
+}
+
+fn binary_header(compression: u8) -> Vec<u8> {
+    let mut header = BINARY_MAGIC.to_vec();
+    header.extend([BINARY_HEADER_VERSION, compression]);
+    header
+}
+
+/// Decode what follows the magic bytes of a binary model file
+fn decode_binary(bytes: &[u8]) -> Result<serde_json::Value> {
+    let [header_version, compression, payload @ ..] = bytes else {
+        anyhow::bail!("Truncated model file header");
+    };
+    if *header_version != BINARY_HEADER_VERSION {
+        anyhow::bail!("Unsupported model file header version {}", header_version);
+    }
+    let model = match *compression {
+        COMPRESSION_NONE => rmp_serde::from_slice(payload)?,
+        COMPRESSION_ZSTD => rmp_serde::from_slice(&zstd::decode_all(payload)?)?,
+        other => anyhow::bail!("Unknown model compression {}", other),
+    };
+    Ok(model)
+}
+
+/// JSON object keys must be strings, so the table is stored as
+/// `[state, action, value]` entries
+mod q_entries {
+    use super::*;
+
+    pub fn serialize<S, A, Ser>(table: &HashMap<(S, A), f64>, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+    where
+        S: Serialize,
+        A: Serialize,
+        Ser: Serializer,
+    {
+        serializer.collect_seq(table.iter().map(|((state, action), value)| (state, action, value)))
+    }
+
+    pub fn deserialize<'de, S, A, D>(deserializer: D) -> Result<HashMap<(S, A), f64>, D::Error>
+    where
+        S: Deserialize<'de> + Eq + Hash,
+        A: Deserialize<'de> + Eq + Hash,
+        D: Deserializer<'de>,
+    {
+        let entries = Vec::<(S, A, f64)>::deserialize(deserializer)?;
+        Ok(entries.into_iter().map(|(state, action, value)| ((state, action), value)).collect())
+    }
 }
 
 impl<S, A> From<&QModel<S, A>> for SerializableQModel<S, A>
@@ -302,6 +448,7 @@ where
         Self {
             metadata: model.metadata.clone(),
             q_table: model.q_table.clone(),
+            approximator: model.approximator.clone(),
         }
     }
 }
@@ -311,6 +458,7 @@ impl<S, A> From<SerializableQModel<S, A>> for QModel<S, A> {
         Self {
             metadata: serializable.metadata,
             q_table: serializable.q_table,
+            approximator: serializable.approximator,
         }
     }
 }
@@ -383,4 +531,104 @@ mod tests {
         let deleted = QModel::<u32, u32>::clean_old_checkpoints(base_path, 1, None).unwrap();
         assert_eq!(deleted, 2);
     }
+
+    #[test]
+    fn test_binary_formats_round_trip() {
+        let dir = tempdir().unwrap();
+        let mut model = QModel::<u32, u32>::new(10, 4, 0.1, 0.99, 0.1);
+        for state in 0..2000 {
+            model.q_table.insert((state, state % 4), state as f64 * 0.25);
+        }
+
+        let json_path = dir.path().join("model.json");
+        model.save(&json_path).unwrap();
+        let json_size = fs::metadata(&json_path).unwrap().len();
+
+        for format in [ModelFormat::MessagePack, ModelFormat::MessagePackZstd] {
+            let path = dir.path().join(format!("{:?}.qmodel", format));
+            model.save_as(&path, format).unwrap();
+            assert!(fs::read(&path).unwrap().starts_with(BINARY_MAGIC));
+            assert!(fs::metadata(&path).unwrap().len() < json_size, "{:?} is not smaller than JSON", format);
+
+            let loaded = QModel::<u32, u32>::load(&path).unwrap();
+            assert_eq!(loaded.q_table, model.q_table);
+            assert_eq!(loaded.metadata.learning_rate, 0.1);
+        }
+
+        // JSON checkpoints from before the binary formats still load
+        let loaded = QModel::<u32, u32>::load(&json_path).unwrap();
+        assert_eq!(loaded.q_table, model.q_table);
+    }
+
+    #[test]
+    fn test_binary_checkpoints_are_found() {
+        let dir = tempdir().unwrap();
+        let mut model = QModel::<u32, u32>::new(10, 4, 0.1, 0.99, 0.1);
+        model.save_checkpoint(dir.path(), 100, false).unwrap();
+        model.q_table.insert((1, 1), 2.0);
+        let path = model.save_checkpoint_as(dir.path(), 200, true, ModelFormat::MessagePackZstd).unwrap();
+        assert_eq!(path.file_name().unwrap(), "checkpoint_000200.qmodel");
+        assert!(dir.path().join("best_model.qmodel").exists());
+        assert_eq!(checkpoint_episode("checkpoint_000200.qmodel"), Some(200));
+        assert_eq!(checkpoint_episode("best_model.json"), None);
+
+        let latest = QModel::<u32, u32>::load_latest_checkpoint(dir.path()).unwrap().unwrap();
+        assert_eq!(latest.q_table.get(&(1, 1)), Some(&2.0));
+    }
+
+    #[test]
+    fn test_load_migrates_old_models() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("old.json");
+        let mut model = QModel::<u32, u32>::new(10, 4, 0.1, 0.99, 0.1);
+        model.metadata.version = "1.0.0".to_string();
+        let mut old = serde_json::to_value(SerializableQModel::from(&model)).unwrap();
+        old["q_table"] = serde_json::json!({ "[7,1]": 1.5 });
+        fs::write(&path, old.to_string()).unwrap();
+
+        let loaded = QModel::<u32, u32>::load_strict(&path).unwrap();
+        assert_eq!(loaded.metadata.version, MODEL_VERSION);
+        assert_eq!(loaded.q_table.get(&(7, 1)), Some(&1.5));
+
+        old["metadata"]["version"] = serde_json::json!("0.1.0");
+        old["q_table"] = serde_json::json!([]);
+        fs::write(&path, old.to_string()).unwrap();
+        assert!(QModel::<u32, u32>::load_strict(&path).is_err());
+        assert!(QModel::<u32, u32>::load(&path).is_ok());
+    }
+
+    #[test]
+    fn test_checkpoint_selection() {
+        let dir = tempdir().unwrap();
+        let mut model = QModel::<u32, u32>::new(10, 4, 0.1, 0.99, 0.1);
+        for (episode, score) in [(999_999, 3.0), (1_000_000, 1.0), (50, 7.0), (200, 7.0)] {
+            model.update_metadata(Some(episode), Some(score), None);
+            model.q_table.insert((episode as u32, 0), score);
+            model.save_checkpoint(dir.path(), episode, false).unwrap();
+        }
+        fs::write(dir.path().join("checkpoint_000300.json"), "not a model").unwrap();
+
+        // Numeric order, unreadable files skipped
+        let listed = list_checkpoints(dir.path()).unwrap();
+        let episodes: Vec<usize> = listed.iter().map(|c| c.episode).collect();
+        assert_eq!(episodes, vec![50, 200, 999_999, 1_000_000]);
+        assert_eq!(listed[2].metadata.best_score, 3.0);
+        assert!(listed[0].metadata.updated_at.is_some());
+
+        let latest = QModel::<u32, u32>::load_latest_checkpoint(dir.path()).unwrap().unwrap();
+        assert_eq!(latest.metadata.episodes_trained, 1_000_000);
+
+        let at = QModel::<u32, u32>::load_checkpoint(dir.path(), 999_999).unwrap().unwrap();
+        assert_eq!(at.metadata.best_score, 3.0);
+        assert!(QModel::<u32, u32>::load_checkpoint(dir.path(), 7).unwrap().is_none());
+
+        // Ties go to the later episode
+        let best = QModel::<u32, u32>::load_best_checkpoint(dir.path()).unwrap().unwrap();
+        assert_eq!(best.metadata.episodes_trained, 200);
+
+        let deleted = QModel::<u32, u32>::clean_old_checkpoints(dir.path(), 2, None).unwrap();
+        assert_eq!(deleted, 3);
+        let left: Vec<usize> = list_checkpoints(dir.path()).unwrap().iter().map(|c| c.episode).collect();
+        assert_eq!(left, vec![999_999, 1_000_000]);
+    }
 }
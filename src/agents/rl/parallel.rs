@@ -0,0 +1,339 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+use rand::Rng;
+use super::approx::QFunction;
+use super::model::{QFunctionConfig, ReplayConfig, TrainingConfig, TrainingHistory, TrainingMetrics};
+use super::{best_action, learn_step, Action, Environment, QLearningAgent, State, Step};
+
+/// Q-table split into separately locked shards, so workers only contend
+/// when they touch state-action pairs that hash to the same shard
+pub struct ShardedQTable<S, A> {
+    shards: Vec<RwLock<HashMap<(S, A), f64>>>,
+}
+
+impl<S: State, A: Action> ShardedQTable<S, A> {
+    pub fn new(shards: usize) -> Self {
+        Self { shards: (0..shards.max(1)).map(|_| RwLock::default()).collect() }
+    }
+
+    fn shard(&self, state: &S, action: &A) -> &RwLock<HashMap<(S, A), f64>> {
+        let mut hasher = DefaultHasher::new();
+        state.hash(&mut hasher);
+        action.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
+
+    /// State-action pairs learned so far
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.read().unwrap_or_else(PoisonError::into_inner).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Merge the shards into a plain Q-table
+    pub fn into_table(self) -> HashMap<(S, A), f64> {
+        self.shards.into_iter()
+            .flat_map(|shard| shard.into_inner().unwrap_or_else(PoisonError::into_inner))
+            .collect()
+    }
+}
+
+/// Shared through a reference: each update locks only its own shard
+impl<S: State, A: Action> QFunction<S, A> for &ShardedQTable<S, A> {
+    fn value(&self, state: &S, action: &A) -> f64 {
+        let shard = self.shard(state, action).read().unwrap_or_else(PoisonError::into_inner);
+        *shard.get(&(state.clone(), action.clone())).unwrap_or(&0.0)
+    }
+
+    fn update(&mut self, state: &S, action: &A, target: f64, learning_rate: f64) -> f64 {
+        let mut shard = self.shard(state, action).write().unwrap_or_else(PoisonError::into_inner);
+        let q = shard.entry((state.clone(), action.clone())).or_insert(0.0);
+        let error = target - *q;
+        *q += learning_rate * error;
+        error
+    }
+}
+
+/// Outcome of [`ParallelTrainer::train`]
+pub struct ParallelReport<S: State, A: Action> {
+    /// Agent holding the merged Q-table, ready to checkpoint or keep training
+    pub agent: QLearningAgent<S, A>,
+    /// Metrics for every episode, in episode order
+    pub history: TrainingHistory,
+    pub workers: usize,
+    pub elapsed: Duration,
+}
+
+impl<S: State, A: Action> ParallelReport<S, A> {
+    pub fn episodes_per_sec(&self) -> f64 {
+        self.history.metrics.len() as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+/// Runs `config.episodes` episodes across worker threads, each with its own
+/// environment from `make_env(worker)`, all learning into one
+/// [`ShardedQTable`].
+///
+/// Workers claim episode numbers from a shared counter and explore with the
+/// epsilon that episode would have in sequential training, so results don't
+/// depend on which worker ran what. Only the plain table is shared: replay,
+/// Double Q-learning and tile coding settings are ignored.
+pub struct ParallelTrainer<F> {
+    make_env: F,
+    workers: usize,
+    shards: usize,
+    max_steps: usize,
+    config: TrainingConfig,
+}
+
+impl<F> ParallelTrainer<F> {
+    pub fn new(workers: usize, config: TrainingConfig, make_env: F) -> Self {
+        let workers = workers.max(1);
+        Self { make_env, workers, shards: workers * 16, max_steps: 10_000, config }
+    }
+
+    /// Lock shards in the shared table; more shards means less contention
+    pub fn with_shards(mut self, shards: usize) -> Self {
+        self.shards = shards.max(1);
+        self
+    }
+
+    /// Cut off episodes that run longer than `max_steps`
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn train<E>(&self) -> ParallelReport<E::S, E::A>
+    where
+        F: Fn(usize) -> E + Sync,
+        E: Environment,
+        E::S: Send + Sync,
+        E::A: Send + Sync,
+    {
+        if self.config.double_q || self.config.replay.enabled() || self.config.q_function != QFunctionConfig::Table {
+            tracing::warn!("Parallel training shares a plain Q-table; replay, Double Q-learning and tile coding are ignored");
+        }
+
+        let table = ShardedQTable::new(self.shards);
+        let next_episode = AtomicUsize::new(1);
+        let metrics = Mutex::new(Vec::with_capacity(self.config.episodes));
+        let started = Instant::now();
+
+        std::thread::scope(|scope| {
+            for worker in 0..self.workers {
+                let (table, next_episode, metrics) = (&table, &next_episode, &metrics);
+                scope.spawn(move || {
+                    let mut env = (self.make_env)(worker);
+                    loop {
+                        let episode = next_episode.fetch_add(1, Ordering::Relaxed);
+                        if episode > self.config.episodes {
+                            break;
+                        }
+                        let episode_metrics = self.run_episode(&mut env, table, episode);
+                        metrics.lock().unwrap_or_else(PoisonError::into_inner).push(episode_metrics);
+                    }
+                });
+            }
+        });
+
+        let elapsed = started.elapsed();
+        let mut metrics = metrics.into_inner().unwrap_or_else(PoisonError::into_inner);
+        metrics.sort_by_key(|m| m.episode);
+        let best_score = metrics.iter().map(|m| m.score).max().unwrap_or(0);
+        tracing::info!("{} episodes on {} workers in {:?}", metrics.len(), self.workers, elapsed);
+
+        let mut agent = QLearningAgent::from_config(&TrainingConfig {
+            replay: ReplayConfig::default(),
+            double_q: false,
+            q_function: QFunctionConfig::Table,
+            ..self.config.clone()
+        });
+        agent.q_table = table.into_table();
+        agent.update_metadata(
+            Some(self.config.episodes),
+            Some(best_score as f64),
+            Some(epsilon_for(&self.config, self.config.episodes + 1)),
+        );
+
+        let mut history = TrainingHistory::new(self.config.clone());
+        history.metrics = metrics;
+        ParallelReport { agent, history, workers: self.workers, elapsed }
+    }
+
+    fn run_episode<E>(&self, env: &mut E, table: &ShardedQTable<E::S, E::A>, episode: usize) -> TrainingMetrics
+    where
+        E: Environment,
+    {
+        let epsilon = epsilon_for(&self.config, episode);
+        let mut rng = rand::thread_rng();
+        let mut q = table;
+        let mut state = env.reset();
+        let mut reward_total = 0.0;
+        let mut steps = 0;
+        let mut q_sum = 0.0;
+
+        loop {
+            let valid_actions = env.valid_actions(&state);
+            if valid_actions.is_empty() {
+                break;
+            }
+            let action = if rng.gen::<f64>() < epsilon {
+                valid_actions[rng.gen_range(0..valid_actions.len())].clone()
+            } else {
+                match best_action(&q, &state, &valid_actions) {
+                    Some(action) => action,
+                    None => break,
+                }
+            };
+
+            let (next_state, reward, done) = env.step(&action);
+            let next_actions = if done { Vec::new() } else { env.valid_actions(&next_state) };
+            let step = Step { state: &state, action: &action, reward, next_state: &next_state, next_valid_actions: &next_actions };
+            learn_step(&mut q, None, step, self.config.learning_rate, self.config.discount_factor);
+            q_sum += valid_actions.iter().map(|a| q.value(&state, a)).sum::<f64>() / valid_actions.len() as f64;
+
+            reward_total += reward;
+            steps += 1;
+            state = next_state;
+            if done || steps >= self.max_steps {
+                break;
+            }
+        }
+
+        TrainingMetrics {
+            episode,
+            reward: reward_total,
+            score: env.score().unwrap_or(reward_total.round() as i32),
+            steps,
+            epsilon,
+            avg_q_value: (steps > 0).then(|| q_sum / steps as f64),
+        }
+    }
+}
+
+/// Epsilon for `episode` (counting from 1) after the decay of the episodes before it
+fn epsilon_for(config: &TrainingConfig, episode: usize) -> f64 {
+    let decays = episode.saturating_sub(1).min(i32::MAX as usize) as i32;
+    (config.epsilon * config.epsilon_decay.powi(decays)).max(config.min_epsilon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+    struct Cell(i32);
+
+    impl State for Cell {
+        fn to_features(&self) -> Vec<f64> {
+            vec![self.0 as f64]
+        }
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+    enum Move {
+        Left,
+        Right,
+    }
+
+    impl Action for Move {
+        fn to_index(&self) -> usize {
+            match self {
+                Move::Left => 0,
+                Move::Right => 1,
+            }
+        }
+
+        fn from_index(index: usize) -> Option<Self> {
+            match index {
+                0 => Some(Move::Left),
+                1 => Some(Move::Right),
+                _ => None,
+            }
+        }
+    }
+
+    /// A corridor with the goal six cells to the right
+    struct Corridor {
+        position: i32,
+    }
+
+    impl Environment for Corridor {
+        type S = Cell;
+        type A = Move;
+
+        fn reset(&mut self) -> Cell {
+            self.position = 0;
+            Cell(0)
+        }
+
+        fn step(&mut self, action: &Move) -> (Cell, f64, bool) {
+            self.position += if *action == Move::Right { 1 } else { -1 };
+            let done = self.position == 6 || self.position == -6;
+            let reward = if self.position == 6 { 10.0 } else { -1.0 };
+            (Cell(self.position), reward, done)
+        }
+
+        fn action_space_size(&self) -> usize {
+            2
+        }
+
+        fn valid_actions(&self, _state: &Cell) -> Vec<Move> {
+            vec![Move::Left, Move::Right]
+        }
+    }
+
+    #[test]
+    fn test_parallel_workers_share_one_table() {
+        let config = TrainingConfig {
+            learning_rate: 0.5,
+            discount_factor: 0.9,
+            epsilon: 1.0,
+            epsilon_decay: 0.98,
+            min_epsilon: 0.0,
+            episodes: 400,
+            ..TrainingConfig::default()
+        };
+        let report = ParallelTrainer::new(4, config, |_| Corridor { position: 0 })
+            .with_max_steps(100)
+            .train();
+
+        assert_eq!(report.workers, 4);
+        let episodes: Vec<usize> = report.history.metrics.iter().map(|m| m.episode).collect();
+        assert_eq!(episodes, (1..=400).collect::<Vec<_>>());
+        assert_eq!(report.agent.metadata.episodes_trained, 400);
+
+        // The merged table walks straight to the goal
+        let mut agent = report.agent;
+        agent.update_metadata(None, None, Some(0.0));
+        let mut env = Corridor { position: 0 };
+        let mut state = env.reset();
+        let mut steps = 0;
+        loop {
+            let action = agent.choose_action(&state, &env.valid_actions(&state));
+            let (next, _, done) = env.step(&action);
+            state = next;
+            steps += 1;
+            if done || steps > 20 {
+                break;
+            }
+        }
+        assert_eq!((state, steps), (Cell(6), 6));
+    }
+
+    #[test]
+    fn test_epsilon_schedule_matches_sequential_decay() {
+        let config = TrainingConfig { epsilon: 0.5, epsilon_decay: 0.5, min_epsilon: 0.1, ..TrainingConfig::default() };
+        assert_eq!(epsilon_for(&config, 1), 0.5);
+        assert_eq!(epsilon_for(&config, 2), 0.25);
+        assert_eq!(epsilon_for(&config, 10), 0.1);
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::VecDeque;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::Rng;
+use super::model::{ReplayConfig, SamplingStrategy};
+
+/// How strongly priorities skew sampling; 0 would be uniform
+const PRIORITY_EXPONENT: f64 = 0.6;
+/// Keeps transitions with no error left in the running
+const MIN_PRIORITY: f64 = 1e-3;
+
+/// One step of experience. An empty `next_valid_actions` marks a terminal step.
+#[derive(Debug, Clone)]
+pub struct Transition<S, A> {
+    pub state: S,
+    pub action: A,
+    pub reward: f64,
+    pub next_state: S,
+    pub next_valid_actions: Vec<A>,
+}
+
+/// Bounded store of past transitions, replayed in batches
+#[derive(Debug, Clone)]
+pub struct ReplayBuffer<S, A> {
+    transitions: VecDeque<Transition<S, A>>,
+    priorities: VecDeque<f64>,
+    capacity: usize,
+    batch_size: usize,
+    strategy: SamplingStrategy,
+}
+
+impl<S, A> ReplayBuffer<S, A> {
+    pub fn new(config: &ReplayConfig) -> Self {
+        Self {
+            transitions: VecDeque::with_capacity(config.capacity),
+            priorities: VecDeque::with_capacity(config.capacity),
+            capacity: config.capacity,
+            batch_size: config.batch_size,
+            strategy: config.strategy,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.transitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transitions.is_empty()
+    }
+
+    /// Settings the buffer was built with
+    pub fn config(&self) -> ReplayConfig {
+        ReplayConfig { capacity: self.capacity, batch_size: self.batch_size, strategy: self.strategy }
+    }
+
+    /// Store a transition, dropping the oldest once full. New transitions get
+    /// the highest priority seen so they are replayed at least once soon.
+    pub fn push(&mut self, transition: Transition<S, A>, td_error: f64) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.transitions.len() == self.capacity {
+            self.transitions.pop_front();
+            self.priorities.pop_front();
+        }
+        let highest = self.priorities.iter().cloned().fold(0.0, f64::max);
+        self.transitions.push_back(transition);
+        self.priorities.push_back(priority(td_error).max(highest));
+    }
+
+    /// Indices of a batch to replay; empty until the buffer holds a full batch
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Vec<usize> {
+        if self.batch_size == 0 || self.transitions.len() < self.batch_size {
+            return Vec::new();
+        }
+        match self.strategy {
+            SamplingStrategy::Uniform => (0..self.batch_size)
+                .map(|_| rng.gen_range(0..self.transitions.len()))
+                .collect(),
+            SamplingStrategy::Prioritized => match WeightedIndex::new(self.priorities.iter()) {
+                Ok(weights) => (0..self.batch_size).map(|_| weights.sample(rng)).collect(),
+                Err(_) => Vec::new(),
+            },
+        }
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Transition<S, A>> {
+        self.transitions.get(index)
+    }
+
+    /// Record the error a replayed transition still has
+    pub fn set_error(&mut self, index: usize, td_error: f64) {
+        if let Some(p) = self.priorities.get_mut(index) {
+            *p = priority(td_error);
+        }
+    }
+}
+
+fn priority(td_error: f64) -> f64 {
+    (td_error.abs() + MIN_PRIORITY).powf(PRIORITY_EXPONENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transition(reward: f64) -> Transition<i32, i32> {
+        Transition { state: 0, action: 0, reward, next_state: 1, next_valid_actions: vec![] }
+    }
+
+    #[test]
+    fn test_buffer_evicts_and_prioritizes() {
+        let config = ReplayConfig { capacity: 3, batch_size: 2, strategy: SamplingStrategy::Prioritized };
+        let mut buffer = ReplayBuffer::new(&config);
+        let mut rng = rand::thread_rng();
+
+        buffer.push(transition(1.0), 0.0);
+        assert!(buffer.sample(&mut rng).is_empty());
+        for reward in [2.0, 3.0, 4.0] {
+            buffer.push(transition(reward), 0.0);
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.get(0).unwrap().reward, 2.0);
+
+        // Once the others have no error left, the one that does dominates sampling
+        buffer.set_error(0, 0.0);
+        buffer.set_error(1, 0.0);
+        buffer.set_error(2, 100.0);
+        let picks: Vec<usize> = (0..50).flat_map(|_| buffer.sample(&mut rng)).collect();
+        assert_eq!(picks.len(), 100);
+        assert!(picks.iter().filter(|&&i| i == 2).count() > 80);
+    }
+}
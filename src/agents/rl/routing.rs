@@ -0,0 +1,299 @@
+//! Experimental: learn which agent should handle a message from how earlier
+//! transfers turned out.
+//!
+//! Each logged transfer is a one-step episode. The state is the message's
+//! features and the actions are the agents seen in the log. Routing to the
+//! logged target earns +1 when the transfer succeeded and -1 when it failed.
+//! Other targets earn nothing, since the log can't say how they would have
+//! done.
+
+use std::collections::BTreeSet;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use crate::Result;
+use crate::error::SwarmError;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use super::model::TrainingConfig;
+use super::{Action, Environment, QLearningAgent, State, Trainer};
+
+/// Keywords marking a message as being about a topic; one feature per topic
+const TOPICS: &[&[&str]] = &[
+    &["hello", "hi", "hey", "welcome"],
+    &["git", "commit", "branch", "merge", "diff"],
+    &["project", "init", "scaffold", "create"],
+    &["haiku", "poem", "poetry", "creative"],
+    &["todo", "task", "remind", "schedule"],
+    &["browse", "website", "url", "http", "https"],
+];
+
+/// A message reduced to a few discrete features: a flag per topic, a length
+/// bucket and whether it asks a question
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MessageFeatures(pub Vec<u8>);
+
+impl MessageFeatures {
+    pub fn from_message(text: &str) -> Self {
+        let lower = text.to_lowercase();
+        let words: Vec<&str> = lower.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect();
+        let mut features: Vec<u8> = TOPICS.iter()
+            .map(|keywords| words.iter().any(|word| keywords.contains(word)) as u8)
+            .collect();
+        features.push(match text.len() {
+            0..=19 => 0,
+            20..=79 => 1,
+            80..=199 => 2,
+            _ => 3,
+        });
+        features.push(text.contains('?') as u8);
+        Self(features)
+    }
+}
+
+impl State for MessageFeatures {
+    fn to_features(&self) -> Vec<f64> {
+        self.0.iter().map(|&f| f as f64).collect()
+    }
+}
+
+/// Hand the message to the named agent
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RouteTarget(pub String);
+
+impl Action for RouteTarget {
+    /// Targets are named, not numbered; tile coding gets a stable hash of the name
+    fn to_index(&self) -> usize {
+        self.0.bytes().fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)) as usize
+    }
+
+    fn from_index(_index: usize) -> Option<Self> {
+        None
+    }
+}
+
+/// How one transfer turned out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransferOutcome {
+    pub message: String,
+    pub from: String,
+    pub to: String,
+    pub success: bool,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Transfer outcomes appended to a JSON lines file, to train a
+/// [`LearnedRouter`] from
+#[derive(Debug, Clone)]
+pub struct OutcomeLog {
+    path: PathBuf,
+}
+
+impl OutcomeLog {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self { path: path.as_ref().to_path_buf() }
+    }
+
+    /// The log at `ROUTING_OUTCOME_LOG`, if set
+    pub fn from_env() -> Option<Self> {
+        std::env::var("ROUTING_OUTCOME_LOG").ok().filter(|path| !path.is_empty()).map(Self::new)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&self, outcome: &TransferOutcome) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(outcome)?)?;
+        Ok(())
+    }
+
+    /// Every outcome logged so far, skipping lines that don't parse
+    pub fn load(&self) -> Result<Vec<TransferOutcome>> {
+        let text = fs::read_to_string(&self.path)?;
+        Ok(text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(outcome) => Some(outcome),
+                Err(e) => {
+                    tracing::warn!("Skipping unreadable transfer outcome: {}", e);
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+/// Replays logged transfers as one-step episodes, in log order
+pub struct RoutingEnvironment {
+    outcomes: Vec<(MessageFeatures, TransferOutcome)>,
+    targets: Vec<RouteTarget>,
+    next: usize,
+    current: usize,
+}
+
+impl RoutingEnvironment {
+    pub fn new(outcomes: Vec<TransferOutcome>) -> Self {
+        let targets = outcomes.iter()
+            .map(|outcome| outcome.to.clone())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .map(RouteTarget)
+            .collect();
+        let outcomes = outcomes.into_iter()
+            .map(|outcome| (MessageFeatures::from_message(&outcome.message), outcome))
+            .collect();
+        Self { outcomes, targets, next: 0, current: 0 }
+    }
+
+    /// Every agent a logged transfer went to
+    pub fn targets(&self) -> &[RouteTarget] {
+        &self.targets
+    }
+}
+
+impl Environment for RoutingEnvironment {
+    type S = MessageFeatures;
+    type A = RouteTarget;
+
+    fn reset(&mut self) -> MessageFeatures {
+        self.current = self.next % self.outcomes.len();
+        self.next += 1;
+        self.outcomes[self.current].0.clone()
+    }
+
+    fn step(&mut self, action: &RouteTarget) -> (MessageFeatures, f64, bool) {
+        let (features, outcome) = &self.outcomes[self.current];
+        let reward = match (action.0 == outcome.to, outcome.success) {
+            (true, true) => 1.0,
+            (true, false) => -1.0,
+            (false, _) => 0.0,
+        };
+        (features.clone(), reward, true)
+    }
+
+    fn action_space_size(&self) -> usize {
+        self.targets.len()
+    }
+
+    fn valid_actions(&self, _state: &MessageFeatures) -> Vec<RouteTarget> {
+        self.targets.clone()
+    }
+}
+
+/// A routing policy learned from transfer outcomes
+pub struct LearnedRouter {
+    agent: QLearningAgent<MessageFeatures, RouteTarget>,
+}
+
+impl LearnedRouter {
+    /// Learn from `outcomes`, replaying them for `episodes` one-step episodes
+    pub async fn train(outcomes: Vec<TransferOutcome>, episodes: usize) -> Result<Self> {
+        if outcomes.is_empty() {
+            return Err(SwarmError::Validation("No transfer outcomes to learn from".to_string()));
+        }
+        let config = TrainingConfig {
+            learning_rate: 0.1,
+            discount_factor: 0.0,
+            epsilon: 1.0,
+            epsilon_decay: 0.995,
+            min_epsilon: 0.05,
+            episodes,
+            visualize: false,
+            checkpoint_freq: 0,
+            save_metrics: false,
+            ..TrainingConfig::default()
+        };
+        let mut trainer = Trainer::new(RoutingEnvironment::new(outcomes), config).with_checkpoint_dir(None);
+        trainer.train().await?;
+        Ok(Self { agent: trainer.into_agent() })
+    }
+
+    pub async fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut agent = QLearningAgent::new(0.1, 0.0, 0.0);
+        agent.load_model(path).await?;
+        Ok(Self { agent })
+    }
+
+    /// The policy saved at `ROUTING_POLICY_PATH`, if set
+    pub async fn from_env() -> Result<Option<Self>> {
+        match std::env::var("ROUTING_POLICY_PATH") {
+            Ok(path) if !path.is_empty() => Ok(Some(Self::load(path).await?)),
+            _ => Ok(None),
+        }
+    }
+
+    pub async fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        Ok(self.agent.save_model(path).await?)
+    }
+
+    /// The agent among `candidates` the policy expects to handle `message`
+    /// successfully. `None` when no candidate has a positive learned value,
+    /// e.g. for messages unlike anything in the log.
+    pub fn route(&self, message: &str, candidates: &[String]) -> Option<String> {
+        let state = MessageFeatures::from_message(message);
+        let targets: Vec<RouteTarget> = candidates.iter().cloned().map(RouteTarget).collect();
+        let best = self.agent.greedy_action(&state, &targets)?;
+        (self.agent.q_value(&state, &best) > 0.0).then_some(best.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn outcome(message: &str, to: &str, success: bool) -> TransferOutcome {
+        TransferOutcome {
+            message: message.to_string(),
+            from: "greeter".to_string(),
+            to: to.to_string(),
+            success,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_message_features() {
+        let features = MessageFeatures::from_message("Can you commit my changes?");
+        assert_eq!(features.0, vec![0, 1, 0, 0, 0, 0, 1, 1]);
+        // Keywords match whole words only
+        assert_eq!(MessageFeatures::from_message("this").0[0], 0);
+    }
+
+    #[tokio::test]
+    async fn test_router_learns_from_outcomes() {
+        let dir = tempdir().unwrap();
+        let log = OutcomeLog::new(dir.path().join("outcomes.jsonl"));
+        for outcome in [
+            outcome("please commit this", "git", true),
+            outcome("please commit this", "greeter", false),
+            outcome("hello there", "greeter", true),
+            outcome("hello there", "git", false),
+        ] {
+            log.record(&outcome).unwrap();
+        }
+        let outcomes = log.load().unwrap();
+        assert_eq!(outcomes.len(), 4);
+
+        let router = LearnedRouter::train(outcomes, 2000).await.unwrap();
+        let candidates = vec!["git".to_string(), "greeter".to_string(), "haiku".to_string()];
+        assert_eq!(router.route("please commit this", &candidates).as_deref(), Some("git"));
+        assert_eq!(router.route("hello there", &candidates).as_deref(), Some("greeter"));
+        // Nothing like this was logged, so the router defers
+        assert_eq!(router.route("write me a haiku", &candidates), None);
+        // Nor does it pick an agent that isn't a candidate
+        assert_eq!(router.route("please commit this", &["greeter".to_string()]), None);
+
+        let path = dir.path().join("router.json");
+        router.save(&path).await.unwrap();
+        let loaded = LearnedRouter::load(&path).await.unwrap();
+        assert_eq!(loaded.route("please commit this", &candidates).as_deref(), Some("git"));
+
+        assert!(LearnedRouter::train(Vec::new(), 10).await.is_err());
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::error::SwarmError;
+use crate::events::EventBus;
+use crate::Result;
+use super::flappy::FlappyBirdEnv;
+use super::model::{TrainingConfig, TrainingMetrics};
+use super::viz::export::MetricsExporter;
+use super::viz::VisualizationTools;
+use super::{Environment, Trainer};
+
+/// Environments a run can train against
+pub const ENVIRONMENTS: &[&str] = &["flappy"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Running,
+    /// Asked to stop; it does so before its next episode
+    Cancelling,
+    Completed,
+    Cancelled,
+    Failed,
+}
+
+/// A run to start. `config` holds any [`TrainingConfig`] fields to change from
+/// the defaults; checkpoint and metrics paths are always the run's own.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunRequest {
+    #[serde(default = "default_environment")]
+    pub environment: String,
+    #[serde(default)]
+    pub config: Value,
+    /// Cut off episodes that run longer than this
+    pub max_steps: Option<usize>,
+}
+
+fn default_environment() -> String {
+    "flappy".to_string()
+}
+
+impl RunRequest {
+    /// The defaults with `config`'s fields laid over them
+    pub fn training_config(&self) -> Result<TrainingConfig> {
+        let mut config = serde_json::to_value(TrainingConfig::default())?;
+        match &self.config {
+            Value::Null => {}
+            Value::Object(fields) => {
+                for (key, value) in fields {
+                    config[key] = value.clone();
+                }
+            }
+            _ => return Err(SwarmError::Validation("config must be an object".to_string())),
+        }
+        serde_json::from_value(config).map_err(|e| SwarmError::Validation(format!("Invalid training config: {}", e)))
+    }
+}
+
+/// Status and progress of a run
+#[derive(Debug, Clone, Serialize)]
+pub struct RunInfo {
+    pub id: String,
+    pub environment: String,
+    pub status: RunStatus,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Episodes the run is configured for
+    pub episodes: usize,
+    pub best_score: i32,
+    /// Metrics of the latest finished episode
+    pub latest: Option<TrainingMetrics>,
+    pub error: Option<String>,
+    /// Directory holding the run's checkpoints and metrics
+    pub dir: PathBuf,
+}
+
+/// A file a run wrote, relative to its directory
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RunArtifact {
+    pub path: String,
+    pub size: u64,
+    pub modified: Option<DateTime<Utc>>,
+}
+
+struct RunHandle {
+    info: Arc<Mutex<RunInfo>>,
+    stop: Arc<AtomicBool>,
+}
+
+/// Keeps each episode's metrics on the run's [`RunInfo`]
+struct ProgressExporter {
+    info: Arc<Mutex<RunInfo>>,
+}
+
+impl MetricsExporter for ProgressExporter {
+    fn record(&mut self, metrics: &TrainingMetrics) -> Result<()> {
+        let mut info = self.info.lock().unwrap();
+        info.best_score = info.best_score.max(metrics.score);
+        info.latest = Some(metrics.clone());
+        Ok(())
+    }
+}
+
+/// Training runs started in the background, each writing checkpoints, metrics
+/// and a final report under its own directory in `root`.
+///
+/// Runs are tracked for the life of the process; their files stay on disk.
+#[derive(Clone)]
+pub struct RunManager {
+    root: PathBuf,
+    runs: Arc<RwLock<HashMap<String, RunHandle>>>,
+    events: Option<EventBus>,
+}
+
+impl RunManager {
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Self { root: root.as_ref().to_path_buf(), runs: Arc::new(RwLock::new(HashMap::new())), events: None }
+    }
+
+    /// Publish every run's episodes on `events` as live metrics
+    pub fn with_event_bus(mut self, events: EventBus) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Validate `request` and start training it on a blocking thread of the
+    /// current Tokio runtime
+    pub fn start(&self, request: RunRequest) -> Result<RunInfo> {
+        let mut config = request.training_config()?;
+        if config.episodes == 0 {
+            return Err(SwarmError::Validation("episodes must be positive".to_string()));
+        }
+        if !ENVIRONMENTS.contains(&request.environment.as_str()) {
+            return Err(SwarmError::Validation(format!(
+                "Unknown environment '{}' (expected one of {})",
+                request.environment,
+                ENVIRONMENTS.join(", ")
+            )));
+        }
+
+        let id = format!("{}-{}", request.environment, &uuid::Uuid::new_v4().simple().to_string()[..8]);
+        let dir = self.root.join(&id);
+        config.checkpoint_path = dir.join("checkpoints").to_string_lossy().to_string();
+        config.metrics_path = dir.join("metrics").to_string_lossy().to_string();
+        config.visualize = false;
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("config.json"), serde_json::to_string_pretty(&config)?)?;
+
+        let info = Arc::new(Mutex::new(RunInfo {
+            id: id.clone(),
+            environment: request.environment.clone(),
+            status: RunStatus::Running,
+            started_at: Utc::now(),
+            finished_at: None,
+            episodes: config.episodes,
+            best_score: 0,
+            latest: None,
+            error: None,
+            dir,
+        }));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let mut trainer = Trainer::new(FlappyBirdEnv::default(), config)
+            .with_exporter(ProgressExporter { info: info.clone() })
+            .with_stop_flag(stop.clone());
+        if let Some(max_steps) = request.max_steps {
+            trainer = trainer.with_max_steps(max_steps);
+        }
+        if let Some(events) = &self.events {
+            trainer = trainer.with_live_metrics(events.clone(), id.clone());
+        }
+
+        let snapshot = info.lock().unwrap().clone();
+        self.runs.write().unwrap().insert(id.clone(), RunHandle { info: info.clone(), stop });
+        tracing::info!("Started training run {}", id);
+
+        let runtime = tokio::runtime::Handle::current();
+        tokio::task::spawn_blocking(move || {
+            let outcome = runtime.block_on(execute(trainer));
+            let mut info = info.lock().unwrap();
+            info.finished_at = Some(Utc::now());
+            match outcome {
+                Ok(true) => info.status = RunStatus::Cancelled,
+                Ok(false) => info.status = RunStatus::Completed,
+                Err(e) => {
+                    tracing::warn!("Training run {} failed: {}", info.id, e);
+                    info.status = RunStatus::Failed;
+                    info.error = Some(e.to_string());
+                }
+            }
+        });
+        Ok(snapshot)
+    }
+
+    /// Every run, newest first
+    pub fn list(&self) -> Vec<RunInfo> {
+        let runs = self.runs.read().unwrap();
+        let mut infos: Vec<RunInfo> = runs.values().map(|run| run.info.lock().unwrap().clone()).collect();
+        infos.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+        infos
+    }
+
+    pub fn get(&self, id: &str) -> Option<RunInfo> {
+        self.runs.read().unwrap().get(id).map(|run| run.info.lock().unwrap().clone())
+    }
+
+    /// Whether `id` is a run started here
+    pub fn owns(&self, id: &str) -> bool {
+        self.runs.read().unwrap().contains_key(id)
+    }
+
+    /// Ask a running run to stop before its next episode
+    pub fn cancel(&self, id: &str) -> Result<RunInfo> {
+        let runs = self.runs.read().unwrap();
+        let run = runs.get(id).ok_or_else(|| SwarmError::NotFound(format!("Training run '{}'", id)))?;
+        let mut info = run.info.lock().unwrap();
+        if info.status != RunStatus::Running {
+            return Err(SwarmError::Conflict(format!("Training run '{}' is {:?}", id, info.status)));
+        }
+        run.stop.store(true, Ordering::Relaxed);
+        info.status = RunStatus::Cancelling;
+        Ok(info.clone())
+    }
+
+    /// Files the run has written so far: its config, checkpoints, metrics and report
+    pub fn artifacts(&self, id: &str) -> Result<Vec<RunArtifact>> {
+        let dir = self.get(id)
+            .ok_or_else(|| SwarmError::NotFound(format!("Training run '{}'", id)))?
+            .dir;
+        let mut artifacts = Vec::new();
+        collect_artifacts(&dir, &dir, &mut artifacts)?;
+        artifacts.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(artifacts)
+    }
+}
+
+/// Train to the end, save the final model and write the report. Returns
+/// whether the run was cancelled.
+async fn execute<E: Environment>(mut trainer: Trainer<E>) -> Result<bool> {
+    let report = trainer.train().await?;
+    let config = report.history.config.clone();
+    let final_model = PathBuf::from(&config.checkpoint_path).join("final_model.json");
+    trainer.agent().save_model(&final_model).await
+        .map_err(|e| SwarmError::Agent(format!("Failed to save final model: {}", e)))?;
+    if !report.history.metrics.is_empty() {
+        VisualizationTools::new(&config.metrics_path).generate_report(&report.history)?;
+    }
+    Ok(report.cancelled)
+}
+
+fn collect_artifacts(root: &Path, dir: &Path, artifacts: &mut Vec<RunArtifact>) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            collect_artifacts(root, &entry.path(), artifacts)?;
+        } else {
+            let path = entry.path();
+            artifacts.push(RunArtifact {
+                path: path.strip_prefix(root).unwrap_or(&path).to_string_lossy().to_string(),
+                size: metadata.len(),
+                modified: metadata.modified().ok().map(DateTime::<Utc>::from),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_request_config_overrides_defaults() {
+        let request: RunRequest = serde_json::from_value(json!({ "config": { "episodes": 7, "learning_rate": 0.5 } })).unwrap();
+        assert_eq!(request.environment, "flappy");
+        let config = request.training_config().unwrap();
+        assert_eq!((config.episodes, config.learning_rate), (7, 0.5));
+        assert_eq!(config.discount_factor, TrainingConfig::default().discount_factor);
+
+        let bad: RunRequest = serde_json::from_value(json!({ "config": { "episodes": "lots" } })).unwrap();
+        assert!(matches!(bad.training_config(), Err(SwarmError::Validation(_))));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_run_completes_with_artifacts() {
+        let dir = tempdir().unwrap();
+        let manager = RunManager::new(dir.path());
+        let request: RunRequest = serde_json::from_value(json!({
+            "config": { "episodes": 3, "checkpoint_freq": 1 },
+            "max_steps": 50,
+        })).unwrap();
+        let run = manager.start(request).unwrap();
+        assert_eq!(run.status, RunStatus::Running);
+
+        let mut info = run.clone();
+        for _ in 0..200 {
+            info = manager.get(&run.id).unwrap();
+            if info.status != RunStatus::Running {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        assert_eq!(info.status, RunStatus::Completed, "{:?}", info.error);
+        assert_eq!(info.latest.unwrap().episode, 3);
+        assert!(matches!(manager.cancel(&run.id), Err(SwarmError::Conflict(_))));
+        assert!(matches!(manager.cancel("nope"), Err(SwarmError::NotFound(_))));
+
+        let artifacts = manager.artifacts(&run.id).unwrap();
+        let paths: Vec<&str> = artifacts.iter().map(|a| a.path.as_str()).collect();
+        assert!(paths.contains(&"config.json"));
+        assert!(paths.iter().any(|p| p.ends_with("final_model.json")));
+        assert!(paths.iter().any(|p| p.ends_with("training_report.html")));
+        assert_eq!(manager.list().len(), 1);
+        assert!(manager.owns(&run.id) && !manager.owns("nope"));
+
+        let unknown: RunRequest = serde_json::from_value(json!({ "environment": "pong" })).unwrap();
+        assert!(matches!(manager.start(unknown), Err(SwarmError::Validation(_))));
+    }
+}
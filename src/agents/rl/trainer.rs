@@ -0,0 +1,473 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use crate::Result;
+use crate::error::SwarmError;
+use crate::events::{Event, EventBus};
+use super::model::{TrainingConfig, TrainingHistory, TrainingMetrics};
+use super::viz::export::{exporters_for, MetricsExporter};
+use super::{Environment, QLearningAgent};
+
+/// Stop training once the moving average reward stops improving
+#[derive(Debug, Clone, PartialEq)]
+pub struct EarlyStopping {
+    /// Episodes in the moving average
+    pub window: usize,
+    /// Episodes without improvement before stopping
+    pub patience: usize,
+    /// Smallest rise in the moving average that counts as improvement
+    pub min_delta: f64,
+}
+
+impl Default for EarlyStopping {
+    fn default() -> Self {
+        Self { window: 50, patience: 200, min_delta: 0.01 }
+    }
+}
+
+/// Plateau detector fed one episode reward at a time
+#[derive(Debug, Clone)]
+struct PlateauTracker {
+    settings: EarlyStopping,
+    recent: VecDeque<f64>,
+    best_average: f64,
+    since_improvement: usize,
+}
+
+impl PlateauTracker {
+    fn new(settings: EarlyStopping) -> Self {
+        Self {
+            recent: VecDeque::with_capacity(settings.window),
+            settings,
+            best_average: f64::NEG_INFINITY,
+            since_improvement: 0,
+        }
+    }
+
+    /// Record a reward, returning true once the plateau has lasted `patience` episodes
+    fn observe(&mut self, reward: f64) -> bool {
+        self.recent.push_back(reward);
+        if self.recent.len() > self.settings.window {
+            self.recent.pop_front();
+        }
+        if self.recent.len() < self.settings.window {
+            return false;
+        }
+
+        let average = self.recent.iter().sum::<f64>() / self.recent.len() as f64;
+        if average > self.best_average + self.settings.min_delta {
+            self.best_average = average;
+            self.since_improvement = 0;
+        } else {
+            self.since_improvement += 1;
+        }
+        self.since_improvement >= self.settings.patience
+    }
+}
+
+/// Outcome of [`Trainer::train`]
+#[derive(Debug, Clone)]
+pub struct TrainingReport {
+    /// Last episode run
+    pub episodes: usize,
+    pub best_score: i32,
+    pub stopped_early: bool,
+    /// Stopped by [`Trainer::with_stop_flag`] before `config.episodes`
+    pub cancelled: bool,
+    pub history: TrainingHistory,
+}
+
+/// Runs Q-learning episodes against any [`Environment`]: epsilon decay,
+/// periodic and best-score checkpoints, per-episode metrics and optional
+/// early stopping.
+///
+/// Episodes are scored with [`Environment::score`] when the environment keeps
+/// one and with the rounded episode reward otherwise.
+pub struct Trainer<E: Environment> {
+    env: E,
+    agent: QLearningAgent<E::S, E::A>,
+    config: TrainingConfig,
+    history: TrainingHistory,
+    early_stopping: Option<EarlyStopping>,
+    checkpoint_dir: Option<PathBuf>,
+    keep_checkpoints: Option<usize>,
+    max_steps: usize,
+    best_score: i32,
+    live_metrics: Option<(EventBus, String)>,
+    exporters: Vec<Box<dyn MetricsExporter>>,
+    stop: Option<Arc<AtomicBool>>,
+}
+
+impl<E: Environment> Trainer<E> {
+    /// A fresh agent built from `config`, checkpointing to `config.checkpoint_path`
+    /// and, when metrics are saved, exporting them with `config.metrics_exporters`
+    pub fn new(env: E, config: TrainingConfig) -> Self {
+        let agent = QLearningAgent::from_config(&config);
+        let exporters = if config.save_metrics { exporters_for(&config) } else { Vec::new() };
+        Self {
+            env,
+            agent,
+            history: TrainingHistory::new(config.clone()),
+            checkpoint_dir: Some(PathBuf::from(&config.checkpoint_path)),
+            config,
+            early_stopping: None,
+            keep_checkpoints: None,
+            max_steps: 10_000,
+            best_score: 0,
+            live_metrics: None,
+            exporters,
+            stop: None,
+        }
+    }
+
+    /// Continue training an existing agent, e.g. one loaded from a checkpoint,
+    /// with the replay and Double Q-learning settings from this trainer's config
+    pub fn with_agent(mut self, mut agent: QLearningAgent<E::S, E::A>) -> Self {
+        agent.configure(&self.config);
+        self.best_score = agent.metadata.best_score as i32;
+        self.agent = agent;
+        self
+    }
+
+    pub fn with_early_stopping(mut self, early_stopping: EarlyStopping) -> Self {
+        self.early_stopping = Some(early_stopping);
+        self
+    }
+
+    /// Where checkpoints go; `None` turns checkpointing off
+    pub fn with_checkpoint_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.checkpoint_dir = dir;
+        self
+    }
+
+    /// Prune all but the latest `keep` checkpoints after each save
+    pub fn with_keep_checkpoints(mut self, keep: usize) -> Self {
+        self.keep_checkpoints = Some(keep);
+        self
+    }
+
+    /// Cut off episodes that run longer than `max_steps`
+    pub fn with_max_steps(mut self, max_steps: usize) -> Self {
+        self.max_steps = max_steps;
+        self
+    }
+
+    pub fn agent(&self) -> &QLearningAgent<E::S, E::A> {
+        &self.agent
+    }
+
+    pub fn into_agent(self) -> QLearningAgent<E::S, E::A> {
+        self.agent
+    }
+
+    pub fn env(&self) -> &E {
+        &self.env
+    }
+
+    pub fn history(&self) -> &TrainingHistory {
+        &self.history
+    }
+
+    /// Play one episode, learning from every step, then decay epsilon
+    pub fn run_episode(&mut self, episode: usize) -> TrainingMetrics {
+        let mut state = self.env.reset();
+        let mut reward_total = 0.0;
+        let mut steps = 0;
+        let mut q_sum = 0.0;
+        let mut q_count = 0;
+
+        loop {
+            let valid_actions = self.env.valid_actions(&state);
+            if valid_actions.is_empty() {
+                break;
+            }
+            let action = self.agent.choose_action(&state, &valid_actions);
+            let (next_state, reward, done) = self.env.step(&action);
+            let next_actions = if done { Vec::new() } else { self.env.valid_actions(&next_state) };
+            self.agent.update(&state, &action, reward, &next_state, &next_actions);
+            if let Some(q) = self.agent.calculate_avg_q_value(&state, &valid_actions) {
+                q_sum += q;
+                q_count += 1;
+            }
+
+            reward_total += reward;
+            steps += 1;
+            state = next_state;
+            if done || steps >= self.max_steps {
+                break;
+            }
+        }
+
+        let metrics = TrainingMetrics {
+            episode,
+            reward: reward_total,
+            score: self.env.score().unwrap_or(reward_total.round() as i32),
+            steps,
+            epsilon: self.agent.metadata.epsilon,
+            avg_q_value: (q_count > 0).then(|| q_sum / q_count as f64),
+        };
+        self.agent.decay_epsilon(&self.config);
+        metrics
+    }
+
+    /// Publish each episode's metrics on `bus` as [`Event::TrainingEpisode`],
+    /// which the MQTT bridge mirrors to `rl/<run_id>/metrics` and the API
+    /// streams to WebSocket clients
+    pub fn with_live_metrics(mut self, bus: EventBus, run_id: impl Into<String>) -> Self {
+        self.live_metrics = Some((bus, run_id.into()));
+        self
+    }
+
+    /// Export each episode's metrics to `exporter` as well
+    pub fn with_exporter(mut self, exporter: impl MetricsExporter + 'static) -> Self {
+        self.exporters.push(Box::new(exporter));
+        self
+    }
+
+    /// Stop before the next episode once `stop` is set, e.g. from another task
+    pub fn with_stop_flag(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Train through `config.episodes`
+    pub async fn train(&mut self) -> Result<TrainingReport> {
+        self.train_from(1).await
+    }
+
+    /// Train from `start_episode` through `config.episodes`, e.g. when resuming
+    pub async fn train_from(&mut self, start_episode: usize) -> Result<TrainingReport> {
+        let mut plateau = self.early_stopping.clone().map(PlateauTracker::new);
+        let mut last_episode = start_episode.saturating_sub(1);
+        let mut stopped_early = false;
+        let mut cancelled = false;
+
+        for episode in start_episode..=self.config.episodes {
+            if self.stop.as_ref().map_or(false, |stop| stop.load(Ordering::Relaxed)) {
+                tracing::info!("Stopping before episode {}: cancelled", episode);
+                cancelled = true;
+                break;
+            }
+            let metrics = self.run_episode(episode);
+            last_episode = episode;
+
+            let is_best = metrics.score > self.best_score;
+            if is_best {
+                self.best_score = metrics.score;
+                tracing::debug!("Episode {}: new best score {}", episode, self.best_score);
+            }
+            let reward = metrics.reward;
+            self.publish_metrics(&metrics);
+            for exporter in &mut self.exporters {
+                exporter.record(&metrics)?;
+            }
+            self.history.add_metrics(metrics);
+
+            let periodic = self.config.checkpoint_freq > 0 && episode % self.config.checkpoint_freq == 0;
+            if periodic || is_best {
+                self.checkpoint(episode, is_best).await?;
+            }
+
+            if plateau.as_mut().map_or(false, |tracker| tracker.observe(reward)) {
+                tracing::info!("Stopping at episode {}: reward plateaued", episode);
+                stopped_early = true;
+                break;
+            }
+        }
+
+        self.agent.update_metadata(Some(last_episode), Some(self.best_score as f64), None);
+        Ok(TrainingReport {
+            episodes: last_episode,
+            best_score: self.best_score,
+            stopped_early,
+            cancelled,
+            history: self.history.clone(),
+        })
+    }
+
+    fn publish_metrics(&self, metrics: &TrainingMetrics) {
+        let Some((bus, run_id)) = &self.live_metrics else {
+            return;
+        };
+        bus.publish(Event::TrainingEpisode {
+            run_id: run_id.clone(),
+            episode: metrics.episode,
+            reward: metrics.reward,
+            score: metrics.score,
+            steps: metrics.steps,
+            epsilon: metrics.epsilon,
+            avg_q_value: metrics.avg_q_value,
+            q_entries: self.agent.q_table.len(),
+        });
+    }
+
+    async fn checkpoint(&mut self, episode: usize, is_best: bool) -> Result<()> {
+        let Some(dir) = self.checkpoint_dir.clone() else {
+            return Ok(());
+        };
+        self.agent.update_metadata(Some(episode), Some(self.best_score as f64), None);
+        let path = self.agent.save_checkpoint(&dir, episode, is_best).await
+            .map_err(|e| SwarmError::Agent(format!("Failed to save checkpoint for episode {}: {}", episode, e)))?;
+        tracing::debug!("Checkpoint saved at {:?}", path);
+
+        if let Some(keep) = self.keep_checkpoints {
+            QLearningAgent::<E::S, E::A>::clean_old_checkpoints(&dir, keep, None)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use tempfile::tempdir;
+    use crate::agents::rl::{Action, State};
+
+    #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+    struct Cell(i32);
+
+    impl State for Cell {
+        fn to_features(&self) -> Vec<f64> {
+            vec![self.0 as f64]
+        }
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+    enum Move {
+        Left,
+        Right,
+    }
+
+    impl Action for Move {
+        fn to_index(&self) -> usize {
+            match self {
+                Move::Left => 0,
+                Move::Right => 1,
+            }
+        }
+
+        fn from_index(index: usize) -> Option<Self> {
+            match index {
+                0 => Some(Move::Left),
+                1 => Some(Move::Right),
+                _ => None,
+            }
+        }
+    }
+
+    /// A corridor with the goal four cells to the right
+    struct Corridor {
+        position: i32,
+    }
+
+    impl Environment for Corridor {
+        type S = Cell;
+        type A = Move;
+
+        fn reset(&mut self) -> Cell {
+            self.position = 0;
+            Cell(0)
+        }
+
+        fn step(&mut self, action: &Move) -> (Cell, f64, bool) {
+            self.position += if *action == Move::Right { 1 } else { -1 };
+            let done = self.position == 4 || self.position == -4;
+            let reward = if self.position == 4 { 10.0 } else { -1.0 };
+            (Cell(self.position), reward, done)
+        }
+
+        fn action_space_size(&self) -> usize {
+            2
+        }
+
+        fn valid_actions(&self, _state: &Cell) -> Vec<Move> {
+            vec![Move::Left, Move::Right]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_trainer_learns_and_checkpoints() {
+        let dir = tempdir().unwrap();
+        let config = TrainingConfig {
+            learning_rate: 0.5,
+            discount_factor: 0.9,
+            epsilon: 0.5,
+            epsilon_decay: 0.95,
+            min_epsilon: 0.0,
+            episodes: 200,
+            checkpoint_freq: 50,
+            checkpoint_path: dir.path().to_string_lossy().to_string(),
+            ..TrainingConfig::default()
+        };
+        let mut trainer = Trainer::new(Corridor { position: 0 }, config)
+            .with_max_steps(100)
+            .with_keep_checkpoints(2);
+
+        let report = trainer.train().await.unwrap();
+        assert_eq!(report.episodes, 200);
+        assert!(!report.stopped_early);
+        assert_eq!(report.history.metrics.len(), 200);
+        // The shortest path is three steps at -1 and the goal at +10
+        assert_eq!(report.history.metrics.last().unwrap().reward, 7.0);
+        assert_eq!(trainer.agent().metadata.episodes_trained, 200);
+        assert!(report.history.metrics.last().unwrap().epsilon < 0.01);
+
+        let resumed = QLearningAgent::<Cell, Move>::load_latest_checkpoint(dir.path()).await.unwrap().unwrap();
+        assert_eq!(resumed.metadata.episodes_trained, 200);
+    }
+
+    #[tokio::test]
+    async fn test_live_metrics_are_published() {
+        let bus = EventBus::new(16);
+        let mut events = bus.subscribe();
+        let config = TrainingConfig { episodes: 3, checkpoint_freq: 0, ..TrainingConfig::default() };
+        let mut trainer = Trainer::new(Corridor { position: 0 }, config)
+            .with_checkpoint_dir(None)
+            .with_max_steps(20)
+            .with_live_metrics(bus, "corridor");
+        trainer.train().await.unwrap();
+
+        for expected in 1..=3 {
+            let event = events.try_recv().unwrap();
+            assert_eq!(event.topic(), "rl/corridor/metrics");
+            let Event::TrainingEpisode { episode, steps, .. } = event else { panic!("expected a training event") };
+            assert_eq!(episode, expected);
+            assert!(steps > 0);
+        }
+        assert!(events.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stop_flag_cancels_training() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let config = TrainingConfig { episodes: 10, checkpoint_freq: 0, ..TrainingConfig::default() };
+        let mut trainer = Trainer::new(Corridor { position: 0 }, config)
+            .with_checkpoint_dir(None)
+            .with_max_steps(20)
+            .with_stop_flag(stop.clone());
+        for episode in 1..=3 {
+            trainer.run_episode(episode);
+        }
+        stop.store(true, Ordering::Relaxed);
+
+        let report = trainer.train_from(4).await.unwrap();
+        assert!(report.cancelled);
+        assert_eq!(report.episodes, 3);
+        assert!(report.history.metrics.is_empty());
+    }
+
+    #[test]
+    fn test_plateau_detection() {
+        let mut tracker = PlateauTracker::new(EarlyStopping { window: 3, patience: 2, min_delta: 0.5 });
+        // Rising rewards keep improving the average
+        for reward in [1.0, 2.0, 3.0, 4.0, 5.0] {
+            assert!(!tracker.observe(reward));
+        }
+        // Flat rewards stop after `patience` non-improving averages
+        assert!(!tracker.observe(5.0));
+        assert!(!tracker.observe(5.0));
+        assert!(tracker.observe(5.0));
+    }
+}
@@ -0,0 +1,264 @@
+use crate::Result;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::agents::rl::model::config::{MetricsExporterKind, TrainingConfig, TrainingMetrics, METRICS_CSV_HEADER};
+
+/// Receives each episode's metrics as training runs, so runs can be followed
+/// and compared in standard tooling
+pub trait MetricsExporter: Send {
+    fn record(&mut self, metrics: &TrainingMetrics) -> Result<()>;
+}
+
+/// The exporters `config.metrics_exporters` selects, writing under `config.metrics_path`
+pub fn exporters_for(config: &TrainingConfig) -> Vec<Box<dyn MetricsExporter>> {
+    let dir = PathBuf::from(&config.metrics_path);
+    config.metrics_exporters.iter()
+        .map(|kind| -> Box<dyn MetricsExporter> {
+            match kind {
+                MetricsExporterKind::Csv => Box::new(CsvExporter::new(dir.join("episodes.csv"))),
+                MetricsExporterKind::Jsonl => Box::new(JsonlExporter::new(dir.join("episodes.jsonl"))),
+                MetricsExporterKind::Tensorboard => Box::new(TensorBoardExporter::new(dir.join("tensorboard"))),
+            }
+        })
+        .collect()
+}
+
+/// Open `path` for appending, creating it and its directory as needed
+fn append_to(path: &Path) -> Result<File> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(OpenOptions::new().create(true).append(true).open(path)?)
+}
+
+/// Appends a CSV row per episode. A header is written when the file is new,
+/// so a resumed run keeps extending the same file.
+pub struct CsvExporter {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl CsvExporter {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self { path: path.as_ref().to_path_buf(), file: None }
+    }
+}
+
+impl MetricsExporter for CsvExporter {
+    fn record(&mut self, metrics: &TrainingMetrics) -> Result<()> {
+        if self.file.is_none() {
+            let mut file = append_to(&self.path)?;
+            if file.metadata()?.len() == 0 {
+                writeln!(file, "{}", METRICS_CSV_HEADER)?;
+            }
+            self.file = Some(file);
+        }
+        if let Some(file) = &mut self.file {
+            writeln!(file, "{}", metrics.csv_row())?;
+        }
+        Ok(())
+    }
+}
+
+/// Appends one JSON object per episode
+pub struct JsonlExporter {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl JsonlExporter {
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        Self { path: path.as_ref().to_path_buf(), file: None }
+    }
+}
+
+impl MetricsExporter for JsonlExporter {
+    fn record(&mut self, metrics: &TrainingMetrics) -> Result<()> {
+        if self.file.is_none() {
+            self.file = Some(append_to(&self.path)?);
+        }
+        if let Some(file) = &mut self.file {
+            writeln!(file, "{}", serde_json::to_string(metrics)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// Writes a TensorBoard event file in `dir`, with one scalar per metric under
+/// `train/` stepped by episode. Point `tensorboard --logdir` at the parent
+/// directory to compare runs.
+pub struct TensorBoardExporter {
+    dir: PathBuf,
+    file: Option<File>,
+}
+
+impl TensorBoardExporter {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self { dir: dir.as_ref().to_path_buf(), file: None }
+    }
+
+    /// Start a new event file, which TensorBoard expects to open with a version record
+    fn open(&self) -> Result<File> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let path = self.dir.join(format!("events.out.tfevents.{}.swarmonomicon", now.as_secs()));
+        let mut file = append_to(&path)?;
+        let mut event = Vec::new();
+        put_double(&mut event, 1, now.as_secs_f64());
+        put_bytes(&mut event, 3, b"brain.Event:2");
+        file.write_all(&tf_record(&event))?;
+        Ok(file)
+    }
+}
+
+impl MetricsExporter for TensorBoardExporter {
+    fn record(&mut self, metrics: &TrainingMetrics) -> Result<()> {
+        if self.file.is_none() {
+            self.file = Some(self.open()?);
+        }
+        let mut scalars = vec![
+            ("train/reward", metrics.reward),
+            ("train/score", metrics.score as f64),
+            ("train/steps", metrics.steps as f64),
+            ("train/epsilon", metrics.epsilon),
+        ];
+        if let Some(q) = metrics.avg_q_value {
+            scalars.push(("train/avg_q_value", q));
+        }
+
+        let mut summary = Vec::new();
+        for (tag, value) in scalars {
+            let mut entry = Vec::new();
+            put_bytes(&mut entry, 1, tag.as_bytes());
+            put_float(&mut entry, 2, value as f32);
+            put_bytes(&mut summary, 1, &entry);
+        }
+        let mut event = Vec::new();
+        put_double(&mut event, 1, SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64());
+        put_varint_field(&mut event, 2, metrics.episode as u64);
+        put_bytes(&mut event, 5, &summary);
+
+        if let Some(file) = &mut self.file {
+            file.write_all(&tf_record(&event))?;
+        }
+        Ok(())
+    }
+}
+
+// Just enough protobuf for `tensorflow.Event` holding scalar summaries
+
+fn put_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn put_varint_field(buf: &mut Vec<u8>, field: u64, value: u64) {
+    put_varint(buf, field << 3);
+    put_varint(buf, value);
+}
+
+fn put_double(buf: &mut Vec<u8>, field: u64, value: f64) {
+    put_varint(buf, (field << 3) | 1);
+    buf.extend(value.to_le_bytes());
+}
+
+fn put_float(buf: &mut Vec<u8>, field: u64, value: f32) {
+    put_varint(buf, (field << 3) | 5);
+    buf.extend(value.to_le_bytes());
+}
+
+fn put_bytes(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    put_varint(buf, (field << 3) | 2);
+    put_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+/// TFRecord framing: length, its checksum, the data and the data's checksum
+fn tf_record(data: &[u8]) -> Vec<u8> {
+    let length = (data.len() as u64).to_le_bytes();
+    let mut record = Vec::with_capacity(data.len() + 16);
+    record.extend(length);
+    record.extend(masked_crc(&length).to_le_bytes());
+    record.extend_from_slice(data);
+    record.extend(masked_crc(data).to_le_bytes());
+    record
+}
+
+fn masked_crc(data: &[u8]) -> u32 {
+    let crc = crc32c(data);
+    ((crc >> 15) | (crc << 17)).wrapping_add(0xa282_ead8)
+}
+
+/// CRC-32C (Castagnoli), bit by bit; records are small
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0x82f6_3b78 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn metrics(episode: usize) -> TrainingMetrics {
+        TrainingMetrics { episode, reward: 2.5, score: 1, steps: 30, epsilon: 0.5, avg_q_value: Some(0.125) }
+    }
+
+    #[test]
+    fn test_exporters_write_each_episode() {
+        let dir = tempdir().unwrap();
+        let config = TrainingConfig {
+            metrics_path: dir.path().to_string_lossy().to_string(),
+            metrics_exporters: vec![MetricsExporterKind::Csv, MetricsExporterKind::Jsonl, MetricsExporterKind::Tensorboard],
+            ..TrainingConfig::default()
+        };
+        for episode in 1..=2 {
+            for exporter in &mut exporters_for(&config) {
+                exporter.record(&metrics(episode)).unwrap();
+            }
+        }
+
+        // Reopening appends without a second header
+        let csv = fs::read_to_string(dir.path().join("episodes.csv")).unwrap();
+        assert_eq!(csv, format!("{}\n1,2.5,1,30,0.5,0.125\n2,2.5,1,30,0.5,0.125\n", METRICS_CSV_HEADER));
+
+        let jsonl = fs::read_to_string(dir.path().join("episodes.jsonl")).unwrap();
+        let episodes: Vec<TrainingMetrics> = jsonl.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(episodes.len(), 2);
+        assert_eq!(episodes[1].episode, 2);
+
+        // Each record's framing checks out against its checksums
+        let event_files: Vec<_> = fs::read_dir(dir.path().join("tensorboard")).unwrap().collect();
+        assert!(!event_files.is_empty());
+        let mut records = 0;
+        for entry in event_files {
+            let bytes = fs::read(entry.unwrap().path()).unwrap();
+            let mut rest = bytes.as_slice();
+            while !rest.is_empty() {
+                let length = u64::from_le_bytes(rest[..8].try_into().unwrap()) as usize;
+                assert_eq!(u32::from_le_bytes(rest[8..12].try_into().unwrap()), masked_crc(&rest[..8]));
+                let data = &rest[12..12 + length];
+                assert_eq!(u32::from_le_bytes(rest[12 + length..16 + length].try_into().unwrap()), masked_crc(data));
+                records += 1;
+                rest = &rest[16 + length..];
+            }
+        }
+        // A version record per file plus one per episode
+        assert!(records >= 3);
+    }
+
+    #[test]
+    fn test_crc32c_check_value() {
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+    }
+}
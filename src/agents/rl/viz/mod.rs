@@ -2,12 +2,24 @@ use anyhow::Result;
 use std::fs::File;
 use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use plotters::prelude::*;
 use crate::agents::rl::model::config::{TrainingHistory, TrainingMetrics};
+use crate::tools::{artifact_key, ArtifactStore};
+
+pub mod export;
+pub mod playback;
+#[cfg(feature = "rl-render")]
+pub mod video;
+
+/// Files written by [`VisualizationTools::generate_report`]
+const REPORT_FILES: [&str; 4] = ["rewards.png", "scores.png", "epsilon.png", "training_report.html"];
 
 #[derive(Clone)]
 pub struct VisualizationTools {
     output_dir: PathBuf,
+    artifacts: Option<(Arc<dyn ArtifactStore>, String)>,
+    videos: Vec<PathBuf>,
 }
 
 impl VisualizationTools {
@@ -15,7 +27,50 @@ impl VisualizationTools {
         let path = PathBuf::from(output_dir.as_ref());
         std::fs::create_dir_all(&path).unwrap_or_default();
         
-        Self { output_dir: path }
+        Self { output_dir: path, artifacts: None, videos: Vec::new() }
+    }
+
+    /// Upload reports to `artifacts` under `rl/<run>/`
+    pub fn with_artifact_store(mut self, artifacts: Arc<dyn ArtifactStore>, run: impl Into<String>) -> Self {
+        self.artifacts = Some((artifacts, run.into()));
+        self
+    }
+
+    /// Show a rendered episode (GIF or MP4) in the report and publish it
+    /// alongside. Videos outside the output directory are copied into it,
+    /// since the report links to them by file name.
+    pub fn with_episode_video<P: AsRef<Path>>(mut self, video: P) -> Self {
+        self.videos.push(video.as_ref().to_path_buf());
+        self
+    }
+
+    /// Rendered episodes attached to the report
+    pub fn episode_videos(&self) -> &[PathBuf] {
+        &self.videos
+    }
+
+    /// Generate the report and upload it with its plots to the artifact store,
+    /// returning the report's URL (or its local path when no store is set)
+    pub async fn publish_report(&self, history: &TrainingHistory) -> Result<String> {
+        let report_path = self.generate_report(history)?;
+        let Some((artifacts, run)) = &self.artifacts else {
+            return Ok(report_path.display().to_string());
+        };
+        // The report links to the plots by file name, so they share a prefix
+        let mut report_url = String::new();
+        for file in REPORT_FILES {
+            let url = artifacts.put_file(&artifact_key(&["rl", run, file]), &self.output_dir.join(file)).await?;
+            if file == "training_report.html" {
+                report_url = url;
+            }
+        }
+        for video in &self.videos {
+            if let Some(name) = video.file_name() {
+                let name = name.to_string_lossy();
+                artifacts.put_file(&artifact_key(&["rl", run, &name]), &self.output_dir.join(name.as_ref())).await?;
+            }
+        }
+        Ok(report_url)
     }
 
     /// Generate reward plot from training history
@@ -134,6 +189,7 @@ impl VisualizationTools {
         let rewards_path = self.plot_rewards(history)?;
         let scores_path = self.plot_scores(history)?;
         let epsilon_path = self.plot_epsilon(history)?;
+        let episodes = self.episode_section()?;
         
         let report_path = self.output_dir.join("training_report.html");
         let mut file = File::create(&report_path)?;
@@ -186,6 +242,7 @@ impl VisualizationTools {
         <h2>Epsilon Decay</h2>
         <img src="{}" alt="Epsilon Plot" style="width: 100%;">
     </div>
+    {}
     
     <h2>Configuration</h2>
     <table>
@@ -222,6 +279,7 @@ impl VisualizationTools {
             rewards_path.file_name().unwrap().to_string_lossy(),
             scores_path.file_name().unwrap().to_string_lossy(),
             epsilon_path.file_name().unwrap().to_string_lossy(),
+            episodes,
             history.config.learning_rate,
             history.config.discount_factor,
             history.config.epsilon,
@@ -231,6 +289,30 @@ impl VisualizationTools {
         
         Ok(report_path)
     }
+
+    /// HTML for the attached episode videos, copying each into the output
+    /// directory first; empty when there are none
+    fn episode_section(&self) -> Result<String> {
+        if self.videos.is_empty() {
+            return Ok(String::new());
+        }
+        let mut html = String::from("<div class=\"plot-container\">\n        <h2>Episodes</h2>\n");
+        for video in &self.videos {
+            let Some(name) = video.file_name() else { continue };
+            let local = self.output_dir.join(name);
+            if !local.exists() {
+                std::fs::copy(video, &local)?;
+            }
+            let name = name.to_string_lossy();
+            if video.extension().is_some_and(|ext| ext == "mp4") {
+                html.push_str(&format!("        <video src=\"{}\" controls loop style=\"max-width: 100%;\"></video>\n", name));
+            } else {
+                html.push_str(&format!("        <img src=\"{}\" alt=\"Episode\">\n", name));
+            }
+        }
+        html.push_str("    </div>\n");
+        Ok(html)
+    }
 }
 
 #[cfg(test)]
@@ -274,4 +356,38 @@ mod tests {
         let report_path = viz.generate_report(&history).unwrap();
         assert!(report_path.exists());
     }
+
+    #[tokio::test]
+    async fn test_publish_report_uploads_plots_with_report() {
+        let temp_dir = TempDir::new().unwrap();
+        let store_dir = TempDir::new().unwrap();
+        let video_dir = TempDir::new().unwrap();
+        let video = video_dir.path().join("episode.gif");
+        std::fs::write(&video, b"GIF89a").unwrap();
+        let viz = VisualizationTools::new(temp_dir.path())
+            .with_artifact_store(Arc::new(crate::tools::LocalArtifactStore::new(store_dir.path())), "run-1")
+            .with_episode_video(&video);
+
+        let mut history = TrainingHistory::new(TrainingConfig::default());
+        for i in 0..10 {
+            history.add_metrics(TrainingMetrics {
+                episode: i,
+                reward: i as f64,
+                score: i as i32,
+                steps: i,
+                epsilon: 0.5,
+                avg_q_value: None,
+            });
+        }
+
+        let url = viz.publish_report(&history).await.unwrap();
+        assert!(url.ends_with("rl/run-1/training_report.html"));
+        for file in REPORT_FILES {
+            assert!(store_dir.path().join("rl/run-1").join(file).exists());
+        }
+        // Attached episodes are embedded and shipped with the report
+        let report = std::fs::read_to_string(temp_dir.path().join("training_report.html")).unwrap();
+        assert!(report.contains(r#"<img src="episode.gif""#));
+        assert!(store_dir.path().join("rl/run-1/episode.gif").exists());
+    }
 } 
@@ -0,0 +1,178 @@
+use crate::Result;
+use crate::error::SwarmError;
+use std::path::Path;
+use serde::Deserialize;
+use crate::agents::rl::eval::Trajectory;
+
+/// Frames to jump when seeking in large steps
+pub const SEEK_STEP: usize = 60;
+
+/// Plays back recorded trajectories one frame at a time, independent of how
+/// frames are drawn. A trajectory's frames are the state before each step
+/// followed by the state it ended in.
+#[derive(Debug, Clone)]
+pub struct TrajectoryPlayer<S, A> {
+    trajectories: Vec<Trajectory<S, A>>,
+    current: usize,
+    frame: usize,
+    paused: bool,
+}
+
+impl<S, A> TrajectoryPlayer<S, A> {
+    pub fn new(trajectories: Vec<Trajectory<S, A>>) -> Result<Self> {
+        if trajectories.is_empty() {
+            return Err(SwarmError::Validation("No trajectories to play".to_string()));
+        }
+        Ok(Self { trajectories, current: 0, frame: 0, paused: false })
+    }
+
+    /// Load trajectories saved by [`Trajectory::save_all`]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self>
+    where
+        S: for<'de> Deserialize<'de>,
+        A: for<'de> Deserialize<'de>,
+    {
+        Self::new(Trajectory::load_all(path)?)
+    }
+
+    pub fn trajectory(&self) -> &Trajectory<S, A> {
+        &self.trajectories[self.current]
+    }
+
+    /// Position of the current trajectory among those loaded
+    pub fn index(&self) -> usize {
+        self.current
+    }
+
+    pub fn trajectory_count(&self) -> usize {
+        self.trajectories.len()
+    }
+
+    /// Switch to the trajectory recorded for `episode`, from its first frame
+    pub fn select_episode(&mut self, episode: usize) -> Result<()> {
+        self.current = self.trajectories.iter()
+            .position(|t| t.episode == episode)
+            .ok_or_else(|| SwarmError::NotFound(format!("No trajectory recorded for episode {}", episode)))?;
+        self.frame = 0;
+        Ok(())
+    }
+
+    /// Move to the next (`delta` > 0) or an earlier trajectory, wrapping around
+    pub fn switch_trajectory(&mut self, delta: isize) {
+        let count = self.trajectories.len() as isize;
+        self.current = (self.current as isize + delta).rem_euclid(count) as usize;
+        self.frame = 0;
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.trajectory().steps.len() + 1
+    }
+
+    /// State shown at the current frame
+    pub fn state(&self) -> &S {
+        let trajectory = self.trajectory();
+        trajectory.steps.get(self.frame).map_or(&trajectory.final_state, |step| &step.state)
+    }
+
+    /// Action taken from the current frame; `None` on the final frame
+    pub fn action(&self) -> Option<&A> {
+        self.trajectory().steps.get(self.frame).map(|step| &step.action)
+    }
+
+    pub fn at_end(&self) -> bool {
+        self.frame + 1 >= self.frame_count()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Pause and move `delta` frames, for stepping through by hand
+    pub fn step(&mut self, delta: isize) {
+        self.paused = true;
+        self.seek_by(delta);
+    }
+
+    /// Jump to `frame`, clamped to the trajectory
+    pub fn seek(&mut self, frame: usize) {
+        self.frame = frame.min(self.frame_count() - 1);
+    }
+
+    /// Move `delta` frames forward or back, stopping at either end
+    pub fn seek_by(&mut self, delta: isize) {
+        self.seek(self.frame.saturating_add_signed(delta));
+    }
+
+    /// Advance one frame while playing, pausing on the last one. Returns
+    /// whether the frame changed.
+    pub fn tick(&mut self) -> bool {
+        if self.paused {
+            return false;
+        }
+        if self.at_end() {
+            self.paused = true;
+            return false;
+        }
+        self.frame += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agents::rl::eval::TrajectoryStep;
+
+    fn trajectory(episode: usize, states: &[i32]) -> Trajectory<i32, bool> {
+        let (last, rest) = states.split_last().unwrap();
+        Trajectory {
+            episode,
+            score: 0,
+            reward: 0.0,
+            steps: rest.iter().map(|&state| TrajectoryStep { state, action: true, reward: 0.0 }).collect(),
+            final_state: *last,
+        }
+    }
+
+    #[test]
+    fn test_play_pause_step_and_seek() {
+        let mut player = TrajectoryPlayer::new(vec![trajectory(1, &[10, 11, 12]), trajectory(2, &[20, 21])]).unwrap();
+        assert_eq!((player.frame_count(), *player.state(), player.action()), (3, 10, Some(&true)));
+
+        assert!(player.tick());
+        assert!(player.tick());
+        assert_eq!((*player.state(), player.action()), (12, None));
+        // Playback stops on the last frame
+        assert!(!player.tick());
+        assert!(player.is_paused());
+
+        player.seek_by(-5);
+        assert_eq!(*player.state(), 10);
+        player.seek(99);
+        assert_eq!(player.frame(), 2);
+        player.seek_by(-1);
+        assert_eq!(*player.state(), 11);
+        assert!(!player.tick());
+        player.toggle_pause();
+        player.step(-1);
+        assert!(player.is_paused());
+        assert_eq!(player.frame(), 0);
+
+        player.switch_trajectory(1);
+        assert_eq!((player.trajectory().episode, player.frame(), *player.state()), (2, 0, 20));
+        player.switch_trajectory(1);
+        assert_eq!(player.index(), 0);
+        player.select_episode(2).unwrap();
+        assert_eq!(player.index(), 1);
+        assert!(player.select_episode(9).is_err());
+        assert!(TrajectoryPlayer::<i32, bool>::new(Vec::new()).is_err());
+    }
+}
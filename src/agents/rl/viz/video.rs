@@ -0,0 +1,161 @@
+use crate::Result;
+use crate::error::SwarmError;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+use serde::{Deserialize, Serialize};
+use crate::agents::rl::eval::Trajectory;
+
+/// Container for rendered episodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VideoFormat {
+    /// Animated GIF, encoded in-process
+    #[default]
+    Gif,
+    /// H.264 MP4, encoded by piping frames to `ffmpeg` on the `PATH`
+    Mp4,
+}
+
+impl VideoFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            VideoFormat::Gif => "gif",
+            VideoFormat::Mp4 => "mp4",
+        }
+    }
+}
+
+impl std::str::FromStr for VideoFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gif" => Ok(Self::Gif),
+            "mp4" => Ok(Self::Mp4),
+            other => Err(format!("unknown video format '{}' (expected gif or mp4)", other)),
+        }
+    }
+}
+
+/// How an episode is turned into a video
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoOptions {
+    pub format: VideoFormat,
+    /// Playback rate of the encoded frames
+    pub fps: u32,
+    /// Encode every `stride`th state; GIFs of long episodes get large
+    pub stride: usize,
+}
+
+impl Default for VideoOptions {
+    fn default() -> Self {
+        Self { format: VideoFormat::Gif, fps: 30, stride: 2 }
+    }
+}
+
+/// Draw every state of `trajectory` off screen with `draw`, which fills an
+/// RGBA buffer of `width` x `height`, and encode the frames to `path`. The
+/// final state is always included so the video ends where the episode did.
+pub fn render_trajectory<S, A, F>(
+    trajectory: &Trajectory<S, A>,
+    width: u32,
+    height: u32,
+    draw: F,
+    path: &Path,
+    options: &VideoOptions,
+) -> Result<PathBuf>
+where
+    F: Fn(&S, &mut [u8]),
+{
+    let states = trajectory.steps.iter()
+        .map(|step| &step.state)
+        .step_by(options.stride.max(1))
+        .chain(std::iter::once(&trajectory.final_state));
+    let frames = states.map(|state| {
+        let mut buffer = vec![0; (width * height * 4) as usize];
+        draw(state, &mut buffer);
+        buffer
+    });
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match options.format {
+        VideoFormat::Gif => encode_gif(frames, width, height, options.fps, path)?,
+        VideoFormat::Mp4 => encode_mp4(frames, width, height, options.fps, path)?,
+    }
+    Ok(path.to_path_buf())
+}
+
+fn encode_gif(frames: impl Iterator<Item = Vec<u8>>, width: u32, height: u32, fps: u32, path: &Path) -> Result<()> {
+    let gif_error = |e: image::ImageError| SwarmError::Tool(format!("GIF encoding failed: {}", e));
+    // Speed 10 of 30 trades a little palette quality for much faster encoding
+    let mut encoder = GifEncoder::new_with_speed(File::create(path)?, 10);
+    encoder.set_repeat(Repeat::Infinite).map_err(gif_error)?;
+    let delay = Delay::from_numer_denom_ms(1000, fps.max(1));
+    for buffer in frames {
+        let image = RgbaImage::from_raw(width, height, buffer).ok_or_else(|| SwarmError::Validation("Frame buffer has the wrong size".to_string()))?;
+        encoder.encode_frame(Frame::from_parts(image, 0, 0, delay)).map_err(gif_error)?;
+    }
+    Ok(())
+}
+
+fn encode_mp4(frames: impl Iterator<Item = Vec<u8>>, width: u32, height: u32, fps: u32, path: &Path) -> Result<()> {
+    let mut ffmpeg = Command::new("ffmpeg")
+        .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgba"])
+        .args(["-s", &format!("{}x{}", width, height), "-r", &fps.max(1).to_string(), "-i", "-"])
+        .args(["-c:v", "libx264", "-pix_fmt", "yuv420p"])
+        .arg(path)
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| SwarmError::Unsupported(format!("MP4 rendering needs ffmpeg on the PATH: {}", e)))?;
+    {
+        let stdin = ffmpeg.stdin.as_mut().ok_or_else(|| SwarmError::Tool("ffmpeg stdin unavailable".to_string()))?;
+        for buffer in frames {
+            stdin.write_all(&buffer)?;
+        }
+    }
+    drop(ffmpeg.stdin.take());
+    let status = ffmpeg.wait()?;
+    if !status.success() {
+        return Err(SwarmError::Tool(format!("ffmpeg exited with {}", status)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifDecoder;
+    use image::AnimationDecoder;
+    use tempfile::tempdir;
+    use crate::agents::rl::eval::TrajectoryStep;
+
+    #[test]
+    fn test_gif_has_a_frame_per_sampled_state() {
+        let trajectory = Trajectory {
+            episode: 1,
+            score: 0,
+            reward: 0.0,
+            steps: (0..5u8).map(|state| TrajectoryStep { state, action: (), reward: 0.0 }).collect(),
+            final_state: 5u8,
+        };
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("episode.gif");
+        let draw = |state: &u8, frame: &mut [u8]| {
+            for pixel in frame.chunks_exact_mut(4) {
+                pixel.copy_from_slice(&[state * 40, 0, 0, 255]);
+            }
+        };
+        render_trajectory(&trajectory, 4, 4, draw, &path, &VideoOptions::default()).unwrap();
+
+        // States 0, 2, 4 and the final state
+        let frames = GifDecoder::new(File::open(&path).unwrap()).unwrap().into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), 4);
+        assert_eq!("mp4".parse::<VideoFormat>(), Ok(VideoFormat::Mp4));
+    }
+}
@@ -0,0 +1,265 @@
+//! Machine-readable replies for callers that asked for
+//! [`ResponseFormat::Json`](crate::types::ResponseFormat::Json).
+//!
+//! Every structured reply is an object with `action`, `result`, `data` and
+//! `metadata`. Agents may also have a schema of their own, which the reply
+//! must satisfy as well.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use jsonschema::JSONSchema;
+use serde_json::{json, Value};
+use crate::ai::{AiProvider, DefaultAiClient};
+use crate::error::SwarmError;
+use crate::types::Message;
+
+/// Appended to an AI agent's system prompt when the caller asked for JSON
+pub const JSON_REPLY_INSTRUCTIONS: &str = "Reply with one JSON object and nothing else: no prose and no code fences. \
+    Its keys are \"action\" (what you did, as a short verb), \"result\" (a one-sentence summary), \
+    \"data\" (an object with the details) and \"metadata\" (an object, may be empty).";
+
+static SHARED: OnceLock<Arc<StructuredOutput>> = OnceLock::new();
+
+/// The shape every structured reply has
+pub fn base_schema() -> Value {
+    json!({
+        "type": "object",
+        "required": ["action", "result", "data", "metadata"],
+        "properties": {
+            "action": { "type": "string" },
+            "result": { "type": "string" },
+            "data": { "type": "object" },
+            "metadata": { "type": "object" }
+        }
+    })
+}
+
+/// A reply that couldn't be turned into a valid structured payload
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("Could not produce a structured reply from {agent}: {reason}")]
+pub struct StructuredOutputError {
+    pub agent: String,
+    pub reason: String,
+}
+
+/// Turns agent replies into structured payloads and checks them against the
+/// base schema and the agent's own
+pub struct StructuredOutput {
+    base: JSONSchema,
+    /// Each agent's own schema, as written and compiled
+    schemas: HashMap<String, (Value, JSONSchema)>,
+    /// Rewrites prose replies that don't fit the agent's schema
+    coercer: Option<Box<dyn AiProvider + Send + Sync>>,
+}
+
+impl Default for StructuredOutput {
+    fn default() -> Self {
+        Self {
+            base: JSONSchema::compile(&base_schema()).expect("the base schema is valid"),
+            schemas: HashMap::new(),
+            coercer: None,
+        }
+    }
+}
+
+impl StructuredOutput {
+    /// Also require `agent`'s replies to satisfy `schema`
+    pub fn with_schema(mut self, agent: impl Into<String>, schema: &Value) -> crate::Result<Self> {
+        let agent = agent.into();
+        let compiled = compile(&agent, schema)?;
+        self.schemas.insert(agent, (schema.clone(), compiled));
+        Ok(self)
+    }
+
+    /// Ask `coercer` to restructure replies that don't fit on their own
+    pub fn with_ai_coercion<T: AiProvider + Send + Sync + 'static>(mut self, coercer: T) -> Self {
+        self.coercer = Some(Box::new(coercer));
+        self
+    }
+
+    /// Loads `<agent>.json` schemas from `AGENT_SCHEMA_DIR`, skipping (and
+    /// logging) ones that don't parse. `STRUCTURED_OUTPUT_AI=true` turns on
+    /// AI coercion.
+    pub fn from_env() -> Self {
+        let mut output = Self::default();
+        if let Ok(dir) = env::var("AGENT_SCHEMA_DIR") {
+            output = output.with_schema_dir(Path::new(&dir));
+        }
+        if env::var("STRUCTURED_OUTPUT_AI").map(|v| v == "true" || v == "1").unwrap_or(false) {
+            output = output.with_ai_coercion(DefaultAiClient::new());
+        }
+        output
+    }
+
+    fn with_schema_dir(mut self, dir: &Path) -> Self {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                tracing::warn!("Cannot read reply schemas from {}: {}", dir.display(), e);
+                return self;
+            }
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.extension().map_or(true, |ext| ext != "json") {
+                continue;
+            }
+            let Some(agent) = path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string) else {
+                continue;
+            };
+            let loaded = fs::read_to_string(&path)
+                .map_err(SwarmError::from)
+                .and_then(|text| Ok(serde_json::from_str::<Value>(&text)?))
+                .and_then(|schema| Ok((compile(&agent, &schema)?, schema)));
+            match loaded {
+                Ok((compiled, schema)) => {
+                    self.schemas.insert(agent, (schema, compiled));
+                }
+                Err(e) => tracing::warn!("Skipping reply schema {}: {}", path.display(), e),
+            }
+        }
+        self
+    }
+
+    /// Process-wide instance read from the environment on first use
+    pub fn shared() -> Arc<Self> {
+        SHARED.get_or_init(|| Arc::new(Self::from_env())).clone()
+    }
+
+    /// `response` as a structured payload. A reply that already is a JSON
+    /// object is used as it is; prose is wrapped as `{"action": "reply",
+    /// "result": <text>}` or, when that doesn't fit the agent's schema,
+    /// rewritten by the AI coercer if there is one.
+    pub async fn render(&self, agent: &str, response: &Message) -> Result<Value, StructuredOutputError> {
+        let error = |reason: String| StructuredOutputError { agent: agent.to_string(), reason };
+
+        if let Ok(value @ Value::Object(_)) = serde_json::from_str::<Value>(strip_code_fence(&response.content)) {
+            return self.validate(agent, &value).map(|_| value).map_err(error);
+        }
+
+        let wrapped = self.wrap(agent, response);
+        let problem = match self.validate(agent, &wrapped) {
+            Ok(()) => return Ok(wrapped),
+            Err(problem) => problem,
+        };
+        let Some(coercer) = &self.coercer else {
+            return Err(error(problem));
+        };
+
+        let coerced = self.coerce(coercer.as_ref(), agent, &response.content).await.map_err(error)?;
+        self.validate(agent, &coerced).map(|_| coerced).map_err(error)
+    }
+
+    fn wrap(&self, agent: &str, response: &Message) -> Value {
+        let mut metadata = json!({ "agent": agent });
+        if let Some(meta) = &response.metadata {
+            if let Some(state) = &meta.state {
+                metadata["state"] = json!(state);
+            }
+            if let Some(target) = &meta.transfer_target {
+                metadata["transfer_target"] = json!(target);
+            }
+        }
+        json!({
+            "action": "reply",
+            "result": response.content,
+            "data": {},
+            "metadata": metadata,
+        })
+    }
+
+    fn validate(&self, agent: &str, value: &Value) -> Result<(), String> {
+        for schema in std::iter::once(&self.base).chain(self.schemas.get(agent).map(|(_, compiled)| compiled)) {
+            if let Err(errors) = schema.validate(value) {
+                let errors: Vec<String> = errors.map(|e| format!("{} at '{}'", e, e.instance_path)).collect();
+                return Err(errors.join("; "));
+            }
+        }
+        Ok(())
+    }
+
+    async fn coerce(&self, coercer: &(dyn AiProvider + Send + Sync), agent: &str, content: &str) -> Result<Value, String> {
+        let mut system_prompt = format!("Rewrite the reply below as structured data. {}", JSON_REPLY_INSTRUCTIONS);
+        if let Some((schema, _)) = self.schemas.get(agent) {
+            system_prompt.push_str(&format!(" It must also satisfy this JSON schema: {}", schema));
+        }
+        let messages = vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), content.to_string()),
+        ])];
+        let reply = coercer.chat(&system_prompt, messages).await
+            .map_err(|e| format!("coercion failed: {}", e))?;
+        serde_json::from_str(strip_code_fence(&reply))
+            .map_err(|e| format!("coerced reply is not JSON: {}", e))
+    }
+}
+
+fn compile(agent: &str, schema: &Value) -> crate::Result<JSONSchema> {
+    JSONSchema::compile(schema).map_err(|e| SwarmError::Validation(format!("Invalid reply schema for {}: {}", agent, e)))
+}
+
+/// `text` without a surrounding ```json fence, if it has one
+pub(crate) fn strip_code_fence(text: &str) -> &str {
+    let text = text.trim();
+    text.strip_prefix("```")
+        .and_then(|rest| rest.strip_suffix("```"))
+        .map(|inner| inner.trim_start_matches("json").trim())
+        .unwrap_or(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Rewrite(&'static str);
+
+    #[async_trait::async_trait]
+    impl AiProvider for Rewrite {
+        async fn chat(&self, _system_prompt: &str, _messages: Vec<HashMap<String, String>>) -> anyhow::Result<String> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    fn commit_schema() -> Value {
+        json!({
+            "type": "object",
+            "properties": { "data": { "type": "object", "required": ["branch"] } }
+        })
+    }
+
+    #[tokio::test]
+    async fn test_wraps_prose_and_passes_json_through() {
+        let output = StructuredOutput::default();
+        let payload = output.render("greeter", &Message::new("Hello!".to_string())).await.unwrap();
+        assert_eq!(payload["action"], "reply");
+        assert_eq!(payload["result"], "Hello!");
+        assert_eq!(payload["metadata"]["agent"], "greeter");
+
+        let json = "```json\n{\"action\": \"greet\", \"result\": \"hi\", \"data\": {}, \"metadata\": {}}\n```";
+        let payload = output.render("greeter", &Message::new(json.to_string())).await.unwrap();
+        assert_eq!(payload["action"], "greet");
+
+        // JSON that misses the required keys is an error, not prose
+        assert!(output.render("greeter", &Message::new("{\"action\": 1}".to_string())).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_agent_schema_and_coercion() {
+        let output = StructuredOutput::default().with_schema("git", &commit_schema()).unwrap();
+        let prose = Message::new("Committed to main".to_string());
+        let error = output.render("git", &prose).await.unwrap_err();
+        assert!(error.reason.contains("branch"), "{}", error.reason);
+
+        let output = output.with_ai_coercion(Rewrite(
+            r#"{"action": "commit", "result": "Committed", "data": {"branch": "main"}, "metadata": {}}"#,
+        ));
+        let payload = output.render("git", &prose).await.unwrap();
+        assert_eq!(payload["data"]["branch"], "main");
+
+        let output = StructuredOutput::default().with_schema("git", &commit_schema()).unwrap()
+            .with_ai_coercion(Rewrite("Sorry, I can't do that"));
+        assert!(output.render("git", &prose).await.unwrap_err().reason.contains("not JSON"));
+    }
+}
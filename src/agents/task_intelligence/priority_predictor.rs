@@ -124,7 +124,7 @@ impl PriorityPredictor {
 
         if executions.is_empty() {
             // No history - use heuristics
-            return Ok(self.heuristic_priority(&features));
+            return Ok(Self::heuristic_priority(&features));
         }
 
         // k-NN: Find k most similar tasks
@@ -156,13 +156,13 @@ impl PriorityPredictor {
             .into_iter()
             .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
             .map(|(priority, _)| priority)
-            .unwrap_or_else(|| self.heuristic_priority(&features));
+            .unwrap_or_else(|| Self::heuristic_priority(&features));
 
         Ok(predicted)
     }
 
     /// Heuristic-based priority when no history available
-    fn heuristic_priority(&self, features: &TaskFeatures) -> TaskPriority {
+    fn heuristic_priority(features: &TaskFeatures) -> TaskPriority {
         // Security or urgency = High
         if features.has_security_keywords || features.has_urgency_keywords {
             return TaskPriority::High;
@@ -304,23 +304,16 @@ mod tests {
 
     #[test]
     fn test_heuristic_priority() {
-        let history = TaskHistory {
-            executions: mongodb::Collection::clone_with_type(&mongodb::Collection::<TaskExecution>::clone(&unsafe {
-                std::mem::zeroed()
-            })),
-        };
-        let predictor = PriorityPredictor::new(history);
-
         // Security task should be High
         let security_features = TaskFeatures::extract("Fix critical security vulnerability");
-        assert_eq!(predictor.heuristic_priority(&security_features), TaskPriority::High);
+        assert_eq!(PriorityPredictor::heuristic_priority(&security_features), TaskPriority::High);
 
         // Bug should be Medium
         let bug_features = TaskFeatures::extract("Fix login bug");
-        assert_eq!(predictor.heuristic_priority(&bug_features), TaskPriority::Medium);
+        assert_eq!(PriorityPredictor::heuristic_priority(&bug_features), TaskPriority::Medium);
 
         // Simple feature should be Low
         let feature_features = TaskFeatures::extract("Add new button to UI");
-        assert_eq!(predictor.heuristic_priority(&feature_features), TaskPriority::Low);
+        assert_eq!(PriorityPredictor::heuristic_priority(&feature_features), TaskPriority::Low);
     }
 }
@@ -7,7 +7,7 @@
 /// - Time estimation
 
 use super::{TaskIntelligenceService, TaskIntelligenceConfig, TaskOutcome};
-use crate::types::{TodoTask, TodoList, TaskPriority, TaskStatus};
+use crate::types::{TodoTask, TodoList, TaskPriority};
 use anyhow::Result;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -122,19 +122,16 @@ impl SmartTodoList {
                     let todo = TodoTask {
                         id: uuid::Uuid::new_v4().to_string(),
                         description: subtask.description,
-                        enhanced_description: None,
                         priority: subtask.estimated_priority,
                         project: parent_task.project.clone(),
                         source_agent: parent_task.source_agent.clone(),
                         target_agent: parent_task.target_agent.clone(),
-                        status: TaskStatus::Pending,
                         created_at: chrono::Utc::now().timestamp(),
-                        completed_at: None,
-                        due_date: None,
                         duration_minutes: subtask.estimated_duration_minutes,
                         notes: Some(format!("Subtask {} of: {}", subtask.order + 1, parent_task.description)),
                         ticket: parent_task.ticket.clone(),
                         last_modified: Some(chrono::Utc::now().timestamp()),
+                        ..Default::default()
                     };
 
                     self.add_smart_task(todo.clone()).await?;
@@ -216,19 +213,11 @@ mod tests {
                 let task = TodoTask {
                     id: uuid::Uuid::new_v4().to_string(),
                     description: "Test task".to_string(),
-                    enhanced_description: None,
                     priority: TaskPriority::Medium,
                     project: Some("test".to_string()),
-                    source_agent: None,
                     target_agent: "test_agent".to_string(),
-                    status: TaskStatus::Pending,
                     created_at: chrono::Utc::now().timestamp(),
-                    completed_at: None,
-                    due_date: None,
-                    duration_minutes: None,
-                    notes: None,
-                    ticket: None,
-                    last_modified: None,
+                    ..Default::default()
                 };
 
                 match smart_list.add_smart_task(task).await {
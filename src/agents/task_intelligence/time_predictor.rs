@@ -282,19 +282,9 @@ mod tests {
         let task = TodoTask {
             id: "test".to_string(),
             description: "Simple task".to_string(),
-            enhanced_description: None,
             priority: TaskPriority::Medium,
-            project: None,
-            source_agent: None,
             target_agent: "test".to_string(),
-            status: crate::types::TaskStatus::Pending,
-            created_at: 0,
-            completed_at: None,
-            due_date: None,
-            duration_minutes: None,
-            notes: None,
-            ticket: None,
-            last_modified: None,
+            ..Default::default()
         };
 
         let features = TaskFeatures::extract(&task.description);
@@ -1,45 +1,160 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use crate::{
+    access::AccessPolicy,
     types::{Message, Agent},
     error::Error,
-    agents::AgentRegistry,
+    agents::{AgentRegistry, RemoteAgents},
+    events::{Event, EventBus},
 };
 use anyhow::{Result, anyhow};
+#[cfg(feature = "rl-routing")]
+use crate::agents::rl::routing::{LearnedRouter, OutcomeLog, TransferOutcome};
 
 pub struct TransferService {
     registry: Arc<RwLock<AgentRegistry>>,
+    events: EventBus,
+    remote: Option<Arc<RemoteAgents>>,
+    access: Arc<AccessPolicy>,
+    #[cfg(feature = "rl-routing")]
+    router: Option<Arc<LearnedRouter>>,
+    #[cfg(feature = "rl-routing")]
+    outcome_log: Option<OutcomeLog>,
 }
 
 impl TransferService {
+    /// Completed transfers are announced on [`EventBus::shared`], and
+    /// [`AccessPolicy::shared`] decides who may reach each agent
     pub fn new(registry: Arc<RwLock<AgentRegistry>>) -> Self {
-        Self { registry }
+        Self {
+            registry,
+            events: EventBus::shared(),
+            remote: None,
+            access: AccessPolicy::shared(),
+            #[cfg(feature = "rl-routing")]
+            router: None,
+            #[cfg(feature = "rl-routing")]
+            outcome_log: None,
+        }
+    }
+
+    /// Announce completed transfers on `bus` instead
+    pub fn set_event_bus(&mut self, bus: EventBus) {
+        self.events = bus;
+    }
+
+    /// Send transfers to agents that aren't registered here through `remote`
+    pub fn set_remote_agents(&mut self, remote: Arc<RemoteAgents>) {
+        self.remote = Some(remote);
+    }
+
+    /// Check who may reach each agent against `policy` instead
+    pub fn set_access_policy(&mut self, policy: Arc<AccessPolicy>) {
+        self.access = policy;
+    }
+
+    /// Let `router` pick the agent for each processed message when it is
+    /// confident one will handle it; otherwise the current agent does
+    #[cfg(feature = "rl-routing")]
+    pub fn set_learned_router(&mut self, router: Arc<LearnedRouter>) {
+        self.router = Some(router);
     }
 
+    /// Record how every transfer turns out to `log`, for training a router
+    #[cfg(feature = "rl-routing")]
+    pub fn set_outcome_log(&mut self, log: OutcomeLog) {
+        self.outcome_log = Some(log);
+    }
+
+    #[cfg(feature = "rl-routing")]
+    fn record_outcome(&self, from: &str, to: &str, message: &str, success: bool) {
+        let Some(log) = &self.outcome_log else {
+            return;
+        };
+        let outcome = TransferOutcome {
+            message: message.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+            success,
+            timestamp: chrono::Utc::now(),
+        };
+        if let Err(e) = log.record(&outcome) {
+            tracing::warn!("Failed to log transfer outcome: {}", e);
+        }
+    }
+
+    /// Another registered agent the learned router prefers for `message`
+    #[cfg(feature = "rl-routing")]
+    async fn learned_route(&self, current_agent: &str, message: &Message) -> Option<String> {
+        let router = self.router.as_ref()?;
+        let target = router.route(&message.content, &self.agent_names().await)?;
+        (target != current_agent).then_some(target)
+    }
+
+    /// Node `name` lives on, when it is a remote agent rather than a local one
+    fn remote_node(&self, name: &str) -> Option<&str> {
+        self.remote.as_ref().and_then(|remote| remote.node_for(name))
+    }
+
+    #[tracing::instrument(name = "transfer.process_message", skip(self, message))]
     pub async fn process_message(&self, message: Message) -> Result<Message> {
-        let registry = self.registry.read().await;
         let current_agent = self.get_current_agent_name().await?;
+
+        #[cfg(feature = "rl-routing")]
+        if let Some(target) = self.learned_route(&current_agent, &message).await {
+            self.access.check_agent(&target)?;
+            tracing::debug!("Learned router sent the message from {} to {}", current_agent, target);
+            let content = message.content.clone();
+            let agent = self.get_agent(&target).await?;
+            self.set_current_agent_name(&target).await?;
+            self.events.publish(Event::AgentTransferred { from: current_agent.clone(), to: target.clone() });
+            let result = agent.process_message(message).await;
+            self.record_outcome(&current_agent, &target, &content, result.is_ok());
+            return Ok(result?);
+        }
+
+        self.access.check_agent(&current_agent)?;
         let agent = self.get_agent(&current_agent).await?;
-        agent.process_message(message).await
+        Ok(agent.process_message(message).await?)
     }
 
+    #[tracing::instrument(name = "transfer.transfer", skip(self, message))]
     pub async fn transfer(&self, from: &str, to: &str, message: Message) -> Result<Message> {
+        #[cfg(feature = "rl-routing")]
+        let content = message.content.clone();
+        let result = self.deliver(from, to, message).await;
+        #[cfg(feature = "rl-routing")]
+        self.record_outcome(from, to, &content, result.is_ok());
+        result
+    }
+
+    async fn deliver(&self, from: &str, to: &str, message: Message) -> Result<Message> {
         // First validate that both agents exist
-        {
+        let remote_target = {
             let registry = self.registry.read().await;
             if registry.get(from).is_none() {
                 return Err(anyhow!("Source agent '{}' not found", from));
             }
-            if registry.get(to).is_none() {
-                return Err(anyhow!("Target agent '{}' not found", to));
+            match (registry.get(to), self.remote_node(to)) {
+                (Some(_), _) => false,
+                (None, Some(_)) => true,
+                (None, None) => return Err(anyhow!("Target agent '{}' not found", to)),
             }
-        } // registry read lock is dropped here
+        }; // registry read lock is dropped here
+        self.access.check_agent(to)?;
+
+        // Remote agents answer over MQTT; the conversation stays with a local agent
+        if remote_target {
+            let remote = self.remote.as_ref().expect("remote target implies remote agents");
+            let result = remote.send(Some(from), to, message).await?;
+            self.events.publish(Event::AgentTransferred { from: from.to_string(), to: to.to_string() });
+            return Ok(result);
+        }
 
         // Get the source agent and perform the transfer
-        let source_agent = {
-            let registry = self.registry.read().await;
-            registry.get(from).unwrap().clone()
-        };
+        let source_agent = self.registry.read().await.get(from)
+            .ok_or_else(|| anyhow!("Source agent '{}' not found", from))?;
+        let source_agent = source_agent.read().await.clone();
 
         // Perform the transfer
         let result = source_agent.transfer_to(to.to_string(), message).await?;
@@ -47,14 +162,23 @@ impl TransferService {
         // Update the current agent
         self.set_current_agent_name(to).await?;
 
+        self.events.publish(Event::AgentTransferred { from: from.to_string(), to: to.to_string() });
+
         Ok(result)
     }
 
     pub async fn get_agent(&self, name: &str) -> Result<Arc<Box<dyn Agent + Send + Sync>>> {
-        let registry = self.registry.read().await;
-        registry.get(name)
-            .map(|wrapper| Arc::new(Box::new(wrapper.clone()) as Box<dyn Agent + Send + Sync>))
-            .ok_or_else(|| anyhow!("Agent '{}' not found", name))
+        let handle = self.registry.read().await.get(name)
+            .ok_or_else(|| anyhow!("Agent '{}' not found", name))?;
+        let wrapper = handle.read().await.clone();
+        Ok(Arc::new(Box::new(wrapper) as Box<dyn Agent + Send + Sync>))
+    }
+
+    /// Every registered agent, sorted
+    pub async fn agent_names(&self) -> Vec<String> {
+        let mut names = self.registry.read().await.names();
+        names.sort();
+        names
     }
 
     pub async fn get_current_agent_name(&self) -> Result<String> {
@@ -106,4 +230,36 @@ mod tests {
         let result = service.transfer("test_greeter", "nonexistent", Message::new("transfer to nonexistent".to_string())).await;
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_transfer_checks_access() {
+        use crate::access::{with_principal, AccessDenied, Principal};
+
+        let config = |name: &str, downstream: &[&str]| AgentConfig {
+            name: name.to_string(),
+            public_description: String::new(),
+            instructions: String::new(),
+            tools: vec![],
+            downstream_agents: downstream.iter().map(|agent| agent.to_string()).collect(),
+            personality: None,
+            state_machine: None,
+        };
+        let mut registry = AgentRegistry::new();
+        registry.register("greeter".to_string(), Box::new(GreeterAgent::new(config("greeter", &["deployer"])))).await.unwrap();
+        registry.register("deployer".to_string(), Box::new(GreeterAgent::new(config("deployer", &[])))).await.unwrap();
+        let mut service = TransferService::new(Arc::new(RwLock::new(registry)));
+        service.set_access_policy(Arc::new(
+            AccessPolicy::default().with_role("ops", &["fleet:deploy"]).with_agent("deployer", "fleet:deploy"),
+        ));
+        service.set_current_agent_name("greeter").await.unwrap();
+
+        let reader = Principal::new("ci", &["reader"]);
+        let denied = with_principal(reader, service.transfer("greeter", "deployer", Message::new("ship it".to_string()))).await;
+        assert!(AccessDenied::find(&denied.unwrap_err()).is_some());
+        assert_eq!(service.get_current_agent_name().await.unwrap(), "greeter");
+
+        let ops = Principal::new("alice", &["ops"]);
+        let allowed = with_principal(ops, service.transfer("greeter", "deployer", Message::new("ship it".to_string()))).await;
+        assert!(allowed.is_ok());
+    }
 }
@@ -9,6 +9,22 @@ use std::collections::HashMap;
 use async_trait::async_trait;
 use uuid::Uuid;
 
+/// Name tasks for a person are routed to
+pub const USER_AGENT: &str = "user";
+
+/// Configuration the `user` agent is registered with
+pub fn default_config() -> AgentConfig {
+    AgentConfig {
+        name: USER_AGENT.to_string(),
+        public_description: "The person running the swarm; their tasks wait in the user inbox.".to_string(),
+        instructions: "Approve, reject or edit the work routed to you.".to_string(),
+        tools: Vec::new(),
+        downstream_agents: Vec::new(),
+        personality: None,
+        state_machine: None,
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TodoItem {
     pub description: String,
@@ -144,23 +160,26 @@ impl UserAgentState {
 
 #[async_trait]
 impl Agent for UserAgent {
-    async fn process_message(&self, message: Message) -> Result<Message> {
-        Ok(Message::new(format!("User received: {}", message.content)))
+    /// Work for the user waits for a decision: as a task, it is parked as
+    /// waiting for input and shows up in the user inbox until approved,
+    /// rejected or edited there
+    async fn process_message(&self, message: Message) -> crate::Result<Message> {
+        Ok(Message::needs_input(&[format!("Approve, reject or edit in the user inbox: {}", message.content)]))
     }
 
-    async fn transfer_to(&self, target_agent: String, message: Message) -> Result<Message> {
+    async fn transfer_to(&self, target_agent: String, message: Message) -> crate::Result<Message> {
         Ok(message)
     }
 
-    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
+    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> crate::Result<String> {
         Ok(format!("Called tool {} with params {:?}", tool.name, params))
     }
 
-    async fn get_current_state(&self) -> Result<Option<State>> {
+    async fn get_current_state(&self) -> crate::Result<Option<State>> {
         Ok(None)
     }
 
-    async fn get_config(&self) -> Result<AgentConfig> {
+    async fn get_config(&self) -> crate::Result<AgentConfig> {
         Ok(self.config.clone())
     }
 }
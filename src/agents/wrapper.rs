@@ -2,11 +2,17 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use async_trait::async_trait;
 use std::collections::HashMap;
-use std::time::Duration;
-use crate::types::{Agent, Message, Tool, State, AgentConfig};
+use std::time::{Duration, Instant};
+use crate::types::{Agent, Message, Tool, State, AgentConfig, Readiness};
 use crate::types::{TodoProcessor, TodoList, TodoTask};
-use futures::executor::block_on;
 use anyhow::Result;
+use tracing::Instrument;
+use crate::ai::budget::{self, BudgetMeter, TaskBudget};
+use crate::recording::{Capture, Recording};
+use crate::tools::dry_run;
+use super::audit::{self, AgentAuditLog, AuditEntry};
+use super::middleware::AgentMiddleware;
+use super::reviewer::ReviewerAgent;
 
 /// A wrapper type that handles the complexity of agent type management.
 /// This provides a consistent interface for working with agents while
@@ -15,6 +21,18 @@ use anyhow::Result;
 pub struct AgentWrapper {
     inner: Arc<Box<dyn Agent + Send + Sync>>,
     todo_list: TodoList,
+    /// Hooks run around every message, outermost first
+    middleware: Vec<Arc<dyn AgentMiddleware>>,
+    /// Reviews each reply before the `after` hooks see it
+    reviewer: Option<Arc<ReviewerAgent>>,
+    /// Shared by clones, so the agent warms up once however it's reached
+    startup: Arc<Startup>,
+}
+
+/// Runs an agent's [`Agent::start`] until it succeeds once
+struct Startup {
+    started: tokio::sync::OnceCell<()>,
+    readiness: std::sync::Mutex<Readiness>,
 }
 
 impl AgentWrapper {
@@ -22,8 +40,127 @@ impl AgentWrapper {
     pub fn new(agent: Box<dyn Agent + Send + Sync>) -> Self {
         Self {
             inner: Arc::new(agent),
-            todo_list: block_on(TodoList::new()).expect("Failed to create TodoList"),
+            todo_list: TodoList::from_env().expect("Failed to create TodoList"),
+            middleware: Vec::new(),
+            reviewer: None,
+            startup: Arc::new(Startup {
+                started: tokio::sync::OnceCell::new(),
+                readiness: std::sync::Mutex::new(Readiness::Pending),
+            }),
+        }
+    }
+
+    /// Warm the agent up if it isn't already. Concurrent callers wait for
+    /// the same attempt; after a failure the next call tries again.
+    pub async fn ensure_started(&self) -> Result<()> {
+        self.startup.started.get_or_try_init(|| async {
+            self.set_readiness(Readiness::Starting);
+            let started = Instant::now();
+            match self.inner.start().await {
+                Ok(()) => {
+                    tracing::info!("Agent {} ready after {:?}", self.agent_name().await, started.elapsed());
+                    self.set_readiness(Readiness::Ready);
+                    Ok(())
+                }
+                Err(e) => {
+                    tracing::warn!("Agent {} failed to start: {}", self.agent_name().await, e);
+                    self.set_readiness(Readiness::Failed(e.to_string()));
+                    Err(e)
+                }
+            }
+        }).await?;
+        Ok(())
+    }
+
+    pub fn readiness(&self) -> Readiness {
+        self.startup.readiness.lock().unwrap().clone()
+    }
+
+    fn set_readiness(&self, readiness: Readiness) {
+        *self.startup.readiness.lock().unwrap() = readiness;
+    }
+
+    /// Add middleware around every message, inside any added before it
+    pub fn with_middleware<M: AgentMiddleware + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Add a chain of middleware, e.g. one built from configuration
+    pub fn with_middleware_chain(mut self, chain: impl IntoIterator<Item = Arc<dyn AgentMiddleware>>) -> Self {
+        self.middleware.extend(chain);
+        self
+    }
+
+    /// Have `reviewer` score every reply, sending weak ones back for one revision
+    pub fn with_reviewer(mut self, reviewer: Arc<ReviewerAgent>) -> Self {
+        self.reviewer = Some(reviewer);
+        self
+    }
+
+    async fn handle_message(&self, message: Message) -> Result<Message> {
+        // Tools called while answering see the dry run through a task-local
+        if message.is_dry_run() && !dry_run::is_active() {
+            return dry_run::with_dry_run(self.respond(message)).await;
+        }
+        self.respond(message).await
+    }
+
+    async fn respond(&self, message: Message) -> Result<Message> {
+        let span = tracing::info_span!(
+            "agent.process_message",
+            correlation_id = crate::telemetry::current_correlation_id().unwrap_or_default(),
+        );
+        let mut message = message;
+        for middleware in &self.middleware {
+            message = middleware.before(message).await?;
+        }
+        let parent_id = message.id.clone();
+        let request = self.reviewer.as_ref().map(|_| message.clone());
+        let mut response = self.inner.process_message(message).instrument(span).await?;
+        if let (Some(reviewer), Some(request)) = (&self.reviewer, request) {
+            response = reviewer.review(&**self.inner, &request, response).await?;
+        }
+        for middleware in self.middleware.iter().rev() {
+            response = middleware.after(response).await?;
+        }
+        if response.parent_id.is_none() && response.id != parent_id {
+            response.parent_id = Some(parent_id);
         }
+        Ok(response)
+    }
+
+    async fn audited(&self, message: Message) -> Result<Message> {
+        let Some(audit_log) = AgentAuditLog::shared() else {
+            return self.handle_message(message).await;
+        };
+        let input = message.content.clone();
+        let task_id = message.task_id().map(str::to_string);
+        let started = Instant::now();
+        let (result, tools) = audit::tracking_tools(self.handle_message(message)).await;
+        let entry = AuditEntry::new(self.agent_name().await, &input, result.as_ref().map(|_| ()), started.elapsed())
+            .with_tools(tools)
+            .with_task(task_id.as_deref());
+        // Off the reply's path; a slow database shouldn't slow agents down
+        tokio::spawn(async move { audit_log.record(&entry).await });
+        result
+    }
+
+    async fn process(&self, message: Message) -> Result<Message> {
+        self.ensure_started().await?;
+        // Task messages are captured whole, by `process_task`
+        let captured = Capture::shared()
+            .filter(|_| message.task_id().is_none())
+            .map(|capture| (capture, message.clone()));
+        let result = self.audited(message).await;
+        if let Some((capture, message)) = captured {
+            capture.record(&Recording::message(self.agent_name().await, message, &result));
+        }
+        result
+    }
+
+    async fn agent_name(&self) -> String {
+        self.inner.get_config().await.map(|config| config.name).unwrap_or_default()
     }
 }
 
@@ -32,22 +169,26 @@ impl TodoProcessor for AgentWrapper {
     async fn process_task(&self, task: TodoTask) -> Result<Message> {
         tracing::info!("Processing task: {}", task.id);
         
-        // Use enhanced description if available, otherwise use the original description
-        let description = match &task.enhanced_description {
-            Some(enhanced) if !enhanced.is_empty() => {
-                tracing::debug!("Using enhanced description for task {}", task.id);
-                enhanced.clone()
-            },
-            _ => {
-                tracing::debug!("Using original description for task {}", task.id);
-                task.description.clone()
-            }
-        };
+        // Enhanced description if available, plus any answers to the agent's questions
+        let description = task.clarified_description();
         
         // Convert the task to a message and process it
-        let message = Message::new(description);
+        let captured = Capture::shared().map(|capture| (capture, task.clone()));
+        let message = Message::new(description)
+            .with_session(task.id.clone())
+            .with_task(task.id.clone())
+            .with_dry_run(task.dry_run);
         
-        match self.process_message(message).await {
+        // Budgeted tasks charge every AI call to their own meter
+        let result = match task.budget.clone().or_else(TaskBudget::from_env) {
+            Some(budget) => budget::metered(Arc::new(BudgetMeter::new(budget)), self.process(message)).await,
+            None => self.process(message).await,
+        };
+        if let Some((capture, task)) = captured {
+            capture.record(&Recording::task(self.agent_name().await, task, &result));
+        }
+
+        match result {
             Ok(response) => {
                 tracing::info!("Successfully processed task {}", task.id);
                 Ok(response)
@@ -71,23 +212,33 @@ impl TodoProcessor for AgentWrapper {
 
 #[async_trait]
 impl Agent for AgentWrapper {
-    async fn process_message(&self, message: Message) -> Result<Message> {
-        self.inner.process_message(message).await
+    async fn start(&self) -> crate::Result<()> {
+        Ok(self.ensure_started().await?)
     }
 
-    async fn transfer_to(&self, target_agent: String, message: Message) -> Result<Message> {
+    async fn process_message(&self, message: Message) -> crate::Result<Message> {
+        Ok(self.process(message).await?)
+    }
+
+    async fn transfer_to(&self, target_agent: String, message: Message) -> crate::Result<Message> {
         self.inner.transfer_to(target_agent, message).await
     }
 
-    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
-        self.inner.call_tool(tool, params).await
+    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> crate::Result<String> {
+        let span = tracing::info_span!("agent.call_tool", tool = %tool.name);
+        self.inner.call_tool(tool, params).instrument(span).await
     }
 
-    async fn get_config(&self) -> Result<AgentConfig> {
+    async fn call_tools(&self, calls: Vec<(Tool, HashMap<String, String>)>) -> Vec<crate::Result<String>> {
+        let span = tracing::info_span!("agent.call_tools", calls = calls.len());
+        self.inner.call_tools(calls).instrument(span).await
+    }
+
+    async fn get_config(&self) -> crate::Result<AgentConfig> {
         self.inner.get_config().await
     }
 
-    async fn get_current_state(&self) -> Result<Option<State>> {
+    async fn get_current_state(&self) -> crate::Result<Option<State>> {
         self.inner.get_current_state().await
     }
 
@@ -100,6 +251,7 @@ impl Agent for AgentWrapper {
 mod tests {
     use super::*;
     use crate::agents::GreeterAgent;
+    use crate::error::SwarmError;
 
     #[tokio::test]
     async fn test_agent_wrapper() {
@@ -124,4 +276,96 @@ mod tests {
         let state = wrapper.get_current_state().await;
         assert!(state.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_middleware_order() {
+        struct Tag(&'static str);
+
+        #[async_trait]
+        impl AgentMiddleware for Tag {
+            async fn before(&self, mut message: Message) -> Result<Message> {
+                message.content.push_str(self.0);
+                Ok(message)
+            }
+
+            async fn after(&self, mut response: Message) -> Result<Message> {
+                response.content.push_str(self.0);
+                Ok(response)
+            }
+        }
+
+        struct Echo;
+
+        #[async_trait]
+        impl Agent for Echo {
+            async fn process_message(&self, message: Message) -> crate::Result<Message> {
+                Ok(Message::new(format!("{}|", message.content)))
+            }
+            async fn transfer_to(&self, _target_agent: String, message: Message) -> crate::Result<Message> {
+                Ok(message)
+            }
+            async fn call_tool(&self, _tool: &Tool, _params: HashMap<String, String>) -> crate::Result<String> {
+                Ok(String::new())
+            }
+            async fn get_current_state(&self) -> crate::Result<Option<State>> {
+                Ok(None)
+            }
+            async fn get_config(&self) -> crate::Result<AgentConfig> {
+                Err(SwarmError::NotFound("no config".to_string()))
+            }
+        }
+
+        let wrapper = AgentWrapper::new(Box::new(Echo))
+            .with_middleware(Tag("a"))
+            .with_middleware(Tag("b"));
+        let response = wrapper.process_message(Message::new(">".to_string())).await.unwrap();
+        assert_eq!(response.content, ">ab|ba");
+    }
+
+    #[tokio::test]
+    async fn test_first_message_starts_the_agent() -> Result<()> {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        /// Fails its first warm-up, succeeds after that
+        struct Flaky(Arc<AtomicU32>);
+
+        #[async_trait]
+        impl Agent for Flaky {
+            async fn start(&self) -> crate::Result<()> {
+                match self.0.fetch_add(1, Ordering::SeqCst) {
+                    0 => Err(SwarmError::Agent("not yet".to_string())),
+                    _ => Ok(()),
+                }
+            }
+            async fn process_message(&self, message: Message) -> crate::Result<Message> {
+                Ok(message)
+            }
+            async fn transfer_to(&self, _target_agent: String, message: Message) -> crate::Result<Message> {
+                Ok(message)
+            }
+            async fn call_tool(&self, _tool: &Tool, _params: HashMap<String, String>) -> crate::Result<String> {
+                Ok(String::new())
+            }
+            async fn get_current_state(&self) -> crate::Result<Option<State>> {
+                Ok(None)
+            }
+            async fn get_config(&self) -> crate::Result<AgentConfig> {
+                Err(SwarmError::NotFound("no config".to_string()))
+            }
+        }
+
+        let starts = Arc::new(AtomicU32::new(0));
+        let wrapper = AgentWrapper::new(Box::new(Flaky(starts.clone())));
+        assert_eq!(wrapper.readiness(), Readiness::Pending);
+
+        assert!(wrapper.process_message(Message::new("hi".to_string())).await.is_err());
+        assert_eq!(wrapper.readiness(), Readiness::Failed("not yet".to_string()));
+
+        // Clones share the warm-up, which only runs until it succeeds
+        wrapper.clone().process_message(Message::new("hi".to_string())).await?;
+        wrapper.process_message(Message::new("hi".to_string())).await?;
+        assert_eq!(wrapper.readiness(), Readiness::Ready);
+        assert_eq!(starts.load(Ordering::SeqCst), 2);
+        Ok(())
+    }
 }
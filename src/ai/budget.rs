@@ -0,0 +1,179 @@
+//! Per-task limits on AI usage and run time. A task's [`TaskBudget`] is
+//! metered by a [`BudgetMeter`] for as long as the task is processed, and AI
+//! clients charge every call to the meter of the task they run under.
+
+use std::env;
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+tokio::task_local! {
+    static METER: Arc<BudgetMeter>;
+}
+
+/// Limits on one task. Unset limits don't apply.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskBudget {
+    #[serde(default)]
+    pub max_ai_calls: Option<u32>,
+    /// Prompt and reply tokens together, estimated at four characters a token
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+    #[serde(default)]
+    pub max_wall_clock_secs: Option<u64>,
+}
+
+impl TaskBudget {
+    /// Default budget for tasks that don't carry one, from `TASK_MAX_AI_CALLS`,
+    /// `TASK_MAX_TOKENS` and `TASK_MAX_WALL_CLOCK_SECS`. `None` when none are set.
+    pub fn from_env() -> Option<Self> {
+        fn read<T: std::str::FromStr>(key: &str) -> Option<T> {
+            env::var(key).ok().and_then(|v| v.parse().ok())
+        }
+
+        let budget = Self {
+            max_ai_calls: read("TASK_MAX_AI_CALLS"),
+            max_tokens: read("TASK_MAX_TOKENS"),
+            max_wall_clock_secs: read("TASK_MAX_WALL_CLOCK_SECS"),
+        };
+        (!budget.is_unlimited()).then_some(budget)
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.max_ai_calls.is_none() && self.max_tokens.is_none() && self.max_wall_clock_secs.is_none()
+    }
+
+    pub fn wall_clock(&self) -> Option<Duration> {
+        self.max_wall_clock_secs.map(Duration::from_secs)
+    }
+}
+
+/// A task ran past one of its budget's limits
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("Task budget exceeded: {0}")]
+pub struct BudgetExceeded(pub String);
+
+impl BudgetExceeded {
+    /// The budget overrun behind `error`, if that is what stopped the task
+    pub fn find(error: &anyhow::Error) -> Option<&Self> {
+        error.chain().find_map(|cause| cause.downcast_ref::<Self>())
+    }
+}
+
+/// Running totals for one task against its budget
+#[derive(Debug, Default)]
+pub struct BudgetMeter {
+    budget: TaskBudget,
+    ai_calls: AtomicU32,
+    tokens: AtomicU64,
+}
+
+impl BudgetMeter {
+    pub fn new(budget: TaskBudget) -> Self {
+        Self { budget, ..Self::default() }
+    }
+
+    pub fn ai_calls(&self) -> u32 {
+        self.ai_calls.load(Ordering::SeqCst)
+    }
+
+    pub fn tokens(&self) -> u64 {
+        self.tokens.load(Ordering::SeqCst)
+    }
+
+    /// Count a call about to be made with `prompt_chars` of prompt, refusing
+    /// it if the call or its prompt would go over budget
+    pub fn charge_call(&self, prompt_chars: usize) -> Result<(), BudgetExceeded> {
+        let calls = self.ai_calls.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Some(max) = self.budget.max_ai_calls {
+            if calls > max {
+                return Err(BudgetExceeded(format!("AI call {} is over the limit of {}", calls, max)));
+            }
+        }
+        self.charge_tokens(prompt_chars)
+    }
+
+    /// Count `chars` of prompt or reply against the token limit
+    pub fn charge_tokens(&self, chars: usize) -> Result<(), BudgetExceeded> {
+        let added = estimate_tokens(chars);
+        let tokens = self.tokens.fetch_add(added, Ordering::SeqCst) + added;
+        match self.budget.max_tokens {
+            Some(max) if tokens > max => Err(BudgetExceeded(format!("{} tokens used, the limit is {}", tokens, max))),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn estimate_tokens(chars: usize) -> u64 {
+    (chars as u64).div_ceil(4)
+}
+
+/// Run `fut` with its AI calls charged to `meter`, stopping it once the
+/// budget's wall-clock limit passes
+pub async fn metered<F, T>(meter: Arc<BudgetMeter>, fut: F) -> anyhow::Result<T>
+where
+    F: Future<Output = anyhow::Result<T>>,
+{
+    let wall_clock = meter.budget.wall_clock();
+    METER.scope(meter, async move {
+        match wall_clock {
+            Some(limit) => tokio::time::timeout(limit, fut).await
+                .unwrap_or_else(|_| Err(BudgetExceeded(format!("still running after {}s", limit.as_secs())).into())),
+            None => fut.await,
+        }
+    }).await
+}
+
+/// Charge a call with `prompt_chars` of prompt to the current task's meter.
+/// Calls made outside a metered task are free.
+pub fn charge_call(prompt_chars: usize) -> Result<(), BudgetExceeded> {
+    METER.try_with(|meter| meter.charge_call(prompt_chars)).unwrap_or(Ok(()))
+}
+
+/// Charge `reply_chars` of reply to the current task's meter
+pub fn charge_reply(reply_chars: usize) -> Result<(), BudgetExceeded> {
+    METER.try_with(|meter| meter.charge_tokens(reply_chars)).unwrap_or(Ok(()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meter_limits() {
+        let meter = BudgetMeter::new(TaskBudget { max_ai_calls: Some(2), max_tokens: Some(100), max_wall_clock_secs: None });
+        assert!(meter.charge_call(40).is_ok());
+        assert!(meter.charge_call(40).is_ok());
+        assert_eq!(meter.tokens(), 20);
+        assert!(meter.charge_call(4).unwrap_err().0.contains("limit of 2"));
+
+        let meter = BudgetMeter::new(TaskBudget { max_tokens: Some(10), ..TaskBudget::default() });
+        assert!(meter.charge_call(40).is_ok());
+        assert!(meter.charge_tokens(1).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_metered_scope() {
+        let meter = Arc::new(BudgetMeter::new(TaskBudget { max_ai_calls: Some(1), ..TaskBudget::default() }));
+        let result = metered(meter.clone(), async {
+            charge_call(10)?;
+            charge_call(10)?;
+            Ok(())
+        }).await;
+        let error = result.unwrap_err().context("agent failed");
+        assert!(BudgetExceeded::find(&error).unwrap().0.contains("limit of 1"));
+        assert_eq!(meter.ai_calls(), 2);
+
+        // Outside a metered task nothing is counted
+        assert!(charge_call(1_000_000).is_ok());
+
+        let slow = Arc::new(BudgetMeter::new(TaskBudget { max_wall_clock_secs: Some(0), ..TaskBudget::default() }));
+        let result = metered(slow, async {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(())
+        }).await;
+        assert!(result.unwrap_err().to_string().contains("still running"));
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use serde_json::{json, Value};
+use crate::clients::Clients;
+
+const DEFAULT_ENDPOINT: &str = "http://127.0.0.1:1234";
+const DEFAULT_MODEL: &str = "nomic-embed-text";
+const EMBED_TIMEOUT: Duration = Duration::from_secs(60);
+/// Dimensions of [`HashedEmbeddings`] vectors
+pub const HASHED_DIMENSIONS: usize = 256;
+
+/// Turns text into vectors whose cosine similarity tracks how related the texts are
+#[async_trait::async_trait]
+pub trait EmbeddingsProvider: Send + Sync {
+    /// One vector per input, in input order
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// Cosine similarity, 0.0 when either vector is all zeros or the lengths differ
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a: f32 = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b: f32 = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// An OpenAI-compatible `/v1/embeddings` endpoint, such as LM Studio or Ollama
+pub struct HttpEmbeddings {
+    client: reqwest::Client,
+    endpoint: String,
+    model: String,
+}
+
+impl HttpEmbeddings {
+    pub fn new(endpoint: impl Into<String>, model: impl Into<String>) -> Self {
+        Self {
+            client: Clients::shared().http(),
+            endpoint: endpoint.into().trim_end_matches('/').to_string(),
+            model: model.into(),
+        }
+    }
+
+    /// Reads `AI_ENDPOINT` and `EMBEDDING_MODEL`
+    pub fn from_env() -> Self {
+        Self::new(
+            env::var("AI_ENDPOINT").unwrap_or_else(|_| DEFAULT_ENDPOINT.to_string()),
+            env::var("EMBEDDING_MODEL").unwrap_or_else(|_| DEFAULT_MODEL.to_string()),
+        )
+    }
+}
+
+#[async_trait::async_trait]
+impl EmbeddingsProvider for HttpEmbeddings {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let response = self.client
+            .post(format!("{}/v1/embeddings", self.endpoint))
+            .json(&json!({ "model": self.model, "input": texts }))
+            .timeout(EMBED_TIMEOUT)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            return Err(anyhow!("Embeddings endpoint returned {}: {}", status, response.text().await.unwrap_or_default()));
+        }
+
+        let body: Value = response.json().await?;
+        let mut data: Vec<(usize, Vec<f32>)> = body["data"].as_array()
+            .ok_or_else(|| anyhow!("Embeddings response has no data"))?
+            .iter()
+            .enumerate()
+            .map(|(position, item)| {
+                let index = item["index"].as_u64().map(|i| i as usize).unwrap_or(position);
+                let vector = item["embedding"].as_array().into_iter().flatten()
+                    .filter_map(|v| v.as_f64().map(|v| v as f32))
+                    .collect();
+                (index, vector)
+            })
+            .collect();
+        if data.len() != texts.len() {
+            return Err(anyhow!("Asked for {} embeddings, got {}", texts.len(), data.len()));
+        }
+        data.sort_by_key(|(index, _)| *index);
+        Ok(data.into_iter().map(|(_, vector)| vector).collect())
+    }
+}
+
+/// Bag-of-words vectors from hashed, lowercased terms. Needs no model, so it
+/// works offline and in tests, but only matches shared words.
+#[derive(Debug, Clone, Default)]
+pub struct HashedEmbeddings;
+
+impl HashedEmbeddings {
+    pub fn embed_one(text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; HASHED_DIMENSIONS];
+        for term in text.split(|c: char| !c.is_alphanumeric()).filter(|t| t.len() > 2) {
+            let mut hasher = DefaultHasher::new();
+            term.to_lowercase().hash(&mut hasher);
+            vector[(hasher.finish() as usize) % HASHED_DIMENSIONS] += 1.0;
+        }
+        vector
+    }
+}
+
+#[async_trait::async_trait]
+impl EmbeddingsProvider for HashedEmbeddings {
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|text| Self::embed_one(text)).collect())
+    }
+}
+
+/// `EMBEDDINGS_PROVIDER=hashed` for [`HashedEmbeddings`], otherwise [`HttpEmbeddings::from_env`]
+pub fn embeddings_from_env() -> Arc<dyn EmbeddingsProvider> {
+    match env::var("EMBEDDINGS_PROVIDER").as_deref() {
+        Ok("hashed") => Arc::new(HashedEmbeddings),
+        _ => Arc::new(HttpEmbeddings::from_env()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hashed_embeddings_rank_shared_words() {
+        let texts = vec![
+            "MQTT broker configuration".to_string(),
+            "Haiku about autumn leaves".to_string(),
+        ];
+        let vectors = HashedEmbeddings.embed(&texts).await.unwrap();
+        let query = HashedEmbeddings::embed_one("configure the mqtt broker");
+        assert!(cosine_similarity(&query, &vectors[0]) > cosine_similarity(&query, &vectors[1]));
+        assert_eq!(cosine_similarity(&query, &[0.0; 3]), 0.0);
+    }
+}
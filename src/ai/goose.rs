@@ -30,9 +30,15 @@ impl GooseClient {
 #[async_trait::async_trait]
 impl AiProvider for GooseClient {
     async fn chat(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+        super::budget::charge_call(super::prompt_chars(system_prompt, &messages))?;
+
+        if let Some(recorded) = crate::recording::replayed_response(system_prompt, &messages) {
+            return recorded;
+        }
+
         // Format the messages into a single prompt
         let mut prompt = format!("System: {}\n\n", system_prompt);
-        for message in messages {
+        for message in &messages {
             if let Some(role) = message.get("role") {
                 if let Some(content) = message.get("content") {
                     prompt.push_str(&format!("{}: {}\n", role, content));
@@ -53,8 +59,11 @@ impl AiProvider for GooseClient {
             .map_err(|e| anyhow!("Failed to execute goose command: {}", e))?;
 
         if output.status.success() {
-            String::from_utf8(output.stdout)
-                .map_err(|e| anyhow!("Failed to parse goose output: {}", e))
+            let reply = String::from_utf8(output.stdout)
+                .map_err(|e| anyhow!("Failed to parse goose output: {}", e))?;
+            super::budget::charge_reply(reply.len())?;
+            crate::recording::capture_response(system_prompt, &messages, &reply);
+            Ok(reply)
         } else {
             Err(anyhow!("Goose command failed: {}", String::from_utf8_lossy(&output.stderr)))
         }
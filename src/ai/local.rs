@@ -87,6 +87,12 @@ impl LocalAiClient {
 #[async_trait::async_trait]
 impl AiProvider for LocalAiClient {
     async fn chat(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String> {
+        super::budget::charge_call(super::prompt_chars(system_prompt, &messages))?;
+
+        if let Some(recorded) = crate::recording::replayed_response(system_prompt, &messages) {
+            return recorded;
+        }
+
         // Ensure model is available
         self.ensure_model().await?;
 
@@ -109,11 +115,14 @@ impl AiProvider for LocalAiClient {
             })?;
 
         if output.status.success() {
-            String::from_utf8(output.stdout)
+            let reply = String::from_utf8(output.stdout)
                 .map_err(|e| {
                     error!("Failed to parse ollama output: {}", e);
                     anyhow!("Failed to parse ollama output: {}", e)
-                })
+                })?;
+            super::budget::charge_reply(reply.len())?;
+            crate::recording::capture_response(system_prompt, &messages, &reply);
+            Ok(reply)
         } else {
             let err = String::from_utf8_lossy(&output.stderr);
             error!("Ollama command failed: {}", err);
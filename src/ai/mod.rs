@@ -2,17 +2,29 @@ use std::collections::HashMap;
 use anyhow::Result;
 use crate::types::TaskPriority;
 
+pub mod budget;
+mod embeddings;
 mod goose;
 mod local;
 
+pub use embeddings::{EmbeddingsProvider, HttpEmbeddings, HashedEmbeddings, cosine_similarity, embeddings_from_env};
 pub use goose::GooseClient;
 pub use local::LocalAiClient;
+pub use budget::{BudgetExceeded, BudgetMeter, TaskBudget};
 
 #[async_trait::async_trait]
 pub trait AiProvider: Send + Sync {
     async fn chat(&self, system_prompt: &str, messages: Vec<HashMap<String, String>>) -> Result<String>;
 }
 
+/// Characters in a chat request, for charging it to the task's budget
+pub(crate) fn prompt_chars(system_prompt: &str, messages: &[HashMap<String, String>]) -> usize {
+    system_prompt.len() + messages.iter()
+        .filter_map(|message| message.get("content"))
+        .map(String::len)
+        .sum::<usize>()
+}
+
 // Re-export the default client based on feature flags
 #[cfg(feature = "goose")]
 pub type DefaultAiClient = GooseClient;
@@ -66,14 +78,8 @@ Use these guidelines:
     ])];
 
     let priority_response = ai_client.chat(priority_prompt, priority_messages).await?;
-    let priority = match priority_response.trim().to_lowercase().as_str() {
-        "inital" => TaskPriority::Inital,
-        "low" => TaskPriority::Low,
-        "medium" => TaskPriority::Medium,
-        "high" => TaskPriority::High,
-        "critical" => TaskPriority::Critical,
-        _ => TaskPriority::Medium, // Default to Medium for any unexpected response
-    };
+    // Default to Medium for any unexpected response
+    let priority = TaskPriority::parse(&priority_response).unwrap_or(TaskPriority::Medium);
 
     // Predict project
     let project_prompt = r#"You are a project classifier. Your task is to determine which project a given task belongs to. 
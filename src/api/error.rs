@@ -0,0 +1,63 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde_json::json;
+use crate::error::SwarmError;
+
+impl SwarmError {
+    /// HTTP status an API handler responds with for this error
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            SwarmError::Validation(_) | SwarmError::Json(_) => StatusCode::BAD_REQUEST,
+            SwarmError::NotFound(_) => StatusCode::NOT_FOUND,
+            SwarmError::Conflict(_) => StatusCode::CONFLICT,
+            SwarmError::Unsupported(_) => StatusCode::NOT_IMPLEMENTED,
+            SwarmError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            SwarmError::Forbidden(_) => StatusCode::FORBIDDEN,
+            SwarmError::Other(e) if crate::access::AccessDenied::find(e).is_some() => StatusCode::FORBIDDEN,
+            SwarmError::Moderation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            SwarmError::Ai(_) | SwarmError::Mqtt(_) => StatusCode::BAD_GATEWAY,
+            SwarmError::Tool(_)
+            | SwarmError::Agent(_)
+            | SwarmError::State(_)
+            | SwarmError::Database(_)
+            | SwarmError::Io(_)
+            | SwarmError::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+impl IntoResponse for SwarmError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        if status.is_server_error() {
+            tracing::error!("API request failed: {}", self);
+        }
+        (status, Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes() {
+        assert_eq!(SwarmError::Validation("bad".into()).status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(SwarmError::NotFound("agent".into()).status_code(), StatusCode::NOT_FOUND);
+        assert_eq!(SwarmError::Conflict("task".into()).status_code(), StatusCode::CONFLICT);
+        assert_eq!(SwarmError::Unauthorized("token".into()).status_code(), StatusCode::UNAUTHORIZED);
+        assert_eq!(SwarmError::Forbidden("role".into()).status_code(), StatusCode::FORBIDDEN);
+        let denied = crate::access::AccessDenied {
+            principal: "ci".into(),
+            permission: "git:write".into(),
+            target: "agent git".into(),
+        };
+        assert_eq!(SwarmError::from(anyhow::Error::new(denied)).status_code(), StatusCode::FORBIDDEN);
+        assert_eq!(SwarmError::Moderation("denied".into()).status_code(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(SwarmError::Ai("timeout".into()).status_code(), StatusCode::BAD_GATEWAY);
+        assert_eq!(SwarmError::Agent("boom".into()).status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,100 @@
+use axum::{
+    async_trait,
+    extract::{FromRequest, Multipart, Request},
+    http::header::CONTENT_TYPE,
+    Json,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use crate::error::SwarmError;
+use crate::types::AttachmentStore;
+
+/// A request body sent either as JSON or as `multipart/form-data`. In a
+/// multipart body, text fields become the JSON object's fields and file
+/// parts are appended to its `attachments`.
+pub struct JsonOrMultipart<T>(pub T);
+
+fn is_multipart(request: &Request) -> bool {
+    request.headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| value.starts_with("multipart/form-data"))
+}
+
+/// Form fields are strings; structured ones (lists, numbers, flags) are sent as JSON text
+fn form_value(text: String) -> Value {
+    match serde_json::from_str::<Value>(&text) {
+        Ok(value @ (Value::Array(_) | Value::Object(_) | Value::Number(_) | Value::Bool(_))) => value,
+        _ => Value::String(text),
+    }
+}
+
+async fn read_multipart(mut multipart: Multipart) -> Result<Map<String, Value>, SwarmError> {
+    let invalid = |e: axum::extract::multipart::MultipartError| SwarmError::Validation(e.body_text());
+    let store = AttachmentStore::from_env();
+    let mut fields = Map::new();
+    let mut attachments = Vec::new();
+
+    while let Some(field) = multipart.next_field().await.map_err(invalid)? {
+        let name = field.name().unwrap_or_default().to_string();
+        match field.file_name().map(str::to_string) {
+            Some(file_name) => {
+                let content_type = field.content_type().unwrap_or("application/octet-stream").to_string();
+                let bytes = field.bytes().await.map_err(invalid)?;
+                let attachment = store.store(Some(file_name), content_type, &bytes).await
+                    .map_err(SwarmError::Other)?;
+                attachments.push(serde_json::to_value(attachment)?);
+            }
+            None => {
+                let text = field.text().await.map_err(invalid)?;
+                fields.insert(name, form_value(text));
+            }
+        }
+    }
+
+    if !attachments.is_empty() {
+        let mut all = match fields.remove("attachments") {
+            Some(Value::Array(existing)) => existing,
+            Some(other) => return Err(SwarmError::Validation(format!("attachments must be a JSON list, got {}", other))),
+            None => Vec::new(),
+        };
+        all.extend(attachments);
+        fields.insert("attachments".to_string(), Value::Array(all));
+    }
+    Ok(fields)
+}
+
+#[async_trait]
+impl<S, T> FromRequest<S> for JsonOrMultipart<T>
+where
+    S: Send + Sync,
+    T: DeserializeOwned,
+{
+    type Rejection = SwarmError;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if !is_multipart(&request) {
+            let Json(value) = Json::<T>::from_request(request, state).await
+                .map_err(|e| SwarmError::Validation(e.body_text()))?;
+            return Ok(Self(value));
+        }
+
+        let multipart = Multipart::from_request(request, state).await
+            .map_err(|e| SwarmError::Validation(e.body_text()))?;
+        let fields = read_multipart(multipart).await?;
+        serde_json::from_value(Value::Object(fields.clone()))
+            .or_else(|e| {
+                // A text field that happened to look like a number or flag
+                let as_text = fields.into_iter()
+                    .map(|(key, value)| match value {
+                        Value::Number(_) | Value::Bool(_) => (key, Value::String(value.to_string())),
+                        other => (key, other),
+                    })
+                    .collect();
+                serde_json::from_value(Value::Object(as_text)).map_err(|_| e)
+            })
+            .map(Self)
+            .map_err(|e| SwarmError::Validation(format!("Invalid form: {}", e)))
+    }
+}
+
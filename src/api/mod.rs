@@ -1,20 +1,39 @@
 use std::sync::Arc;
 use std::net::SocketAddr;
+use std::time::Duration;
 use axum::{
+    extract::Request,
+    http::HeaderValue,
+    middleware::{self, Next},
+    response::Response,
     routing::{get, post},
     Router,
 };
+use tracing::Instrument;
+use crate::mqtt::{MqttConfig, MqttService};
 use tower_http::cors::CorsLayer;
+use tower_http::services::ServeDir;
 use tokio::sync::RwLock;
 use crate::{
-    agents::{AgentRegistry, TransferService},
+    access::AccessPolicy,
+    agents::{discovery, remote, AgentAuditLog, AgentRegistry, ApprovalInbox, OutputFilter, RemoteAgents, StructuredOutput, SwarmDirectory, TransferService},
+    clients::Clients,
+    config::SwarmConfig,
+    events::{self, EventBus, EventMetrics},
+    state::{open_state_store, StateStore},
+    telemetry,
+    tools::{LocalArtifactStore, TodoAuditLog, WorkerMetricsStore},
     types::Agent,
 };
 
+mod auth;
+mod error;
+mod form;
 mod models;
 mod routes;
 mod websocket;
 
+pub use form::JsonOrMultipart;
 pub use models::*;
 pub use routes::*;
 pub use websocket::*;
@@ -22,15 +41,150 @@ pub use websocket::*;
 pub struct AppState {
     pub transfer_service: Arc<RwLock<TransferService>>,
     pub agents: Arc<RwLock<AgentRegistry>>,
+    pub mqtt_client: Option<MqttService>,
+    pub events: EventBus,
+    /// Events other processes published to MQTT, for WebSocket clients only;
+    /// kept off `events` so the MQTT bridge doesn't echo them back
+    pub relayed_events: EventBus,
+    pub event_metrics: EventMetrics,
+    pub audit_log: Option<Arc<TodoAuditLog>>,
+    /// Every agent invocation, for the audit query route
+    pub agent_audit: Option<AgentAuditLog>,
+    pub metrics_store: Option<WorkerMetricsStore>,
+    pub state_store: Option<Arc<dyn StateStore>>,
+    /// Bearer token for the operator routes; they are closed when unset
+    pub admin_token: Option<String>,
+    /// Nodes announcing themselves on the broker
+    pub directory: Option<SwarmDirectory>,
+    /// Training runs started through the API
+    #[cfg(feature = "rl")]
+    pub rl_runs: crate::agents::rl::RunManager,
+    /// Checks agent replies before they are sent to HTTP and WebSocket clients
+    pub output_filter: Arc<OutputFilter>,
+    /// Builds replies for callers that asked for `response_format: json`
+    pub structured_output: Arc<StructuredOutput>,
+    /// Operations in this process waiting for someone to approve them
+    pub inbox: Arc<ApprovalInbox>,
+    /// Maps `X-Api-Key` to a principal and decides which agents it may reach
+    pub access: Arc<AccessPolicy>,
+    /// Connection pools for MongoDB and outgoing HTTP
+    pub clients: Clients,
 }
 
 impl AppState {
     pub fn new(transfer_service: Arc<RwLock<TransferService>>) -> Self {
+        // Shared, so events from agents, tool registries and the worker loop reach API clients
+        let events = EventBus::shared();
         Self {
             transfer_service,
-            agents: Arc::new(RwLock::new(AgentRegistry::new()))
+            agents: Arc::new(RwLock::new(AgentRegistry::new())),
+            mqtt_client: None,
+            event_metrics: EventMetrics::spawn(&events),
+            relayed_events: EventBus::default(),
+            audit_log: None,
+            agent_audit: None,
+            metrics_store: None,
+            state_store: None,
+            admin_token: None,
+            directory: None,
+            #[cfg(feature = "rl")]
+            rl_runs: crate::agents::rl::RunManager::new(crate::config::ApiSettings::default().rl_runs_dir)
+                .with_event_bus(events.clone()),
+            output_filter: OutputFilter::shared(),
+            structured_output: StructuredOutput::shared(),
+            inbox: ApprovalInbox::shared(),
+            access: AccessPolicy::shared(),
+            clients: Clients::shared(),
+            events,
         }
     }
+
+    /// Serve todo history from `audit_log`
+    pub fn with_audit_log(mut self, audit_log: TodoAuditLog) -> Self {
+        self.audit_log = Some(Arc::new(audit_log));
+        self
+    }
+
+    /// Serve the agent audit trail from `log`
+    pub fn with_agent_audit(mut self, log: AgentAuditLog) -> Self {
+        self.agent_audit = Some(log);
+        self
+    }
+
+    /// Serve worker metrics history from `store`
+    pub fn with_metrics_store(mut self, store: WorkerMetricsStore) -> Self {
+        self.metrics_store = Some(store);
+        self
+    }
+
+    /// Serve and manage persisted agent state from `store`
+    pub fn with_state_store(mut self, store: Arc<dyn StateStore>) -> Self {
+        self.state_store = Some(store);
+        self
+    }
+
+    pub fn with_admin_token(mut self, token: impl Into<String>) -> Self {
+        self.admin_token = Some(token.into());
+        self
+    }
+
+    /// Serve the swarm's nodes from `directory`
+    pub fn with_directory(mut self, directory: SwarmDirectory) -> Self {
+        self.directory = Some(directory);
+        self
+    }
+
+    /// Keep training runs started through the API under `dir`
+    #[cfg(feature = "rl")]
+    pub fn with_rl_runs_dir<P: AsRef<std::path::Path>>(mut self, dir: P) -> Self {
+        self.rl_runs = crate::agents::rl::RunManager::new(dir).with_event_bus(self.events.clone());
+        self
+    }
+
+    /// Check agent replies with `filter` instead of the one from the environment
+    pub fn with_output_filter(mut self, filter: OutputFilter) -> Self {
+        self.output_filter = Arc::new(filter);
+        self
+    }
+
+    /// Build structured replies with `output` instead of the one from the environment
+    pub fn with_structured_output(mut self, output: StructuredOutput) -> Self {
+        self.structured_output = Arc::new(output);
+        self
+    }
+
+    /// Hold gated operations in `inbox` instead of the shared one
+    pub fn with_inbox(mut self, inbox: ApprovalInbox) -> Self {
+        self.inbox = Arc::new(inbox);
+        self
+    }
+
+    /// Check callers against `policy` instead of the one from the environment
+    pub fn with_access_policy(mut self, policy: AccessPolicy) -> Self {
+        self.access = Arc::new(policy);
+        self
+    }
+
+    /// Use `clients` instead of the shared connection pools
+    pub fn with_clients(mut self, clients: Clients) -> Self {
+        self.clients = clients;
+        self
+    }
+
+    /// Mirror bus events to MQTT through `client`
+    pub fn with_mqtt_client(mut self, client: MqttService) -> Self {
+        events::spawn_mqtt_bridge(&self.events, client.clone());
+        self.mqtt_client = Some(client);
+        self
+    }
+}
+
+/// Connect to the MQTT broker configured via `MQTT_HOST`/`MQTT_PORT` so the API
+/// can publish task notifications. Returns `None` when no broker is configured.
+pub fn connect_mqtt_from_env() -> Option<MqttService> {
+    std::env::var("MQTT_HOST").ok()?;
+    let config = MqttConfig::from_env(format!("swarmonomicon-api-{}", uuid::Uuid::new_v4()));
+    Some(MqttService::connect(config))
 }
 
 pub async fn create_app_state() -> Arc<AppState> {
@@ -41,12 +195,131 @@ pub async fn create_app_state() -> Arc<AppState> {
     Arc::new(AppState::new(transfer_service))
 }
 
-pub async fn serve(addr: SocketAddr, transfer_service: Arc<RwLock<TransferService>>) {
+pub async fn serve(config: &SwarmConfig, transfer_service: Arc<RwLock<TransferService>>) {
+    let addr = config.api.addr();
+    let mut app_state = AppState::new(transfer_service);
+    if let Some(client) = connect_mqtt_from_env() {
+        app_state = app_state.with_mqtt_client(client);
+    }
+    match open_state_store(config).await {
+        Ok(store) => app_state = app_state.with_state_store(store),
+        Err(e) => tracing::warn!("Agent state routes unavailable: {}", e),
+    }
+    let app = router(config, app_state).await;
+
+    println!("Server running on {}", addr);
+    axum::serve(
+        tokio::net::TcpListener::bind(addr).await.unwrap(),
+        app,
+    )
+    .await
+    .unwrap();
+}
+
+/// Every route `serve` answers, over `app_state` with the default agents and
+/// whatever else the environment configures. The MQTT client and state store
+/// are taken from `app_state`, so callers can hand in their own.
+pub async fn router(config: &SwarmConfig, mut app_state: AppState) -> Router {
+    let transfer_service = app_state.transfer_service.clone();
     let registry = AgentRegistry::create_default_agents(routes::default_agents()).await.unwrap();
-    let app_state = Arc::new(AppState {
-        transfer_service,
-        agents: Arc::new(RwLock::new(registry)),
-    });
+    app_state.agents = Arc::new(RwLock::new(registry));
+    match TodoAuditLog::from_env().await {
+        Ok(audit_log) => app_state = app_state.with_audit_log(audit_log),
+        Err(e) => tracing::warn!("Todo history unavailable: {}", e),
+    }
+    match AgentAuditLog::from_env().await {
+        Ok(log) => app_state = app_state.with_agent_audit(AgentAuditLog::init_shared(log)),
+        Err(e) => tracing::warn!("Agent audit trail unavailable: {}", e),
+    }
+    match WorkerMetricsStore::from_env().await {
+        Ok(store) => app_state = app_state.with_metrics_store(store),
+        Err(e) => tracing::warn!("Worker metrics history unavailable: {}", e),
+    }
+    if let Err(e) = events::spawn_mongo_sink_from_env(&app_state.events).await {
+        tracing::warn!("Event log unavailable: {}", e);
+    }
+    if let Some(token) = &config.api.admin_token {
+        app_state = app_state.with_admin_token(token.clone());
+    }
+    #[cfg(feature = "rl")]
+    {
+        app_state = app_state.with_rl_runs_dir(&config.api.rl_runs_dir);
+    }
+    transfer_service.write().await.set_event_bus(app_state.events.clone());
+    #[cfg(feature = "rl-routing")]
+    {
+        use crate::agents::rl::routing::{LearnedRouter, OutcomeLog};
+        let mut service = transfer_service.write().await;
+        if let Some(log) = OutcomeLog::from_env() {
+            service.set_outcome_log(log);
+        }
+        match LearnedRouter::from_env().await {
+            Ok(Some(router)) => service.set_learned_router(Arc::new(router)),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Learned routing unavailable: {}", e),
+        }
+    }
+    if let Some(client) = app_state.mqtt_client.clone() {
+        let node_id = config.swarm.node_id();
+        discovery::spawn_announcer(client.clone(), node_id.clone(), app_state.agents.clone(), config.swarm.heartbeat());
+        // Live metrics from training runs in other processes; runs started
+        // here already reach WebSocket clients on the local bus
+        #[cfg(feature = "rl")]
+        let keep = {
+            let local_runs = app_state.rl_runs.clone();
+            move |event: &events::Event| match event {
+                events::Event::TrainingEpisode { run_id, .. } => !local_runs.owns(run_id),
+                _ => true,
+            }
+        };
+        #[cfg(not(feature = "rl"))]
+        let keep = |_: &events::Event| true;
+        if let Err(e) = events::spawn_mqtt_relay(&client, "rl/+/metrics", app_state.relayed_events.clone(), keep).await {
+            tracing::warn!("Live training metrics unavailable: {}", e);
+        }
+        let directory = SwarmDirectory::new(config.swarm.stale_after());
+        match directory.spawn(&client).await {
+            Ok(_) => app_state = app_state.with_directory(directory),
+            Err(e) => tracing::warn!("Swarm directory unavailable: {}", e),
+        }
+
+        // Other nodes reach this node's agents, and transfers reach theirs
+        if let Err(e) = remote::spawn_inbox(app_state.agents.clone(), client.clone(), &node_id).await {
+            tracing::warn!("Remote messages to this node unavailable: {}", e);
+        }
+        if !config.swarm.remote_agents.is_empty() {
+            match RemoteAgents::connect(client, &config.swarm).await {
+                Ok(remote) => transfer_service.write().await.set_remote_agents(Arc::new(remote)),
+                Err(e) => tracing::warn!("Remote agents unavailable: {}", e),
+            }
+        }
+    }
+    route_table(Arc::new(app_state))
+}
+
+/// The route table over a ready `app_state`. Operator routes, and deciding
+/// what sits in the user inbox, need the admin token.
+fn route_table(app_state: Arc<AppState>) -> Router {
+    // Operator routes for inspecting and repairing persisted agent state
+    let admin = Router::new()
+        .route("/api/agents/:name/state", get(routes::get_agent_state))
+        .route("/api/agents/:name/state/rollback", post(routes::rollback_agent_state))
+        .route("/api/agents/:name/state/transition", post(routes::force_agent_transition))
+        .route("/api/audit", get(routes::get_audit_log))
+        // Approving releases gated operations like `git push`, so it can't be open to any caller
+        .route("/api/user/inbox/:id/approve", post(routes::approve_inbox_item))
+        .route("/api/user/inbox/:id/reject", post(routes::reject_inbox_item))
+        .route("/api/user/inbox/:id/edit", post(routes::edit_inbox_item));
+
+    // Training runs use the server's CPU, so starting and stopping them is for operators too
+    #[cfg(feature = "rl")]
+    let admin = admin
+        .route("/api/rl/runs", get(routes::list_rl_runs).post(routes::start_rl_run))
+        .route("/api/rl/runs/:run_id", get(routes::get_rl_run).delete(routes::cancel_rl_run))
+        .route("/api/rl/runs/:run_id/artifacts", get(routes::list_rl_run_artifacts));
+
+    let admin = admin
+        .route_layer(middleware::from_fn_with_state(app_state.clone(), auth::require_admin));
 
     let app = Router::new()
         .route("/", get(routes::index))
@@ -56,18 +329,58 @@ pub async fn serve(addr: SocketAddr, transfer_service: Arc<RwLock<TransferServic
         .route("/api/agents/:name/send", post(routes::send_message))
         .route("/api/agents/:name/tasks", get(routes::get_tasks))
         .route("/api/agents/:name/tasks", post(routes::add_task))
-        .route("/api/agents/:name/tasks/:task_id", get(routes::get_task))
+        .route("/api/agents/:name/tasks/:task_id", get(routes::get_task).delete(routes::cancel_task))
+        .route("/api/agents/:name/tasks/:task_id/retry", post(routes::retry_task))
+        .route("/api/agents/:name/tasks/:task_id/answer", post(routes::answer_task))
+        .route("/api/user/inbox", get(routes::get_inbox))
+        .route("/api/todos/:todo_id/history", get(routes::get_todo_history))
+        .route("/api/events/metrics", get(routes::get_event_metrics))
+        .route("/api/metrics/history", get(routes::get_metrics_history))
+        .route("/api/swarm/nodes", get(routes::list_swarm_nodes))
         .route("/ws", get(websocket::websocket_handler))
+        .merge(admin);
+
+    #[cfg(feature = "haiku-agent")]
+    let app = app.route("/api/haiku/archive", get(routes::get_haiku_archive));
+
+    // The local artifact store's URLs point here when ARTIFACT_BASE_URL is set to `<server>/artifacts`
+    let app = if std::env::var("ARTIFACT_STORE").map_or(false, |store| store.eq_ignore_ascii_case("local")) {
+        app.nest_service("/artifacts", ServeDir::new(LocalArtifactStore::from_env().dir()))
+    } else {
+        app
+    };
+
+    app
+        .layer(middleware::from_fn_with_state(app_state.clone(), auth::resolve_principal))
+        .layer(middleware::from_fn(correlation_middleware))
         .layer(CorsLayer::permissive())
-        .with_state(app_state);
+        .with_state(app_state)
+}
 
-    println!("Server running on {}", addr);
-    axum::serve(
-        tokio::net::TcpListener::bind(addr).await.unwrap(),
-        app,
-    )
-    .await
-    .unwrap();
+/// Give every request a correlation id (reusing the caller's if provided) and a
+/// tracing span, so downstream agent, tool, and MCP calls can be tied back to it.
+async fn correlation_middleware(request: Request, next: Next) -> Response {
+    let correlation_id = request.headers()
+        .get(telemetry::CORRELATION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+        .unwrap_or_else(telemetry::new_correlation_id);
+
+    let span = tracing::info_span!(
+        "http_request",
+        method = %request.method(),
+        path = %request.uri().path(),
+        correlation_id = %correlation_id,
+    );
+
+    let mut response = telemetry::with_correlation_id(correlation_id.clone(), next.run(request))
+        .instrument(span)
+        .await;
+
+    if let Ok(value) = HeaderValue::from_str(&correlation_id) {
+        response.headers_mut().insert(telemetry::CORRELATION_HEADER, value);
+    }
+    response
 }
 
 pub fn create_router(state: Arc<AppState>) -> Router {
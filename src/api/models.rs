@@ -1,4 +1,5 @@
-use crate::types::{TodoTask, TaskPriority, TaskStatus};
+use crate::agents::Approval;
+use crate::types::{Clarification, TodoTask, TaskPriority, TaskStatus};
 use serde::Serialize;
 
 #[derive(Debug, Serialize)]
@@ -13,6 +14,10 @@ pub struct TaskResponse {
     pub status: TaskStatus,
     pub created_at: i64,
     pub completed_at: Option<i64>,
+    pub depends_on: Vec<String>,
+    pub due_at: Option<i64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub clarifications: Vec<Clarification>,
 }
 
 impl From<TodoTask> for TaskResponse {
@@ -28,6 +33,18 @@ impl From<TodoTask> for TaskResponse {
             status: task.status,
             created_at: task.created_at,
             completed_at: task.completed_at,
+            depends_on: task.depends_on,
+            due_at: task.due_at,
+            clarifications: task.clarifications,
         }
     }
-} 
+}
+
+/// Something in the user inbox: a task routed to the `user` agent, or an
+/// operation waiting for approval
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum InboxEntry {
+    Task(TaskResponse),
+    Operation(Approval),
+}
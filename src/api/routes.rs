@@ -1,6 +1,5 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{Path, Query, State},
     response::{IntoResponse, Response},
     Json,
 };
@@ -9,19 +8,28 @@ use std::sync::Arc;
 use tokio::sync::RwLock;
 use std::collections::HashMap;
 use std::time::Duration;
-use futures::executor::block_on;
 use async_trait::async_trait;
 use anyhow::anyhow;
 use mongodb::{Client, Collection};
 
 use crate::{
+    access::AccessDenied,
     api::AppState,
-    types::{Message, AgentConfig, Agent, AgentInfo, TodoTask, TaskPriority, TaskStatus, TodoProcessor, TodoList, StateMachine, AgentStateManager, Tool},
-    agents::AgentRegistry,
-    ai::{AiProvider, DefaultAiClient},
+    types::{Attachment, Message, AgentConfig, ResponseFormat, Agent, AgentInfo, TodoTask, TaskPriority, TaskStatus, TodoProcessor, TodoList, StateMachine, AgentStateManager, Tool, Recurrence, TaskSchedule, derive_idempotency_key, idempotency_window_secs},
+    agents::{AgentHandle, AuditEntry, AuditQuery, NodeStatus, user_agent::{self, USER_AGENT}},
+    ai::{AiProvider, DefaultAiClient, TaskBudget},
+    events::Event,
+    error::SwarmError,
+    mcp::schema::LogEntry,
+    state::{
+        BasicStateValidator, PersistedState, StatePersistence, StateRecovery, StateStore, StateTransition,
+        StateValidator, VersionConflict,
+    },
+    tools::{MetricsHistoryQuery, MetricsSnapshot},
 };
 
-use super::models::TaskResponse;
+use super::form::JsonOrMultipart;
+use super::models::{InboxEntry, TaskResponse};
 
 pub async fn index() -> Response {
     "Welcome to the Swarmonomicon API".into_response()
@@ -36,25 +44,62 @@ pub struct AgentResponse {
 #[derive(Debug, Deserialize)]
 pub struct MessageRequest {
     content: String,
+    #[serde(default)]
+    attachments: Vec<Attachment>,
+    /// Keeps per-caller agent state, like the git assistant's repository, apart
+    #[serde(default)]
+    session_id: Option<String>,
+    /// `json` for a structured payload instead of the agent's message
+    #[serde(default)]
+    response_format: ResponseFormat,
+    /// Describe what the agent's tools would change instead of changing it
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// What the message routes answer with: the agent's message, or the
+/// structured payload made from it
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum MessageReply {
+    Text(Message),
+    Json(serde_json::Value),
+}
+
+impl MessageRequest {
+    fn into_message(self) -> Message {
+        let message = Message::new(self.content)
+            .with_attachments(self.attachments)
+            .with_response_format(self.response_format)
+            .with_dry_run(self.dry_run);
+        match self.session_id {
+            Some(session) => message.with_session(session),
+            None => message,
+        }
+    }
 }
 
 pub async fn list_agents(
     State(state): State<Arc<AppState>>,
-) -> Result<Json<Vec<AgentInfo>>, StatusCode> {
-    let registry = state.agents.read().await;
+) -> Result<Json<Vec<AgentInfo>>, SwarmError> {
+    // Ask each agent for its config without holding up the registry
+    let handles: Vec<_> = state.agents.read().await.iter()
+        .map(|(name, handle)| (name.clone(), handle.clone()))
+        .collect();
     let mut agents = Vec::new();
 
-    for (name, agent) in registry.agents.iter() {
-        match agent.get_config().await {
-            Ok(config) => agents.push(AgentInfo {
-                name: name.clone(),
-                description: config.public_description,
-                instructions: config.instructions.clone(),
-                tools: config.tools.clone(),
-                downstream_agents: config.downstream_agents.clone(),
-            }),
-            Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR),
-        }
+    for (name, handle) in handles {
+        let agent = handle.read().await;
+        let config = agent.get_config().await
+            .map_err(|e| SwarmError::Agent(e.to_string()))?;
+        agents.push(AgentInfo {
+            name,
+            description: config.public_description,
+            instructions: config.instructions.clone(),
+            tools: config.tools.clone(),
+            downstream_agents: config.downstream_agents.clone(),
+            readiness: agent.readiness(),
+        });
     }
 
     Ok(Json(agents))
@@ -63,56 +108,57 @@ pub async fn list_agents(
 pub async fn get_agent(
     State(state): State<Arc<AppState>>,
     Path(name): Path<String>,
-) -> Result<Json<AgentInfo>, StatusCode> {
-    let registry = state.agents.read().await;
-
-    if let Some(agent) = registry.get(&name) {
-        match agent.get_config().await {
-            Ok(config) => Ok(Json(AgentInfo {
-                name: config.name,
-                description: config.public_description,
-                instructions: config.instructions.clone(),
-                tools: config.tools.clone(),
-                downstream_agents: config.downstream_agents.clone(),
-            })),
-            Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-        }
-    } else {
-        Err(StatusCode::NOT_FOUND)
-    }
+) -> Result<Json<AgentInfo>, SwarmError> {
+    let handle = agent_handle(&state, &name).await?;
+    let agent = handle.read().await;
+    let config = agent.get_config().await
+        .map_err(|e| SwarmError::Agent(e.to_string()))?;
+
+    Ok(Json(AgentInfo {
+        name: config.name,
+        description: config.public_description,
+        instructions: config.instructions.clone(),
+        tools: config.tools.clone(),
+        downstream_agents: config.downstream_agents.clone(),
+        readiness: agent.readiness(),
+    }))
 }
 
 pub async fn process_message(
     State(state): State<Arc<AppState>>,
     Path(agent_name): Path<String>,
-    Json(request): Json<MessageRequest>,
-) -> Result<Json<Message>, StatusCode> {
-    let registry = state.agents.read().await;
-
-    if let Some(agent) = registry.get(&agent_name) {
-        match agent.process_message(Message::new(request.content)).await {
-            Ok(response) => Ok(Json(response)),
-            Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-        }
-    } else {
-        Err(StatusCode::NOT_FOUND)
-    }
+    JsonOrMultipart(request): JsonOrMultipart<MessageRequest>,
+) -> Result<Json<MessageReply>, SwarmError> {
+    reply_to(&state, &agent_name, request).await.map(Json)
 }
 
 pub async fn send_message(
     State(state): State<Arc<AppState>>,
     Path(agent_name): Path<String>,
-    Json(request): Json<MessageRequest>,
-) -> Result<Json<Message>, StatusCode> {
-    let registry = state.agents.read().await;
-
-    if let Some(agent) = registry.get(&agent_name) {
-        match agent.process_message(Message::new(request.content)).await {
-            Ok(response) => Ok(Json(response)),
-            Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
-        }
-    } else {
-        Err(StatusCode::NOT_FOUND)
+    JsonOrMultipart(request): JsonOrMultipart<MessageRequest>,
+) -> Result<Json<MessageReply>, SwarmError> {
+    reply_to(&state, &agent_name, request).await.map(Json)
+}
+
+/// Hand `request` to `agent_name` and check its reply on the way out
+async fn reply_to(state: &AppState, agent_name: &str, request: MessageRequest) -> Result<MessageReply, SwarmError> {
+    let format = request.response_format;
+    let handle = agent_handle(state, agent_name).await?;
+    state.access.check_agent(agent_name)?;
+    let response = handle.read().await.process_message(request.into_message()).await
+        .map_err(|e| match e {
+            SwarmError::Other(e) => match AccessDenied::find(&e) {
+                Some(denied) => denied.clone().into(),
+                None => SwarmError::Agent(e.to_string()),
+            },
+            e => e,
+        })?;
+    let response = state.output_filter.check(agent_name, response).await?;
+
+    publish_state_change(state, agent_name, &response);
+    match format {
+        ResponseFormat::Text => Ok(MessageReply::Text(response)),
+        ResponseFormat::Json => Ok(MessageReply::Json(state.structured_output.render(agent_name, &response).await?)),
     }
 }
 
@@ -185,6 +231,9 @@ pub fn default_agents() -> Vec<AgentConfig> {
         state_machine: None,
     });
 
+    // Tasks for the user are listed in the user inbox
+    agents.push(user_agent::default_config());
+
     agents
 }
 
@@ -194,23 +243,49 @@ pub struct AddTaskRequest {
     pub priority: TaskPriority,
     pub source_agent: Option<String>,
     pub project: Option<String>,
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// Deadline as unix seconds
+    pub due_at: Option<i64>,
+    pub recurrence: Option<Recurrence>,
+    /// Replaying a key within the idempotency window returns the original task.
+    /// Derived from the description and project when omitted.
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+    /// Files for the agent; multipart requests add their file parts here
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    /// Limits on AI calls, tokens and run time for this task
+    #[serde(default)]
+    pub budget: Option<TaskBudget>,
+    /// Process the task as a dry run
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct TaskListQuery {
+    /// Only return active tasks that are past their deadline
+    pub overdue: Option<bool>,
 }
 
 // Get all tasks for an agent
 pub async fn get_tasks(
     State(state): State<Arc<AppState>>,
     Path(agent_name): Path<String>,
-) -> Result<Json<Vec<TaskResponse>>, StatusCode> {
-    let registry = state.agents.read().await;
-
-    let agent = registry.get(&agent_name)
-        .ok_or(StatusCode::NOT_FOUND)?;
-
-    let todo_list = <dyn Agent>::get_todo_list(agent)
-        .ok_or(StatusCode::NOT_IMPLEMENTED)?;
-
-    let tasks = todo_list.get_all_tasks().await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Query(query): Query<TaskListQuery>,
+) -> Result<Json<Vec<TaskResponse>>, SwarmError> {
+    let handle = agent_handle(&state, &agent_name).await?;
+    let agent = handle.read().await;
+
+    let todo_list = <dyn Agent>::get_todo_list(&*agent)
+        .ok_or_else(|| SwarmError::Unsupported(format!("Agent '{}' has no todo list", agent_name)))?;
+
+    let mut tasks = todo_list.get_all_tasks().await?;
+    if let Some(overdue) = query.overdue {
+        let now = chrono::Utc::now().timestamp();
+        tasks.retain(|task| task.is_overdue(now) == overdue);
+    }
 
     Ok(Json(tasks.into_iter().map(TaskResponse::from).collect()))
 }
@@ -219,18 +294,15 @@ pub async fn get_tasks(
 pub async fn get_task(
     State(state): State<Arc<AppState>>,
     Path((agent_name, task_id)): Path<(String, String)>,
-) -> Result<Json<TaskResponse>, StatusCode> {
-    let registry = state.agents.read().await;
-
-    let agent = registry.get(&agent_name)
-        .ok_or(StatusCode::NOT_FOUND)?;
+) -> Result<Json<TaskResponse>, SwarmError> {
+    let handle = agent_handle(&state, &agent_name).await?;
+    let agent = handle.read().await;
 
-    let todo_list = <dyn Agent>::get_todo_list(agent)
-        .ok_or(StatusCode::NOT_IMPLEMENTED)?;
+    let todo_list = <dyn Agent>::get_todo_list(&*agent)
+        .ok_or_else(|| SwarmError::Unsupported(format!("Agent '{}' has no todo list", agent_name)))?;
 
-    let task = todo_list.get_task(&task_id).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::NOT_FOUND)?;
+    let task = todo_list.get_task(&task_id).await?
+        .ok_or_else(|| SwarmError::NotFound(format!("Task '{}'", task_id)))?;
 
     Ok(Json(TaskResponse::from(task)))
 }
@@ -239,37 +311,553 @@ pub async fn get_task(
 pub async fn add_task(
     State(state): State<Arc<AppState>>,
     Path(agent_name): Path<String>,
-    Json(request): Json<AddTaskRequest>,
-) -> Result<Json<TaskResponse>, StatusCode> {
-    let registry = state.agents.read().await;
-
-    let agent = registry.get(&agent_name)
-        .ok_or(StatusCode::NOT_FOUND)?;
-
-    let todo_list = <dyn Agent>::get_todo_list(agent)
-        .ok_or(StatusCode::NOT_IMPLEMENTED)?;
+    JsonOrMultipart(request): JsonOrMultipart<AddTaskRequest>,
+) -> Result<Json<TaskResponse>, SwarmError> {
+    let handle = agent_handle(&state, &agent_name).await?;
+    let agent = handle.read().await;
+    state.access.check_agent(&agent_name)?;
+
+    let todo_list = <dyn Agent>::get_todo_list(&*agent)
+        .ok_or_else(|| SwarmError::Unsupported(format!("Agent '{}' has no todo list", agent_name)))?;
+
+    // A retried request gets the task it already created
+    let idempotency_key = request.idempotency_key
+        .unwrap_or_else(|| derive_idempotency_key(&request.description, request.project.as_deref()));
+    if let Some(existing) = todo_list.find_by_idempotency_key(&idempotency_key, idempotency_window_secs()).await? {
+        tracing::info!("Idempotency key {} replayed, returning task {}", idempotency_key, existing.id);
+        return Ok(Json(TaskResponse::from(existing)));
+    }
 
     // Create task with optional AI enhancement
     let task = todo_list.create_task_with_enhancement(
         request.description,
         request.priority,
         request.source_agent,
-        agent_name,
+        agent_name.clone(),
         request.project,
+        TaskSchedule {
+            depends_on: request.depends_on,
+            due_at: request.due_at,
+            recurrence: request.recurrence,
+            idempotency_key: Some(idempotency_key),
+            attachments: request.attachments,
+            budget: request.budget,
+            dry_run: request.dry_run,
+        },
         None, // No AI enhancement at API level - this should be handled by the agent's todo processor
-    ).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    ).await?;
+
+    state.events.publish(Event::task_created(&agent_name, &task));
+
+    Ok(Json(TaskResponse::from(task)))
+}
+
+// Cancel a pending task, or signal a running one to discard its result
+pub async fn cancel_task(
+    State(state): State<Arc<AppState>>,
+    Path((agent_name, task_id)): Path<(String, String)>,
+) -> Result<Json<TaskResponse>, SwarmError> {
+    let handle = agent_handle(&state, &agent_name).await?;
+    let agent = handle.read().await;
+
+    let todo_list = <dyn Agent>::get_todo_list(&*agent)
+        .ok_or_else(|| SwarmError::Unsupported(format!("Agent '{}' has no todo list", agent_name)))?;
+
+    let task = todo_list.get_task(&task_id).await?
+        .ok_or_else(|| SwarmError::NotFound(format!("Task '{}'", task_id)))?;
+
+    if !task.status.can_cancel() {
+        return Err(SwarmError::Conflict(format!("Task '{}' is {:?} and cannot be cancelled", task_id, task.status)));
+    }
+
+    let task = TodoProcessor::cancel_task(&*agent, &task_id).await
+        .map_err(|e| SwarmError::Conflict(e.to_string()))?;
+
+    state.events.publish(Event::task_cancelled(&agent_name, &task));
+
+    Ok(Json(TaskResponse::from(task)))
+}
+
+// Re-queue a failed or cancelled task
+pub async fn retry_task(
+    State(state): State<Arc<AppState>>,
+    Path((agent_name, task_id)): Path<(String, String)>,
+) -> Result<Json<TaskResponse>, SwarmError> {
+    let handle = agent_handle(&state, &agent_name).await?;
+    let agent = handle.read().await;
+
+    let todo_list = <dyn Agent>::get_todo_list(&*agent)
+        .ok_or_else(|| SwarmError::Unsupported(format!("Agent '{}' has no todo list", agent_name)))?;
+
+    let task = todo_list.get_task(&task_id).await?
+        .ok_or_else(|| SwarmError::NotFound(format!("Task '{}'", task_id)))?;
+
+    if !task.status.can_retry() {
+        return Err(SwarmError::Conflict(format!("Task '{}' is {:?} and cannot be retried", task_id, task.status)));
+    }
+
+    let task = TodoProcessor::retry_task(&*agent, &task_id).await
+        .map_err(|e| SwarmError::Conflict(e.to_string()))?;
+
+    state.events.publish(Event::task_requeued(&agent_name, &task));
+
+    Ok(Json(TaskResponse::from(task)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnswerTaskRequest {
+    pub answer: String,
+}
+
+// Answer the questions a task is parked on and put it back in the queue
+pub async fn answer_task(
+    State(state): State<Arc<AppState>>,
+    Path((agent_name, task_id)): Path<(String, String)>,
+    Json(request): Json<AnswerTaskRequest>,
+) -> Result<Json<TaskResponse>, SwarmError> {
+    if request.answer.trim().is_empty() {
+        return Err(SwarmError::Validation("Answer must not be empty".to_string()));
+    }
+
+    let handle = agent_handle(&state, &agent_name).await?;
+    let agent = handle.read().await;
+
+    let todo_list = <dyn Agent>::get_todo_list(&*agent)
+        .ok_or_else(|| SwarmError::Unsupported(format!("Agent '{}' has no todo list", agent_name)))?;
+
+    let task = todo_list.get_task(&task_id).await?
+        .ok_or_else(|| SwarmError::NotFound(format!("Task '{}'", task_id)))?;
+
+    if task.status != TaskStatus::WaitingForInput {
+        return Err(SwarmError::Conflict(format!("Task '{}' is {:?} and not waiting for input", task_id, task.status)));
+    }
+
+    let task = TodoProcessor::answer_task(&*agent, &task_id, request.answer.trim()).await
+        .map_err(|e| SwarmError::Conflict(e.to_string()))?;
+
+    state.events.publish(Event::task_requeued(&agent_name, &task));
 
     Ok(Json(TaskResponse::from(task)))
 }
 
+#[derive(Debug, Deserialize, Default)]
+pub struct InboxDecisionRequest {
+    /// Reason recorded with an operation's approval or rejection
+    pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InboxEditRequest {
+    /// New description for a task
+    pub description: Option<String>,
+    /// Params to change on an operation before it runs
+    #[serde(default)]
+    pub params: HashMap<String, String>,
+}
+
+/// The `user` agent's todo list, when it is registered
+async fn user_todo_list(state: &AppState) -> Option<TodoList> {
+    let handle = state.agents.read().await.get(USER_AGENT)?;
+    let agent = handle.read().await;
+    <dyn Agent>::get_todo_list(&*agent).cloned()
+}
+
+/// The user's task `id` and the list it is on; it must still be waiting for a decision
+async fn waiting_user_task(state: &AppState, id: &str) -> Result<(TodoList, TodoTask), SwarmError> {
+    let not_found = || SwarmError::NotFound(format!("Inbox item '{}'", id));
+    let todo_list = user_todo_list(state).await.ok_or_else(not_found)?;
+    let task = todo_list.get_task(id).await?
+        .filter(|task| task.target_agent == USER_AGENT)
+        .ok_or_else(not_found)?;
+    if task.status != TaskStatus::WaitingForInput {
+        return Err(SwarmError::Conflict(format!("Task '{}' is {:?} and not waiting for a decision", id, task.status)));
+    }
+    Ok((todo_list, task))
+}
+
+fn already_decided(id: &str) -> SwarmError {
+    SwarmError::Conflict(format!("Inbox item '{}' has already been decided", id))
+}
+
+// Operations waiting for approval, then tasks routed to the user
+pub async fn get_inbox(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<InboxEntry>>, SwarmError> {
+    let mut entries: Vec<InboxEntry> = state.inbox.pending().into_iter().map(InboxEntry::Operation).collect();
+
+    let todo_list = user_todo_list(&state).await;
+    if let Some(todo_list) = todo_list {
+        let tasks = todo_list.get_waiting_tasks(USER_AGENT).await?;
+        entries.extend(tasks.into_iter().map(|task| InboxEntry::Task(TaskResponse::from(task))));
+    }
+
+    Ok(Json(entries))
+}
+
+// Let a held operation run, or mark a user task done
+pub async fn approve_inbox_item(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    request: Option<Json<InboxDecisionRequest>>,
+) -> Result<Json<InboxEntry>, SwarmError> {
+    if state.inbox.get(&id).is_some() {
+        let note = request.and_then(|Json(request)| request.note);
+        let approval = state.inbox.approve(&id, note).ok_or_else(|| already_decided(&id))?;
+        return Ok(Json(InboxEntry::Operation(approval)));
+    }
+
+    let (todo_list, _) = waiting_user_task(&state, &id).await?;
+    if !todo_list.mark_task_completed(&id).await? {
+        return Err(already_decided(&id));
+    }
+    let task = todo_list.get_task(&id).await?
+        .ok_or_else(|| SwarmError::NotFound(format!("Inbox item '{}'", id)))?;
+    state.events.publish(Event::task_completed(USER_AGENT, &task));
+
+    Ok(Json(InboxEntry::Task(TaskResponse::from(task))))
+}
+
+// Refuse a held operation, or cancel a user task
+pub async fn reject_inbox_item(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    request: Option<Json<InboxDecisionRequest>>,
+) -> Result<Json<InboxEntry>, SwarmError> {
+    if state.inbox.get(&id).is_some() {
+        let note = request.and_then(|Json(request)| request.note);
+        let approval = state.inbox.reject(&id, note).ok_or_else(|| already_decided(&id))?;
+        return Ok(Json(InboxEntry::Operation(approval)));
+    }
+
+    let (todo_list, _) = waiting_user_task(&state, &id).await?;
+    let task = todo_list.cancel_task(&id).await?.ok_or_else(|| already_decided(&id))?;
+    state.events.publish(Event::task_cancelled(USER_AGENT, &task));
+
+    Ok(Json(InboxEntry::Task(TaskResponse::from(task))))
+}
+
+// Change what a held operation runs with, or reword a user task, before deciding
+pub async fn edit_inbox_item(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(request): Json<InboxEditRequest>,
+) -> Result<Json<InboxEntry>, SwarmError> {
+    if state.inbox.get(&id).is_some() {
+        if request.params.is_empty() {
+            return Err(SwarmError::Validation("Editing an operation needs params".to_string()));
+        }
+        let approval = state.inbox.edit(&id, request.params).ok_or_else(|| already_decided(&id))?;
+        return Ok(Json(InboxEntry::Operation(approval)));
+    }
+
+    let description = request.description
+        .map(|description| description.trim().to_string())
+        .filter(|description| !description.is_empty())
+        .ok_or_else(|| SwarmError::Validation("Editing a task needs a description".to_string()))?;
+    let (todo_list, _) = waiting_user_task(&state, &id).await?;
+    let set = mongodb::bson::doc! {
+        "description": description,
+        "enhanced_description": mongodb::bson::Bson::Null,
+        "last_modified": chrono::Utc::now().timestamp()
+    };
+    let task = todo_list.update_task(&id, set).await?
+        .ok_or_else(|| SwarmError::NotFound(format!("Inbox item '{}'", id)))?;
+
+    Ok(Json(InboxEntry::Task(TaskResponse::from(task))))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchiveQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+// Browse archived haikus, newest first
+#[cfg(feature = "haiku-agent")]
+pub async fn get_haiku_archive(
+    Query(query): Query<ArchiveQuery>,
+) -> Result<Json<Vec<crate::agents::haiku::HaikuEntry>>, SwarmError> {
+    let path = crate::agents::haiku::HaikuMemory::default_path();
+    let memory = crate::agents::haiku::HaikuMemory::load(path)?;
+
+    Ok(Json(memory.archive(query.offset.unwrap_or(0), query.limit.unwrap_or(20).min(100))))
+}
+
+fn agent_not_found(name: &str) -> SwarmError {
+    SwarmError::NotFound(format!("Agent '{}'", name))
+}
+
+/// `name`'s handle, taken so the registry lock is released before the
+/// handler waits on the agent
+async fn agent_handle(state: &AppState, name: &str) -> Result<AgentHandle, SwarmError> {
+    state.agents.read().await.get(name).ok_or_else(|| agent_not_found(name))
+}
+
+fn publish_state_change(state: &AppState, agent_name: &str, response: &Message) {
+    if let Some(agent_state) = response.metadata.as_ref().and_then(|m| m.state.clone()) {
+        state.events.publish(Event::StateChanged { agent: agent_name.to_string(), state: agent_state });
+    }
+}
+
+// Audit history of a todo, oldest change first
+pub async fn get_todo_history(
+    State(state): State<Arc<AppState>>,
+    Path(todo_id): Path<String>,
+) -> Result<Json<Vec<LogEntry>>, SwarmError> {
+    let audit_log = state.audit_log.as_ref()
+        .ok_or_else(|| SwarmError::Unsupported("Todo audit log is not configured".to_string()))?;
+    Ok(Json(audit_log.history(&todo_id).await?))
+}
+
+// Persisted worker metrics snapshots, oldest first, for trend graphs
+pub async fn get_metrics_history(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<MetricsHistoryQuery>,
+) -> Result<Json<Vec<MetricsSnapshot>>, SwarmError> {
+    let store = state.metrics_store.as_ref()
+        .ok_or_else(|| SwarmError::Unsupported("Worker metrics history is not configured".to_string()))?;
+    Ok(Json(store.history(&query).await?))
+}
+
+// Agent invocations matching the query, oldest first
+pub async fn get_audit_log(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, SwarmError> {
+    let log = state.agent_audit.as_ref()
+        .ok_or_else(|| SwarmError::Unsupported("Agent audit trail is not configured".to_string()))?;
+    Ok(Json(log.query(&query).await?))
+}
+
+// Counts of events seen on the in-process event bus, by type
+pub async fn get_event_metrics(
+    State(state): State<Arc<AppState>>,
+) -> Json<HashMap<String, u64>> {
+    Json(state.event_metrics.snapshot().await)
+}
+
+// Nodes announcing themselves on the broker, stale ones included
+pub async fn list_swarm_nodes(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<Vec<NodeStatus>>, SwarmError> {
+    let directory = state.directory.as_ref()
+        .ok_or_else(|| SwarmError::Unsupported("Swarm discovery needs an MQTT broker".to_string()))?;
+    Ok(Json(directory.nodes().await))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StateHistoryQuery {
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AgentStateResponse {
+    pub state: Option<PersistedState>,
+    /// One page of transitions, newest first
+    pub transitions: Vec<StateTransition>,
+    pub total_transitions: usize,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ForceTransitionRequest {
+    pub to_state: String,
+    /// Recorded in the transition's trigger as `operator:<reason>`
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Respond 409 if the agent has moved past this version
+    #[serde(default)]
+    pub expected_version: Option<i32>,
+    /// Replaces the state data; kept when omitted
+    #[serde(default)]
+    pub state_data: Option<serde_json::Value>,
+}
+
+fn state_store(state: &AppState) -> Result<&Arc<dyn StateStore>, SwarmError> {
+    state.state_store.as_ref()
+        .ok_or_else(|| SwarmError::Unsupported("Agent state store is not configured".to_string()))
+}
+
+fn state_error(e: anyhow::Error) -> SwarmError {
+    match VersionConflict::from_error(&e) {
+        Some(conflict) => SwarmError::Conflict(conflict.to_string()),
+        None => SwarmError::State(e.to_string()),
+    }
+}
+
+async fn record_operator_transition(
+    store: &dyn StateStore,
+    agent_name: &str,
+    from_state: &str,
+    to_state: &str,
+    trigger: String,
+) -> Result<(), SwarmError> {
+    // Replay rejects self-transitions, so a no-op move isn't recorded
+    if from_state == to_state {
+        return Ok(());
+    }
+    store.record_transition(StateTransition {
+        id: uuid::Uuid::new_v4().to_string(),
+        agent_id: agent_name.to_string(),
+        from_state: from_state.to_string(),
+        to_state: to_state.to_string(),
+        trigger,
+        timestamp: chrono::Utc::now(),
+        success: true,
+        error: None,
+    }).await.map_err(state_error)
+}
+
+// An agent's persisted state and a page of its transitions, newest first
+pub async fn get_agent_state(
+    State(state): State<Arc<AppState>>,
+    Path(agent_name): Path<String>,
+    Query(query): Query<StateHistoryQuery>,
+) -> Result<Json<AgentStateResponse>, SwarmError> {
+    let store = state_store(&state)?;
+    let current = store.load_state(&agent_name).await.map_err(state_error)?;
+    let mut transitions = store.get_transitions(&agent_name).await.map_err(state_error)?;
+    if current.is_none() && transitions.is_empty() {
+        return Err(SwarmError::NotFound(format!("State for agent '{}'", agent_name)));
+    }
+
+    transitions.reverse();
+    let total_transitions = transitions.len();
+    let offset = query.offset.unwrap_or(0);
+    let limit = query.limit.unwrap_or(50).min(500);
+    Ok(Json(AgentStateResponse {
+        state: current,
+        transitions: transitions.into_iter().skip(offset).take(limit).collect(),
+        total_transitions,
+        offset,
+        limit,
+    }))
+}
+
+// Restore an agent's newest checkpoint as a new version of its state
+pub async fn rollback_agent_state(
+    State(state): State<Arc<AppState>>,
+    Path(agent_name): Path<String>,
+) -> Result<Json<PersistedState>, SwarmError> {
+    let store = state_store(&state)?;
+    let checkpoint = store.rollback_to_checkpoint(&agent_name).await.map_err(state_error)?
+        .ok_or_else(|| SwarmError::NotFound(format!("Checkpoint for agent '{}'", agent_name)))?;
+    let current = store.load_state(&agent_name).await.map_err(state_error)?;
+    let (from_state, current_version) = current.map_or((String::new(), 0), |s| (s.state_name, s.version));
+
+    let mut restored = PersistedState { updated_at: chrono::Utc::now(), ..checkpoint.clone() };
+    restored.metadata.insert("rolled_back_to".to_string(), serde_json::json!(checkpoint.version));
+    let saved = store.save_state_if(restored, current_version).await.map_err(state_error)?;
+    if !from_state.is_empty() {
+        record_operator_transition(store.as_ref(), &agent_name, &from_state, &saved.state_name, "operator:rollback".to_string()).await?;
+    }
+
+    state.events.publish(Event::StateTransitioned {
+        agent: agent_name,
+        from: from_state,
+        to: saved.state_name.clone(),
+        trigger: "operator:rollback".to_string(),
+    });
+    Ok(Json(saved))
+}
+
+// Move an agent to a named state outside its state machine, for operator recovery
+pub async fn force_agent_transition(
+    State(state): State<Arc<AppState>>,
+    Path(agent_name): Path<String>,
+    Json(request): Json<ForceTransitionRequest>,
+) -> Result<Json<PersistedState>, SwarmError> {
+    let store = state_store(&state)?;
+    let current = store.load_state(&agent_name).await.map_err(state_error)?
+        .ok_or_else(|| SwarmError::NotFound(format!("State for agent '{}'", agent_name)))?;
+    // A stale caller is told so before its transition is judged against a state it never saw
+    let expected_version = request.expected_version.unwrap_or(current.version);
+    if expected_version != current.version {
+        let conflict = VersionConflict { agent_id: agent_name, expected: expected_version, actual: current.version };
+        return Err(SwarmError::Conflict(conflict.to_string()));
+    }
+    BasicStateValidator.validate_transition(&current.state_name, &request.to_state)
+        .map_err(|e| SwarmError::Validation(e.to_string()))?;
+
+    let reason = request.reason.unwrap_or_else(|| "forced".to_string());
+    let mut next = PersistedState {
+        state_name: request.to_state,
+        state_data: request.state_data.or_else(|| current.state_data.clone()),
+        updated_at: chrono::Utc::now(),
+        ..current.clone()
+    };
+    next.metadata.insert("forced_transition".to_string(), serde_json::json!({
+        "from": current.state_name,
+        "reason": reason,
+    }));
+    BasicStateValidator.validate_state(&next).map_err(|e| SwarmError::Validation(e.to_string()))?;
+
+    let saved = store.save_state_if(next, expected_version).await.map_err(state_error)?;
+    let trigger = format!("operator:{}", reason);
+    record_operator_transition(store.as_ref(), &agent_name, &current.state_name, &saved.state_name, trigger.clone()).await?;
+
+    state.events.publish(Event::StateTransitioned {
+        agent: agent_name,
+        from: current.state_name,
+        to: saved.state_name.clone(),
+        trigger,
+    });
+    Ok(Json(saved))
+}
+
+// Start a training run as a background job
+#[cfg(feature = "rl")]
+pub async fn start_rl_run(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<crate::agents::rl::RunRequest>,
+) -> Result<Json<crate::agents::rl::RunInfo>, SwarmError> {
+    Ok(Json(state.rl_runs.start(request)?))
+}
+
+// Training runs started on this server with their status and latest metrics, newest first
+#[cfg(feature = "rl")]
+pub async fn list_rl_runs(
+    State(state): State<Arc<AppState>>,
+) -> Json<Vec<crate::agents::rl::RunInfo>> {
+    Json(state.rl_runs.list())
+}
+
+#[cfg(feature = "rl")]
+pub async fn get_rl_run(
+    State(state): State<Arc<AppState>>,
+    Path(run_id): Path<String>,
+) -> Result<Json<crate::agents::rl::RunInfo>, SwarmError> {
+    state.rl_runs.get(&run_id)
+        .map(Json)
+        .ok_or_else(|| SwarmError::NotFound(format!("Training run '{}'", run_id)))
+}
+
+// Stop a running training run before its next episode
+#[cfg(feature = "rl")]
+pub async fn cancel_rl_run(
+    State(state): State<Arc<AppState>>,
+    Path(run_id): Path<String>,
+) -> Result<Json<crate::agents::rl::RunInfo>, SwarmError> {
+    Ok(Json(state.rl_runs.cancel(&run_id)?))
+}
+
+// Checkpoints, metrics and reports a training run has written
+#[cfg(feature = "rl")]
+pub async fn list_rl_run_artifacts(
+    State(state): State<Arc<AppState>>,
+    Path(run_id): Path<String>,
+) -> Result<Json<Vec<crate::agents::rl::RunArtifact>>, SwarmError> {
+    Ok(Json(state.rl_runs.artifacts(&run_id)?))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::types::{Message, State, StateMachine, AgentStateManager, TodoProcessor, TodoTask};
     use crate::types::todo::{TaskStatus, TaskPriority, TodoList};
     use std::time::Duration;
-    use futures::executor::block_on;
     use crate::agents::{AgentRegistry, GreeterAgent, TransferService};
     use mongodb::{Client, Collection};
     use std::sync::Arc;
@@ -332,26 +920,26 @@ mod tests {
 
     #[async_trait]
     impl Agent for TestAgent {
-        async fn process_message(&self, message: Message) -> Result<Message, anyhow::Error> {
+        async fn process_message(&self, message: Message) -> crate::Result<Message> {
             Ok(Message::new("Test response".to_string()))
         }
 
-        async fn transfer_to(&self, target_agent: String, message: Message) -> Result<Message, anyhow::Error> {
+        async fn transfer_to(&self, target_agent: String, message: Message) -> crate::Result<Message> {
             if !self.config.downstream_agents.contains(&target_agent) {
-                return Err(anyhow!("Cannot transfer to unknown agent: {}", target_agent));
+                return Err(SwarmError::NotFound(format!("Cannot transfer to unknown agent: {}", target_agent)));
             }
             Ok(Message::new(format!("Transferring to {}", target_agent)))
         }
 
-        async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String, anyhow::Error> {
+        async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> crate::Result<String> {
             Ok("Tool called".to_string())
         }
 
-        async fn get_current_state(&self) -> Result<Option<State>, anyhow::Error> {
+        async fn get_current_state(&self) -> crate::Result<Option<State>> {
             Ok(None)
         }
 
-        async fn get_config(&self) -> Result<AgentConfig, anyhow::Error> {
+        async fn get_config(&self) -> crate::Result<AgentConfig> {
             Ok(self.config.clone())
         }
 
@@ -416,8 +1004,8 @@ mod tests {
         let registry = Arc::new(RwLock::new(registry));
         let transfer_service = Arc::new(RwLock::new(crate::agents::TransferService::new(registry.clone())));
         let state = Arc::new(AppState {
-            transfer_service,
             agents: registry,
+            ..AppState::new(transfer_service)
         });
 
         // Test 1: Add a task with AI enhancement
@@ -426,12 +1014,19 @@ mod tests {
             priority: TaskPriority::High,
             source_agent: Some("user".to_string()),
             project: None,
+            depends_on: Vec::new(),
+            due_at: None,
+            recurrence: None,
+            idempotency_key: None,
+            attachments: Vec::new(),
+            budget: None,
+            dry_run: false,
         };
 
         let response = add_task(
             State(state.clone()),
             Path("test_agent".to_string()),
-            Json(add_request.clone()),
+            JsonOrMultipart(add_request.clone()),
         ).await.map_err(|e| anyhow!("Failed to add task: {:?}", e))?;
 
         let task_id = response.0.id.clone();
@@ -449,6 +1044,13 @@ mod tests {
             priority: TaskPriority::Low,
             source_agent: None,
             project: None,
+            depends_on: Vec::new(),
+            due_at: None,
+            recurrence: None,
+            idempotency_key: None,
+            attachments: Vec::new(),
+            budget: None,
+            dry_run: false,
         };
 
         let medium_priority_task = AddTaskRequest {
@@ -456,24 +1058,32 @@ mod tests {
             priority: TaskPriority::Medium,
             source_agent: None,
             project: None,
+            depends_on: Vec::new(),
+            due_at: None,
+            recurrence: None,
+            idempotency_key: None,
+            attachments: Vec::new(),
+            budget: None,
+            dry_run: false,
         };
 
         add_task(
             State(state.clone()),
             Path("test_agent".to_string()),
-            Json(low_priority_task),
+            JsonOrMultipart(low_priority_task),
         ).await.map_err(|e| anyhow!("Failed to add low priority task: {:?}", e))?;
 
         add_task(
             State(state.clone()),
             Path("test_agent".to_string()),
-            Json(medium_priority_task),
+            JsonOrMultipart(medium_priority_task),
         ).await.map_err(|e| anyhow!("Failed to add medium priority task: {:?}", e))?;
 
         // Test 3: Get all tasks and verify ordering
         let tasks = get_tasks(
             State(state.clone()),
             Path("test_agent".to_string()),
+            Query(TaskListQuery::default()),
         ).await.map_err(|e| anyhow!("Failed to get tasks: {:?}", e))?;
 
         assert_eq!(tasks.0.len(), 3);
@@ -495,17 +1105,17 @@ mod tests {
         ).await;
 
         assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), StatusCode::NOT_FOUND);
+        assert_eq!(result.unwrap_err().status_code(), StatusCode::NOT_FOUND);
 
         // Test 6: Error handling for non-existent agent
         let result = add_task(
             State(state.clone()),
             Path("non-existent".to_string()),
-            Json(add_request),
+            JsonOrMultipart(add_request),
         ).await;
 
         assert!(result.is_err());
-        assert_eq!(result.unwrap_err(), StatusCode::NOT_FOUND);
+        assert_eq!(result.unwrap_err().status_code(), StatusCode::NOT_FOUND);
 
         // Test 7: Task delegation between agents
         let delegated_task = AddTaskRequest {
@@ -513,20 +1123,78 @@ mod tests {
             priority: TaskPriority::Medium,
             source_agent: Some("test_agent".to_string()),
             project: None,
+            depends_on: Vec::new(),
+            due_at: None,
+            recurrence: None,
+            idempotency_key: None,
+            attachments: Vec::new(),
+            budget: None,
+            dry_run: false,
         };
 
         let response = add_task(
             State(state.clone()),
             Path("haiku".to_string()),
-            Json(delegated_task),
+            JsonOrMultipart(delegated_task),
         ).await;
 
         assert!(response.is_err()); // Should fail since haiku agent isn't registered
-        assert_eq!(response.unwrap_err(), StatusCode::NOT_FOUND);
+        assert_eq!(response.unwrap_err().status_code(), StatusCode::NOT_FOUND);
 
         // Clean up test database
         db.collection::<TodoTask>("todos").drop(None).await?;
 
         Ok(())
     }
+
+    #[cfg(feature = "embedded-state")]
+    #[tokio::test]
+    async fn test_agent_state_routes() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = Arc::new(crate::state::SledStateStore::open(dir.path())?);
+        let registry = Arc::new(RwLock::new(AgentRegistry::new()));
+        let transfer_service = Arc::new(RwLock::new(crate::agents::TransferService::new(registry)));
+        let state = Arc::new(AppState::new(transfer_service).with_state_store(store.clone()));
+
+        let now = chrono::Utc::now();
+        let initial = PersistedState {
+            agent_id: "greeter".to_string(),
+            state_name: "idle".to_string(),
+            state_data: None,
+            conversation_context: vec![],
+            created_at: now,
+            updated_at: now,
+            version: 1,
+            metadata: HashMap::new(),
+        };
+        store.save_state(initial.clone()).await?;
+        store.create_checkpoint(&initial).await?;
+
+        let force = |expected_version| ForceTransitionRequest {
+            to_state: "stuck".to_string(),
+            reason: Some("test".to_string()),
+            expected_version,
+            state_data: None,
+        };
+        let forced = force_agent_transition(axum::extract::State(state.clone()), Path("greeter".to_string()), Json(force(None))).await?;
+        assert_eq!((forced.0.state_name.as_str(), forced.0.version), ("stuck", 2));
+        let stale = force_agent_transition(axum::extract::State(state.clone()), Path("greeter".to_string()), Json(force(Some(1)))).await;
+        assert!(matches!(stale, Err(SwarmError::Conflict(_))));
+
+        let restored = rollback_agent_state(axum::extract::State(state.clone()), Path("greeter".to_string())).await?;
+        assert_eq!((restored.0.state_name.as_str(), restored.0.version), ("idle", 3));
+
+        let page = get_agent_state(
+            axum::extract::State(state.clone()),
+            Path("greeter".to_string()),
+            Query(StateHistoryQuery { offset: Some(0), limit: Some(1) }),
+        ).await?;
+        assert_eq!(page.0.state.map(|s| s.version), Some(3));
+        assert_eq!(page.0.total_transitions, 2);
+        assert_eq!(page.0.transitions[0].trigger, "operator:rollback");
+
+        let missing = get_agent_state(axum::extract::State(state), Path("nobody".to_string()), Query(StateHistoryQuery { offset: None, limit: None })).await;
+        assert!(matches!(missing, Err(SwarmError::NotFound(_))));
+        Ok(())
+    }
 }
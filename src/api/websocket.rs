@@ -9,8 +9,9 @@ use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
 use crate::{
     api::AppState,
+    events::Event,
     agents::{AgentRegistry, TransferService, GreeterAgent},
-    types::{AgentConfig, Tool, Message},
+    types::{AgentConfig, Tool, Message, ResponseFormat},
 };
 
 #[cfg(feature = "haiku-agent")]
@@ -26,7 +27,12 @@ const CHANNEL_SIZE: usize = 32;
 #[serde(tag = "type", content = "data")]
 pub enum ClientMessage {
     Connect { agent: String },
-    Message { content: String },
+    Message {
+        content: String,
+        /// `json` for a `Structured` reply instead of a `Message`
+        #[serde(default)]
+        response_format: ResponseFormat,
+    },
     Transfer { from: String, to: String },
     UpdateSession {
         instructions: String,
@@ -49,62 +55,108 @@ pub struct TurnDetection {
 pub enum ServerMessage {
     Connected { agent: String },
     Message { content: String },
+    /// Reply to a message sent with `response_format: json`
+    Structured { payload: Value },
     Error { message: String },
     Transferred { from: String, to: String },
     SessionUpdated,
+    Event(Event),
 }
 
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
 ) -> Response {
-    ws.on_upgrade(|socket| handle_socket(socket, state))
+    // The socket outlives this request, so carry its caller over explicitly
+    let principal = crate::access::current_principal();
+    ws.on_upgrade(move |socket| async move {
+        match principal {
+            Some(principal) => crate::access::with_principal(principal, handle_socket(socket, state)).await,
+            None => handle_socket(socket, state).await,
+        }
+    })
 }
 
 async fn handle_socket(socket: axum::extract::ws::WebSocket, state: Arc<AppState>) {
     let (mut sender, mut receiver) = socket.split();
-
-    while let Some(Ok(msg)) = receiver.next().await {
-        if let WsMessage::Text(content) = msg {
-            let response = match serde_json::from_str::<ClientMessage>(&content) {
-                Ok(client_msg) => {
-                    match handle_client_message(client_msg, state.clone()).await {
-                        Ok(server_msg) => {
-                            match serde_json::to_string(&server_msg) {
-                                Ok(json) => WsMessage::Text(json),
-                                Err(_) => WsMessage::Text("Error serializing response".to_string()),
+    let mut events = state.events.subscribe();
+    let mut relayed = state.relayed_events.subscribe();
+    // Agents that keep per-caller state, like the git assistant, key it by connection
+    let session = uuid::Uuid::new_v4().to_string();
+
+    loop {
+        let response = tokio::select! {
+            msg = receiver.next() => match msg {
+                Some(Ok(WsMessage::Text(content))) => {
+                    match serde_json::from_str::<ClientMessage>(&content) {
+                        Ok(client_msg) => {
+                            match handle_client_message(client_msg, state.clone(), &session).await {
+                                Ok(server_msg) => {
+                                    match serde_json::to_string(&server_msg) {
+                                        Ok(json) => WsMessage::Text(json),
+                                        Err(_) => WsMessage::Text("Error serializing response".to_string()),
+                                    }
+                                },
+                                Err(e) => WsMessage::Text(format!("Error: {}", e)),
                             }
                         },
-                        Err(e) => WsMessage::Text(format!("Error: {}", e)),
+                        Err(_) => WsMessage::Text("Invalid message format".to_string()),
                     }
+                }
+                Some(Ok(_)) => continue,
+                _ => break,
+            },
+            event = events.recv() => match event {
+                Ok(event) => match serde_json::to_string(&ServerMessage::Event(event)) {
+                    Ok(json) => WsMessage::Text(json),
+                    Err(_) => continue,
+                },
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            event = relayed.recv() => match event {
+                Ok(event) => match serde_json::to_string(&ServerMessage::Event(event)) {
+                    Ok(json) => WsMessage::Text(json),
+                    Err(_) => continue,
                 },
-                Err(_) => WsMessage::Text("Invalid message format".to_string()),
-            };
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+        };
 
-            if sender.send(response).await.is_err() {
-                break;
-            }
+        if sender.send(response).await.is_err() {
+            break;
         }
     }
 }
 
-async fn handle_client_message(msg: ClientMessage, state: Arc<AppState>) -> Result<ServerMessage, String> {
+async fn handle_client_message(msg: ClientMessage, state: Arc<AppState>, session: &str) -> Result<ServerMessage, String> {
     match msg {
         ClientMessage::Connect { agent } => {
             let mut transfer_service = state.transfer_service.write().await;
             transfer_service.set_current_agent_name(&agent).await.map_err(|e| e.to_string())?;
             Ok(ServerMessage::Connected { agent })
         },
-        ClientMessage::Message { content } => {
+        ClientMessage::Message { content, response_format } => {
             let transfer_service = state.transfer_service.read().await;
-            match transfer_service.process_message(Message::new(content)).await {
-                Ok(response) => Ok(ServerMessage::Message { content: response.content }),
-                Err(e) => Err(e.to_string()),
+            let message = Message::new(content).with_session(session).with_response_format(response_format);
+            let response = transfer_service.process_message(message).await
+                .map_err(|e| e.to_string())?;
+            // The learned router may have handed the message on, so ask who answered afterwards
+            let agent = transfer_service.get_current_agent_name().await.unwrap_or_default();
+            let response = state.output_filter.check(&agent, response).await.map_err(|e| e.to_string())?;
+            match response_format {
+                ResponseFormat::Text => Ok(ServerMessage::Message { content: response.content }),
+                ResponseFormat::Json => {
+                    let payload = state.structured_output.render(&agent, &response).await.map_err(|e| e.to_string())?;
+                    Ok(ServerMessage::Structured { payload })
+                }
             }
         },
         ClientMessage::Transfer { from, to } => {
             let mut transfer_service = state.transfer_service.write().await;
             transfer_service.set_current_agent_name(&to).await.map_err(|e| e.to_string())?;
+            state.events.publish(Event::AgentTransferred { from: from.clone(), to: to.clone() });
             Ok(ServerMessage::Transferred { from, to })
         },
         ClientMessage::UpdateSession { instructions, tools, turn_detection } => {
@@ -160,6 +212,7 @@ async fn handle_message(text: String, transfer_service: &Arc<RwLock<TransferServ
                 .ok_or_else(|| anyhow!("Missing content field"))?;
 
             let current_agent = transfer_service.get_current_agent_name().await?;
+            crate::access::AccessPolicy::shared().check_agent(&current_agent)?;
             let agent = transfer_service.get_agent(&current_agent).await?;
             let response = agent.process_message(Message::new(content.to_string())).await?;
 
@@ -227,8 +280,8 @@ mod tests {
 
         let registry = Arc::new(RwLock::new(registry));
         Arc::new(AppState {
-            transfer_service: Arc::new(RwLock::new(TransferService::new(registry.clone()))),
-            agents: registry,
+            agents: registry.clone(),
+            ..AppState::new(Arc::new(RwLock::new(TransferService::new(registry))))
         })
     }
 
@@ -239,7 +292,7 @@ mod tests {
             agent: "greeter".to_string(),
         };
 
-        let response = handle_client_message(msg, state).await;
+        let response = handle_client_message(msg, state, "test").await;
         match response {
             Ok(ServerMessage::Connected { agent }) => {
                 assert_eq!(agent, "greeter");
@@ -256,14 +309,15 @@ mod tests {
         let connect_msg = ClientMessage::Connect {
             agent: "greeter".to_string(),
         };
-        handle_client_message(connect_msg, state.clone()).await.expect("Failed to connect");
+        handle_client_message(connect_msg, state.clone(), "test").await.expect("Failed to connect");
 
         // Then send a message
         let msg = ClientMessage::Message {
             content: "hi".to_string(),
+            response_format: ResponseFormat::Text,
         };
 
-        let response = handle_client_message(msg, state).await;
+        let response = handle_client_message(msg, state, "test").await;
         match response {
             Ok(ServerMessage::Message { content }) => {
                 assert!(!content.is_empty());
@@ -272,6 +326,29 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_handle_structured_message() {
+        let state = setup_test_state().await;
+        let connect_msg = ClientMessage::Connect {
+            agent: "greeter".to_string(),
+        };
+        handle_client_message(connect_msg, state.clone(), "test").await.expect("Failed to connect");
+
+        // Asking for poetry is answered without the AI backend
+        let msg = ClientMessage::Message {
+            content: "write me some poetry".to_string(),
+            response_format: ResponseFormat::Json,
+        };
+        match handle_client_message(msg, state, "test").await {
+            Ok(ServerMessage::Structured { payload }) => {
+                assert_eq!(payload["action"], "reply");
+                assert_eq!(payload["metadata"]["agent"], "greeter");
+                assert_eq!(payload["metadata"]["transfer_target"], "haiku");
+            }
+            other => panic!("Expected Structured response, got {:?}", other),
+        }
+    }
+
     #[cfg(feature = "haiku-agent")]
     #[tokio::test]
     async fn test_handle_transfer() {
@@ -281,14 +358,15 @@ mod tests {
         let connect_msg = ClientMessage::Connect {
             agent: "greeter".to_string(),
         };
-        let response = handle_client_message(connect_msg, state.clone()).await.unwrap();
+        let response = handle_client_message(connect_msg, state.clone(), "test").await.unwrap();
         assert!(matches!(response, ServerMessage::Connected { agent } if agent == "greeter"));
 
         // Test 2: Send a message to establish context
         let context_msg = ClientMessage::Message {
             content: "I want to write a haiku about coding".to_string(),
+            response_format: ResponseFormat::Text,
         };
-        let response = handle_client_message(context_msg, state.clone()).await.unwrap();
+        let response = handle_client_message(context_msg, state.clone(), "test").await.unwrap();
         assert!(matches!(response, ServerMessage::Message { .. }));
 
         // Test 3: Transfer to haiku agent with context
@@ -296,14 +374,15 @@ mod tests {
             from: "greeter".to_string(),
             to: "haiku".to_string(),
         };
-        let response = handle_client_message(transfer_msg, state.clone()).await.unwrap();
+        let response = handle_client_message(transfer_msg, state.clone(), "test").await.unwrap();
         assert!(matches!(response, ServerMessage::Transferred { from, to } if from == "greeter" && to == "haiku"));
 
         // Test 4: Verify haiku agent received context
         let verify_msg = ClientMessage::Message {
             content: "What was I writing about?".to_string(),
+            response_format: ResponseFormat::Text,
         };
-        let response = handle_client_message(verify_msg, state.clone()).await.unwrap();
+        let response = handle_client_message(verify_msg, state.clone(), "test").await.unwrap();
         match response {
             ServerMessage::Message { content } => {
                 assert!(content.contains("coding"), "Context should be preserved after transfer");
@@ -316,21 +395,22 @@ mod tests {
             from: "greeter".to_string(),
             to: "nonexistent".to_string(),
         };
-        let response = handle_client_message(invalid_transfer, state.clone()).await;
+        let response = handle_client_message(invalid_transfer, state.clone(), "test").await;
         assert!(response.is_err(), "Transfer to nonexistent agent should fail");
 
         // Test 6: Test transfer with state preservation
         let connect_msg = ClientMessage::Connect {
             agent: "haiku".to_string(),
         };
-        let response = handle_client_message(connect_msg, state.clone()).await.unwrap();
+        let response = handle_client_message(connect_msg, state.clone(), "test").await.unwrap();
         assert!(matches!(response, ServerMessage::Connected { agent } if agent == "haiku"));
 
         // Set up state in haiku agent
         let state_msg = ClientMessage::Message {
             content: "nature".to_string(),
+            response_format: ResponseFormat::Text,
         };
-        let response = handle_client_message(state_msg, state.clone()).await.unwrap();
+        let response = handle_client_message(state_msg, state.clone(), "test").await.unwrap();
         assert!(matches!(response, ServerMessage::Message { .. }));
 
         // Transfer back to greeter
@@ -338,14 +418,15 @@ mod tests {
             from: "haiku".to_string(),
             to: "greeter".to_string(),
         };
-        let response = handle_client_message(transfer_msg, state.clone()).await.unwrap();
+        let response = handle_client_message(transfer_msg, state.clone(), "test").await.unwrap();
         assert!(matches!(response, ServerMessage::Transferred { from, to } if from == "haiku" && to == "greeter"));
 
         // Verify state was preserved
         let verify_msg = ClientMessage::Message {
             content: "What was my last topic?".to_string(),
+            response_format: ResponseFormat::Text,
         };
-        let response = handle_client_message(verify_msg, state).await.unwrap();
+        let response = handle_client_message(verify_msg, state, "test").await.unwrap();
         match response {
             ServerMessage::Message { content } => {
                 assert!(content.contains("nature"), "State should be preserved after transfer");
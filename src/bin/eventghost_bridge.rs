@@ -0,0 +1,67 @@
+use std::sync::Arc;
+use anyhow::Result;
+use swarmonomicon::agents::eventghost::{
+    spawn_event_forwarder, spawn_mqtt_listener, spawn_tcp_listener, EventGhostAgent, EventRules,
+    EVENT_TOPIC_PREFIX, INTAKE_TOPIC, SWARM_TOPIC_PREFIX,
+};
+use swarmonomicon::config::SwarmConfig;
+use swarmonomicon::events::{self, EventBus};
+use swarmonomicon::mqtt::MqttService;
+use swarmonomicon::tools::ToolRegistry;
+use swarmonomicon::types::AgentConfig;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    swarmonomicon::telemetry::init_tracing(
+        "eventghost_bridge",
+        tracing::Level::INFO,
+        tracing_subscriber::fmt::format::FmtSpan::NONE,
+    );
+
+    let config = SwarmConfig::from_cli_or_exit();
+
+    let rules = EventRules::from_env()?;
+    if rules.is_empty() {
+        tracing::warn!("No EventGhost rules loaded; set EVENTGHOST_RULES to a rules file");
+    }
+
+    let client = MqttService::connect(config.mqtt_config("eventghost_bridge"));
+    let event_topics = format!("{}/#", EVENT_TOPIC_PREFIX);
+    let swarm_topics = format!("{}/+", SWARM_TOPIC_PREFIX);
+    client.log_topic_map(&[event_topics.as_str(), swarm_topics.as_str(), INTAKE_TOPIC]);
+
+    let mut agent = EventGhostAgent::new(AgentConfig {
+        name: "eventghost".to_string(),
+        public_description: "Bridges EventGhost automation events into the swarm".to_string(),
+        instructions: "Map EventGhost events to swarm actions using the configured rules".to_string(),
+        tools: vec![],
+        downstream_agents: vec![],
+        personality: None,
+        state_machine: None,
+    })
+    .with_rules(rules)
+    .with_mqtt(client.clone());
+
+    match ToolRegistry::create_default_tools().await {
+        Ok(tools) => agent = agent.with_tools(Arc::new(tools)),
+        Err(e) => tracing::warn!("Tools unavailable, run_tool rules will fail: {}", e),
+    }
+    let agent = Arc::new(agent);
+    tracing::info!("EventGhost bridge started with {} rules", agent.rules().len());
+
+    // This process's own tool and state events reach EventGhost through the same topics
+    events::spawn_mqtt_bridge(&EventBus::shared(), client.clone());
+    let _forwarder = spawn_event_forwarder(client.clone()).await?;
+    let _mqtt_listener = spawn_mqtt_listener(agent.clone(), client.clone()).await?;
+    let _tcp_listener = match std::env::var("EVENTGHOST_TCP_ADDR") {
+        Ok(addr) if !addr.is_empty() => Some(spawn_tcp_listener(agent.clone(), &addr).await?),
+        _ => None,
+    };
+
+    tokio::signal::ctrl_c().await?;
+    tracing::info!("Shutting down EventGhost bridge");
+    if let Err(e) = client.disconnect().await {
+        tracing::error!("Error disconnecting from MQTT: {}", e);
+    }
+    Ok(())
+}
@@ -0,0 +1,47 @@
+use clap::{Parser, ValueEnum};
+use anyhow::Result;
+use swarmonomicon::mcp::McpServer;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Transport {
+    /// Newline-delimited JSON-RPC on stdin/stdout, for clients that spawn the server
+    Stdio,
+    /// HTTP with server-sent events
+    Sse,
+}
+
+#[derive(Parser)]
+#[command(author, version, about = "Serve Swarmonomicon tools over the Model Context Protocol", long_about = None)]
+struct Cli {
+    #[arg(short, long, value_enum, default_value = "stdio")]
+    transport: Transport,
+
+    /// Address to listen on for the SSE transport
+    #[arg(short, long, default_value = "127.0.0.1:3100")]
+    addr: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+
+    // stdout carries the protocol on stdio, so logs go to stderr
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(tracing::Level::INFO)
+        .init();
+
+    let server = McpServer::with_default_tools().await?;
+    tracing::info!("Serving {} tools over MCP", server.tools().len());
+
+    match cli.transport {
+        Transport::Stdio => server.serve_stdio().await,
+        Transport::Sse => {
+            let listener = tokio::net::TcpListener::bind(&cli.addr).await?;
+            tracing::info!("MCP SSE endpoint listening on http://{}/sse", cli.addr);
+            axum::serve(listener, server.sse_router()).await?;
+            Ok(())
+        }
+    }
+}
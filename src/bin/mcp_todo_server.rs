@@ -1,16 +1,16 @@
 use std::time::Duration;
 use std::collections::HashMap;
-use swarmonomicon::types::{TodoTask, TaskPriority, TaskStatus};
+use swarmonomicon::types::TaskPriority;
 use swarmonomicon::tools::todo::TodoTool;
 use swarmonomicon::tools::ToolExecutor;
-use rumqttc::{MqttOptions, AsyncClient, QoS, Event};
+use swarmonomicon::mqtt::QoS;
+use swarmonomicon::mqtt::MqttService;
+use swarmonomicon::config::SwarmConfig;
+use swarmonomicon::mcp::McpClient;
 use serde::{Deserialize, Serialize};
-use tokio::{task, time, sync::Semaphore};
-use std::error::Error as StdError;
-use std::process::Command;
+use tokio::sync::Semaphore;
 use anyhow::{Result, anyhow};
 use std::sync::Arc;
-use tokio::sync::broadcast;
 use serde_json::json;
 use std::time::Instant;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -75,9 +75,14 @@ impl TaskMetrics {
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging with more verbose output
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::DEBUG)
-        .init();
+    swarmonomicon::telemetry::init_tracing(
+        "mcp_todo_server",
+        tracing::Level::DEBUG,
+        tracing_subscriber::fmt::format::FmtSpan::NONE,
+    );
+
+    let config = SwarmConfig::from_cli_or_exit();
+    McpClient::init_shared(config.mcp_client_config())?;
 
     // Initialize TodoTool
     let todo_tool = Arc::new(TodoTool::new().await.map_err(|e| anyhow!("Failed to initialize TodoTool: {}", e))?);
@@ -89,37 +94,19 @@ async fn main() -> Result<()> {
     // Initialize metrics
     let metrics = Arc::new(TaskMetrics::new());
 
-    let aws_ip = std::env::var("AWSIP").expect("AWSIP environment variable not set");
-    let aws_port = std::env::var("AWSPORT").expect("AWSPORT environment variable not set").parse::<u16>().expect("AWSPORT must be a number");
-
     // Connect to MQTT broker
-    let mut mqtt_options = MqttOptions::new("mcp_todo_server", &aws_ip, aws_port);
-    mqtt_options.set_keep_alive(Duration::from_secs(30));
-    mqtt_options.set_clean_session(true);
-    let (client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
-    let client = Arc::new(client);
-    tracing::info!("Connecting to MQTT broker at {}:{}", aws_ip, aws_port);
-
-    // Subscribe to mcp/+ topic with retry logic
-    for attempt in 1..=3 {
-        match client.subscribe("mcp/+", QoS::ExactlyOnce).await {
-            Ok(_) => {
-                tracing::info!("Successfully subscribed to mcp/+");
-                break;
-            }
-            Err(e) => {
-                if attempt == 3 {
-                    return Err(anyhow!("Failed to subscribe after 3 attempts: {}", e));
-                }
-                tracing::warn!("Subscribe attempt {} failed: {}. Retrying...", attempt, e);
-                time::sleep(Duration::from_secs(1)).await;
-            }
-        }
-    }
+    let client = MqttService::connect(config.mqtt_config("mcp_todo_server"));
+    client.log_topic_map(&[
+        "mcp/+",
+        "response/+/todo",
+        "response/+/error",
+        "response/mcp_server/status",
+        "metrics/response/mcp_todo_server",
+    ]);
+    let mut requests = client.subscribe("mcp/+", client.config().task_qos).await?;
 
     // // Also subscribe to control topic
-    // client.subscribe("mcp_server/control", QoS::ExactlyOnce).await
-    //     .map_err(|e| anyhow!("Failed to subscribe to control topic: {}", e))?;
+    // let mut control = client.subscribe("mcp_server/control", QoS::ExactlyOnce).await?;
 
     tracing::info!("MCP Todo Server started. Listening for new tasks...");
 
@@ -133,12 +120,7 @@ async fn main() -> Result<()> {
 
             // Report metrics
             let metrics_json = metrics_cloned.as_json();
-            let _ = metrics_client.publish(
-                "metrics/response/mcp_todo_server",
-                QoS::ExactlyOnce,
-                false,
-                metrics_json.to_string()
-            ).await;
+            let _ = metrics_client.publish_metrics("metrics/response/mcp_todo_server", &metrics_json).await;
         }
     });
 
@@ -163,6 +145,7 @@ async fn main() -> Result<()> {
             result = shutdown_rx.recv() => {
                 if result.is_ok() {
                     tracing::info!("Shutdown signal received, closing MQTT connection...");
+                    metrics_reporter.abort();
 
                     // Publish final metrics and shutdown status
                     let shutdown_payload = json!({
@@ -192,149 +175,95 @@ async fn main() -> Result<()> {
                 }
             }
 
-            // Handle MQTT events
-            event_result = event_loop.poll() => {
-                match event_result {
-                    Ok(notification) => {
-                        if let Event::Incoming(rumqttc::Packet::Publish(publish)) = notification {
-                            let topic = publish.topic.clone();
-                            let payload = String::from_utf8_lossy(&publish.payload).to_string();
-
-                            // Handle control messages
-                            if topic == "mcp_server/control" {
-                                if let Ok(control_json) = serde_json::from_str::<serde_json::Value>(&payload) {
-                                    if let Some(command) = control_json.get("command").and_then(|c| c.as_str()) {
-                                        if command == "shutdown" {
-                                            tracing::info!("Received shutdown command, initiating graceful shutdown...");
-                                            let _ = shutdown_tx.send(());
-                                            continue;
-                                        } else if command == "status" {
-                                            // Report current status
-                                            let status_payload = json!({
-                                                "status": "running",
-                                                "timestamp": chrono::Utc::now().to_rfc3339(),
-                                                "metrics": metrics.as_json()
-                                            }).to_string();
-
-                                            if let Err(e) = client.publish(
-                                                "response/mcp_server/status",
-                                                QoS::ExactlyOnce,
-                                                false,
-                                                status_payload
-                                            ).await {
-                                                tracing::error!("Failed to publish status: {}", e);
-                                            }
-                                            continue;
-                                        }
-                                    }
-                                }
+            // Handle MCP task requests
+            Some(message) = requests.recv() => {
+                let topic = message.topic.clone();
+                let payload = message.payload_str().to_string();
+                tracing::info!("Received payload on {}: {}", topic, payload);
+
+                // Increment the task received counter
+                let task_count = metrics.increment_received();
+                tracing::debug!("Task count: {}", task_count);
+
+                // Clone necessary Arc's for the task
+                let todo_tool = todo_tool.clone();
+                let task_semaphore = task_semaphore.clone();
+                let ai_semaphore = ai_semaphore.clone();
+                let metrics = metrics.clone();
+                let client = client.clone();
+
+                // Spawn a new task to handle this request
+                tokio::spawn(async move {
+                    // Acquire task processing permit
+                    let _task_permit = match task_semaphore.acquire().await {
+                        Ok(permit) => permit,
+                        Err(e) => {
+                            tracing::error!("Failed to acquire task permit: {}", e);
+                            metrics.increment_failed();
+                            return;
+                        }
+                    };
+
+                    // Try to parse as McpTodoRequest, if fails treat as plain text
+                    let description = match serde_json::from_str::<McpTodoRequest>(&payload) {
+                        Ok(request) => request.description,
+                        Err(_) => payload,
+                    };
+
+                    let target_agent = topic.split('/').nth(1).unwrap_or("user");
+
+                    // Add todo using TodoTool
+                    let mut params = HashMap::new();
+                    params.insert("command".to_string(), "add".to_string());
+                    params.insert("description".to_string(), description.clone());
+                    params.insert("context".to_string(), "mcp_server".to_string());
+                    params.insert("target_agent".to_string(), target_agent.to_string());
+
+                    // Acquire AI enhancement permit before processing
+                    let _ai_permit = match ai_semaphore.acquire().await {
+                        Ok(permit) => permit,
+                        Err(e) => {
+                            tracing::error!("Failed to acquire AI permit: {}", e);
+                            metrics.increment_failed();
+                            return;
+                        }
+                    };
+
+                    match todo_tool.execute(params).await {
+                        Ok(result) => {
+                            tracing::info!("Successfully added todo: {}", description);
+                            metrics.increment_processed();
+
+                            // Publish success response
+                            let response_topic = format!("response/{}/todo", target_agent);
+                            let response_payload = json!({
+                                "status": "success",
+                                "message": result,
+                                "timestamp": chrono::Utc::now().to_rfc3339()
+                            }).to_string();
+
+                            if let Err(e) = client.reply(&message, response_topic, response_payload).await {
+                                tracing::error!("Failed to publish success response: {}", e);
                             }
-
-                            // Handle normal MCP task requests
-                            if topic.starts_with("mcp/") {
-                                tracing::info!("Received payload on {}: {}", topic, payload);
-
-                                // Increment the task received counter
-                                let task_count = metrics.increment_received();
-                                tracing::debug!("Task count: {}", task_count);
-
-                                // Clone necessary Arc's for the task
-                                let todo_tool = todo_tool.clone();
-                                let task_semaphore = task_semaphore.clone();
-                                let ai_semaphore = ai_semaphore.clone();
-                                let metrics = metrics.clone();
-                                let client = client.clone();
-
-                                // Spawn a new task to handle this request
-                                tokio::spawn(async move {
-                                    // Acquire task processing permit
-                                    let _task_permit = match task_semaphore.acquire().await {
-                                        Ok(permit) => permit,
-                                        Err(e) => {
-                                            tracing::error!("Failed to acquire task permit: {}", e);
-                                            metrics.increment_failed();
-                                            return;
-                                        }
-                                    };
-
-                                    // Try to parse as McpTodoRequest, if fails treat as plain text
-                                    let description = match serde_json::from_str::<McpTodoRequest>(&payload) {
-                                        Ok(request) => request.description,
-                                        Err(_) => payload,
-                                    };
-
-                                    let target_agent = topic.split('/').nth(1).unwrap_or("user");
-
-                                    // Add todo using TodoTool
-                                    let mut params = HashMap::new();
-                                    params.insert("command".to_string(), "add".to_string());
-                                    params.insert("description".to_string(), description.clone());
-                                    params.insert("context".to_string(), "mcp_server".to_string());
-                                    params.insert("target_agent".to_string(), target_agent.to_string());
-
-                                    // Acquire AI enhancement permit before processing
-                                    let _ai_permit = match ai_semaphore.acquire().await {
-                                        Ok(permit) => permit,
-                                        Err(e) => {
-                                            tracing::error!("Failed to acquire AI permit: {}", e);
-                                            metrics.increment_failed();
-                                            return;
-                                        }
-                                    };
-
-                                    match todo_tool.execute(params).await {
-                                        Ok(result) => {
-                                            tracing::info!("Successfully added todo: {}", description);
-                                            metrics.increment_processed();
-
-                                            // Publish success response
-                                            let response_topic = format!("response/{}/todo", target_agent);
-                                            let response_payload = json!({
-                                                "status": "success",
-                                                "message": result,
-                                                "timestamp": chrono::Utc::now().to_rfc3339()
-                                            }).to_string();
-
-                                            if let Err(e) = client.publish(
-                                                response_topic,
-                                                QoS::ExactlyOnce,
-                                                false,
-                                                response_payload
-                                            ).await {
-                                                tracing::error!("Failed to publish success response: {}", e);
-                                            }
-                                        },
-                                        Err(e) => {
-                                            tracing::error!("Failed to add todo: {}", e);
-                                            metrics.increment_failed();
-
-                                            // Publish error response
-                                            let error_topic = format!("response/{}/error", target_agent);
-                                            let error_payload = json!({
-                                                "status": "error",
-                                                "error": e.to_string(),
-                                                "timestamp": chrono::Utc::now().to_rfc3339()
-                                            }).to_string();
-
-                                            if let Err(e) = client.publish(
-                                                error_topic,
-                                                QoS::ExactlyOnce,
-                                                false,
-                                                error_payload
-                                            ).await {
-                                                tracing::error!("Failed to publish error response: {}", e);
-                                            }
-                                        }
-                                    }
-                                });
+                        },
+                        Err(e) => {
+                            tracing::error!("Failed to add todo: {}", e);
+                            metrics.increment_failed();
+
+                            // Publish error response
+                            let error_topic = format!("response/{}/error", target_agent);
+                            let error_payload = json!({
+                                "status": "error",
+                                "error": e.to_string(),
+                                "timestamp": chrono::Utc::now().to_rfc3339()
+                            }).to_string();
+
+                            if let Err(e) = client.reply(&message, error_topic, error_payload).await {
+                                tracing::error!("Failed to publish error response: {}", e);
                             }
                         }
                     }
-                    Err(e) => {
-                        tracing::error!("Error from eventloop: {:?}", e);
-                        time::sleep(Duration::from_secs(1)).await;
-                    }
-                }
+                });
             }
         }
     }
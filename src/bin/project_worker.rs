@@ -1,12 +1,14 @@
 use std::time::Duration;
-use std::collections::HashMap;
-use swarmonomicon::agents::project::{ProjectAgent, ProjectClassificationRequest, ProjectClassificationResponse};
-use swarmonomicon::types::{AgentConfig, Message};
+use swarmonomicon::agents::project::{
+    ProjectAgent, CLASSIFY_REQUEST_TOPIC, DEFAULT_CLASSIFY_TIMEOUT, classify_response_topic, fallback_classification,
+    parse_classification_request,
+};
+use swarmonomicon::types::AgentConfig;
 use swarmonomicon::Agent;
-use rumqttc::{MqttOptions, AsyncClient, QoS, Event};
-use serde::{Deserialize, Serialize};
-use tokio::{time, sync::Semaphore};
-use std::error::Error as StdError;
+use swarmonomicon::mqtt::QoS;
+use swarmonomicon::mqtt::MqttService;
+use swarmonomicon::config::SwarmConfig;
+use swarmonomicon::supervisor::TaskSupervisor;
 use anyhow::{Result, anyhow};
 use std::sync::Arc;
 use serde_json::json;
@@ -68,9 +70,13 @@ const METRICS_REPORTING_INTERVAL: u64 = 300; // 5 minutes
 #[tokio::main]
 async fn main() -> Result<()> {
     // Initialize logging
-    tracing_subscriber::fmt()
-        .with_max_level(tracing::Level::INFO)
-        .init();
+    swarmonomicon::telemetry::init_tracing(
+        "project_worker",
+        tracing::Level::INFO,
+        tracing_subscriber::fmt::format::FmtSpan::NONE,
+    );
+
+    let config = SwarmConfig::from_cli_or_exit();
 
     // Initialize ProjectAgent
     let project_config = AgentConfig {
@@ -85,47 +91,31 @@ async fn main() -> Result<()> {
 
     let project_agent = Arc::new(ProjectAgent::new(project_config).await
         .map_err(|e| anyhow!("Failed to initialize ProjectAgent: {}", e))?);
+    // Classification works from the built-in project list until this finishes
+    let warming = project_agent.clone();
+    tokio::spawn(async move {
+        if let Err(e) = warming.start().await {
+            tracing::warn!("ProjectAgent warm-up failed: {}", e);
+        }
+    });
 
-    // Create semaphore for rate limiting
-    let request_semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_REQUESTS));
+    // Bounds requests in flight; a panic in one is logged instead of lost
+    let jobs = TaskSupervisor::new("project_worker", MAX_CONCURRENT_REQUESTS);
 
     // Initialize metrics
     let metrics = Arc::new(ProjectMetrics::new());
 
-    let aws_ip = std::env::var("AWSIP").expect("AWSIP environment variable not set");
-    let aws_port = std::env::var("AWSPORT")
-        .expect("AWSPORT environment variable not set")
-        .parse::<u16>()
-        .expect("AWSPORT must be a number");
-
     // Connect to MQTT broker
-    let mut mqtt_options = MqttOptions::new("project_worker", &aws_ip, aws_port);
-    mqtt_options.set_keep_alive(Duration::from_secs(30));
-    mqtt_options.set_clean_session(true);
-    let (client, mut event_loop) = AsyncClient::new(mqtt_options, 10);
-    let client = Arc::new(client);
-    tracing::info!("Connecting to MQTT broker at {}:{}", aws_ip, aws_port);
-
-    // Subscribe to project classification topic
-    for attempt in 1..=3 {
-        match client.subscribe("project/classify", QoS::ExactlyOnce).await {
-            Ok(_) => {
-                tracing::info!("Successfully subscribed to project/classify");
-                break;
-            }
-            Err(e) => {
-                if attempt == 3 {
-                    return Err(anyhow!("Failed to subscribe after 3 attempts: {}", e));
-                }
-                tracing::warn!("Subscribe attempt {} failed: {}. Retrying...", attempt, e);
-                time::sleep(Duration::from_secs(1)).await;
-            }
-        }
-    }
-
-    // Also subscribe to control topic
-    client.subscribe("project_worker/control", QoS::ExactlyOnce).await
-        .map_err(|e| anyhow!("Failed to subscribe to control topic: {}", e))?;
+    let client = MqttService::connect(config.mqtt_config("project_worker"));
+    client.log_topic_map(&[
+        CLASSIFY_REQUEST_TOPIC,
+        "project_worker/control",
+        "project/classify/response/+",
+        "response/project_worker/status",
+        "metrics/response/project_worker",
+    ]);
+    let mut requests = client.subscribe(CLASSIFY_REQUEST_TOPIC, client.config().task_qos).await?;
+    let mut control = client.subscribe("project_worker/control", QoS::ExactlyOnce).await?;
 
     tracing::info!("Project Worker started. Listening for classification requests...");
 
@@ -139,12 +129,7 @@ async fn main() -> Result<()> {
 
             // Report metrics
             let metrics_json = metrics_cloned.as_json();
-            let _ = metrics_client.publish(
-                "metrics/response/project_worker",
-                QoS::ExactlyOnce,
-                false,
-                metrics_json.to_string()
-            ).await;
+            let _ = metrics_client.publish_metrics("metrics/response/project_worker", &metrics_json).await;
         }
     });
 
@@ -170,6 +155,10 @@ async fn main() -> Result<()> {
                 if result.is_ok() {
                     tracing::info!("Shutdown signal received, closing MQTT connection...");
 
+                    // Answer what was already accepted, and stop background maintenance
+                    jobs.drain(config.worker.shutdown_grace()).await;
+                    project_agent.shutdown(config.worker.shutdown_grace()).await;
+
                     // Publish final metrics and shutdown status
                     let shutdown_payload = json!({
                         "status": "shutdown",
@@ -198,145 +187,97 @@ async fn main() -> Result<()> {
                 }
             }
 
-            // Handle MQTT events
-            event_result = event_loop.poll() => {
-                match event_result {
-                    Ok(notification) => {
-                        if let Event::Incoming(rumqttc::Packet::Publish(publish)) = notification {
-                            let topic = publish.topic.clone();
-                            let payload = String::from_utf8_lossy(&publish.payload).to_string();
-
-                            // Handle control messages
-                            if topic == "project_worker/control" {
-                                if let Ok(control_json) = serde_json::from_str::<serde_json::Value>(&payload) {
-                                    if let Some(command) = control_json.get("command").and_then(|c| c.as_str()) {
-                                        if command == "shutdown" {
-                                            tracing::info!("Received shutdown command, initiating graceful shutdown...");
-                                            let _ = shutdown_tx.send(());
-                                            continue;
-                                        } else if command == "status" {
-                                            // Report current status
-                                            let status_payload = json!({
-                                                "status": "running",
-                                                "timestamp": chrono::Utc::now().to_rfc3339(),
-                                                "metrics": metrics.as_json()
-                                            }).to_string();
-
-                                            if let Err(e) = client.publish(
-                                                "response/project_worker/status",
-                                                QoS::ExactlyOnce,
-                                                false,
-                                                status_payload
-                                            ).await {
-                                                tracing::error!("Failed to publish status: {}", e);
-                                            }
-                                            continue;
-                                        }
-                                    }
-                                }
-                            }
-
-                            // Handle project classification requests
-                            if topic == "project/classify" {
-                                tracing::info!("Received classification request: {}", payload);
-
-                                // Increment the request received counter
-                                let request_count = metrics.increment_received();
-                                tracing::debug!("Request count: {}", request_count);
-
-                                // Clone necessary Arc's for the task
-                                let project_agent = project_agent.clone();
-                                let request_semaphore = request_semaphore.clone();
-                                let metrics = metrics.clone();
-                                let client = client.clone();
-
-                                // Spawn a new task to handle this request
-                                tokio::spawn(async move {
-                                    // Acquire request processing permit
-                                    let _request_permit = match request_semaphore.acquire().await {
-                                        Ok(permit) => permit,
-                                        Err(e) => {
-                                            tracing::error!("Failed to acquire request permit: {}", e);
-                                            metrics.increment_failed();
-                                            return;
-                                        }
-                                    };
-
-                                    // Try to parse as ProjectClassificationRequest
-                                    let classification_request = match serde_json::from_str::<ProjectClassificationRequest>(&payload) {
-                                        Ok(request) => request,
-                                        Err(_) => {
-                                            // If parsing fails, create a simple request with the payload as description
-                                            ProjectClassificationRequest {
-                                                description: payload,
-                                                request_id: None,
-                                                context: None,
-                                            }
-                                        }
-                                    };
-
-                                    // Process the classification request
-                                    match project_agent.classify_project(classification_request.clone()).await {
-                                        Ok(response) => {
-                                            tracing::info!("Successfully classified project: {} -> {}", 
-                                                classification_request.description, response.project_name);
-                                            metrics.increment_processed();
-
-                                            // Publish success response
-                                            let response_topic = if let Some(request_id) = &response.request_id {
-                                                format!("response/project/classify/{}", request_id)
-                                            } else {
-                                                "response/project/classify".to_string()
-                                            };
-
-                                            let response_payload = serde_json::to_string(&response).unwrap_or_else(|_| {
-                                                json!({
-                                                    "project_name": response.project_name,
-                                                    "confidence": response.confidence
-                                                }).to_string()
-                                            });
-
-                                            if let Err(e) = client.publish(
-                                                response_topic,
-                                                QoS::ExactlyOnce,
-                                                false,
-                                                response_payload
-                                            ).await {
-                                                tracing::error!("Failed to publish success response: {}", e);
-                                            }
-                                        },
-                                        Err(e) => {
-                                            tracing::error!("Failed to classify project: {}", e);
-                                            metrics.increment_failed();
-
-                                            // Publish error response
-                                            let error_topic = "response/project/classify/error";
-                                            let error_payload = json!({
-                                                "status": "error",
-                                                "error": e.to_string(),
-                                                "request_id": classification_request.request_id,
-                                                "fallback_project": "madness_interactive",
-                                                "timestamp": chrono::Utc::now().to_rfc3339()
-                                            }).to_string();
-
-                                            if let Err(e) = client.publish(
-                                                error_topic,
-                                                QoS::ExactlyOnce,
-                                                false,
-                                                error_payload
-                                            ).await {
-                                                tracing::error!("Failed to publish error response: {}", e);
-                                            }
-                                        }
-                                    }
-                                });
+            // Handle control messages
+            Some(message) = control.recv() => {
+                let payload = message.payload_str();
+                if let Ok(control_json) = serde_json::from_str::<serde_json::Value>(&payload) {
+                    if let Some(command) = control_json.get("command").and_then(|c| c.as_str()) {
+                        if command == "shutdown" {
+                            tracing::info!("Received shutdown command, initiating graceful shutdown...");
+                            let _ = shutdown_tx.send(());
+                        } else if command == "status" {
+                            // Report current status
+                            let status_payload = json!({
+                                "status": "running",
+                                "timestamp": chrono::Utc::now().to_rfc3339(),
+                                "metrics": metrics.as_json()
+                            }).to_string();
+
+                            if let Err(e) = client.publish(
+                                "response/project_worker/status",
+                                QoS::ExactlyOnce,
+                                false,
+                                status_payload
+                            ).await {
+                                tracing::error!("Failed to publish status: {}", e);
                             }
                         }
                     }
-                    Err(e) => {
-                        tracing::error!("Error from eventloop: {:?}", e);
-                        time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+
+            // Handle project classification requests
+            Some(message) = requests.recv() => {
+                let payload = message.payload_str().to_string();
+                tracing::info!("Received classification request: {}", payload);
+
+                // Increment the request received counter
+                let request_count = metrics.increment_received();
+                tracing::debug!("Request count: {}", request_count);
+
+                // Clone necessary Arc's for the task
+                let project_agent = project_agent.clone();
+                let metrics_cloned = metrics.clone();
+                let metrics = metrics.clone();
+                let client = client.clone();
+
+                // Handle it in the background once a slot is free
+                let spawned = jobs.spawn(format!("classify {}", message.topic), async move {
+                    let classification_request = parse_classification_request(&payload);
+                    let request_id = classification_request.request_id.clone();
+                    let response_topic = match &request_id {
+                        Some(request_id) => classify_response_topic(request_id),
+                        None => "project/classify/response".to_string(),
+                    };
+
+                    // Process the classification request, answering with the default project if it fails
+                    let classification = tokio::time::timeout(
+                        DEFAULT_CLASSIFY_TIMEOUT,
+                        project_agent.classify_project(classification_request.clone())
+                    ).await;
+                    let response = match classification {
+                        Ok(Ok(response)) => {
+                            tracing::info!("Successfully classified project: {} -> {}", 
+                                classification_request.description, response.project_name);
+                            metrics.increment_processed();
+                            response
+                        },
+                        Ok(Err(e)) => {
+                            tracing::error!("Failed to classify project: {}", e);
+                            metrics.increment_failed();
+                            fallback_classification(request_id, format!("Classification failed: {}", e))
+                        },
+                        Err(_) => {
+                            tracing::error!("Project classification timed out");
+                            metrics.increment_failed();
+                            fallback_classification(request_id, "Classification timed out")
+                        }
+                    };
+
+                    let response_payload = serde_json::to_string(&response).unwrap_or_else(|_| {
+                        json!({
+                            "project_name": response.project_name,
+                            "confidence": response.confidence
+                        }).to_string()
+                    });
+
+                    if let Err(e) = client.reply(&message, response_topic, response_payload).await {
+                        tracing::error!("Failed to publish classification response: {}", e);
                     }
+                }).await;
+                if let Err(e) = spawned {
+                    tracing::error!("Failed to start classification: {}", e);
+                    metrics_cloned.increment_failed();
                 }
             }
         }
@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+use anyhow::Result;
+use clap::Parser;
+use swarmonomicon::agents::{create_agent, default_agents, AgentRegistry};
+use swarmonomicon::recording::{self, Recording, Replay};
+use swarmonomicon::tools::dry_run;
+use swarmonomicon::types::{Agent, TodoProcessor};
+
+/// Re-run captured messages and tasks against the current code, answering AI
+/// calls from the capture, and report every reply that changed
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Capture file written with CAPTURE_FILE set
+    capture: PathBuf,
+
+    /// Only replay traffic for this agent
+    #[arg(short, long)]
+    agent: Option<String>,
+
+    /// Describe tool calls instead of running them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    swarmonomicon::telemetry::init_tracing(
+        "replay",
+        tracing::Level::WARN,
+        tracing_subscriber::fmt::format::FmtSpan::NONE,
+    );
+    let args = Args::parse();
+
+    // Replaying must not append to the capture it reads, or to any other
+    std::env::remove_var("CAPTURE_FILE");
+    // Agents open their todo list on creation; nothing here writes to it, and
+    // the client doesn't connect until it is used
+    if std::env::var("RTK_MONGO_URI").is_err() {
+        std::env::set_var("RTK_MONGO_URI", "mongodb://localhost:27017");
+    }
+
+    let recordings = recording::load(&args.capture)?;
+    Replay::init_shared(Replay::new(&recordings));
+
+    let mut registry = AgentRegistry::new();
+    for config in default_agents() {
+        let name = config.name.clone();
+        match create_agent(config).await {
+            Ok(agent) => registry.register(name, agent).await?,
+            Err(e) => eprintln!("Skipping agent {}: {}", name, e),
+        }
+    }
+
+    let (mut replayed, mut changed) = (0, 0);
+    for (index, recorded) in recordings.iter().enumerate() {
+        let (agent, label) = match recorded {
+            Recording::Message { agent, message, .. } => (agent, format!("message {:?}", message.content)),
+            Recording::Task { agent, task, .. } => (agent, format!("task {}", task.id)),
+            Recording::Ai { .. } => continue,
+        };
+        if args.agent.as_ref().is_some_and(|only| only != agent) {
+            continue;
+        }
+        let Some(handle) = registry.get(agent) else {
+            eprintln!("#{} {}: agent {} is not available", index + 1, label, agent);
+            changed += 1;
+            continue;
+        };
+        let wrapper = handle.read().await;
+
+        let rerun = async {
+            match recorded {
+                Recording::Message { message, .. } => {
+                    let result = wrapper.process_message(message.clone()).await.map_err(anyhow::Error::from);
+                    Recording::message(agent.as_str(), message.clone(), &result)
+                }
+                Recording::Task { task, .. } => {
+                    let result = wrapper.process_task(task.clone()).await;
+                    Recording::task(agent.as_str(), task.clone(), &result)
+                }
+                Recording::Ai { .. } => unreachable!("AI responses are skipped above"),
+            }
+        };
+        let now = if args.dry_run { dry_run::with_dry_run(rerun).await } else { rerun.await };
+
+        replayed += 1;
+        if recorded.outcome() != now.outcome() {
+            changed += 1;
+            println!("#{} {} to {} changed", index + 1, label, agent);
+            println!("  recorded: {}", describe(recorded.outcome()));
+            println!("  replayed: {}", describe(now.outcome()));
+        }
+    }
+
+    println!("Replayed {} recording(s), {} changed", replayed, changed);
+    if changed > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn describe(outcome: Option<Result<&str, &str>>) -> String {
+    match outcome {
+        Some(Ok(reply)) => format!("{:?}", reply),
+        Some(Err(error)) => format!("error {:?}", error),
+        None => "nothing".to_string(),
+    }
+}
@@ -1,19 +1,14 @@
 use clap::{Parser, Subcommand};
 use swarmonomicon::{
-    agents::{self, AgentRegistry, TransferService, GitAssistantAgent, HaikuAgent, GreeterAgent},
-    types::{AgentConfig, Message, Agent, TodoProcessor, TodoTask, TaskPriority, TaskStatus},
-    error::Error,
+    agents::{self, AgentRegistry, GitAssistantAgent, HaikuAgent, GreeterAgent},
+    types::{AgentConfig, TodoProcessor, TodoTask, TaskPriority},
+    config::ConfigManager,
 };
-use std::sync::Arc;
-use tokio::sync::RwLock;
-use std::io::Write;
+use std::path::{Path, PathBuf};
 use anyhow::{Result, anyhow};
 use chrono::Utc;
 use uuid::Uuid;
 
-#[cfg(feature = "project-agent")]
-use swarmonomicon::agents::project::ProjectAgent;
-
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 struct Cli {
@@ -59,6 +54,12 @@ enum Commands {
         /// The message to send
         message: String,
     },
+
+    /// Check a config directory (agent_sets/ and tools/) before deploying it
+    LintConfig {
+        /// Directory to check
+        dir: PathBuf,
+    },
 }
 
 async fn initialize_registry() -> Result<AgentRegistry> {
@@ -118,22 +119,15 @@ async fn handle_git_command(
     let task = TodoTask {
         id: Uuid::new_v4().to_string(),
         description: input,
-        enhanced_description: None,
         priority: TaskPriority::Medium,
-        project: None,
         source_agent: Some("swarm".to_string()),
         target_agent: "git".to_string(),
-        status: TaskStatus::Pending,
         created_at: Utc::now().timestamp(),
-        completed_at: None,
-        due_date: None,
-        duration_minutes: None,
-        notes: None,
-        ticket: None,
         last_modified: Some(Utc::now().timestamp()),
+        ..Default::default()
     };
     let agent = reg.get("git").ok_or_else(|| anyhow!("Git agent not found"))?;
-    agent.process_task(task).await.map_err(|e| anyhow!(e))?;
+    agent.read().await.process_task(task).await.map_err(|e| anyhow!(e))?;
     Ok(())
 }
 
@@ -145,22 +139,15 @@ async fn handle_init_command(
     let task = TodoTask {
         id: Uuid::new_v4().to_string(),
         description: init_message,
-        enhanced_description: None,
         priority: TaskPriority::Medium,
-        project: None,
         source_agent: Some("swarm".to_string()),
         target_agent: "greeter".to_string(),
-        status: TaskStatus::Pending,
         created_at: Utc::now().timestamp(),
-        completed_at: None,
-        due_date: None,
-        duration_minutes: None,
-        notes: None,
-        ticket: None,
         last_modified: Some(Utc::now().timestamp()),
+        ..Default::default()
     };
     let agent = reg.get("greeter").ok_or_else(|| anyhow!("Greeter agent not found"))?;
-    agent.process_task(task).await.map_err(|e| anyhow!(e))?;
+    agent.read().await.process_task(task).await.map_err(|e| anyhow!(e))?;
     Ok(())
 }
 
@@ -169,25 +156,18 @@ async fn handle_message(
     message: String,
 ) -> Result<()> {
     let current_agent_name = reg.get_current_agent().ok_or_else(|| anyhow!("No current agent set"))?;
-    let agent = reg.get(&current_agent_name).ok_or_else(|| anyhow!("Current agent not found"))?;
+    let agent = reg.get(current_agent_name).ok_or_else(|| anyhow!("Current agent not found"))?;
     let task = TodoTask {
         id: Uuid::new_v4().to_string(),
         description: message,
-        enhanced_description: None,
         priority: TaskPriority::Medium,
-        project: None,
         source_agent: Some("swarm".to_string()),
         target_agent: current_agent_name.to_string(),
-        status: TaskStatus::Pending,
         created_at: Utc::now().timestamp(),
-        completed_at: None,
-        due_date: None,
-        duration_minutes: None,
-        notes: None,
-        ticket: None,
         last_modified: Some(Utc::now().timestamp()),
+        ..Default::default()
     };
-    agent.process_task(task).await.map_err(|e| anyhow!(e))?;
+    agent.read().await.process_task(task).await.map_err(|e| anyhow!(e))?;
     Ok(())
 }
 
@@ -206,10 +186,27 @@ async fn interactive_mode(reg: &mut AgentRegistry) -> Result<()> {
     Ok(())
 }
 
+fn lint_config(dir: &Path) -> Result<()> {
+    let problems = ConfigManager::lint_dir(dir)?;
+    if problems.is_empty() {
+        println!("{}: OK", dir.display());
+        return Ok(());
+    }
+    for problem in &problems {
+        eprintln!("{}", problem);
+    }
+    Err(anyhow!("{} problem(s) in {}", problems.len(), dir.display()))
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
 
+    // Linting needs no agents, so skip building the registry
+    if let Some(Commands::LintConfig { dir }) = &cli.command {
+        return lint_config(dir);
+    }
+
     let mut reg = initialize_registry().await?;
 
     if let Some(command) = cli.command {
@@ -228,6 +225,7 @@ async fn main() -> Result<()> {
             Commands::Message { message } => {
                 handle_message(&mut reg, message).await?;
             }
+            Commands::LintConfig { .. } => unreachable!("handled before the registry is built"),
         }
     } else {
         interactive_mode(&mut reg).await?;
@@ -239,7 +237,13 @@ async fn main() -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
     use tempfile::tempdir;
+    use swarmonomicon::agents::TransferService;
+    use swarmonomicon::types::Message;
+    #[cfg(feature = "project-agent")]
+    use swarmonomicon::agents::project::ProjectAgent;
 
     #[tokio::test]
     #[cfg(all(feature = "haiku-agent", feature = "git-agent"))]
@@ -267,8 +271,7 @@ mod tests {
 
             #[cfg(feature = "git-agent")]
             {
-                let mut git = git_assistant;
-                git.update_working_dir(temp_dir.path().to_path_buf())?;
+                let git = git_assistant.with_working_dir(temp_dir.path());
                 registry.register("git".to_string(), Box::new(git)).await?;
             }
 
@@ -299,10 +302,10 @@ mod tests {
         }
 
         // Create transfer service
-        let mut service = TransferService::new(registry.clone());
+        let service = TransferService::new(registry.clone());
 
         // Test haiku generation and git commit
-        service.set_current_agent_name("haiku");
+        service.set_current_agent_name("haiku").await?;
         let response = service.process_message(Message::new("generate haiku about coding".to_string())).await?;
 
         assert!(response.content.contains("Generated haiku:"));
@@ -317,7 +320,7 @@ mod tests {
         assert!(git_log.contains("[haiku]"));
 
         // Test project initialization
-        service.set_current_agent_name("project");
+        service.set_current_agent_name("project").await?;
         let response = service.process_message(Message::new("create rust test-project 'A test project'".to_string())).await?;
 
         assert!(response.content.contains("Project created"));
@@ -0,0 +1,334 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::{anyhow, ensure, Result};
+use clap::Parser;
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+use tokio::time::Instant;
+use swarmonomicon::agents::project::{
+    classify_response_topic, parse_classification_request, ProjectClassificationResponse, CLASSIFY_REQUEST_TOPIC,
+};
+use swarmonomicon::agents::{AgentRegistry, TransferService};
+use swarmonomicon::api::{self, AppState};
+use swarmonomicon::config::{ConfigArgs, SwarmConfig};
+use swarmonomicon::mcp::McpClient;
+use swarmonomicon::mqtt::{topic_matches, MqttMessage, MqttService, QoS};
+use swarmonomicon::recording::Replay;
+use swarmonomicon::test_support::{McpFake, MemoryBroker, MemoryStateStore};
+use swarmonomicon::telemetry::StallWatch;
+use swarmonomicon::tools::TodoTool;
+use swarmonomicon::worker::{self, WorkerContext};
+
+/// Agent every scripted task goes to; it answers transfer requests without a model
+const AGENT: &str = "greeter";
+/// Project the stand-in classifier files every intake request under
+const CLASSIFIED_PROJECT: &str = "swarmonomicon";
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Run the API, a todo worker and MQTT intake in this process against
+/// in-memory MQTT and MCP fakes, push a scripted set of tasks through
+/// intake, classification, processing and completion, and check where each
+/// one ends up. Todos are kept in a scratch MongoDB database.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Seconds to wait for each step before calling it failed
+    #[arg(long, default_value_t = 30)]
+    timeout: u64,
+
+    /// Keep the scratch database for inspection instead of dropping it
+    #[arg(long)]
+    keep_db: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    // Blocking code on a worker thread fails the run like a broken scenario
+    if std::env::var("RUNTIME_STALL_MS").is_err() {
+        std::env::set_var("RUNTIME_STALL_MS", "500");
+    }
+    swarmonomicon::telemetry::init_tracing(
+        "swarm-e2e",
+        tracing::Level::WARN,
+        tracing_subscriber::fmt::format::FmtSpan::NONE,
+    );
+    let args = Args::parse();
+
+    // Everything the pipeline stores goes to a database of its own
+    let database = format!("swarm_e2e_{}", &uuid::Uuid::new_v4().simple().to_string()[..8]);
+    let scratch = std::env::temp_dir().join(&database);
+    if std::env::var("RTK_MONGO_URI").is_err() {
+        std::env::set_var("RTK_MONGO_URI", "mongodb://localhost:27017");
+    }
+    std::env::set_var("RTK_MONGO_DB", &database);
+    // Intake writes todos where the worker and the API read them
+    std::env::set_var("TODO_BACKEND", "mongo");
+    std::env::set_var("TODO_OUTBOX_PATH", scratch.join("todo_outbox.jsonl"));
+    // Classification is answered by `spawn_classifier` instead of a model
+    std::env::set_var("INTAKE_LOCAL_CLASSIFIER", "false");
+    // A failing task settles on its first attempt instead of waiting out backoff
+    std::env::set_var("TASK_MAX_ATTEMPTS", "1");
+    std::env::remove_var("CAPTURE_FILE");
+    std::env::remove_var("HAIKU_DAILY_HOUR");
+
+    // No model is reachable: every AI call fails at once, so enhancement falls
+    // back to the plain description and tasks that need a model fail
+    Replay::init_shared(Replay::new(&[]));
+    let mcp = McpFake::with_fixtures().await;
+    McpClient::init_shared(mcp.config())?;
+
+    let config = SwarmConfig::load(&ConfigArgs::default())?;
+    let broker = MemoryBroker::new();
+    let pipeline = Pipeline::start(&broker, &config, Duration::from_secs(args.timeout)).await;
+
+    let mut failed = 0;
+    match pipeline {
+        Ok(pipeline) => {
+            // Run one after another so each sees the pipeline idle
+            let scenarios = [
+                ("intake request is classified, processed and completed", intake_to_completion(&pipeline).await),
+                ("API task is processed and completed", api_to_completion(&pipeline).await),
+                ("task the model can't answer is dead-lettered", failure_to_dead_letter(&pipeline).await),
+            ];
+            for (name, result) in &scenarios {
+                match result {
+                    Ok(()) => println!("ok      {}", name),
+                    Err(e) => {
+                        failed += 1;
+                        println!("FAILED  {}: {:#}", name, e);
+                    }
+                }
+            }
+            println!("{} scenario(s), {} failed", scenarios.len(), failed);
+        }
+        Err(e) => {
+            failed += 1;
+            println!("FAILED  pipeline didn't start: {:#}", e);
+        }
+    }
+
+    if args.keep_db {
+        println!("Kept database {}", database);
+    } else if let Err(e) = drop_database(&database).await {
+        eprintln!("Failed to drop database {}: {}", database, e);
+    }
+    let _ = std::fs::remove_dir_all(&scratch);
+
+    let stalls = StallWatch::shared().map_or(0, StallWatch::stalls);
+    if stalls > 0 {
+        failed += 1;
+        println!("FAILED  the async runtime stalled {} time(s); see the warnings above", stalls);
+    }
+
+    if failed > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// The running services, and everything published while they ran
+struct Pipeline {
+    api: String,
+    http: reqwest::Client,
+    mqtt: MqttService,
+    seen: Arc<Mutex<Vec<MqttMessage>>>,
+    timeout: Duration,
+}
+
+impl Pipeline {
+    async fn start(broker: &MemoryBroker, config: &SwarmConfig, timeout: Duration) -> Result<Self> {
+        let mqtt = broker.connect(config.mqtt_config("swarm-e2e"));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut everything = mqtt.subscribe("#", QoS::AtLeastOnce).await?;
+        let recorded = seen.clone();
+        tokio::spawn(async move {
+            while let Some(message) = everything.recv().await {
+                recorded.lock().unwrap().push(message);
+            }
+        });
+        spawn_classifier(&mqtt).await?;
+
+        let worker = WorkerContext::new(broker.connect(config.mqtt_config("todo_worker-e2e")), config, "todo_worker-e2e")
+            .with_check_interval(Duration::from_secs(1));
+        tokio::spawn(async move {
+            if let Err(e) = worker::run_worker(worker).await {
+                eprintln!("Worker stopped: {:#}", e);
+            }
+        });
+
+        let intake = broker.connect(config.mqtt_config("mqtt_intake-e2e"));
+        let intake_config = config.clone();
+        let todo_tool = Arc::new(TodoTool::new().await?);
+        tokio::spawn(async move {
+            if let Err(e) = worker::run_intake(&intake_config, intake, todo_tool).await {
+                eprintln!("Intake stopped: {:#}", e);
+            }
+        });
+
+        let registry = Arc::new(RwLock::new(AgentRegistry::new()));
+        let app_state = AppState::new(Arc::new(RwLock::new(TransferService::new(registry))))
+            .with_mqtt_client(broker.connect(config.mqtt_config("swarmonomicon-api-e2e")))
+            .with_state_store(Arc::new(MemoryStateStore::new()));
+        let app = api::router(config, app_state).await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let api = format!("http://{}", listener.local_addr()?);
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                eprintln!("API stopped: {}", e);
+            }
+        });
+
+        let pipeline = Self { api, http: reqwest::Client::new(), mqtt, seen, timeout };
+        pipeline.wait_until_ready("the worker", "todo_worker/control", "todo_worker/status").await?;
+        pipeline.wait_until_ready("intake", "mcp_server/control", "response/mcp_server/status").await?;
+        Ok(pipeline)
+    }
+
+    /// Ask for status over `control` until an answer shows up on `status`
+    async fn wait_until_ready(&self, service: &str, control: &str, status: &str) -> Result<()> {
+        let deadline = Instant::now() + self.timeout;
+        while self.messages_on(status).is_empty() {
+            self.mqtt.publish(control, QoS::AtLeastOnce, false, json!({ "command": "status" }).to_string()).await?;
+            self.tick(deadline, service).await?;
+        }
+        Ok(())
+    }
+
+    async fn publish(&self, topic: &str, payload: Value) -> Result<()> {
+        Ok(self.mqtt.publish(topic, QoS::AtLeastOnce, false, payload.to_string()).await?)
+    }
+
+    /// JSON payloads published on topics matching `filter` so far
+    fn messages_on(&self, filter: &str) -> Vec<Value> {
+        self.seen.lock().unwrap().iter()
+            .filter(|message| topic_matches(filter, &message.topic))
+            .filter_map(|message| message.json().ok())
+            .collect()
+    }
+
+    async fn wait_for_message(&self, filter: &str, matches: impl Fn(&Value) -> bool) -> Result<Value> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(message) = self.messages_on(filter).into_iter().find(|message| matches(message)) {
+                return Ok(message);
+            }
+            self.tick(deadline, &format!("a message on {}", filter)).await?;
+        }
+    }
+
+    /// Add a task for [`AGENT`] through the API, returning its id
+    async fn add_task(&self, description: &str, priority: &str) -> Result<String> {
+        let task: Value = self.http.post(format!("{}/api/agents/{}/tasks", self.api, AGENT))
+            .json(&json!({ "description": description, "priority": priority }))
+            .send().await?
+            .error_for_status()?
+            .json().await?;
+        task["id"].as_str().map(str::to_string).ok_or_else(|| anyhow!("API returned a task without an id: {}", task))
+    }
+
+    async fn tasks(&self) -> Result<Vec<Value>> {
+        Ok(self.http.get(format!("{}/api/agents/{}/tasks", self.api, AGENT))
+            .send().await?
+            .error_for_status()?
+            .json().await?)
+    }
+
+    async fn wait_for_task(&self, what: &str, matches: impl Fn(&Value) -> bool) -> Result<Value> {
+        let deadline = Instant::now() + self.timeout;
+        loop {
+            if let Some(task) = self.tasks().await?.into_iter().find(|task| matches(task)) {
+                return Ok(task);
+            }
+            self.tick(deadline, what).await?;
+        }
+    }
+
+    /// Wait a moment before looking again, or give up at `deadline`
+    async fn tick(&self, deadline: Instant, what: &str) -> Result<()> {
+        if Instant::now() >= deadline {
+            return Err(anyhow!("timed out after {}s waiting for {}", self.timeout.as_secs(), what));
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+        Ok(())
+    }
+}
+
+/// Answer classification requests the way project_worker does, filing
+/// everything under [`CLASSIFIED_PROJECT`]
+async fn spawn_classifier(client: &MqttService) -> Result<()> {
+    let mut requests = client.subscribe(CLASSIFY_REQUEST_TOPIC, QoS::AtLeastOnce).await?;
+    let client = client.clone();
+    tokio::spawn(async move {
+        while let Some(message) = requests.recv().await {
+            let request = parse_classification_request(&message.payload_str());
+            let topic = classify_response_topic(request.request_id.as_deref().unwrap_or_default());
+            let response = ProjectClassificationResponse {
+                project_name: CLASSIFIED_PROJECT.to_string(),
+                confidence: 1.0,
+                request_id: request.request_id,
+                reasoning: Some("swarm-e2e classifier".to_string()),
+            };
+            let payload = serde_json::to_string(&response).unwrap_or_default();
+            if let Err(e) = client.reply(&message, topic, payload).await {
+                eprintln!("Failed to answer classification request: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn intake_to_completion(pipeline: &Pipeline) -> Result<()> {
+    let description = "Write a haiku about the harbour at dawn";
+    let topic = format!("mcp/{}", AGENT);
+    let request = json!({
+        "description": description,
+        "source": "swarm-e2e",
+        "idempotency_key": "swarm-e2e-intake",
+    });
+    // The retry is a duplicate and must not become a second todo
+    pipeline.publish(&topic, request.clone()).await?;
+    pipeline.publish(&topic, request).await?;
+
+    let accepted = pipeline.wait_for_message(&format!("response/{}/todo", AGENT), |_| true).await?;
+    ensure!(accepted["project"] == CLASSIFIED_PROJECT, "intake filed the todo under {}", accepted["project"]);
+
+    let task = pipeline.wait_for_task("the intake todo to complete", |task| {
+        task["description"] == description && task["status"] == "completed"
+    }).await?;
+    ensure!(task["project"] == CLASSIFIED_PROJECT, "todo stored under {}", task["project"]);
+    let copies = pipeline.tasks().await?.iter().filter(|task| task["description"] == description).count();
+    ensure!(copies == 1, "the retried request made {} todos", copies);
+
+    let reply = pipeline.wait_for_message(&format!("agent/{}/todo/response", AGENT), |reply| reply["task_id"] == task["id"]).await?;
+    ensure!(reply["message"].as_str().is_some_and(|message| message.contains("haiku")), "unexpected reply {}", reply["message"]);
+    ensure!(pipeline.messages_on(&format!("response/{}/error", AGENT)).is_empty(), "intake reported an error");
+    Ok(())
+}
+
+async fn api_to_completion(pipeline: &Pipeline) -> Result<()> {
+    let id = pipeline.add_task("Show me the git history of this repository", "High").await?;
+
+    pipeline.wait_for_task("the API task to complete", |task| task["id"] == id.as_str() && task["status"] == "completed").await?;
+    let reply = pipeline.wait_for_message(&format!("agent/{}/todo/response", AGENT), |reply| reply["task_id"] == id.as_str()).await?;
+    ensure!(reply["message"].as_str().is_some_and(|message| message.contains("git")), "unexpected reply {}", reply["message"]);
+    Ok(())
+}
+
+async fn failure_to_dead_letter(pipeline: &Pipeline) -> Result<()> {
+    // Nothing in it routes to another agent, so the greeter asks the model
+    let id = pipeline.add_task("Tell me how your day went", "Low").await?;
+
+    pipeline.wait_for_task("the task to fail", |task| task["id"] == id.as_str() && task["status"] == "failed").await?;
+    pipeline.wait_for_message("todo/dead_letter", |letter| letter["task"]["id"] == id.as_str()).await?;
+    ensure!(
+        !pipeline.messages_on(&format!("agent/{}/todo/response", AGENT)).iter().any(|reply| reply["task_id"] == id.as_str()),
+        "the failed task was answered"
+    );
+    Ok(())
+}
+
+async fn drop_database(database: &str) -> Result<()> {
+    let uri = std::env::var("RTK_MONGO_URI")?;
+    mongodb::Client::with_uri_str(&uri).await?.database(database).drop(None).await?;
+    Ok(())
+}
@@ -5,6 +5,7 @@ use clap::{Parser, Subcommand};
 use serde_json::json;
 use tokio::time;
 use swarmonomicon::types::TaskPriority;
+use swarmonomicon::mqtt::MqttConfig;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -25,6 +26,10 @@ struct Cli {
     #[arg(short, long, default_value = "user")]
     target: String,
 
+    /// Topic namespace the workers run under (defaults to `MQTT_TOPIC_PREFIX`)
+    #[arg(long)]
+    topic_prefix: Option<String>,
+
     /// Command to execute
     #[command(subcommand)]
     command: Commands,
@@ -63,23 +68,17 @@ enum Commands {
 }
 
 fn parse_priority(priority_str: &str) -> TaskPriority {
-    match priority_str.to_lowercase().as_str() {
-        "inital" => TaskPriority::Inital,
-        "low" => TaskPriority::Low,
-        "medium" => TaskPriority::Medium,
-        "high" => TaskPriority::High,
-        "critical" => TaskPriority::Critical,
-        _ => TaskPriority::Medium,
-    }
+    TaskPriority::parse(priority_str).unwrap_or(TaskPriority::Medium)
 }
 
 async fn publish_todo(
     client: &AsyncClient, 
+    topics: &MqttConfig,
     target: &str, 
     description: &str, 
     priority: TaskPriority,
 ) -> Result<()> {
-    let topic = format!("mcp/{}", target);
+    let topic = topics.topic(&format!("mcp/{}", target));
     
     let payload = json!({
         "description": description,
@@ -92,8 +91,8 @@ async fn publish_todo(
         .map_err(|e| anyhow!("Failed to publish todo: {}", e))
 }
 
-async fn send_status_request(client: &AsyncClient, target: &str) -> Result<()> {
-    let topic = format!("{}/control", target);
+async fn send_status_request(client: &AsyncClient, topics: &MqttConfig, target: &str) -> Result<()> {
+    let topic = topics.topic(&format!("{}/control", target));
     
     let payload = json!({
         "command": "status",
@@ -105,8 +104,8 @@ async fn send_status_request(client: &AsyncClient, target: &str) -> Result<()> {
         .map_err(|e| anyhow!("Failed to request status: {}", e))
 }
 
-async fn send_shutdown_command(client: &AsyncClient, target: &str) -> Result<()> {
-    let topic = format!("{}/control", target);
+async fn send_shutdown_command(client: &AsyncClient, topics: &MqttConfig, target: &str) -> Result<()> {
+    let topic = topics.topic(&format!("{}/control", target));
     
     let payload = json!({
         "command": "shutdown",
@@ -120,6 +119,7 @@ async fn send_shutdown_command(client: &AsyncClient, target: &str) -> Result<()>
 
 async fn wait_for_response(
     event_loop: &mut rumqttc::EventLoop, 
+    topics: &MqttConfig,
     response_type: &str, 
     timeout_seconds: u64,
 ) -> Result<()> {
@@ -134,7 +134,7 @@ async fn wait_for_response(
         match time::timeout(Duration::from_secs(1), event_loop.poll()).await {
             Ok(Ok(notification)) => {
                 if let Event::Incoming(rumqttc::Packet::Publish(publish)) = notification {
-                    let topic = publish.topic;
+                    let topic = topics.strip_prefix(&publish.topic).unwrap_or(&publish.topic).to_string();
                     let payload = String::from_utf8_lossy(&publish.payload);
                     
                     println!("Received message on topic {}: {}", topic, payload);
@@ -162,6 +162,10 @@ async fn wait_for_response(
 #[tokio::main]
 async fn main() -> Result<()> {
     let cli = Cli::parse();
+    let topic_prefix = cli.topic_prefix.clone()
+        .or_else(|| std::env::var("MQTT_TOPIC_PREFIX").ok())
+        .unwrap_or_default();
+    let topics = MqttConfig::new(cli.client_id.clone(), cli.host.clone(), cli.port).with_topic_prefix(topic_prefix);
     
     // Set up MQTT client
     let mut mqtt_options = MqttOptions::new(cli.client_id, cli.host.clone(), cli.port);
@@ -172,12 +176,12 @@ async fn main() -> Result<()> {
     println!("Connected to MQTT broker at {}:{}", cli.host, cli.port);
     
     // Subscribe to response topics
-    client.subscribe("response/+/todo", QoS::ExactlyOnce).await?;
-    client.subscribe("response/+/error", QoS::ExactlyOnce).await?;
-    client.subscribe("agent/+/todo/response", QoS::ExactlyOnce).await?;
-    client.subscribe("agent/+/todo/error", QoS::ExactlyOnce).await?;
-    client.subscribe("response/mcp_server/status", QoS::ExactlyOnce).await?;
-    client.subscribe("response/todo_worker/status", QoS::ExactlyOnce).await?;
+    client.subscribe(topics.topic("response/+/todo"), QoS::ExactlyOnce).await?;
+    client.subscribe(topics.topic("response/+/error"), QoS::ExactlyOnce).await?;
+    client.subscribe(topics.topic("agent/+/todo/response"), QoS::ExactlyOnce).await?;
+    client.subscribe(topics.topic("agent/+/todo/error"), QoS::ExactlyOnce).await?;
+    client.subscribe(topics.topic("response/mcp_server/status"), QoS::ExactlyOnce).await?;
+    client.subscribe(topics.topic("response/todo_worker/status"), QoS::ExactlyOnce).await?;
     
     // For a system that uses ACK, wait a bit to ensure subscriptions are processed
     tokio::time::sleep(Duration::from_millis(500)).await;
@@ -185,27 +189,27 @@ async fn main() -> Result<()> {
     match &cli.command {
         Commands::Publish { description, priority, wait } => {
             let task_priority = parse_priority(priority);
-            publish_todo(&client, &cli.target, description, task_priority).await?;
+            publish_todo(&client, &topics, &cli.target, description, task_priority).await?;
             println!("Todo published successfully");
             
             if *wait > 0 {
                 println!("Waiting for completion (timeout: {} seconds)...", wait);
-                wait_for_response(&mut eventloop, "todo", *wait).await?;
+                wait_for_response(&mut eventloop, &topics, "todo", *wait).await?;
             }
         },
         Commands::Status { target } => {
-            send_status_request(&client, target).await?;
+            send_status_request(&client, &topics, target).await?;
             println!("Status request sent to {}", target);
             
             println!("Waiting for status response (timeout: 5 seconds)...");
-            wait_for_response(&mut eventloop, "status", 5).await?;
+            wait_for_response(&mut eventloop, &topics, "status", 5).await?;
         },
         Commands::Shutdown { target } => {
-            send_shutdown_command(&client, target).await?;
+            send_shutdown_command(&client, &topics, target).await?;
             println!("Shutdown command sent to {}", target);
             
             println!("Waiting for shutdown confirmation (timeout: 5 seconds)...");
-            wait_for_response(&mut eventloop, "status", 5).await?;
+            wait_for_response(&mut eventloop, &topics, "status", 5).await?;
         },
     }
     
@@ -4,11 +4,12 @@ use clap::Parser;
 use swarmonomicon::agents::rl::{
     Environment,
     flappy::{FlappyBirdEnv, FlappyBirdState, FlappyBirdAction, viz::FlappyViz},
-    model::config::{TrainingConfig, TrainingMetrics, TrainingHistory},
-    viz::VisualizationTools,
-    QLearningAgent,
+    model::config::{MetricsExporterKind, ModelFormat, SamplingStrategy, TrainingConfig, TrainingMetrics, TrainingHistory},
+    viz::{export::{exporters_for, MetricsExporter}, playback::TrajectoryPlayer, VisualizationTools},
+    Evaluator, QLearningAgent, ScoreStats, Trajectory,
 };
-use anyhow::Result;
+use swarmonomicon::Result;
+use swarmonomicon::error::SwarmError;
 use std::path::{PathBuf, Path};
 use winit::event_loop::{EventLoop, ControlFlow};
 use winit::window::WindowBuilder;
@@ -21,7 +22,7 @@ use std::io::{self, Write};
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Path to save/load the model
+    /// Directory to save/load model checkpoints
     #[arg(short, long)]
     model_path: Option<PathBuf>,
 
@@ -30,20 +31,93 @@ struct Args {
     visualize: bool,
 
     /// Number of episodes to train
-    #[arg(short, long, default_value = "1000")]
-    episodes: usize,
+    #[arg(short, long)]
+    episodes: Option<usize>,
 
-    /// Path to config file
+    /// Path to config file (JSON); created with the defaults if missing
     #[arg(short, long)]
     config: Option<PathBuf>,
 
     /// Path to save metrics and visualizations
-    #[arg(short = 'm', long)]
+    #[arg(long)]
     metrics_path: Option<PathBuf>,
+
+    /// Don't record metrics, reports or history
+    #[arg(long)]
+    no_metrics: bool,
+
+    /// Learning rate (alpha)
+    #[arg(long)]
+    learning_rate: Option<f64>,
+
+    /// Discount factor for future rewards (gamma)
+    #[arg(long)]
+    discount_factor: Option<f64>,
+
+    /// Starting exploration rate
+    #[arg(long)]
+    epsilon: Option<f64>,
+
+    /// Multiplier applied to epsilon after every episode
+    #[arg(long)]
+    epsilon_decay: Option<f64>,
+
+    /// Floor epsilon decays to
+    #[arg(long)]
+    min_epsilon: Option<f64>,
+
+    /// Save a checkpoint every this many episodes
+    #[arg(long)]
+    checkpoint_freq: Option<usize>,
+
+    /// Transitions kept for experience replay; 0 turns replay off
+    #[arg(long)]
+    replay_capacity: Option<usize>,
+
+    /// Transitions replayed after every step
+    #[arg(long)]
+    replay_batch_size: Option<usize>,
+
+    /// How replayed transitions are picked: uniform or prioritized
+    #[arg(long)]
+    replay_strategy: Option<SamplingStrategy>,
+
+    /// Use Double Q-learning
+    #[arg(long)]
+    double_q: bool,
+
+    /// Checkpoint format: json, msgpack or msgpack-zstd
+    #[arg(long)]
+    model_format: Option<ModelFormat>,
+
+    /// Stream per-episode metrics to these, comma separated: csv, jsonl, tensorboard
+    #[arg(long, value_delimiter = ',')]
+    export: Vec<MetricsExporterKind>,
     
     /// Resume training from the latest checkpoint
     #[arg(short, long)]
     resume: bool,
+
+    /// Instead of training, play this many greedy episodes with the loaded model and report its scores
+    #[arg(long)]
+    evaluate: Option<usize>,
+
+    /// With --evaluate, save every episode's trajectory to this JSON file
+    #[arg(long, requires = "evaluate")]
+    record_trajectories: Option<PathBuf>,
+
+    /// Render a greedy episode to a video in the metrics directory after training
+    /// or evaluating, attaching it to the report (needs the rl-render feature)
+    #[arg(long, value_parser = ["gif", "mp4"])]
+    render_video: Option<String>,
+
+    /// Instead of training, replay trajectories saved by --record-trajectories in a window
+    #[arg(long)]
+    replay: Option<PathBuf>,
+
+    /// With --replay, start at this episode's trajectory
+    #[arg(long, requires = "replay")]
+    replay_episode: Option<usize>,
     
     /// Number of latest checkpoints to keep
     #[arg(long, default_value = "5")]
@@ -54,9 +128,167 @@ struct Args {
     checkpoint_interval: usize,
 }
 
+impl Args {
+    /// Flags given on the command line win over the config file
+    fn apply(&self, config: &mut TrainingConfig) {
+        if let Some(episodes) = self.episodes {
+            config.episodes = episodes;
+        }
+        if self.visualize {
+            config.visualize = true;
+        }
+        if let Some(path) = &self.model_path {
+            config.checkpoint_path = path.to_string_lossy().to_string();
+        }
+        if let Some(path) = &self.metrics_path {
+            config.metrics_path = path.to_string_lossy().to_string();
+            config.save_metrics = true;
+        }
+        if self.no_metrics {
+            config.save_metrics = false;
+        }
+        if let Some(rate) = self.learning_rate {
+            config.learning_rate = rate;
+        }
+        if let Some(factor) = self.discount_factor {
+            config.discount_factor = factor;
+        }
+        if let Some(epsilon) = self.epsilon {
+            config.epsilon = epsilon;
+        }
+        if let Some(decay) = self.epsilon_decay {
+            config.epsilon_decay = decay;
+        }
+        if let Some(min) = self.min_epsilon {
+            config.min_epsilon = min;
+        }
+        if let Some(freq) = self.checkpoint_freq {
+            config.checkpoint_freq = freq.max(1);
+        }
+        if let Some(capacity) = self.replay_capacity {
+            config.replay.capacity = capacity;
+        }
+        if let Some(batch_size) = self.replay_batch_size {
+            config.replay.batch_size = batch_size;
+        }
+        if let Some(strategy) = self.replay_strategy {
+            config.replay.strategy = strategy;
+        }
+        if self.double_q {
+            config.double_q = true;
+        }
+        if let Some(format) = self.model_format {
+            config.model_format = format;
+        }
+        if !self.export.is_empty() {
+            config.metrics_exporters = self.export.clone();
+        }
+    }
+}
+
+/// Hand an episode's metrics to every exporter, reporting failures without stopping training
+fn export_metrics(exporters: &mut [Box<dyn MetricsExporter>], metrics: &TrainingMetrics) {
+    for exporter in exporters {
+        if let Err(e) = exporter.record(metrics) {
+            eprintln!("Error exporting metrics: {}", e);
+        }
+    }
+}
+
+/// Render `trajectory` into `dir` as a `format` video, for sharing results
+/// from machines without a display
+fn render_video(trajectory: &Trajectory<FlappyBirdState, FlappyBirdAction>, format: &str, dir: &Path) -> Result<PathBuf> {
+    #[cfg(feature = "rl-render")]
+    {
+        use swarmonomicon::agents::rl::{flappy::viz::render_episode, viz::video::{VideoFormat, VideoOptions}};
+        let format: VideoFormat = format.parse().map_err(SwarmError::Validation)?;
+        let path = dir.join(format!("episode_{}.{}", trajectory.episode, format.extension()));
+        render_episode(trajectory, &path, &VideoOptions { format, ..VideoOptions::default() })
+    }
+    #[cfg(not(feature = "rl-render"))]
+    {
+        let _ = (trajectory, format, dir);
+        Err(SwarmError::Unsupported("--render-video needs a build with --features rl-render".to_string()))
+    }
+}
+
+fn print_stats(name: &str, stats: &ScoreStats) {
+    println!(
+        "{:>7}: mean {:.2} (95% CI {:.2}..{:.2}), median {:.1}, min {:.1}, max {:.1}, std dev {:.2}",
+        name, stats.mean, stats.ci95_low, stats.ci95_high, stats.median, stats.min, stats.max, stats.std_dev
+    );
+}
+
+/// Play recorded trajectories frame by frame until the window is closed
+fn replay_trajectories(path: &Path, episode: Option<usize>) -> Result<()> {
+    let mut player = TrajectoryPlayer::<FlappyBirdState, FlappyBirdAction>::load(path)?;
+    if let Some(episode) = episode {
+        player.select_episode(episode)?;
+    }
+    println!("Loaded {} trajectories from {:?}", player.trajectory_count(), path);
+    println!("Space: pause, Left/Right: step, PageUp/PageDown: seek, Home/End: jump to start/end, N/P: next/previous trajectory");
+
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("Flappy Bird Replay")
+        .with_inner_size(winit::dpi::LogicalSize::new(288.0, 512.0))
+        .build(&event_loop)
+        .map_err(|e| SwarmError::Unsupported(format!("No window to replay in: {}", e)))?;
+    let mut viz = FlappyViz::new(&window);
+    let frame_time = Duration::from_secs_f64(1.0 / 60.0);
+    let mut last_frame = Instant::now();
+    let mut title = String::new();
+
+    event_loop.run(move |event, _, control_flow| {
+        *control_flow = ControlFlow::Poll;
+        if let Event::WindowEvent { event: winit::event::WindowEvent::CloseRequested, .. } = event {
+            *control_flow = ControlFlow::Exit;
+            return;
+        }
+        if !viz.playback_controls(&event, &mut player) {
+            return;
+        }
+        if last_frame.elapsed() >= frame_time {
+            player.tick();
+            last_frame = Instant::now();
+        }
+
+        let trajectory = player.trajectory();
+        let status = format!(
+            "Episode {} (score {}) - frame {}/{}{}{}",
+            trajectory.episode,
+            trajectory.score,
+            player.frame() + 1,
+            player.frame_count(),
+            player.action().map(|a| format!(" - {:?}", a)).unwrap_or_default(),
+            if player.is_paused() { " - paused" } else { "" },
+        );
+        if status != title {
+            window.set_title(&status);
+            title = status;
+        }
+        viz.render(player.state());
+    })
+}
+
+/// Write the history as `training_history.json` and `training_metrics.csv`
+fn save_history(history: &TrainingHistory, metrics_path: &str) {
+    let dir = PathBuf::from(metrics_path);
+    let json_path = dir.join("training_history.json");
+    if let Err(e) = history.save(&json_path) {
+        eprintln!("Error saving training history: {}", e);
+    }
+    if let Err(e) = history.save_csv(dir.join("training_metrics.csv")) {
+        eprintln!("Error saving training metrics CSV: {}", e);
+    }
+}
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<()> {
     let args = Args::parse();
+    if let Some(path) = &args.replay {
+        return replay_trajectories(path, args.replay_episode);
+    }
     
     // Load or create config
     let mut config = if let Some(config_path) = &args.config {
@@ -72,15 +304,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     };
     
     // Override config with command line args
-    config.episodes = args.episodes;
-    config.visualize = args.visualize;
-    if let Some(path) = &args.model_path {
-        config.checkpoint_path = path.to_string_lossy().to_string();
-    }
-    if let Some(path) = &args.metrics_path {
-        config.metrics_path = path.to_string_lossy().to_string();
-        config.save_metrics = true;
-    }
+    args.apply(&mut config);
     
     // Ensure directories exist
     fs::create_dir_all(&config.checkpoint_path).unwrap_or_default();
@@ -105,8 +329,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("Attempting to resume from latest checkpoint...");
             match QLearningAgent::<FlappyBirdState, FlappyBirdAction>::load_latest_checkpoint(&checkpoint_dir).await {
                 Ok(Some(mut agent)) => {
-                    // Extract training progress from the loaded model
-                    starting_episode = agent.metadata.episodes_trained;
+                    agent.configure(&config);
+                    // The checkpoint was saved after its episode finished
+                    starting_episode = agent.metadata.episodes_trained + 1;
                     best_score = agent.metadata.best_score as i32;
                     println!("Resuming from episode {}, best score: {}", starting_episode, best_score);
                     
@@ -126,29 +351,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
                 Ok(None) => {
                     println!("No checkpoint found. Starting new training.");
-                    QLearningAgent::new(
-                        config.learning_rate,
-                        config.discount_factor,
-                        config.epsilon,
-                    )
+                    QLearningAgent::from_config(&config)
                 }
                 Err(e) => {
                     println!("Error loading checkpoint: {}. Starting new training.", e);
-                    QLearningAgent::new(
-                        config.learning_rate,
-                        config.discount_factor,
-                        config.epsilon,
-                    )
+                    QLearningAgent::from_config(&config)
                 }
             }
         } else if model_path.exists() {
             // Load from specific model path
             println!("Loading model from: {:?}", model_path);
-            let mut agent = QLearningAgent::new(
-                config.learning_rate,
-                config.discount_factor,
-                config.epsilon,
-            );
+            let mut agent = QLearningAgent::from_config(&config);
             match agent.load_model(&model_path).await {
                 Ok(_) => {
                     println!("Model loaded successfully");
@@ -156,27 +369,45 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
                 Err(e) => {
                     println!("Error loading model: {}. Starting new training.", e);
-                    QLearningAgent::new(
-                        config.learning_rate,
-                        config.discount_factor,
-                        config.epsilon,
-                    )
+                    QLearningAgent::from_config(&config)
                 }
             }
         } else {
             // Create new agent
             println!("Starting new training.");
-            QLearningAgent::new(
-                config.learning_rate,
-                config.discount_factor,
-                config.epsilon,
-            )
+            QLearningAgent::from_config(&config)
         }
     }));
 
+    if let Some(episodes) = args.evaluate {
+        let agent = agent.lock().unwrap();
+        println!("Evaluating {} episodes with a greedy policy...", episodes);
+        let report = Evaluator::new(episodes)
+            .with_recording(args.record_trajectories.is_some() || args.render_video.is_some())
+            .run(&mut FlappyBirdEnv::default(), &agent);
+        print_stats("Score", &report.score_stats);
+        print_stats("Reward", &report.reward_stats);
+        if let Some(path) = &args.record_trajectories {
+            Trajectory::save_all(&report.trajectories, path)?;
+            println!("Trajectories saved to {:?}", path);
+        }
+        if let Some(format) = &args.render_video {
+            if let Some(best) = report.trajectories.iter().max_by_key(|t| t.score) {
+                let path = render_video(best, format, Path::new(&config.metrics_path))?;
+                println!("Best episode rendered to {:?}", path);
+            }
+        }
+        return Ok(());
+    }
+
     // Create visualization tools if metrics are enabled
     let viz_tools = if config.save_metrics {
-        Some(VisualizationTools::new(&config.metrics_path))
+        let viz_tools = VisualizationTools::new(&config.metrics_path);
+        let run = format!("flappy-{}", chrono::Utc::now().format("%Y%m%dT%H%M%S"));
+        Some(match swarmonomicon::tools::artifact_store_or_none() {
+            Some(artifacts) => viz_tools.with_artifact_store(artifacts, run),
+            None => viz_tools,
+        })
     } else {
         None
     };
@@ -185,11 +416,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let running = Arc::new(Mutex::new(true));
     let r = running.clone();
     
-    ctrlc::set_handler(move || {
-        println!("\nReceived Ctrl+C, saving checkpoint before exiting...");
-        let mut running = r.lock().unwrap();
-        *running = false;
-    }).expect("Error setting Ctrl+C handler");
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            println!("\nReceived Ctrl+C, saving checkpoint before exiting...");
+            let mut running = r.lock().unwrap();
+            *running = false;
+        }
+    });
+
+    let mut exporters = if config.save_metrics { exporters_for(&config) } else { Vec::new() };
+
+    let target_fps = 60.0;
+    let frame_time = Duration::from_secs_f64(1.0 / target_fps);
 
     if config.visualize {
         // Training with visualization
@@ -208,30 +446,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let checkpoint_dir_clone = checkpoint_dir.clone();
         let viz_tools_clone = viz_tools.clone();
         let running_clone = running.clone();
+        let runtime = tokio::runtime::Handle::current();
         
         let mut current_episode = starting_episode;
         
         event_loop.run(move |event, _, control_flow| {
-            // Check for shutdown signal
-            if !*running_clone.lock().unwrap() {
-                // Save final checkpoint
-                let final_checkpoint_path = checkpoint_dir_clone.join("final_checkpoint.json");
+            // Stop on Ctrl+C or once every episode has run, saving either way
+            if !*running_clone.lock().unwrap() || current_episode >= config_clone.episodes {
+                let final_model_path = checkpoint_dir_clone.join("final_model.json");
                 futures::executor::block_on(async {
-                    let agent = agent_clone.lock().unwrap();
-                    if let Err(e) = agent.save_model(&final_checkpoint_path).await {
-                        eprintln!("Error saving final checkpoint: {}", e);
+                    let agent = agent_clone.lock().unwrap().clone();
+                    if let Err(e) = agent.save_model(&final_model_path).await {
+                        eprintln!("Error saving final model: {}", e);
                     } else {
-                        println!("Final checkpoint saved at {:?}", final_checkpoint_path);
+                        println!("\nFinal model saved at {:?}", final_model_path);
                     }
                 });
                 
-                // Save training history
-                let history_path = PathBuf::from(&config_clone.metrics_path).join("training_history.json");
-                let history = history_clone.lock().unwrap();
-                if let Err(e) = history.save(&history_path) {
-                    eprintln!("Error saving training history: {}", e);
-                } else {
-                    println!("Training history saved at {:?}", history_path);
+                if config_clone.save_metrics {
+                    save_history(&history_clone.lock().unwrap(), &config_clone.metrics_path);
+                    println!("Training history saved to {}", config_clone.metrics_path);
                 }
                 
                 *control_flow = ControlFlow::Exit;
@@ -242,11 +476,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             match event {
                 Event::MainEventsCleared => {
-                    if current_episode >= config_clone.episodes {
-                        *control_flow = ControlFlow::Exit;
-                        return;
-                    }
-                    
                     let frame_start = Instant::now();
                     
                     // Training loop for one episode
@@ -267,7 +496,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         
                         {
                             let mut agent = agent_clone.lock().unwrap();
-                            agent.update(&current_state, &action, reward, &next_state);
+                            let next_actions = if is_done { Vec::new() } else { env.valid_actions(&next_state) };
+                            agent.update(&current_state, &action, reward, &next_state, &next_actions);
                         }
                         
                         episode_reward += reward;
@@ -296,7 +526,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         io::stdout().flush().unwrap();
                     }
                     
-                    total_reward += episode_reward;
                     
                     // Decay epsilon
                     {
@@ -308,7 +537,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     if config_clone.save_metrics {
                         let avg_q = {
                             let agent = agent_clone.lock().unwrap();
-                            agent.calculate_avg_q_value(&current_state)
+                            agent.calculate_avg_q_value(&current_state, &env.valid_actions(&current_state))
                         };
                         
                         let epsilon = {
@@ -325,6 +554,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             avg_q_value: avg_q,
                         };
                         
+                        export_metrics(&mut exporters, &metrics);
                         {
                             let mut history = history_clone.lock().unwrap();
                             history.add_metrics(metrics);
@@ -338,6 +568,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         let checkpoint_dir_for_save = checkpoint_dir_clone.clone();
                         let history_for_report = history_clone.clone();
                         let viz_tools_for_report = viz_tools_clone.clone();
+                        let runtime_for_report = runtime.clone();
+                        let metrics_path_for_report = config_clone.metrics_path.clone();
+                        let keep_checkpoints = args.keep_checkpoints;
+                        let checkpoint_interval = args.checkpoint_interval;
                         
                         // Use a blocking thread to avoid disrupting the event loop
                         std::thread::spawn(move || {
@@ -360,8 +594,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             // Clean up old checkpoints
                             match QLearningAgent::<FlappyBirdState, FlappyBirdAction>::clean_old_checkpoints(
                                 &checkpoint_dir_for_save, 
-                                args.keep_checkpoints, 
-                                Some(args.checkpoint_interval)
+                                keep_checkpoints, 
+                                Some(checkpoint_interval)
                             ) {
                                 Ok(deleted) => {
                                     if deleted > 0 {
@@ -374,15 +608,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                             // Generate a report if metrics are enabled
                             if let Some(viz_tools) = viz_tools_for_report {
                                 let history = history_for_report.lock().unwrap();
-                                if let Ok(report_path) = viz_tools.generate_report(&history) {
-                                    println!("Training report generated at {:?}", report_path);
+                                match runtime_for_report.block_on(viz_tools.publish_report(&history)) {
+                                    Ok(report) => println!("Training report generated at {}", report),
+                                    Err(e) => eprintln!("Error generating training report: {}", e),
                                 }
                                 
-                                // Save the training history
-                                let history_path = PathBuf::from(&config_clone.metrics_path).join("training_history.json");
-                                if let Err(e) = history.save(&history_path) {
-                                    eprintln!("Error saving training history: {}", e);
-                                }
+                                save_history(&history, &metrics_path_for_report);
                             }
                         });
                     }
@@ -399,10 +630,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         });
     } else {
         // Command-line training without visualization
-        let target_fps = 60.0;
-        let frame_time = Duration::from_secs_f64(1.0 / target_fps);
-        let mut total_reward = 0.0;
-        
         let episodes_range = starting_episode..config.episodes;
         let progress_step = config.episodes / 100;
         let progress_step = if progress_step == 0 { 1 } else { progress_step };
@@ -415,37 +642,42 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 break;
             }
             
-            // Training loop for one episode
-            let mut env = env.lock().unwrap();
-            let state = env.reset();
-            let mut current_state = state;
-            let mut done = false;
-            let mut episode_reward = 0.0;
-            let mut steps = 0;
-
-            while !done {
-                let valid_actions = env.valid_actions(&current_state);
-                let action = {
-                    let mut agent = agent.lock().unwrap();
-                    agent.choose_action(&current_state, &valid_actions)
-                };
-                let (next_state, reward, is_done) = env.step(&action);
+            // Training loop for one episode; the environment is only locked while it runs
+            let (current_state, final_actions, episode_reward, steps, score) = {
+                let mut env = env.lock().unwrap();
+                let state = env.reset();
+                let mut current_state = state;
+                let mut done = false;
+                let mut episode_reward = 0.0;
+                let mut steps = 0;
+
+                while !done {
+                    let valid_actions = env.valid_actions(&current_state);
+                    let action = {
+                        let mut agent = agent.lock().unwrap();
+                        agent.choose_action(&current_state, &valid_actions)
+                    };
+                    let (next_state, reward, is_done) = env.step(&action);
                 
-                {
-                    let mut agent = agent.lock().unwrap();
-                    agent.update(&current_state, &action, reward, &next_state);
-                }
+                    {
+                        let mut agent = agent.lock().unwrap();
+                        let next_actions = if is_done { Vec::new() } else { env.valid_actions(&next_state) };
+                        agent.update(&current_state, &action, reward, &next_state, &next_actions);
+                    }
                 
-                episode_reward += reward;
-                done = is_done;
-                current_state = next_state.clone();
-                steps += 1;
+                    episode_reward += reward;
+                    done = is_done;
+                    current_state = next_state.clone();
+                    steps += 1;
 
-                // Sleep to prevent CPU overuse
-                std::thread::sleep(Duration::from_millis(1));
-            }
+                    // Sleep to prevent CPU overuse
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+
+                let final_actions = env.valid_actions(&current_state);
+                (current_state, final_actions, episode_reward, steps, env.get_score())
+            };
 
-            let score = env.get_score();
             let is_best = score > best_score;
             if is_best {
                 best_score = score;
@@ -457,8 +689,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 io::stdout().flush().unwrap();
             }
             
-            total_reward += episode_reward;
-            
             // Decay epsilon
             {
                 let mut agent = agent.lock().unwrap();
@@ -469,7 +699,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             if config.save_metrics {
                 let avg_q = {
                     let agent = agent.lock().unwrap();
-                    agent.calculate_avg_q_value(&current_state)
+                    agent.calculate_avg_q_value(&current_state, &final_actions)
                 };
                 
                 let epsilon = {
@@ -486,25 +716,27 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     avg_q_value: avg_q,
                 };
                 
+                export_metrics(&mut exporters, &metrics);
                 history.add_metrics(metrics);
             }
             
             // Checkpoint if needed
             if current_episode % config.checkpoint_freq == 0 || is_best {
-                // Update agent metadata
-                {
+                // Update agent metadata, then save a snapshot without holding the lock
+                let snapshot = {
                     let mut agent_lock = agent.lock().unwrap();
                     agent_lock.update_metadata(
                         Some(current_episode),
                         Some(best_score as f64),
                         None
                     );
-                    
-                    // Save the checkpoint
-                    match agent_lock.save_checkpoint(&checkpoint_dir, current_episode, is_best).await {
-                        Ok(path) => println!("\nCheckpoint saved at {:?}", path),
-                        Err(e) => eprintln!("\nError saving checkpoint: {}", e),
-                    }
+                    agent_lock.clone()
+                };
+
+                // Save the checkpoint
+                match snapshot.save_checkpoint(&checkpoint_dir, current_episode, is_best).await {
+                    Ok(path) => println!("\nCheckpoint saved at {:?}", path),
+                    Err(e) => eprintln!("\nError saving checkpoint: {}", e),
                 }
                 
                 // Clean up old checkpoints
@@ -523,37 +755,47 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 
                 // Generate a report if metrics are enabled
                 if let Some(viz_tools) = &viz_tools {
-                    if let Ok(report_path) = viz_tools.generate_report(&history) {
-                        println!("Training report generated at {:?}", report_path);
+                    match viz_tools.publish_report(&history).await {
+                        Ok(report) => println!("Training report generated at {}", report),
+                        Err(e) => eprintln!("Error generating training report: {}", e),
                     }
                     
-                    // Save the training history
-                    let history_path = PathBuf::from(&config.metrics_path).join("training_history.json");
-                    if let Err(e) = history.save(&history_path) {
-                        eprintln!("Error saving training history: {}", e);
-                    }
+                    save_history(&history, &config.metrics_path);
                 }
             }
         }
         
         // Save final model
         let final_model_path = checkpoint_dir.join("final_model.json");
-        {
-            let agent_lock = agent.lock().unwrap();
-            match agent_lock.save_model(&final_model_path).await {
-                Ok(_) => println!("\nFinal model saved at {:?}", final_model_path),
-                Err(e) => eprintln!("\nError saving final model: {}", e),
-            }
+        let snapshot = agent.lock().unwrap().clone();
+        match snapshot.save_model(&final_model_path).await {
+            Ok(_) => println!("\nFinal model saved at {:?}", final_model_path),
+            Err(e) => eprintln!("\nError saving final model: {}", e),
         }
         
+        // Render a greedy episode of the final policy and attach it to the report
+        if let Some(format) = &args.render_video {
+            let evaluation = Evaluator::new(1)
+                .with_recording(true)
+                .run(&mut FlappyBirdEnv::default(), &agent.lock().unwrap());
+            match render_video(&evaluation.trajectories[0], format, Path::new(&config.metrics_path)) {
+                Ok(path) => {
+                    println!("Episode rendered to {:?}", path);
+                    if let Some(viz_tools) = &viz_tools {
+                        match viz_tools.clone().with_episode_video(&path).publish_report(&history).await {
+                            Ok(report) => println!("Training report generated at {}", report),
+                            Err(e) => eprintln!("Error generating training report: {}", e),
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Error rendering episode: {}", e),
+            }
+        }
+
         // Save final training history
         if config.save_metrics {
-            let history_path = PathBuf::from(&config.metrics_path).join("training_history.json");
-            if let Err(e) = history.save(&history_path) {
-                eprintln!("Error saving training history: {}", e);
-            } else {
-                println!("Training history saved at {:?}", history_path);
-            }
+            save_history(&history, &config.metrics_path);
+            println!("Training history saved to {}", config.metrics_path);
         }
         
         println!("\nTraining complete. Total episodes: {}, Best score: {}", config.episodes, best_score);
@@ -562,6 +804,36 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_override_config() {
+        let args = Args::parse_from(["train_flappy", "--episodes", "50", "--epsilon-decay", "0.99", "--no-metrics"]);
+        let mut config = TrainingConfig { learning_rate: 0.3, ..TrainingConfig::default() };
+        args.apply(&mut config);
+        assert_eq!(config.episodes, 50);
+        assert_eq!(config.epsilon_decay, 0.99);
+        assert_eq!(config.learning_rate, 0.3);
+        assert!(!config.save_metrics);
+
+        let args = Args::parse_from(["train_flappy", "--replay-capacity", "5000", "--replay-strategy", "prioritized", "--double-q"]);
+        args.apply(&mut config);
+        assert_eq!(config.replay.capacity, 5000);
+        assert_eq!(config.replay.strategy, SamplingStrategy::Prioritized);
+        assert!(config.double_q);
+
+        let args = Args::parse_from(["train_flappy", "--model-format", "msgpack-zstd", "--export", "csv,tensorboard"]);
+        args.apply(&mut config);
+        assert_eq!(config.model_format, ModelFormat::MessagePackZstd);
+        assert_eq!(config.metrics_exporters, vec![MetricsExporterKind::Csv, MetricsExporterKind::Tensorboard]);
+
+        assert_eq!(Args::parse_from(["train_flappy", "--render-video", "mp4"]).render_video.as_deref(), Some("mp4"));
+        assert!(Args::try_parse_from(["train_flappy", "--render-video", "avi"]).is_err());
+    }
+}
+
 #[cfg(not(feature = "rl"))]
 fn main() {
     println!("This binary requires the 'rl' feature to be enabled.");
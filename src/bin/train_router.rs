@@ -0,0 +1,39 @@
+use std::path::PathBuf;
+use anyhow::Result;
+use clap::Parser;
+use swarmonomicon::agents::rl::routing::{LearnedRouter, OutcomeLog};
+
+/// Train the experimental agent router from logged transfer outcomes
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Transfer outcomes to learn from; defaults to ROUTING_OUTCOME_LOG
+    #[arg(long)]
+    log: Option<PathBuf>,
+
+    /// Where to save the policy; point ROUTING_POLICY_PATH here to use it
+    #[arg(long, default_value = "data/router.json")]
+    output: PathBuf,
+
+    /// One-step episodes to replay the log for
+    #[arg(long, default_value = "5000")]
+    episodes: usize,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+    let log = args.log.map(OutcomeLog::new)
+        .or_else(OutcomeLog::from_env)
+        .ok_or_else(|| anyhow::anyhow!("Pass --log or set ROUTING_OUTCOME_LOG"))?;
+
+    let outcomes = log.load()?;
+    let successes = outcomes.iter().filter(|outcome| outcome.success).count();
+    println!("Training on {} transfer outcomes ({} successful) from {:?}", outcomes.len(), successes, log.path());
+
+    let router = LearnedRouter::train(outcomes, args.episodes).await?;
+    router.save(&args.output).await?;
+    println!("Routing policy saved to {:?}", args.output);
+    Ok(())
+}
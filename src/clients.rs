@@ -0,0 +1,154 @@
+//! Connection pools shared by everything in the process. Each MongoDB URI
+//! gets one driver client, and with it one connection pool, however many
+//! todo lists, stores and logs open collections on it. Plain HTTP calls go
+//! through one pooled `reqwest::Client`.
+
+use std::collections::HashMap;
+use std::env;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use futures_util::FutureExt;
+use mongodb::options::ClientOptions;
+
+/// Pool sizes for the shared clients
+#[derive(Debug, Clone, PartialEq)]
+pub struct PoolSettings {
+    /// Connections per MongoDB server; the driver's default (10) when unset
+    pub mongo_max_pool_size: Option<u32>,
+    /// Connections kept open to each MongoDB server even when idle
+    pub mongo_min_pool_size: Option<u32>,
+    /// Idle HTTP connections kept per host
+    pub http_max_idle_per_host: usize,
+}
+
+impl Default for PoolSettings {
+    fn default() -> Self {
+        Self { mongo_max_pool_size: None, mongo_min_pool_size: None, http_max_idle_per_host: 16 }
+    }
+}
+
+impl PoolSettings {
+    /// Reads `MONGO_MAX_POOL_SIZE`, `MONGO_MIN_POOL_SIZE` and `HTTP_POOL_MAX_IDLE`
+    pub fn from_env() -> Self {
+        let var = |name: &str| env::var(name).ok().and_then(|s| s.parse().ok());
+        let defaults = Self::default();
+        Self {
+            mongo_max_pool_size: var("MONGO_MAX_POOL_SIZE"),
+            mongo_min_pool_size: var("MONGO_MIN_POOL_SIZE"),
+            http_max_idle_per_host: var("HTTP_POOL_MAX_IDLE").map(|n: u32| n as usize).unwrap_or(defaults.http_max_idle_per_host),
+        }
+    }
+}
+
+/// Lazily created clients, shared by clones
+#[derive(Debug, Clone)]
+pub struct Clients {
+    settings: Arc<PoolSettings>,
+    http: Arc<OnceLock<reqwest::Client>>,
+    mongo: Arc<Mutex<HashMap<String, mongodb::Client>>>,
+}
+
+static SHARED: OnceLock<Clients> = OnceLock::new();
+
+impl Clients {
+    pub fn new(settings: PoolSettings) -> Self {
+        Self {
+            settings: Arc::new(settings),
+            http: Arc::new(OnceLock::new()),
+            mongo: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(PoolSettings::from_env())
+    }
+
+    /// Process-wide clients configured from the environment
+    pub fn shared() -> Self {
+        SHARED.get_or_init(Self::from_env).clone()
+    }
+
+    /// Make `clients` the process-wide ones. Call before anything uses
+    /// [`shared`](Self::shared); later calls keep the first.
+    pub fn init_shared(clients: Clients) -> Self {
+        SHARED.get_or_init(|| clients).clone()
+    }
+
+    pub fn settings(&self) -> &PoolSettings {
+        &self.settings
+    }
+
+    /// The pooled HTTP client. Callers that need a timeout set it per request.
+    pub fn http(&self) -> reqwest::Client {
+        self.http.get_or_init(|| {
+            reqwest::Client::builder()
+                .pool_max_idle_per_host(self.settings.http_max_idle_per_host)
+                .pool_idle_timeout(Duration::from_secs(90))
+                .connect_timeout(Duration::from_secs(5))
+                .build()
+                .unwrap_or_default()
+        }).clone()
+    }
+
+    /// The driver client for `uri`, created on first use. A `mongodb://` URI
+    /// is parsed in place and the driver connects in the background, so
+    /// nothing blocks. A `mongodb+srv://` URI needs DNS lookups first; those
+    /// run via `block_in_place` on a multi-threaded runtime, and block the
+    /// caller otherwise.
+    pub fn mongo(&self, uri: &str) -> mongodb::error::Result<mongodb::Client> {
+        if let Some(client) = self.mongo.lock().unwrap().get(uri) {
+            return Ok(client.clone());
+        }
+
+        let mut options = match ClientOptions::parse(uri).now_or_never() {
+            Some(options) => options?,
+            None => wait_for(ClientOptions::parse(uri))?,
+        };
+        if let Some(size) = self.settings.mongo_max_pool_size {
+            options.max_pool_size = Some(size);
+        }
+        if let Some(size) = self.settings.mongo_min_pool_size {
+            options.min_pool_size = Some(size);
+        }
+        let client = mongodb::Client::with_options(options)?;
+
+        // Another caller may have got here first, in which case theirs is kept
+        Ok(self.mongo.lock().unwrap().entry(uri.to_string()).or_insert(client).clone())
+    }
+
+    /// Database `name` on the server at `uri`
+    pub fn database(&self, uri: &str, name: &str) -> mongodb::error::Result<mongodb::Database> {
+        Ok(self.mongo(uri)?.database(name))
+    }
+}
+
+/// Finish `future` from synchronous code, letting the runtime move other
+/// tasks off this worker while it waits when it can
+pub(crate) fn wait_for<F: Future>(future: F) -> F::Output {
+    use tokio::runtime::{Handle, RuntimeFlavor};
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(|| handle.block_on(future))
+        }
+        _ => futures::executor::block_on(future),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_one_pool_per_uri() -> anyhow::Result<()> {
+        let clients = Clients::new(PoolSettings { mongo_max_pool_size: Some(4), ..PoolSettings::default() });
+        clients.mongo("mongodb://localhost:27017")?;
+        clients.clone().mongo("mongodb://localhost:27017")?;
+        clients.mongo("mongodb://127.0.0.1:27018")?;
+        assert_eq!(clients.mongo.lock().unwrap().len(), 2);
+
+        assert!(clients.mongo("not a uri").is_err());
+        assert_eq!(clients.mongo.lock().unwrap().len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,145 @@
+//! Time and id sources. The worker, the scheduler, dedup and the todo tooling
+//! take a [`Clock`] and an [`IdGenerator`] instead of calling `Utc::now`,
+//! `Instant::now` or `Uuid::new_v4` directly, so tests can pin them with
+//! [`FakeClock`] and [`SequentialIds`].
+
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Where the current time comes from
+pub trait Clock: Send + Sync + Debug {
+    /// Wall-clock time, for timestamps that are stored or compared across processes
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Monotonic time, for measuring windows and durations in this process
+    fn instant(&self) -> Instant;
+
+    /// `now` in Unix seconds, the form todos store their times in
+    fn timestamp(&self) -> i64 {
+        self.now().timestamp()
+    }
+}
+
+/// Where new task, message and lease ids come from
+pub trait IdGenerator: Send + Sync + Debug {
+    fn next_id(&self) -> String;
+}
+
+/// The real time
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// Random v4 UUIDs
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UuidIds;
+
+impl IdGenerator for UuidIds {
+    fn next_id(&self) -> String {
+        Uuid::new_v4().to_string()
+    }
+}
+
+/// The clock everything uses unless given another
+pub fn system() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
+
+/// The ids everything uses unless given others
+pub fn uuids() -> Arc<dyn IdGenerator> {
+    Arc::new(UuidIds)
+}
+
+/// A clock that only moves when told to. Both `now` and `instant` advance
+/// together, so wall-clock and monotonic logic see the same elapsed time.
+#[derive(Debug)]
+pub struct FakeClock {
+    start: DateTime<Utc>,
+    base: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl FakeClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self { start, base: Instant::now(), elapsed: Mutex::new(Duration::ZERO) }
+    }
+
+    /// A clock starting at Unix time `secs`
+    pub fn at(secs: i64) -> Self {
+        Self::new(DateTime::from_timestamp(secs, 0).expect("timestamp in range"))
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.elapsed.lock().unwrap() += by;
+    }
+
+    fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.start + chrono::Duration::from_std(self.elapsed()).expect("elapsed time in range")
+    }
+
+    fn instant(&self) -> Instant {
+        self.base + self.elapsed()
+    }
+}
+
+/// Ids `prefix-1`, `prefix-2`, ... in order
+#[derive(Debug)]
+pub struct SequentialIds {
+    prefix: String,
+    next: AtomicU64,
+}
+
+impl SequentialIds {
+    pub fn new(prefix: impl Into<String>) -> Self {
+        Self { prefix: prefix.into(), next: AtomicU64::new(1) }
+    }
+}
+
+impl IdGenerator for SequentialIds {
+    fn next_id(&self) -> String {
+        format!("{}-{}", self.prefix, self.next.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fake_clock() {
+        let clock = FakeClock::at(1_700_000_000);
+        let started = clock.instant();
+        assert_eq!(clock.timestamp(), 1_700_000_000);
+
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(clock.timestamp(), 1_700_000_090);
+        assert_eq!(clock.instant().duration_since(started), Duration::from_secs(90));
+    }
+
+    #[test]
+    fn test_sequential_ids() {
+        let ids = SequentialIds::new("task");
+        assert_eq!(ids.next_id(), "task-1");
+        assert_eq!(ids.next_id(), "task-2");
+        assert_ne!(UuidIds.next_id(), UuidIds.next_id());
+    }
+}
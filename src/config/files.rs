@@ -0,0 +1,296 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::error::SwarmError;
+use crate::types::Tool;
+use crate::Result;
+use super::{AgentSet, ConfigManager};
+
+/// Subdirectory holding one file per agent set
+pub const AGENT_SETS_DIR: &str = "agent_sets";
+/// Subdirectory holding one file per tool template, named after the template
+pub const TOOLS_DIR: &str = "tools";
+
+/// On-disk format of a config directory file, chosen by extension
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigFormat {
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "toml" => Some(ConfigFormat::Toml),
+            "yaml" | "yml" => Some(ConfigFormat::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ConfigFormat::Toml => "toml",
+            ConfigFormat::Yaml => "yaml",
+        }
+    }
+
+    fn parse<T: DeserializeOwned>(&self, contents: &str) -> std::result::Result<T, String> {
+        match self {
+            ConfigFormat::Toml => toml::from_str(contents).map_err(|e| e.to_string().trim_end().to_string()),
+            ConfigFormat::Yaml => serde_yaml::from_str(contents).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn render<T: Serialize>(&self, value: &T) -> std::result::Result<String, String> {
+        match self {
+            ConfigFormat::Toml => toml::to_string_pretty(value).map_err(|e| e.to_string()),
+            ConfigFormat::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Problems with a tool definition: names must be snake_case identifiers and
+/// the tool and every parameter need a description
+pub fn validate_tool(tool: &Tool) -> Vec<String> {
+    let mut problems = Vec::new();
+    if !is_identifier(&tool.name) {
+        problems.push(format!("tool name '{}' must be snake_case", tool.name));
+    }
+    if tool.description.trim().is_empty() {
+        problems.push(format!("tool '{}' has no description", tool.name));
+    }
+    let mut parameters: Vec<_> = tool.parameters.iter().collect();
+    parameters.sort();
+    for (name, description) in parameters {
+        if !is_identifier(name) {
+            problems.push(format!("tool '{}' parameter '{}' must be snake_case", tool.name, name));
+        }
+        if description.trim().is_empty() {
+            problems.push(format!("tool '{}' parameter '{}' has no description", tool.name, name));
+        }
+    }
+    problems
+}
+
+/// Problems with an agent set: duplicate or dangling agent names, invalid
+/// tools, and state machines whose states don't line up
+pub fn validate_agent_set(agent_set: &AgentSet) -> Vec<String> {
+    let mut problems = Vec::new();
+    if agent_set.name.trim().is_empty() {
+        problems.push("agent set has no name".to_string());
+    }
+    if agent_set.agents.is_empty() {
+        problems.push(format!("agent set '{}' has no agents", agent_set.name));
+    }
+
+    let mut names = HashSet::new();
+    for agent in &agent_set.agents {
+        if agent.name.trim().is_empty() {
+            problems.push(format!("agent set '{}' has an agent with no name", agent_set.name));
+        } else if !names.insert(agent.name.as_str()) {
+            problems.push(format!("agent '{}' is defined more than once", agent.name));
+        }
+    }
+
+    for agent in &agent_set.agents {
+        for downstream in &agent.downstream_agents {
+            if !names.contains(downstream.as_str()) {
+                problems.push(format!("agent '{}' hands off to unknown agent '{}'", agent.name, downstream));
+            }
+        }
+        for tool in &agent.tools {
+            problems.extend(validate_tool(tool).into_iter().map(|p| format!("agent '{}': {}", agent.name, p)));
+        }
+        if let Some(machine) = &agent.state_machine {
+            if !machine.states.contains_key(&machine.initial_state) {
+                problems.push(format!("agent '{}' starts in unknown state '{}'", agent.name, machine.initial_state));
+            }
+            let mut states: Vec<_> = machine.states.iter().collect();
+            states.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, state) in states {
+                let mut targets: Vec<_> = state.transitions.iter().flatten().map(|(_, target)| target).collect();
+                targets.sort();
+                for target in targets {
+                    if !machine.states.contains_key(target) {
+                        problems.push(format!("agent '{}' state '{}' transitions to unknown state '{}'", agent.name, name, target));
+                    }
+                }
+            }
+        }
+    }
+    problems
+}
+
+/// Config files in `dir`, sorted; a missing directory has none
+fn config_files(dir: &Path) -> Result<Vec<(PathBuf, ConfigFormat)>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter_map(|path| ConfigFormat::from_path(&path).map(|format| (path, format)))
+        .collect();
+    files.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(files)
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default()
+}
+
+impl ConfigManager {
+    /// Read `agent_sets/*` and `tools/*` (TOML or YAML) under `dir`. Fails
+    /// with every problem [`lint_dir`](Self::lint_dir) would report.
+    pub fn load_from_dir(dir: &Path) -> Result<Self> {
+        let (manager, problems) = Self::read_dir(dir)?;
+        if !problems.is_empty() {
+            return Err(SwarmError::Validation(problems.join("; ")));
+        }
+        Ok(manager)
+    }
+
+    /// Everything wrong with the config directory, each prefixed with its file.
+    /// Only a missing or unreadable `dir` is an error.
+    pub fn lint_dir(dir: &Path) -> Result<Vec<String>> {
+        Ok(Self::read_dir(dir)?.1)
+    }
+
+    fn read_dir(dir: &Path) -> Result<(Self, Vec<String>)> {
+        if !dir.is_dir() {
+            return Err(SwarmError::NotFound(format!("config directory {}", dir.display())));
+        }
+        let mut manager = Self::new();
+        let mut problems = Vec::new();
+
+        for (path, format) in config_files(&dir.join(TOOLS_DIR))? {
+            let prefix = path.display().to_string();
+            match format.parse::<Tool>(&fs::read_to_string(&path)?) {
+                Ok(tool) => {
+                    problems.extend(validate_tool(&tool).into_iter().map(|p| format!("{}: {}", prefix, p)));
+                    manager.register_tool_template(file_stem(&path), tool);
+                }
+                Err(e) => problems.push(format!("{}: {}", prefix, e)),
+            }
+        }
+
+        for (path, format) in config_files(&dir.join(AGENT_SETS_DIR))? {
+            let prefix = path.display().to_string();
+            match format.parse::<AgentSet>(&fs::read_to_string(&path)?) {
+                Ok(agent_set) => {
+                    if agent_set.name != file_stem(&path) {
+                        problems.push(format!("{}: agent set '{}' should be in a file named after it", prefix, agent_set.name));
+                    }
+                    if manager.agent_sets.contains_key(&agent_set.name) {
+                        problems.push(format!("{}: agent set '{}' is defined more than once", prefix, agent_set.name));
+                    }
+                    problems.extend(validate_agent_set(&agent_set).into_iter().map(|p| format!("{}: {}", prefix, p)));
+                    manager.register_agent_set(agent_set);
+                }
+                Err(e) => problems.push(format!("{}: {}", prefix, e)),
+            }
+        }
+        Ok((manager, problems))
+    }
+
+    /// Write every agent set and tool template to its own file under `dir`,
+    /// in the layout [`load_from_dir`](Self::load_from_dir) reads
+    pub fn save_to_dir(&self, dir: &Path, format: ConfigFormat) -> Result<()> {
+        for (subdir, files) in [
+            (AGENT_SETS_DIR, self.agent_sets.iter().map(|(name, set)| (name, format.render(set))).collect::<Vec<_>>()),
+            (TOOLS_DIR, self.tool_templates.iter().map(|(name, tool)| (name, format.render(tool))).collect()),
+        ] {
+            let subdir = dir.join(subdir);
+            fs::create_dir_all(&subdir)?;
+            for (name, contents) in files {
+                let contents = contents.map_err(|e| SwarmError::Validation(format!("{}: {}", name, e)))?;
+                fs::write(subdir.join(format!("{}.{}", name, format.extension())), contents)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Problems with everything registered, as [`lint_dir`](Self::lint_dir) would find them
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems: Vec<String> = self.tool_templates.values().flat_map(validate_tool).collect();
+        problems.extend(self.agent_sets.values().flat_map(validate_agent_set));
+        problems
+    }
+
+    pub fn agent_sets(&self) -> impl Iterator<Item = &AgentSet> {
+        self.agent_sets.values()
+    }
+
+    pub fn tool_templates(&self) -> &HashMap<String, Tool> {
+        &self.tool_templates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::get_transfer_tool;
+    use crate::types::AgentConfig;
+
+    fn agent(name: &str, downstream: &[&str]) -> AgentConfig {
+        AgentConfig {
+            name: name.to_string(),
+            public_description: format!("{} agent", name),
+            instructions: "Help".to_string(),
+            tools: vec![],
+            downstream_agents: downstream.iter().map(|s| s.to_string()).collect(),
+            personality: None,
+            state_machine: None,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for format in [ConfigFormat::Toml, ConfigFormat::Yaml] {
+            let dir = tempfile::tempdir().unwrap();
+            let mut manager = ConfigManager::new();
+            let mut greeter = agent("greeter", &["haiku"]);
+            greeter.tools.push(get_transfer_tool());
+            manager.register_agent_set(AgentSet {
+                name: "default".to_string(),
+                description: "Everyday agents".to_string(),
+                agents: vec![greeter, agent("haiku", &[])],
+            });
+            manager.register_tool_template("agent_transfer".to_string(), get_transfer_tool());
+            manager.save_to_dir(dir.path(), format).unwrap();
+
+            let loaded = ConfigManager::load_from_dir(dir.path()).unwrap();
+            let set = loaded.get_agent_set("default").unwrap();
+            assert_eq!(set.agents.len(), 2);
+            assert_eq!(set.agents[0].tools[0].name, "agent_transfer");
+            assert!(loaded.get_tool_template("agent_transfer").is_some());
+        }
+    }
+
+    #[test]
+    fn test_lint_reports_every_problem() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(AGENT_SETS_DIR)).unwrap();
+        fs::create_dir_all(dir.path().join(TOOLS_DIR)).unwrap();
+        fs::write(dir.path().join(TOOLS_DIR).join("broken.toml"), "name = \"Bad Name\"\ndescription = \"\"\n[parameters]\nx = \"\"\n").unwrap();
+        fs::write(dir.path().join(AGENT_SETS_DIR).join("unparseable.yaml"), "name: [").unwrap();
+
+        let problems = ConfigManager::lint_dir(dir.path()).unwrap();
+        assert_eq!(problems.len(), 4, "{:?}", problems);
+        assert!(matches!(ConfigManager::load_from_dir(dir.path()), Err(SwarmError::Validation(_))));
+
+        let set = AgentSet {
+            name: "default".to_string(),
+            description: String::new(),
+            agents: vec![agent("greeter", &["nobody"]), agent("greeter", &[])],
+        };
+        assert_eq!(validate_agent_set(&set).len(), 2);
+    }
+}
@@ -3,6 +3,18 @@ use serde::{Deserialize, Serialize};
 use crate::types::{AgentConfig, Tool, ToolParameter};
 use crate::Result;
 
+mod files;
+mod swarm;
+mod watch;
+
+pub use swarm::{
+    AgentRetention, AiSettings, ApiSettings, ConfigArgs, ConfigError, McpSettings, MongoSettings,
+    MqttSettings, RemoteAgent, RetentionSettings, StateSettings, SwarmConfig, SwarmSettings, WorkerSettings,
+    DEFAULT_CONFIG_FILE,
+};
+pub use files::{validate_agent_set, validate_tool, ConfigFormat, AGENT_SETS_DIR, TOOLS_DIR};
+pub use watch::{ConfigChanged, ConfigSection, ConfigWatcher};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentSet {
     pub name: String,
@@ -62,6 +74,10 @@ pub fn get_transfer_tool() -> Tool {
                 "target_agent".to_string(),
                 "Name of the agent to transfer to".to_string(),
             );
+            params.insert(
+                "message".to_string(),
+                "Message handed to the target agent".to_string(),
+            );
             params
         },
     }
@@ -0,0 +1,828 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use crate::mcp::McpClientConfig;
+use crate::mqtt::{BoundedQueue, MetricsPublishing, MqttConfig, OverflowPolicy, QoS};
+use crate::mqtt::metrics::qos_from_level;
+use crate::state::StateBackend;
+
+/// Read from the working directory when neither `--config` nor `SWARM_CONFIG` names a file
+pub const DEFAULT_CONFIG_FILE: &str = "swarm.toml";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttSettings {
+    pub host: String,
+    pub port: u16,
+    /// Generated per process when unset
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    #[serde(skip_serializing)]
+    pub password: Option<String>,
+    pub keep_alive_secs: u64,
+    pub topic_prefix: String,
+    /// QoS level (0-2) for task requests and replies
+    pub task_qos: u8,
+    /// QoS level for the periodic metrics reports
+    pub metrics_qos: u8,
+    /// Gzip metrics reports
+    pub compress_metrics: bool,
+    /// Send only what changed in each metrics report, in full every `metrics_full_every`
+    pub metrics_delta: bool,
+    pub metrics_full_every: u32,
+}
+
+impl Default for MqttSettings {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 1883,
+            client_id: None,
+            username: None,
+            password: None,
+            keep_alive_secs: 20,
+            topic_prefix: String::new(),
+            task_qos: 2,
+            metrics_qos: 1,
+            compress_metrics: false,
+            metrics_delta: false,
+            metrics_full_every: 6,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MongoSettings {
+    pub uri: Option<String>,
+    pub db: String,
+}
+
+impl Default for MongoSettings {
+    fn default() -> Self {
+        Self { uri: None, db: "swarmonomicon".to_string() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StateSettings {
+    /// `mongo`, or `sled` for an embedded database (needs the `embedded-state` feature)
+    pub backend: String,
+    /// Directory of the sled database
+    pub path: PathBuf,
+    pub retention: RetentionSettings,
+}
+
+impl Default for StateSettings {
+    fn default() -> Self {
+        Self {
+            backend: "mongo".to_string(),
+            path: PathBuf::from("data/state"),
+            retention: RetentionSettings::default(),
+        }
+    }
+}
+
+/// How much state history stays in the hot collections
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionSettings {
+    /// Seconds between compaction runs; 0 disables compaction
+    pub interval_secs: u64,
+    /// `collection` (cold `*_archive` collections), `gzip` (JSON lines under `archive_dir`) or `delete`
+    pub archive: String,
+    pub archive_dir: PathBuf,
+    /// Newest state versions kept per agent
+    pub keep_versions: usize,
+    /// Older transitions are archived; 0 keeps them all
+    pub transition_max_age_days: u64,
+    /// Overrides by agent name
+    pub agents: HashMap<String, AgentRetention>,
+}
+
+impl Default for RetentionSettings {
+    fn default() -> Self {
+        Self {
+            interval_secs: 0,
+            archive: "collection".to_string(),
+            archive_dir: PathBuf::from("data/state-archive"),
+            keep_versions: 50,
+            transition_max_age_days: 30,
+            agents: HashMap::new(),
+        }
+    }
+}
+
+impl RetentionSettings {
+    pub fn interval(&self) -> Option<Duration> {
+        (self.interval_secs > 0).then(|| Duration::from_secs(self.interval_secs))
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AgentRetention {
+    pub keep_versions: Option<usize>,
+    pub transition_max_age_days: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct McpSettings {
+    pub server_url: String,
+    pub timeout_secs: u64,
+    pub max_retries: u32,
+}
+
+impl Default for McpSettings {
+    fn default() -> Self {
+        let client = McpClientConfig::default();
+        Self {
+            server_url: client.base_url,
+            timeout_secs: client.default_timeout.as_secs(),
+            max_retries: client.max_retries,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorkerSettings {
+    /// Seconds between `todo_worker` task checks
+    pub check_interval_secs: u64,
+    pub queue_capacity: usize,
+    /// `reject` or `drop_oldest`
+    pub queue_policy: String,
+    pub queue_workers: usize,
+    /// Background jobs `todo_worker` runs at once, such as task processing and drain watches
+    pub max_jobs: usize,
+    /// Seconds shutdown waits for running jobs before aborting them
+    pub shutdown_grace_secs: u64,
+}
+
+impl Default for WorkerSettings {
+    fn default() -> Self {
+        Self {
+            check_interval_secs: 30,
+            queue_capacity: 100,
+            queue_policy: "reject".to_string(),
+            queue_workers: 1,
+            max_jobs: 32,
+            shutdown_grace_secs: 30,
+        }
+    }
+}
+
+impl WorkerSettings {
+    pub fn check_interval(&self) -> Duration {
+        Duration::from_secs(self.check_interval_secs)
+    }
+
+    pub fn shutdown_grace(&self) -> Duration {
+        Duration::from_secs(self.shutdown_grace_secs)
+    }
+
+    pub fn overflow_policy(&self) -> OverflowPolicy {
+        self.queue_policy.parse().unwrap_or(OverflowPolicy::Reject)
+    }
+
+    pub fn request_queue<T>(&self) -> BoundedQueue<T> {
+        BoundedQueue::new(self.queue_capacity, self.overflow_policy())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AiSettings {
+    #[serde(skip_serializing)]
+    pub openai_api_key: Option<String>,
+    /// Passed to Spindlewrit when projects are generated from todos
+    #[serde(skip_serializing)]
+    pub gemma_api_key: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ApiSettings {
+    pub host: String,
+    pub port: u16,
+    /// Bearer token for operator routes such as state rollback; they are closed when unset
+    #[serde(skip_serializing)]
+    pub admin_token: Option<String>,
+    /// Where training runs started through `/api/rl/runs` keep their checkpoints and reports
+    pub rl_runs_dir: PathBuf,
+}
+
+impl Default for ApiSettings {
+    fn default() -> Self {
+        Self { host: "127.0.0.1".to_string(), port: 3000, admin_token: None, rl_runs_dir: PathBuf::from("data/rl-runs") }
+    }
+}
+
+impl ApiSettings {
+    pub fn addr(&self) -> SocketAddr {
+        SocketAddr::new(self.host.parse().unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)), self.port)
+    }
+}
+
+/// This process's place in a multi-node swarm
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SwarmSettings {
+    /// Name other nodes address this one by; the host name when unset
+    pub node_id: Option<String>,
+    /// Seconds a transfer to a remote agent waits for its reply
+    pub remote_timeout_secs: u64,
+    /// Agents that live on other nodes, by name
+    pub remote_agents: HashMap<String, RemoteAgent>,
+    /// Seconds between republishing this node's manifest
+    pub heartbeat_secs: u64,
+    /// Nodes whose manifest is older than this are reported stale
+    pub stale_after_secs: u64,
+}
+
+impl Default for SwarmSettings {
+    fn default() -> Self {
+        Self {
+            node_id: None,
+            remote_timeout_secs: 30,
+            remote_agents: HashMap::new(),
+            heartbeat_secs: 30,
+            stale_after_secs: 90,
+        }
+    }
+}
+
+impl SwarmSettings {
+    pub fn node_id(&self) -> String {
+        self.node_id.clone()
+            .or_else(|| env::var("HOSTNAME").ok())
+            .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+            .unwrap_or_else(|| "localhost".to_string())
+    }
+
+    pub fn remote_timeout(&self) -> Duration {
+        Duration::from_secs(self.remote_timeout_secs)
+    }
+
+    pub fn heartbeat(&self) -> Duration {
+        Duration::from_secs(self.heartbeat_secs)
+    }
+
+    pub fn stale_after(&self) -> Duration {
+        Duration::from_secs(self.stale_after_secs)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoteAgent {
+    /// Node the agent runs on
+    pub node: String,
+}
+
+/// Whether `id` can be used as a single MQTT topic level
+fn is_topic_level(id: &str) -> bool {
+    !id.trim().is_empty() && !id.contains(['/', '+', '#'])
+}
+
+/// Flags every binary accepts on top of the config file and environment
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct ConfigArgs {
+    /// TOML config file (default: $SWARM_CONFIG, then ./swarm.toml if present)
+    #[arg(long = "config", value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    #[arg(long)]
+    pub mqtt_host: Option<String>,
+    #[arg(long)]
+    pub mqtt_port: Option<u16>,
+    #[arg(long)]
+    pub mcp_server_url: Option<String>,
+    #[arg(long)]
+    pub mongo_uri: Option<String>,
+    /// Seconds between todo_worker task checks
+    #[arg(long)]
+    pub check_interval: Option<u64>,
+    #[arg(long)]
+    pub api_port: Option<u16>,
+}
+
+#[derive(clap::Parser)]
+struct ConfigCli {
+    #[command(flatten)]
+    config: ConfigArgs,
+}
+
+impl ConfigArgs {
+    /// Parse the binary's command line, for binaries with no flags of their own
+    pub fn from_cli() -> Self {
+        <ConfigCli as clap::Parser>::parse().config
+    }
+}
+
+/// Every problem found while loading, not just the first
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub errors: Vec<String>,
+}
+
+impl ConfigError {
+    fn new(error: impl Into<String>) -> Self {
+        Self { errors: vec![error.into()] }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid configuration:")?;
+        for error in &self.errors {
+            write!(f, "\n  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Settings shared by the binaries, layered defaults → TOML file →
+/// environment → command line, each overriding the one before.
+///
+/// ```toml
+/// [mqtt]
+/// host = "broker.local"
+/// topic_prefix = "staging"
+///
+/// [worker]
+/// check_interval_secs = 10
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SwarmConfig {
+    pub mqtt: MqttSettings,
+    pub mongo: MongoSettings,
+    pub state: StateSettings,
+    pub mcp: McpSettings,
+    pub worker: WorkerSettings,
+    pub ai: AiSettings,
+    pub api: ApiSettings,
+    pub swarm: SwarmSettings,
+    /// File the settings were read from, if any
+    #[serde(skip)]
+    pub source: Option<PathBuf>,
+}
+
+/// Overwrite `target` with the parsed value of `key`, if set
+fn parse_var<T: std::str::FromStr>(
+    var: &impl Fn(&str) -> Option<String>,
+    key: &str,
+    target: &mut T,
+    errors: &mut Vec<String>,
+) {
+    if let Some(value) = var(key) {
+        match value.trim().parse() {
+            Ok(parsed) => *target = parsed,
+            Err(_) => errors.push(format!("{}: '{}' is not a valid value", key, value)),
+        }
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.starts_with("http://") || url.starts_with("https://")
+}
+
+impl SwarmConfig {
+    /// Parse the binary's command line and load from it. Each source
+    /// overrides the ones before it: built-in defaults, then the config file
+    /// (`--config`, `SWARM_CONFIG`, or `swarm.toml` in the working
+    /// directory), then the environment, then flags.
+    pub fn from_cli() -> Result<Self, ConfigError> {
+        Self::load(&ConfigArgs::from_cli())
+    }
+
+    /// How the binaries load their configuration: [`SwarmConfig::from_cli`],
+    /// logging the file it came from, or printing every problem and exiting
+    /// with status 2 when it doesn't load
+    pub fn from_cli_or_exit() -> Self {
+        Self::load_or_exit(&ConfigArgs::from_cli())
+    }
+
+    /// [`SwarmConfig::from_cli_or_exit`] for a binary that keeps its `args`,
+    /// e.g. to hand them to a [`ConfigWatcher`](super::ConfigWatcher)
+    pub fn load_or_exit(args: &ConfigArgs) -> Self {
+        match Self::load(args) {
+            Ok(config) => {
+                if let Some(source) = &config.source {
+                    tracing::info!("Loaded configuration from {}", source.display());
+                }
+                config
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(2);
+            }
+        }
+    }
+
+    pub fn load(args: &ConfigArgs) -> Result<Self, ConfigError> {
+        Self::load_with(args, |key| env::var(key).ok())
+    }
+
+    fn load_with(args: &ConfigArgs, var: impl Fn(&str) -> Option<String>) -> Result<Self, ConfigError> {
+        let path = args.config.clone().or_else(|| var("SWARM_CONFIG").map(PathBuf::from));
+        let mut config = match path {
+            Some(path) => Self::from_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?,
+            None => Self::default(),
+        };
+
+        let mut errors = Vec::new();
+        config.apply_env(&var, &mut errors);
+        config.apply_args(args);
+        config.check(&mut errors);
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(ConfigError { errors })
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::new(format!("{}: {}", path.display(), e)))?;
+        let mut config = Self::from_toml(&contents)
+            .map_err(|e| ConfigError::new(format!("{}: {}", path.display(), e.errors.join("; "))))?;
+        config.source = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// Parse file contents; absent keys keep their defaults, unknown keys are errors
+    pub fn from_toml(contents: &str) -> Result<Self, ConfigError> {
+        toml::from_str(contents).map_err(|e| ConfigError::new(e.to_string().trim_end().to_string()))
+    }
+
+    fn apply_env(&mut self, var: &impl Fn(&str) -> Option<String>, errors: &mut Vec<String>) {
+        if let Some(host) = var("MQTT_HOST").or_else(|| var("AWSIP")) {
+            self.mqtt.host = host;
+        }
+        if var("MQTT_PORT").is_some() {
+            parse_var(var, "MQTT_PORT", &mut self.mqtt.port, errors);
+        } else {
+            parse_var(var, "AWSPORT", &mut self.mqtt.port, errors);
+        }
+        if let Some(client_id) = var("MQTT_CLIENT_ID") {
+            self.mqtt.client_id = Some(client_id);
+        }
+        if let Some(username) = var("MQTT_USERNAME") {
+            self.mqtt.username = Some(username);
+        }
+        if let Some(password) = var("MQTT_PASSWORD") {
+            self.mqtt.password = Some(password);
+        }
+        parse_var(var, "MQTT_KEEP_ALIVE_SECS", &mut self.mqtt.keep_alive_secs, errors);
+        if let Some(prefix) = var("MQTT_TOPIC_PREFIX") {
+            self.mqtt.topic_prefix = prefix;
+        }
+        parse_var(var, "MQTT_TASK_QOS", &mut self.mqtt.task_qos, errors);
+        parse_var(var, "MQTT_METRICS_QOS", &mut self.mqtt.metrics_qos, errors);
+        parse_var(var, "MQTT_COMPRESS_METRICS", &mut self.mqtt.compress_metrics, errors);
+        parse_var(var, "MQTT_METRICS_DELTA", &mut self.mqtt.metrics_delta, errors);
+        parse_var(var, "MQTT_METRICS_FULL_EVERY", &mut self.mqtt.metrics_full_every, errors);
+
+        if let Some(uri) = var("RTK_MONGO_URI") {
+            self.mongo.uri = Some(uri);
+        }
+        if let Some(db) = var("RTK_MONGO_DB") {
+            self.mongo.db = db;
+        }
+
+        if let Some(backend) = var("STATE_BACKEND") {
+            self.state.backend = backend;
+        }
+        if let Some(path) = var("STATE_PATH") {
+            self.state.path = PathBuf::from(path);
+        }
+        parse_var(var, "STATE_COMPACTION_INTERVAL_SECS", &mut self.state.retention.interval_secs, errors);
+        if let Some(archive) = var("STATE_ARCHIVE") {
+            self.state.retention.archive = archive;
+        }
+        if let Some(dir) = var("STATE_ARCHIVE_DIR") {
+            self.state.retention.archive_dir = PathBuf::from(dir);
+        }
+        parse_var(var, "STATE_KEEP_VERSIONS", &mut self.state.retention.keep_versions, errors);
+        parse_var(var, "STATE_TRANSITION_MAX_AGE_DAYS", &mut self.state.retention.transition_max_age_days, errors);
+
+        if let Some(url) = var("MCP_SERVER_URL") {
+            self.mcp.server_url = url;
+        }
+        parse_var(var, "MCP_TIMEOUT_SECS", &mut self.mcp.timeout_secs, errors);
+        parse_var(var, "MCP_MAX_RETRIES", &mut self.mcp.max_retries, errors);
+
+        parse_var(var, "TODO_CHECK_INTERVAL_SECS", &mut self.worker.check_interval_secs, errors);
+        parse_var(var, "TASK_QUEUE_CAPACITY", &mut self.worker.queue_capacity, errors);
+        if let Some(policy) = var("TASK_QUEUE_POLICY") {
+            self.worker.queue_policy = policy;
+        }
+        parse_var(var, "TASK_QUEUE_WORKERS", &mut self.worker.queue_workers, errors);
+        parse_var(var, "WORKER_MAX_JOBS", &mut self.worker.max_jobs, errors);
+        parse_var(var, "WORKER_SHUTDOWN_GRACE_SECS", &mut self.worker.shutdown_grace_secs, errors);
+
+        if let Some(key) = var("OPENAI_API_KEY") {
+            self.ai.openai_api_key = Some(key);
+        }
+        if let Some(key) = var("GEMMA_API_KEY") {
+            self.ai.gemma_api_key = Some(key);
+        }
+
+        if let Some(host) = var("API_HOST") {
+            self.api.host = host;
+        }
+        parse_var(var, "API_PORT", &mut self.api.port, errors);
+        if let Some(token) = var("API_ADMIN_TOKEN") {
+            self.api.admin_token = Some(token);
+        }
+        if let Some(dir) = var("API_RL_RUNS_DIR") {
+            self.api.rl_runs_dir = PathBuf::from(dir);
+        }
+
+        if let Some(node_id) = var("SWARM_NODE_ID") {
+            self.swarm.node_id = Some(node_id);
+        }
+        parse_var(var, "SWARM_REMOTE_TIMEOUT_SECS", &mut self.swarm.remote_timeout_secs, errors);
+        parse_var(var, "SWARM_HEARTBEAT_SECS", &mut self.swarm.heartbeat_secs, errors);
+        parse_var(var, "SWARM_STALE_AFTER_SECS", &mut self.swarm.stale_after_secs, errors);
+        // `agent=node,agent=node`, added to the file's remote agents
+        if let Some(spec) = var("SWARM_REMOTE_AGENTS") {
+            for pair in spec.split(',').filter(|pair| !pair.trim().is_empty()) {
+                match pair.split_once('=') {
+                    Some((agent, node)) => {
+                        self.swarm.remote_agents.insert(agent.trim().to_string(), RemoteAgent { node: node.trim().to_string() });
+                    }
+                    None => errors.push(format!("SWARM_REMOTE_AGENTS: '{}' is not agent=node", pair)),
+                }
+            }
+        }
+    }
+
+    fn apply_args(&mut self, args: &ConfigArgs) {
+        if let Some(host) = &args.mqtt_host {
+            self.mqtt.host = host.clone();
+        }
+        if let Some(port) = args.mqtt_port {
+            self.mqtt.port = port;
+        }
+        if let Some(url) = &args.mcp_server_url {
+            self.mcp.server_url = url.clone();
+        }
+        if let Some(uri) = &args.mongo_uri {
+            self.mongo.uri = Some(uri.clone());
+        }
+        if let Some(secs) = args.check_interval {
+            self.worker.check_interval_secs = secs;
+        }
+        if let Some(port) = args.api_port {
+            self.api.port = port;
+        }
+    }
+
+    /// Check the merged settings, reporting every problem at once
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut errors = Vec::new();
+        self.check(&mut errors);
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ConfigError { errors })
+        }
+    }
+
+    fn check(&self, errors: &mut Vec<String>) {
+        if self.mqtt.host.trim().is_empty() {
+            errors.push("mqtt.host must not be empty".to_string());
+        }
+        if self.mqtt.port == 0 {
+            errors.push("mqtt.port must be between 1 and 65535".to_string());
+        }
+        if self.mqtt.username.is_some() != self.mqtt.password.is_some() {
+            errors.push("mqtt.username and mqtt.password must be set together".to_string());
+        }
+        for (key, qos) in [("mqtt.task_qos", self.mqtt.task_qos), ("mqtt.metrics_qos", self.mqtt.metrics_qos)] {
+            if qos_from_level(qos).is_none() {
+                errors.push(format!("{} must be 0, 1 or 2, got {}", key, qos));
+            }
+        }
+        if self.mqtt.metrics_full_every == 0 {
+            errors.push("mqtt.metrics_full_every must be at least 1".to_string());
+        }
+        if let Some(uri) = &self.mongo.uri {
+            if !uri.starts_with("mongodb://") && !uri.starts_with("mongodb+srv://") {
+                errors.push(format!("mongo.uri must start with mongodb:// or mongodb+srv://, got '{}'", uri));
+            }
+        }
+        if self.mongo.db.trim().is_empty() {
+            errors.push("mongo.db must not be empty".to_string());
+        }
+        match self.state.backend.parse::<StateBackend>() {
+            Ok(StateBackend::Sled) if !cfg!(feature = "embedded-state") => {
+                errors.push("state.backend sled needs the `embedded-state` feature".to_string());
+            }
+            Ok(_) => {}
+            Err(e) => errors.push(format!("state.backend: {}", e)),
+        }
+        let retention = &self.state.retention;
+        if !matches!(retention.archive.as_str(), "collection" | "gzip" | "delete") {
+            errors.push(format!("state.retention.archive must be collection, gzip or delete, got '{}'", retention.archive));
+        }
+        let keeps = std::iter::once(("state.retention.keep_versions".to_string(), Some(retention.keep_versions)))
+            .chain(retention.agents.iter().map(|(agent, r)| (format!("state.retention.agents.{}.keep_versions", agent), r.keep_versions)));
+        for (key, keep) in keeps {
+            // Replay needs at least one saved version to start from
+            if keep == Some(0) {
+                errors.push(format!("{} must be at least 1", key));
+            }
+        }
+        if !is_http_url(&self.mcp.server_url) {
+            errors.push(format!("mcp.server_url must be an http(s) URL, got '{}'", self.mcp.server_url));
+        }
+        if self.mcp.timeout_secs == 0 {
+            errors.push("mcp.timeout_secs must be at least 1".to_string());
+        }
+        if self.worker.check_interval_secs == 0 {
+            errors.push("worker.check_interval_secs must be at least 1".to_string());
+        }
+        if self.worker.queue_capacity == 0 {
+            errors.push("worker.queue_capacity must be at least 1".to_string());
+        }
+        if let Err(e) = self.worker.queue_policy.parse::<OverflowPolicy>() {
+            errors.push(format!("worker.queue_policy: {}", e));
+        }
+        if self.worker.queue_workers == 0 {
+            errors.push("worker.queue_workers must be at least 1".to_string());
+        }
+        if self.worker.max_jobs == 0 {
+            errors.push("worker.max_jobs must be at least 1".to_string());
+        }
+        if self.api.host.parse::<IpAddr>().is_err() {
+            errors.push(format!("api.host must be an IP address, got '{}'", self.api.host));
+        }
+        if self.api.port == 0 {
+            errors.push("api.port must be between 1 and 65535".to_string());
+        }
+        let node_id = self.swarm.node_id();
+        if !is_topic_level(&node_id) {
+            errors.push(format!("swarm.node_id must be non-empty without '/', '+' or '#', got '{}'", node_id));
+        }
+        if self.swarm.remote_timeout_secs == 0 {
+            errors.push("swarm.remote_timeout_secs must be at least 1".to_string());
+        }
+        if self.swarm.heartbeat_secs == 0 {
+            errors.push("swarm.heartbeat_secs must be at least 1".to_string());
+        }
+        // A node is only stale once it has missed a heartbeat
+        if self.swarm.stale_after_secs <= self.swarm.heartbeat_secs {
+            errors.push("swarm.stale_after_secs must be longer than swarm.heartbeat_secs".to_string());
+        }
+        let mut remote: Vec<_> = self.swarm.remote_agents.iter().collect();
+        remote.sort_by_key(|(agent, _)| agent.as_str());
+        for (agent, remote) in remote {
+            if !is_topic_level(agent) || !is_topic_level(&remote.node) {
+                errors.push(format!("swarm.remote_agents.{}: agent and node must be non-empty without '/', '+' or '#'", agent));
+            } else if remote.node == node_id {
+                errors.push(format!("swarm.remote_agents.{} points at this node ({})", agent, node_id));
+            }
+        }
+    }
+
+    /// Broker settings for an [`MqttService`](crate::mqtt::MqttService) connecting as `client_id`
+    pub fn mqtt_config(&self, client_id: impl Into<String>) -> MqttConfig {
+        let mut config = MqttConfig::new(client_id, self.mqtt.host.clone(), self.mqtt.port)
+            .with_keep_alive(Duration::from_secs(self.mqtt.keep_alive_secs))
+            .with_topic_prefix(self.mqtt.topic_prefix.clone())
+            .with_metrics_publishing(MetricsPublishing {
+                qos: qos_from_level(self.mqtt.metrics_qos).unwrap_or(QoS::AtLeastOnce),
+                compress: self.mqtt.compress_metrics,
+                delta: self.mqtt.metrics_delta,
+                full_every: self.mqtt.metrics_full_every,
+            });
+        if let Some(qos) = qos_from_level(self.mqtt.task_qos) {
+            config = config.with_task_qos(qos);
+        }
+        if let (Some(username), Some(password)) = (&self.mqtt.username, &self.mqtt.password) {
+            config.credentials = Some((username.clone(), password.clone()));
+        }
+        config
+    }
+
+    /// The configured client id, or `default` for a fresh per-process one
+    pub fn mqtt_client_id(&self, default: impl FnOnce() -> String) -> String {
+        self.mqtt.client_id.clone().unwrap_or_else(default)
+    }
+
+    /// MCP client settings; per-endpoint timeouts and breaker tuning still come from the environment
+    pub fn mcp_client_config(&self) -> McpClientConfig {
+        let mut config = McpClientConfig::from_env();
+        config.base_url = self.mcp.server_url.clone();
+        config.default_timeout = Duration::from_secs(self.mcp.timeout_secs);
+        config.max_retries = self.mcp.max_retries;
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env_of(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn test_layering() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, br#"
+            [mqtt]
+            host = "file-broker"
+            port = 1884
+            topic_prefix = "staging"
+
+            [worker]
+            check_interval_secs = 10
+        "#).unwrap();
+
+        let args = ConfigArgs {
+            config: Some(file.path().to_path_buf()),
+            mqtt_port: Some(1885),
+            ..Default::default()
+        };
+        let config = SwarmConfig::load_with(&args, env_of(&[
+            ("MQTT_HOST", "env-broker"),
+            ("MQTT_PORT", "1886"),
+            ("TODO_CHECK_INTERVAL_SECS", "5"),
+        ])).unwrap();
+
+        assert_eq!(config.mqtt.host, "env-broker");
+        assert_eq!(config.mqtt.port, 1885);
+        assert_eq!(config.mqtt.topic_prefix, "staging");
+        assert_eq!(config.worker.check_interval_secs, 5);
+        assert_eq!(config.mongo.db, "swarmonomicon");
+        assert_eq!(config.source.as_deref(), Some(file.path()));
+        assert_eq!(config.mqtt_config("worker").topic("todos"), "staging/todos");
+    }
+
+    #[test]
+    fn test_reports_every_problem() {
+        let error = SwarmConfig::load_with(&ConfigArgs::default(), env_of(&[
+            ("SWARM_CONFIG", "/nonexistent/swarm.toml"),
+        ])).unwrap_err();
+        assert_eq!(error.errors.len(), 1);
+
+        let error = SwarmConfig::load_with(&ConfigArgs::default(), env_of(&[
+            ("MQTT_PORT", "not-a-port"),
+            ("MQTT_USERNAME", "swarm"),
+            ("MCP_SERVER_URL", "localhost:8000"),
+            ("TASK_QUEUE_POLICY", "shrug"),
+            ("TODO_CHECK_INTERVAL_SECS", "0"),
+            ("STATE_BACKEND", "postgres"),
+        ])).unwrap_err();
+        assert_eq!(error.errors.len(), 6, "{}", error);
+
+        assert!(SwarmConfig::from_toml("[mqtt]\nhots = \"typo\"").is_err());
+    }
+
+    #[test]
+    fn test_retention_overrides() {
+        let config = SwarmConfig::from_toml(
+            "[state.retention]\narchive = \"gzip\"\n\n[state.retention.agents.git]\nkeep_versions = 0\n",
+        ).unwrap();
+        assert_eq!(config.state.retention.archive, "gzip");
+        assert_eq!(config.state.retention.interval(), None);
+
+        let mut errors = Vec::new();
+        config.check(&mut errors);
+        assert_eq!(errors, ["state.retention.agents.git.keep_versions must be at least 1"]);
+    }
+
+    #[test]
+    fn test_remote_agents() {
+        let mut config = SwarmConfig::from_toml(
+            "[swarm]\nnode_id = \"studio\"\n\n[swarm.remote_agents]\nbrowser = { node = \"linux-box\" }\n",
+        ).unwrap();
+        let mut errors = Vec::new();
+        config.apply_env(&env_of(&[("SWARM_REMOTE_AGENTS", "haiku=pi, git=studio,bogus")]), &mut errors);
+        assert_eq!(errors, ["SWARM_REMOTE_AGENTS: 'bogus' is not agent=node"]);
+        assert_eq!(config.swarm.remote_agents["browser"].node, "linux-box");
+        assert_eq!(config.swarm.remote_agents["haiku"].node, "pi");
+
+        let mut errors = Vec::new();
+        config.check(&mut errors);
+        assert_eq!(errors, ["swarm.remote_agents.git points at this node (studio)"]);
+    }
+}
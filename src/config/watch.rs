@@ -0,0 +1,209 @@
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::sync::broadcast;
+use tokio::task::JoinHandle;
+use super::swarm::{ConfigArgs, ConfigError, SwarmConfig};
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(2);
+const CHANGE_CAPACITY: usize = 16;
+
+/// Top-level table of a [`SwarmConfig`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigSection {
+    Mqtt,
+    Mongo,
+    State,
+    Mcp,
+    Worker,
+    Ai,
+    Api,
+    Swarm,
+}
+
+impl ConfigSection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConfigSection::Mqtt => "mqtt",
+            ConfigSection::Mongo => "mongo",
+            ConfigSection::State => "state",
+            ConfigSection::Mcp => "mcp",
+            ConfigSection::Worker => "worker",
+            ConfigSection::Ai => "ai",
+            ConfigSection::Api => "api",
+            ConfigSection::Swarm => "swarm",
+        }
+    }
+
+    /// Connections and listeners are set up once, so changes to these sections
+    /// only take effect after a restart
+    pub fn requires_restart(&self) -> bool {
+        !matches!(self, ConfigSection::Worker)
+    }
+}
+
+/// The config file was edited and re-parsed successfully
+#[derive(Debug, Clone)]
+pub struct ConfigChanged {
+    pub previous: Arc<SwarmConfig>,
+    pub current: Arc<SwarmConfig>,
+    pub sections: Vec<ConfigSection>,
+}
+
+impl ConfigChanged {
+    pub fn touches(&self, section: ConfigSection) -> bool {
+        self.sections.contains(&section)
+    }
+}
+
+fn changed_sections(previous: &SwarmConfig, current: &SwarmConfig) -> Vec<ConfigSection> {
+    let mut sections = Vec::new();
+    if previous.mqtt != current.mqtt {
+        sections.push(ConfigSection::Mqtt);
+    }
+    if previous.mongo != current.mongo {
+        sections.push(ConfigSection::Mongo);
+    }
+    if previous.state != current.state {
+        sections.push(ConfigSection::State);
+    }
+    if previous.mcp != current.mcp {
+        sections.push(ConfigSection::Mcp);
+    }
+    if previous.worker != current.worker {
+        sections.push(ConfigSection::Worker);
+    }
+    if previous.ai != current.ai {
+        sections.push(ConfigSection::Ai);
+    }
+    if previous.api != current.api {
+        sections.push(ConfigSection::Api);
+    }
+    if previous.swarm != current.swarm {
+        sections.push(ConfigSection::Swarm);
+    }
+    sections
+}
+
+/// Re-reads the config file whenever its modification time moves and tells
+/// subscribers what changed. Environment variables and flags keep overriding
+/// the file across reloads. An edit that fails to parse or validate is logged
+/// and the previous settings stay in effect.
+pub struct ConfigWatcher {
+    args: ConfigArgs,
+    path: PathBuf,
+    poll_interval: Duration,
+    current: RwLock<Arc<SwarmConfig>>,
+    changes: broadcast::Sender<ConfigChanged>,
+}
+
+impl ConfigWatcher {
+    /// `None` when `config` wasn't read from a file, since there is nothing to watch
+    pub fn new(config: SwarmConfig, args: &ConfigArgs) -> Option<Self> {
+        let path = config.source.clone()?;
+        let mut args = args.clone();
+        args.config = Some(path.clone());
+        Some(Self {
+            args,
+            path,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            current: RwLock::new(Arc::new(config)),
+            changes: broadcast::channel(CHANGE_CAPACITY).0,
+        })
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn current(&self) -> Arc<SwarmConfig> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<ConfigChanged> {
+        self.changes.subscribe()
+    }
+
+    /// Re-parse the file now. `Ok(None)` when nothing in it changed.
+    pub fn reload(&self) -> Result<Option<ConfigChanged>, ConfigError> {
+        let current = Arc::new(SwarmConfig::load(&self.args)?);
+        let previous = self.current();
+        let sections = changed_sections(&previous, &current);
+        if sections.is_empty() {
+            return Ok(None);
+        }
+
+        *self.current.write().unwrap() = current.clone();
+        let change = ConfigChanged { previous, current, sections };
+        self.changes.send(change.clone()).ok();
+        Ok(Some(change))
+    }
+
+    /// Poll the file until the watcher is dropped
+    pub fn spawn(self: Arc<Self>) -> JoinHandle<()> {
+        tracing::info!("Watching {} for configuration changes", self.path.display());
+        let watcher = Arc::downgrade(&self);
+        let path = self.path.clone();
+        let poll_interval = self.poll_interval;
+        drop(self);
+
+        tokio::spawn(async move {
+            let mut last_modified = modified(&path).await;
+            let mut interval = tokio::time::interval(poll_interval);
+            loop {
+                interval.tick().await;
+                let Some(watcher) = watcher.upgrade() else { break };
+                let modified = modified(&path).await;
+                if modified == last_modified {
+                    continue;
+                }
+                last_modified = modified;
+
+                match watcher.reload() {
+                    Ok(Some(change)) => {
+                        let sections: Vec<&str> = change.sections.iter().map(|s| s.as_str()).collect();
+                        tracing::info!("Reloaded {}: changed {}", path.display(), sections.join(", "));
+                        for section in change.sections.iter().filter(|s| s.requires_restart()) {
+                            tracing::warn!("Changes to [{}] take effect after a restart", section.as_str());
+                        }
+                    }
+                    Ok(None) => tracing::debug!("{} was touched but no settings changed", path.display()),
+                    Err(e) => tracing::error!("Keeping the previous configuration. {}", e),
+                }
+            }
+        })
+    }
+}
+
+async fn modified(path: &PathBuf) -> Option<SystemTime> {
+    tokio::fs::metadata(path).await.ok()?.modified().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reload_reports_changed_sections() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), "[worker]\ncheck_interval_secs = 30\n").unwrap();
+        let args = ConfigArgs { config: Some(file.path().to_path_buf()), ..Default::default() };
+        let watcher = ConfigWatcher::new(SwarmConfig::load(&args).unwrap(), &args).unwrap();
+        let mut changes = watcher.subscribe();
+
+        assert!(watcher.reload().unwrap().is_none());
+
+        std::fs::write(file.path(), "[worker]\ncheck_interval_secs = 5\n").unwrap();
+        let change = watcher.reload().unwrap().unwrap();
+        assert_eq!(change.sections, vec![ConfigSection::Worker]);
+        assert_eq!(change.previous.worker.check_interval_secs, 30);
+        assert_eq!(watcher.current().worker.check_interval_secs, 5);
+        assert!(changes.try_recv().unwrap().touches(ConfigSection::Worker));
+
+        // A broken edit leaves the last good settings in place
+        std::fs::write(file.path(), "[worker]\ncheck_interval_secs = 0\n").unwrap();
+        assert!(watcher.reload().is_err());
+        assert_eq!(watcher.current().worker.check_interval_secs, 5);
+    }
+}
@@ -1,60 +1,120 @@
-use std::error::Error as StdError;
-use std::fmt;
-
+/// Errors surfaced across the crate. Each variant names the subsystem that
+/// failed so callers (and the HTTP API) can match on it instead of on strings.
 #[derive(Debug, thiserror::Error)]
-pub enum Error {
-    #[error("I/O error: {0}")]
-    Io(#[from] std::io::Error),
+pub enum SwarmError {
+    #[error("AI error: {0}")]
+    Ai(String),
 
-    #[error("JSON error: {0}")]
-    Json(#[from] serde_json::Error),
-
-    #[error("Agent error: {0}")]
-    Agent(String),
+    #[error("MQTT error: {0}")]
+    Mqtt(String),
 
     #[error("Tool error: {0}")]
     Tool(String),
 
+    #[error("Agent error: {0}")]
+    Agent(String),
+
     #[error("State error: {0}")]
     State(String),
+
+    #[error("Validation error: {0}")]
+    Validation(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    #[error("Unsupported: {0}")]
+    Unsupported(String),
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    /// The caller is known but its roles don't allow the operation
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    /// An agent reply the output filter refused to send
+    #[error("Moderation: {0}")]
+    Moderation(String),
+
+    #[error("Database error: {0}")]
+    Database(#[from] mongodb::error::Error),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+
+    #[error(transparent)]
+    Other(anyhow::Error),
 }
 
-#[derive(Debug, thiserror::Error)]
-pub enum SwarmError {
-    #[error("Agent error: {0}")]
-    Agent(String),
+/// Kept so existing `crate::Error` imports continue to work
+pub type Error = SwarmError;
 
-    #[error("Tool error: {0}")]
-    Tool(String),
+impl From<anyhow::Error> for SwarmError {
+    fn from(err: anyhow::Error) -> Self {
+        // A typed error that passed through anyhow comes back out as itself
+        err.downcast::<SwarmError>().unwrap_or_else(SwarmError::Other)
+    }
+}
 
-    #[error("State error: {0}")]
-    State(String),
+impl From<crate::types::TodoError> for SwarmError {
+    fn from(err: crate::types::TodoError) -> Self {
+        match err {
+            crate::types::TodoError::Invalid(message) => SwarmError::Validation(message),
+            crate::types::TodoError::Database(err) => SwarmError::Database(err),
+        }
+    }
 }
 
-impl From<&str> for Error {
-    fn from(msg: &str) -> Self {
-        Error::Agent(msg.to_string())
+impl From<crate::agents::moderation::ModerationViolation> for SwarmError {
+    fn from(violation: crate::agents::moderation::ModerationViolation) -> Self {
+        SwarmError::Moderation(violation.to_string())
     }
 }
 
-impl From<String> for Error {
-    fn from(msg: String) -> Self {
-        Error::Agent(msg)
+impl From<crate::access::AccessDenied> for SwarmError {
+    fn from(denied: crate::access::AccessDenied) -> Self {
+        SwarmError::Forbidden(denied.to_string())
     }
 }
 
-impl From<SwarmError> for Error {
-    fn from(err: SwarmError) -> Self {
-        match err {
-            SwarmError::Agent(msg) => Error::Agent(msg),
-            SwarmError::Tool(msg) => Error::Tool(msg),
-            SwarmError::State(msg) => Error::State(msg),
-        }
+impl From<crate::agents::structured::StructuredOutputError> for SwarmError {
+    fn from(err: crate::agents::structured::StructuredOutputError) -> Self {
+        SwarmError::Agent(err.to_string())
+    }
+}
+
+impl From<rumqttc::ClientError> for SwarmError {
+    fn from(err: rumqttc::ClientError) -> Self {
+        SwarmError::Mqtt(err.to_string())
     }
 }
 
-impl From<Box<dyn std::error::Error + Send + Sync>> for Error {
+impl From<Box<dyn std::error::Error + Send + Sync>> for SwarmError {
     fn from(err: Box<dyn std::error::Error + Send + Sync>) -> Self {
-        Error::Agent(err.to_string())
+        SwarmError::Agent(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversions() {
+        let io = std::io::Error::new(std::io::ErrorKind::NotFound, "missing");
+        assert!(matches!(SwarmError::from(io), SwarmError::Io(_)));
+
+        let cycle = crate::types::TodoError::Invalid("Task dependency cycle: a -> b -> a".to_string());
+        assert!(matches!(SwarmError::from(cycle), SwarmError::Validation(_)));
+
+        let other = SwarmError::from(anyhow::anyhow!("wrapped"));
+        assert_eq!(other.to_string(), "wrapped");
     }
 }
@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use chrono::{DateTime, Utc};
+use mongodb::Collection;
+use crate::clients::Clients;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, RwLock};
+use tokio::task::JoinHandle;
+use crate::mqtt::QoS;
+use crate::mqtt::MqttService;
+use crate::agents::inbox::ApprovalStatus;
+use crate::types::{TaskStatus, TodoTask};
+use crate::error::SwarmError;
+
+const DEFAULT_CAPACITY: usize = 256;
+
+static SHARED: OnceLock<EventBus> = OnceLock::new();
+
+/// Something that happened inside the process that other components may care about.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    TaskCreated { agent: String, task_id: String, description: String, status: TaskStatus },
+    TaskCompleted { agent: String, task_id: String, status: TaskStatus },
+    TaskCancelled { agent: String, task_id: String, status: TaskStatus },
+    TaskRequeued { agent: String, task_id: String, status: TaskStatus },
+    /// A worker run failed; `dead_lettered` when the task has no attempts left
+    TaskFailed { agent: String, task_id: String, error: String, dead_lettered: bool },
+    /// An agent parked a task until `questions` are answered
+    TaskNeedsInput { agent: String, task_id: String, questions: Vec<String> },
+    /// A gated operation is waiting in the [`ApprovalInbox`](crate::agents::ApprovalInbox)
+    ApprovalRequested { id: String, tool: String, params: HashMap<String, String> },
+    /// Someone approved or rejected a gated operation, or it timed out
+    ApprovalDecided { id: String, tool: String, status: ApprovalStatus },
+    AgentTransferred { from: String, to: String },
+    /// An agent reported its state after handling a message
+    StateChanged { agent: String, state: String },
+    /// An agent's state machine moved on `trigger`
+    StateTransitioned { agent: String, from: String, to: String, trigger: String },
+    /// A call through a [`ToolRegistry`](crate::tools::ToolRegistry) finished
+    ToolExecuted { tool: String, success: bool, duration_ms: u64, error: Option<String> },
+    /// A reinforcement learning run finished an episode; `q_entries` counts
+    /// the state-action pairs learned so far
+    TrainingEpisode {
+        run_id: String,
+        episode: usize,
+        reward: f64,
+        score: i32,
+        steps: usize,
+        epsilon: f64,
+        avg_q_value: Option<f64>,
+        q_entries: usize,
+    },
+}
+
+impl Event {
+    pub fn task_created(agent: &str, task: &TodoTask) -> Self {
+        Event::TaskCreated {
+            agent: agent.to_string(),
+            task_id: task.id.clone(),
+            description: task.description.clone(),
+            status: task.status.clone(),
+        }
+    }
+
+    pub fn task_completed(agent: &str, task: &TodoTask) -> Self {
+        Event::TaskCompleted { agent: agent.to_string(), task_id: task.id.clone(), status: task.status.clone() }
+    }
+
+    pub fn task_cancelled(agent: &str, task: &TodoTask) -> Self {
+        Event::TaskCancelled { agent: agent.to_string(), task_id: task.id.clone(), status: task.status.clone() }
+    }
+
+    pub fn task_requeued(agent: &str, task: &TodoTask) -> Self {
+        Event::TaskRequeued { agent: agent.to_string(), task_id: task.id.clone(), status: task.status.clone() }
+    }
+
+    pub fn task_needs_input(agent: &str, task: &TodoTask, questions: Vec<String>) -> Self {
+        Event::TaskNeedsInput { agent: agent.to_string(), task_id: task.id.clone(), questions }
+    }
+
+    /// Short name of the event, matching its serialized `type` tag
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::TaskCreated { .. } => "task_created",
+            Event::TaskCompleted { .. } => "task_completed",
+            Event::TaskCancelled { .. } => "task_cancelled",
+            Event::TaskRequeued { .. } => "task_requeued",
+            Event::TaskFailed { .. } => "task_failed",
+            Event::TaskNeedsInput { .. } => "task_needs_input",
+            Event::ApprovalRequested { .. } => "approval_requested",
+            Event::ApprovalDecided { .. } => "approval_decided",
+            Event::AgentTransferred { .. } => "agent_transferred",
+            Event::StateChanged { .. } => "state_changed",
+            Event::StateTransitioned { .. } => "state_transitioned",
+            Event::ToolExecuted { .. } => "tool_executed",
+            Event::TrainingEpisode { .. } => "training_episode",
+        }
+    }
+
+    /// MQTT topic the event is mirrored to by [`spawn_mqtt_bridge`]
+    pub fn topic(&self) -> String {
+        match self {
+            Event::TaskCreated { agent, .. } => format!("agent/{}/todo/created", agent),
+            Event::TaskCompleted { agent, .. } => format!("agent/{}/todo/completed", agent),
+            Event::TaskCancelled { agent, .. } => format!("agent/{}/todo/cancelled", agent),
+            Event::TaskRequeued { agent, .. } => format!("agent/{}/todo/requeued", agent),
+            Event::TaskFailed { agent, .. } => format!("agent/{}/todo/failed", agent),
+            Event::TaskNeedsInput { agent, .. } => format!("agent/{}/todo/needs_input", agent),
+            Event::ApprovalRequested { .. } => "user/inbox/requested".to_string(),
+            Event::ApprovalDecided { .. } => "user/inbox/decided".to_string(),
+            Event::AgentTransferred { from, .. } => format!("agent/{}/transfer", from),
+            Event::StateChanged { agent, .. } => format!("agent/{}/state", agent),
+            Event::StateTransitioned { agent, .. } => format!("agent/{}/state/transition", agent),
+            Event::ToolExecuted { tool, .. } => format!("tools/{}/executed", tool),
+            Event::TrainingEpisode { run_id, .. } => format!("rl/{}/metrics", run_id),
+        }
+    }
+}
+
+/// In-process broadcast of [`Event`]s. Cloning shares the same channel.
+///
+/// Publishers never block or fail when nobody is listening, and subscribers that
+/// fall too far behind skip the events they missed instead of stalling the bus.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Publish an event, returning how many subscribers will see it
+    pub fn publish(&self, event: Event) -> usize {
+        tracing::debug!(event = event.kind(), "Publishing event");
+        self.sender.send(event).unwrap_or(0)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    /// Process-wide bus. Agents, tool registries and the worker publish here
+    /// without being handed a bus; the API and sinks subscribe to it.
+    pub fn shared() -> Self {
+        SHARED.get_or_init(Self::default).clone()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+/// Run `handler` for every event on the bus until the bus is dropped.
+pub fn spawn_subscriber<F, Fut>(bus: &EventBus, name: &'static str, mut handler: F) -> JoinHandle<()>
+where
+    F: FnMut(Event) -> Fut + Send + 'static,
+    Fut: std::future::Future<Output = ()> + Send,
+{
+    let mut receiver = bus.subscribe();
+    tokio::spawn(async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => handler(event).await,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Event subscriber {} lagged, skipped {} events", name, skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+/// Mirror every event to MQTT so external services see the same stream.
+pub fn spawn_mqtt_bridge(bus: &EventBus, client: MqttService) -> JoinHandle<()> {
+    spawn_subscriber(bus, "mqtt_bridge", move |event| {
+        let client = client.clone();
+        async move {
+            let mut payload = serde_json::to_value(&event).unwrap_or_default();
+            if let Some(obj) = payload.as_object_mut() {
+                obj.insert("timestamp".to_string(), chrono::Utc::now().to_rfc3339().into());
+            }
+
+            if let Err(e) = client.publish(event.topic(), QoS::AtLeastOnce, false, payload.to_string()).await {
+                tracing::warn!("Failed to mirror {} event to MQTT: {}", event.kind(), e);
+            }
+        }
+    })
+}
+
+/// Publish events other processes mirrored to topics matching `filter` on
+/// `bus`, skipping those `keep` rejects (e.g. this process's own). Give it a
+/// bus that isn't bridged back to MQTT, or every event would be echoed forever.
+pub async fn spawn_mqtt_relay<F>(client: &MqttService, filter: &str, bus: EventBus, keep: F) -> crate::Result<JoinHandle<()>>
+where
+    F: Fn(&Event) -> bool + Send + 'static,
+{
+    let mut messages = client.subscribe(filter, QoS::AtMostOnce).await?;
+    Ok(tokio::spawn(async move {
+        while let Some(message) = messages.recv().await {
+            match message.json::<Event>() {
+                Ok(event) if keep(&event) => {
+                    bus.publish(event);
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Ignoring malformed event on {}: {}", message.topic, e),
+            }
+        }
+    }))
+}
+
+/// An event as kept by [`spawn_mongo_sink`]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EventRecord {
+    pub kind: String,
+    pub event: Event,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Append every event to `collection`, as an event log for replay and audit.
+pub fn spawn_mongo_sink(bus: &EventBus, collection: Collection<EventRecord>) -> JoinHandle<()> {
+    spawn_subscriber(bus, "mongo_sink", move |event| {
+        let collection = collection.clone();
+        async move {
+            let record = EventRecord { kind: event.kind().to_string(), event, timestamp: Utc::now() };
+            if let Err(e) = collection.insert_one(&record, None).await {
+                tracing::warn!("Failed to store {} event: {}", record.kind, e);
+            }
+        }
+    })
+}
+
+/// Start [`spawn_mongo_sink`] when `EVENT_LOG_COLLECTION` names a collection in
+/// the `RTK_MONGO_URI` database
+pub async fn spawn_mongo_sink_from_env(bus: &EventBus) -> crate::Result<Option<JoinHandle<()>>> {
+    let collection = std::env::var("EVENT_LOG_COLLECTION").unwrap_or_default();
+    if collection.is_empty() {
+        return Ok(None);
+    }
+    let uri = std::env::var("RTK_MONGO_URI")
+        .map_err(|_| SwarmError::Validation("EVENT_LOG_COLLECTION needs RTK_MONGO_URI".to_string()))?;
+    let db_name = std::env::var("RTK_MONGO_DB").unwrap_or_else(|_| "swarmonomicon".to_string());
+    let client = Clients::shared().mongo(&uri)?;
+    Ok(Some(spawn_mongo_sink(bus, client.database(&db_name).collection(&collection))))
+}
+
+/// Running count of events seen on the bus, keyed by [`Event::kind`].
+#[derive(Debug, Clone, Default)]
+pub struct EventMetrics {
+    counts: Arc<RwLock<HashMap<String, u64>>>,
+}
+
+impl EventMetrics {
+    /// Start counting events published on `bus`
+    pub fn spawn(bus: &EventBus) -> Self {
+        let metrics = Self::default();
+        let counts = metrics.counts.clone();
+        spawn_subscriber(bus, "metrics", move |event| {
+            let counts = counts.clone();
+            async move {
+                *counts.write().await.entry(event.kind().to_string()).or_insert(0) += 1;
+            }
+        });
+        metrics
+    }
+
+    pub async fn snapshot(&self) -> HashMap<String, u64> {
+        self.counts.read().await.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_publish_and_subscribe() {
+        let bus = EventBus::new(8);
+        assert_eq!(bus.publish(Event::AgentTransferred { from: "a".into(), to: "b".into() }), 0);
+
+        let mut receiver = bus.subscribe();
+        let event = Event::StateChanged { agent: "haiku".into(), state: "generating".into() };
+        assert_eq!(bus.publish(event.clone()), 1);
+        assert_eq!(receiver.recv().await.unwrap(), event);
+    }
+
+    #[test]
+    fn test_event_serialization_and_topic() {
+        let event = Event::TaskCancelled {
+            agent: "git".into(),
+            task_id: "t1".into(),
+            status: TaskStatus::Cancelled,
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "task_cancelled");
+        assert_eq!(json["task_id"], "t1");
+        assert_eq!(json["status"], "cancelled");
+        assert_eq!(event.kind(), "task_cancelled");
+        assert_eq!(event.topic(), "agent/git/todo/cancelled");
+
+        let event = Event::ToolExecuted { tool: "git".into(), success: false, duration_ms: 12, error: Some("dirty tree".into()) };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "tool_executed");
+        assert_eq!(event.topic(), "tools/git/executed");
+        assert_eq!(serde_json::from_value::<Event>(json).unwrap(), event);
+
+        let event = Event::TaskNeedsInput { agent: "git".into(), task_id: "t2".into(), questions: vec!["Which branch?".into()] };
+        assert_eq!(serde_json::to_value(&event).unwrap()["type"], "task_needs_input");
+        assert_eq!(event.topic(), "agent/git/todo/needs_input");
+
+        let event = Event::ApprovalDecided { id: "a1".into(), tool: "git".into(), status: ApprovalStatus::Rejected };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!((json["type"].as_str(), json["status"].as_str()), (Some("approval_decided"), Some("rejected")));
+        assert_eq!(event.topic(), "user/inbox/decided");
+
+        let event = Event::TrainingEpisode {
+            run_id: "flappy-1".into(),
+            episode: 12,
+            reward: 3.5,
+            score: 2,
+            steps: 140,
+            epsilon: 0.4,
+            avg_q_value: Some(0.25),
+            q_entries: 310,
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(json["type"], "training_episode");
+        assert_eq!(json["score"], 2);
+        assert_eq!(event.topic(), "rl/flappy-1/metrics");
+        assert_eq!(serde_json::from_value::<Event>(json).unwrap(), event);
+    }
+
+    #[tokio::test]
+    async fn test_metrics_subscriber() {
+        let bus = EventBus::default();
+        let metrics = EventMetrics::spawn(&bus);
+
+        bus.publish(Event::AgentTransferred { from: "a".into(), to: "b".into() });
+        bus.publish(Event::AgentTransferred { from: "b".into(), to: "a".into() });
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(metrics.snapshot().await.get("agent_transferred"), Some(&2));
+    }
+}
@@ -6,9 +6,22 @@ pub mod api;
 pub mod error;
 pub mod types;
 pub mod ai;
+pub mod telemetry;
+pub mod events;
+pub mod mcp;
+pub mod mqtt;
+pub mod state;
+pub mod access;
+pub mod recording;
+pub mod clock;
+pub mod clients;
+pub mod supervisor;
+pub mod worker;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 
-pub use error::Error;
-pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync>>;
+pub use error::{Error, SwarmError};
+pub type Result<T> = std::result::Result<T, SwarmError>;
 
 // Re-export commonly used types
 pub use types::{Agent, AgentConfig, Message, Tool, State};
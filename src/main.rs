@@ -1,18 +1,21 @@
-use std::net::SocketAddr;
 use swarmonomicon::api::{serve, create_app_state};
+use swarmonomicon::config::SwarmConfig;
 
 #[tokio::main]
 async fn main() {
     // Initialize the logger
-    env_logger::init();
+    swarmonomicon::telemetry::init_tracing(
+        "swarmonomicon-api",
+        tracing::Level::INFO,
+        tracing_subscriber::fmt::format::FmtSpan::NONE,
+    );
 
-    // Set up the server address
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
+    let config = SwarmConfig::from_cli_or_exit();
 
     // Create app state
     let app_state = create_app_state().await;
 
     // Run the server
-    println!("Starting server on {}", addr);
-    serve(addr, app_state.transfer_service.clone()).await;
+    println!("Starting server on {}", config.api.addr());
+    serve(&config, app_state.transfer_service.clone()).await;
 } 
@@ -0,0 +1,136 @@
+//! Short-lived copies of read-only tool responses. A fresh copy is served
+//! without a request; a stale one is kept with the server's validators so
+//! it can be revalidated with a conditional request instead of refetched.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Entries kept before the oldest is dropped
+const MAX_ENTRIES: usize = 64;
+
+/// What the server said identifies a response, for `If-None-Match` and
+/// `If-Modified-Since`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    body: String,
+    validators: Validators,
+    fetched: Instant,
+}
+
+/// Responses by request, fresh for `ttl`
+#[derive(Debug)]
+pub(crate) struct ResponseCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, Entry>>,
+    /// Bumped by every invalidation, so a response fetched before one isn't stored after it
+    generation: AtomicU64,
+    hits: AtomicU64,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+            generation: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.ttl.is_zero()
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+
+    /// The body for `key` if it was fetched within the TTL
+    pub fn fresh(&self, key: &str) -> Option<String> {
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(key).filter(|entry| entry.fetched.elapsed() < self.ttl)?;
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(entry.body.clone())
+    }
+
+    /// Validators of a stale entry for `key`, to revalidate it with
+    pub fn validators(&self, key: &str) -> Option<Validators> {
+        self.entries.lock().unwrap().get(key)
+            .map(|entry| entry.validators.clone())
+            .filter(|validators| !validators.is_empty())
+    }
+
+    /// The server says `key` hasn't changed: keep it for another TTL
+    pub fn renew(&self, key: &str, generation: u64) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap();
+        if generation != self.generation() {
+            return None;
+        }
+        let entry = entries.get_mut(key)?;
+        entry.fetched = Instant::now();
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(entry.body.clone())
+    }
+
+    /// Keep `body` for `key`, unless the cache was invalidated since
+    /// `generation` was read
+    pub fn store(&self, key: String, body: String, validators: Validators, generation: u64) {
+        let mut entries = self.entries.lock().unwrap();
+        if generation != self.generation() {
+            return;
+        }
+        if entries.len() >= MAX_ENTRIES && !entries.contains_key(&key) {
+            let oldest = entries.iter().min_by_key(|(_, entry)| entry.fetched).map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key, Entry { body, validators, fetched: Instant::now() });
+    }
+
+    /// Forget everything, e.g. after a write
+    pub fn invalidate(&self) {
+        let mut entries = self.entries.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::SeqCst);
+        entries.clear();
+    }
+
+    /// Answers served without fetching the body again
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invalidation_drops_in_flight_responses() {
+        let cache = ResponseCache::new(Duration::from_secs(60));
+        let before = cache.generation();
+        cache.store("q".to_string(), "old".to_string(), Validators::default(), before);
+        assert_eq!(cache.fresh("q").as_deref(), Some("old"));
+
+        // A query that started before a write must not repopulate the cache
+        let in_flight = cache.generation();
+        cache.invalidate();
+        cache.store("q".to_string(), "stale".to_string(), Validators::default(), in_flight);
+        assert_eq!(cache.fresh("q"), None);
+        assert_eq!(cache.hits(), 1);
+    }
+}
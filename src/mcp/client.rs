@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use crate::types::scheduler::parse_limits;
+use super::cache::{ResponseCache, Validators};
+use super::circuit_breaker::{BreakerState, CircuitBreaker};
+
+/// The MCP server could not be reached, as opposed to rejecting the request
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to call MCP server: {0}")]
+pub struct McpUnavailable(pub String);
+
+pub fn is_unavailable(error: &anyhow::Error) -> bool {
+    error.downcast_ref::<McpUnavailable>().is_some()
+}
+
+fn mcp_status_error(status: reqwest::StatusCode, error_text: String) -> anyhow::Error {
+    let message = format!("MCP server returned error {}: {}", status, error_text);
+    match status {
+        reqwest::StatusCode::BAD_GATEWAY
+        | reqwest::StatusCode::SERVICE_UNAVAILABLE
+        | reqwest::StatusCode::GATEWAY_TIMEOUT => anyhow::Error::new(McpUnavailable(message)),
+        _ => anyhow!(message),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct McpClientConfig {
+    pub base_url: String,
+    /// Timeout for tools without an entry in `endpoint_timeouts`
+    pub default_timeout: Duration,
+    /// Per-tool timeouts, e.g. longer for AI-backed tools
+    pub endpoint_timeouts: HashMap<String, Duration>,
+    /// Extra attempts after a call fails because the server was unreachable
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled for each further one
+    pub retry_backoff: Duration,
+    /// Consecutive failures that trip the circuit breaker
+    pub failure_threshold: u32,
+    /// How long the breaker stays open before a trial call
+    pub cooldown: Duration,
+    /// Idle connections kept per host
+    pub pool_max_idle: usize,
+    /// How long [`call_tool_cached`](McpClient::call_tool_cached) answers
+    /// repeat calls without asking the server; zero turns caching off
+    pub cache_ttl: Duration,
+}
+
+impl Default for McpClientConfig {
+    fn default() -> Self {
+        Self {
+            base_url: "http://localhost:8000".to_string(),
+            default_timeout: Duration::from_secs(30),
+            endpoint_timeouts: HashMap::new(),
+            max_retries: 2,
+            retry_backoff: Duration::from_millis(250),
+            failure_threshold: 5,
+            cooldown: Duration::from_secs(30),
+            pool_max_idle: 8,
+            cache_ttl: Duration::from_secs(5),
+        }
+    }
+}
+
+impl McpClientConfig {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self { base_url: base_url.into(), ..Self::default() }
+    }
+
+    /// Reads `MCP_SERVER_URL`, `MCP_TIMEOUT_SECS`, `MCP_ENDPOINT_TIMEOUTS`,
+    /// `MCP_MAX_RETRIES`, `MCP_RETRY_BACKOFF_MS`, `MCP_BREAKER_THRESHOLD`,
+    /// `MCP_BREAKER_COOLDOWN_SECS`, `MCP_POOL_MAX_IDLE` and `MCP_CACHE_TTL_MS`
+    pub fn from_env() -> Self {
+        fn read<T: FromStr>(key: &str, default: T) -> T {
+            env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+        }
+
+        let default = Self::default();
+        Self {
+            base_url: env::var("MCP_SERVER_URL").unwrap_or(default.base_url),
+            default_timeout: Duration::from_secs(read("MCP_TIMEOUT_SECS", default.default_timeout.as_secs())),
+            endpoint_timeouts: parse_limits(&env::var("MCP_ENDPOINT_TIMEOUTS").unwrap_or_default())
+                .into_iter()
+                .map(|(tool, secs)| (tool, Duration::from_secs(secs as u64)))
+                .collect(),
+            max_retries: read("MCP_MAX_RETRIES", default.max_retries),
+            retry_backoff: Duration::from_millis(read("MCP_RETRY_BACKOFF_MS", default.retry_backoff.as_millis() as u64)),
+            failure_threshold: read("MCP_BREAKER_THRESHOLD", default.failure_threshold),
+            cooldown: Duration::from_secs(read("MCP_BREAKER_COOLDOWN_SECS", default.cooldown.as_secs())),
+            pool_max_idle: read("MCP_POOL_MAX_IDLE", default.pool_max_idle),
+            cache_ttl: Duration::from_millis(read("MCP_CACHE_TTL_MS", default.cache_ttl.as_millis() as u64)),
+        }
+    }
+
+    pub fn timeout_for(&self, tool: &str) -> Duration {
+        self.endpoint_timeouts.get(tool).copied().unwrap_or(self.default_timeout)
+    }
+}
+
+/// Calls MCP tool endpoints over a pooled connection. Clones share the pool,
+/// the circuit breaker and the response cache, so every caller sees the
+/// server go down, and every write invalidate cached reads, at once.
+#[derive(Debug, Clone)]
+pub struct McpClient {
+    http_client: reqwest::Client,
+    config: Arc<McpClientConfig>,
+    breaker: Arc<CircuitBreaker>,
+    cache: Arc<ResponseCache>,
+}
+
+/// A tool's answer to a possibly conditional request
+enum Reply {
+    Body { text: String, validators: Validators },
+    /// 304: the copy the validators came from is still current
+    NotModified,
+}
+
+static SHARED: OnceLock<McpClient> = OnceLock::new();
+
+impl McpClient {
+    pub fn new(config: McpClientConfig) -> Result<Self> {
+        let http_client = reqwest::Client::builder()
+            .pool_max_idle_per_host(config.pool_max_idle)
+            .pool_idle_timeout(Duration::from_secs(90))
+            .connect_timeout(Duration::from_secs(5))
+            .build()
+            .map_err(|e| anyhow!("Failed to create HTTP client: {}", e))?;
+
+        Ok(Self {
+            http_client,
+            breaker: Arc::new(CircuitBreaker::new(config.failure_threshold, config.cooldown)),
+            cache: Arc::new(ResponseCache::new(config.cache_ttl)),
+            config: Arc::new(config),
+        })
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Self::new(McpClientConfig::from_env())
+    }
+
+    /// Process-wide client configured from the environment
+    pub fn shared() -> Result<Self> {
+        if let Some(client) = SHARED.get() {
+            return Ok(client.clone());
+        }
+        let client = Self::from_env()?;
+        Ok(SHARED.get_or_init(|| client).clone())
+    }
+
+    /// Make a client built from `config` the process-wide one. Call before
+    /// anything uses [`shared`](Self::shared); later calls keep the first client.
+    pub fn init_shared(config: McpClientConfig) -> Result<Self> {
+        let client = Self::new(config)?;
+        Ok(SHARED.get_or_init(|| client).clone())
+    }
+
+    pub fn config(&self) -> &McpClientConfig {
+        &self.config
+    }
+
+    pub fn breaker_state(&self) -> BreakerState {
+        self.breaker.state()
+    }
+
+    /// Breaker state and settings, for metrics reporting
+    pub fn status_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "server": self.config.base_url,
+            "breaker": self.breaker.state().as_str(),
+            "consecutive_failures": self.breaker.consecutive_failures(),
+            "cache_hits": self.cache.hits(),
+        })
+    }
+
+    /// POST `body` to a tool endpoint and return the response body. Calls that
+    /// fail because the server is unreachable are retried with backoff and
+    /// count against the circuit breaker; while it is open, calls fail fast
+    /// with [`McpUnavailable`].
+    pub async fn call_tool<B: Serialize + ?Sized>(&self, tool: &str, body: &B) -> Result<String> {
+        match self.call(tool, body, None).await? {
+            Reply::Body { text, .. } => Ok(text),
+            Reply::NotModified => Err(anyhow!("MCP server answered {} with 304 to an unconditional request", tool)),
+        }
+    }
+
+    /// Like [`call_tool`](Self::call_tool), for read-only tools. Repeat calls
+    /// within `cache_ttl` are answered from memory; after that the cached
+    /// answer is revalidated with `If-None-Match`/`If-Modified-Since` when
+    /// the server sent an `ETag` or `Last-Modified`. Call
+    /// [`invalidate_cache`](Self::invalidate_cache) after every write.
+    pub async fn call_tool_cached<B: Serialize + ?Sized>(&self, tool: &str, body: &B) -> Result<String> {
+        if !self.cache.enabled() {
+            return self.call_tool(tool, body).await;
+        }
+        let key = format!("{}:{}", tool, serde_json::to_string(body)?);
+        if let Some(text) = self.cache.fresh(&key) {
+            return Ok(text);
+        }
+
+        let generation = self.cache.generation();
+        let validators = self.cache.validators(&key);
+        match self.call(tool, body, validators.as_ref()).await? {
+            Reply::Body { text, validators } => {
+                self.cache.store(key, text.clone(), validators, generation);
+                Ok(text)
+            }
+            // Invalidated while we asked; fetch the body again rather than trust the old copy
+            Reply::NotModified => match self.cache.renew(&key, generation) {
+                Some(text) => Ok(text),
+                None => self.call_tool(tool, body).await,
+            },
+        }
+    }
+
+    /// Drop every cached response, e.g. after changing a todo
+    pub fn invalidate_cache(&self) {
+        self.cache.invalidate();
+    }
+
+    async fn call<B: Serialize + ?Sized>(&self, tool: &str, body: &B, validators: Option<&Validators>) -> Result<Reply> {
+        let mut attempt = 0;
+        loop {
+            if !self.breaker.allow() {
+                return Err(anyhow::Error::new(McpUnavailable(format!("circuit open, skipping {}", tool))));
+            }
+
+            match self.send(tool, body, validators).await {
+                Ok(reply) => {
+                    self.breaker.record_success();
+                    return Ok(reply);
+                }
+                Err(e) if is_unavailable(&e) => {
+                    self.breaker.record_failure();
+                    if attempt >= self.config.max_retries {
+                        return Err(e);
+                    }
+                    let delay = self.config.retry_backoff * 2u32.pow(attempt);
+                    tracing::debug!("MCP call to {} failed ({}), retrying in {:?}", tool, e, delay);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                // The server answered, so it is up even though it rejected the request
+                Err(e) => {
+                    self.breaker.record_success();
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    async fn send<B: Serialize + ?Sized>(&self, tool: &str, body: &B, validators: Option<&Validators>) -> Result<Reply> {
+        let mut request = self.http_client
+            .post(&format!("{}/tools/{}", self.config.base_url, tool))
+            .header("Content-Type", "application/json")
+            .timeout(self.config.timeout_for(tool))
+            .json(body);
+        if let Some(id) = crate::telemetry::current_correlation_id() {
+            request = request.header(crate::telemetry::CORRELATION_HEADER, id);
+        }
+        if let Some(validators) = validators {
+            if let Some(etag) = &validators.etag {
+                request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+            }
+        }
+
+        let response = request.send()
+            .await
+            .map_err(|e| anyhow::Error::new(McpUnavailable(e.to_string())))?;
+
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(Reply::NotModified);
+        }
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            return Err(mcp_status_error(status, error_text));
+        }
+
+        let header = |name: reqwest::header::HeaderName| response.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let validators = Validators {
+            etag: header(reqwest::header::ETAG),
+            last_modified: header(reqwest::header::LAST_MODIFIED),
+        };
+        let text = response.text().await
+            .map_err(|e| anyhow!("Failed to read MCP response: {}", e))?;
+        Ok(Reply::Body { text, validators })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_timeouts() {
+        let mut config = McpClientConfig::new("http://mcp:8000");
+        config.endpoint_timeouts.insert("add_todo_tool".to_string(), Duration::from_secs(60));
+        assert_eq!(config.timeout_for("add_todo_tool"), Duration::from_secs(60));
+        assert_eq!(config.timeout_for("query_todos_tool"), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_status_errors() {
+        assert!(is_unavailable(&mcp_status_error(reqwest::StatusCode::SERVICE_UNAVAILABLE, String::new())));
+        assert!(!is_unavailable(&mcp_status_error(reqwest::StatusCode::NOT_FOUND, String::new())));
+    }
+
+    #[tokio::test]
+    async fn test_breaker_opens_on_unreachable_server() -> Result<()> {
+        // Nothing listens on the discard port
+        let client = McpClient::new(McpClientConfig {
+            max_retries: 1,
+            retry_backoff: Duration::from_millis(1),
+            failure_threshold: 2,
+            cooldown: Duration::from_secs(60),
+            ..McpClientConfig::new("http://127.0.0.1:9")
+        })?;
+
+        let err = client.call_tool("query_todos_tool", &serde_json::json!({})).await.unwrap_err();
+        assert!(is_unavailable(&err));
+        assert_eq!(client.breaker_state(), BreakerState::Open);
+
+        // Fails fast without touching the network
+        let err = client.clone().call_tool("query_todos_tool", &serde_json::json!({})).await.unwrap_err();
+        assert!(err.to_string().contains("circuit open"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cached_calls_revalidate_with_etag() -> Result<()> {
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("POST")).and(path("/tools/query_todos_tool")).and(header("if-none-match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .with_priority(1)
+            .mount(&server).await;
+        Mock::given(method("POST")).and(path("/tools/query_todos_tool"))
+            .respond_with(ResponseTemplate::new(200).insert_header("etag", "\"v1\"").set_body_string("[]"))
+            .mount(&server).await;
+        let client = McpClient::new(McpClientConfig {
+            max_retries: 0,
+            cache_ttl: Duration::from_millis(50),
+            ..McpClientConfig::new(server.uri())
+        })?;
+        let body = serde_json::json!({ "limit": 100 });
+        let requests = || async { server.received_requests().await.unwrap_or_default().len() };
+
+        assert_eq!(client.call_tool_cached("query_todos_tool", &body).await?, "[]");
+        assert_eq!(client.call_tool_cached("query_todos_tool", &body).await?, "[]");
+        assert_eq!(requests().await, 1);
+
+        // Stale now, so the server is asked and answers 304
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(client.call_tool_cached("query_todos_tool", &body).await?, "[]");
+        assert_eq!(requests().await, 2);
+        assert_eq!(client.status_json()["cache_hits"], 2);
+
+        client.invalidate_cache();
+        client.call_tool_cached("query_todos_tool", &body).await?;
+        assert_eq!(requests().await, 3);
+        assert_eq!(client.status_json()["cache_hits"], 2);
+        Ok(())
+    }
+}
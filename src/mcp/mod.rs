@@ -0,0 +1,16 @@
+//! Model Context Protocol support: a client for the Omnispindle MCP server's
+//! tool endpoints and, with the `mcp-server` feature, a server exposing this
+//! crate's own tools.
+
+pub mod cache;
+pub mod circuit_breaker;
+pub mod client;
+pub mod schema;
+#[cfg(feature = "mcp-server")]
+pub mod server;
+
+pub use cache::Validators;
+pub use circuit_breaker::{BreakerState, CircuitBreaker};
+pub use client::{McpClient, McpClientConfig, McpUnavailable, is_unavailable};
+#[cfg(feature = "mcp-server")]
+pub use server::{McpServer, ToolSpec};
@@ -0,0 +1,253 @@
+//! Request and response bodies of the Omnispindle MCP tool endpoints.
+//!
+//! Responses are parsed strictly: a body missing a required field or carrying
+//! a value of the wrong type is an error rather than an empty result, so schema
+//! drift on the server shows up immediately.
+
+use std::collections::HashMap;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::types::{TaskPriority, TaskStatus, TodoTask, MCP_PRIORITY_KEY};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AddTodoRequest {
+    pub description: String,
+    pub project: String,
+    pub priority: String,
+    pub target_agent: String,
+    pub metadata: Option<HashMap<String, Value>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UpdateTodoRequest {
+    pub todo_id: String,
+    pub updates: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QueryTodosRequest {
+    /// JSON-encoded Mongo filter
+    pub query_or_filter: Option<String>,
+    pub fields_or_projection: Option<String>,
+    pub limit: Option<i32>,
+}
+
+/// Body of the get, mark-complete and delete endpoints
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TodoIdRequest {
+    pub todo_id: String,
+}
+
+/// Envelope every tool endpoint responds with
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct McpResponse<T> {
+    pub success: bool,
+    #[serde(default = "Option::default")]
+    pub data: Option<T>,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+impl<T: DeserializeOwned> McpResponse<T> {
+    pub fn parse(body: &str) -> Result<Self> {
+        serde_json::from_str(body).map_err(|e| {
+            let preview: String = body.chars().take(200).collect();
+            anyhow!("MCP response does not match the Omnispindle schema: {} (body: {})", e, preview)
+        })
+    }
+
+    /// The payload of a successful response
+    pub fn into_data(self) -> Result<T> {
+        if !self.success {
+            return Err(anyhow!("MCP server error: {}", self.message.as_deref().unwrap_or("Unknown MCP error")));
+        }
+        self.data.ok_or_else(|| anyhow!("MCP response reported success without data"))
+    }
+
+    /// The message of a successful response
+    pub fn into_message(self, default: &str) -> Result<String> {
+        if !self.success {
+            return Err(anyhow!("MCP server error: {}", self.message.as_deref().unwrap_or("Unknown MCP error")));
+        }
+        Ok(self.message.unwrap_or_else(|| default.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QueryTodosData {
+    pub items: Vec<OmnispindleTodo>,
+    #[serde(default)]
+    pub count: Option<usize>,
+}
+
+/// A todo as Omnispindle stores it
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OmnispindleTodo {
+    pub id: String,
+    pub description: String,
+    #[serde(default)]
+    pub project: Option<String>,
+    pub priority: TaskPriority,
+    pub status: TaskStatus,
+    #[serde(default = "default_target_agent")]
+    pub target_agent: String,
+    pub created_at: i64,
+    #[serde(default)]
+    pub updated_at: Option<i64>,
+    #[serde(default)]
+    pub completed_at: Option<i64>,
+    #[serde(default)]
+    pub completion_comment: Option<String>,
+    #[serde(default)]
+    pub metadata: HashMap<String, Value>,
+}
+
+fn default_target_agent() -> String {
+    "user".to_string()
+}
+
+impl From<OmnispindleTodo> for TodoTask {
+    fn from(todo: OmnispindleTodo) -> Self {
+        let metadata_str = |key: &str| todo.metadata.get(key).and_then(|v| v.as_str()).map(|s| s.to_string());
+        TodoTask {
+            enhanced_description: metadata_str("enhanced_description"),
+            source_agent: metadata_str("source"),
+            idempotency_key: metadata_str("idempotency_key"),
+            parent_id: metadata_str("parent_id"),
+            depends_on: todo.metadata.get("depends_on")
+                .and_then(|v| serde_json::from_value(v.clone()).ok())
+                .unwrap_or_default(),
+            id: todo.id,
+            description: todo.description,
+            priority: TaskPriority::from_mcp(todo.priority, metadata_str(MCP_PRIORITY_KEY).as_deref()),
+            project: todo.project,
+            target_agent: todo.target_agent,
+            status: todo.status,
+            created_at: todo.created_at,
+            completed_at: todo.completed_at,
+            notes: todo.completion_comment,
+            last_modified: todo.updated_at,
+            ..Default::default()
+        }
+    }
+}
+
+/// One field changed by a todo mutation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChangeEntry {
+    pub field: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_value: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_value: Option<Value>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogOperation {
+    Create,
+    Update,
+    Delete,
+    Complete,
+}
+
+/// An entry in Omnispindle's todo change log
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LogEntry {
+    pub timestamp: DateTime<Utc>,
+    pub operation: LogOperation,
+    #[serde(rename = "todoId")]
+    pub todo_id: String,
+    pub description: String,
+    pub project: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changes: Option<Vec<ChangeEntry>>,
+    #[serde(rename = "userAgent")]
+    pub user_agent: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUERY_FIXTURE: &str = include_str!("fixtures/query_todos.json");
+    const GET_FIXTURE: &str = include_str!("fixtures/get_todo.json");
+    const ADD_FIXTURE: &str = include_str!("fixtures/add_todo.json");
+    const ERROR_FIXTURE: &str = include_str!("fixtures/error.json");
+    const LOG_FIXTURE: &str = include_str!("fixtures/log_entry.json");
+
+    /// Parsing and re-serializing a fixture must not lose anything we model
+    fn round_trip<T: Serialize + DeserializeOwned + PartialEq + std::fmt::Debug>(body: &str) -> T {
+        let parsed: T = serde_json::from_str(body).unwrap();
+        let reparsed: T = serde_json::from_str(&serde_json::to_string(&parsed).unwrap()).unwrap();
+        assert_eq!(parsed, reparsed);
+        parsed
+    }
+
+    #[test]
+    fn test_query_todos_contract() {
+        let response: McpResponse<QueryTodosData> = round_trip(QUERY_FIXTURE);
+        let data = response.into_data().unwrap();
+        assert_eq!(data.items.len(), 2);
+        assert_eq!(data.count, Some(2));
+
+        let task = TodoTask::from(data.items[0].clone());
+        assert_eq!(task.priority, TaskPriority::High);
+        assert_eq!(task.status, TaskStatus::Pending);
+        assert_eq!(task.project.as_deref(), Some("swarmonomicon"));
+        assert!(task.enhanced_description.is_some());
+        assert!(task.idempotency_key.is_some());
+
+        let completed = TodoTask::from(data.items[1].clone());
+        assert_eq!(completed.status, TaskStatus::Completed);
+        assert!(completed.completed_at.is_some());
+        assert_eq!(completed.notes.as_deref(), Some("Shipped in 0.1.3"));
+    }
+
+    #[test]
+    fn test_get_todo_contract() {
+        let response: McpResponse<OmnispindleTodo> = round_trip(GET_FIXTURE);
+        assert_eq!(response.into_data().unwrap().target_agent, "git");
+    }
+
+    #[test]
+    fn test_add_todo_contract() {
+        let response: McpResponse<Value> = round_trip(ADD_FIXTURE);
+        assert!(response.success);
+        assert_eq!(response.into_message("").unwrap(), "Todo created successfully");
+    }
+
+    #[test]
+    fn test_error_response_is_an_error() {
+        let response = McpResponse::<OmnispindleTodo>::parse(ERROR_FIXTURE).unwrap();
+        let err = response.into_data().unwrap_err();
+        assert!(err.to_string().contains("not found"));
+    }
+
+    #[test]
+    fn test_schema_drift_fails_loudly() {
+        // A todo without a status, and one whose priority the schema doesn't know
+        let missing_field = r#"{"success": true, "data": {"items": [{"id": "1", "description": "x", "priority": "High", "created_at": 1}]}}"#;
+        assert!(McpResponse::<QueryTodosData>::parse(missing_field).is_err());
+
+        let bad_priority = r#"{"success": true, "data": {"id": "1", "description": "x", "priority": "Urgent", "status": "pending", "created_at": 1}}"#;
+        let err = McpResponse::<OmnispindleTodo>::parse(bad_priority).unwrap_err();
+        assert!(err.to_string().contains("Omnispindle schema"));
+
+        // The envelope itself is required
+        assert!(McpResponse::<Value>::parse(r#"{"items": []}"#).is_err());
+    }
+
+    #[test]
+    fn test_log_entry_contract() {
+        let entry: LogEntry = round_trip(LOG_FIXTURE);
+        assert_eq!(entry.operation, LogOperation::Update);
+        assert_eq!(entry.user_agent, "swarmonomicon");
+        let changes = entry.changes.unwrap();
+        assert_eq!(changes[0].field, "status");
+        assert_eq!(changes[0].new_value, Some(Value::String("completed".to_string())));
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use anyhow::Result;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::{get, post},
+    Json, Router,
+};
+use futures::stream::{self, Stream, StreamExt};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::{mpsc, Mutex};
+use crate::tools::{
+    GitTool, ObjectDetectionTool, ProjectTool, ScreenshotDetectionTool, TodoTool, ToolExecutor, ToolRegistry,
+};
+use crate::types::Tool;
+
+/// MCP protocol revision this server speaks
+pub const PROTOCOL_VERSION: &str = "2024-11-05";
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// A tool as advertised by `tools/list`. Every argument is a string, matching
+/// the `HashMap<String, String>` parameters tools are executed with.
+#[derive(Debug, Clone)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    properties: serde_json::Map<String, Value>,
+    required: Vec<String>,
+}
+
+impl ToolSpec {
+    pub fn new(name: impl Into<String>, description: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            properties: serde_json::Map::new(),
+            required: Vec::new(),
+        }
+    }
+
+    pub fn required(mut self, name: &str, description: &str) -> Self {
+        self.required.push(name.to_string());
+        self.optional(name, description)
+    }
+
+    pub fn optional(mut self, name: &str, description: &str) -> Self {
+        self.properties.insert(name.to_string(), json!({ "type": "string", "description": description }));
+        self
+    }
+
+    pub fn input_schema(&self) -> Value {
+        json!({
+            "type": "object",
+            "properties": self.properties,
+            "required": self.required,
+        })
+    }
+
+    fn as_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "description": self.description,
+            "inputSchema": self.input_schema(),
+        })
+    }
+}
+
+/// Exposes registered tools over the Model Context Protocol (JSON-RPC 2.0),
+/// either on stdio or over HTTP with server-sent events.
+pub struct McpServer {
+    registry: ToolRegistry,
+    specs: Vec<ToolSpec>,
+}
+
+impl McpServer {
+    pub fn new() -> Self {
+        Self { registry: ToolRegistry::new(), specs: Vec::new() }
+    }
+
+    pub fn with_tool<T: ToolExecutor + 'static>(mut self, spec: ToolSpec, executor: T) -> Self {
+        self.registry.register(spec.name.clone(), executor);
+        self.specs.push(spec);
+        self
+    }
+
+    /// Git, project, todo and detection tools
+    pub async fn with_default_tools() -> Result<Self> {
+        Ok(Self::new()
+            .with_tool(
+                ToolSpec::new("git", "Run a git operation in the working directory")
+                    .required("command", "One of: diff, branch, stage, commit, merge")
+                    .optional("name", "Branch name, for branch")
+                    .optional("message", "Commit message, for commit")
+                    .optional("target", "Branch to merge into, for merge"),
+                GitTool::new(),
+            )
+            .with_tool(
+                ToolSpec::new("project", "Scaffold a new project")
+                    .required("type", "One of: python, rust, common")
+                    .required("name", "Project name")
+                    .required("description", "What the project is for"),
+                ProjectTool::new(),
+            )
+            .with_tool(
+                ToolSpec::new("todo", "Add, list, complete or fail todos")
+                    .required("command", "One of: add, list, complete, fail")
+                    .optional("description", "Todo description; required except for list")
+                    .optional("context", "Extra context for add")
+                    .optional("target_agent", "Agent that should handle the todo")
+                    .optional("project", "Project name; predicted when omitted")
+                    .optional("idempotency_key", "Key that makes retried adds return the original todo"),
+                TodoTool::new().await?,
+            )
+            .with_tool(
+                ToolSpec::new("object_detection", "Detect objects in an image")
+                    .optional("image", "Path to the image; defaults to the first image in attachments")
+                    .optional("attachments", "JSON list of attachments")
+                    .optional("confidence", "Minimum confidence, 0 to 1")
+                    .optional("classes", "Comma-separated classes to report, e.g. person,laptop")
+                    .optional("annotate", "false to skip writing the annotated image"),
+                ObjectDetectionTool::new(),
+            )
+            .with_tool(
+                ToolSpec::new("screenshot_detection", "Capture the screen and detect objects on it"),
+                ScreenshotDetectionTool::new(),
+            ))
+    }
+
+    pub fn tools(&self) -> &[ToolSpec] {
+        &self.specs
+    }
+
+    /// Handle one JSON-RPC message. Returns `None` for notifications.
+    pub async fn handle(&self, message: Value) -> Option<Value> {
+        let id = message.get("id").cloned();
+        let Some(method) = message.get("method").and_then(|m| m.as_str()) else {
+            return Some(error_response(id.unwrap_or(Value::Null), INVALID_REQUEST, "Missing method"));
+        };
+        // Notifications (no id) never get a response
+        let id = id?;
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+
+        let result = match method {
+            "initialize" => Ok(json!({
+                "protocolVersion": PROTOCOL_VERSION,
+                "capabilities": { "tools": { "listChanged": false } },
+                "serverInfo": { "name": "swarmonomicon", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "ping" => Ok(json!({})),
+            "tools/list" => Ok(json!({
+                "tools": self.specs.iter().map(ToolSpec::as_json).collect::<Vec<_>>()
+            })),
+            "tools/call" => self.call_tool(params).await,
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method: {}", method))),
+        };
+
+        Some(match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error_response(id, code, &message),
+        })
+    }
+
+    /// Handle a raw message, reporting unparseable input as a JSON-RPC error
+    pub async fn handle_str(&self, line: &str) -> Option<String> {
+        let response = match serde_json::from_str::<Value>(line) {
+            Ok(message) => self.handle(message).await?,
+            Err(e) => error_response(Value::Null, PARSE_ERROR, &e.to_string()),
+        };
+        Some(response.to_string())
+    }
+
+    async fn call_tool(&self, params: Value) -> std::result::Result<Value, (i64, String)> {
+        let name = params.get("name").and_then(|n| n.as_str())
+            .ok_or((INVALID_PARAMS, "Missing tool name".to_string()))?;
+        let spec = self.specs.iter().find(|spec| spec.name == name)
+            .ok_or_else(|| (INVALID_PARAMS, format!("Unknown tool: {}", name)))?;
+
+        let arguments = match params.get("arguments") {
+            Some(Value::Object(args)) => args.iter()
+                .map(|(key, value)| (key.clone(), match value {
+                    Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                }))
+                .collect(),
+            None | Some(Value::Null) => HashMap::new(),
+            Some(_) => return Err((INVALID_PARAMS, "Tool arguments must be an object".to_string())),
+        };
+
+        let tool = Tool {
+            name: spec.name.clone(),
+            description: spec.description.clone(),
+            parameters: HashMap::new(),
+        };
+        // Tool failures are results the client's model should see, not protocol errors
+        let (text, is_error) = match self.registry.execute(&tool, arguments).await {
+            Ok(output) => (output, false),
+            Err(e) => (e.to_string(), true),
+        };
+        Ok(json!({
+            "content": [{ "type": "text", "text": text }],
+            "isError": is_error,
+        }))
+    }
+
+    /// Serve newline-delimited JSON-RPC on stdin/stdout until stdin closes
+    pub async fn serve_stdio(self) -> Result<()> {
+        let mut lines = BufReader::new(tokio::io::stdin()).lines();
+        let mut stdout = tokio::io::stdout();
+        while let Some(line) = lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Some(response) = self.handle_str(&line).await {
+                stdout.write_all(response.as_bytes()).await?;
+                stdout.write_all(b"\n").await?;
+                stdout.flush().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// HTTP+SSE transport: clients open `GET /sse`, are told which endpoint to
+    /// POST messages to, and receive responses as `message` events.
+    pub fn sse_router(self) -> Router {
+        let state = Arc::new(SseState { server: self, sessions: Mutex::new(HashMap::new()) });
+        Router::new()
+            .route("/sse", get(sse_handler))
+            .route("/message", post(message_handler))
+            .with_state(state)
+    }
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+struct SseState {
+    server: McpServer,
+    sessions: Mutex<HashMap<String, mpsc::Sender<String>>>,
+}
+
+async fn sse_handler(
+    State(state): State<Arc<SseState>>,
+) -> Sse<impl Stream<Item = std::result::Result<Event, Infallible>>> {
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let (tx, rx) = mpsc::channel(32);
+    state.sessions.lock().await.insert(session_id.clone(), tx);
+    tracing::info!("MCP SSE session {} opened", session_id);
+
+    let endpoint = Event::default().event("endpoint").data(format!("/message?sessionId={}", session_id));
+    let messages = stream::unfold(rx, |mut rx| async move {
+        let message = rx.recv().await?;
+        Some((Ok(Event::default().event("message").data(message)), rx))
+    });
+
+    Sse::new(stream::once(async move { Ok(endpoint) }).chain(messages))
+        .keep_alive(KeepAlive::default())
+}
+
+async fn message_handler(
+    State(state): State<Arc<SseState>>,
+    Query(query): Query<HashMap<String, String>>,
+    Json(message): Json<Value>,
+) -> StatusCode {
+    let Some(session_id) = query.get("sessionId") else {
+        return StatusCode::BAD_REQUEST;
+    };
+    let Some(tx) = state.sessions.lock().await.get(session_id).cloned() else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    let session_id = session_id.clone();
+    tokio::spawn(async move {
+        if let Some(response) = state.server.handle(message).await {
+            if tx.send(response.to_string()).await.is_err() {
+                tracing::info!("MCP SSE session {} closed", session_id);
+                state.sessions.lock().await.remove(&session_id);
+            }
+        }
+    });
+    StatusCode::ACCEPTED
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+
+    struct EchoTool;
+
+    #[async_trait]
+    impl ToolExecutor for EchoTool {
+        async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+            params.get("text").cloned().ok_or_else(|| anyhow::anyhow!("Missing text"))
+        }
+    }
+
+    fn server() -> McpServer {
+        McpServer::new().with_tool(ToolSpec::new("echo", "Echo text").required("text", "Text to echo"), EchoTool)
+    }
+
+    #[tokio::test]
+    async fn test_list_and_call() {
+        let server = server();
+        let listed = server.handle(json!({ "jsonrpc": "2.0", "id": 1, "method": "tools/list" })).await.unwrap();
+        assert_eq!(listed["result"]["tools"][0]["name"], "echo");
+        assert_eq!(listed["result"]["tools"][0]["inputSchema"]["required"][0], "text");
+
+        let called = server.handle(json!({
+            "jsonrpc": "2.0", "id": 2, "method": "tools/call",
+            "params": { "name": "echo", "arguments": { "text": "hello" } }
+        })).await.unwrap();
+        assert_eq!(called["id"], 2);
+        assert_eq!(called["result"]["content"][0]["text"], "hello");
+        assert_eq!(called["result"]["isError"], false);
+
+        // Tool failures come back as results flagged isError
+        let failed = server.handle(json!({
+            "jsonrpc": "2.0", "id": 3, "method": "tools/call", "params": { "name": "echo" }
+        })).await.unwrap();
+        assert_eq!(failed["result"]["isError"], true);
+    }
+
+    #[tokio::test]
+    async fn test_protocol_errors() {
+        let server = server();
+        assert!(server.handle(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" })).await.is_none());
+
+        let unknown = server.handle(json!({ "jsonrpc": "2.0", "id": 1, "method": "resources/list" })).await.unwrap();
+        assert_eq!(unknown["error"]["code"], METHOD_NOT_FOUND);
+
+        let missing = server.handle(json!({
+            "jsonrpc": "2.0", "id": 2, "method": "tools/call", "params": { "name": "nope" }
+        })).await.unwrap();
+        assert_eq!(missing["error"]["code"], INVALID_PARAMS);
+
+        let garbage: Value = serde_json::from_str(&server.handle_str("{not json").await.unwrap()).unwrap();
+        assert_eq!(garbage["error"]["code"], PARSE_ERROR);
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use sha2::{Digest, Sha256};
+use crate::clock::{self, Clock};
+use super::MqttMessage;
+
+/// Keys remembered before the oldest are evicted, regardless of the window
+const DEFAULT_CAPACITY: usize = 10_000;
+
+/// Remembers recently seen message keys so broker redeliveries and producer
+/// retries are handled once
+pub struct DedupCache {
+    window: Duration,
+    capacity: usize,
+    seen: Mutex<HashMap<String, Instant>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl DedupCache {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            capacity: DEFAULT_CAPACITY,
+            seen: Mutex::new(HashMap::new()),
+            clock: clock::system(),
+        }
+    }
+
+    /// Reads the window from `INTAKE_DEDUP_WINDOW_SECS` (default 60; 0 disables)
+    pub fn from_env() -> Self {
+        let secs = env::var("INTAKE_DEDUP_WINDOW_SECS").ok().and_then(|s| s.parse().ok()).unwrap_or(60);
+        Self::new(Duration::from_secs(secs))
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Hash of the message's topic and payload, for publishers that send no key of their own
+    pub fn message_key(message: &MqttMessage) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(message.topic.as_bytes());
+        hasher.update([0u8]);
+        hasher.update(&message.payload);
+        format!("{:x}", hasher.finalize())
+    }
+
+    /// Whether `key` was already seen within the window. Unseen keys are recorded.
+    pub fn is_duplicate(&self, key: &str) -> bool {
+        self.is_duplicate_at(key, self.clock.instant())
+    }
+
+    fn is_duplicate_at(&self, key: &str, now: Instant) -> bool {
+        if self.window.is_zero() {
+            return false;
+        }
+        let mut seen = self.seen.lock().unwrap();
+        if let Some(first_seen) = seen.get(key) {
+            if now.duration_since(*first_seen) < self.window {
+                return true;
+            }
+        }
+
+        if seen.len() >= self.capacity {
+            seen.retain(|_, first_seen| now.duration_since(*first_seen) < self.window);
+            // Still full of live keys: forget the oldest
+            if seen.len() >= self.capacity {
+                if let Some(oldest) = seen.iter().min_by_key(|(_, first_seen)| **first_seen).map(|(k, _)| k.clone()) {
+                    seen.remove(&oldest);
+                }
+            }
+        }
+        seen.insert(key.to_string(), now);
+        false
+    }
+
+    pub fn len(&self) -> usize {
+        self.seen.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mqtt::QoS;
+
+    #[test]
+    fn test_duplicates_within_window() {
+        let cache = DedupCache::new(Duration::from_secs(60));
+        let start = Instant::now();
+        assert!(!cache.is_duplicate_at("a", start));
+        assert!(cache.is_duplicate_at("a", start + Duration::from_secs(30)));
+        assert!(!cache.is_duplicate_at("b", start + Duration::from_secs(30)));
+
+        // Seen again after the window: processed, and the window restarts
+        assert!(!cache.is_duplicate_at("a", start + Duration::from_secs(61)));
+        assert!(cache.is_duplicate_at("a", start + Duration::from_secs(62)));
+    }
+
+    #[test]
+    fn test_capacity_evicts_expired_then_oldest() {
+        let cache = DedupCache::new(Duration::from_secs(60)).with_capacity(2);
+        let start = Instant::now();
+        cache.is_duplicate_at("a", start);
+        cache.is_duplicate_at("b", start + Duration::from_secs(1));
+        cache.is_duplicate_at("c", start + Duration::from_secs(2));
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.is_duplicate_at("a", start + Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn test_window_follows_clock() {
+        let clock = Arc::new(clock::FakeClock::at(0));
+        let cache = DedupCache::new(Duration::from_secs(60)).with_clock(clock.clone());
+        assert!(!cache.is_duplicate("a"));
+        clock.advance(Duration::from_secs(59));
+        assert!(cache.is_duplicate("a"));
+        clock.advance(Duration::from_secs(2));
+        assert!(!cache.is_duplicate("a"));
+    }
+
+    #[test]
+    fn test_zero_window_disables() {
+        let cache = DedupCache::new(Duration::ZERO);
+        assert!(!cache.is_duplicate("a"));
+        assert!(!cache.is_duplicate("a"));
+    }
+
+    #[test]
+    fn test_message_key() {
+        let message = |topic: &str, payload: &[u8]| MqttMessage {
+            topic: topic.into(),
+            payload: bytes::Bytes::copy_from_slice(payload),
+            qos: QoS::ExactlyOnce,
+            retain: false,
+            response_topic: None,
+            correlation_data: None,
+            content_type: None,
+        };
+        let key = DedupCache::message_key(&message("mcp/git", b"commit"));
+        assert_eq!(key, DedupCache::message_key(&message("mcp/git", b"commit")));
+        assert_ne!(key, DedupCache::message_key(&message("mcp/user", b"commit")));
+        assert_ne!(key, DedupCache::message_key(&message("mcp/git", b"commit!")));
+    }
+}
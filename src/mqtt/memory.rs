@@ -0,0 +1,98 @@
+use std::sync::{Arc, Mutex, Weak};
+use tokio::sync::Mutex as AsyncMutex;
+use super::service::{dispatch_to, Subscription};
+use super::{topic_matches, MqttConfig, MqttMessage, MqttService};
+
+/// An in-process stand-in for the broker, for tests that exercise MQTT flows
+/// without mosquitto. Services connected to it see each other's publishes
+/// exactly as they would through a real broker, topic prefixes included.
+/// Retained messages are recorded but not replayed to later subscribers.
+#[derive(Clone, Default)]
+pub struct MemoryBroker {
+    clients: Arc<Mutex<Vec<Client>>>,
+    published: Arc<Mutex<Vec<MqttMessage>>>,
+}
+
+struct Client {
+    config: Arc<MqttConfig>,
+    subscriptions: Weak<AsyncMutex<Vec<Subscription>>>,
+}
+
+impl MemoryBroker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A service connected to this broker
+    pub fn connect(&self, config: MqttConfig) -> MqttService {
+        MqttService::in_memory(config, self.clone())
+    }
+
+    pub(super) fn attach(&self, config: Arc<MqttConfig>, subscriptions: Weak<AsyncMutex<Vec<Subscription>>>) {
+        self.clients.lock().unwrap().push(Client { config, subscriptions });
+    }
+
+    /// Record `message`, whose topic is a full broker topic, and deliver it to
+    /// every connected service subscribed to it
+    pub(super) async fn publish(&self, message: MqttMessage) {
+        self.published.lock().unwrap().push(message.clone());
+        let deliveries: Vec<_> = {
+            let mut clients = self.clients.lock().unwrap();
+            clients.retain(|client| client.subscriptions.strong_count() > 0);
+            clients.iter()
+                .filter_map(|client| {
+                    let topic = client.config.strip_prefix(&message.topic)?;
+                    Some((client.subscriptions.upgrade()?, Arc::<str>::from(topic)))
+                })
+                .collect()
+        };
+        for (subscriptions, topic) in deliveries {
+            dispatch_to(&subscriptions, MqttMessage { topic, ..message.clone() }).await;
+        }
+    }
+
+    /// Everything published so far, oldest first, with full broker topics
+    pub fn published(&self) -> Vec<MqttMessage> {
+        self.published.lock().unwrap().clone()
+    }
+
+    /// Published messages whose full topic matches `filter`
+    pub fn published_on(&self, filter: &str) -> Vec<MqttMessage> {
+        self.published().into_iter()
+            .filter(|message| topic_matches(filter, &message.topic))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mqtt::QoS;
+
+    #[tokio::test]
+    async fn test_services_talk_through_the_broker() -> anyhow::Result<()> {
+        let broker = MemoryBroker::new();
+        let worker = broker.connect(MqttConfig::new("worker", "memory", 0).with_topic_prefix("staging"));
+        let intake = broker.connect(MqttConfig::new("intake", "memory", 0).with_topic_prefix("staging"));
+        let other = broker.connect(MqttConfig::new("other", "memory", 0).with_topic_prefix("prod"));
+        assert!(worker.is_connected());
+
+        let mut requests = worker.subscribe("agent/+/todo/process", QoS::AtLeastOnce).await?;
+        let mut replies = intake.subscribe("replies/#", QoS::AtLeastOnce).await?;
+        let mut unrelated = other.subscribe("agent/+/todo/process", QoS::AtLeastOnce).await?;
+
+        intake.publish_request("agent/git/todo/process", "commit", "replies/1", "1").await?;
+        let request = requests.recv().await.unwrap();
+        assert_eq!(&*request.topic, "agent/git/todo/process");
+        assert_eq!(request.response_topic.as_deref(), Some("staging/replies/1"));
+        assert!(unrelated.try_recv().is_err(), "other namespaces don't see it");
+
+        worker.reply(&request, "agent/git/todo/response", "done").await?;
+        let reply = replies.recv().await.unwrap();
+        assert_eq!((reply.payload_str().as_ref(), reply.correlation_data.as_deref()), ("done", Some(&b"1"[..])));
+
+        assert_eq!(broker.published().len(), 2);
+        assert_eq!(broker.published_on("staging/replies/+").len(), 1);
+        Ok(())
+    }
+}
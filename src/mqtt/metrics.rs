@@ -0,0 +1,202 @@
+//! How metrics reports go out. Every binary publishes one every few seconds,
+//! so over a slow broker link they are most of the traffic: they can be
+//! gzipped, cut down to what changed since the last report, and sent at a
+//! lower QoS than task traffic.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::env;
+use std::io::{Read, Write};
+use crate::Result;
+use crate::error::SwarmError;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde_json::{Map, Value};
+use super::QoS;
+
+/// MQTT v5 user property marking a compressed payload
+pub const CONTENT_ENCODING: &str = "content-encoding";
+pub const GZIP: &str = "gzip";
+/// Set on reports that only carry what changed
+pub const DELTA_FIELD: &str = "_delta";
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsPublishing {
+    pub qos: QoS,
+    /// Gzip each report, marked with a `content-encoding: gzip` user property
+    pub compress: bool,
+    /// Send only what changed since the last report on the topic
+    pub delta: bool,
+    /// With `delta`, every this many reports is sent in full so new
+    /// subscribers catch up
+    pub full_every: u32,
+}
+
+impl Default for MetricsPublishing {
+    fn default() -> Self {
+        Self { qos: QoS::AtLeastOnce, compress: false, delta: false, full_every: 6 }
+    }
+}
+
+impl MetricsPublishing {
+    /// Reads `MQTT_METRICS_QOS`, `MQTT_COMPRESS_METRICS`, `MQTT_METRICS_DELTA`
+    /// and `MQTT_METRICS_FULL_EVERY`
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let var = |key: &str| env::var(key).ok();
+        Self {
+            qos: var("MQTT_METRICS_QOS").and_then(|q| qos_from_level(q.trim().parse().ok()?)).unwrap_or(default.qos),
+            compress: var("MQTT_COMPRESS_METRICS").and_then(|v| v.parse().ok()).unwrap_or(default.compress),
+            delta: var("MQTT_METRICS_DELTA").and_then(|v| v.parse().ok()).unwrap_or(default.delta),
+            full_every: var("MQTT_METRICS_FULL_EVERY").and_then(|v| v.parse().ok()).unwrap_or(default.full_every),
+        }
+    }
+}
+
+/// The QoS for an MQTT level, 0 to 2
+pub fn qos_from_level(level: u8) -> Option<QoS> {
+    match level {
+        0 => Some(QoS::AtMostOnce),
+        1 => Some(QoS::AtLeastOnce),
+        2 => Some(QoS::ExactlyOnce),
+        _ => None,
+    }
+}
+
+/// The last report per topic, for working out deltas
+#[derive(Debug, Default)]
+pub(super) struct MetricsHistory {
+    last: HashMap<String, (Value, u32)>,
+}
+
+impl MetricsHistory {
+    /// What to send for `report` on `topic`: all of it on the first report
+    /// and every `full_every` after, otherwise what changed
+    pub fn next<'a>(&mut self, topic: &str, report: &'a Value, full_every: u32) -> Cow<'a, Value> {
+        if let Some((last, since_full)) = self.last.get_mut(topic) {
+            if *since_full + 1 < full_every.max(1) {
+                *since_full += 1;
+                let changed = diff(last, report);
+                *last = report.clone();
+                let mut delta = match changed {
+                    Some(Value::Object(changed)) => changed,
+                    Some(other) => return Cow::Owned(other),
+                    None => Map::new(),
+                };
+                delta.insert(DELTA_FIELD.to_string(), Value::Bool(true));
+                return Cow::Owned(Value::Object(delta));
+            }
+        }
+        self.last.insert(topic.to_string(), (report.clone(), 0));
+        Cow::Borrowed(report)
+    }
+}
+
+/// What changed from `previous` to `current`: objects are compared key by
+/// key, with removed keys set to null; anything else is replaced whole.
+/// `None` when nothing changed.
+pub fn diff(previous: &Value, current: &Value) -> Option<Value> {
+    match (previous, current) {
+        (Value::Object(previous), Value::Object(current)) => {
+            let mut changed = Map::new();
+            for (key, value) in current {
+                let delta = match previous.get(key) {
+                    Some(old) => diff(old, value),
+                    None => Some(value.clone()),
+                };
+                if let Some(delta) = delta {
+                    changed.insert(key.clone(), delta);
+                }
+            }
+            for key in previous.keys().filter(|key| !current.contains_key(*key)) {
+                changed.insert(key.clone(), Value::Null);
+            }
+            (!changed.is_empty()).then_some(Value::Object(changed))
+        }
+        _ if previous == current => None,
+        _ => Some(current.clone()),
+    }
+}
+
+/// Apply a report from [`diff`] to the last full one. Null removes a key.
+pub fn merge(base: &mut Value, delta: &Value) {
+    match (base, delta) {
+        (Value::Object(base), Value::Object(delta)) => {
+            for (key, value) in delta {
+                if key == DELTA_FIELD {
+                    continue;
+                }
+                match value {
+                    Value::Null => {
+                        base.remove(key);
+                    }
+                    Value::Object(_) if base.get(key).map_or(false, Value::is_object) => {
+                        merge(base.get_mut(key).unwrap(), value);
+                    }
+                    _ => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, delta) => *base = delta.clone(),
+    }
+}
+
+pub fn gzip(payload: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(payload)?;
+    Ok(encoder.finish()?)
+}
+
+/// A metrics payload as JSON, unzipped if it was compressed. Recognises gzip
+/// by its header, for subscribers that don't see MQTT v5 user properties.
+pub fn decode(payload: &[u8]) -> Result<Value> {
+    let json: Cow<[u8]> = if payload.starts_with(&GZIP_MAGIC) {
+        let mut unzipped = Vec::new();
+        GzDecoder::new(payload).read_to_end(&mut unzipped)
+            .map_err(|e| SwarmError::Mqtt(format!("Failed to unzip metrics payload: {}", e)))?;
+        Cow::Owned(unzipped)
+    } else {
+        Cow::Borrowed(payload)
+    };
+    Ok(serde_json::from_slice(&json)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_deltas_rebuild_the_report() {
+        let mut history = MetricsHistory::default();
+        let first = json!({ "processed": 1, "queue": { "depth": 3, "capacity": 100 }, "uptime": 10 });
+        let second = json!({ "processed": 2, "queue": { "depth": 3, "capacity": 100 }, "uptime": 20 });
+        let third = json!({ "processed": 2, "queue": { "depth": 0, "capacity": 100 } });
+
+        assert_eq!(history.next("m", &first, 3).into_owned(), first);
+        let delta = history.next("m", &second, 3).into_owned();
+        assert_eq!(delta, json!({ "processed": 2, "uptime": 20, "_delta": true }));
+        let delta_two = history.next("m", &third, 3).into_owned();
+        assert_eq!(delta_two, json!({ "queue": { "depth": 0 }, "uptime": null, "_delta": true }));
+
+        let mut rebuilt = first.clone();
+        merge(&mut rebuilt, &delta);
+        merge(&mut rebuilt, &delta_two);
+        assert_eq!(rebuilt, third);
+
+        // Every third report is whole again
+        assert_eq!(history.next("m", &third, 3).into_owned(), third);
+    }
+
+    #[test]
+    fn test_compressed_payloads_decode() -> Result<()> {
+        let report = json!({ "processed": 42, "failed": 0 });
+        let bytes = serde_json::to_vec(&report)?;
+        assert_eq!(decode(&gzip(&bytes)?)?, report);
+        assert_eq!(decode(&bytes)?, report);
+        Ok(())
+    }
+}
@@ -0,0 +1,21 @@
+//! Shared MQTT connection handling for the worker binaries and the API.
+//!
+//! [`MqttService`] owns the rumqttc event loop, reconnects on failure,
+//! re-subscribes after every reconnect, and fans incoming publishes out to
+//! bounded per-subscription channels.
+
+mod dedup;
+#[cfg(any(test, feature = "test-support"))]
+mod memory;
+pub mod metrics;
+pub mod payload;
+mod queue;
+mod service;
+
+pub use dedup::DedupCache;
+#[cfg(any(test, feature = "test-support"))]
+pub use memory::MemoryBroker;
+pub use metrics::MetricsPublishing;
+pub use payload::PayloadFormat;
+pub use queue::{BoundedQueue, OverflowPolicy, Pushed, QueueStats};
+pub use service::{MqttConfig, MqttMessage, MqttService, Presence, QoS, Will, topic_matches};
@@ -0,0 +1,135 @@
+//! Encodings for task payloads. JSON is the default; publishers on metered
+//! links, like Cogwyrm on mobile data, can send MessagePack or CBOR instead
+//! and say so with a topic suffix (`mcp/tasker/msgpack`) or the MQTT v5
+//! content type. Replies stay JSON.
+
+use crate::Result;
+use crate::error::SwarmError;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PayloadFormat {
+    #[default]
+    Json,
+    MessagePack,
+    Cbor,
+}
+
+impl PayloadFormat {
+    /// Formats other than JSON, which need a topic suffix or content type
+    pub const BINARY: [PayloadFormat; 2] = [PayloadFormat::MessagePack, PayloadFormat::Cbor];
+
+    /// Last topic level naming the format; JSON topics have none
+    pub fn suffix(self) -> Option<&'static str> {
+        match self {
+            PayloadFormat::Json => None,
+            PayloadFormat::MessagePack => Some("msgpack"),
+            PayloadFormat::Cbor => Some("cbor"),
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            PayloadFormat::Json => "application/json",
+            PayloadFormat::MessagePack => "application/msgpack",
+            PayloadFormat::Cbor => "application/cbor",
+        }
+    }
+
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let essence = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        match essence.as_str() {
+            "application/json" => Some(PayloadFormat::Json),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => Some(PayloadFormat::MessagePack),
+            "application/cbor" => Some(PayloadFormat::Cbor),
+            _ => None,
+        }
+    }
+
+    /// The format named by `topic`'s suffix, JSON when there is none
+    pub fn from_topic(topic: &str) -> Self {
+        let last = topic.rsplit('/').next().unwrap_or_default();
+        Self::BINARY.into_iter()
+            .find(|format| format.suffix() == Some(last))
+            .unwrap_or_default()
+    }
+
+    /// `filter` plus a variant for each binary format's suffix, to subscribe
+    /// to a task topic in every format
+    pub fn topic_filters(filter: &str) -> Vec<String> {
+        std::iter::once(filter.to_string())
+            .chain(Self::BINARY.into_iter().filter_map(|format| Some(format!("{}/{}", filter, format.suffix()?))))
+            .collect()
+    }
+
+    pub fn encode<T: Serialize + ?Sized>(self, value: &T) -> Result<Vec<u8>> {
+        match self {
+            PayloadFormat::Json => Ok(serde_json::to_vec(value)?),
+            // Field names are kept, so either side can add optional fields
+            PayloadFormat::MessagePack => rmp_serde::to_vec_named(value)
+                .map_err(|e| SwarmError::Mqtt(format!("Failed to encode MessagePack: {}", e))),
+            PayloadFormat::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::ser::into_writer(value, &mut bytes).map_err(|e| SwarmError::Mqtt(format!("Failed to encode CBOR: {}", e)))?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, payload: &[u8]) -> Result<T> {
+        match self {
+            PayloadFormat::Json => Ok(serde_json::from_slice(payload)?),
+            PayloadFormat::MessagePack => rmp_serde::from_slice(payload)
+                .map_err(|e| SwarmError::Mqtt(format!("Failed to decode MessagePack: {}", e))),
+            PayloadFormat::Cbor => ciborium::de::from_reader(payload).map_err(|e| SwarmError::Mqtt(format!("Failed to decode CBOR: {}", e))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use crate::types::TodoTask;
+
+    #[test]
+    fn test_tasks_round_trip_in_every_format() -> Result<()> {
+        let task: TodoTask = serde_json::from_value(json!({
+            "id": "task-1",
+            "description": "Water the plants",
+            "priority": "High",
+            "project": "lab_management",
+            "target_agent": "user",
+            "status": "pending",
+            "created_at": 1_770_000_000,
+            "depends_on": ["task-0"],
+            "dry_run": true,
+        }))?;
+        let as_json = serde_json::to_value(&task)?;
+
+        for format in [PayloadFormat::Json, PayloadFormat::MessagePack, PayloadFormat::Cbor] {
+            let bytes = format.encode(&task)?;
+            let decoded: TodoTask = format.decode(&bytes)?;
+            assert_eq!(serde_json::to_value(&decoded)?, as_json, "{:?}", format);
+        }
+        // Binary is the point: both are smaller than the JSON
+        let json_len = PayloadFormat::Json.encode(&task)?.len();
+        assert!(PayloadFormat::MessagePack.encode(&task)?.len() < json_len);
+        assert!(PayloadFormat::Cbor.encode(&task)?.len() < json_len);
+        Ok(())
+    }
+
+    #[test]
+    fn test_negotiation() {
+        assert_eq!(PayloadFormat::from_topic("mcp/tasker/msgpack"), PayloadFormat::MessagePack);
+        assert_eq!(PayloadFormat::from_topic("agent/git/todo/process/cbor"), PayloadFormat::Cbor);
+        assert_eq!(PayloadFormat::from_topic("agent/git/todo/process"), PayloadFormat::Json);
+        assert_eq!(PayloadFormat::from_content_type("application/x-msgpack"), Some(PayloadFormat::MessagePack));
+        assert_eq!(PayloadFormat::from_content_type("application/json; charset=utf-8"), Some(PayloadFormat::Json));
+        assert_eq!(PayloadFormat::topic_filters("mcp/+"), vec!["mcp/+", "mcp/+/msgpack", "mcp/+/cbor"]);
+
+        let value: Value = PayloadFormat::Cbor.decode(&PayloadFormat::Cbor.encode(&json!({ "n": 1 })).unwrap()).unwrap();
+        assert_eq!(value, json!({ "n": 1 }));
+    }
+}
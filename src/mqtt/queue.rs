@@ -0,0 +1,225 @@
+use std::collections::VecDeque;
+use std::env;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use crate::error::SwarmError;
+use serde::Serialize;
+use tokio::sync::Notify;
+
+/// What a full [`BoundedQueue`] does with one more item
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Make room by discarding the item that has waited longest
+    DropOldest,
+    /// Turn the new item away
+    Reject,
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = SwarmError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().replace('-', "_").as_str() {
+            "drop_oldest" => Ok(Self::DropOldest),
+            "reject" => Ok(Self::Reject),
+            other => Err(SwarmError::Validation(format!("Unknown queue overflow policy: {}", other))),
+        }
+    }
+}
+
+/// Outcome of [`BoundedQueue::push`]. Items that didn't make it are handed
+/// back so the caller can tell their sender.
+#[derive(Debug, PartialEq)]
+pub enum Pushed<T> {
+    Queued,
+    DroppedOldest(T),
+    Rejected(T),
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, PartialEq)]
+pub struct QueueStats {
+    pub depth: usize,
+    pub capacity: usize,
+    /// Deepest the queue has been
+    pub high_water: usize,
+    pub enqueued: u64,
+    pub dropped: u64,
+    pub rejected: u64,
+}
+
+struct State<T> {
+    items: VecDeque<T>,
+    stats: QueueStats,
+    closed: bool,
+}
+
+/// Hands received messages to a pool of workers without ever making the
+/// receiving side wait: when the workers fall behind, the overflow policy
+/// decides what gets lost.
+pub struct BoundedQueue<T> {
+    state: Arc<Mutex<State<T>>>,
+    notify: Arc<Notify>,
+    policy: OverflowPolicy,
+}
+
+impl<T> Clone for BoundedQueue<T> {
+    fn clone(&self) -> Self {
+        Self { state: self.state.clone(), notify: self.notify.clone(), policy: self.policy }
+    }
+}
+
+impl<T> BoundedQueue<T> {
+    pub fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            state: Arc::new(Mutex::new(State {
+                items: VecDeque::with_capacity(capacity),
+                stats: QueueStats { capacity, ..Default::default() },
+                closed: false,
+            })),
+            notify: Arc::new(Notify::new()),
+            policy,
+        }
+    }
+
+    /// Reads `TASK_QUEUE_CAPACITY` (default 100) and `TASK_QUEUE_POLICY`
+    /// (`reject` or `drop_oldest`, default `reject`)
+    pub fn from_env() -> Self {
+        let capacity = env::var("TASK_QUEUE_CAPACITY").ok().and_then(|s| s.parse().ok()).unwrap_or(100);
+        let policy = match env::var("TASK_QUEUE_POLICY") {
+            Ok(policy) => policy.parse().unwrap_or_else(|e| {
+                tracing::warn!("{}; rejecting when full", e);
+                OverflowPolicy::Reject
+            }),
+            Err(_) => OverflowPolicy::Reject,
+        };
+        Self::new(capacity, policy)
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    pub fn push(&self, item: T) -> Pushed<T> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            state.stats.rejected += 1;
+            return Pushed::Rejected(item);
+        }
+
+        let mut outcome = Pushed::Queued;
+        if state.items.len() >= state.stats.capacity {
+            match self.policy {
+                OverflowPolicy::Reject => {
+                    state.stats.rejected += 1;
+                    return Pushed::Rejected(item);
+                }
+                OverflowPolicy::DropOldest => {
+                    if let Some(oldest) = state.items.pop_front() {
+                        state.stats.dropped += 1;
+                        outcome = Pushed::DroppedOldest(oldest);
+                    }
+                }
+            }
+        }
+
+        state.items.push_back(item);
+        state.stats.enqueued += 1;
+        state.stats.high_water = state.stats.high_water.max(state.items.len());
+        drop(state);
+        self.notify.notify_one();
+        outcome
+    }
+
+    /// The next item, waiting for one if the queue is empty. `None` once the
+    /// queue is closed and drained.
+    pub async fn pop(&self) -> Option<T> {
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            // Register before checking so a close in between still wakes us
+            notified.as_mut().enable();
+            {
+                let mut state = self.state.lock().unwrap();
+                if let Some(item) = state.items.pop_front() {
+                    return Some(item);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Stop accepting items; workers finish what is queued and then stop
+    pub fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.notify.notify_waiters();
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        let state = self.state.lock().unwrap();
+        QueueStats { depth: state.items.len(), ..state.stats }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reject_when_full() {
+        let queue = BoundedQueue::new(2, OverflowPolicy::Reject);
+        assert_eq!(queue.push(1), Pushed::Queued);
+        assert_eq!(queue.push(2), Pushed::Queued);
+        assert_eq!(queue.push(3), Pushed::Rejected(3));
+
+        let stats = queue.stats();
+        assert_eq!(stats.depth, 2);
+        assert_eq!(stats.high_water, 2);
+        assert_eq!(stats.enqueued, 2);
+        assert_eq!(stats.rejected, 1);
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest_when_full() {
+        let queue = BoundedQueue::new(2, OverflowPolicy::DropOldest);
+        queue.push(1);
+        queue.push(2);
+        assert_eq!(queue.push(3), Pushed::DroppedOldest(1));
+        assert_eq!(queue.stats().dropped, 1);
+
+        assert_eq!(queue.pop().await, Some(2));
+        assert_eq!(queue.pop().await, Some(3));
+        assert_eq!(queue.stats().depth, 0);
+    }
+
+    #[tokio::test]
+    async fn test_close_drains_then_stops_workers() {
+        let queue = BoundedQueue::new(4, OverflowPolicy::Reject);
+        let worker = {
+            let queue = queue.clone();
+            tokio::spawn(async move {
+                let mut seen = Vec::new();
+                while let Some(item) = queue.pop().await {
+                    seen.push(item);
+                }
+                seen
+            })
+        };
+
+        queue.push("a");
+        queue.push("b");
+        queue.close();
+        assert_eq!(queue.push("c"), Pushed::Rejected("c"));
+        assert_eq!(worker.await.unwrap(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_policy_parsing() {
+        assert_eq!("drop-oldest".parse::<OverflowPolicy>().unwrap(), OverflowPolicy::DropOldest);
+        assert_eq!("REJECT".parse::<OverflowPolicy>().unwrap(), OverflowPolicy::Reject);
+        assert!("block".parse::<OverflowPolicy>().is_err());
+    }
+}
@@ -0,0 +1,767 @@
+use std::borrow::Cow;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+use crate::Result;
+use crate::error::SwarmError;
+use bytes::Bytes;
+use rumqttc::v5::{AsyncClient, Event, EventLoop, MqttOptions};
+use rumqttc::v5::mqttbytes::v5::{LastWill, Packet, PublishProperties};
+pub use rumqttc::v5::mqttbytes::QoS;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::{mpsc, watch, Mutex};
+use super::metrics::{self, MetricsHistory, MetricsPublishing};
+use super::payload::PayloadFormat;
+
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Connection settings for an [`MqttService`]
+#[derive(Debug, Clone)]
+pub struct MqttConfig {
+    pub client_id: String,
+    pub host: String,
+    pub port: u16,
+    pub keep_alive: Duration,
+    pub clean_session: bool,
+    pub credentials: Option<(String, String)>,
+    /// Namespace prepended to every topic, so environments can share a broker
+    pub topic_prefix: String,
+    pub last_will: Option<Will>,
+    /// Retained online/offline status maintained across reconnects
+    pub presence: Option<Presence>,
+    /// Outgoing requests buffered before publishes start waiting
+    pub request_capacity: usize,
+    /// Incoming messages buffered per subscription before the event loop waits
+    pub subscriber_capacity: usize,
+    /// QoS for task requests and replies
+    pub task_qos: QoS,
+    /// How [`publish_metrics`](MqttService::publish_metrics) sends reports
+    pub metrics: MetricsPublishing,
+}
+
+impl MqttConfig {
+    pub fn new(client_id: impl Into<String>, host: impl Into<String>, port: u16) -> Self {
+        Self {
+            client_id: client_id.into(),
+            host: host.into(),
+            port,
+            keep_alive: Duration::from_secs(20),
+            clean_session: true,
+            credentials: None,
+            topic_prefix: String::new(),
+            last_will: None,
+            presence: None,
+            request_capacity: 100,
+            subscriber_capacity: 64,
+            task_qos: QoS::ExactlyOnce,
+            metrics: MetricsPublishing::default(),
+        }
+    }
+
+    /// Reads the broker from `MQTT_HOST`/`MQTT_PORT`, falling back to the older
+    /// `AWSIP`/`AWSPORT` and then `localhost:1883`, with credentials from
+    /// `MQTT_USERNAME`/`MQTT_PASSWORD`, `MQTT_KEEP_ALIVE_SECS`, the topic
+    /// namespace from `MQTT_TOPIC_PREFIX`, task QoS from `MQTT_TASK_QOS` and
+    /// metrics publishing as [`MetricsPublishing::from_env`] reads it.
+    pub fn from_env(client_id: impl Into<String>) -> Self {
+        let host = env::var("MQTT_HOST")
+            .or_else(|_| env::var("AWSIP"))
+            .unwrap_or_else(|_| "localhost".to_string());
+        let port = env::var("MQTT_PORT")
+            .or_else(|_| env::var("AWSPORT"))
+            .ok()
+            .and_then(|p| p.parse().ok())
+            .unwrap_or(1883);
+
+        let mut config = Self::new(client_id, host, port);
+        if let (Ok(username), Ok(password)) = (env::var("MQTT_USERNAME"), env::var("MQTT_PASSWORD")) {
+            config.credentials = Some((username, password));
+        }
+        if let Ok(prefix) = env::var("MQTT_TOPIC_PREFIX") {
+            config = config.with_topic_prefix(prefix);
+        }
+        if let Some(secs) = env::var("MQTT_KEEP_ALIVE_SECS").ok().and_then(|s| s.parse().ok()) {
+            config.keep_alive = Duration::from_secs(secs);
+        }
+        if let Some(qos) = env::var("MQTT_TASK_QOS").ok().and_then(|q| metrics::qos_from_level(q.trim().parse().ok()?)) {
+            config.task_qos = qos;
+        }
+        config.with_metrics_publishing(MetricsPublishing::from_env())
+    }
+
+    pub fn with_keep_alive(mut self, keep_alive: Duration) -> Self {
+        self.keep_alive = keep_alive;
+        self
+    }
+
+    pub fn with_task_qos(mut self, qos: QoS) -> Self {
+        self.task_qos = qos;
+        self
+    }
+
+    pub fn with_metrics_publishing(mut self, metrics: MetricsPublishing) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn with_topic_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.topic_prefix = prefix.into().trim_matches('/').to_string();
+        self
+    }
+
+    pub fn with_last_will(mut self, topic: impl Into<String>, payload: impl Into<Vec<u8>>, retain: bool) -> Self {
+        self.last_will = Some(Will { topic: topic.into(), payload: payload.into(), retain });
+        self
+    }
+
+    /// Keep a retained status on `topic`: "online" on every connect, and
+    /// "offline" from the broker if the process dies without disconnecting
+    pub fn with_presence(self, topic: impl Into<String>, instance_id: impl Into<String>) -> Self {
+        let presence = Presence { topic: topic.into(), instance_id: instance_id.into() };
+        let mut config = self.with_last_will(presence.topic.clone(), presence.will_payload(), true);
+        config.presence = Some(presence);
+        config
+    }
+
+    /// `topic` as it appears on the broker
+    pub fn topic(&self, topic: &str) -> String {
+        if self.topic_prefix.is_empty() {
+            topic.to_string()
+        } else {
+            format!("{}/{}", self.topic_prefix, topic)
+        }
+    }
+
+    /// The inverse of [`topic`](Self::topic); `None` for topics outside the namespace
+    pub fn strip_prefix<'a>(&self, topic: &'a str) -> Option<&'a str> {
+        if self.topic_prefix.is_empty() {
+            return Some(topic);
+        }
+        topic.strip_prefix(self.topic_prefix.as_str())?.strip_prefix('/')
+    }
+
+    fn options(&self) -> MqttOptions {
+        let mut options = MqttOptions::new(self.client_id.clone(), self.host.clone(), self.port);
+        options.set_keep_alive(self.keep_alive);
+        options.set_clean_start(self.clean_session);
+        if let Some((username, password)) = &self.credentials {
+            options.set_credentials(username.clone(), password.clone());
+        }
+        if let Some(will) = &self.last_will {
+            // The will topic is namespaced like any other
+            let topic = self.topic(&will.topic);
+            options.set_last_will(LastWill::new(topic, will.payload.clone(), QoS::AtLeastOnce, will.retain, None));
+        }
+        options
+    }
+}
+
+/// Published by the broker if the client disconnects without saying goodbye
+#[derive(Debug, Clone)]
+pub struct Will {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub retain: bool,
+}
+
+/// A retained status topic identifying one running instance
+#[derive(Debug, Clone)]
+pub struct Presence {
+    pub topic: String,
+    pub instance_id: String,
+}
+
+impl Presence {
+    fn will_payload(&self) -> Vec<u8> {
+        // Registered at connect time, so a timestamp would be misleading
+        json!({
+            "status": "offline",
+            "instance_id": self.instance_id,
+            "version": env!("CARGO_PKG_VERSION"),
+        }).to_string().into_bytes()
+    }
+
+    fn payload(&self, status: &str) -> Vec<u8> {
+        json!({
+            "status": status,
+            "instance_id": self.instance_id,
+            "version": env!("CARGO_PKG_VERSION"),
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        }).to_string().into_bytes()
+    }
+}
+
+/// A publish received on one of the service's subscriptions. Topic, payload
+/// and correlation data are shared, so handing a message to every matching
+/// subscriber copies none of them.
+#[derive(Debug, Clone)]
+pub struct MqttMessage {
+    /// Topic with the namespace prefix removed
+    pub topic: Arc<str>,
+    /// The broker's buffer, as received
+    pub payload: Bytes,
+    pub qos: QoS,
+    pub retain: bool,
+    /// Where the publisher asked for the reply to go (MQTT v5), as a full broker topic
+    pub response_topic: Option<String>,
+    /// Opaque data the publisher expects echoed back on the reply (MQTT v5)
+    pub correlation_data: Option<Bytes>,
+    /// How the payload is encoded, if the publisher said (MQTT v5)
+    pub content_type: Option<String>,
+}
+
+impl MqttMessage {
+    pub fn payload_str(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.payload)
+    }
+
+    /// How the payload is encoded: the content type if there is one we know,
+    /// then the topic suffix, then JSON
+    pub fn format(&self) -> PayloadFormat {
+        self.content_type.as_deref()
+            .and_then(PayloadFormat::from_content_type)
+            .unwrap_or_else(|| PayloadFormat::from_topic(&self.topic))
+    }
+
+    /// Decode the payload in whichever [`format`](Self::format) it was sent
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T> {
+        self.format().decode(&self.payload)
+    }
+
+    /// The payload as JSON text, converting binary payloads, for handlers
+    /// that parse JSON themselves
+    pub fn json_text(&self) -> Result<Cow<'_, str>> {
+        match self.format() {
+            PayloadFormat::Json => Ok(Cow::Borrowed(std::str::from_utf8(&self.payload)
+                .map_err(|e| SwarmError::Mqtt(format!("Payload is not UTF-8: {}", e)))?)),
+            format => {
+                let value: serde_json::Value = format.decode(&self.payload)?;
+                Ok(Cow::Owned(value.to_string()))
+            }
+        }
+    }
+}
+
+pub(super) struct Subscription {
+    filter: String,
+    qos: QoS,
+    sender: mpsc::Sender<MqttMessage>,
+}
+
+/// Whether `topic` matches the subscription `filter`, including `+` and `#` wildcards
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut filter_levels = filter.split('/');
+    let mut topic_levels = topic.split('/');
+    loop {
+        match (filter_levels.next(), topic_levels.next()) {
+            (Some("#"), _) => return true,
+            (Some("+"), Some(_)) => {}
+            (Some(f), Some(t)) if f == t => {}
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+/// One broker connection shared by everything in a process. Cloning is cheap;
+/// all clones publish through, and receive from, the same connection.
+///
+/// Topics passed in and handed out are relative to the configured
+/// [`topic_prefix`](MqttConfig::topic_prefix); the service adds and strips it.
+#[derive(Clone)]
+pub struct MqttService {
+    transport: Transport,
+    subscriptions: Arc<Mutex<Vec<Subscription>>>,
+    connected: watch::Receiver<bool>,
+    config: Arc<MqttConfig>,
+    /// Last metrics report per topic, when only deltas are sent
+    metrics_history: Arc<std::sync::Mutex<MetricsHistory>>,
+}
+
+/// Where publishes go and subscriptions are registered
+#[derive(Clone)]
+enum Transport {
+    Broker(Arc<AsyncClient>),
+    #[cfg(any(test, feature = "test-support"))]
+    Memory(super::memory::MemoryBroker),
+}
+
+impl MqttService {
+    /// Start connecting in the background. Publishes and subscriptions made
+    /// before the connection is up are queued.
+    pub fn connect(config: MqttConfig) -> Self {
+        let (client, eventloop) = AsyncClient::new(config.options(), config.request_capacity);
+        let client = Arc::new(client);
+        let (connected_tx, connected) = watch::channel(false);
+        let service = Self {
+            transport: Transport::Broker(client.clone()),
+            subscriptions: Arc::new(Mutex::new(Vec::new())),
+            connected,
+            config: Arc::new(config),
+            metrics_history: Arc::default(),
+        };
+
+        tracing::info!("Connecting to MQTT broker at {}:{} as {}", service.config.host, service.config.port, service.config.client_id);
+        tokio::spawn(service.clone().run(client, eventloop, connected_tx));
+        service
+    }
+
+    /// A service attached to an in-process broker, connected from the start
+    #[cfg(any(test, feature = "test-support"))]
+    pub(super) fn in_memory(config: MqttConfig, broker: super::memory::MemoryBroker) -> Self {
+        let (_, connected) = watch::channel(true);
+        let service = Self {
+            transport: Transport::Memory(broker.clone()),
+            subscriptions: Arc::new(Mutex::new(Vec::new())),
+            connected,
+            config: Arc::new(config),
+            metrics_history: Arc::default(),
+        };
+        broker.attach(service.config.clone(), Arc::downgrade(&service.subscriptions));
+        service
+    }
+
+    pub fn config(&self) -> &MqttConfig {
+        &self.config
+    }
+
+    /// `topic` as it appears on the broker
+    pub fn topic(&self, topic: &str) -> String {
+        self.config.topic(topic)
+    }
+
+    /// Log where each of `topics` resolves to on the broker
+    pub fn log_topic_map(&self, topics: &[&str]) {
+        let prefix = if self.config.topic_prefix.is_empty() { "(none)" } else { self.config.topic_prefix.as_str() };
+        tracing::info!("MQTT topic prefix: {}", prefix);
+        for topic in topics {
+            tracing::info!("  {} -> {}", topic, self.topic(topic));
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        *self.connected.borrow()
+    }
+
+    /// Wait until the broker has acknowledged the connection
+    pub async fn wait_connected(&self, timeout: Duration) -> Result<()> {
+        let mut connected = self.connected.clone();
+        tokio::time::timeout(timeout, connected.wait_for(|up| *up))
+            .await
+            .map_err(|_| SwarmError::Mqtt(format!("Timed out connecting to MQTT broker at {}:{}", self.config.host, self.config.port)))?
+            .map_err(|_| SwarmError::Mqtt("MQTT event loop stopped".to_string()))?;
+        Ok(())
+    }
+
+    /// Receive publishes matching `filter`. The subscription is restored after
+    /// every reconnect and ends when the receiver is dropped. When the
+    /// receiver falls `subscriber_capacity` messages behind, the event loop
+    /// waits for it rather than dropping messages.
+    pub async fn subscribe(&self, filter: &str, qos: QoS) -> Result<mpsc::Receiver<MqttMessage>> {
+        let (sender, receiver) = mpsc::channel(self.config.subscriber_capacity);
+        self.subscriptions.lock().await.push(Subscription { filter: filter.to_string(), qos, sender });
+        match &self.transport {
+            Transport::Broker(client) => client.subscribe(self.topic(filter), qos).await.map_err(client_error)?,
+            #[cfg(any(test, feature = "test-support"))]
+            Transport::Memory(_) => {}
+        }
+        tracing::info!("Subscribed to topic: {}", self.topic(filter));
+        Ok(receiver)
+    }
+
+    /// Like [`subscribe`](Self::subscribe), with publishes matching any of
+    /// `filters` arriving on the one receiver
+    pub async fn subscribe_all(&self, filters: &[String], qos: QoS) -> Result<mpsc::Receiver<MqttMessage>> {
+        let (sender, receiver) = mpsc::channel(self.config.subscriber_capacity);
+        for filter in filters {
+            self.subscriptions.lock().await.push(Subscription { filter: filter.clone(), qos, sender: sender.clone() });
+            match &self.transport {
+                Transport::Broker(client) => client.subscribe(self.topic(filter), qos).await.map_err(client_error)?,
+                #[cfg(any(test, feature = "test-support"))]
+                Transport::Memory(_) => {}
+            }
+            tracing::info!("Subscribed to topic: {}", self.topic(filter));
+        }
+        Ok(receiver)
+    }
+
+    /// Drop every subscription on `filter`
+    pub async fn unsubscribe(&self, filter: &str) -> Result<()> {
+        self.subscriptions.lock().await.retain(|s| s.filter != filter);
+        match &self.transport {
+            Transport::Broker(client) => client.unsubscribe(self.topic(filter)).await.map_err(client_error)?,
+            #[cfg(any(test, feature = "test-support"))]
+            Transport::Memory(_) => {}
+        }
+        Ok(())
+    }
+
+    pub async fn publish(&self, topic: impl Into<String>, qos: QoS, retain: bool, payload: impl Into<Vec<u8>>) -> Result<()> {
+        self.send(self.topic(&topic.into()), qos, retain, payload.into(), None).await
+    }
+
+    /// Publish a request whose reply should come back on `response_topic`,
+    /// tagged with `correlation_data`
+    pub async fn publish_request(
+        &self,
+        topic: impl Into<String>,
+        payload: impl Into<Vec<u8>>,
+        response_topic: &str,
+        correlation_data: impl Into<Vec<u8>>,
+    ) -> Result<()> {
+        let properties = PublishProperties {
+            response_topic: Some(self.topic(response_topic)),
+            correlation_data: Some(correlation_data.into().into()),
+            ..Default::default()
+        };
+        self.send(self.topic(&topic.into()), self.config.task_qos, false, payload.into(), Some(properties)).await
+    }
+
+    /// Reply to `request`: on the response topic it named, echoing its
+    /// correlation data, or on `fallback_topic` for requesters that rely on
+    /// topic conventions
+    pub async fn reply(&self, request: &MqttMessage, fallback_topic: impl Into<String>, payload: impl Into<Vec<u8>>) -> Result<()> {
+        let topic = self.reply_topic(request, &fallback_topic.into());
+        let properties = PublishProperties {
+            correlation_data: request.correlation_data.clone(),
+            ..Default::default()
+        };
+        self.send(topic, self.config.task_qos, false, payload.into(), Some(properties)).await
+    }
+
+    /// Publish `value` as JSON with exactly-once delivery
+    pub async fn publish_json<T: Serialize + ?Sized>(&self, topic: impl Into<String>, value: &T) -> Result<()> {
+        self.publish(topic, self.config.task_qos, false, serde_json::to_vec(value)?).await
+    }
+
+    /// Publish a metrics report as the config's [`MetricsPublishing`] says:
+    /// at its QoS, cut to a delta and gzipped if asked. Subscribers read
+    /// either form with [`metrics::decode`] and [`metrics::merge`].
+    pub async fn publish_metrics(&self, topic: impl Into<String>, report: &serde_json::Value) -> Result<()> {
+        let topic = topic.into();
+        let publishing = &self.config.metrics;
+        let payload = if publishing.delta {
+            let report = self.metrics_history.lock().unwrap().next(&topic, report, publishing.full_every);
+            serde_json::to_vec(&*report)?
+        } else {
+            serde_json::to_vec(report)?
+        };
+
+        if !publishing.compress {
+            return self.send(self.topic(&topic), publishing.qos, false, payload, None).await;
+        }
+        let properties = PublishProperties {
+            content_type: Some("application/json".to_string()),
+            user_properties: vec![(metrics::CONTENT_ENCODING.to_string(), metrics::GZIP.to_string())],
+            ..Default::default()
+        };
+        self.send(self.topic(&topic), publishing.qos, false, metrics::gzip(&payload)?, Some(properties)).await
+    }
+
+    /// Publish on `topic`, a full broker topic
+    async fn send(&self, topic: String, qos: QoS, retain: bool, payload: Vec<u8>, properties: Option<PublishProperties>) -> Result<()> {
+        match &self.transport {
+            Transport::Broker(client) => match properties {
+                Some(properties) => client.publish_with_properties(topic, qos, retain, payload, properties).await.map_err(client_error)?,
+                None => client.publish(topic, qos, retain, payload).await.map_err(client_error)?,
+            },
+            #[cfg(any(test, feature = "test-support"))]
+            Transport::Memory(broker) => {
+                let properties = properties.unwrap_or_default();
+                broker.publish(MqttMessage {
+                    topic: topic.into(),
+                    payload: payload.into(),
+                    qos,
+                    retain,
+                    response_topic: properties.response_topic,
+                    correlation_data: properties.correlation_data,
+                    content_type: properties.content_type,
+                }).await;
+            }
+        }
+        Ok(())
+    }
+
+    fn reply_topic(&self, request: &MqttMessage, fallback_topic: &str) -> String {
+        match &request.response_topic {
+            // Already a full broker topic; the requester chose it
+            Some(response_topic) => response_topic.clone(),
+            None => self.topic(fallback_topic),
+        }
+    }
+
+    /// Mark this instance offline ahead of a clean disconnect, which the
+    /// broker does not announce with the will
+    pub async fn publish_offline(&self) -> Result<()> {
+        if let Some(presence) = &self.config.presence {
+            self.publish(presence.topic.clone(), QoS::AtLeastOnce, true, presence.payload("offline")).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn disconnect(&self) -> Result<()> {
+        match &self.transport {
+            Transport::Broker(client) => client.disconnect().await.map_err(client_error)?,
+            #[cfg(any(test, feature = "test-support"))]
+            Transport::Memory(_) => {}
+        }
+        Ok(())
+    }
+
+    async fn run(self, client: Arc<AsyncClient>, mut eventloop: EventLoop, connected: watch::Sender<bool>) {
+        let mut reconnect_delay = Duration::from_secs(1);
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                    tracing::info!("Connected to MQTT broker at {}:{}", self.config.host, self.config.port);
+                    reconnect_delay = Duration::from_secs(1);
+                    let _ = connected.send(true);
+                    self.resubscribe(&client).await;
+                    self.publish_online(&client);
+                }
+                Ok(Event::Incoming(Packet::Publish(publish))) => {
+                    let full_topic = String::from_utf8_lossy(&publish.topic);
+                    let Some(topic) = self.config.strip_prefix(&full_topic) else {
+                        tracing::debug!("Ignoring message outside the topic namespace: {}", full_topic);
+                        continue;
+                    };
+                    let properties = publish.properties.unwrap_or_default();
+                    let message = MqttMessage {
+                        topic: topic.into(),
+                        payload: publish.payload,
+                        qos: publish.qos,
+                        retain: publish.retain,
+                        response_topic: properties.response_topic,
+                        correlation_data: properties.correlation_data,
+                        content_type: properties.content_type,
+                    };
+                    self.dispatch(message).await;
+                }
+                Ok(Event::Outgoing(rumqttc::Outgoing::Disconnect)) => {
+                    tracing::info!("Disconnected from MQTT broker");
+                    let _ = connected.send(false);
+                    return;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    if *connected.borrow() {
+                        tracing::warn!("MQTT connection lost: {}", e);
+                    } else {
+                        tracing::debug!("MQTT connection attempt failed: {}", e);
+                    }
+                    let _ = connected.send(false);
+                    tokio::time::sleep(reconnect_delay).await;
+                    reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                }
+            }
+        }
+    }
+
+    /// Clean sessions lose their subscriptions on reconnect, so restore them
+    async fn resubscribe(&self, client: &AsyncClient) {
+        let mut subscriptions = self.subscriptions.lock().await;
+        subscriptions.retain(|s| !s.sender.is_closed());
+        let mut filters: Vec<(String, QoS)> = subscriptions.iter().map(|s| (s.filter.clone(), s.qos)).collect();
+        filters.sort_by(|a, b| a.0.cmp(&b.0));
+        filters.dedup_by(|a, b| a.0 == b.0);
+        for (filter, qos) in filters {
+            // try_subscribe: awaiting here would block the loop that drains the request queue
+            if let Err(e) = client.try_subscribe(self.topic(&filter), qos) {
+                tracing::error!("Failed to restore subscription to {}: {}", filter, e);
+            }
+        }
+    }
+
+    /// Replaces the will's retained "offline" left by a previous connection
+    fn publish_online(&self, client: &AsyncClient) {
+        if let Some(presence) = &self.config.presence {
+            let topic = self.topic(&presence.topic);
+            if let Err(e) = client.try_publish(topic.clone(), QoS::AtLeastOnce, true, presence.payload("online")) {
+                tracing::error!("Failed to publish online status to {}: {}", topic, e);
+            }
+        }
+    }
+
+    async fn dispatch(&self, message: MqttMessage) {
+        dispatch_to(&self.subscriptions, message).await
+    }
+}
+
+/// Hand `message` to every live subscription whose filter matches its topic
+pub(super) async fn dispatch_to(subscriptions: &Mutex<Vec<Subscription>>, message: MqttMessage) {
+    let senders: Vec<_> = {
+        let mut subscriptions = subscriptions.lock().await;
+        subscriptions.retain(|s| !s.sender.is_closed());
+        subscriptions.iter()
+            .filter(|s| topic_matches(&s.filter, &message.topic))
+            .map(|s| s.sender.clone())
+            .collect()
+    };
+    if senders.is_empty() {
+        tracing::debug!("No subscriber for message on {}", message.topic);
+    }
+    for sender in senders {
+        // A full channel holds up the event loop, pushing back on the broker
+        let _ = sender.send(message.clone()).await;
+    }
+}
+
+fn client_error(err: rumqttc::v5::ClientError) -> SwarmError {
+    SwarmError::Mqtt(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(topic: &str, payload: &[u8]) -> MqttMessage {
+        MqttMessage {
+            topic: topic.into(),
+            payload: Bytes::copy_from_slice(payload),
+            qos: QoS::AtLeastOnce,
+            retain: false,
+            response_topic: None,
+            correlation_data: None,
+            content_type: None,
+        }
+    }
+
+    #[test]
+    fn test_topic_matches() {
+        assert!(topic_matches("agent/+/todo/process", "agent/git/todo/process"));
+        assert!(!topic_matches("agent/+/todo/process", "agent/git/todo/response"));
+        assert!(!topic_matches("agent/+/todo/process", "agent/git/todo/process/extra"));
+        assert!(topic_matches("mcp/+", "mcp/user"));
+        assert!(!topic_matches("mcp/+", "mcp"));
+        assert!(topic_matches("response/#", "response/project/classify/123"));
+        assert!(topic_matches("todo_worker/control", "todo_worker/control"));
+    }
+
+    #[test]
+    fn test_config_options() {
+        let config = MqttConfig::new("worker-1", "broker", 1884)
+            .with_keep_alive(Duration::from_secs(30))
+            .with_last_will("todo_worker/status", "offline", true);
+        let options = config.options();
+        assert_eq!(options.client_id(), "worker-1");
+        assert_eq!(options.broker_address(), ("broker".to_string(), 1884));
+        assert_eq!(options.keep_alive(), Duration::from_secs(30));
+        assert_eq!(&options.last_will().unwrap().topic[..], b"todo_worker/status");
+    }
+
+    #[test]
+    fn test_presence() {
+        let config = MqttConfig::new("worker-1", "broker", 1883)
+            .with_topic_prefix("prod")
+            .with_presence("todo_worker/status", "worker-1");
+        let options = config.options();
+        let will = options.last_will().unwrap();
+        assert_eq!(&will.topic[..], b"prod/todo_worker/status");
+        assert!(will.retain);
+
+        let will: serde_json::Value = serde_json::from_slice(&will.message).unwrap();
+        assert_eq!(will["status"], "offline");
+        assert_eq!(will["instance_id"], "worker-1");
+        assert_eq!(will["version"], env!("CARGO_PKG_VERSION"));
+
+        let online: serde_json::Value = serde_json::from_slice(&config.presence.unwrap().payload("online")).unwrap();
+        assert_eq!(online["status"], "online");
+        assert!(online["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_topic_prefix() {
+        let config = MqttConfig::new("worker-1", "broker", 1883).with_topic_prefix("staging/");
+        assert_eq!(config.topic("agent/+/todo/process"), "staging/agent/+/todo/process");
+        assert_eq!(config.strip_prefix("staging/agent/git/todo/process"), Some("agent/git/todo/process"));
+        assert_eq!(config.strip_prefix("stagingx/agent"), None);
+        assert_eq!(config.strip_prefix("prod/agent/git/todo/process"), None);
+
+        let config = config.with_last_will("todo_worker/status", "offline", true);
+        assert_eq!(&config.options().last_will().unwrap().topic[..], b"staging/todo_worker/status");
+
+        let unprefixed = MqttConfig::new("worker-1", "broker", 1883);
+        assert_eq!(unprefixed.topic("metrics/todo_worker"), "metrics/todo_worker");
+        assert_eq!(unprefixed.strip_prefix("metrics/todo_worker"), Some("metrics/todo_worker"));
+    }
+
+    #[tokio::test]
+    async fn test_subscriptions_are_dispatched_by_filter() -> Result<()> {
+        // Never connects; dispatch is exercised directly
+        let service = MqttService::connect(MqttConfig::new("test", "127.0.0.1", 9));
+        let mut tasks = service.subscribe("agent/+/todo/process", QoS::AtLeastOnce).await?;
+        let mut control = service.subscribe("todo_worker/control", QoS::AtLeastOnce).await?;
+
+        service.dispatch(message("agent/git/todo/process", br#"{"description": "commit"}"#)).await;
+
+        let task = tasks.recv().await.unwrap();
+        assert_eq!(task.json::<serde_json::Value>()?["description"], "commit");
+        assert!(control.try_recv().is_err());
+
+        // Dropped receivers are pruned
+        drop(control);
+        service.dispatch(message("todo_worker/control", b"")).await;
+        assert_eq!(service.subscriptions.lock().await.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_reply_topic() {
+        let service = MqttService::connect(MqttConfig::new("test", "127.0.0.1", 9).with_topic_prefix("staging"));
+
+        // Convention-based requesters get the prefixed fallback
+        let request = message("agent/git/todo/process", b"{}");
+        assert_eq!(service.reply_topic(&request, "agent/git/todo/response"), "staging/agent/git/todo/response");
+
+        // A v5 response topic is used verbatim
+        let request = MqttMessage {
+            response_topic: Some("nodered/replies/42".to_string()),
+            correlation_data: Some(Bytes::from_static(b"42")),
+            ..request
+        };
+        assert_eq!(service.reply_topic(&request, "agent/git/todo/response"), "nodered/replies/42");
+    }
+
+    #[tokio::test]
+    async fn test_metrics_go_out_compressed_deltas_at_their_own_qos() -> Result<()> {
+        let broker = crate::mqtt::MemoryBroker::new();
+        let publishing = MetricsPublishing { qos: QoS::AtMostOnce, compress: true, delta: true, full_every: 10 };
+        let worker = broker.connect(MqttConfig::new("worker", "memory", 0).with_metrics_publishing(publishing));
+        let dashboard = broker.connect(MqttConfig::new("dashboard", "memory", 0));
+        let mut reports = dashboard.subscribe("metrics/#", QoS::AtLeastOnce).await?;
+
+        let first = json!({ "processed": 1, "failed": 0 });
+        worker.publish_metrics("metrics/todo_worker", &first).await?;
+        worker.publish_metrics("metrics/todo_worker", &json!({ "processed": 2, "failed": 0 })).await?;
+
+        let full = reports.recv().await.unwrap();
+        assert_eq!(full.qos, QoS::AtMostOnce);
+        assert_eq!(metrics::decode(&full.payload)?, first);
+        let delta = metrics::decode(&reports.recv().await.unwrap().payload)?;
+        assert_eq!(delta, json!({ "processed": 2, "_delta": true }));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_binary_task_payloads_by_topic_suffix() -> Result<()> {
+        let broker = crate::mqtt::MemoryBroker::new();
+        let worker = broker.connect(MqttConfig::new("worker", "memory", 0));
+        let phone = broker.connect(MqttConfig::new("phone", "memory", 0));
+        let mut tasks = worker.subscribe_all(&PayloadFormat::topic_filters("mcp/+"), QoS::AtLeastOnce).await?;
+
+        let request = json!({ "description": "Water the plants", "priority": "high" });
+        phone.publish("mcp/tasker/msgpack", QoS::AtLeastOnce, false, PayloadFormat::MessagePack.encode(&request)?).await?;
+        phone.publish("mcp/tasker", QoS::AtLeastOnce, false, request.to_string()).await?;
+
+        let binary = tasks.recv().await.unwrap();
+        assert_eq!(binary.format(), PayloadFormat::MessagePack);
+        assert_eq!(binary.json::<serde_json::Value>()?, request);
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&binary.json_text()?)?, request);
+        let text = tasks.recv().await.unwrap();
+        assert_eq!(text.format(), PayloadFormat::Json);
+        assert_eq!(text.json::<serde_json::Value>()?, request);
+        Ok(())
+    }
+}
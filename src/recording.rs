@@ -0,0 +1,245 @@
+//! Capture and replay of live traffic. With `CAPTURE_FILE` set, the messages
+//! and tasks agents handle and the AI responses they get are appended to that
+//! file as JSON lines. The `replay` binary re-runs a capture against the
+//! current code, answering AI calls from the recording instead of a model.
+
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::types::{Message, TodoTask};
+
+static CAPTURE: OnceLock<Option<Arc<Capture>>> = OnceLock::new();
+static REPLAY: OnceLock<Arc<Replay>> = OnceLock::new();
+
+/// One line of a capture file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Recording {
+    /// A message an agent answered, with its reply or error
+    Message {
+        agent: String,
+        message: Message,
+        reply: Option<String>,
+        error: Option<String>,
+    },
+    /// A task an agent processed
+    Task {
+        agent: String,
+        task: TodoTask,
+        reply: Option<String>,
+        error: Option<String>,
+    },
+    /// What the model answered to the prompt hashed as `key`
+    Ai { key: String, response: String },
+}
+
+impl Recording {
+    /// A message `agent` answered with `result`
+    pub fn message(agent: impl Into<String>, message: Message, result: &Result<Message>) -> Self {
+        let (reply, error) = split(result);
+        Self::Message { agent: agent.into(), message, reply, error }
+    }
+
+    /// A task `agent` processed with `result`
+    pub fn task(agent: impl Into<String>, task: TodoTask, result: &Result<Message>) -> Self {
+        let (reply, error) = split(result);
+        Self::Task { agent: agent.into(), task, reply, error }
+    }
+
+    /// The reply or error recorded for a message or task
+    pub fn outcome(&self) -> Option<std::result::Result<&str, &str>> {
+        match self {
+            Self::Message { reply, error, .. } | Self::Task { reply, error, .. } => match (reply, error) {
+                (_, Some(error)) => Some(Err(error.as_str())),
+                (Some(reply), None) => Some(Ok(reply.as_str())),
+                (None, None) => None,
+            },
+            Self::Ai { .. } => None,
+        }
+    }
+}
+
+fn split(result: &Result<Message>) -> (Option<String>, Option<String>) {
+    match result {
+        Ok(reply) => (Some(reply.content.clone()), None),
+        Err(e) => (None, Some(e.to_string())),
+    }
+}
+
+/// Hex SHA-256 identifying a chat request, so a replay can find its response
+pub fn prompt_key(system_prompt: &str, messages: &[HashMap<String, String>]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(system_prompt.as_bytes());
+    for message in messages {
+        let mut fields: Vec<_> = message.iter().collect();
+        fields.sort();
+        for (name, value) in fields {
+            hasher.update([0u8]);
+            hasher.update(name.as_bytes());
+            hasher.update([0u8]);
+            hasher.update(value.as_bytes());
+        }
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Every recording in the capture file at `path`, in order
+pub fn load(path: impl AsRef<Path>) -> Result<Vec<Recording>> {
+    let path = path.as_ref();
+    let file = File::open(path).map_err(|e| anyhow!("Failed to open capture {}: {}", path.display(), e))?;
+    let mut recordings = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let recording = serde_json::from_str(&line)
+            .map_err(|e| anyhow!("{}:{}: {}", path.display(), number + 1, e))?;
+        recordings.push(recording);
+    }
+    Ok(recordings)
+}
+
+/// Appends recordings to a capture file. Writes are best-effort: a failed one
+/// is logged and never fails the work being recorded.
+#[derive(Debug)]
+pub struct Capture {
+    file: Mutex<File>,
+}
+
+impl Capture {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    /// Captures to `CAPTURE_FILE`, or `None` when it is unset
+    pub fn from_env() -> Option<Self> {
+        let path = env::var("CAPTURE_FILE").ok().filter(|path| !path.is_empty())?;
+        match Self::open(&path) {
+            Ok(capture) => {
+                tracing::info!("Capturing traffic to {}", path);
+                Some(capture)
+            }
+            Err(e) => {
+                tracing::warn!("Failed to open capture file {}: {}", path, e);
+                None
+            }
+        }
+    }
+
+    /// The capture configured from the environment, shared process-wide
+    pub fn shared() -> Option<Arc<Self>> {
+        CAPTURE.get_or_init(|| Self::from_env().map(Arc::new)).clone()
+    }
+
+    pub fn record(&self, recording: &Recording) {
+        let written = serde_json::to_string(recording)
+            .map_err(anyhow::Error::from)
+            .and_then(|line| Ok(writeln!(self.file.lock().unwrap(), "{}", line)?));
+        if let Err(e) = written {
+            tracing::warn!("Failed to capture recording: {}", e);
+        }
+    }
+}
+
+/// Recorded AI responses, handed out in the order they were captured
+#[derive(Debug, Default)]
+pub struct Replay {
+    responses: Mutex<HashMap<String, VecDeque<String>>>,
+}
+
+impl Replay {
+    pub fn new(recordings: &[Recording]) -> Self {
+        let mut responses: HashMap<String, VecDeque<String>> = HashMap::new();
+        for recording in recordings {
+            if let Recording::Ai { key, response } = recording {
+                responses.entry(key.clone()).or_default().push_back(response.clone());
+            }
+        }
+        Self { responses: Mutex::new(responses) }
+    }
+
+    /// Answer every AI call in this process from `replay`. Later calls keep the first.
+    pub fn init_shared(replay: Self) -> Arc<Self> {
+        REPLAY.get_or_init(|| Arc::new(replay)).clone()
+    }
+
+    /// The replay answering AI calls, if one was set up
+    pub fn shared() -> Option<Arc<Self>> {
+        REPLAY.get().cloned()
+    }
+
+    /// The next recorded response to the prompt hashed as `key`. A prompt asked
+    /// more often than it was recorded gets its last response again.
+    pub fn answer(&self, key: &str) -> Result<String> {
+        let mut responses = self.responses.lock().unwrap();
+        let queue = responses.get_mut(key)
+            .ok_or_else(|| anyhow!("No recorded AI response for prompt {}", key))?;
+        match queue.len() {
+            0 => Err(anyhow!("No recorded AI response for prompt {}", key)),
+            1 => Ok(queue[0].clone()),
+            _ => Ok(queue.pop_front().expect("queue is not empty")),
+        }
+    }
+}
+
+/// The recorded answer to a chat request, when AI calls are being replayed
+pub(crate) fn replayed_response(system_prompt: &str, messages: &[HashMap<String, String>]) -> Option<Result<String>> {
+    let replay = Replay::shared()?;
+    Some(replay.answer(&prompt_key(system_prompt, messages)))
+}
+
+/// Capture a model's answer to a chat request, when capturing
+pub(crate) fn capture_response(system_prompt: &str, messages: &[HashMap<String, String>], response: &str) {
+    if let Some(capture) = Capture::shared() {
+        capture.record(&Recording::Ai { key: prompt_key(system_prompt, messages), response: response.to_string() });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(content: &str) -> Vec<HashMap<String, String>> {
+        vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), content.to_string()),
+        ])]
+    }
+
+    #[test]
+    fn test_prompt_key() {
+        assert_eq!(prompt_key("classify", &user("fix login")), prompt_key("classify", &user("fix login")));
+        assert_ne!(prompt_key("classify", &user("fix login")), prompt_key("classify", &user("fix logout")));
+        assert_ne!(prompt_key("classify", &user("fix login")), prompt_key("enhance", &user("fix login")));
+    }
+
+    #[test]
+    fn test_capture_round_trip_and_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture.jsonl");
+        let key = prompt_key("classify", &user("fix login"));
+
+        let capture = Capture::open(&path).unwrap();
+        capture.record(&Recording::Ai { key: key.clone(), response: "high".to_string() });
+        capture.record(&Recording::Ai { key: key.clone(), response: "medium".to_string() });
+        capture.record(&Recording::message("greeter", Message::new("hello".to_string()), &Ok(Message::new("Hello!".to_string()))));
+
+        let recordings = load(&path).unwrap();
+        assert_eq!(recordings.len(), 3);
+        assert_eq!(recordings[2].outcome(), Some(Ok("Hello!")));
+
+        let replay = Replay::new(&recordings);
+        assert_eq!(replay.answer(&key).unwrap(), "high");
+        assert_eq!(replay.answer(&key).unwrap(), "medium");
+        assert_eq!(replay.answer(&key).unwrap(), "medium");
+        assert!(replay.answer("unrecorded").is_err());
+    }
+}
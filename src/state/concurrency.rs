@@ -0,0 +1,215 @@
+use anyhow::Result;
+use chrono::Utc;
+use super::{PersistedState, StatePersistence};
+
+/// A conditional save found a different latest version than the caller read
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+#[error("Version conflict for agent {agent_id}: expected version {expected}, found {actual}")]
+pub struct VersionConflict {
+    pub agent_id: String,
+    /// Version the caller based its state on; 0 when nothing was saved
+    pub expected: i32,
+    pub actual: i32,
+}
+
+impl VersionConflict {
+    /// The conflict behind `error`, if a conditional save lost a race
+    pub fn from_error(error: &anyhow::Error) -> Option<&Self> {
+        error.downcast_ref::<Self>()
+    }
+}
+
+/// Fails with a [`VersionConflict`] unless the agent's latest saved version is `expected`
+pub async fn check_version<P: StatePersistence + Sync + ?Sized>(store: &P, agent_id: &str, expected: i32) -> Result<()> {
+    let actual = store.load_state(agent_id).await?.map_or(0, |state| state.version);
+    if actual != expected {
+        return Err(VersionConflict { agent_id: agent_id.to_string(), expected, actual }.into());
+    }
+    Ok(())
+}
+
+/// Turns a failed insert into a [`VersionConflict`] when another writer's
+/// version is now the latest, the way a unique index reports a lost race
+pub async fn conflict_or<P: StatePersistence + Sync + ?Sized>(
+    store: &P,
+    agent_id: &str,
+    expected: i32,
+    error: anyhow::Error,
+) -> anyhow::Error {
+    match check_version(store, agent_id, expected).await {
+        Err(conflict) if VersionConflict::from_error(&conflict).is_some() => conflict,
+        _ => error,
+    }
+}
+
+/// Decides what to do when a conditional save loses a race. Resolvers see only
+/// the attempted and current states, so every worker resolves a conflict the
+/// same way.
+pub trait ConflictResolver: Send + Sync {
+    /// The state to save on top of `current` instead, or `None` to give up
+    fn resolve(&self, attempted: &PersistedState, current: &PersistedState) -> Option<PersistedState>;
+}
+
+impl<F> ConflictResolver for F
+where
+    F: Fn(&PersistedState, &PersistedState) -> Option<PersistedState> + Send + Sync,
+{
+    fn resolve(&self, attempted: &PersistedState, current: &PersistedState) -> Option<PersistedState> {
+        self(attempted, current)
+    }
+}
+
+/// Fails with the [`VersionConflict`]
+pub struct FailOnConflict;
+
+impl ConflictResolver for FailOnConflict {
+    fn resolve(&self, _attempted: &PersistedState, _current: &PersistedState) -> Option<PersistedState> {
+        None
+    }
+}
+
+/// Saves the attempted state over whatever won the race
+pub struct LastWriterWins;
+
+impl ConflictResolver for LastWriterWins {
+    fn resolve(&self, attempted: &PersistedState, _current: &PersistedState) -> Option<PersistedState> {
+        Some(attempted.clone())
+    }
+}
+
+/// Keeps the current state and conversation, adding metadata keys the current
+/// state doesn't have and the attempted state's data when the current has none
+pub struct MergeMetadata;
+
+impl ConflictResolver for MergeMetadata {
+    fn resolve(&self, attempted: &PersistedState, current: &PersistedState) -> Option<PersistedState> {
+        let mut merged = current.clone();
+        for (key, value) in &attempted.metadata {
+            merged.metadata.entry(key.clone()).or_insert_with(|| value.clone());
+        }
+        if merged.state_data.is_none() {
+            merged.state_data = attempted.state_data.clone();
+        }
+        Some(merged)
+    }
+}
+
+/// Saves `state` on top of `expected_version`, asking `resolver` for a
+/// replacement each time another writer got there first. Gives up with the
+/// last [`VersionConflict`] after `max_attempts` saves.
+pub async fn save_with_resolver<P: StatePersistence + Sync + ?Sized>(
+    store: &P,
+    mut state: PersistedState,
+    mut expected_version: i32,
+    resolver: &dyn ConflictResolver,
+    max_attempts: usize,
+) -> Result<PersistedState> {
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let error = match store.save_state_if(state.clone(), expected_version).await {
+            Ok(saved) => return Ok(saved),
+            Err(error) => error,
+        };
+        let Some(conflict) = VersionConflict::from_error(&error).cloned() else {
+            return Err(error);
+        };
+        if attempts >= max_attempts {
+            return Err(error);
+        }
+        let Some(current) = store.load_state(&state.agent_id).await? else {
+            return Err(error);
+        };
+        match resolver.resolve(&state, &current) {
+            Some(resolved) => {
+                tracing::debug!("Retrying save for {} on version {}", state.agent_id, conflict.actual);
+                state = PersistedState { updated_at: Utc::now(), ..resolved };
+                expected_version = current.version;
+            }
+            None => return Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use async_trait::async_trait;
+    use tokio::sync::Mutex;
+    use crate::state::StateTransition;
+
+    /// Saves in memory, letting `concurrent_saves` writers sneak in first
+    struct RacyStore {
+        states: Mutex<Vec<PersistedState>>,
+        concurrent_saves: Mutex<usize>,
+    }
+
+    #[async_trait]
+    impl StatePersistence for RacyStore {
+        async fn save_state(&self, state: PersistedState) -> Result<()> {
+            let mut states = self.states.lock().await;
+            let mut racing = self.concurrent_saves.lock().await;
+            if *racing > 0 {
+                *racing -= 1;
+                let mut other = state.clone();
+                other.metadata = HashMap::from([("writer".to_string(), serde_json::json!("other"))]);
+                states.push(other);
+            }
+            if states.iter().any(|s| s.version == state.version) {
+                return Err(anyhow::anyhow!("duplicate version {}", state.version));
+            }
+            states.push(state);
+            Ok(())
+        }
+
+        async fn load_state(&self, _agent_id: &str) -> Result<Option<PersistedState>> {
+            Ok(self.states.lock().await.iter().max_by_key(|s| s.version).cloned())
+        }
+
+        async fn record_transition(&self, _transition: StateTransition) -> Result<()> {
+            Ok(())
+        }
+
+        async fn get_transitions(&self, _agent_id: &str) -> Result<Vec<StateTransition>> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn state(metadata: &[(&str, &str)]) -> PersistedState {
+        PersistedState {
+            agent_id: "greeter".to_string(),
+            state_name: "idle".to_string(),
+            state_data: None,
+            conversation_context: vec![],
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            version: 0,
+            metadata: metadata.iter().map(|(k, v)| (k.to_string(), serde_json::json!(v))).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_conflicts_are_typed_and_resolved() -> Result<()> {
+        let store = RacyStore { states: Mutex::new(Vec::new()), concurrent_saves: Mutex::new(1) };
+
+        let error = store.save_state_if(state(&[]), 0).await.unwrap_err();
+        assert_eq!(
+            VersionConflict::from_error(&error),
+            Some(&VersionConflict { agent_id: "greeter".to_string(), expected: 0, actual: 1 })
+        );
+        let stale = store.save_state_if(state(&[]), 0).await.unwrap_err();
+        assert_eq!(VersionConflict::from_error(&stale).map(|c| c.actual), Some(1));
+
+        *store.concurrent_saves.lock().await = 1;
+        let saved = save_with_resolver(&store, state(&[("mine", "yes")]), 1, &MergeMetadata, 3).await?;
+        assert_eq!(saved.version, 3);
+        assert_eq!(saved.metadata["writer"], "other");
+        assert_eq!(saved.metadata["mine"], "yes");
+
+        *store.concurrent_saves.lock().await = 1;
+        let error = save_with_resolver(&store, state(&[]), 3, &FailOnConflict, 3).await.unwrap_err();
+        assert!(VersionConflict::from_error(&error).is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+use std::path::Path;
+use std::sync::Arc;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use anyhow::{Result, anyhow};
+use serde::{de::DeserializeOwned, Serialize};
+use super::{
+    apply_transitions, BasicStateValidator, PersistedState, StateCompaction, StatePersistence, StateRecovery,
+    StateTransition, StateValidator,
+};
+
+/// Stores agent state in an embedded sled database, for running without MongoDB.
+///
+/// Keys are `<agent_id>\0` followed by big-endian sort fields, so a prefix scan
+/// returns an agent's states by version, transitions by time and checkpoints by
+/// version then creation order.
+pub struct SledStateStore {
+    db: sled::Db,
+    states: sled::Tree,
+    transitions: sled::Tree,
+    checkpoints: sled::Tree,
+    /// Cold trees compaction moves old records to
+    states_archive: sled::Tree,
+    transitions_archive: sled::Tree,
+    validator: Option<Arc<dyn StateValidator + Send + Sync>>,
+}
+
+fn agent_prefix(agent_id: &str) -> Vec<u8> {
+    let mut key = agent_id.as_bytes().to_vec();
+    key.push(0);
+    key
+}
+
+/// Maps `i32` onto `u32` so byte order matches numeric order
+fn sortable_version(version: i32) -> [u8; 4] {
+    ((version as i64 - i32::MIN as i64) as u32).to_be_bytes()
+}
+
+fn state_key(agent_id: &str, version: i32) -> Vec<u8> {
+    let mut key = agent_prefix(agent_id);
+    key.extend_from_slice(&sortable_version(version));
+    key
+}
+
+fn transition_key(transition: &StateTransition) -> Vec<u8> {
+    let mut key = agent_prefix(&transition.agent_id);
+    // Flipping the sign bit makes byte order match time order
+    key.extend_from_slice(&((transition.timestamp.timestamp_micros() as u64) ^ (1 << 63)).to_be_bytes());
+    key.extend_from_slice(transition.id.as_bytes());
+    key
+}
+
+fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    Ok(serde_json::to_vec(value)?)
+}
+
+fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    serde_json::from_slice(bytes).map_err(|e| anyhow!("Corrupt state record: {}", e))
+}
+
+impl SledStateStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let db = sled::open(path).map_err(|e| anyhow!("Failed to open state store at {}: {}", path.display(), e))?;
+        Ok(Self {
+            states: db.open_tree("agent_states")?,
+            transitions: db.open_tree("state_transitions")?,
+            checkpoints: db.open_tree("state_checkpoints")?,
+            states_archive: db.open_tree("agent_states_archive")?,
+            transitions_archive: db.open_tree("state_transitions_archive")?,
+            db,
+            validator: None,
+        })
+    }
+
+    /// Check replayed transitions against `validator`
+    pub fn with_validator(mut self, validator: Arc<dyn StateValidator + Send + Sync>) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    fn validator(&self) -> &dyn StateValidator {
+        match &self.validator {
+            Some(validator) => validator.as_ref(),
+            None => &BasicStateValidator,
+        }
+    }
+
+    fn last_in<T: DeserializeOwned>(tree: &sled::Tree, agent_id: &str) -> Result<Option<T>> {
+        match tree.scan_prefix(agent_prefix(agent_id)).next_back() {
+            Some(entry) => Ok(Some(decode(&entry?.1)?)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[async_trait]
+impl StatePersistence for SledStateStore {
+    async fn save_state(&self, state: PersistedState) -> Result<()> {
+        // Versions are unique per agent, like the Mongo index on (agent_id, version)
+        let key = state_key(&state.agent_id, state.version);
+        self.states.compare_and_swap(key, None as Option<&[u8]>, Some(encode(&state)?))?
+            .map_err(|_| anyhow!("State version {} already saved for agent {}", state.version, state.agent_id))?;
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    async fn load_state(&self, agent_id: &str) -> Result<Option<PersistedState>> {
+        Self::last_in(&self.states, agent_id)
+    }
+
+    async fn record_transition(&self, transition: StateTransition) -> Result<()> {
+        self.transitions.insert(transition_key(&transition), encode(&transition)?)?;
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    async fn get_transitions(&self, agent_id: &str) -> Result<Vec<StateTransition>> {
+        self.transitions.scan_prefix(agent_prefix(agent_id))
+            .map(|entry| decode(&entry?.1))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl StateRecovery for SledStateStore {
+    async fn create_checkpoint(&self, state: &PersistedState) -> Result<()> {
+        let mut key = state_key(&state.agent_id, state.version);
+        key.extend_from_slice(&self.db.generate_id()?.to_be_bytes());
+        self.checkpoints.insert(key, encode(state)?)?;
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    async fn rollback_to_checkpoint(&self, agent_id: &str) -> Result<Option<PersistedState>> {
+        Self::last_in(&self.checkpoints, agent_id)
+    }
+
+    async fn replay_transitions(&self, agent_id: &str, from_version: i32) -> Result<PersistedState> {
+        // Start from the newest checkpoint at or before the version, falling back to a saved state
+        let at_or_before = |tree: &sled::Tree| -> Result<Option<PersistedState>> {
+            let mut end = state_key(agent_id, from_version);
+            end.push(0xff);
+            match tree.range(agent_prefix(agent_id)..end).next_back() {
+                Some(entry) => Ok(Some(decode(&entry?.1)?)),
+                None => Ok(None),
+            }
+        };
+        let base = match at_or_before(&self.checkpoints)? {
+            Some(checkpoint) => checkpoint,
+            None => at_or_before(&self.states)?
+                .ok_or_else(|| anyhow!("No checkpoint or state for agent {} at or before version {}", agent_id, from_version))?,
+        };
+
+        let transitions = self.get_transitions(agent_id).await?;
+        apply_transitions(base, transitions, self.validator(), from_version)
+    }
+}
+
+#[async_trait]
+impl StateCompaction for SledStateStore {
+    async fn agent_ids(&self) -> Result<Vec<String>> {
+        let mut ids = Vec::new();
+        for key in self.states.iter().keys().chain(self.transitions.iter().keys()) {
+            let key = key?;
+            let end = key.iter().position(|&b| b == 0).unwrap_or(key.len());
+            ids.push(String::from_utf8_lossy(&key[..end]).into_owned());
+        }
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    async fn stale_states(&self, agent_id: &str, keep_versions: usize) -> Result<Vec<PersistedState>> {
+        self.states.scan_prefix(agent_prefix(agent_id))
+            .rev()
+            .skip(keep_versions)
+            .map(|entry| decode(&entry?.1))
+            .collect()
+    }
+
+    async fn transitions_before(&self, agent_id: &str, before: DateTime<Utc>) -> Result<Vec<StateTransition>> {
+        let transitions = self.get_transitions(agent_id).await?;
+        Ok(transitions.into_iter().take_while(|t| t.timestamp < before).collect())
+    }
+
+    async fn archive_cold(&self, states: &[PersistedState], transitions: &[StateTransition]) -> Result<()> {
+        for state in states {
+            self.states_archive.insert(state_key(&state.agent_id, state.version), encode(state)?)?;
+        }
+        for transition in transitions {
+            self.transitions_archive.insert(transition_key(transition), encode(transition)?)?;
+        }
+        self.db.flush_async().await?;
+        Ok(())
+    }
+
+    async fn remove(&self, states: &[PersistedState], transitions: &[StateTransition]) -> Result<()> {
+        for state in states {
+            self.states.remove(state_key(&state.agent_id, state.version))?;
+        }
+        for transition in transitions {
+            self.transitions.remove(transition_key(transition))?;
+        }
+        self.db.flush_async().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use anyhow::{Result, anyhow};
+use super::{
+    apply_transitions, BasicStateValidator, PersistedState, StateCompaction, StatePersistence, StateRecovery,
+    StateTransition, StateValidator,
+};
+
+type TransitionKey = (String, DateTime<Utc>, String);
+
+fn transition_key(transition: &StateTransition) -> TransitionKey {
+    (transition.agent_id.clone(), transition.timestamp, transition.id.clone())
+}
+
+#[derive(Default)]
+struct Tables {
+    /// By agent then version
+    states: BTreeMap<(String, i32), PersistedState>,
+    /// By agent, time, then id
+    transitions: BTreeMap<TransitionKey, StateTransition>,
+    /// By agent, version, then creation order
+    checkpoints: BTreeMap<(String, i32, u64), PersistedState>,
+    states_archive: Vec<PersistedState>,
+    transitions_archive: Vec<StateTransition>,
+    next_checkpoint: u64,
+}
+
+/// Keeps agent state in memory, for tests that need a [`StateStore`](super::StateStore)
+/// without MongoDB. Behaves like the other backends, down to rejecting a
+/// version that is already saved.
+#[derive(Clone, Default)]
+pub struct MemoryStateStore {
+    tables: Arc<Mutex<Tables>>,
+    validator: Option<Arc<dyn StateValidator + Send + Sync>>,
+}
+
+impl MemoryStateStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Check replayed transitions against `validator`
+    pub fn with_validator(mut self, validator: Arc<dyn StateValidator + Send + Sync>) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    /// States and transitions compaction has archived, for assertions
+    pub fn archived(&self) -> (Vec<PersistedState>, Vec<StateTransition>) {
+        let tables = self.tables.lock().unwrap();
+        (tables.states_archive.clone(), tables.transitions_archive.clone())
+    }
+
+    fn validator(&self) -> &dyn StateValidator {
+        match &self.validator {
+            Some(validator) => validator.as_ref(),
+            None => &BasicStateValidator,
+        }
+    }
+}
+
+#[async_trait]
+impl StatePersistence for MemoryStateStore {
+    async fn save_state(&self, state: PersistedState) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        let key = (state.agent_id.clone(), state.version);
+        if tables.states.contains_key(&key) {
+            return Err(anyhow!("State version {} already saved for agent {}", state.version, state.agent_id));
+        }
+        tables.states.insert(key, state);
+        Ok(())
+    }
+
+    async fn load_state(&self, agent_id: &str) -> Result<Option<PersistedState>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.states.range((agent_id.to_string(), i32::MIN)..=(agent_id.to_string(), i32::MAX))
+            .next_back()
+            .map(|(_, state)| state.clone()))
+    }
+
+    async fn record_transition(&self, transition: StateTransition) -> Result<()> {
+        self.tables.lock().unwrap().transitions.insert(transition_key(&transition), transition);
+        Ok(())
+    }
+
+    async fn get_transitions(&self, agent_id: &str) -> Result<Vec<StateTransition>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.transitions.values().filter(|t| t.agent_id == agent_id).cloned().collect())
+    }
+}
+
+#[async_trait]
+impl StateRecovery for MemoryStateStore {
+    async fn create_checkpoint(&self, state: &PersistedState) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.next_checkpoint += 1;
+        let key = (state.agent_id.clone(), state.version, tables.next_checkpoint);
+        tables.checkpoints.insert(key, state.clone());
+        Ok(())
+    }
+
+    async fn rollback_to_checkpoint(&self, agent_id: &str) -> Result<Option<PersistedState>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.checkpoints.range((agent_id.to_string(), i32::MIN, 0)..=(agent_id.to_string(), i32::MAX, u64::MAX))
+            .next_back()
+            .map(|(_, state)| state.clone()))
+    }
+
+    async fn replay_transitions(&self, agent_id: &str, from_version: i32) -> Result<PersistedState> {
+        // Start from the newest checkpoint at or before the version, falling back to a saved state
+        let base = {
+            let tables = self.tables.lock().unwrap();
+            let checkpoint = tables.checkpoints
+                .range((agent_id.to_string(), i32::MIN, 0)..=(agent_id.to_string(), from_version, u64::MAX))
+                .next_back()
+                .map(|(_, state)| state.clone());
+            checkpoint.or_else(|| tables.states
+                .range((agent_id.to_string(), i32::MIN)..=(agent_id.to_string(), from_version))
+                .next_back()
+                .map(|(_, state)| state.clone()))
+        };
+        let base = base
+            .ok_or_else(|| anyhow!("No checkpoint or state for agent {} at or before version {}", agent_id, from_version))?;
+
+        let transitions = self.get_transitions(agent_id).await?;
+        apply_transitions(base, transitions, self.validator(), from_version)
+    }
+}
+
+#[async_trait]
+impl StateCompaction for MemoryStateStore {
+    async fn agent_ids(&self) -> Result<Vec<String>> {
+        let tables = self.tables.lock().unwrap();
+        let mut ids: Vec<String> = tables.states.keys().map(|(agent_id, _)| agent_id.clone())
+            .chain(tables.transitions.keys().map(|(agent_id, _, _)| agent_id.clone()))
+            .collect();
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
+
+    async fn stale_states(&self, agent_id: &str, keep_versions: usize) -> Result<Vec<PersistedState>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.states.range((agent_id.to_string(), i32::MIN)..=(agent_id.to_string(), i32::MAX))
+            .rev()
+            .skip(keep_versions)
+            .map(|(_, state)| state.clone())
+            .collect())
+    }
+
+    async fn transitions_before(&self, agent_id: &str, before: DateTime<Utc>) -> Result<Vec<StateTransition>> {
+        let transitions = self.get_transitions(agent_id).await?;
+        Ok(transitions.into_iter().take_while(|t| t.timestamp < before).collect())
+    }
+
+    async fn archive_cold(&self, states: &[PersistedState], transitions: &[StateTransition]) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        tables.states_archive.extend_from_slice(states);
+        tables.transitions_archive.extend_from_slice(transitions);
+        Ok(())
+    }
+
+    async fn remove(&self, states: &[PersistedState], transitions: &[StateTransition]) -> Result<()> {
+        let mut tables = self.tables.lock().unwrap();
+        for state in states {
+            tables.states.remove(&(state.agent_id.clone(), state.version));
+        }
+        for transition in transitions {
+            tables.transitions.remove(&transition_key(transition));
+        }
+        Ok(())
+    }
+}
@@ -8,14 +8,33 @@ use mongodb::{
     options::IndexOptions,
     IndexModel,
 };
+use std::sync::Arc;
+use crate::clients::Clients;
+use crate::config::SwarmConfig;
 use crate::types::Message;
 use anyhow::{Result, anyhow};
 use serde_json::Value;
+use futures_util::TryStreamExt;
 
 pub mod persistence;
 pub mod validation;
 pub mod recovery;
 pub mod agent_persistence;
+pub mod retention;
+pub mod concurrency;
+#[cfg(feature = "embedded-state")]
+pub mod embedded;
+#[cfg(any(test, feature = "test-support"))]
+pub mod memory;
+
+#[cfg(feature = "embedded-state")]
+pub use embedded::SledStateStore;
+#[cfg(any(test, feature = "test-support"))]
+pub use memory::MemoryStateStore;
+pub use concurrency::{
+    save_with_resolver, ConflictResolver, FailOnConflict, LastWriterWins, MergeMetadata, VersionConflict,
+};
+pub use retention::{ArchiveMode, CompactionReport, RetentionPolicy, StateCompaction, StateCompactor};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PersistedState {
@@ -47,6 +66,59 @@ pub trait StatePersistence {
     async fn load_state(&self, agent_id: &str) -> Result<Option<PersistedState>>;
     async fn record_transition(&self, transition: StateTransition) -> Result<()>;
     async fn get_transitions(&self, agent_id: &str) -> Result<Vec<StateTransition>>;
+
+    /// Saves `state` as version `expected_version + 1` if the agent's latest
+    /// version is still `expected_version` (0 when nothing is saved), and
+    /// fails with a [`VersionConflict`] otherwise. Relies on `save_state`
+    /// rejecting a version that already exists.
+    async fn save_state_if(&self, state: PersistedState, expected_version: i32) -> Result<PersistedState> {
+        concurrency::check_version(self, &state.agent_id, expected_version).await?;
+        let state = PersistedState { version: expected_version + 1, ..state };
+        match self.save_state(state.clone()).await {
+            Ok(()) => Ok(state),
+            Err(e) => Err(concurrency::conflict_or(self, &state.agent_id, expected_version, e).await),
+        }
+    }
+}
+
+/// A complete state backend, as selected by [`open_state_store`]
+pub trait StateStore: StatePersistence + StateRecovery + StateCompaction + Send + Sync {}
+
+impl<T: StatePersistence + StateRecovery + StateCompaction + Send + Sync> StateStore for T {}
+
+/// Where agent state is kept, from the `state.backend` setting
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateBackend {
+    Mongo,
+    /// Embedded sled database at `state.path`; needs the `embedded-state` feature
+    Sled,
+}
+
+impl std::str::FromStr for StateBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "mongo" | "mongodb" => Ok(Self::Mongo),
+            "sled" | "embedded" => Ok(Self::Sled),
+            other => Err(format!("unknown state backend '{}', expected mongo or sled", other)),
+        }
+    }
+}
+
+/// Opens the state backend chosen in `config`
+pub async fn open_state_store(config: &SwarmConfig) -> Result<Arc<dyn StateStore>> {
+    match config.state.backend.parse::<StateBackend>().map_err(|e| anyhow!(e))? {
+        StateBackend::Mongo => {
+            let uri = config.mongo.uri.as_deref().unwrap_or("mongodb://localhost:27017");
+            let client = Clients::shared().mongo(uri)?;
+            Ok(Arc::new(MongoStateManager::new(&client).await?))
+        }
+        #[cfg(feature = "embedded-state")]
+        StateBackend::Sled => Ok(Arc::new(SledStateStore::open(&config.state.path)?)),
+        #[cfg(not(feature = "embedded-state"))]
+        StateBackend::Sled => Err(anyhow!("The sled state backend needs the `embedded-state` feature")),
+    }
 }
 
 pub trait StateValidator {
@@ -62,10 +134,161 @@ pub trait StateRecovery {
     async fn replay_transitions(&self, agent_id: &str, from_version: i32) -> Result<PersistedState>;
 }
 
+/// Structural checks shared by every backend; [`StateValidatorImpl`](validation::StateValidatorImpl)
+/// adds a configured state machine on top
+pub struct BasicStateValidator;
+
+impl StateValidator for BasicStateValidator {
+    fn validate_state(&self, state: &PersistedState) -> Result<()> {
+        // Validate required fields
+        if state.agent_id.is_empty() {
+            return Err(anyhow!("Agent ID cannot be empty"));
+        }
+
+        if state.state_name.is_empty() {
+            return Err(anyhow!("State name cannot be empty"));
+        }
+
+        // Validate version is non-negative
+        if state.version < 0 {
+            return Err(anyhow!("State version cannot be negative"));
+        }
+
+        // Validate timestamps
+        if state.updated_at < state.created_at {
+            return Err(anyhow!("Updated timestamp cannot be before created timestamp"));
+        }
+
+        // Validate state_data if present
+        if let Some(data) = &state.state_data {
+            self.validate_data(data)?;
+        }
+
+        Ok(())
+    }
+
+    fn validate_transition(&self, from: &str, to: &str) -> Result<()> {
+        // Validate transition parameters
+        if from.is_empty() {
+            return Err(anyhow!("Source state cannot be empty"));
+        }
+
+        if to.is_empty() {
+            return Err(anyhow!("Target state cannot be empty"));
+        }
+
+        // Prevent self-transitions (optional rule, can be removed if needed)
+        if from == to {
+            return Err(anyhow!("State cannot transition to itself: {}", from));
+        }
+
+        Ok(())
+    }
+
+    fn validate_data(&self, state_data: &Value) -> Result<()> {
+        // Basic validation that data is properly formed
+        match state_data {
+            Value::Object(map) => {
+                // Ensure all keys are valid strings
+                for (key, value) in map {
+                    if key.is_empty() {
+                        return Err(anyhow!("State data keys cannot be empty"));
+                    }
+
+                    // Recursively validate nested objects
+                    if let Value::Object(_) = value {
+                        self.validate_data(value)?;
+                    }
+                }
+                Ok(())
+            },
+            Value::Array(arr) => {
+                // Validate array elements
+                for item in arr {
+                    if let Value::Object(_) = item {
+                        self.validate_data(item)?;
+                    }
+                }
+                Ok(())
+            },
+            _ => Ok(()) // Primitive values are always valid
+        }
+    }
+}
+
+/// Rebuilds state from `base` (the checkpoint or saved state at or before
+/// `from_version`) by applying the successful transitions recorded after it in
+/// timestamp order, checking each against `validator`. The result carries a
+/// `replay_info` audit of what was applied; it is not saved.
+pub fn apply_transitions(
+    mut state: PersistedState,
+    transitions: impl IntoIterator<Item = StateTransition>,
+    validator: &dyn StateValidator,
+    from_version: i32,
+) -> Result<PersistedState> {
+    let since = state.updated_at;
+    let base_version = state.version;
+    let mut transitions: Vec<StateTransition> = transitions.into_iter()
+        .filter(|t| t.success && t.timestamp > since)
+        .collect();
+    // Ties on timestamp are broken by id so replays are repeatable
+    transitions.sort_by(|a, b| a.timestamp.cmp(&b.timestamp).then_with(|| a.id.cmp(&b.id)));
+
+    let mut applied = Vec::with_capacity(transitions.len());
+    for transition in transitions {
+        // Ensure we're in the expected state before applying transition
+        if state.state_name != transition.from_state {
+            return Err(anyhow!(
+                "Invalid transition replay at {}: expected state '{}' but found '{}'",
+                transition.id,
+                transition.from_state,
+                state.state_name
+            ));
+        }
+        validator.validate_transition(&transition.from_state, &transition.to_state)
+            .map_err(|e| anyhow!("Invalid transition replay at {}: {}", transition.id, e))?;
+
+        state.state_name = transition.to_state.clone();
+        state.updated_at = transition.timestamp;
+        state.version += 1;
+        applied.push(serde_json::json!({
+            "id": transition.id,
+            "from_state": transition.from_state,
+            "to_state": transition.to_state,
+            "trigger": transition.trigger,
+            "timestamp": transition.timestamp,
+            "version": state.version,
+        }));
+        state.metadata.insert(
+            "last_transition".to_string(),
+            serde_json::to_value(&transition)?,
+        );
+    }
+
+    state.metadata.insert(
+        "replay_info".to_string(),
+        serde_json::json!({
+            "replayed_at": chrono::Utc::now().timestamp(),
+            "from_version": from_version,
+            "base_version": base_version,
+            "transitions_applied": applied.len(),
+            "applied": applied,
+        }),
+    );
+
+    validator.validate_state(&state)?;
+    Ok(state)
+}
+
 pub struct MongoStateManager {
     states: Collection<PersistedState>,
     transitions: Collection<StateTransition>,
     checkpoints: Collection<PersistedState>,
+    /// Cold collections compaction moves old records to
+    states_archive: Collection<PersistedState>,
+    transitions_archive: Collection<StateTransition>,
+    /// The agent's state machine rules; structural checks only when unset
+    validator: Option<Arc<dyn StateValidator + Send + Sync>>,
 }
 
 impl MongoStateManager {
@@ -76,6 +299,8 @@ impl MongoStateManager {
         let states = db.collection("agent_states");
         let transitions = db.collection("state_transitions");
         let checkpoints = db.collection("state_checkpoints");
+        let states_archive = db.collection("agent_states_archive");
+        let transitions_archive = db.collection("state_transitions_archive");
 
         // Create indexes
         let state_index = IndexModel::builder()
@@ -98,8 +323,24 @@ impl MongoStateManager {
             states,
             transitions,
             checkpoints,
+            states_archive,
+            transitions_archive,
+            validator: None,
         })
     }
+
+    /// Check states and replayed transitions against `validator`
+    pub fn with_validator(mut self, validator: Arc<dyn StateValidator + Send + Sync>) -> Self {
+        self.validator = Some(validator);
+        self
+    }
+
+    fn validator(&self) -> &dyn StateValidator {
+        match &self.validator {
+            Some(validator) => validator.as_ref(),
+            None => &BasicStateValidator,
+        }
+    }
 }
 
 #[async_trait]
@@ -139,79 +380,15 @@ impl StatePersistence for MongoStateManager {
 
 impl StateValidator for MongoStateManager {
     fn validate_state(&self, state: &PersistedState) -> Result<()> {
-        // Validate required fields
-        if state.agent_id.is_empty() {
-            return Err(anyhow!("Agent ID cannot be empty"));
-        }
-
-        if state.state_name.is_empty() {
-            return Err(anyhow!("State name cannot be empty"));
-        }
-
-        // Validate version is non-negative
-        if state.version < 0 {
-            return Err(anyhow!("State version cannot be negative"));
-        }
-
-        // Validate timestamps
-        if state.updated_at < state.created_at {
-            return Err(anyhow!("Updated timestamp cannot be before created timestamp"));
-        }
-
-        // Validate state_data if present
-        if let Some(data) = &state.state_data {
-            self.validate_data(data)?;
-        }
-
-        Ok(())
+        self.validator().validate_state(state)
     }
 
     fn validate_transition(&self, from: &str, to: &str) -> Result<()> {
-        // Validate transition parameters
-        if from.is_empty() {
-            return Err(anyhow!("Source state cannot be empty"));
-        }
-
-        if to.is_empty() {
-            return Err(anyhow!("Target state cannot be empty"));
-        }
-
-        // Prevent self-transitions (optional rule, can be removed if needed)
-        if from == to {
-            return Err(anyhow!("State cannot transition to itself: {}", from));
-        }
-
-        Ok(())
+        self.validator().validate_transition(from, to)
     }
 
     fn validate_data(&self, state_data: &Value) -> Result<()> {
-        // Basic validation that data is properly formed
-        match state_data {
-            Value::Object(map) => {
-                // Ensure all keys are valid strings
-                for (key, value) in map {
-                    if key.is_empty() {
-                        return Err(anyhow!("State data keys cannot be empty"));
-                    }
-
-                    // Recursively validate nested objects
-                    if let Value::Object(_) = value {
-                        self.validate_data(value)?;
-                    }
-                }
-                Ok(())
-            },
-            Value::Array(arr) => {
-                // Validate array elements
-                for item in arr {
-                    if let Value::Object(_) = item {
-                        self.validate_data(item)?;
-                    }
-                }
-                Ok(())
-            },
-            _ => Ok(()) // Primitive values are always valid
-        }
+        self.validator().validate_data(state_data)
     }
 }
 
@@ -231,75 +408,75 @@ impl StateRecovery for MongoStateManager {
     }
 
     async fn replay_transitions(&self, agent_id: &str, from_version: i32) -> Result<PersistedState> {
-        // Get the base state at the specified version
-        let filter = doc! {
-            "agent_id": agent_id,
-            "version": from_version
-        };
-        let mut current_state = self.states
-            .find_one(filter, None)
-            .await?
-            .ok_or_else(|| anyhow!("State not found for agent {} at version {}", agent_id, from_version))?;
-
-        // Get all transitions after this version's timestamp
-        let transition_filter = doc! {
-            "agent_id": agent_id,
-            "timestamp": { "$gt": current_state.updated_at.timestamp() }
-        };
-        let options = mongodb::options::FindOptions::builder()
-            .sort(doc! { "timestamp": 1 })
+        // Start from the newest checkpoint at or before the version, falling back to a saved state
+        let filter = doc! { "agent_id": agent_id, "version": { "$lte": from_version } };
+        let options = || mongodb::options::FindOneOptions::builder()
+            .sort(doc! { "version": -1 })
             .build();
+        let base = match self.checkpoints.find_one(filter.clone(), options()).await? {
+            Some(checkpoint) => checkpoint,
+            None => self.states.find_one(filter, options()).await?
+                .ok_or_else(|| anyhow!("No checkpoint or state for agent {} at or before version {}", agent_id, from_version))?,
+        };
 
-        let mut cursor = self.transitions.find(transition_filter, options).await?;
+        let transitions = self.get_transitions(agent_id).await?;
+        apply_transitions(base, transitions, self, from_version)
+    }
+}
 
-        // Replay each successful transition
-        let mut transitions_applied = 0;
-        while let Some(transition) = cursor.try_next().await? {
-            if transition.success {
-                // Validate the transition
-                self.validate_transition(&transition.from_state, &transition.to_state)?;
-
-                // Ensure we're in the expected state before applying transition
-                if current_state.state_name != transition.from_state {
-                    return Err(anyhow!(
-                        "Invalid transition replay: expected state '{}' but found '{}'",
-                        transition.from_state,
-                        current_state.state_name
-                    ));
-                }
+#[async_trait]
+impl StateCompaction for MongoStateManager {
+    async fn agent_ids(&self) -> Result<Vec<String>> {
+        let mut ids = Vec::new();
+        for values in [
+            self.states.distinct("agent_id", None, None).await?,
+            self.transitions.distinct("agent_id", None, None).await?,
+        ] {
+            ids.extend(values.into_iter().filter_map(|v| v.as_str().map(str::to_string)));
+        }
+        ids.sort();
+        ids.dedup();
+        Ok(ids)
+    }
 
-                // Apply the transition
-                current_state.state_name = transition.to_state.clone();
-                current_state.updated_at = transition.timestamp;
-                current_state.version += 1;
-
-                // Add transition info to metadata
-                current_state.metadata.insert(
-                    "last_transition".to_string(),
-                    serde_json::to_value(&transition)?,
-                );
-
-                transitions_applied += 1;
-            }
-        }
-
-        // Add replay metadata
-        current_state.metadata.insert(
-            "replay_info".to_string(),
-            serde_json::json!({
-                "replayed_at": chrono::Utc::now().timestamp(),
-                "from_version": from_version,
-                "transitions_applied": transitions_applied,
-            }),
-        );
+    async fn stale_states(&self, agent_id: &str, keep_versions: usize) -> Result<Vec<PersistedState>> {
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "version": -1 })
+            .skip(keep_versions as u64)
+            .build();
+        let cursor = self.states.find(doc! { "agent_id": agent_id }, options).await?;
+        Ok(cursor.try_collect().await?)
+    }
 
-        // Validate the final state before saving
-        self.validate_state(&current_state)?;
+    async fn transitions_before(&self, agent_id: &str, before: DateTime<Utc>) -> Result<Vec<StateTransition>> {
+        // Timestamps are stored as strings, so compare them here rather than in the query
+        let transitions = self.get_transitions(agent_id).await?;
+        Ok(transitions.into_iter().filter(|t| t.timestamp < before).collect())
+    }
 
-        // Save the replayed state
-        self.save_state(current_state.clone()).await?;
+    async fn archive_cold(&self, states: &[PersistedState], transitions: &[StateTransition]) -> Result<()> {
+        if !states.is_empty() {
+            self.states_archive.insert_many(states, None).await?;
+        }
+        if !transitions.is_empty() {
+            self.transitions_archive.insert_many(transitions, None).await?;
+        }
+        Ok(())
+    }
 
-        Ok(current_state)
+    async fn remove(&self, states: &[PersistedState], transitions: &[StateTransition]) -> Result<()> {
+        let mut by_agent: HashMap<&str, Vec<i32>> = HashMap::new();
+        for state in states {
+            by_agent.entry(&state.agent_id).or_default().push(state.version);
+        }
+        for (agent_id, versions) in by_agent {
+            self.states.delete_many(doc! { "agent_id": agent_id, "version": { "$in": versions } }, None).await?;
+        }
+        if !transitions.is_empty() {
+            let ids: Vec<&str> = transitions.iter().map(|t| t.id.as_str()).collect();
+            self.transitions.delete_many(doc! { "id": { "$in": ids } }, None).await?;
+        }
+        Ok(())
     }
 }
 
@@ -365,6 +542,104 @@ mod tests {
         }
     }
 
+    /// Behaviour every [`StateStore`] backend must share
+    async fn check_state_store(store: &dyn StateStore, agent_id: &str) -> Result<()> {
+        let now = Utc::now();
+        let state = PersistedState {
+            agent_id: agent_id.to_string(),
+            state_name: "initial".to_string(),
+            state_data: Some(serde_json::json!({ "step": 1 })),
+            conversation_context: vec![Message::new("hello".to_string())],
+            created_at: now,
+            updated_at: now,
+            version: 1,
+            metadata: HashMap::new(),
+        };
+        store.save_state(state.clone()).await?;
+        store.create_checkpoint(&state).await?;
+        assert!(store.save_state(state.clone()).await.is_err(), "versions are unique per agent");
+
+        let loaded = store.load_state(agent_id).await?.unwrap();
+        assert_eq!(loaded.state_data, state.state_data);
+        assert_eq!(loaded.conversation_context[0].content, "hello");
+        assert!(store.load_state("unknown_agent").await?.is_none());
+
+        for (offset, (from, to)) in [(1, ("initial", "processing")), (2, ("processing", "completed"))] {
+            store.record_transition(StateTransition {
+                id: format!("{}-{}", agent_id, offset),
+                agent_id: agent_id.to_string(),
+                from_state: from.to_string(),
+                to_state: to.to_string(),
+                trigger: "test".to_string(),
+                timestamp: now + chrono::Duration::seconds(offset),
+                success: true,
+                error: None,
+            }).await?;
+        }
+        let transitions = store.get_transitions(agent_id).await?;
+        assert_eq!(transitions.iter().map(|t| t.to_state.as_str()).collect::<Vec<_>>(), ["processing", "completed"]);
+
+        // Replay starts from the checkpoint at or before the version and is deterministic
+        let replayed = store.replay_transitions(agent_id, 2).await?;
+        assert_eq!((replayed.state_name.as_str(), replayed.version), ("completed", 3));
+        assert_eq!(replayed.state_data, state.state_data);
+        let audit = &replayed.metadata["replay_info"];
+        assert_eq!(audit["base_version"], 1);
+        assert_eq!(audit["applied"][1]["id"], format!("{}-2", agent_id));
+        assert_eq!(store.replay_transitions(agent_id, 2).await?.state_name, replayed.state_name);
+        assert!(store.replay_transitions(agent_id, 0).await.is_err());
+        assert_eq!(store.load_state(agent_id).await?.unwrap().version, 1, "replay does not save");
+
+        let checkpoint = store.rollback_to_checkpoint(agent_id).await?.unwrap();
+        assert_eq!((checkpoint.state_name.as_str(), checkpoint.version), ("initial", 1));
+
+        // Compaction moves all but the newest versions out of the hot collection
+        store.save_state(PersistedState { version: 2, ..state.clone() }).await?;
+        let stale = store.stale_states(agent_id, 1).await?;
+        assert_eq!(stale.iter().map(|s| s.version).collect::<Vec<_>>(), [1]);
+        store.archive_cold(&stale, &[]).await?;
+        store.remove(&stale, &[]).await?;
+        assert_eq!(store.load_state(agent_id).await?.unwrap().version, 2);
+        assert!(store.stale_states(agent_id, 1).await?.is_empty());
+        assert!(store.agent_ids().await?.contains(&agent_id.to_string()));
+        assert_eq!(store.transitions_before(agent_id, now + chrono::Duration::milliseconds(1500)).await?.len(), 1);
+
+        // Conditional saves only land on top of the version they were based on
+        let stale = store.save_state_if(state.clone(), 1).await.unwrap_err();
+        assert_eq!(VersionConflict::from_error(&stale).map(|c| c.actual), Some(2));
+        assert_eq!(store.save_state_if(state.clone(), 2).await?.version, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_memory_state_store() -> Result<()> {
+        let store = MemoryStateStore::new();
+        check_state_store(&store, "memory_agent").await?;
+        assert_eq!(store.archived().0.len(), 1);
+        Ok(())
+    }
+
+    #[cfg(feature = "embedded-state")]
+    #[tokio::test]
+    async fn test_sled_state_store() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = SledStateStore::open(dir.path())?;
+        check_state_store(&store, "sled_agent").await?;
+
+        // Reopening sees what was written
+        drop(store);
+        let store = SledStateStore::open(dir.path())?;
+        assert_eq!(store.get_transitions("sled_agent").await?.len(), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_mongo_state_store() -> Result<()> {
+        let client = Client::with_uri_str("mongodb://localhost:27017").await?;
+        let store = MongoStateManager::new(&client).await?;
+        check_state_store(&store, &format!("mongo_agent_{}", uuid::Uuid::new_v4())).await
+    }
+
     async fn create_test_manager() -> Result<TestStateManager> {
         let client = Client::with_uri_str("mongodb://localhost:27017").await?;
         let db = client.database("swarmonomicon_test");
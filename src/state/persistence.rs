@@ -5,12 +5,13 @@ use mongodb::{
     options::{IndexOptions, FindOneOptions, FindOptions},
     IndexModel,
 };
-use futures_util::StreamExt;
+use futures_util::TryStreamExt;
 use chrono::{DateTime, Utc};
 use anyhow::{Result, anyhow};
 use serde_json::Value;
 use crate::types::Message;
 use super::{PersistedState, StateTransition, StatePersistence};
+use super::concurrency::{check_version, conflict_or};
 use async_trait::async_trait;
 
 pub struct MongoPersistence {
@@ -116,6 +117,16 @@ impl StatePersistence for MongoPersistence {
         Ok(())
     }
 
+    async fn save_state_if(&self, state: PersistedState, expected_version: i32) -> Result<PersistedState> {
+        // save_state picks its own version, so insert directly and let the unique index catch races
+        check_version(self, &state.agent_id, expected_version).await?;
+        let new_state = PersistedState { version: expected_version + 1, updated_at: Utc::now(), ..state };
+        match self.states.insert_one(&new_state, None).await {
+            Ok(_) => Ok(new_state),
+            Err(e) => Err(conflict_or(self, &new_state.agent_id, expected_version, e.into()).await),
+        }
+    }
+
     async fn load_state(&self, agent_id: &str) -> Result<Option<PersistedState>> {
         let filter = doc! { "agent_id": agent_id };
         let options = FindOneOptions::builder()
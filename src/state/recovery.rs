@@ -5,7 +5,7 @@ use mongodb::{
     options::{IndexOptions, FindOneOptions, FindOptions},
     IndexModel,
 };
-use futures_util::StreamExt;
+use futures_util::TryStreamExt;
 use chrono::{DateTime, Utc, Duration};
 use anyhow::{Result, anyhow};
 use serde_json::Value;
@@ -204,9 +204,9 @@ impl StateRecovery for StateRecoveryManager {
 
         // Apply transitions in sequence
         let mut current_state = base_state;
-        for transition in transitions {
+        for transition in &transitions {
             if transition.success {
-                self.apply_transition(&mut current_state, &transition).await?;
+                self.apply_transition(&mut current_state, transition).await?;
             }
         }
 
@@ -221,7 +221,7 @@ impl StateRecovery for StateRecoveryManager {
         );
 
         self.states
-            .replace_one(doc! { "agent_id": current_state.agent_id }, current_state.clone(), None)
+            .replace_one(doc! { "agent_id": &current_state.agent_id }, current_state.clone(), None)
             .await?;
 
         Ok(current_state)
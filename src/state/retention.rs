@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use anyhow::{Result, anyhow};
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use crate::config::StateSettings;
+use super::{PersistedState, StateTransition};
+
+/// How much history to keep hot for an agent
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetentionPolicy {
+    /// Newest state versions left in place
+    pub keep_versions: usize,
+    /// Transitions older than this are moved out; `None` keeps them all
+    pub transition_max_age: Option<chrono::Duration>,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self { keep_versions: 50, transition_max_age: Some(chrono::Duration::days(30)) }
+    }
+}
+
+/// Where records that fall out of retention go
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArchiveMode {
+    /// The backend's cold collection (`agent_states_archive`, `state_transitions_archive`)
+    Collection,
+    /// Gzipped JSON lines files in this directory
+    Gzip(PathBuf),
+    Delete,
+}
+
+/// What one compaction moved out of the hot collections
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CompactionReport {
+    pub agent_id: String,
+    pub states_archived: usize,
+    pub transitions_archived: usize,
+    /// Export file, in [`ArchiveMode::Gzip`]
+    pub archive_file: Option<PathBuf>,
+}
+
+/// Backend operations the [`StateCompactor`] needs
+#[async_trait]
+pub trait StateCompaction: Send + Sync {
+    async fn agent_ids(&self) -> Result<Vec<String>>;
+    /// States beyond the newest `keep_versions`
+    async fn stale_states(&self, agent_id: &str, keep_versions: usize) -> Result<Vec<PersistedState>>;
+    async fn transitions_before(&self, agent_id: &str, before: DateTime<Utc>) -> Result<Vec<StateTransition>>;
+    /// Copy records to the backend's cold collections
+    async fn archive_cold(&self, states: &[PersistedState], transitions: &[StateTransition]) -> Result<()>;
+    /// Delete records from the hot collections
+    async fn remove(&self, states: &[PersistedState], transitions: &[StateTransition]) -> Result<()>;
+}
+
+/// Periodically moves old state versions and transitions out of the hot
+/// collections, with per-agent policies over a default
+pub struct StateCompactor<S: ?Sized = dyn StateCompaction> {
+    store: Arc<S>,
+    mode: ArchiveMode,
+    default_policy: RetentionPolicy,
+    agent_policies: HashMap<String, RetentionPolicy>,
+}
+
+impl<S: StateCompaction + ?Sized + 'static> StateCompactor<S> {
+    pub fn new(store: Arc<S>, mode: ArchiveMode, default_policy: RetentionPolicy) -> Self {
+        Self { store, mode, default_policy, agent_policies: HashMap::new() }
+    }
+
+    pub fn with_agent_policy(mut self, agent_id: impl Into<String>, policy: RetentionPolicy) -> Self {
+        self.agent_policies.insert(agent_id.into(), policy);
+        self
+    }
+
+    /// Policies from the `[state.retention]` settings
+    pub fn from_settings(store: Arc<S>, settings: &StateSettings) -> Result<Self> {
+        let retention = &settings.retention;
+        let mode = match retention.archive.as_str() {
+            "collection" => ArchiveMode::Collection,
+            "gzip" => ArchiveMode::Gzip(retention.archive_dir.clone()),
+            "delete" => ArchiveMode::Delete,
+            other => return Err(anyhow!("Unknown state archive mode '{}'", other)),
+        };
+        let max_age = |days: u64| (days > 0).then(|| chrono::Duration::days(days as i64));
+        let default_policy = RetentionPolicy {
+            keep_versions: retention.keep_versions,
+            transition_max_age: max_age(retention.transition_max_age_days),
+        };
+
+        let mut compactor = Self::new(store, mode, default_policy.clone());
+        for (agent_id, overrides) in &retention.agents {
+            compactor = compactor.with_agent_policy(agent_id.clone(), RetentionPolicy {
+                keep_versions: overrides.keep_versions.unwrap_or(default_policy.keep_versions),
+                transition_max_age: overrides.transition_max_age_days.map_or(default_policy.transition_max_age, max_age),
+            });
+        }
+        Ok(compactor)
+    }
+
+    pub fn policy_for(&self, agent_id: &str) -> &RetentionPolicy {
+        self.agent_policies.get(agent_id).unwrap_or(&self.default_policy)
+    }
+
+    pub async fn compact_agent(&self, agent_id: &str) -> Result<CompactionReport> {
+        let policy = self.policy_for(agent_id);
+        let states = self.store.stale_states(agent_id, policy.keep_versions).await?;
+        let transitions = match policy.transition_max_age {
+            Some(max_age) => self.store.transitions_before(agent_id, Utc::now() - max_age).await?,
+            None => Vec::new(),
+        };
+        let mut report = CompactionReport {
+            agent_id: agent_id.to_string(),
+            states_archived: states.len(),
+            transitions_archived: transitions.len(),
+            archive_file: None,
+        };
+        if states.is_empty() && transitions.is_empty() {
+            return Ok(report);
+        }
+
+        // Archive before removing, so a failure leaves the records hot
+        match &self.mode {
+            ArchiveMode::Collection => self.store.archive_cold(&states, &transitions).await?,
+            ArchiveMode::Gzip(dir) => report.archive_file = Some(write_gzip_archive(dir, agent_id, &states, &transitions).await?),
+            ArchiveMode::Delete => {}
+        }
+        self.store.remove(&states, &transitions).await?;
+        Ok(report)
+    }
+
+    /// Compacts every agent, logging and skipping those that fail
+    pub async fn run_once(&self) -> Result<Vec<CompactionReport>> {
+        let mut reports = Vec::new();
+        for agent_id in self.store.agent_ids().await? {
+            match self.compact_agent(&agent_id).await {
+                Ok(report) => reports.push(report),
+                Err(e) => tracing::error!("State compaction failed for {}: {}", agent_id, e),
+            }
+        }
+        Ok(reports)
+    }
+
+    pub fn spawn(self, interval: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                match self.run_once().await {
+                    Ok(reports) => {
+                        for report in reports.iter().filter(|r| r.states_archived + r.transitions_archived > 0) {
+                            tracing::info!(
+                                "Compacted state for {}: {} versions, {} transitions archived",
+                                report.agent_id, report.states_archived, report.transitions_archived
+                            );
+                        }
+                    }
+                    Err(e) => tracing::error!("State compaction failed: {}", e),
+                }
+            }
+        })
+    }
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", content = "record", rename_all = "snake_case")]
+enum ArchivedRecord<'a> {
+    State(&'a PersistedState),
+    Transition(&'a StateTransition),
+}
+
+/// Writes `<dir>/<agent>-<timestamp>.jsonl.gz`, one record per line
+async fn write_gzip_archive(
+    dir: &std::path::Path,
+    agent_id: &str,
+    states: &[PersistedState],
+    transitions: &[StateTransition],
+) -> Result<PathBuf> {
+    let mut lines = Vec::new();
+    for record in states.iter().map(ArchivedRecord::State).chain(transitions.iter().map(ArchivedRecord::Transition)) {
+        serde_json::to_writer(&mut lines, &record)?;
+        lines.push(b'\n');
+    }
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&lines)?;
+    let compressed = encoder.finish()?;
+
+    let agent: String = agent_id.chars().map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' }).collect();
+    let path = dir.join(format!("{}-{}.jsonl.gz", agent, Utc::now().format("%Y%m%dT%H%M%S%.f")));
+    tokio::fs::create_dir_all(dir).await?;
+    tokio::fs::write(&path, compressed).await
+        .map_err(|e| anyhow!("Failed to write state archive {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use tokio::sync::Mutex;
+
+    #[derive(Default)]
+    struct MemoryStore {
+        states: Mutex<Vec<PersistedState>>,
+        transitions: Mutex<Vec<StateTransition>>,
+        cold: Mutex<usize>,
+    }
+
+    #[async_trait]
+    impl StateCompaction for MemoryStore {
+        async fn agent_ids(&self) -> Result<Vec<String>> {
+            let mut ids: Vec<String> = self.states.lock().await.iter().map(|s| s.agent_id.clone()).collect();
+            ids.dedup();
+            Ok(ids)
+        }
+
+        async fn stale_states(&self, agent_id: &str, keep_versions: usize) -> Result<Vec<PersistedState>> {
+            let mut states: Vec<_> = self.states.lock().await.iter().filter(|s| s.agent_id == agent_id).cloned().collect();
+            states.sort_by_key(|s| std::cmp::Reverse(s.version));
+            Ok(states.into_iter().skip(keep_versions).collect())
+        }
+
+        async fn transitions_before(&self, agent_id: &str, before: DateTime<Utc>) -> Result<Vec<StateTransition>> {
+            Ok(self.transitions.lock().await.iter().filter(|t| t.agent_id == agent_id && t.timestamp < before).cloned().collect())
+        }
+
+        async fn archive_cold(&self, states: &[PersistedState], transitions: &[StateTransition]) -> Result<()> {
+            *self.cold.lock().await += states.len() + transitions.len();
+            Ok(())
+        }
+
+        async fn remove(&self, states: &[PersistedState], transitions: &[StateTransition]) -> Result<()> {
+            self.states.lock().await.retain(|s| !states.iter().any(|r| r.agent_id == s.agent_id && r.version == s.version));
+            self.transitions.lock().await.retain(|t| !transitions.iter().any(|r| r.id == t.id));
+            Ok(())
+        }
+    }
+
+    fn store_with_history(agent_id: &str, versions: i32) -> MemoryStore {
+        let store = MemoryStore::default();
+        let now = Utc::now();
+        let states = (1..=versions).map(|version| PersistedState {
+            agent_id: agent_id.to_string(),
+            state_name: "idle".to_string(),
+            state_data: None,
+            conversation_context: vec![],
+            created_at: now,
+            updated_at: now,
+            version,
+            metadata: HashMap::new(),
+        });
+        store.states.try_lock().unwrap().extend(states);
+        store.transitions.try_lock().unwrap().extend([40, 1].into_iter().map(|days_ago| StateTransition {
+            id: format!("t-{}", days_ago),
+            agent_id: agent_id.to_string(),
+            from_state: "idle".to_string(),
+            to_state: "busy".to_string(),
+            trigger: "test".to_string(),
+            timestamp: now - chrono::Duration::days(days_ago),
+            success: true,
+            error: None,
+        }));
+        store
+    }
+
+    #[tokio::test]
+    async fn test_compaction_keeps_newest_versions_per_agent() -> Result<()> {
+        let store = Arc::new(store_with_history("haiku", 10));
+        let compactor = StateCompactor::new(store.clone(), ArchiveMode::Collection, RetentionPolicy::default())
+            .with_agent_policy("haiku", RetentionPolicy { keep_versions: 3, transition_max_age: Some(chrono::Duration::days(7)) });
+
+        let reports = compactor.run_once().await?;
+        assert_eq!((reports[0].states_archived, reports[0].transitions_archived), (7, 1));
+        let versions: Vec<i32> = store.states.lock().await.iter().map(|s| s.version).collect();
+        assert_eq!(versions, [8, 9, 10]);
+        assert_eq!(store.transitions.lock().await[0].id, "t-1");
+        assert_eq!(*store.cold.lock().await, 8);
+
+        // Nothing left to move
+        assert_eq!(compactor.compact_agent("haiku").await?.states_archived, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_gzip_archive_export() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let store = Arc::new(store_with_history("git", 2));
+        let compactor = StateCompactor::new(
+            store.clone(),
+            ArchiveMode::Gzip(dir.path().to_path_buf()),
+            RetentionPolicy { keep_versions: 1, transition_max_age: None },
+        );
+
+        let report = compactor.compact_agent("git").await?;
+        let mut lines = String::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(report.archive_file.unwrap())?).read_to_string(&mut lines)?;
+        let record: serde_json::Value = serde_json::from_str(lines.lines().next().unwrap())?;
+        assert_eq!(record["kind"], "state");
+        assert_eq!(record["record"]["version"], 1);
+        assert_eq!(store.transitions.lock().await.len(), 2);
+        Ok(())
+    }
+}
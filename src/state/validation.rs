@@ -1,7 +1,9 @@
 use std::collections::{HashMap, HashSet};
 use serde_json::Value;
 use anyhow::{Result, anyhow};
-use super::{PersistedState, StateValidator};
+use jsonschema::JSONSchema;
+use super::{BasicStateValidator, PersistedState, StateValidator};
+use crate::types::{AgentConfig, StateMachine};
 use regex::Regex;
 
 #[derive(Debug, Clone)]
@@ -27,6 +29,10 @@ pub struct StateValidationConfig {
     state_rules: HashMap<String, Vec<ValidationRule>>,
     transition_rules: HashMap<(String, String), Vec<ValidationRule>>,
     data_rules: HashMap<String, Vec<ValidationRule>>,
+    /// Patterns a user's reply must match while the agent is in a state
+    input_rules: HashMap<String, Vec<ValidationRule>>,
+    /// JSON schemas `state_data` must satisfy in a state
+    data_schemas: HashMap<String, JSONSchema>,
     valid_states: HashSet<String>,
     valid_transitions: HashSet<(String, String)>,
 }
@@ -37,6 +43,8 @@ impl StateValidationConfig {
             state_rules: HashMap::new(),
             transition_rules: HashMap::new(),
             data_rules: HashMap::new(),
+            input_rules: HashMap::new(),
+            data_schemas: HashMap::new(),
             valid_states: HashSet::new(),
             valid_transitions: HashSet::new(),
         }
@@ -82,6 +90,60 @@ impl StateValidationConfig {
             .push(rule);
         Ok(())
     }
+
+    pub fn add_input_rule(&mut self, state: &str, pattern: &str, error_message: &str) -> Result<()> {
+        let rule = ValidationRule::new(pattern, error_message)?;
+        self.input_rules
+            .entry(state.to_string())
+            .or_insert_with(Vec::new)
+            .push(rule);
+        Ok(())
+    }
+
+    pub fn add_data_schema(&mut self, state: &str, schema: &Value) -> Result<()> {
+        let compiled = JSONSchema::compile(schema)
+            .map_err(|e| anyhow!("Invalid data schema for state {}: {}", state, e))?;
+        self.data_schemas.insert(state.to_string(), compiled);
+        Ok(())
+    }
+
+    /// Rules from a state machine definition: its states, the targets of each
+    /// state's `transitions`, `validation` as `[pattern, message]` pairs for
+    /// replies, and `data` as a JSON schema for `state_data`
+    pub fn from_state_machine(machine: &StateMachine) -> Result<Self> {
+        let mut config = Self::new();
+        for (name, state) in &machine.states {
+            config.add_state(name);
+            for target in state.transitions.iter().flat_map(|t| t.values()) {
+                config.add_transition(name, target);
+            }
+            for pair in state.validation.iter().flat_map(|v| v.chunks(2)) {
+                let message = pair.get(1).map(String::as_str).unwrap_or("Invalid input");
+                config.add_input_rule(name, &pair[0], message)?;
+            }
+            if let Some(schema) = &state.data {
+                let schema: Value = serde_json::from_str(schema)
+                    .map_err(|e| anyhow!("State {} data is not a JSON schema: {}", name, e))?;
+                config.add_data_schema(name, &schema)?;
+            }
+        }
+        // Transitions may lead to terminal states that have no entry of their own
+        let targets: Vec<String> = config.valid_transitions.iter().map(|(_, to)| to.clone()).collect();
+        config.valid_states.extend(targets);
+        if !config.valid_states.contains(&machine.initial_state) {
+            return Err(anyhow!("Initial state {} is not defined", machine.initial_state));
+        }
+        Ok(config)
+    }
+
+    fn allowed_from(&self, state: &str) -> Vec<&str> {
+        let mut allowed: Vec<&str> = self.valid_transitions.iter()
+            .filter(|(from, _)| from == state)
+            .map(|(_, to)| to.as_str())
+            .collect();
+        allowed.sort();
+        allowed
+    }
 }
 
 pub struct StateValidatorImpl {
@@ -93,10 +155,35 @@ impl StateValidatorImpl {
         Self { config }
     }
 
-    fn validate_state_rules(&self, state_name: &str, state: &PersistedState) -> Result<()> {
+    /// Validator for an agent's state machine, or `None` if it has none
+    pub fn from_agent_config(config: &AgentConfig) -> Result<Option<Self>> {
+        config.state_machine.as_ref()
+            .map(|machine| StateValidationConfig::from_state_machine(machine).map(Self::new))
+            .transpose()
+            .map_err(|e| anyhow!("Agent {}: {}", config.name, e))
+    }
+
+    /// Check a user's reply against the rules of the state the agent is in
+    pub fn validate_input(&self, state: &str, input: &str) -> Result<()> {
+        for rule in self.config.input_rules.get(state).into_iter().flatten() {
+            if !rule.validate(input.trim()) {
+                return Err(anyhow!("{}", rule.error_message));
+            }
+        }
+        Ok(())
+    }
+
+    fn require_known(&self, state_name: &str) -> Result<()> {
         if !self.config.valid_states.contains(state_name) {
-            return Err(anyhow!("Invalid state: {}", state_name));
+            let mut known: Vec<&str> = self.config.valid_states.iter().map(String::as_str).collect();
+            known.sort();
+            return Err(anyhow!("Unknown state: {} (known states: {})", state_name, known.join(", ")));
         }
+        Ok(())
+    }
+
+    fn validate_state_rules(&self, state_name: &str, state: &PersistedState) -> Result<()> {
+        self.require_known(state_name)?;
 
         if let Some(rules) = self.config.state_rules.get(state_name) {
             for rule in rules {
@@ -109,8 +196,12 @@ impl StateValidatorImpl {
     }
 
     fn validate_transition_rules(&self, from: &str, to: &str) -> Result<()> {
+        self.require_known(from)?;
+        self.require_known(to)?;
         if !self.config.valid_transitions.contains(&(from.to_string(), to.to_string())) {
-            return Err(anyhow!("Invalid transition from {} to {}", from, to));
+            let allowed = self.config.allowed_from(from);
+            let allowed = if allowed.is_empty() { "none".to_string() } else { allowed.join(", ") };
+            return Err(anyhow!("Invalid transition from {} to {}; allowed from {}: {}", from, to, from, allowed));
         }
 
         if let Some(rules) = self.config.transition_rules.get(&(from.to_string(), to.to_string())) {
@@ -143,7 +234,18 @@ impl StateValidatorImpl {
 
 impl StateValidator for StateValidatorImpl {
     fn validate_state(&self, state: &PersistedState) -> Result<()> {
-        self.validate_state_rules(&state.state_name, state)
+        BasicStateValidator.validate_state(state)?;
+        self.validate_state_rules(&state.state_name, state)?;
+        if let Some(schema) = self.config.data_schemas.get(&state.state_name) {
+            let data = state.state_data.clone().unwrap_or(Value::Null);
+            // The error iterator borrows `data`, so the messages are collected before it drops
+            let result = schema.validate(&data)
+                .map_err(|errors| errors.map(|e| format!("{} at '{}'", e, e.instance_path)).collect::<Vec<_>>());
+            if let Err(errors) = result {
+                return Err(anyhow!("Invalid data for state {}: {}", state.state_name, errors.join("; ")));
+            }
+        }
+        Ok(())
     }
 
     fn validate_transition(&self, from: &str, to: &str) -> Result<()> {
@@ -151,6 +253,7 @@ impl StateValidator for StateValidatorImpl {
     }
 
     fn validate_data(&self, state_data: &Value) -> Result<()> {
+        BasicStateValidator.validate_data(state_data)?;
         self.validate_data_rules(state_data)
     }
 }
@@ -240,6 +343,56 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_rules_from_state_machine() -> Result<()> {
+        let machine: StateMachine = serde_json::from_value(serde_json::json!({
+            "initial_state": "awaiting_topic",
+            "states": {
+                "awaiting_topic": {
+                    "name": "awaiting_topic",
+                    "transitions": { "topic_received": "generating" },
+                },
+                "generating": {
+                    "name": "generating",
+                    "data": r#"{"type": "object", "required": ["topic"], "properties": {"topic": {"type": "string"}}}"#,
+                    "transitions": { "haiku_generated": "complete", "failed": "awaiting_topic" },
+                },
+                "complete": {
+                    "name": "complete",
+                    "transitions": { "yes": "awaiting_topic", "no": "goodbye" },
+                    "validation": ["^(yes|no)$", "Please respond with 'yes' or 'no'."],
+                },
+            },
+        }))?;
+        let validator = StateValidatorImpl::new(StateValidationConfig::from_state_machine(&machine)?);
+
+        assert!(validator.validate_transition("complete", "goodbye").is_ok());
+        let error = validator.validate_transition("awaiting_topic", "complete").unwrap_err().to_string();
+        assert!(error.ends_with("allowed from awaiting_topic: generating"), "{}", error);
+        let error = validator.validate_transition("generating", "published").unwrap_err().to_string();
+        assert!(error.starts_with("Unknown state: published"), "{}", error);
+
+        let mut state = PersistedState {
+            agent_id: "haiku".to_string(),
+            state_name: "generating".to_string(),
+            state_data: Some(serde_json::json!({ "topic": "autumn" })),
+            conversation_context: vec![],
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            version: 1,
+            metadata: HashMap::new(),
+        };
+        assert!(validator.validate_state(&state).is_ok());
+        state.state_data = Some(serde_json::json!({ "topic": 5 }));
+        assert!(validator.validate_state(&state).is_err());
+        state.state_name = "dreaming".to_string();
+        assert!(validator.validate_state(&state).is_err());
+
+        assert!(validator.validate_input("complete", "yes").is_ok());
+        assert_eq!(validator.validate_input("complete", "maybe").unwrap_err().to_string(), "Please respond with 'yes' or 'no'.");
+        Ok(())
+    }
+
     #[test]
     fn test_data_validation() -> Result<()> {
         let config = create_test_config()?;
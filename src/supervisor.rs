@@ -0,0 +1,156 @@
+//! Supervised background jobs. Work that used to be handed to a bare
+//! `tokio::spawn` goes through a [`TaskSupervisor`] instead, so the number of
+//! jobs in flight is bounded, a panic is logged with the job it came from
+//! rather than vanishing with its `JoinHandle`, and shutdown can wait for
+//! what is still running.
+
+use std::any::Any;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use futures_util::FutureExt;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::Instrument;
+
+/// Owns a group of spawned jobs, at most `max_jobs` at a time
+#[derive(Clone)]
+pub struct TaskSupervisor {
+    name: Arc<str>,
+    max_jobs: usize,
+    permits: Arc<Semaphore>,
+    jobs: Arc<Mutex<JoinSet<()>>>,
+    panics: Arc<AtomicU64>,
+}
+
+impl TaskSupervisor {
+    pub fn new(name: impl Into<String>, max_jobs: usize) -> Self {
+        let max_jobs = max_jobs.max(1);
+        Self {
+            name: name.into().into(),
+            max_jobs,
+            permits: Arc::new(Semaphore::new(max_jobs)),
+            jobs: Arc::new(Mutex::new(JoinSet::new())),
+            panics: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Run `future` as a job called `job`, waiting for a free slot first.
+    /// Fails once [`drain`](Self::drain) has started.
+    pub async fn spawn<F>(&self, job: impl Into<String>, future: F) -> Result<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let job = job.into();
+        let permit = self.permits.clone().acquire_owned().await
+            .map_err(|_| anyhow!("{} is shutting down, not starting {}", self.name, job))?;
+
+        let mut jobs = self.jobs.lock().unwrap();
+        // Checked under the lock so a job can't slip in after drain took the set
+        if self.permits.is_closed() {
+            return Err(anyhow!("{} is shutting down, not starting {}", self.name, job));
+        }
+        self.reap(&mut jobs);
+
+        let (name, panics) = (self.name.clone(), self.panics.clone());
+        jobs.spawn(async move {
+            let outcome = AssertUnwindSafe(future).catch_unwind().await;
+            drop(permit);
+            if let Err(panic) = outcome {
+                panics.fetch_add(1, Ordering::Relaxed);
+                tracing::error!(supervisor = %name, job = %job, "Job panicked: {}", panic_message(&*panic));
+            }
+        }.in_current_span());
+        Ok(())
+    }
+
+    /// Jobs running now
+    pub fn active(&self) -> usize {
+        self.max_jobs - self.permits.available_permits()
+    }
+
+    /// Jobs that have panicked since the supervisor was created
+    pub fn panics(&self) -> u64 {
+        self.panics.load(Ordering::Relaxed)
+    }
+
+    /// Stop accepting jobs and wait up to `grace` for the running ones, then
+    /// abort whatever is left. Returns how many were aborted.
+    pub async fn drain(&self, grace: Duration) -> usize {
+        let mut jobs = {
+            let mut jobs = self.jobs.lock().unwrap();
+            self.permits.close();
+            std::mem::take(&mut *jobs)
+        };
+        if jobs.is_empty() {
+            return 0;
+        }
+        tracing::info!("Waiting up to {:?} for {} {} job(s)", grace, jobs.len(), self.name);
+
+        let finished = tokio::time::timeout(grace, async {
+            while jobs.join_next().await.is_some() {}
+        }).await;
+        if finished.is_ok() {
+            return 0;
+        }
+
+        let aborted = jobs.len();
+        tracing::warn!("Aborting {} {} job(s) still running after {:?}", aborted, self.name, grace);
+        jobs.shutdown().await;
+        aborted
+    }
+
+    /// Drop finished jobs from the set. Their panics were already logged by
+    /// the job itself, so only an unexpected cancellation is left to report.
+    fn reap(&self, jobs: &mut JoinSet<()>) {
+        while let Some(result) = jobs.try_join_next() {
+            if let Err(e) = result {
+                tracing::warn!(supervisor = %self.name, "Job ended abnormally: {}", e);
+            }
+        }
+    }
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    panic.downcast_ref::<&str>().copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("non-string panic payload")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_panics_are_counted_and_slots_freed() -> Result<()> {
+        let supervisor = TaskSupervisor::new("test", 1);
+        supervisor.spawn("boom", async { panic!("boom") }).await?;
+        // The only slot comes back once the panicking job is done
+        tokio::time::timeout(Duration::from_secs(1), supervisor.spawn("ok", async {})).await??;
+
+        assert_eq!(supervisor.drain(Duration::from_secs(1)).await, 0);
+        assert_eq!(supervisor.panics(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_drain_aborts_stragglers_and_refuses_new_jobs() -> Result<()> {
+        let supervisor = TaskSupervisor::new("test", 4);
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel();
+        supervisor.spawn("quick", async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let _ = done_tx.send(());
+        }).await?;
+        supervisor.spawn("stuck", std::future::pending()).await?;
+        assert_eq!(supervisor.active(), 2);
+
+        assert_eq!(supervisor.drain(Duration::from_millis(200)).await, 1);
+        assert!(done_rx.await.is_ok(), "finished jobs run to completion");
+        assert_eq!(supervisor.active(), 0);
+        assert!(supervisor.spawn("late", async {}).await.is_err());
+        Ok(())
+    }
+}
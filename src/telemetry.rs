@@ -0,0 +1,220 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use serde_json::Value;
+use tracing::Level;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::prelude::*;
+
+/// HTTP header carrying the correlation id between services
+pub const CORRELATION_HEADER: &str = "x-correlation-id";
+/// Field injected into MQTT JSON payloads carrying the correlation id
+pub const CORRELATION_FIELD: &str = "_correlation_id";
+
+tokio::task_local! {
+    static CORRELATION_ID: String;
+}
+
+pub fn new_correlation_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// Run `fut` with `id` as the current correlation id
+pub async fn with_correlation_id<F: Future>(id: String, fut: F) -> F::Output {
+    CORRELATION_ID.scope(id, fut).await
+}
+
+/// Correlation id of the request or task currently being handled, if any
+pub fn current_correlation_id() -> Option<String> {
+    CORRELATION_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Add the current correlation id to a JSON object payload
+pub fn inject_correlation_id(payload: &mut Value) {
+    if let (Some(id), Value::Object(obj)) = (current_correlation_id(), payload) {
+        obj.entry(CORRELATION_FIELD.to_string()).or_insert(Value::String(id));
+    }
+}
+
+/// Read a correlation id from a JSON payload, if one was injected upstream
+pub fn extract_correlation_id(payload: &str) -> Option<String> {
+    serde_json::from_str::<Value>(payload).ok()?
+        .get(CORRELATION_FIELD)?
+        .as_str()
+        .map(|s| s.to_string())
+}
+
+/// Watches an async runtime for worker threads held up by blocking code.
+///
+/// A task sleeps in short ticks and measures how late it wakes up; a wake-up
+/// later than the threshold means the thread it was scheduled on was busy
+/// with something that never yielded, such as sync file or process I/O. Each
+/// one is logged at warn level and counted. The watchdog stops when dropped.
+///
+/// It is most precise on a current-thread runtime, as `#[tokio::test]` uses.
+/// On a multi-threaded runtime other workers can pick the tick up, so it
+/// catches stalls long or widespread enough to starve the timer.
+pub struct StallWatch {
+    stalls: Arc<AtomicU64>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+static SHARED_WATCH: OnceLock<StallWatch> = OnceLock::new();
+
+impl StallWatch {
+    /// Start watching the current runtime for stalls longer than `threshold`
+    pub fn start(threshold: Duration) -> Self {
+        let stalls = Arc::new(AtomicU64::new(0));
+        let tick = (threshold / 2).max(Duration::from_millis(10));
+        let counter = stalls.clone();
+        let task = tokio::spawn(async move {
+            loop {
+                let started = Instant::now();
+                tokio::time::sleep(tick).await;
+                let late = started.elapsed().saturating_sub(tick);
+                if late > threshold {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    tracing::warn!(
+                        late_ms = late.as_millis() as u64,
+                        "Async runtime stalled for {:?}; blocking code is running on a worker thread", late
+                    );
+                }
+            }
+        });
+        Self { stalls, task }
+    }
+
+    /// A watchdog with the threshold in `RUNTIME_STALL_MS`, if set and non-zero
+    pub fn from_env() -> Option<Self> {
+        let millis: u64 = std::env::var("RUNTIME_STALL_MS").ok()?.parse().ok()?;
+        (millis > 0).then(|| Self::start(Duration::from_millis(millis)))
+    }
+
+    /// The watchdog [`init_tracing`] started for this process, if any
+    pub fn shared() -> Option<&'static StallWatch> {
+        SHARED_WATCH.get()
+    }
+
+    /// Stalls seen so far
+    pub fn stalls(&self) -> u64 {
+        self.stalls.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for StallWatch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Initialize the global tracing subscriber for a binary.
+///
+/// Logs go to stdout. With the `otel` feature enabled and
+/// `OTEL_EXPORTER_OTLP_ENDPOINT` set, spans are also exported over OTLP.
+/// Called inside a tokio runtime with `RUNTIME_STALL_MS` set, it also starts
+/// the process's [`StallWatch`].
+pub fn init_tracing(service_name: &str, level: Level, span_events: FmtSpan) {
+    if tokio::runtime::Handle::try_current().is_ok() && SHARED_WATCH.get().is_none() {
+        if let Some(watch) = StallWatch::from_env() {
+            let _ = SHARED_WATCH.set(watch);
+        }
+    }
+
+    let fmt_layer = tracing_subscriber::fmt::layer()
+        .with_span_events(span_events)
+        .with_filter(tracing_subscriber::filter::LevelFilter::from_level(level));
+
+    let registry = tracing_subscriber::registry().with(fmt_layer);
+
+    #[cfg(feature = "otel")]
+    {
+        if let Ok(endpoint) = std::env::var("OTEL_EXPORTER_OTLP_ENDPOINT") {
+            match otel::tracer(service_name, &endpoint) {
+                Ok(tracer) => {
+                    registry.with(tracing_opentelemetry::layer().with_tracer(tracer)).init();
+                    tracing::info!("Exporting traces to {}", endpoint);
+                    return;
+                }
+                Err(e) => eprintln!("Failed to initialize OTLP exporter: {}", e),
+            }
+        }
+    }
+
+    let _ = service_name;
+    registry.init();
+}
+
+/// Flush any buffered spans before the process exits
+pub fn shutdown_tracing() {
+    #[cfg(feature = "otel")]
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+    use opentelemetry_sdk::{trace, Resource};
+
+    pub fn tracer(service_name: &str, endpoint: &str) -> Result<trace::Tracer, opentelemetry::trace::TraceError> {
+        let tracer = opentelemetry_otlp::new_pipeline()
+            .tracing()
+            .with_exporter(
+                opentelemetry_otlp::new_exporter()
+                    .tonic()
+                    .with_endpoint(endpoint),
+            )
+            .with_trace_config(
+                trace::config().with_resource(Resource::new(vec![
+                    KeyValue::new("service.name", service_name.to_string()),
+                ])),
+            )
+            .install_batch(opentelemetry_sdk::runtime::Tokio)?;
+        Ok(tracer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_correlation_id_scope() {
+        assert!(current_correlation_id().is_none());
+
+        let id = with_correlation_id("abc".to_string(), async {
+            current_correlation_id()
+        }).await;
+        assert_eq!(id.as_deref(), Some("abc"));
+    }
+
+    #[tokio::test]
+    async fn test_payload_round_trip() {
+        let payload = with_correlation_id("abc".to_string(), async {
+            let mut payload = json!({ "id": "task-1" });
+            inject_correlation_id(&mut payload);
+            payload
+        }).await;
+
+        assert_eq!(payload[CORRELATION_FIELD], "abc");
+        assert_eq!(extract_correlation_id(&payload.to_string()).as_deref(), Some("abc"));
+        assert!(extract_correlation_id("plain text").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_stall_watch_flags_blocking_code() {
+        let watch = StallWatch::start(Duration::from_millis(100));
+        tokio::time::sleep(Duration::from_millis(10)).await;
+
+        // Yielding code keeps the runtime responsive
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(watch.stalls(), 0);
+
+        // Sync I/O stand-in: holds the only worker thread without yielding
+        std::thread::sleep(Duration::from_millis(400));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(watch.stalls() >= 1);
+    }
+}
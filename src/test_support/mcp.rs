@@ -0,0 +1,118 @@
+use anyhow::Result;
+use serde_json::Value;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+use crate::mcp::{McpClient, McpClientConfig};
+
+/// Omnispindle responses recorded from a real server, as the schema tests use them
+pub const ADD_TODO: &str = include_str!("../mcp/fixtures/add_todo.json");
+pub const QUERY_TODOS: &str = include_str!("../mcp/fixtures/query_todos.json");
+pub const GET_TODO: &str = include_str!("../mcp/fixtures/get_todo.json");
+pub const ERROR: &str = include_str!("../mcp/fixtures/error.json");
+
+/// Priority of responses set up per test, ahead of the fixture defaults
+const EXPLICIT: u8 = 1;
+
+/// An Omnispindle MCP server on a local port, answering tool calls with
+/// canned responses in the server's schema
+pub struct McpFake {
+    server: MockServer,
+}
+
+impl McpFake {
+    /// A server that answers nothing until responses are set up
+    pub async fn start() -> Self {
+        Self { server: MockServer::start().await }
+    }
+
+    /// A server answering every todo tool: the recorded fixtures for add,
+    /// query and get, and plain successes for update, complete and delete
+    pub async fn with_fixtures() -> Self {
+        let fake = Self::start().await;
+        for (tool, body) in [
+            ("add_todo_tool", ADD_TODO.to_string()),
+            ("query_todos_tool", QUERY_TODOS.to_string()),
+            ("get_todo_tool", GET_TODO.to_string()),
+            ("update_todo_tool", success("Todo updated successfully")),
+            ("mark_todo_complete_tool", success("Todo marked as complete")),
+            ("delete_todo_tool", success("Todo deleted")),
+        ] {
+            fake.mount(tool, ResponseTemplate::new(200).set_body_raw(body, "application/json"), None).await;
+        }
+        fake
+    }
+
+    pub fn url(&self) -> String {
+        self.server.uri()
+    }
+
+    /// Client settings pointing at this server, without retries
+    pub fn config(&self) -> McpClientConfig {
+        McpClientConfig { max_retries: 0, ..McpClientConfig::new(self.url()) }
+    }
+
+    pub fn client(&self) -> Result<McpClient> {
+        McpClient::new(self.config())
+    }
+
+    /// Answer `tool` with `body`. The first response set up for a tool wins
+    /// over later ones and over the fixtures.
+    pub async fn respond(&self, tool: &str, body: impl Into<String>) {
+        let response = ResponseTemplate::new(200).set_body_raw(body.into(), "application/json");
+        self.mount(tool, response, Some(EXPLICIT)).await;
+    }
+
+    /// Answer `tool` with `value` serialized as JSON
+    pub async fn respond_json(&self, tool: &str, value: &Value) {
+        self.respond(tool, value.to_string()).await;
+    }
+
+    /// Answer `tool` with an HTTP `status` and the recorded error body; a 5xx
+    /// makes the client treat the server as unavailable
+    pub async fn fail(&self, tool: &str, status: u16) {
+        let response = ResponseTemplate::new(status).set_body_raw(ERROR, "application/json");
+        self.mount(tool, response, Some(EXPLICIT)).await;
+    }
+
+    /// JSON bodies the server received for `tool`, oldest first
+    pub async fn requests(&self, tool: &str) -> Vec<Value> {
+        let endpoint = format!("/tools/{}", tool);
+        self.server.received_requests().await.unwrap_or_default()
+            .into_iter()
+            .filter(|request| request.url.path() == endpoint)
+            .filter_map(|request| serde_json::from_slice(&request.body).ok())
+            .collect()
+    }
+
+    async fn mount(&self, tool: &str, response: ResponseTemplate, priority: Option<u8>) {
+        let mut mock = Mock::given(method("POST")).and(path(format!("/tools/{}", tool))).respond_with(response);
+        if let Some(priority) = priority {
+            mock = mock.with_priority(priority);
+        }
+        mock.mount(&self.server).await;
+    }
+}
+
+fn success(message: &str) -> String {
+    serde_json::json!({ "success": true, "message": message }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcp::is_unavailable;
+
+    #[tokio::test]
+    async fn test_fixtures_and_overrides() -> Result<()> {
+        let fake = McpFake::with_fixtures().await;
+        fake.fail("delete_todo_tool", 503).await;
+        let client = fake.client()?;
+
+        let body: Value = serde_json::from_str(&client.call_tool("add_todo_tool", &serde_json::json!({ "description": "x" })).await?)?;
+        assert_eq!(body["data"]["project"], "madness_interactive");
+        assert!(is_unavailable(&client.call_tool("delete_todo_tool", &serde_json::json!({})).await.unwrap_err()));
+
+        assert_eq!(fake.requests("add_todo_tool").await, vec![serde_json::json!({ "description": "x" })]);
+        Ok(())
+    }
+}
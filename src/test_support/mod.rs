@@ -0,0 +1,10 @@
+//! In-process stand-ins for the services the swarm talks to, so tests run
+//! hermetically instead of skipping when mosquitto, Omnispindle or MongoDB is
+//! down. Always built for this crate's tests; other crates get it with the
+//! `test-support` feature.
+
+pub mod mcp;
+
+pub use crate::mqtt::MemoryBroker;
+pub use crate::state::MemoryStateStore;
+pub use mcp::McpFake;
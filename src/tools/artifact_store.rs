@@ -0,0 +1,248 @@
+use std::env;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+
+/// Presigned S3 URLs are valid for at most a week
+pub const DEFAULT_URL_EXPIRY_SECS: u32 = 7 * 24 * 60 * 60;
+
+/// Where generated files (plots, annotated screenshots, project archives) are
+/// kept. `put` returns a URL that can be handed back in responses.
+#[async_trait]
+pub trait ArtifactStore: Send + Sync {
+    /// Stores `bytes` under `key` (a relative `/`-separated path) and returns its URL
+    async fn put(&self, key: &str, content_type: &str, bytes: Vec<u8>) -> Result<String>;
+
+    /// Stores a local file, with the content type taken from its extension
+    async fn put_file(&self, key: &str, path: &Path) -> Result<String> {
+        let bytes = tokio::fs::read(path).await
+            .map_err(|e| anyhow!("Failed to read artifact {}: {}", path.display(), e))?;
+        self.put(key, content_type_for(path), bytes).await
+    }
+}
+
+pub fn content_type_for(path: &Path) -> &'static str {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+    match name.rsplit('.').next().unwrap_or_default() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "html" => "text/html",
+        "json" => "application/json",
+        "txt" | "log" => "text/plain",
+        "gz" | "tgz" => "application/gzip",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Builds a key from path components, replacing anything that isn't safe in a
+/// file name or object key
+pub fn artifact_key(parts: &[&str]) -> String {
+    parts.iter()
+        .map(|part| part.chars()
+            .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+            .collect::<String>())
+        .map(|part| if part.chars().all(|c| c == '.') { "_".to_string() } else { part })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn validate_key(key: &str) -> Result<()> {
+    let path = Path::new(key);
+    if key.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(anyhow!("Invalid artifact key: {}", key));
+    }
+    Ok(())
+}
+
+/// Keeps artifacts in a local directory. URLs are `file://` paths unless a
+/// `base_url` the directory is served from is set.
+#[derive(Debug, Clone)]
+pub struct LocalArtifactStore {
+    dir: PathBuf,
+    base_url: Option<String>,
+}
+
+impl LocalArtifactStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into(), base_url: None }
+    }
+
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    /// Shares `SWARM_ARTIFACT_DIR` with the tool output archive; `ARTIFACT_BASE_URL`
+    /// is where that directory is served, e.g. `http://localhost:3000/artifacts`
+    pub fn from_env() -> Self {
+        let dir = env::var("SWARM_ARTIFACT_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| env::temp_dir().join("swarmonomicon").join("artifacts"));
+        let store = Self::new(dir);
+        match env::var("ARTIFACT_BASE_URL") {
+            Ok(base_url) if !base_url.is_empty() => store.with_base_url(base_url),
+            _ => store,
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+#[async_trait]
+impl ArtifactStore for LocalArtifactStore {
+    async fn put(&self, key: &str, _content_type: &str, bytes: Vec<u8>) -> Result<String> {
+        validate_key(key)?;
+        let path = self.dir.join(key);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await
+                .map_err(|e| anyhow!("Failed to create artifact directory: {}", e))?;
+        }
+        tokio::fs::write(&path, bytes).await
+            .map_err(|e| anyhow!("Failed to write artifact {}: {}", path.display(), e))?;
+
+        Ok(match &self.base_url {
+            Some(base_url) => format!("{}/{}", base_url, key),
+            None => format!("file://{}", path.display()),
+        })
+    }
+}
+
+/// Keeps artifacts in an S3-compatible bucket (AWS, MinIO, R2, ...). URLs are
+/// presigned unless the bucket is published at `public_url`.
+#[cfg(feature = "s3")]
+pub struct S3ArtifactStore {
+    bucket: s3::Bucket,
+    prefix: String,
+    public_url: Option<String>,
+    url_expiry_secs: u32,
+}
+
+#[cfg(feature = "s3")]
+impl S3ArtifactStore {
+    pub fn new(bucket: s3::Bucket) -> Self {
+        Self { bucket, prefix: String::new(), public_url: None, url_expiry_secs: DEFAULT_URL_EXPIRY_SECS }
+    }
+
+    /// Keys are stored under `prefix/`
+    pub fn with_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into().trim_matches('/').to_string();
+        self
+    }
+
+    pub fn with_public_url(mut self, public_url: impl Into<String>) -> Self {
+        self.public_url = Some(public_url.into().trim_end_matches('/').to_string());
+        self
+    }
+
+    pub fn with_url_expiry_secs(mut self, secs: u32) -> Self {
+        self.url_expiry_secs = secs;
+        self
+    }
+
+    /// Reads `S3_BUCKET`, `S3_REGION`, `S3_ENDPOINT`, `S3_ACCESS_KEY_ID`,
+    /// `S3_SECRET_ACCESS_KEY`, `S3_PREFIX`, `S3_PUBLIC_URL` and `ARTIFACT_URL_EXPIRY_SECS`
+    pub fn from_env() -> Result<Self> {
+        use s3::creds::Credentials;
+        use s3::{Bucket, Region};
+
+        let name = env::var("S3_BUCKET").map_err(|_| anyhow!("S3_BUCKET is not set"))?;
+        let region_name = env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+        let region = match env::var("S3_ENDPOINT") {
+            Ok(endpoint) => Region::Custom { region: region_name, endpoint },
+            Err(_) => region_name.parse().map_err(|e| anyhow!("Invalid S3_REGION: {}", e))?,
+        };
+        let access_key = env::var("S3_ACCESS_KEY_ID").or_else(|_| env::var("AWS_ACCESS_KEY_ID")).ok();
+        let secret_key = env::var("S3_SECRET_ACCESS_KEY").or_else(|_| env::var("AWS_SECRET_ACCESS_KEY")).ok();
+        let credentials = Credentials::new(access_key.as_deref(), secret_key.as_deref(), None, None, None)
+            .map_err(|e| anyhow!("Invalid S3 credentials: {}", e))?;
+        // Path-style addressing works with MinIO and other self-hosted endpoints
+        let bucket = Bucket::new(&name, region, credentials)
+            .map_err(|e| anyhow!("Invalid S3 bucket {}: {}", name, e))?
+            .with_path_style();
+
+        let mut store = Self::new(bucket);
+        if let Ok(prefix) = env::var("S3_PREFIX") {
+            store = store.with_prefix(prefix);
+        }
+        if let Ok(public_url) = env::var("S3_PUBLIC_URL") {
+            store = store.with_public_url(public_url);
+        }
+        if let Some(secs) = env::var("ARTIFACT_URL_EXPIRY_SECS").ok().and_then(|v| v.parse().ok()) {
+            store = store.with_url_expiry_secs(secs);
+        }
+        Ok(store)
+    }
+
+    fn object_key(&self, key: &str) -> String {
+        if self.prefix.is_empty() { key.to_string() } else { format!("{}/{}", self.prefix, key) }
+    }
+}
+
+#[cfg(feature = "s3")]
+#[async_trait]
+impl ArtifactStore for S3ArtifactStore {
+    async fn put(&self, key: &str, content_type: &str, bytes: Vec<u8>) -> Result<String> {
+        validate_key(key)?;
+        let object_key = self.object_key(key);
+        let response = self.bucket.put_object_with_content_type(&object_key, &bytes, content_type).await
+            .map_err(|e| anyhow!("Failed to upload artifact {}: {}", object_key, e))?;
+        if !(200..300).contains(&response.status_code()) {
+            return Err(anyhow!("Failed to upload artifact {}: HTTP {}", object_key, response.status_code()));
+        }
+
+        match &self.public_url {
+            Some(public_url) => Ok(format!("{}/{}", public_url, object_key)),
+            None => self.bucket.presign_get(&object_key, self.url_expiry_secs, None)
+                .map_err(|e| anyhow!("Failed to presign {}: {}", object_key, e)),
+        }
+    }
+}
+
+/// The store selected by `ARTIFACT_STORE` (`local` or `s3`), or `None` when
+/// unset, in which case artifacts stay where each tool writes them
+pub fn artifact_store_from_env() -> Result<Option<Arc<dyn ArtifactStore>>> {
+    match env::var("ARTIFACT_STORE").unwrap_or_default().to_lowercase().as_str() {
+        "" | "none" => Ok(None),
+        "local" => Ok(Some(Arc::new(LocalArtifactStore::from_env()))),
+        #[cfg(feature = "s3")]
+        "s3" => Ok(Some(Arc::new(S3ArtifactStore::from_env()?))),
+        #[cfg(not(feature = "s3"))]
+        "s3" => Err(anyhow!("ARTIFACT_STORE=s3 needs the `s3` feature")),
+        other => Err(anyhow!("Unknown ARTIFACT_STORE '{}', expected local or s3", other)),
+    }
+}
+
+/// [`artifact_store_from_env`], logging and ignoring a bad configuration
+pub fn artifact_store_or_none() -> Option<Arc<dyn ArtifactStore>> {
+    artifact_store_from_env().unwrap_or_else(|e| {
+        tracing::warn!("Artifact store unavailable: {}", e);
+        None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_local_store_urls_and_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = LocalArtifactStore::new(dir.path());
+
+        let url = store.put("rl/run-1/rewards.png", "image/png", b"png".to_vec()).await.unwrap();
+        assert_eq!(url, format!("file://{}", dir.path().join("rl/run-1/rewards.png").display()));
+        assert_eq!(std::fs::read(dir.path().join("rl/run-1/rewards.png")).unwrap(), b"png");
+
+        let served = store.clone().with_base_url("http://localhost:3000/artifacts/");
+        let url = served.put("report.html", "text/html", Vec::new()).await.unwrap();
+        assert_eq!(url, "http://localhost:3000/artifacts/report.html");
+
+        assert!(store.put("../escape.txt", "text/plain", Vec::new()).await.is_err());
+        assert!(store.put("/etc/passwd", "text/plain", Vec::new()).await.is_err());
+        assert_eq!(artifact_key(&["projects", "..", "my app.tar.gz"]), "projects/_/my_app.tar.gz");
+        assert_eq!(content_type_for(Path::new("app.tar.gz")), "application/gzip");
+    }
+}
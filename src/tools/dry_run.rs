@@ -0,0 +1,51 @@
+//! Dry runs: tools that change something describe what they would do instead
+//! of doing it. A run is dry when the message or task asked for it, or for
+//! everything when `DRY_RUN` is set.
+
+use std::env;
+use std::fmt::Display;
+use std::future::Future;
+
+tokio::task_local! {
+    static DRY_RUN: bool;
+}
+
+/// Whether `DRY_RUN` is set, making every run dry
+pub fn globally_enabled() -> bool {
+    env::var("DRY_RUN").map(|v| v == "true" || v == "1").unwrap_or(false)
+}
+
+/// Run `fut` as a dry run
+pub async fn with_dry_run<F: Future>(fut: F) -> F::Output {
+    DRY_RUN.scope(true, fut).await
+}
+
+/// Whether the work being done now should only be described
+pub fn is_active() -> bool {
+    DRY_RUN.try_with(|dry_run| *dry_run).unwrap_or(false) || globally_enabled()
+}
+
+/// What a dry-run call returns in place of its output: one line per step
+pub fn plan<I, S>(steps: I) -> String
+where
+    I: IntoIterator<Item = S>,
+    S: Display,
+{
+    let mut plan = "Dry run, nothing was changed. Would:".to_string();
+    for step in steps {
+        plan.push_str(&format!("\n- {}", step));
+    }
+    plan
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_scope() {
+        assert!(!DRY_RUN.try_with(|dry_run| *dry_run).unwrap_or(false));
+        assert!(with_dry_run(async { is_active() }).await);
+        assert_eq!(plan(["run `git add .`"]), "Dry run, nothing was changed. Would:\n- run `git add .`");
+    }
+}
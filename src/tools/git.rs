@@ -1,7 +1,7 @@
 use std::collections::HashMap;
-use std::process::Command;
+use tokio::process::Command;
 use async_trait::async_trait;
-use crate::tools::ToolExecutor;
+use crate::tools::{dry_run, ToolExecutor};
 use anyhow::{Result, anyhow};
 
 pub struct GitTool;
@@ -11,11 +11,12 @@ impl GitTool {
         Self
     }
 
-    fn get_git_diff(&self) -> Result<String> {
+    async fn get_git_diff(&self) -> Result<String> {
         // Check staged changes
         let staged = Command::new("git")
             .args(["diff", "--staged"])
             .output()
+            .await
             .map_err(|e| anyhow!("Failed to get staged changes: {}", e))?;
 
         if !staged.stdout.is_empty() {
@@ -26,40 +27,45 @@ impl GitTool {
         let unstaged = Command::new("git")
             .args(["diff"])
             .output()
+            .await
             .map_err(|e| anyhow!("Failed to get unstaged changes: {}", e))?;
 
         Ok(String::from_utf8_lossy(&unstaged.stdout).to_string())
     }
 
-    fn create_branch(&self, branch_name: &str) -> Result<()> {
+    async fn create_branch(&self, branch_name: &str) -> Result<()> {
         Command::new("git")
             .args(["checkout", "-b", branch_name])
             .output()
+            .await
             .map_err(|e| anyhow!("Failed to create branch: {}", e))?;
         Ok(())
     }
 
-    fn stage_changes(&self) -> Result<()> {
+    async fn stage_changes(&self) -> Result<()> {
         Command::new("git")
             .args(["add", "."])
             .output()
+            .await
             .map_err(|e| anyhow!("Failed to stage changes: {}", e))?;
         Ok(())
     }
 
-    fn commit_changes(&self, message: &str) -> Result<()> {
+    async fn commit_changes(&self, message: &str) -> Result<()> {
         Command::new("git")
             .args(["commit", "-m", message])
             .output()
+            .await
             .map_err(|e| anyhow!("Failed to commit changes: {}", e))?;
         Ok(())
     }
 
-    fn merge_branch(&self, target_branch: &str) -> Result<()> {
+    async fn merge_branch(&self, target_branch: &str) -> Result<()> {
         // Get current branch
         let current = Command::new("git")
             .args(["rev-parse", "--abbrev-ref", "HEAD"])
             .output()
+            .await
             .map_err(|e| anyhow!("Failed to get current branch: {}", e))?;
         let current_branch = String::from_utf8_lossy(&current.stdout).trim().to_string();
 
@@ -67,12 +73,14 @@ impl GitTool {
         Command::new("git")
             .args(["checkout", target_branch])
             .output()
+            .await
             .map_err(|e| anyhow!("Failed to checkout target branch: {}", e))?;
 
         // Merge the feature branch
         Command::new("git")
             .args(["merge", &current_branch])
             .output()
+            .await
             .map_err(|e| anyhow!("Failed to merge branch: {}", e))?;
 
         Ok(())
@@ -86,29 +94,51 @@ impl ToolExecutor for GitTool {
 
         match command.as_str() {
             "diff" => {
-                let diff = self.get_git_diff()?;
+                let diff = self.get_git_diff().await?;
                 Ok(diff)
             }
             "branch" => {
                 let name = params.get("name").ok_or_else(|| anyhow!("Missing branch name"))?;
-                self.create_branch(name)?;
+                self.create_branch(name).await?;
                 Ok(format!("Created and switched to branch: {}", name))
             }
             "stage" => {
-                self.stage_changes()?;
+                self.stage_changes().await?;
                 Ok("Changes staged successfully".to_string())
             }
             "commit" => {
                 let message = params.get("message").ok_or_else(|| anyhow!("Missing commit message"))?;
-                self.commit_changes(message)?;
+                self.commit_changes(message).await?;
                 Ok(format!("Changes committed with message: {}", message))
             }
             "merge" => {
                 let target = params.get("target").ok_or_else(|| anyhow!("Missing target branch"))?;
-                self.merge_branch(target)?;
+                self.merge_branch(target).await?;
                 Ok(format!("Merged current branch into: {}", target))
             }
             _ => Err(anyhow!("Unknown git command")),
         }
     }
+
+    fn dry_run(&self, params: &HashMap<String, String>) -> Result<Option<String>> {
+        let command = params.get("command").ok_or_else(|| anyhow!("Missing command parameter"))?;
+        let steps = match command.as_str() {
+            "diff" => return Ok(None),
+            "branch" => {
+                let name = params.get("name").ok_or_else(|| anyhow!("Missing branch name"))?;
+                vec![format!("run `git checkout -b {}`", name)]
+            }
+            "stage" => vec!["run `git add .`".to_string()],
+            "commit" => {
+                let message = params.get("message").ok_or_else(|| anyhow!("Missing commit message"))?;
+                vec![format!("run `git commit -m {:?}`", message)]
+            }
+            "merge" => {
+                let target = params.get("target").ok_or_else(|| anyhow!("Missing target branch"))?;
+                vec![format!("run `git checkout {}`", target), "merge the current branch into it with `git merge`".to_string()]
+            }
+            _ => return Err(anyhow!("Unknown git command")),
+        };
+        Ok(Some(dry_run::plan(steps)))
+    }
 }
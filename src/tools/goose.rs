@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use std::process::Command;
 use async_trait::async_trait;
-use crate::tools::ToolExecutor;
+use crate::tools::{dry_run, ToolExecutor};
 use anyhow::{Result, anyhow};
 use tokio::process::Command as TokioCommand;
 use std::fs::{self, File};
@@ -91,6 +91,23 @@ impl ToolExecutor for GooseTool {
             _ => Err(anyhow!("Unknown goose action. Use 'exec' or 'edit'")),
         }
     }
+
+    fn dry_run(&self, params: &HashMap<String, String>) -> Result<Option<String>> {
+        let action = params.get("action").ok_or_else(|| anyhow!("Missing action parameter"))?;
+        let step = match action.as_str() {
+            "exec" => {
+                let command = params.get("command").ok_or_else(|| anyhow!("Missing command parameter"))?;
+                format!("run `goose {}`", command)
+            }
+            "edit" => {
+                let file_path = params.get("file_path").ok_or_else(|| anyhow!("Missing file_path parameter"))?;
+                let instructions = params.get("instructions").ok_or_else(|| anyhow!("Missing instructions parameter"))?;
+                format!("have goose rewrite {} to: {}", file_path, instructions)
+            }
+            _ => return Err(anyhow!("Unknown goose action. Use 'exec' or 'edit'")),
+        };
+        Ok(Some(dry_run::plan([step])))
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,301 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+use tokio::sync::RwLock;
+use crate::ai::{cosine_similarity, embeddings_from_env, AiProvider, DefaultAiClient, EmbeddingsProvider};
+use crate::tools::ToolExecutor;
+
+/// Longest chunk of a document embedded on its own, in characters
+const MAX_CHUNK_CHARS: usize = 1200;
+/// Chunks embedded per request to the provider
+const EMBED_BATCH: usize = 32;
+/// Passages returned by `search` when no limit is given
+pub const DEFAULT_SEARCH_LIMIT: usize = 5;
+const DOC_EXTENSIONS: &[&str] = &["md", "markdown", "txt"];
+const SKIP_DIRS: &[&str] = &["target", "node_modules", ".git"];
+
+/// One embedded piece of a document
+#[derive(Debug, Clone)]
+pub struct Passage {
+    pub path: PathBuf,
+    /// Nearest heading above the passage, if any
+    pub heading: Option<String>,
+    pub text: String,
+    embedding: Vec<f32>,
+}
+
+impl Passage {
+    /// `path#heading`, for citing the passage
+    pub fn source(&self) -> String {
+        match &self.heading {
+            Some(heading) => format!("{}#{}", self.path.display(), heading),
+            None => self.path.display().to_string(),
+        }
+    }
+}
+
+/// Split markdown into chunks that follow its headings, breaking long
+/// sections on paragraph boundaries
+pub fn chunk_markdown(text: &str) -> Vec<(Option<String>, String)> {
+    let mut chunks = Vec::new();
+    let mut heading: Option<String> = None;
+    let mut current = String::new();
+
+    fn flush(chunks: &mut Vec<(Option<String>, String)>, heading: &Option<String>, current: &mut String) {
+        let text = current.trim();
+        if !text.is_empty() {
+            chunks.push((heading.clone(), text.to_string()));
+        }
+        current.clear();
+    }
+
+    for paragraph in text.split("\n\n") {
+        let trimmed = paragraph.trim_start();
+        if trimmed.starts_with('#') {
+            flush(&mut chunks, &heading, &mut current);
+            let (title, body) = trimmed.split_once('\n').unwrap_or((trimmed, ""));
+            heading = Some(title.trim_start_matches('#').trim().to_string());
+            current.push_str(body);
+            continue;
+        }
+        if current.chars().count() + paragraph.chars().count() > MAX_CHUNK_CHARS {
+            flush(&mut chunks, &heading, &mut current);
+        }
+        if !current.is_empty() {
+            current.push_str("\n\n");
+        }
+        current.push_str(paragraph);
+    }
+    flush(&mut chunks, &heading, &mut current);
+    chunks
+}
+
+/// Documentation files under `path` (or `path` itself), skipping build and VCS directories
+fn doc_files(path: &Path, files: &mut Vec<PathBuf>) {
+    if path.is_file() {
+        if path.extension().and_then(|e| e.to_str()).map_or(false, |e| DOC_EXTENSIONS.contains(&e)) {
+            files.push(path.to_path_buf());
+        }
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(path) else { return };
+    let mut entries: Vec<_> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+    entries.sort();
+    for entry in entries {
+        let skipped = entry.file_name().and_then(|n| n.to_str()).map_or(false, |n| SKIP_DIRS.contains(&n));
+        if !skipped {
+            doc_files(&entry, files);
+        }
+    }
+}
+
+/// Embedded passages from the configured documentation, searched by similarity.
+/// Indexes lazily on first use.
+pub struct KnowledgeBase {
+    sources: Vec<PathBuf>,
+    embedder: Arc<dyn EmbeddingsProvider>,
+    passages: RwLock<Option<Vec<Passage>>>,
+}
+
+static SHARED: OnceLock<Option<Arc<KnowledgeBase>>> = OnceLock::new();
+
+impl KnowledgeBase {
+    pub fn new(sources: Vec<PathBuf>, embedder: Arc<dyn EmbeddingsProvider>) -> Self {
+        Self { sources, embedder, passages: RwLock::new(None) }
+    }
+
+    /// Directories and files from `KNOWLEDGE_DIRS` (comma-separated), or `None` when unset
+    pub fn from_env() -> Option<Self> {
+        let sources: Vec<PathBuf> = env::var("KNOWLEDGE_DIRS").ok()?
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(PathBuf::from)
+            .collect();
+        if sources.is_empty() {
+            return None;
+        }
+        Some(Self::new(sources, embeddings_from_env()))
+    }
+
+    /// Process-wide knowledge base, so agents share one index
+    pub fn shared() -> Option<Arc<Self>> {
+        SHARED.get_or_init(|| Self::from_env().map(Arc::new)).clone()
+    }
+
+    pub fn sources(&self) -> &[PathBuf] {
+        &self.sources
+    }
+
+    /// Re-read and re-embed every document, returning how many passages were indexed
+    pub async fn reindex(&self) -> Result<usize> {
+        let mut files = Vec::new();
+        for source in &self.sources {
+            doc_files(source, &mut files);
+        }
+
+        let mut pending = Vec::new();
+        for path in files {
+            let text = match tokio::fs::read_to_string(&path).await {
+                Ok(text) => text,
+                Err(e) => {
+                    tracing::warn!("Skipping {}: {}", path.display(), e);
+                    continue;
+                }
+            };
+            for (heading, chunk) in chunk_markdown(&text) {
+                pending.push((path.clone(), heading, chunk));
+            }
+        }
+
+        let mut passages = Vec::with_capacity(pending.len());
+        for batch in pending.chunks(EMBED_BATCH) {
+            let texts: Vec<String> = batch.iter().map(|(_, _, text)| text.clone()).collect();
+            let embeddings = self.embedder.embed(&texts).await?;
+            for ((path, heading, text), embedding) in batch.iter().cloned().zip(embeddings) {
+                passages.push(Passage { path, heading, text, embedding });
+            }
+        }
+
+        let count = passages.len();
+        tracing::info!("Indexed {} passages from {} source(s)", count, self.sources.len());
+        *self.passages.write().await = Some(passages);
+        Ok(count)
+    }
+
+    /// The `limit` passages most similar to `query`, best first, with their scores
+    pub async fn search(&self, query: &str, limit: usize) -> Result<Vec<(f32, Passage)>> {
+        if self.passages.read().await.is_none() {
+            self.reindex().await?;
+        }
+        let query = self.embedder.embed(&[query.to_string()]).await?
+            .pop()
+            .ok_or_else(|| anyhow!("No embedding returned for the query"))?;
+
+        let passages = self.passages.read().await;
+        let mut scored: Vec<(f32, Passage)> = passages.iter().flatten()
+            .map(|passage| (cosine_similarity(&query, &passage.embedding), passage.clone()))
+            .filter(|(score, _)| *score > 0.0)
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(limit);
+        Ok(scored)
+    }
+
+    /// Top passages formatted for a system prompt, or `None` when nothing matches
+    pub async fn context_for(&self, query: &str, limit: usize) -> Option<String> {
+        let results = match self.search(query, limit).await {
+            Ok(results) => results,
+            Err(e) => {
+                tracing::warn!("Knowledge search failed: {}", e);
+                return None;
+            }
+        };
+        if results.is_empty() {
+            return None;
+        }
+        Some(results.iter()
+            .map(|(_, passage)| format!("[{}]\n{}", passage.source(), passage.text))
+            .collect::<Vec<_>>()
+            .join("\n\n"))
+    }
+}
+
+/// Searches and answers questions from project documentation.
+///
+/// Commands: `search` (`query`, optional `limit`), `answer` (`question`) and `reindex`.
+pub struct KnowledgeTool {
+    knowledge: Arc<KnowledgeBase>,
+    ai_client: Arc<dyn AiProvider>,
+}
+
+impl KnowledgeTool {
+    pub fn new(knowledge: Arc<KnowledgeBase>) -> Self {
+        Self { knowledge, ai_client: Arc::new(DefaultAiClient::new()) }
+    }
+
+    pub fn with_ai_client<T: AiProvider + 'static>(mut self, client: T) -> Self {
+        self.ai_client = Arc::new(client);
+        self
+    }
+
+    async fn answer(&self, question: &str) -> Result<String> {
+        let context = self.knowledge.context_for(question, DEFAULT_SEARCH_LIMIT).await
+            .ok_or_else(|| anyhow!("Nothing in the indexed documentation matches that question"))?;
+        let system_prompt = format!(
+            "Answer the question using only the documentation excerpts below. \
+            Cite the [source] of each fact you use. If the excerpts don't answer it, say so.\n\n{}",
+            context
+        );
+        let messages = vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), question.to_string()),
+        ])];
+        self.ai_client.chat(&system_prompt, messages).await
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for KnowledgeTool {
+    async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+        let command = params.get("command").ok_or_else(|| anyhow!("Missing command parameter"))?;
+        match command.as_str() {
+            "search" => {
+                let query = params.get("query").ok_or_else(|| anyhow!("Missing query parameter"))?;
+                let limit = params.get("limit").and_then(|l| l.parse().ok()).unwrap_or(DEFAULT_SEARCH_LIMIT);
+                let results = self.knowledge.search(query, limit).await?;
+                if results.is_empty() {
+                    return Ok("No matching documentation found".to_string());
+                }
+                Ok(results.iter()
+                    .map(|(score, passage)| format!("[{:.2}] {}\n{}", score, passage.source(), passage.text))
+                    .collect::<Vec<_>>()
+                    .join("\n\n"))
+            }
+            "answer" => {
+                let question = params.get("question").ok_or_else(|| anyhow!("Missing question parameter"))?;
+                self.answer(question).await
+            }
+            "reindex" => Ok(format!("Indexed {} passages", self.knowledge.reindex().await?)),
+            _ => Err(anyhow!("Unknown knowledge command. Use 'search', 'answer' or 'reindex'")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::HashedEmbeddings;
+
+    #[test]
+    fn test_chunk_markdown() {
+        let chunks = chunk_markdown("Intro text\n\n# Setup\nInstall mosquitto.\n\nThen run it.\n\n## Usage\nPublish to mcp/todo.");
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[0], (None, "Intro text".to_string()));
+        assert_eq!(chunks[1].0.as_deref(), Some("Setup"));
+        assert!(chunks[1].1.contains("Then run it."));
+        assert_eq!(chunks[2].0.as_deref(), Some("Usage"));
+    }
+
+    #[tokio::test]
+    async fn test_search_finds_relevant_passage() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("mqtt.md"), "# Broker\nThe mosquitto broker listens on port 1883.").unwrap();
+        std::fs::write(dir.path().join("haiku.md"), "# Poems\nAutumn leaves drift over the quiet pond.").unwrap();
+        std::fs::create_dir(dir.path().join("target")).unwrap();
+        std::fs::write(dir.path().join("target").join("skip.md"), "mosquitto broker port").unwrap();
+
+        let knowledge = Arc::new(KnowledgeBase::new(vec![dir.path().to_path_buf()], Arc::new(HashedEmbeddings)));
+        let tool = KnowledgeTool::new(knowledge.clone());
+        let output = tool.execute(HashMap::from([
+            ("command".to_string(), "search".to_string()),
+            ("query".to_string(), "which port does the mosquitto broker use".to_string()),
+            ("limit".to_string(), "1".to_string()),
+        ])).await.unwrap();
+        assert!(output.contains("mqtt.md#Broker"), "{}", output);
+        assert_eq!(knowledge.reindex().await.unwrap(), 2);
+    }
+}
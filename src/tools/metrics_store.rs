@@ -0,0 +1,164 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use mongodb::bson::{doc, Document};
+use mongodb::options::{FindOptions, IndexOptions};
+use mongodb::{Collection, IndexModel};
+use crate::clients::Clients;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Snapshots returned by a history query when no limit is given
+pub const DEFAULT_HISTORY_LIMIT: i64 = 500;
+/// Most snapshots a single history query returns
+pub const MAX_HISTORY_LIMIT: i64 = 5000;
+
+/// One periodic copy of a worker's metrics JSON
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MetricsSnapshot {
+    /// Binary that reported it, e.g. `todo_worker`
+    pub worker: String,
+    pub instance_id: String,
+    #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub timestamp: DateTime<Utc>,
+    pub metrics: Value,
+}
+
+impl MetricsSnapshot {
+    pub fn new(worker: impl Into<String>, instance_id: impl Into<String>, metrics: Value) -> Self {
+        Self {
+            worker: worker.into(),
+            instance_id: instance_id.into(),
+            timestamp: Utc::now(),
+            metrics,
+        }
+    }
+}
+
+/// Filters for [`WorkerMetricsStore::history`]; every field is optional
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct MetricsHistoryQuery {
+    pub worker: Option<String>,
+    pub instance_id: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<i64>,
+}
+
+impl MetricsHistoryQuery {
+    fn filter(&self) -> Document {
+        let mut filter = Document::new();
+        if let Some(worker) = &self.worker {
+            filter.insert("worker", worker.as_str());
+        }
+        if let Some(instance_id) = &self.instance_id {
+            filter.insert("instance_id", instance_id.as_str());
+        }
+        let mut range = Document::new();
+        if let Some(since) = self.since {
+            range.insert("$gte", mongodb::bson::DateTime::from_chrono(since));
+        }
+        if let Some(until) = self.until {
+            range.insert("$lte", mongodb::bson::DateTime::from_chrono(until));
+        }
+        if !range.is_empty() {
+            filter.insert("timestamp", range);
+        }
+        filter
+    }
+
+    fn limit(&self) -> i64 {
+        self.limit.unwrap_or(DEFAULT_HISTORY_LIMIT).clamp(1, MAX_HISTORY_LIMIT)
+    }
+}
+
+/// Keeps worker metrics across restarts in the `worker_metrics` collection,
+/// for trend graphs
+#[derive(Clone)]
+pub struct WorkerMetricsStore {
+    collection: Collection<MetricsSnapshot>,
+}
+
+impl WorkerMetricsStore {
+    pub fn new(collection: Collection<MetricsSnapshot>) -> Self {
+        Self { collection }
+    }
+
+    /// Connects using `RTK_MONGO_URI`/`RTK_MONGO_DB` and expires snapshots
+    /// after `WORKER_METRICS_RETENTION_DAYS` (default 30; 0 keeps them forever)
+    pub async fn from_env() -> Result<Self> {
+        let uri = std::env::var("RTK_MONGO_URI").map_err(|_| anyhow!("RTK_MONGO_URI is not set"))?;
+        let db_name = std::env::var("RTK_MONGO_DB").unwrap_or_else(|_| "swarmonomicon".to_string());
+        let client = Clients::shared().mongo(&uri)?;
+        let store = Self::new(client.database(&db_name).collection("worker_metrics"));
+
+        let retention_days: u64 = std::env::var("WORKER_METRICS_RETENTION_DAYS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(30);
+        if let Err(e) = store.ensure_indexes(retention_days).await {
+            tracing::warn!("Failed to create worker_metrics indexes: {}", e);
+        }
+        Ok(store)
+    }
+
+    async fn ensure_indexes(&self, retention_days: u64) -> Result<()> {
+        let lookup = IndexModel::builder()
+            .keys(doc! { "worker": 1, "instance_id": 1, "timestamp": 1 })
+            .build();
+        self.collection.create_index(lookup, None).await?;
+
+        if retention_days > 0 {
+            let expiry = IndexModel::builder()
+                .keys(doc! { "timestamp": 1 })
+                .options(IndexOptions::builder()
+                    .expire_after(std::time::Duration::from_secs(retention_days * 24 * 60 * 60))
+                    .build())
+                .build();
+            self.collection.create_index(expiry, None).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn record(&self, snapshot: &MetricsSnapshot) -> Result<()> {
+        self.collection.insert_one(snapshot, None).await?;
+        Ok(())
+    }
+
+    /// Matching snapshots, oldest first. With more matches than the limit, the
+    /// most recent ones are returned.
+    pub async fn history(&self, query: &MetricsHistoryQuery) -> Result<Vec<MetricsSnapshot>> {
+        let options = FindOptions::builder()
+            .sort(doc! { "timestamp": -1 })
+            .limit(query.limit())
+            .build();
+        let mut snapshots: Vec<MetricsSnapshot> = self.collection.find(query.filter(), options).await?.try_collect().await?;
+        snapshots.reverse();
+        Ok(snapshots)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_filter() {
+        let since = "2026-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap();
+        let query = MetricsHistoryQuery {
+            worker: Some("todo_worker".to_string()),
+            since: Some(since),
+            ..Default::default()
+        };
+        let filter = query.filter();
+        assert_eq!(filter.get_str("worker").unwrap(), "todo_worker");
+        assert!(filter.get("instance_id").is_none());
+        let range = filter.get_document("timestamp").unwrap();
+        assert_eq!(range.get_datetime("$gte").unwrap().to_chrono(), since);
+        assert!(range.get("$lte").is_none());
+
+        assert_eq!(MetricsHistoryQuery::default().limit(), DEFAULT_HISTORY_LIMIT);
+        assert_eq!(MetricsHistoryQuery { limit: Some(1_000_000), ..Default::default() }.limit(), MAX_HISTORY_LIMIT);
+        assert!(MetricsHistoryQuery::default().filter().is_empty());
+    }
+}
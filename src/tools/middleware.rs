@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::env;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use async_trait::async_trait;
+use serde::Serialize;
+use serde_json::json;
+use crate::access::AccessPolicy;
+use crate::agents::ApprovalInbox;
+use crate::events::{Event, EventBus};
+use super::summarizer::truncate_middle;
+use super::ToolExecutor;
+
+/// Characters of a tool's input or output kept in logs
+pub const DEFAULT_LOG_CHARS: usize = 200;
+/// Delay before the first retry, doubled for each further one
+pub const DEFAULT_RETRY_BACKOFF: Duration = Duration::from_millis(250);
+
+/// One tool invocation on its way through the middleware chain
+#[derive(Debug, Clone)]
+pub struct ToolCall {
+    pub tool: String,
+    pub params: HashMap<String, String>,
+}
+
+/// The rest of the chain, ending at the tool itself. Copy, so middleware can
+/// run it more than once.
+#[derive(Clone, Copy)]
+pub struct Next<'a> {
+    executor: &'a dyn ToolExecutor,
+    rest: &'a [Arc<dyn ToolMiddleware>],
+}
+
+impl<'a> Next<'a> {
+    pub fn new(executor: &'a dyn ToolExecutor, chain: &'a [Arc<dyn ToolMiddleware>]) -> Self {
+        Self { executor, rest: chain }
+    }
+
+    pub async fn run(&self, call: ToolCall) -> Result<String> {
+        match self.rest.split_first() {
+            Some((middleware, rest)) => middleware.handle(call, Next { executor: self.executor, rest }).await,
+            None => {
+                if super::dry_run::is_active() {
+                    if let Some(plan) = self.executor.dry_run(&call.params)? {
+                        return Ok(plan);
+                    }
+                }
+                self.executor.execute(call.params).await
+            }
+        }
+    }
+}
+
+/// Wraps tool calls made through a [`ToolRegistry`](super::ToolRegistry).
+/// Implementations call `next.run(call)` to continue down the chain.
+#[async_trait]
+pub trait ToolMiddleware: Send + Sync {
+    async fn handle(&self, call: ToolCall, next: Next<'_>) -> Result<String>;
+}
+
+/// Errors worth retrying: the other end was unreachable or slow, not wrong
+pub fn is_transient(error: &anyhow::Error) -> bool {
+    if crate::mcp::is_unavailable(error) || error.downcast_ref::<tokio::time::error::Elapsed>().is_some() {
+        return true;
+    }
+    if let Some(e) = error.downcast_ref::<reqwest::Error>() {
+        return e.is_timeout() || e.is_connect();
+    }
+    if let Some(e) = error.downcast_ref::<std::io::Error>() {
+        use std::io::ErrorKind::*;
+        return matches!(e.kind(), TimedOut | ConnectionRefused | ConnectionReset | ConnectionAborted | Interrupted);
+    }
+    false
+}
+
+/// Counters for one tool
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct ToolStats {
+    pub calls: u64,
+    pub failures: u64,
+    pub retries: u64,
+    pub total_ms: f64,
+    pub max_ms: f64,
+    pub last_error: Option<String>,
+}
+
+/// Per-tool call counts and timings. Clones share the same counters.
+#[derive(Debug, Clone, Default)]
+pub struct ToolMetrics {
+    stats: Arc<Mutex<HashMap<String, ToolStats>>>,
+}
+
+impl ToolMetrics {
+    pub fn record(&self, tool: &str, elapsed: Duration, outcome: Result<(), &anyhow::Error>) {
+        let mut stats = self.stats.lock().unwrap();
+        let entry = stats.entry(tool.to_string()).or_default();
+        let millis = elapsed.as_secs_f64() * 1000.0;
+        entry.calls += 1;
+        entry.total_ms += millis;
+        entry.max_ms = entry.max_ms.max(millis);
+        if let Err(e) = outcome {
+            entry.failures += 1;
+            entry.last_error = Some(e.to_string());
+        }
+    }
+
+    pub fn record_retry(&self, tool: &str) {
+        self.stats.lock().unwrap().entry(tool.to_string()).or_default().retries += 1;
+    }
+
+    pub fn get(&self, tool: &str) -> Option<ToolStats> {
+        self.stats.lock().unwrap().get(tool).cloned()
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let stats = self.stats.lock().unwrap();
+        let tools: serde_json::Map<String, serde_json::Value> = stats.iter()
+            .map(|(tool, stats)| {
+                let avg_ms = if stats.calls > 0 { stats.total_ms / stats.calls as f64 } else { 0.0 };
+                (tool.clone(), json!({
+                    "calls": stats.calls,
+                    "failures": stats.failures,
+                    "retries": stats.retries,
+                    "avg_ms": avg_ms,
+                    "max_ms": stats.max_ms,
+                    "last_error": stats.last_error,
+                }))
+            })
+            .collect();
+        serde_json::Value::Object(tools)
+    }
+}
+
+/// Times every call and records it in [`ToolMetrics`]
+pub struct MetricsMiddleware {
+    metrics: ToolMetrics,
+}
+
+impl MetricsMiddleware {
+    pub fn new(metrics: ToolMetrics) -> Self {
+        Self { metrics }
+    }
+}
+
+#[async_trait]
+impl ToolMiddleware for MetricsMiddleware {
+    async fn handle(&self, call: ToolCall, next: Next<'_>) -> Result<String> {
+        let tool = call.tool.clone();
+        let started = Instant::now();
+        let result = next.run(call).await;
+        let elapsed = started.elapsed();
+        self.metrics.record(&tool, elapsed, result.as_ref().map(|_| ()));
+        tracing::info!(tool = %tool, elapsed_ms = elapsed.as_millis() as u64, ok = result.is_ok(), "Tool call finished");
+        result
+    }
+}
+
+/// Publishes an [`Event::ToolExecuted`](crate::events::Event::ToolExecuted) for each call
+pub struct EventMiddleware {
+    bus: EventBus,
+}
+
+impl EventMiddleware {
+    pub fn new(bus: EventBus) -> Self {
+        Self { bus }
+    }
+}
+
+#[async_trait]
+impl ToolMiddleware for EventMiddleware {
+    async fn handle(&self, call: ToolCall, next: Next<'_>) -> Result<String> {
+        let tool = call.tool.clone();
+        let started = Instant::now();
+        let result = next.run(call).await;
+        self.bus.publish(Event::ToolExecuted {
+            tool,
+            success: result.is_ok(),
+            duration_ms: started.elapsed().as_millis() as u64,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        });
+        result
+    }
+}
+
+/// Holds calls that need approval in an [`ApprovalInbox`] until someone
+/// decides, then runs them with the approved, possibly edited, params
+pub struct ApprovalMiddleware {
+    inbox: Arc<ApprovalInbox>,
+}
+
+impl ApprovalMiddleware {
+    pub fn new(inbox: Arc<ApprovalInbox>) -> Self {
+        Self { inbox }
+    }
+}
+
+#[async_trait]
+impl ToolMiddleware for ApprovalMiddleware {
+    async fn handle(&self, call: ToolCall, next: Next<'_>) -> Result<String> {
+        let params = self.inbox.approve_call(&call.tool, call.params).await?;
+        next.run(ToolCall { tool: call.tool, params }).await
+    }
+}
+
+/// Refuses calls the current principal's roles don't allow
+pub struct AccessMiddleware {
+    policy: Arc<AccessPolicy>,
+}
+
+impl AccessMiddleware {
+    pub fn new(policy: Arc<AccessPolicy>) -> Self {
+        Self { policy }
+    }
+}
+
+#[async_trait]
+impl ToolMiddleware for AccessMiddleware {
+    async fn handle(&self, call: ToolCall, next: Next<'_>) -> Result<String> {
+        self.policy.check_tool(&call.tool, &call.params)?;
+        next.run(call).await
+    }
+}
+
+/// Logs each call's input and output, cut down to `max_chars`
+pub struct LoggingMiddleware {
+    max_chars: usize,
+}
+
+impl LoggingMiddleware {
+    pub fn new(max_chars: usize) -> Self {
+        Self { max_chars }
+    }
+
+    /// Reads `TOOL_LOG_CHARS` (default 200)
+    pub fn from_env() -> Self {
+        Self::new(env::var("TOOL_LOG_CHARS").ok().and_then(|s| s.parse().ok()).unwrap_or(DEFAULT_LOG_CHARS))
+    }
+}
+
+#[async_trait]
+impl ToolMiddleware for LoggingMiddleware {
+    async fn handle(&self, call: ToolCall, next: Next<'_>) -> Result<String> {
+        let mut params: Vec<_> = call.params.iter().collect();
+        params.sort();
+        tracing::debug!(tool = %call.tool, "Tool input: {}", truncate_middle(&format!("{:?}", params), self.max_chars));
+
+        let tool = call.tool.clone();
+        let result = next.run(call).await;
+        match &result {
+            Ok(output) => tracing::debug!(tool = %tool, "Tool output: {}", truncate_middle(output, self.max_chars)),
+            Err(e) => tracing::warn!(tool = %tool, "Tool failed: {}", truncate_middle(&e.to_string(), self.max_chars)),
+        }
+        result
+    }
+}
+
+/// Retries transient failures with exponential backoff. Only add it to tools
+/// that are safe to run twice.
+pub struct RetryMiddleware {
+    max_retries: u32,
+    backoff: Duration,
+    metrics: Option<ToolMetrics>,
+}
+
+impl RetryMiddleware {
+    pub fn new(max_retries: u32) -> Self {
+        Self { max_retries, backoff: DEFAULT_RETRY_BACKOFF, metrics: None }
+    }
+
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Count retries in `metrics`
+    pub fn with_metrics(mut self, metrics: ToolMetrics) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+}
+
+#[async_trait]
+impl ToolMiddleware for RetryMiddleware {
+    async fn handle(&self, call: ToolCall, next: Next<'_>) -> Result<String> {
+        let mut attempt = 0;
+        loop {
+            match next.run(call.clone()).await {
+                Err(e) if attempt < self.max_retries && is_transient(&e) => {
+                    let delay = self.backoff * 2u32.saturating_pow(attempt);
+                    attempt += 1;
+                    tracing::warn!(tool = %call.tool, "Transient failure ({}), retry {} of {} in {:?}", e, attempt, self.max_retries, delay);
+                    if let Some(metrics) = &self.metrics {
+                        metrics.record_retry(&call.tool);
+                    }
+                    tokio::time::sleep(delay).await;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Fails with a connection error until it has been called `failures` times
+    struct FlakyTool {
+        calls: AtomicU32,
+        failures: u32,
+    }
+
+    #[async_trait]
+    impl ToolExecutor for FlakyTool {
+        async fn execute(&self, _params: HashMap<String, String>) -> Result<String> {
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                return Err(std::io::Error::new(std::io::ErrorKind::ConnectionRefused, "refused").into());
+            }
+            Ok("done".to_string())
+        }
+    }
+
+    fn call() -> ToolCall {
+        ToolCall { tool: "flaky".to_string(), params: HashMap::new() }
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_failures() {
+        let metrics = ToolMetrics::default();
+        let chain: Vec<Arc<dyn ToolMiddleware>> = vec![
+            Arc::new(MetricsMiddleware::new(metrics.clone())),
+            Arc::new(RetryMiddleware::new(2).with_backoff(Duration::from_millis(1)).with_metrics(metrics.clone())),
+        ];
+        let tool = FlakyTool { calls: AtomicU32::new(0), failures: 2 };
+        assert_eq!(Next::new(&tool, &chain).run(call()).await.unwrap(), "done");
+
+        let stats = metrics.get("flaky").unwrap();
+        assert_eq!((stats.calls, stats.failures, stats.retries), (1, 0, 2));
+
+        // One failure too many gives up and reports it
+        let tool = FlakyTool { calls: AtomicU32::new(0), failures: 3 };
+        assert!(Next::new(&tool, &chain).run(call()).await.is_err());
+        assert_eq!(metrics.get("flaky").unwrap().failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_permanent_failures_are_not_retried() {
+        struct Broken(AtomicU32);
+
+        #[async_trait]
+        impl ToolExecutor for Broken {
+            async fn execute(&self, _params: HashMap<String, String>) -> Result<String> {
+                self.0.fetch_add(1, Ordering::SeqCst);
+                Err(anyhow::anyhow!("bad arguments"))
+            }
+        }
+
+        let chain: Vec<Arc<dyn ToolMiddleware>> = vec![Arc::new(RetryMiddleware::new(3))];
+        let tool = Broken(AtomicU32::new(0));
+        assert!(Next::new(&tool, &chain).run(call()).await.is_err());
+        assert_eq!(tool.0.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_event_middleware_publishes_outcomes() {
+        let bus = EventBus::new(8);
+        let mut events = bus.subscribe();
+        let chain: Vec<Arc<dyn ToolMiddleware>> = vec![Arc::new(EventMiddleware::new(bus))];
+
+        let tool = FlakyTool { calls: AtomicU32::new(0), failures: 1 };
+        assert!(Next::new(&tool, &chain).run(call()).await.is_err());
+        assert!(Next::new(&tool, &chain).run(call()).await.is_ok());
+
+        let Event::ToolExecuted { tool, success, error, .. } = events.recv().await.unwrap() else { panic!("expected a tool event") };
+        assert_eq!((tool.as_str(), success, error.as_deref()), ("flaky", false, Some("refused")));
+        assert!(matches!(events.recv().await.unwrap(), Event::ToolExecuted { success: true, error: None, .. }));
+    }
+
+    #[tokio::test]
+    async fn test_access_middleware_refuses_before_running() {
+        use crate::access::{with_principal, AccessDenied, Principal};
+
+        let policy = AccessPolicy::default().with_role("ops", &["flaky:run"]).with_tool("flaky", "flaky:run");
+        let chain: Vec<Arc<dyn ToolMiddleware>> = vec![Arc::new(AccessMiddleware::new(Arc::new(policy)))];
+        let tool = FlakyTool { calls: AtomicU32::new(0), failures: 0 };
+
+        let denied = with_principal(Principal::new("ci", &["reader"]), Next::new(&tool, &chain).run(call())).await;
+        assert!(AccessDenied::find(&denied.unwrap_err()).is_some());
+        assert_eq!(tool.calls.load(Ordering::SeqCst), 0);
+
+        let allowed = with_principal(Principal::new("alice", &["ops"]), Next::new(&tool, &chain).run(call())).await;
+        assert_eq!(allowed.unwrap(), "done");
+    }
+}
@@ -1,46 +1,128 @@
 use async_trait::async_trait;
 use std::collections::HashMap;
-use crate::types::Tool;
-use anyhow::Result;
+use std::sync::Arc;
+use serde_json::json;
+use tokio::sync::RwLock;
+use crate::agents::TransferService;
+use crate::types::{Attachment, Message, Tool};
+use anyhow::{Result, anyhow};
+use tracing::Instrument;
+use futures::StreamExt;
 
 mod git;
 mod project;
 mod object_detection;
 mod screenshot_detection;
 pub mod todo;
+pub mod todo_audit;
+pub mod todo_outbox;
+pub mod todo_store;
+pub mod metrics_store;
+pub mod middleware;
+pub mod dry_run;
+pub mod shell;
+pub mod knowledge;
+pub mod notify;
 mod goose;
 mod gpt_batch;
+pub mod summarizer;
+pub mod artifact_store;
 
 #[cfg(feature = "yolo")]
 pub mod yolo;
 
 pub use git::GitTool;
 pub use project::ProjectTool;
-pub use object_detection::ObjectDetectionTool;
-pub use screenshot_detection::ScreenshotDetectionTool;
-pub use todo::TodoTool;
+pub use object_detection::{ObjectDetectionTool, DetectionConfig, Detection, Detector};
+pub use screenshot_detection::{ScreenshotDetectionTool, ScreenshotWatcher, WatchConfig, DetectionReport};
+pub use todo::{TodoTool, McpTodoStore};
+pub use todo_audit::{TodoAuditLog, AuditedTodoStore};
+pub use todo_outbox::{TodoOutbox, PendingOperation};
+pub use todo_store::{TodoStore, MongoTodoStore, NewTodo, TodoQuery};
+pub use metrics_store::{MetricsSnapshot, MetricsHistoryQuery, WorkerMetricsStore};
 pub use goose::GooseTool;
-pub use gpt_batch::GPTBatchTool;
+pub use gpt_batch::{GPTBatchTool, BatchLimits, BatchRequest, BatchResponse, BatchItemResult, BatchUsage, BatchJobStatus};
+pub use artifact_store::{ArtifactStore, LocalArtifactStore, artifact_store_from_env, artifact_store_or_none, artifact_key};
+#[cfg(feature = "s3")]
+pub use artifact_store::S3ArtifactStore;
+pub use summarizer::{OutputSummarizer, TruncatingSummarizer, AiSummarizer, ArtifactArchive};
+pub use shell::{ShellPolicy, ShellTool};
+pub use knowledge::{KnowledgeBase, KnowledgeTool, Passage};
+pub use notify::{NotifyBackend, NotifyLevel, NotifyTool, Notification, notify_host, notify_topic};
+pub use middleware::{
+    AccessMiddleware, ApprovalMiddleware, EventMiddleware, LoggingMiddleware, MetricsMiddleware, Next, RetryMiddleware, ToolCall, ToolMetrics,
+    ToolMiddleware, ToolStats,
+};
 
 #[async_trait]
 pub trait ToolExecutor: Send + Sync {
     async fn execute(&self, params: HashMap<String, String>) -> Result<String>;
+
+    /// What a call with `params` would change, returned instead of running it
+    /// during a [dry run](dry_run). `None` means the call changes nothing and
+    /// runs as usual, so tools with side effects must override this.
+    fn dry_run(&self, _params: &HashMap<String, String>) -> Result<Option<String>> {
+        Ok(None)
+    }
+}
+
+/// Tool parameter carrying a message's or task's attachments, as a JSON list
+pub const ATTACHMENTS_PARAM: &str = "attachments";
+
+/// Adds `attachments` to a tool call's params so tools that accept files can read them
+pub fn with_attachments(mut params: HashMap<String, String>, attachments: &[Attachment]) -> HashMap<String, String> {
+    if !attachments.is_empty() {
+        if let Ok(value) = serde_json::to_string(attachments) {
+            params.insert(ATTACHMENTS_PARAM.to_string(), value);
+        }
+    }
+    params
+}
+
+/// The attachments passed to a tool call, if any
+pub fn attachments_from_params(params: &HashMap<String, String>) -> Result<Vec<Attachment>> {
+    match params.get(ATTACHMENTS_PARAM) {
+        Some(value) => serde_json::from_str(value).map_err(|e| anyhow!("Invalid attachments: {}", e)),
+        None => Ok(Vec::new()),
+    }
 }
 
+/// Executes the injected `agent_transfer` tool for `source_agent`, handing the
+/// conversation to `target_agent` through the [`TransferService`]
 pub struct AgentTransferTool {
-    target_agent: String,
+    source_agent: String,
+    transfer_service: Arc<RwLock<TransferService>>,
 }
 
 impl AgentTransferTool {
-    pub fn new(target_agent: String) -> Self {
-        Self { target_agent }
+    pub fn new(source_agent: String, transfer_service: Arc<RwLock<TransferService>>) -> Self {
+        Self { source_agent, transfer_service }
     }
 }
 
 #[async_trait]
 impl ToolExecutor for AgentTransferTool {
-    async fn execute(&self, _params: HashMap<String, String>) -> Result<String> {
-        unimplemented!("Agent transfer tool execution not yet implemented")
+    async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+        let target = params.get("target_agent")
+            .map(|t| t.trim())
+            .filter(|t| !t.is_empty())
+            .ok_or_else(|| anyhow!("Missing target_agent parameter"))?;
+        let content = params.get("message").cloned()
+            .unwrap_or_else(|| format!("Transferred from {}", self.source_agent));
+
+        let service = self.transfer_service.read().await;
+        let agents = service.agent_names().await;
+        if !agents.iter().any(|name| name == target) {
+            return Err(anyhow!("Unknown agent '{}'. Available agents: {}", target, agents.join(", ")));
+        }
+
+        let response = service.transfer(&self.source_agent, target, Message::new(content)).await?;
+        Ok(json!({
+            "status": "transferred",
+            "from": self.source_agent,
+            "to": target,
+            "response": response.content,
+        }).to_string())
     }
 }
 
@@ -64,13 +146,101 @@ impl OpenAITool {
 
 pub struct ToolRegistry {
     tools: HashMap<String, Box<dyn ToolExecutor>>,
+    summarizer: Box<dyn OutputSummarizer>,
+    output_threshold: usize,
+    archive: ArtifactArchive,
+    /// Runs around every call, outermost first
+    middleware: Vec<Arc<dyn ToolMiddleware>>,
+    /// Runs inside `middleware` for calls to one tool
+    tool_middleware: HashMap<String, Vec<Arc<dyn ToolMiddleware>>>,
+    metrics: ToolMetrics,
+    /// Most calls [`execute_batch`](Self::execute_batch) runs at once
+    parallelism: usize,
 }
 
+/// Concurrent calls per batch when `TOOL_PARALLELISM` is unset
+pub const DEFAULT_TOOL_PARALLELISM: usize = 4;
+
 impl ToolRegistry {
+    /// Every call is timed, logged and published on the shared event bus. Tools named in `TOOL_RETRIES`
+    /// (e.g. `project=2,goose=1`) are treated as idempotent and retried that
+    /// many times on transient failures. Calls gated by `APPROVAL_REQUIRED`
+    /// wait in the shared [`ApprovalInbox`](crate::agents::ApprovalInbox) first,
+    /// and calls the shared [`AccessPolicy`](crate::access::AccessPolicy)
+    /// doesn't allow are refused before that.
     pub fn new() -> Self {
-        Self {
+        let metrics = ToolMetrics::default();
+        let mut registry = Self {
             tools: HashMap::new(),
+            summarizer: Box::new(TruncatingSummarizer::default()),
+            output_threshold: summarizer::DEFAULT_OUTPUT_THRESHOLD,
+            archive: ArtifactArchive::from_env(),
+            middleware: vec![
+                Arc::new(MetricsMiddleware::new(metrics.clone())),
+                Arc::new(LoggingMiddleware::from_env()),
+                Arc::new(EventMiddleware::new(crate::events::EventBus::shared())),
+            ],
+            tool_middleware: HashMap::new(),
+            metrics,
+            parallelism: std::env::var("TOOL_PARALLELISM")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(DEFAULT_TOOL_PARALLELISM),
+        };
+        let retries = crate::types::scheduler::parse_limits(&std::env::var("TOOL_RETRIES").unwrap_or_default());
+        for (tool, retries) in retries {
+            let retry = RetryMiddleware::new(retries as u32).with_metrics(registry.metrics.clone());
+            registry = registry.with_tool_middleware(tool, retry);
         }
+        // Outermost, so calls that are never approved are not counted as run
+        let inbox = crate::agents::ApprovalInbox::shared();
+        if inbox.is_gated() {
+            registry.middleware.insert(0, Arc::new(ApprovalMiddleware::new(inbox)));
+        }
+        // Outside that, so nobody is asked to approve a call that isn't allowed
+        let policy = crate::access::AccessPolicy::shared();
+        if policy.is_enabled() {
+            registry.middleware.insert(0, Arc::new(AccessMiddleware::new(policy)));
+        }
+        registry
+    }
+
+    /// Add middleware around calls to every tool, inside any added before it
+    pub fn with_middleware<M: ToolMiddleware + 'static>(mut self, middleware: M) -> Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    /// Add middleware around calls to `tool` only
+    pub fn with_tool_middleware<M: ToolMiddleware + 'static>(mut self, tool: impl Into<String>, middleware: M) -> Self {
+        self.tool_middleware.entry(tool.into()).or_default().push(Arc::new(middleware));
+        self
+    }
+
+    pub fn with_parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+
+    /// Call counts, failures, retries and timings per tool
+    pub fn metrics(&self) -> &ToolMetrics {
+        &self.metrics
+    }
+
+    /// Replace the summarizer used for outputs over the size threshold
+    pub fn with_summarizer<S: OutputSummarizer + 'static>(mut self, summarizer: S) -> Self {
+        self.summarizer = Box::new(summarizer);
+        self
+    }
+
+    pub fn with_output_threshold(mut self, threshold: usize) -> Self {
+        self.output_threshold = threshold;
+        self
+    }
+
+    pub fn with_archive(mut self, archive: ArtifactArchive) -> Self {
+        self.archive = archive;
+        self
     }
 
     pub fn register<T: ToolExecutor + 'static>(&mut self, name: String, executor: T) {
@@ -79,12 +249,61 @@ impl ToolRegistry {
 
     pub async fn execute(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String> {
         if let Some(executor) = self.tools.get(&tool.name) {
-            executor.execute(params).await
+            let span = tracing::info_span!(
+                "tool.execute",
+                tool = %tool.name,
+                correlation_id = crate::telemetry::current_correlation_id().unwrap_or_default(),
+            );
+            let mut chain = self.middleware.clone();
+            chain.extend(self.tool_middleware.get(&tool.name).into_iter().flatten().cloned());
+            crate::agents::audit::record_tool(&tool.name, &params);
+            let call = ToolCall { tool: tool.name.clone(), params };
+            let output = Next::new(executor.as_ref(), &chain).run(call).instrument(span).await?;
+            self.condense_output(&tool.name, output).await
         } else {
             Err(anyhow::anyhow!("Tool not found in registry"))
         }
     }
 
+    /// Run independent calls concurrently, at most `parallelism` at a time.
+    /// Results come back in the order the calls were given, and one failing
+    /// call doesn't stop the others.
+    pub async fn execute_batch(&self, calls: Vec<(Tool, HashMap<String, String>)>) -> Vec<Result<String>> {
+        futures::stream::iter(calls)
+            .map(|(tool, params)| async move { self.execute(&tool, params).await })
+            .buffered(self.parallelism.max(1))
+            .collect()
+            .await
+    }
+
+    /// Archive and summarize outputs that are too large to hand to an agent as-is
+    async fn condense_output(&self, tool_name: &str, output: String) -> Result<String> {
+        if output.chars().count() <= self.output_threshold {
+            return Ok(output);
+        }
+
+        let artifact = match self.archive.store(tool_name, &output).await {
+            Ok(path) => Some(path),
+            Err(e) => {
+                tracing::warn!("Failed to archive full output of {}: {}", tool_name, e);
+                None
+            }
+        };
+
+        let summary = match self.summarizer.summarize(tool_name, &output).await {
+            Ok(summary) => summary,
+            Err(e) => {
+                tracing::warn!("Failed to summarize output of {}: {}", tool_name, e);
+                summarizer::truncate_middle(&output, self.output_threshold)
+            }
+        };
+
+        Ok(match artifact {
+            Some(path) => format!("{}\n\n[Full output archived at {}]", summary, path.display()),
+            None => summary,
+        })
+    }
+
     pub async fn create_default_tools() -> Result<Self> {
         let mut registry = Self::new();
 
@@ -101,9 +320,19 @@ impl ToolRegistry {
         // Register Goose tool
         registry.register("goose".to_string(), GooseTool::new());
 
+        // Register the sandboxed shell tool
+        registry.register("shell".to_string(), ShellTool::from_env());
+
+        // Register the documentation knowledge base when KNOWLEDGE_DIRS is set
+        if let Some(knowledge) = KnowledgeBase::shared() {
+            registry.register("knowledge".to_string(), KnowledgeTool::new(knowledge));
+        }
+
         // Register GPT Batch tool
-        let api_key = std::env::var("OPENAI_API_KEY").unwrap_or_else(|_| "".to_string());
-        registry.register("gpt_batch".to_string(), GPTBatchTool::new(api_key));
+        registry.register("gpt_batch".to_string(), GPTBatchTool::from_env());
+
+        // Register the desktop notification tool; without a broker it can only notify this host
+        registry.register("notify".to_string(), NotifyTool::from_env());
 
         Ok(registry)
     }
@@ -135,5 +364,111 @@ mod tests {
 
         let result = registry.execute(&tool, HashMap::new()).await.unwrap();
         assert_eq!(result, "mock result");
+        assert_eq!(registry.metrics().get("mock").unwrap().calls, 1);
+    }
+
+    /// Sleeps briefly, tracking how many calls overlap
+    struct SlowTool {
+        in_flight: Arc<std::sync::atomic::AtomicUsize>,
+        peak: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl ToolExecutor for SlowTool {
+        async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+            use std::sync::atomic::Ordering;
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            match params.get("n").map(String::as_str) {
+                Some("fail") => Err(anyhow::anyhow!("asked to fail")),
+                n => Ok(n.unwrap_or_default().to_string()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_execute_batch() {
+        let peak = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut registry = ToolRegistry::new().with_parallelism(2);
+        registry.register("slow".to_string(), SlowTool { in_flight: Arc::default(), peak: peak.clone() });
+
+        let tool = Tool { name: "slow".to_string(), description: "Sleeps".to_string(), parameters: HashMap::new() };
+        let calls = ["1", "2", "fail", "4", "5"].iter()
+            .map(|n| (tool.clone(), HashMap::from([("n".to_string(), n.to_string())])))
+            .collect();
+
+        let results = registry.execute_batch(calls).await;
+        assert_eq!(results.len(), 5);
+        assert_eq!(results[0].as_ref().unwrap(), "1");
+        assert!(results[2].is_err());
+        assert_eq!(results[4].as_ref().unwrap(), "5");
+        assert_eq!(peak.load(std::sync::atomic::Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_agent_transfer_tool() {
+        use crate::agents::{greeter::GreeterAgent, AgentRegistry};
+        use crate::types::AgentConfig;
+
+        let config = |name: &str, downstream: &[&str]| AgentConfig {
+            name: name.to_string(),
+            public_description: format!("{} agent", name),
+            instructions: "Help".to_string(),
+            tools: vec![],
+            downstream_agents: downstream.iter().map(|s| s.to_string()).collect(),
+            personality: None,
+            state_machine: None,
+        };
+        let mut agents = AgentRegistry::new();
+        agents.register("greeter".to_string(), Box::new(GreeterAgent::new(config("greeter", &["haiku"])))).await.unwrap();
+        agents.register("haiku".to_string(), Box::new(GreeterAgent::new(config("haiku", &[])))).await.unwrap();
+        let service = Arc::new(RwLock::new(TransferService::new(Arc::new(RwLock::new(agents)))));
+        let tool = AgentTransferTool::new("greeter".to_string(), service.clone());
+
+        let err = tool.execute(HashMap::from([("target_agent".to_string(), "nobody".to_string())])).await.unwrap_err();
+        assert!(err.to_string().contains("Available agents: greeter, haiku"), "{}", err);
+        assert!(tool.execute(HashMap::new()).await.is_err());
+
+        let output: serde_json::Value = serde_json::from_str(
+            &tool.execute(HashMap::from([("target_agent".to_string(), "haiku".to_string())])).await.unwrap()
+        ).unwrap();
+        assert_eq!(output["status"], "transferred");
+        assert_eq!(output["to"], "haiku");
+        assert_eq!(service.read().await.get_current_agent_name().await.unwrap(), "haiku");
+    }
+
+    struct VerboseTool;
+
+    #[async_trait]
+    impl ToolExecutor for VerboseTool {
+        async fn execute(&self, _params: HashMap<String, String>) -> Result<String> {
+            Ok("x".repeat(500))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_oversized_output_is_summarized_and_archived() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut registry = ToolRegistry::new()
+            .with_summarizer(TruncatingSummarizer::new(50))
+            .with_output_threshold(100)
+            .with_archive(ArtifactArchive::new(dir.path()));
+        registry.register("verbose".to_string(), VerboseTool);
+
+        let tool = Tool {
+            name: "verbose".to_string(),
+            description: "Produces a lot of output".to_string(),
+            parameters: HashMap::new(),
+        };
+
+        let result = registry.execute(&tool, HashMap::new()).await.unwrap();
+        assert!(result.len() < 500);
+        assert!(result.contains("characters omitted"));
+        assert!(result.contains("Full output archived at"));
+
+        let archived: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(archived.len(), 1);
     }
 }
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::env;
+use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use tokio::process::Command;
+use crate::mqtt::{MqttService, QoS};
+use crate::tools::{dry_run, ToolExecutor};
+use crate::types::{TaskPriority, TodoTask};
+
+/// Hammerspoon URL event notifications are sent as, bound in `init.lua` with
+/// `hs.urlevent.bind("swarm_notify", ...)`
+pub const DEFAULT_HAMMERSPOON_EVENT: &str = "swarm_notify";
+const DEFAULT_TITLE: &str = "Swarmonomicon";
+
+/// This machine's name for `notify/<host>`: `NOTIFY_HOST`, then `HOSTNAME`,
+/// then `/etc/hostname`
+pub fn notify_host() -> String {
+    env::var("NOTIFY_HOST")
+        .or_else(|_| env::var("HOSTNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+/// Topic notifications for `host` are published to
+pub fn notify_topic(host: &str) -> String {
+    format!("notify/{}", host)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyLevel {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+impl std::str::FromStr for NotifyLevel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "info" => Ok(Self::Info),
+            "success" => Ok(Self::Success),
+            "warning" | "warn" => Ok(Self::Warning),
+            "error" => Ok(Self::Error),
+            other => Err(anyhow!("Unknown notification level: {}", other)),
+        }
+    }
+}
+
+/// A notification as delivered, and as published to `notify/<host>`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Notification {
+    pub title: String,
+    pub message: String,
+    pub level: NotifyLevel,
+    /// Opened when the notification is clicked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// Deliver on this host over MQTT instead of locally
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+}
+
+impl Notification {
+    pub fn new(title: impl Into<String>, message: impl Into<String>, level: NotifyLevel) -> Self {
+        Self { title: title.into(), message: message.into(), level, url: None, host: None }
+    }
+
+    pub fn with_host(mut self, host: impl Into<String>) -> Self {
+        self.host = Some(host.into());
+        self
+    }
+
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    pub fn task_completed(task: &TodoTask) -> Self {
+        Self::new(
+            format!("✅ {:?} task done", task.priority),
+            task.description.clone(),
+            NotifyLevel::Success,
+        )
+    }
+
+    pub fn task_failed(task: &TodoTask, error: &str, dead_lettered: bool) -> Self {
+        let title = if dead_lettered { "❌ Task dead-lettered" } else { "⚠️ Task failed" };
+        let level = if dead_lettered { NotifyLevel::Error } else { NotifyLevel::Warning };
+        Self::new(title, format!("{}: {}", task.description, error), level)
+    }
+}
+
+/// How a [`NotifyTool`] delivers notifications meant for this host
+#[derive(Debug, Clone, PartialEq)]
+pub enum NotifyBackend {
+    /// `open -g hammerspoon://<event>?title=..&message=..&level=..`
+    Hammerspoon { event: String },
+    /// The `terminal-notifier` command line tool
+    TerminalNotifier,
+    /// Published to this host's `notify/<host>` topic for a listener to show
+    Mqtt,
+    /// Only logged
+    Log,
+}
+
+impl NotifyBackend {
+    /// Parses `hammerspoon`, `terminal-notifier`, `mqtt` or `log`
+    pub fn parse(name: &str, hammerspoon_event: &str) -> Result<Self> {
+        match name {
+            "hammerspoon" => Ok(Self::Hammerspoon { event: hammerspoon_event.to_string() }),
+            "terminal-notifier" => Ok(Self::TerminalNotifier),
+            "mqtt" => Ok(Self::Mqtt),
+            "log" => Ok(Self::Log),
+            other => Err(anyhow!("Unknown NOTIFY_BACKEND: {}", other)),
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Hammerspoon { .. } => "hammerspoon",
+            Self::TerminalNotifier => "terminal-notifier",
+            Self::Mqtt => "mqtt",
+            Self::Log => "log",
+        }
+    }
+}
+
+/// Surfaces task completions and errors to a person: on this Mac through
+/// Hammerspoon or terminal-notifier, or on another host through `notify/<host>`
+pub struct NotifyTool {
+    backend: NotifyBackend,
+    host: String,
+    mqtt: Option<MqttService>,
+    /// Completed tasks below this priority don't notify
+    min_priority: TaskPriority,
+}
+
+impl NotifyTool {
+    pub fn new(backend: NotifyBackend) -> Self {
+        Self { backend, host: notify_host(), mqtt: None, min_priority: TaskPriority::High }
+    }
+
+    /// Reads `NOTIFY_BACKEND` (default `hammerspoon` on macOS, `mqtt`
+    /// elsewhere), `NOTIFY_HAMMERSPOON_EVENT`, `NOTIFY_HOST` and
+    /// `NOTIFY_MIN_PRIORITY` (default `high`)
+    pub fn from_env() -> Self {
+        let event = env::var("NOTIFY_HAMMERSPOON_EVENT").unwrap_or_else(|_| DEFAULT_HAMMERSPOON_EVENT.to_string());
+        let default = if cfg!(target_os = "macos") { "hammerspoon" } else { "mqtt" };
+        let name = env::var("NOTIFY_BACKEND").unwrap_or_else(|_| default.to_string());
+        let backend = NotifyBackend::parse(name.trim(), &event).unwrap_or_else(|e| {
+            tracing::warn!("{}, logging notifications instead", e);
+            NotifyBackend::Log
+        });
+        let mut tool = Self::new(backend);
+        if let Some(priority) = env::var("NOTIFY_MIN_PRIORITY").ok().and_then(|p| TaskPriority::parse(&p)) {
+            tool.min_priority = priority;
+        }
+        tool
+    }
+
+    /// Broker used for the `mqtt` backend and for notifications addressed to other hosts
+    pub fn with_mqtt(mut self, client: MqttService) -> Self {
+        self.mqtt = Some(client);
+        self
+    }
+
+    pub fn with_host(mut self, host: impl Into<String>) -> Self {
+        self.host = host.into();
+        self
+    }
+
+    pub fn with_min_priority(mut self, priority: TaskPriority) -> Self {
+        self.min_priority = priority;
+        self
+    }
+
+    /// Whether completing `task` is worth interrupting someone for
+    pub fn should_notify_completion(&self, task: &TodoTask) -> bool {
+        task.priority >= self.min_priority
+    }
+
+    pub async fn notify_task_completed(&self, task: &TodoTask) -> Result<()> {
+        if !self.should_notify_completion(task) {
+            return Ok(());
+        }
+        self.notify(&Notification::task_completed(task)).await
+    }
+
+    pub async fn notify_task_failed(&self, task: &TodoTask, error: &str, dead_lettered: bool) -> Result<()> {
+        self.notify(&Notification::task_failed(task, error, dead_lettered)).await
+    }
+
+    /// Delivers `notification`, over MQTT when it names another host
+    pub async fn notify(&self, notification: &Notification) -> Result<()> {
+        match notification.host.as_deref() {
+            Some(host) if host != self.host => return self.publish(host, notification).await,
+            _ => {}
+        }
+        match &self.backend {
+            NotifyBackend::Hammerspoon { event } => {
+                let url = hammerspoon_url(event, notification)?;
+                run("open", &["-g", url.as_str()]).await
+            }
+            NotifyBackend::TerminalNotifier => {
+                let mut args = vec!["-title", notification.title.as_str(), "-message", notification.message.as_str(), "-group", "swarmonomicon"];
+                if let Some(url) = &notification.url {
+                    args.extend(["-open", url.as_str()]);
+                }
+                if notification.level == NotifyLevel::Error {
+                    args.extend(["-sound", "Basso"]);
+                }
+                run("terminal-notifier", &args).await
+            }
+            NotifyBackend::Mqtt => self.publish(&self.host, notification).await,
+            NotifyBackend::Log => {
+                tracing::info!("Notification [{:?}] {}: {}", notification.level, notification.title, notification.message);
+                Ok(())
+            }
+        }
+    }
+
+    async fn publish(&self, host: &str, notification: &Notification) -> Result<()> {
+        let client = self.mqtt.as_ref()
+            .ok_or_else(|| anyhow!("No MQTT connection to notify {}", host))?;
+        let mut payload = serde_json::to_value(notification)?;
+        if let Some(obj) = payload.as_object_mut() {
+            obj.insert("timestamp".to_string(), chrono::Utc::now().to_rfc3339().into());
+        }
+        Ok(client.publish(notify_topic(host), QoS::AtLeastOnce, false, payload.to_string()).await?)
+    }
+}
+
+fn hammerspoon_url(event: &str, notification: &Notification) -> Result<reqwest::Url> {
+    let level = serde_json::to_value(notification.level)?;
+    let mut params = vec![
+        ("title", notification.title.as_str()),
+        ("message", notification.message.as_str()),
+        ("level", level.as_str().unwrap_or("info")),
+    ];
+    if let Some(url) = &notification.url {
+        params.push(("url", url.as_str()));
+    }
+    reqwest::Url::parse_with_params(&format!("hammerspoon://{}", event), &params)
+        .map_err(|e| anyhow!("Invalid Hammerspoon URL: {}", e))
+}
+
+async fn run(program: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| anyhow!("Failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(anyhow!("{} failed: {}", program, String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl ToolExecutor for NotifyTool {
+    /// Params: `message` (required), `title`, `level` (info, success, warning
+    /// or error), `url` and `host` to deliver on another machine
+    async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+        let message = params.get("message").ok_or_else(|| anyhow!("Missing message parameter"))?;
+        let title = params.get("title").map(String::as_str).unwrap_or(DEFAULT_TITLE);
+        let level = match params.get("level") {
+            Some(level) => level.parse()?,
+            None => NotifyLevel::Info,
+        };
+        let mut notification = Notification::new(title, message.clone(), level);
+        notification.url = params.get("url").cloned();
+        notification.host = params.get("host").cloned();
+
+        self.notify(&notification).await?;
+        Ok(match &notification.host {
+            Some(host) if *host != self.host => format!("Notification sent to {}", notify_topic(host)),
+            _ => format!("Notification sent via {}", self.backend.name()),
+        })
+    }
+
+    fn dry_run(&self, params: &HashMap<String, String>) -> Result<Option<String>> {
+        let message = params.get("message").ok_or_else(|| anyhow!("Missing message parameter"))?;
+        let step = match params.get("host") {
+            Some(host) if *host != self.host => format!("publish {:?} to {}", message, notify_topic(host)),
+            _ => format!("show {:?} via {}", message, self.backend.name()),
+        };
+        Ok(Some(dry_run::plan([step])))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hammerspoon_url_encodes_params() {
+        let notification = Notification::new("Done", "Fix login & deploy", NotifyLevel::Success);
+        let url = hammerspoon_url(DEFAULT_HAMMERSPOON_EVENT, &notification).unwrap();
+        assert_eq!(url.scheme(), "hammerspoon");
+        assert_eq!(url.host_str(), Some("swarm_notify"));
+        let params: HashMap<_, _> = url.query_pairs().into_owned().collect();
+        assert_eq!(params["message"], "Fix login & deploy");
+        assert_eq!(params["level"], "success");
+    }
+
+    #[tokio::test]
+    async fn test_execute_and_priority_threshold() {
+        let tool = NotifyTool::new(NotifyBackend::Log).with_host("studio");
+        let params = HashMap::from([("message".to_string(), "hello".to_string())]);
+        assert_eq!(tool.execute(params).await.unwrap(), "Notification sent via log");
+
+        let remote = HashMap::from([
+            ("message".to_string(), "hello".to_string()),
+            ("host".to_string(), "laptop".to_string()),
+        ]);
+        let error = tool.execute(remote).await.unwrap_err();
+        assert!(error.to_string().contains("No MQTT connection to notify laptop"));
+
+        let mut task: TodoTask = serde_json::from_value(serde_json::json!({
+            "id": "todo-1",
+            "description": "Ship the release",
+            "priority": "Medium",
+            "target_agent": "git",
+            "status": "completed",
+            "created_at": 1
+        })).unwrap();
+        assert!(!tool.should_notify_completion(&task));
+        task.priority = TaskPriority::Critical;
+        assert!(tool.should_notify_completion(&task));
+    }
+}
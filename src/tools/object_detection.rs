@@ -1,42 +1,435 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::env;
 use std::fs;
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 use async_trait::async_trait;
-use crate::tools::ToolExecutor;
+use image::{DynamicImage, Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use crate::tools::{artifact_key, artifact_store_or_none, attachments_from_params, ArtifactStore, ToolExecutor};
 use anyhow::{Result, anyhow};
 
-pub struct ObjectDetectionTool;
+pub const DEFAULT_MODEL_PATH: &str = "models/yolov8n.onnx";
+pub const DEFAULT_CONFIDENCE: f32 = 0.25;
+pub const DEFAULT_IOU_THRESHOLD: f32 = 0.45;
+/// Square input size the model was exported with
+pub const DEFAULT_INPUT_SIZE: u32 = 640;
+
+/// Class names of the COCO-trained YOLO models, in class id order
+pub const COCO_CLASSES: [&str; 80] = [
+    "person", "bicycle", "car", "motorcycle", "airplane", "bus", "train", "truck", "boat", "traffic light",
+    "fire hydrant", "stop sign", "parking meter", "bench", "bird", "cat", "dog", "horse", "sheep", "cow",
+    "elephant", "bear", "zebra", "giraffe", "backpack", "umbrella", "handbag", "tie", "suitcase", "frisbee",
+    "skis", "snowboard", "sports ball", "kite", "baseball bat", "baseball glove", "skateboard", "surfboard",
+    "tennis racket", "bottle", "wine glass", "cup", "fork", "knife", "spoon", "bowl", "banana", "apple",
+    "sandwich", "orange", "broccoli", "carrot", "hot dog", "pizza", "donut", "cake", "chair", "couch",
+    "potted plant", "bed", "dining table", "toilet", "tv", "laptop", "mouse", "remote", "keyboard", "cell phone",
+    "microwave", "oven", "toaster", "sink", "refrigerator", "book", "clock", "vase", "scissors", "teddy bear",
+    "hair drier", "toothbrush",
+];
+
+/// One detected object, with its box in pixels of the original image
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Detection {
+    pub class: String,
+    pub class_id: usize,
+    pub confidence: f32,
+    /// `[x, y, width, height]` from the top-left corner
+    pub bbox: [f32; 4],
+}
+
+/// Model, thresholds and output settings for object detection
+#[derive(Debug, Clone)]
+pub struct DetectionConfig {
+    pub model_path: PathBuf,
+    pub labels: Vec<String>,
+    pub confidence: f32,
+    pub iou_threshold: f32,
+    pub input_size: u32,
+    /// Only report these classes; all when `None`
+    pub classes: Option<HashSet<String>>,
+    /// Where annotated images are written; none are written when `None`
+    pub results_dir: Option<PathBuf>,
+    /// Ask ONNX Runtime for the CUDA execution provider
+    pub use_gpu: bool,
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        Self {
+            model_path: PathBuf::from(DEFAULT_MODEL_PATH),
+            labels: COCO_CLASSES.iter().map(|c| c.to_string()).collect(),
+            confidence: DEFAULT_CONFIDENCE,
+            iou_threshold: DEFAULT_IOU_THRESHOLD,
+            input_size: DEFAULT_INPUT_SIZE,
+            classes: None,
+            results_dir: None,
+            use_gpu: false,
+        }
+    }
+}
+
+/// Comma-separated class names, trimmed and lowercased
+pub fn parse_classes(spec: &str) -> Option<HashSet<String>> {
+    let classes: HashSet<String> = spec.split(',')
+        .map(|c| c.trim().to_lowercase())
+        .filter(|c| !c.is_empty())
+        .collect();
+    (!classes.is_empty()).then_some(classes)
+}
+
+impl DetectionConfig {
+    /// Reads `YOLO_MODEL_PATH`, `YOLO_LABELS` (a file with one class per line),
+    /// `YOLO_CONFIDENCE`, `YOLO_IOU`, `YOLO_INPUT_SIZE`, `YOLO_CLASSES`,
+    /// `YOLO_RESULTS_DIR` and `YOLO_USE_GPU`
+    pub fn from_env() -> Result<Self> {
+        let defaults = Self::default();
+        let labels = match env::var("YOLO_LABELS") {
+            Ok(path) => fs::read_to_string(&path)
+                .map_err(|e| anyhow!("Failed to read YOLO_LABELS {}: {}", path, e))?
+                .lines()
+                .map(|l| l.trim().to_string())
+                .filter(|l| !l.is_empty())
+                .collect(),
+            Err(_) => defaults.labels,
+        };
+        let parse = |name: &str| env::var(name).ok().and_then(|v| v.trim().parse().ok());
+        Ok(Self {
+            model_path: env::var("YOLO_MODEL_PATH").map(PathBuf::from).unwrap_or(defaults.model_path),
+            labels,
+            confidence: parse("YOLO_CONFIDENCE").unwrap_or(defaults.confidence),
+            iou_threshold: parse("YOLO_IOU").unwrap_or(defaults.iou_threshold),
+            input_size: env::var("YOLO_INPUT_SIZE").ok().and_then(|v| v.trim().parse().ok()).unwrap_or(defaults.input_size),
+            classes: env::var("YOLO_CLASSES").ok().and_then(|spec| parse_classes(&spec)),
+            results_dir: env::var("YOLO_RESULTS_DIR").ok().filter(|d| !d.is_empty()).map(PathBuf::from),
+            use_gpu: env::var("YOLO_USE_GPU").map(|v| v == "1" || v.eq_ignore_ascii_case("true")).unwrap_or(false),
+        })
+    }
+
+    fn label(&self, class_id: usize) -> String {
+        self.labels.get(class_id).cloned().unwrap_or_else(|| format!("class_{}", class_id))
+    }
+}
+
+/// Runs a detection model over an image
+pub trait Detector: Send + Sync {
+    fn detect(&self, image: &DynamicImage) -> Result<Vec<Detection>>;
+}
+
+fn iou(a: &[f32; 4], b: &[f32; 4]) -> f32 {
+    let x1 = a[0].max(b[0]);
+    let y1 = a[1].max(b[1]);
+    let x2 = (a[0] + a[2]).min(b[0] + b[2]);
+    let y2 = (a[1] + a[3]).min(b[1] + b[3]);
+    let intersection = (x2 - x1).max(0.0) * (y2 - y1).max(0.0);
+    let union = a[2] * a[3] + b[2] * b[3] - intersection;
+    if union <= 0.0 { 0.0 } else { intersection / union }
+}
+
+/// Keep the most confident of each group of same-class boxes overlapping by more than `iou_threshold`
+pub fn non_max_suppression(mut detections: Vec<Detection>, iou_threshold: f32) -> Vec<Detection> {
+    detections.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    let mut kept: Vec<Detection> = Vec::new();
+    for detection in detections {
+        let overlaps = kept.iter().any(|k| k.class_id == detection.class_id && iou(&k.bbox, &detection.bbox) > iou_threshold);
+        if !overlaps {
+            kept.push(detection);
+        }
+    }
+    kept
+}
+
+/// Turn raw YOLOv8 output into detections. `shape` is `[1, 4 + classes, boxes]`
+/// (or transposed); boxes are centre/size in model input pixels and are scaled
+/// by `scale` (`original / input` per axis).
+pub fn decode_yolov8(output: &[f32], shape: &[usize], scale: (f32, f32), config: &DetectionConfig) -> Result<Vec<Detection>> {
+    let (rows, cols) = match shape {
+        [1, rows, cols] | [rows, cols] => (*rows, *cols),
+        _ => return Err(anyhow!("Unexpected YOLO output shape {:?}", shape)),
+    };
+    if output.len() != rows * cols {
+        return Err(anyhow!("YOLO output has {} values, shape {:?} needs {}", output.len(), shape, rows * cols));
+    }
+    // Exports put attributes first ([84, 8400]); some tools transpose them
+    let expected = config.labels.len() + 4;
+    let transposed = if rows == expected { false } else if cols == expected { true } else { rows > cols };
+    let (attributes, boxes) = if transposed { (cols, rows) } else { (rows, cols) };
+    if attributes < 5 {
+        return Err(anyhow!("YOLO output has no class scores: {:?}", shape));
+    }
+    let value = |attribute: usize, index: usize| {
+        if transposed { output[index * attributes + attribute] } else { output[attribute * boxes + index] }
+    };
+
+    let mut detections = Vec::new();
+    for index in 0..boxes {
+        let (class_id, confidence) = (4..attributes)
+            .map(|attribute| (attribute - 4, value(attribute, index)))
+            .fold((0, f32::MIN), |best, candidate| if candidate.1 > best.1 { candidate } else { best });
+        if confidence < config.confidence {
+            continue;
+        }
+        let class = config.label(class_id);
+        if let Some(classes) = &config.classes {
+            if !classes.contains(&class.to_lowercase()) {
+                continue;
+            }
+        }
+        let (cx, cy, w, h) = (value(0, index), value(1, index), value(2, index), value(3, index));
+        detections.push(Detection {
+            class,
+            class_id,
+            confidence,
+            bbox: [(cx - w / 2.0) * scale.0, (cy - h / 2.0) * scale.1, w * scale.0, h * scale.1],
+        });
+    }
+    Ok(non_max_suppression(detections, config.iou_threshold))
+}
+
+const PALETTE: [[u8; 3]; 6] = [[255, 56, 56], [56, 255, 56], [56, 56, 255], [255, 178, 29], [207, 56, 255], [0, 212, 187]];
+
+/// A copy of `image` with each detection outlined, coloured by class
+pub fn annotate(image: &DynamicImage, detections: &[Detection]) -> RgbImage {
+    let mut canvas = image.to_rgb8();
+    let (width, height) = canvas.dimensions();
+    if width == 0 || height == 0 {
+        return canvas;
+    }
+    for detection in detections {
+        let color = Rgb(PALETTE[detection.class_id % PALETTE.len()]);
+        let clamp = |v: f32, max: u32| (v.max(0.0) as u32).min(max - 1);
+        let [x, y, w, h] = detection.bbox;
+        let (x1, y1) = (clamp(x, width), clamp(y, height));
+        let (x2, y2) = (clamp(x + w, width), clamp(y + h, height));
+        for thickness in 0..2 {
+            for px in x1..=x2 {
+                canvas.put_pixel(px, (y1 + thickness).min(height - 1), color);
+                canvas.put_pixel(px, y2.saturating_sub(thickness), color);
+            }
+            for py in y1..=y2 {
+                canvas.put_pixel((x1 + thickness).min(width - 1), py, color);
+                canvas.put_pixel(x2.saturating_sub(thickness), py, color);
+            }
+        }
+    }
+    canvas
+}
+
+#[cfg(feature = "yolo")]
+fn default_detector(config: &DetectionConfig) -> Result<Arc<dyn Detector>> {
+    Ok(Arc::new(crate::tools::yolo::OnnxDetector::load(config.clone())?))
+}
+
+#[cfg(not(feature = "yolo"))]
+fn default_detector(_config: &DetectionConfig) -> Result<Arc<dyn Detector>> {
+    Err(anyhow!("Object detection needs the `yolo` feature"))
+}
+
+/// Detects objects in an image file with a YOLO model.
+///
+/// Params: `image` (path) or an image in `attachments`, optional `confidence`,
+/// `classes` (comma-separated) and `annotate` (`false` skips writing and
+/// uploading the annotated image).
+pub struct ObjectDetectionTool {
+    config: DetectionConfig,
+    detector: Option<Arc<dyn Detector>>,
+    artifacts: Option<Arc<dyn ArtifactStore>>,
+}
 
 impl ObjectDetectionTool {
+    /// Configured from the environment; the model loads on first use
     pub fn new() -> Self {
-        Self
+        let config = DetectionConfig::from_env().unwrap_or_else(|e| {
+            tracing::warn!("Invalid detection config, using defaults: {}", e);
+            DetectionConfig::default()
+        });
+        let tool = Self::with_config(config);
+        match artifact_store_or_none() {
+            Some(artifacts) => tool.with_artifact_store(artifacts),
+            None => tool,
+        }
+    }
+
+    pub fn with_config(config: DetectionConfig) -> Self {
+        Self { config, detector: None, artifacts: None }
+    }
+
+    /// Annotated images are also uploaded here, and their URL returned
+    pub fn with_artifact_store(mut self, artifacts: Arc<dyn ArtifactStore>) -> Self {
+        self.artifacts = Some(artifacts);
+        self
     }
 
-    fn load_yolo_model(&self, weights_path: &str, cfg_path: &str) -> Result<()> {
-        if !Path::new(weights_path).exists() || !Path::new(cfg_path).exists() {
-            return Err(anyhow!("Model weights or configuration files missing."));
+    pub fn with_detector(mut self, detector: Arc<dyn Detector>) -> Self {
+        self.detector = Some(detector);
+        self
+    }
+
+    pub fn config(&self) -> &DetectionConfig {
+        &self.config
+    }
+
+    fn detector(&self) -> Result<Arc<dyn Detector>> {
+        match &self.detector {
+            Some(detector) => Ok(detector.clone()),
+            None => shared_detector(&self.config),
         }
-        // Load the model (this is a placeholder for actual loading logic)
-        Ok(())
     }
 
-    fn perform_detection(&self, image_path: &str) -> Result<String> {
-        // Placeholder for detection logic
-        // Here you would call the YOLO detection logic
-        Ok(format!("Detection performed on image: {}", image_path))
+    /// Detections in `image` that pass `confidence` and `classes` (tighter than the model config)
+    pub async fn detect(&self, image: DynamicImage, confidence: Option<f32>, classes: Option<&HashSet<String>>) -> Result<Vec<Detection>> {
+        let detector = self.detector()?;
+        let detections = tokio::task::spawn_blocking(move || detector.detect(&image)).await??;
+        Ok(detections.into_iter()
+            .filter(|d| confidence.map_or(true, |min| d.confidence >= min))
+            .filter(|d| classes.map_or(true, |classes| classes.contains(&d.class.to_lowercase())))
+            .collect())
+    }
+
+    /// Write `image` with `detections` outlined to the results directory, if one is configured
+    pub fn save_annotated(&self, image_path: &Path, image: &DynamicImage, detections: &[Detection]) -> Result<Option<PathBuf>> {
+        let Some(dir) = &self.config.results_dir else { return Ok(None) };
+        fs::create_dir_all(dir)?;
+        let stem = image_path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(|| "image".to_string());
+        let path = dir.join(format!("{}-detections.png", stem));
+        annotate(image, detections).save(&path)?;
+        Ok(Some(path))
+    }
+
+    /// Upload `image` with `detections` outlined to the artifact store, if one is configured
+    pub async fn publish_annotated(&self, image_path: &Path, image: &DynamicImage, detections: &[Detection]) -> Result<Option<String>> {
+        let Some(artifacts) = &self.artifacts else { return Ok(None) };
+        let mut png = std::io::Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(annotate(image, detections)).write_to(&mut png, image::ImageOutputFormat::Png)?;
+        let stem = image_path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(|| "image".to_string());
+        let name = format!("{}-{}-detections.png", chrono::Utc::now().format("%Y%m%dT%H%M%S"), stem);
+        let url = artifacts.put(&artifact_key(&["detections", &name]), "image/png", png.into_inner()).await?;
+        Ok(Some(url))
     }
 }
 
+/// Models are expensive to load, so tools built from the same config share one
+fn shared_detector(config: &DetectionConfig) -> Result<Arc<dyn Detector>> {
+    use std::sync::Mutex;
+    static LOADED: Mutex<Vec<(PathBuf, Arc<dyn Detector>)>> = Mutex::new(Vec::new());
+    let mut loaded = LOADED.lock().unwrap();
+    if let Some((_, detector)) = loaded.iter().find(|(path, _)| path == &config.model_path) {
+        return Ok(detector.clone());
+    }
+    let detector = default_detector(config)?;
+    loaded.push((config.model_path.clone(), detector.clone()));
+    Ok(detector)
+}
+
 #[async_trait]
 impl ToolExecutor for ObjectDetectionTool {
     async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
-        let image_path = params.get("image").ok_or_else(|| anyhow!("Missing image path"))?;
-        let weights_path = "Dataset/yolov3.weights"; // Adjust as necessary
-        let cfg_path = "Dataset/yolov3.cfg"; // Adjust as necessary
+        let confidence = params.get("confidence").map(|c| c.parse::<f32>()).transpose()
+            .map_err(|e| anyhow!("Invalid confidence: {}", e))?;
+        let classes = params.get("classes").and_then(|c| parse_classes(c));
+        let annotate = params.get("annotate").map_or(true, |a| a != "false");
+
+        // An explicit path wins; otherwise use the first image attached to the message or task
+        let (image_path, image) = match params.get("image") {
+            Some(path) => {
+                let path = PathBuf::from(path);
+                let image = image::open(&path).map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))?;
+                (path, image)
+            }
+            None => {
+                let attachment = attachments_from_params(&params)?.into_iter()
+                    .find(|a| a.is_image())
+                    .ok_or_else(|| anyhow!("Missing image path or image attachment"))?;
+                let image = image::load_from_memory(&attachment.bytes().await?)
+                    .map_err(|e| anyhow!("Failed to decode attached image: {}", e))?;
+                (PathBuf::from(attachment.name.unwrap_or_else(|| "attachment".to_string())), image)
+            }
+        };
+        let detections = self.detect(image.clone(), confidence, classes.as_ref()).await?;
+        let (annotated, annotated_url) = if annotate {
+            (self.save_annotated(&image_path, &image, &detections)?, self.publish_annotated(&image_path, &image, &detections).await?)
+        } else {
+            (None, None)
+        };
+
+        Ok(json!({
+            "image": image_path,
+            "width": image.width(),
+            "height": image.height(),
+            "detections": detections,
+            "annotated_image": annotated,
+            "annotated_url": annotated_url,
+        }).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two overlapping "person" boxes and one "car", in [attributes, boxes] layout
+    fn raw_output() -> (Vec<f32>, Vec<usize>) {
+        let boxes = [
+            // cx, cy, w, h, person, bicycle, car
+            [50.0, 50.0, 20.0, 20.0, 0.9, 0.0, 0.1],
+            [52.0, 50.0, 20.0, 20.0, 0.8, 0.0, 0.1],
+            [150.0, 100.0, 40.0, 20.0, 0.0, 0.1, 0.7],
+            [10.0, 10.0, 4.0, 4.0, 0.1, 0.0, 0.1],
+        ];
+        let mut output = Vec::new();
+        for attribute in 0..7 {
+            output.extend(boxes.iter().map(|b| b[attribute]));
+        }
+        (output, vec![1, 7, 4])
+    }
+
+    #[test]
+    fn test_decode_filters_and_suppresses() {
+        let (output, shape) = raw_output();
+        let labels = vec!["person".to_string(), "bicycle".to_string(), "car".to_string()];
+        let config = DetectionConfig { labels: labels.clone(), ..Default::default() };
+        let detections = decode_yolov8(&output, &shape, (2.0, 1.0), &config).unwrap();
+        assert_eq!(detections.len(), 2);
+        assert_eq!(detections[0].class, "person");
+        assert_eq!(detections[0].bbox, [80.0, 40.0, 40.0, 20.0]);
+        assert_eq!(detections[1].class, "car");
+
+        let config = DetectionConfig { labels, classes: parse_classes("Car"), ..Default::default() };
+        let detections = decode_yolov8(&output, &shape, (1.0, 1.0), &config).unwrap();
+        assert_eq!(detections.iter().map(|d| d.class.as_str()).collect::<Vec<_>>(), vec!["car"]);
+
+        assert!(decode_yolov8(&output, &[1, 7, 5], (1.0, 1.0), &config).is_err());
+    }
+
+    struct FixedDetector;
+
+    impl Detector for FixedDetector {
+        fn detect(&self, _image: &DynamicImage) -> Result<Vec<Detection>> {
+            Ok(vec![
+                Detection { class: "person".to_string(), class_id: 0, confidence: 0.9, bbox: [2.0, 2.0, 10.0, 10.0] },
+                Detection { class: "cat".to_string(), class_id: 15, confidence: 0.3, bbox: [0.0, 0.0, 4.0, 4.0] },
+            ])
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tool_writes_json_and_annotated_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let image_path = dir.path().join("desk.png");
+        RgbImage::new(32, 32).save(&image_path).unwrap();
+
+        let config = DetectionConfig { results_dir: Some(dir.path().join("results")), ..Default::default() };
+        let tool = ObjectDetectionTool::with_config(config).with_detector(Arc::new(FixedDetector));
+        let output: serde_json::Value = serde_json::from_str(&tool.execute(HashMap::from([
+            ("image".to_string(), image_path.display().to_string()),
+            ("confidence".to_string(), "0.5".to_string()),
+        ])).await.unwrap()).unwrap();
 
-        self.load_yolo_model(weights_path, cfg_path)?;
-        let result = self.perform_detection(image_path)?;
-        Ok(result)
+        assert_eq!(output["detections"].as_array().unwrap().len(), 1);
+        assert_eq!(output["detections"][0]["class"], "person");
+        let annotated = PathBuf::from(output["annotated_image"].as_str().unwrap());
+        let pixel = *image::open(&annotated).unwrap().to_rgb8().get_pixel(2, 5);
+        assert_eq!(pixel, Rgb(PALETTE[0]));
     }
 }
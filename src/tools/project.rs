@@ -1,31 +1,67 @@
 use std::collections::HashMap;
-use std::fs;
 use std::path::Path;
-use std::process::Command;
+use tokio::fs;
+use tokio::process::Command;
+use std::sync::Arc;
 use async_trait::async_trait;
-use crate::tools::ToolExecutor;
+use crate::tools::{artifact_key, artifact_store_or_none, dry_run, ArtifactStore, ToolExecutor};
 use anyhow::{Result, anyhow};
 
-pub struct ProjectTool;
+pub struct ProjectTool {
+    /// Generated projects are archived here when set
+    artifacts: Option<Arc<dyn ArtifactStore>>,
+}
 
 impl ProjectTool {
     pub fn new() -> Self {
-        Self
+        Self { artifacts: artifact_store_or_none() }
+    }
+
+    pub fn with_artifact_store(mut self, artifacts: Arc<dyn ArtifactStore>) -> Self {
+        self.artifacts = Some(artifacts);
+        self
+    }
+
+    /// Uploads `project_dir` as `projects/<type>/<name>.tar.gz` and returns its URL
+    async fn archive_project(&self, project_type: &str, name: &str, project_dir: &Path) -> Result<Option<String>> {
+        let Some(artifacts) = &self.artifacts else { return Ok(None) };
+        let archive = tokio::task::spawn_blocking({
+            let (name, project_dir) = (name.to_string(), project_dir.to_path_buf());
+            move || tar_gz(&name, &project_dir)
+        }).await??;
+        let key = artifact_key(&["projects", project_type, &format!("{}.tar.gz", name)]);
+        Ok(Some(artifacts.put(&key, "application/gzip", archive).await?))
+    }
+
+    /// Files and commands `init_*_project` create for `project_type`, relative to the project directory
+    fn planned_steps(project_type: &str, name: &str) -> Vec<String> {
+        let mut steps = match project_type {
+            "python" => vec![
+                format!("write src/{}/__init__.py", name),
+                "write src/tests/__init__.py".to_string(),
+                "write requirements.txt".to_string(),
+                "write setup.py".to_string(),
+            ],
+            "rust" => vec![format!("run `cargo init --name {}`", name)],
+            _ => vec!["create src/, docs/ and examples/".to_string()],
+        };
+        steps.push("write README.md".to_string());
+        steps
     }
 
-    fn init_python_project(&self, name: &str, description: &str, path: &Path) -> Result<()> {
+    async fn init_python_project(&self, name: &str, description: &str, path: &Path) -> Result<()> {
         // Create project structure
         let src_dir = path.join("src");
-        fs::create_dir_all(&src_dir).map_err(|e| anyhow!("Failed to create src directory: {}", e))?;
-        fs::create_dir_all(src_dir.join(name)).map_err(|e| anyhow!("Failed to create project directory: {}", e))?;
-        fs::create_dir_all(src_dir.join("tests")).map_err(|e| anyhow!("Failed to create tests directory: {}", e))?;
+        fs::create_dir_all(&src_dir).await.map_err(|e| anyhow!("Failed to create src directory: {}", e))?;
+        fs::create_dir_all(src_dir.join(name)).await.map_err(|e| anyhow!("Failed to create project directory: {}", e))?;
+        fs::create_dir_all(src_dir.join("tests")).await.map_err(|e| anyhow!("Failed to create tests directory: {}", e))?;
 
         // Create __init__.py files
-        fs::write(src_dir.join(name).join("__init__.py"), "").map_err(|e| anyhow!("Failed to create __init__.py: {}", e))?;
-        fs::write(src_dir.join("tests").join("__init__.py"), "").map_err(|e| anyhow!("Failed to create test __init__.py: {}", e))?;
+        fs::write(src_dir.join(name).join("__init__.py"), "").await.map_err(|e| anyhow!("Failed to create __init__.py: {}", e))?;
+        fs::write(src_dir.join("tests").join("__init__.py"), "").await.map_err(|e| anyhow!("Failed to create test __init__.py: {}", e))?;
 
         // Create requirements.txt
-        fs::write(path.join("requirements.txt"), "# Core dependencies\n").map_err(|e| anyhow!("Failed to create requirements.txt: {}", e))?;
+        fs::write(path.join("requirements.txt"), "# Core dependencies\n").await.map_err(|e| anyhow!("Failed to create requirements.txt: {}", e))?;
 
         // Create setup.py
         let setup_content = format!(
@@ -41,33 +77,34 @@ setup(
 )"#,
             name
         );
-        fs::write(path.join("setup.py"), setup_content).map_err(|e| anyhow!("Failed to create setup.py: {}", e))?;
+        fs::write(path.join("setup.py"), setup_content).await.map_err(|e| anyhow!("Failed to create setup.py: {}", e))?;
 
-        self.create_readme(name, description, "python", path)?;
+        self.create_readme(name, description, "python", path).await?;
         Ok(())
     }
 
-    fn init_rust_project(&self, name: &str, description: &str, path: &Path) -> Result<()> {
+    async fn init_rust_project(&self, name: &str, description: &str, path: &Path) -> Result<()> {
         Command::new("cargo")
             .args(["init", "--name", name])
             .current_dir(path)
             .output()
+            .await
             .map_err(|e| anyhow!("Failed to initialize Rust project: {}", e))?;
 
-        self.create_readme(name, description, "rust", path)?;
+        self.create_readme(name, description, "rust", path).await?;
         Ok(())
     }
 
-    fn init_common_project(&self, name: &str, description: &str, path: &Path) -> Result<()> {
-        fs::create_dir_all(path.join("src")).map_err(|e| anyhow!("Failed to create src directory: {}", e))?;
-        fs::create_dir_all(path.join("docs")).map_err(|e| anyhow!("Failed to create docs directory: {}", e))?;
-        fs::create_dir_all(path.join("examples")).map_err(|e| anyhow!("Failed to create examples directory: {}", e))?;
+    async fn init_common_project(&self, name: &str, description: &str, path: &Path) -> Result<()> {
+        fs::create_dir_all(path.join("src")).await.map_err(|e| anyhow!("Failed to create src directory: {}", e))?;
+        fs::create_dir_all(path.join("docs")).await.map_err(|e| anyhow!("Failed to create docs directory: {}", e))?;
+        fs::create_dir_all(path.join("examples")).await.map_err(|e| anyhow!("Failed to create examples directory: {}", e))?;
 
-        self.create_readme(name, description, "common", path)?;
+        self.create_readme(name, description, "common", path).await?;
         Ok(())
     }
 
-    fn create_readme(
+    async fn create_readme(
         &self,
         name: &str,
         description: &str,
@@ -121,7 +158,7 @@ This is a {project_type} project created with the project initialization tool.
             _ => {}
         }
 
-        fs::write(path.join("README.md"), content).map_err(|e| anyhow!("Failed to create README.md: {}", e))?;
+        fs::write(path.join("README.md"), content).await.map_err(|e| anyhow!("Failed to create README.md: {}", e))?;
         Ok(())
     }
 }
@@ -129,39 +166,73 @@ This is a {project_type} project created with the project initialization tool.
 #[async_trait]
 impl ToolExecutor for ProjectTool {
     async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
-        let project_type = params.get("type").ok_or_else(|| anyhow!("Missing project type"))?;
-        let name = params.get("name").ok_or_else(|| anyhow!("Missing project name"))?;
-        let description = params.get("description").ok_or_else(|| anyhow!("Missing project description"))?;
+        let (project_type, name, description) = project_params(&params)?;
+        let project_dir = new_project_dir(project_type, name)?;
 
-        // Validate project type
-        if !["python", "rust", "common"].contains(&project_type.as_str()) {
-            return Err(anyhow!("Project type must be one of: python, rust, common"));
-        }
-
-        // Create project directory
-        let base_dir = Path::new("projects").join(project_type);
-        let project_dir = base_dir.join(name);
-
-        if project_dir.exists() {
-            return Err(anyhow!("Project directory {} already exists!", project_dir.display()));
-        }
-
-        fs::create_dir_all(&project_dir).map_err(|e| anyhow!("Failed to create project directory: {}", e))?;
+        fs::create_dir_all(&project_dir).await.map_err(|e| anyhow!("Failed to create project directory: {}", e))?;
 
         // Initialize project based on type
         match project_type.as_str() {
-            "python" => self.init_python_project(name, description, &project_dir)?,
-            "rust" => self.init_rust_project(name, description, &project_dir)?,
-            "common" => self.init_common_project(name, description, &project_dir)?,
+            "python" => self.init_python_project(name, description, &project_dir).await?,
+            "rust" => self.init_rust_project(name, description, &project_dir).await?,
+            "common" => self.init_common_project(name, description, &project_dir).await?,
             _ => unreachable!(),
         }
 
-        Ok(format!(
+        let mut result = format!(
             "Project {} created successfully in {}\nType: {}\nDescription: {}",
             name,
             project_dir.display(),
             project_type,
             description
-        ))
+        );
+        match self.archive_project(project_type, name, &project_dir).await {
+            Ok(Some(url)) => result.push_str(&format!("\nArchive: {}", url)),
+            Ok(None) => {}
+            Err(e) => tracing::warn!("Failed to archive project {}: {}", name, e),
+        }
+        Ok(result)
+    }
+
+    fn dry_run(&self, params: &HashMap<String, String>) -> Result<Option<String>> {
+        let (project_type, name, _) = project_params(params)?;
+        let project_dir = new_project_dir(project_type, name)?;
+        let mut steps = vec![format!("create {}", project_dir.display())];
+        steps.extend(ProjectTool::planned_steps(project_type, name));
+        if self.artifacts.is_some() {
+            steps.push(format!("upload projects/{}/{}.tar.gz to the artifact store", project_type, name));
+        }
+        Ok(Some(dry_run::plan(steps)))
     }
+}
+
+/// The `type`, `name` and `description` params, with the type checked
+fn project_params(params: &HashMap<String, String>) -> Result<(&String, &String, &String)> {
+    let project_type = params.get("type").ok_or_else(|| anyhow!("Missing project type"))?;
+    let name = params.get("name").ok_or_else(|| anyhow!("Missing project name"))?;
+    let description = params.get("description").ok_or_else(|| anyhow!("Missing project description"))?;
+
+    // Validate project type
+    if !["python", "rust", "common"].contains(&project_type.as_str()) {
+        return Err(anyhow!("Project type must be one of: python, rust, common"));
+    }
+    Ok((project_type, name, description))
+}
+
+/// Where a new project goes; it must not exist yet
+fn new_project_dir(project_type: &str, name: &str) -> Result<std::path::PathBuf> {
+    let project_dir = Path::new("projects").join(project_type).join(name);
+    if project_dir.exists() {
+        return Err(anyhow!("Project directory {} already exists!", project_dir.display()));
+    }
+    Ok(project_dir)
+}
+
+/// The contents of `dir` as a gzipped tarball rooted at `name/`
+fn tar_gz(name: &str, dir: &Path) -> Result<Vec<u8>> {
+    let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let mut archive = tar::Builder::new(encoder);
+    archive.append_dir_all(name, dir)
+        .map_err(|e| anyhow!("Failed to archive {}: {}", dir.display(), e))?;
+    Ok(archive.into_inner()?.finish()?)
 } 
@@ -1,20 +1,275 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use async_trait::async_trait;
-use crate::tools::ToolExecutor;
+use crate::mqtt::MqttService;
+use crate::tools::object_detection::{parse_classes, Detection};
+use crate::tools::{NewTodo, ObjectDetectionTool, TodoStore, ToolExecutor};
 use anyhow::{Result, anyhow};
-use image::{DynamicImage, GenericImageView};
+use chrono::{DateTime, Utc};
+use image::DynamicImage;
 use screenshots::Screen;
-use std::path::Path;
-use std::fs;
-use std::io;
-use std::error::Error;
-use crate::types::Tool;
+use serde::Serialize;
+use serde_json::json;
+use tokio::task::JoinHandle;
 
-pub struct ScreenshotDetectionTool;
+pub const DEFAULT_WATCH_INTERVAL: Duration = Duration::from_secs(2);
+pub const DEFAULT_TODO_PROJECT: &str = "madness_interactive";
+const IMAGE_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg"];
+
+/// This machine's name for `detections/<host>`: `DETECTION_HOST`, then
+/// `HOSTNAME`, then `/etc/hostname`
+pub fn detection_host() -> String {
+    env::var("DETECTION_HOST")
+        .or_else(|_| env::var("HOSTNAME"))
+        .ok()
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+/// Topic detections from `host` are published to
+pub fn detections_topic(host: &str) -> String {
+    format!("detections/{}", host)
+}
+
+/// What to watch and what to do with the results
+#[derive(Debug, Clone)]
+pub struct WatchConfig {
+    pub dir: PathBuf,
+    pub poll_interval: Duration,
+    pub host: String,
+    /// Detections of these classes become todos; none do when `None`
+    pub todo_classes: Option<HashSet<String>>,
+    pub todo_project: String,
+}
+
+impl WatchConfig {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            poll_interval: DEFAULT_WATCH_INTERVAL,
+            host: detection_host(),
+            todo_classes: None,
+            todo_project: DEFAULT_TODO_PROJECT.to_string(),
+        }
+    }
+
+    /// Reads `SCREENSHOT_WATCH_DIR` (`None` when unset), `SCREENSHOT_WATCH_INTERVAL_SECS`,
+    /// `SCREENSHOT_TODO_CLASSES` and `SCREENSHOT_TODO_PROJECT`
+    pub fn from_env() -> Option<Self> {
+        let dir = env::var("SCREENSHOT_WATCH_DIR").ok().filter(|d| !d.trim().is_empty())?;
+        let mut config = Self::new(dir.trim());
+        if let Some(secs) = env::var("SCREENSHOT_WATCH_INTERVAL_SECS").ok().and_then(|s| s.parse::<u64>().ok()) {
+            config.poll_interval = Duration::from_secs(secs.max(1));
+        }
+        config.todo_classes = env::var("SCREENSHOT_TODO_CLASSES").ok().and_then(|spec| parse_classes(&spec));
+        if let Ok(project) = env::var("SCREENSHOT_TODO_PROJECT") {
+            config.todo_project = project;
+        }
+        Some(config)
+    }
+}
+
+/// Detections for one screenshot, as published to `detections/<host>`
+#[derive(Debug, Clone, Serialize)]
+pub struct DetectionReport {
+    pub host: String,
+    pub image: PathBuf,
+    pub detections: Vec<Detection>,
+    pub annotated_image: Option<PathBuf>,
+    /// Artifact store URL of the annotated image
+    pub annotated_url: Option<String>,
+    pub detected_at: DateTime<Utc>,
+    /// Id of the todo created for this screenshot, if any
+    pub todo_id: Option<String>,
+}
+
+fn is_image(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .map_or(false, |e| IMAGE_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// Polls a directory for new screenshots and runs detection on each once its
+/// size has stopped changing. Files already there when watching starts are skipped.
+pub struct ScreenshotWatcher {
+    detector: Arc<ObjectDetectionTool>,
+    config: WatchConfig,
+    mqtt: Option<MqttService>,
+    todos: Option<Arc<dyn TodoStore>>,
+    seen: HashSet<PathBuf>,
+    /// New files and their size at the last scan, waiting for writes to finish
+    pending: HashMap<PathBuf, u64>,
+    started: bool,
+}
+
+impl ScreenshotWatcher {
+    pub fn new(detector: Arc<ObjectDetectionTool>, config: WatchConfig) -> Self {
+        Self { detector, config, mqtt: None, todos: None, seen: HashSet::new(), pending: HashMap::new(), started: false }
+    }
+
+    pub fn with_mqtt(mut self, mqtt: MqttService) -> Self {
+        self.mqtt = Some(mqtt);
+        self
+    }
+
+    /// Where todos for `todo_classes` detections are created
+    pub fn with_todo_store(mut self, todos: Arc<dyn TodoStore>) -> Self {
+        self.todos = Some(todos);
+        self
+    }
+
+    /// Screenshots that have finished writing since the last scan
+    pub fn scan(&mut self) -> Result<Vec<PathBuf>> {
+        let mut files: Vec<(PathBuf, u64)> = std::fs::read_dir(&self.config.dir)
+            .map_err(|e| anyhow!("Failed to read {}: {}", self.config.dir.display(), e))?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| is_image(&entry.path()))
+            .filter_map(|entry| entry.metadata().ok().filter(|m| m.is_file()).map(|m| (entry.path(), m.len())))
+            .collect();
+        files.sort();
+
+        if !self.started {
+            self.started = true;
+            self.seen.extend(files.into_iter().map(|(path, _)| path));
+            return Ok(Vec::new());
+        }
+
+        let mut ready = Vec::new();
+        for (path, size) in files {
+            if self.seen.contains(&path) {
+                continue;
+            }
+            match self.pending.insert(path.clone(), size) {
+                Some(previous) if previous == size && size > 0 => {
+                    self.pending.remove(&path);
+                    self.seen.insert(path.clone());
+                    ready.push(path);
+                }
+                _ => {}
+            }
+        }
+        Ok(ready)
+    }
+
+    /// Detect objects in `path`, publish the report and create a todo if configured
+    pub async fn process(&self, path: &Path) -> Result<DetectionReport> {
+        let image = image::open(path).map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))?;
+        let detections = self.detector.detect(image.clone(), None, None).await?;
+        let annotated_image = self.detector.save_annotated(path, &image, &detections)?;
+        let annotated_url = self.detector.publish_annotated(path, &image, &detections).await?;
+        let mut report = DetectionReport {
+            host: self.config.host.clone(),
+            image: path.to_path_buf(),
+            detections,
+            annotated_image,
+            annotated_url,
+            detected_at: Utc::now(),
+            todo_id: None,
+        };
+        report.todo_id = self.create_todo(&report).await?;
+
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.publish_json(detections_topic(&self.config.host), &report).await?;
+        }
+        Ok(report)
+    }
+
+    async fn create_todo(&self, report: &DetectionReport) -> Result<Option<String>> {
+        let (Some(todos), Some(classes)) = (&self.todos, &self.config.todo_classes) else { return Ok(None) };
+        let mut matched: Vec<&str> = report.detections.iter()
+            .map(|d| d.class.as_str())
+            .filter(|class| classes.contains(&class.to_lowercase()))
+            .collect();
+        matched.sort();
+        matched.dedup();
+        if matched.is_empty() {
+            return Ok(None);
+        }
+
+        let file = report.image.file_name().map(|f| f.to_string_lossy().into_owned()).unwrap_or_default();
+        let metadata = HashMap::from([
+            ("source".to_string(), json!("screenshot_watch")),
+            ("host".to_string(), json!(report.host)),
+            ("image".to_string(), json!(report.image)),
+            ("annotated_image".to_string(), json!(report.annotated_image)),
+            ("annotated_url".to_string(), json!(report.annotated_url)),
+            ("detections".to_string(), serde_json::to_value(&report.detections)?),
+        ]);
+        let id = todos.add(NewTodo {
+            description: format!("Review screenshot {} from {}: detected {}", file, report.host, matched.join(", ")),
+            project: self.config.todo_project.clone(),
+            priority: "medium".to_string(),
+            target_agent: "user".to_string(),
+            metadata: Some(metadata),
+        }).await?;
+        Ok(Some(id))
+    }
+
+    /// Scan and process until the task is dropped. Errors on one screenshot
+    /// are logged and don't stop the watch.
+    pub async fn run(mut self) {
+        tracing::info!("Watching {} for screenshots every {:?}", self.config.dir.display(), self.config.poll_interval);
+        let mut ticker = tokio::time::interval(self.config.poll_interval);
+        loop {
+            ticker.tick().await;
+            let ready = match self.scan() {
+                Ok(ready) => ready,
+                Err(e) => {
+                    tracing::warn!("Screenshot scan failed: {}", e);
+                    continue;
+                }
+            };
+            for path in ready {
+                match self.process(&path).await {
+                    Ok(report) => tracing::info!("{}: {} detection(s)", path.display(), report.detections.len()),
+                    Err(e) => tracing::warn!("Detection failed for {}: {}", path.display(), e),
+                }
+            }
+        }
+    }
+}
+
+/// Captures the screen and detects objects on it, or with `command=watch`
+/// watches a directory of screenshots in the background.
+///
+/// Params: `command` (`capture`, the default, `watch` or `unwatch`) and
+/// `dir` for `watch`/`unwatch` (defaults to `SCREENSHOT_WATCH_DIR`).
+pub struct ScreenshotDetectionTool {
+    detector: Arc<ObjectDetectionTool>,
+    mqtt: Option<MqttService>,
+    todos: Option<Arc<dyn TodoStore>>,
+    watches: Mutex<HashMap<PathBuf, JoinHandle<()>>>,
+}
 
 impl ScreenshotDetectionTool {
     pub fn new() -> Self {
-        Self
+        Self {
+            detector: Arc::new(ObjectDetectionTool::new()),
+            mqtt: None,
+            todos: None,
+            watches: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_detector(mut self, detector: ObjectDetectionTool) -> Self {
+        self.detector = Arc::new(detector);
+        self
+    }
+
+    /// Publish watch results over `mqtt`
+    pub fn with_mqtt(mut self, mqtt: MqttService) -> Self {
+        self.mqtt = Some(mqtt);
+        self
+    }
+
+    pub fn with_todo_store(mut self, todos: Arc<dyn TodoStore>) -> Self {
+        self.todos = Some(todos);
+        self
     }
 
     pub async fn capture_screen(&self) -> Result<DynamicImage> {
@@ -28,17 +283,143 @@ impl ScreenshotDetectionTool {
         }
     }
 
-    pub async fn detect_objects(&self, _image: &DynamicImage) -> Result<Vec<String>> {
-        // Placeholder for object detection logic
-        Ok(vec!["object1".to_string(), "object2".to_string()])
+    pub async fn detect_objects(&self, image: &DynamicImage) -> Result<Vec<Detection>> {
+        self.detector.detect(image.clone(), None, None).await
+    }
+
+    /// A watcher for `config` sharing this tool's detector, MQTT client and todo store
+    pub fn watcher(&self, config: WatchConfig) -> ScreenshotWatcher {
+        let mut watcher = ScreenshotWatcher::new(self.detector.clone(), config);
+        watcher.mqtt = self.mqtt.clone();
+        watcher.todos = self.todos.clone();
+        watcher
+    }
+
+    /// Start watching `config.dir` in the background; `false` if it's already watched
+    pub fn watch(&self, config: WatchConfig) -> bool {
+        let mut watches = self.watches.lock().unwrap();
+        watches.retain(|_, handle| !handle.is_finished());
+        if watches.contains_key(&config.dir) {
+            return false;
+        }
+        let dir = config.dir.clone();
+        watches.insert(dir, tokio::spawn(self.watcher(config).run()));
+        true
+    }
+
+    pub fn unwatch(&self, dir: &Path) -> bool {
+        match self.watches.lock().unwrap().remove(dir) {
+            Some(handle) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn watch_config(params: &HashMap<String, String>) -> Result<WatchConfig> {
+        let env_config = WatchConfig::from_env();
+        match (params.get("dir"), env_config) {
+            (Some(dir), Some(config)) => Ok(WatchConfig { dir: PathBuf::from(dir), ..config }),
+            (Some(dir), None) => Ok(WatchConfig::new(dir)),
+            (None, Some(config)) => Ok(config),
+            (None, None) => Err(anyhow!("Missing dir parameter and SCREENSHOT_WATCH_DIR is unset")),
+        }
     }
 }
 
 #[async_trait::async_trait]
 impl ToolExecutor for ScreenshotDetectionTool {
-    async fn execute(&self, _params: HashMap<String, String>) -> Result<String> {
-        let screenshot = self.capture_screen().await?;
-        let analysis_result = self.detect_objects(&screenshot).await?;
-        Ok(analysis_result.join(", "))
+    async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+        match params.get("command").map(String::as_str).unwrap_or("capture") {
+            "capture" => {
+                let screenshot = self.capture_screen().await?;
+                let detections = self.detect_objects(&screenshot).await?;
+                Ok(serde_json::to_string(&detections)?)
+            }
+            "watch" => {
+                let config = Self::watch_config(&params)?;
+                if !config.dir.is_dir() {
+                    return Err(anyhow!("{} is not a directory", config.dir.display()));
+                }
+                let dir = config.dir.display().to_string();
+                let topic = detections_topic(&config.host);
+                Ok(if self.watch(config) {
+                    format!("Watching {}; results go to {}", dir, topic)
+                } else {
+                    format!("Already watching {}", dir)
+                })
+            }
+            "unwatch" => {
+                let config = Self::watch_config(&params)?;
+                Ok(if self.unwatch(&config.dir) {
+                    format!("Stopped watching {}", config.dir.display())
+                } else {
+                    format!("Not watching {}", config.dir.display())
+                })
+            }
+            other => Err(anyhow!("Unknown screenshot command '{}'. Use 'capture', 'watch' or 'unwatch'", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::object_detection::{DetectionConfig, Detector};
+    use crate::tools::TodoQuery;
+    use crate::types::TodoTask;
+    use serde_json::Value;
+
+    struct LaptopDetector;
+
+    impl Detector for LaptopDetector {
+        fn detect(&self, _image: &DynamicImage) -> Result<Vec<Detection>> {
+            Ok(vec![Detection { class: "laptop".to_string(), class_id: 63, confidence: 0.8, bbox: [0.0, 0.0, 2.0, 2.0] }])
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordingStore(Mutex<Vec<NewTodo>>);
+
+    #[async_trait]
+    impl TodoStore for RecordingStore {
+        fn name(&self) -> &'static str { "recording" }
+        async fn add(&self, todo: NewTodo) -> Result<String> {
+            self.0.lock().unwrap().push(todo);
+            Ok("todo-1".to_string())
+        }
+        async fn query(&self, _query: TodoQuery) -> Result<Vec<TodoTask>> { Ok(vec![]) }
+        async fn update(&self, _id: &str, _updates: HashMap<String, Value>) -> Result<String> { Ok(String::new()) }
+        async fn complete(&self, _id: &str) -> Result<String> { Ok(String::new()) }
+        async fn get(&self, _id: &str) -> Result<TodoTask> { Err(anyhow!("not found")) }
+        async fn delete(&self, _id: &str) -> Result<String> { Ok(String::new()) }
+    }
+
+    #[tokio::test]
+    async fn test_watcher_processes_new_screenshots() {
+        let dir = tempfile::tempdir().unwrap();
+        image::RgbImage::new(4, 4).save(dir.path().join("old.png")).unwrap();
+
+        let detector = ObjectDetectionTool::with_config(DetectionConfig::default()).with_detector(Arc::new(LaptopDetector));
+        let store = Arc::new(RecordingStore::default());
+        let config = WatchConfig { todo_classes: parse_classes("laptop"), host: "desk".to_string(), ..WatchConfig::new(dir.path()) };
+        let mut watcher = ScreenshotWatcher::new(Arc::new(detector), config).with_todo_store(store.clone());
+
+        // Existing files are skipped; new ones wait a scan for their size to settle
+        assert!(watcher.scan().unwrap().is_empty());
+        let new = dir.path().join("new.png");
+        image::RgbImage::new(4, 4).save(&new).unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "not an image").unwrap();
+        assert!(watcher.scan().unwrap().is_empty());
+        assert_eq!(watcher.scan().unwrap(), vec![new.clone()]);
+        assert!(watcher.scan().unwrap().is_empty());
+
+        let report = watcher.process(&new).await.unwrap();
+        assert_eq!(report.detections[0].class, "laptop");
+        assert_eq!(report.todo_id.as_deref(), Some("todo-1"));
+        let todos = store.0.lock().unwrap();
+        assert!(todos[0].description.contains("new.png from desk: detected laptop"));
+        assert_eq!(detections_topic(&report.host), "detections/desk");
     }
 }
@@ -0,0 +1,441 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Component, Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+use crate::tools::{dry_run, ToolExecutor};
+
+/// Read-only commands allowed when `SHELL_TOOL_ALLOW` is unset. Programs that
+/// can start other programs (`find -exec`, `cargo` build scripts, `git`
+/// aliases and hooks) are left out, since they'd sidestep the allowlist.
+pub const DEFAULT_ALLOW: &[&str] = &["ls", "cat", "head", "tail", "wc", "grep", "echo", "pwd"];
+/// Refused even when an allowlist names them
+pub const DEFAULT_DENY: &[&str] = &[
+    "rm", "rmdir", "dd", "mkfs", "shutdown", "reboot", "sudo", "su", "chmod", "chown", "kill", "killall", "sh", "bash", "zsh",
+];
+/// Environment variables passed through to commands; everything else is dropped
+const PASSTHROUGH_ENV: &[&str] = &["PATH", "LANG", "LC_ALL", "TERM"];
+/// Characters that would mean something to a shell. Commands run without one,
+/// so they're refused instead of silently passed as literal arguments.
+const SHELL_OPERATORS: &[char] = &[';', '|', '&', '>', '<', '`', '$', '\n'];
+/// Options that follow symlinks met while walking a directory, where the
+/// argument checks can't see them: program, short flag and long form
+const FOLLOWS_SYMLINKS: &[(&str, char, &str)] = &[
+    ("grep", 'R', "--dereference-recursive"),
+    ("ls", 'L', "--dereference"),
+];
+
+/// What a [`ShellTool`] lets a command do
+#[derive(Debug, Clone)]
+pub struct ShellPolicy {
+    /// Programs that may run; matched on the program's file name
+    pub allow: Vec<String>,
+    /// Programs that may never run; wins over `allow`
+    pub deny: Vec<String>,
+    /// Commands run inside this directory and may not name paths outside it
+    pub root: PathBuf,
+    /// Wall-clock limit before the command is killed
+    pub timeout: Duration,
+    /// CPU-seconds limit (unix only)
+    pub cpu_secs: Option<u64>,
+    /// Bytes of stdout and of stderr kept; the rest is counted and dropped
+    pub max_output: usize,
+}
+
+impl ShellPolicy {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            allow: DEFAULT_ALLOW.iter().map(|s| s.to_string()).collect(),
+            deny: DEFAULT_DENY.iter().map(|s| s.to_string()).collect(),
+            root: root.into(),
+            timeout: Duration::from_secs(30),
+            cpu_secs: Some(10),
+            max_output: 64 * 1024,
+        }
+    }
+
+    /// Reads `SHELL_TOOL_ROOT` (default: the working directory),
+    /// `SHELL_TOOL_ALLOW`/`SHELL_TOOL_DENY` (comma-separated program names),
+    /// `SHELL_TOOL_TIMEOUT_SECS`, `SHELL_TOOL_CPU_SECS` (0 disables) and
+    /// `SHELL_TOOL_MAX_OUTPUT`
+    pub fn from_env() -> Self {
+        fn list(key: &str) -> Option<Vec<String>> {
+            env::var(key).ok().map(|v| v.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
+        }
+        fn number(key: &str) -> Option<u64> {
+            env::var(key).ok().and_then(|v| v.parse().ok())
+        }
+
+        let root = env::var("SHELL_TOOL_ROOT").map(PathBuf::from)
+            .unwrap_or_else(|_| env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+        let mut policy = Self::new(root);
+        if let Some(allow) = list("SHELL_TOOL_ALLOW") {
+            policy.allow = allow;
+        }
+        if let Some(deny) = list("SHELL_TOOL_DENY") {
+            policy.deny.extend(deny);
+        }
+        if let Some(secs) = number("SHELL_TOOL_TIMEOUT_SECS") {
+            policy.timeout = Duration::from_secs(secs.max(1));
+        }
+        if let Some(secs) = number("SHELL_TOOL_CPU_SECS") {
+            policy.cpu_secs = (secs > 0).then_some(secs);
+        }
+        if let Some(bytes) = number("SHELL_TOOL_MAX_OUTPUT") {
+            policy.max_output = bytes as usize;
+        }
+        policy
+    }
+
+    /// Refuse programs that are denied or not allowed
+    fn check_program(&self, program: &str) -> Result<()> {
+        let name = program_name(program);
+        if self.deny.iter().any(|denied| denied == name) {
+            return Err(anyhow!("'{}' is not permitted", name));
+        }
+        if !self.allow.iter().any(|allowed| allowed == name) {
+            return Err(anyhow!("'{}' is not on the shell tool's allowlist", name));
+        }
+        Ok(())
+    }
+
+    /// `path` resolved against `cwd`, if that stays inside the root once
+    /// every symlink in it is followed
+    fn jail(&self, cwd: &Path, path: &str) -> Result<PathBuf> {
+        let root = self.root.canonicalize().unwrap_or_else(|_| normalize(&self.root));
+        let resolved = resolve(&cwd.join(path))
+            .ok_or_else(|| anyhow!("'{}' is a symlink the shell tool can't follow", path))?;
+        if !resolved.starts_with(&root) {
+            return Err(anyhow!("'{}' is outside the shell tool's root {}", path, self.root.display()));
+        }
+        Ok(resolved)
+    }
+}
+
+/// Refuse options that would have `program` follow symlinks while recursing
+fn check_options(program: &str, args: &[String]) -> Result<()> {
+    let name = program_name(program);
+    for (_, short, long) in FOLLOWS_SYMLINKS.iter().filter(|(follower, ..)| *follower == name) {
+        // Long options may be abbreviated to any unambiguous prefix
+        let follows = |arg: &String| match arg.strip_prefix("--") {
+            Some(_) => {
+                let option = arg.split_once('=').map_or(arg.as_str(), |(option, _)| option);
+                option.len() > 2 && long.starts_with(option)
+            }
+            None => arg.starts_with('-') && arg.contains(*short),
+        };
+        if let Some(arg) = args.iter().take_while(|arg| *arg != "--").find(|arg| follows(arg)) {
+            return Err(anyhow!("'{}' would have {} follow symlinks out of the shell tool's root", arg, name));
+        }
+    }
+    Ok(())
+}
+
+fn program_name(program: &str) -> &str {
+    Path::new(program).file_name().and_then(|n| n.to_str()).unwrap_or(program)
+}
+
+/// The value glued to an option: `value` in `--opt=value`, and `value` in
+/// `-Xvalue`
+fn option_value(arg: &str) -> Option<&str> {
+    if let Some(long) = arg.strip_prefix("--") {
+        return long.split_once('=').map(|(_, value)| value);
+    }
+    let short = arg.strip_prefix('-')?;
+    let mut chars = short.chars();
+    chars.next()?;
+    Some(chars.as_str()).filter(|value| !value.is_empty())
+}
+
+/// Resolve `.` and `..` without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// `path` with its symlinks followed: the longest part that exists is
+/// canonicalized and the rest normalized. `None` if a part exists but can't be
+/// canonicalized, like a symlink whose target is missing.
+fn resolve(path: &Path) -> Option<PathBuf> {
+    let components: Vec<Component> = path.components().collect();
+    for split in (1..=components.len()).rev() {
+        let existing: PathBuf = components[..split].iter().collect();
+        match existing.canonicalize() {
+            Ok(canonical) => return Some(normalize(&components[split..].iter().fold(canonical, |path, part| path.join(part)))),
+            Err(_) if existing.symlink_metadata().is_ok() => return None,
+            Err(_) => {}
+        }
+    }
+    Some(normalize(path))
+}
+
+/// Split a command line into words, honouring single and double quotes
+pub fn split_command(command: &str) -> Result<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+    for c in command.chars() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), c) => word.push(c),
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_word = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            (None, c) if SHELL_OPERATORS.contains(&c) => {
+                return Err(anyhow!("Shell operators like '{}' are not supported; run one command at a time", c));
+            }
+            (None, c) => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err(anyhow!("Unterminated quote in command"));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Keep the first `limit` bytes of a stream, draining the rest so the child never blocks
+async fn read_capped<R: AsyncRead + Unpin>(mut reader: R, limit: usize) -> (Vec<u8>, usize) {
+    let mut kept = Vec::new();
+    let mut dropped = 0;
+    let mut buffer = [0u8; 8192];
+    while let Ok(read) = reader.read(&mut buffer).await {
+        if read == 0 {
+            break;
+        }
+        let room = limit.saturating_sub(kept.len()).min(read);
+        kept.extend_from_slice(&buffer[..room]);
+        dropped += read - room;
+    }
+    (kept, dropped)
+}
+
+fn render(bytes: &[u8], dropped: usize) -> String {
+    let text = String::from_utf8_lossy(bytes).into_owned();
+    if dropped > 0 {
+        format!("{}\n[{} more bytes truncated]", text, dropped)
+    } else {
+        text
+    }
+}
+
+/// Runs allowlisted commands without a shell, inside a root directory, with a
+/// scrubbed environment, time and CPU limits and capped output.
+///
+/// Parameters: `command` (required) and `cwd`, relative to the root.
+pub struct ShellTool {
+    policy: ShellPolicy,
+}
+
+impl ShellTool {
+    pub fn new(policy: ShellPolicy) -> Self {
+        Self { policy }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(ShellPolicy::from_env())
+    }
+
+    pub fn policy(&self) -> &ShellPolicy {
+        &self.policy
+    }
+
+    async fn run(&self, command: &str, cwd: Option<&str>) -> Result<String> {
+        let words = split_command(command)?;
+        let (program, args) = words.split_first().ok_or_else(|| anyhow!("Empty command"))?;
+        self.policy.check_program(program)?;
+        check_options(program, args)?;
+
+        let cwd = self.policy.jail(&self.policy.root, cwd.unwrap_or("."))?;
+        for arg in args {
+            // Any argument may name a path, so each must resolve inside the
+            // root, as must the value of `--opt=value` and `-Xvalue`
+            self.policy.jail(&cwd, arg)?;
+            if let Some(value) = option_value(arg) {
+                self.policy.jail(&cwd, value)?;
+            }
+        }
+
+        let mut cmd = match self.policy.cpu_secs {
+            #[cfg(unix)]
+            Some(cpu_secs) => {
+                // ulimit applies to the exec'd program; "$@" keeps the arguments unparsed
+                let mut cmd = Command::new("/bin/sh");
+                cmd.arg("-c").arg("ulimit -t \"$0\" && exec \"$@\"").arg(cpu_secs.to_string()).arg(program).args(args);
+                cmd
+            }
+            _ => {
+                let mut cmd = Command::new(program);
+                cmd.args(args);
+                cmd
+            }
+        };
+        cmd.current_dir(&cwd)
+            .env_clear()
+            .envs(PASSTHROUGH_ENV.iter().filter_map(|key| env::var(key).ok().map(|value| (*key, value))))
+            .env("HOME", &self.policy.root)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let mut child = cmd.spawn().map_err(|e| anyhow!("Failed to start {}: {}", program, e))?;
+        let stdout = child.stdout.take().ok_or_else(|| anyhow!("No stdout"))?;
+        let stderr = child.stderr.take().ok_or_else(|| anyhow!("No stderr"))?;
+        let limit = self.policy.max_output;
+
+        let finished = tokio::time::timeout(self.policy.timeout, async {
+            let (stdout, stderr, status) = tokio::join!(read_capped(stdout, limit), read_capped(stderr, limit), child.wait());
+            (stdout, stderr, status)
+        }).await;
+        let ((stdout, stdout_dropped), (stderr, stderr_dropped), status) = match finished {
+            Ok(finished) => finished,
+            Err(_) => return Err(anyhow!("Command timed out after {}s and was killed", self.policy.timeout.as_secs())),
+        };
+        let status = status?;
+
+        let stdout = render(&stdout, stdout_dropped);
+        let stderr = render(&stderr, stderr_dropped);
+        if status.success() {
+            Ok(if stderr.is_empty() { stdout } else { format!("{}\n[stderr]\n{}", stdout, stderr) })
+        } else {
+            Err(anyhow!("Command exited with {}: {}", status, if stderr.is_empty() { stdout } else { stderr }))
+        }
+    }
+}
+
+#[async_trait]
+impl ToolExecutor for ShellTool {
+    async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
+        let command = params.get("command").ok_or_else(|| anyhow!("Missing command parameter"))?;
+        self.run(command, params.get("cwd").map(String::as_str)).await
+    }
+
+    fn dry_run(&self, params: &HashMap<String, String>) -> Result<Option<String>> {
+        let command = params.get("command").ok_or_else(|| anyhow!("Missing command parameter"))?;
+        let step = match params.get("cwd") {
+            Some(cwd) => format!("run `{}` in {}", command, cwd),
+            None => format!("run `{}`", command),
+        };
+        Ok(Some(dry_run::plan([step])))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn tool(root: &Path) -> ShellTool {
+        let mut policy = ShellPolicy::new(root);
+        policy.allow.push("sleep".to_string());
+        policy.timeout = Duration::from_millis(200);
+        policy.max_output = 16;
+        ShellTool::new(policy)
+    }
+
+    #[test]
+    fn test_option_value() {
+        assert_eq!(option_value("--file=/etc/shadow"), Some("/etc/shadow"));
+        assert_eq!(option_value("--git-dir=../x"), Some("../x"));
+        assert_eq!(option_value("-C/etc"), Some("/etc"));
+        assert_eq!(option_value("--count"), None);
+        assert_eq!(option_value("-n"), None);
+        assert_eq!(option_value("notes.txt"), None);
+    }
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(split_command("grep -n 'two words' \"a b\" c").unwrap(), vec!["grep", "-n", "two words", "a b", "c"]);
+        assert_eq!(split_command("echo ''").unwrap(), vec!["echo", ""]);
+        assert!(split_command("echo hi; rm -rf /").is_err());
+        assert!(split_command("echo $HOME").is_err());
+        assert!(split_command("echo 'unterminated").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_policy_is_enforced() {
+        let dir = tempfile::tempdir().unwrap();
+        let tool = tool(dir.path());
+
+        assert!(tool.run("rm -rf .", None).await.unwrap_err().to_string().contains("not permitted"));
+        assert!(tool.run("python3 -c 1", None).await.unwrap_err().to_string().contains("allowlist"));
+        assert!(tool.run("cat /etc/passwd", None).await.unwrap_err().to_string().contains("outside"));
+        assert!(tool.run("ls ../..", None).await.is_err());
+        assert!(tool.run("ls", Some("..")).await.is_err());
+        assert!(tool.run("git log", None).await.unwrap_err().to_string().contains("allowlist"));
+        // Paths glued to options are checked too
+        assert!(tool.run("grep --file=/etc/passwd x", None).await.unwrap_err().to_string().contains("outside"));
+        assert!(tool.run("grep -f/etc/passwd x", None).await.unwrap_err().to_string().contains("outside"));
+        assert!(tool.run("ls -C../..", None).await.unwrap_err().to_string().contains("outside"));
+        assert!(tool.run("sleep 5", None).await.unwrap_err().to_string().contains("timed out"));
+    }
+
+    #[tokio::test]
+    async fn test_symlinks_cannot_lead_out() {
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret"), "secret").unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sub")).unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "hello").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret"), dir.path().join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("sub/out")).unwrap();
+        std::os::unix::fs::symlink(outside.path().join("missing"), dir.path().join("dangling")).unwrap();
+        std::os::unix::fs::symlink("notes.txt", dir.path().join("inside")).unwrap();
+        let tool = tool(dir.path());
+
+        assert!(tool.run("cat link", None).await.unwrap_err().to_string().contains("outside"));
+        assert!(tool.run("head -n1 link", None).await.unwrap_err().to_string().contains("outside"));
+        assert!(tool.run("cat sub/out/secret", None).await.unwrap_err().to_string().contains("outside"));
+        assert!(tool.run("cat out/../secret", Some("sub")).await.unwrap_err().to_string().contains("outside"));
+        assert!(tool.run("ls", Some("sub/out")).await.unwrap_err().to_string().contains("outside"));
+        assert!(tool.run("grep --file=link x", None).await.unwrap_err().to_string().contains("outside"));
+        assert!(tool.run("cat dangling", None).await.unwrap_err().to_string().contains("can't follow"));
+        // Recursing would follow symlinks below the arguments
+        assert!(tool.run("grep -R secret .", None).await.unwrap_err().to_string().contains("follow symlinks"));
+        assert!(tool.run("grep -rR secret .", None).await.is_err());
+        assert!(tool.run("grep --dereference-rec secret .", None).await.is_err());
+        assert!(tool.run("ls -RL", None).await.is_err());
+        assert!(tool.run("grep -- -R notes.txt", None).await.unwrap_err().to_string().contains("exited"));
+        // Symlinks that stay inside the root still work
+        assert_eq!(tool.run("cat inside", None).await.unwrap(), "hello");
+        assert_eq!(tool.run("cat sub/../notes.txt", None).await.unwrap(), "hello");
+    }
+
+    #[tokio::test]
+    async fn test_runs_in_root_with_capped_output() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "hello").unwrap();
+        let tool = tool(dir.path());
+
+        assert_eq!(tool.run("cat notes.txt", None).await.unwrap(), "hello");
+        let output = tool.run("echo 'a much longer line than sixteen bytes'", None).await.unwrap();
+        assert!(output.starts_with("a much longer li"));
+        assert!(output.contains("more bytes truncated"));
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+use crate::ai::AiProvider;
+
+/// Outputs longer than this many characters are summarized by default
+pub const DEFAULT_OUTPUT_THRESHOLD: usize = 8_000;
+
+/// Condenses an oversized tool output before it is handed back to an agent.
+#[async_trait]
+pub trait OutputSummarizer: Send + Sync {
+    async fn summarize(&self, tool_name: &str, output: &str) -> Result<String>;
+}
+
+/// Keeps the head and tail of the output and drops the middle.
+pub struct TruncatingSummarizer {
+    max_chars: usize,
+}
+
+impl TruncatingSummarizer {
+    pub fn new(max_chars: usize) -> Self {
+        Self { max_chars }
+    }
+}
+
+impl Default for TruncatingSummarizer {
+    fn default() -> Self {
+        Self::new(DEFAULT_OUTPUT_THRESHOLD)
+    }
+}
+
+#[async_trait]
+impl OutputSummarizer for TruncatingSummarizer {
+    async fn summarize(&self, _tool_name: &str, output: &str) -> Result<String> {
+        Ok(truncate_middle(output, self.max_chars))
+    }
+}
+
+/// Asks an AI provider for a summary, falling back to truncation on failure.
+pub struct AiSummarizer {
+    ai_client: Box<dyn AiProvider + Send + Sync>,
+    max_input_chars: usize,
+}
+
+impl AiSummarizer {
+    pub fn new<T: AiProvider + Send + Sync + 'static>(client: T) -> Self {
+        Self {
+            ai_client: Box::new(client),
+            max_input_chars: DEFAULT_OUTPUT_THRESHOLD * 4,
+        }
+    }
+
+    pub fn with_max_input_chars(mut self, max_input_chars: usize) -> Self {
+        self.max_input_chars = max_input_chars;
+        self
+    }
+}
+
+#[async_trait]
+impl OutputSummarizer for AiSummarizer {
+    async fn summarize(&self, tool_name: &str, output: &str) -> Result<String> {
+        let system_prompt = "You summarize command and tool output for another AI agent. \
+            Keep errors, warnings, counts, names, and anything actionable. Drop repetition. \
+            Output ONLY the summary.";
+
+        let messages = vec![HashMap::from([
+            ("role".to_string(), "user".to_string()),
+            ("content".to_string(), format!(
+                "Summarize the output of the '{}' tool:\n\n{}",
+                tool_name,
+                truncate_middle(output, self.max_input_chars)
+            )),
+        ])];
+
+        match self.ai_client.chat(system_prompt, messages).await {
+            Ok(summary) if !summary.trim().is_empty() => Ok(summary),
+            Ok(_) => Err(anyhow!("AI summarizer returned an empty summary")),
+            Err(e) => {
+                tracing::warn!("AI summarization of {} output failed, truncating instead: {}", tool_name, e);
+                Ok(truncate_middle(output, DEFAULT_OUTPUT_THRESHOLD))
+            }
+        }
+    }
+}
+
+/// Writes full tool outputs to disk so summarized responses can link to them.
+#[derive(Debug, Clone)]
+pub struct ArtifactArchive {
+    dir: PathBuf,
+}
+
+impl ArtifactArchive {
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Uses `SWARM_ARTIFACT_DIR`, or a directory under the system temp dir.
+    pub fn from_env() -> Self {
+        let dir = std::env::var("SWARM_ARTIFACT_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| std::env::temp_dir().join("swarmonomicon").join("artifacts"));
+        Self::new(dir)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub async fn store(&self, tool_name: &str, output: &str) -> Result<PathBuf> {
+        tokio::fs::create_dir_all(&self.dir).await
+            .map_err(|e| anyhow!("Failed to create artifact directory: {}", e))?;
+
+        let file_name = format!(
+            "{}-{}-{}.txt",
+            sanitize_file_component(tool_name),
+            chrono::Utc::now().format("%Y%m%dT%H%M%S"),
+            uuid::Uuid::new_v4()
+        );
+        let path = self.dir.join(file_name);
+        tokio::fs::write(&path, output).await
+            .map_err(|e| anyhow!("Failed to archive tool output: {}", e))?;
+
+        Ok(path)
+    }
+}
+
+fn sanitize_file_component(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+/// Shortens `text` to roughly `max_chars`, keeping the beginning and end.
+pub fn truncate_middle(text: &str, max_chars: usize) -> String {
+    let total = text.chars().count();
+    if total <= max_chars {
+        return text.to_string();
+    }
+
+    let head_len = max_chars / 2;
+    let tail_len = max_chars - head_len;
+    let head: String = text.chars().take(head_len).collect();
+    let tail: String = text.chars().skip(total - tail_len).collect();
+
+    format!(
+        "{}\n... [{} characters omitted] ...\n{}",
+        head,
+        total - head_len - tail_len,
+        tail
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_middle() {
+        assert_eq!(truncate_middle("short", 10), "short");
+
+        let text = "a".repeat(50) + &"b".repeat(50);
+        let truncated = truncate_middle(&text, 20);
+        assert!(truncated.starts_with(&"a".repeat(10)));
+        assert!(truncated.ends_with(&"b".repeat(10)));
+        assert!(truncated.contains("[80 characters omitted]"));
+    }
+
+    #[tokio::test]
+    async fn test_archive_store() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let archive = ArtifactArchive::new(dir.path());
+
+        let path = archive.store("git/log", "full output").await?;
+        assert!(path.starts_with(dir.path()));
+        assert!(path.file_name().unwrap().to_string_lossy().starts_with("git_log-"));
+        assert_eq!(tokio::fs::read_to_string(&path).await?, "full output");
+        Ok(())
+    }
+}
@@ -1,365 +1,344 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use async_trait::async_trait;
-use chrono::Utc;
-use reqwest;
-use std::time::Duration;
 use futures_util::StreamExt;
-use crate::tools::ToolExecutor;
-use crate::types::{TodoTask, TaskPriority, TaskStatus, projects};
+use crate::clock::{self, Clock};
+use crate::mcp::{McpClient, is_unavailable};
+use crate::mcp::schema::{
+    AddTodoRequest, McpResponse, OmnispindleTodo, QueryTodosData, QueryTodosRequest, TodoIdRequest, UpdateTodoRequest,
+};
+use crate::tools::{dry_run, ToolExecutor};
+use crate::tools::todo_outbox::{PendingOperation, TodoOutbox};
+use crate::tools::todo_audit::{AuditedTodoStore, TodoAuditLog};
+use crate::tools::todo_store::{MongoTodoStore, NewTodo, TodoQuery, TodoStore};
+use crate::types::{TodoTask, TaskPriority, TaskStatus, MCP_PRIORITY_KEY, projects, derive_idempotency_key, idempotency_window_secs};
 use anyhow::{Result, anyhow};
 use serde_json::Value;
-use uuid::Uuid;
 use regex::Regex;
 use crate::ai::{AiProvider, DefaultAiClient, LocalAiClient};
 use serde::{Serialize, Deserialize};
 // use langgraph::{Graph, Node};
 
-// MCP Server request/response structures
-#[derive(Debug, Serialize, Deserialize)]
-struct McpAddTodoRequest {
-    description: String,
-    project: String,
-    priority: String,
-    target_agent: String,
-    metadata: Option<HashMap<String, serde_json::Value>>,
+/// Todo storage backed by the Omnispindle MCP server's HTTP tool endpoints
+#[derive(Clone)]
+pub struct McpTodoStore {
+    client: McpClient,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct McpUpdateTodoRequest {
-    todo_id: String,
-    updates: HashMap<String, serde_json::Value>,
-}
+impl McpTodoStore {
+    pub fn new(client: McpClient) -> Self {
+        Self { client }
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct McpResponse {
-    success: bool,
-    data: Option<serde_json::Value>,
-    message: Option<String>,
-}
+    /// Uses the process-wide MCP client
+    pub fn from_env() -> Result<Self> {
+        Ok(Self::new(McpClient::shared()?))
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct McpQueryRequest {
-    query_or_filter: Option<String>,
-    fields_or_projection: Option<String>,
-    limit: Option<i32>,
-}
+    pub fn client(&self) -> &McpClient {
+        &self.client
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct McpMarkCompleteRequest {
-    todo_id: String,
-}
+    /// Call a tool that changes todos. Cached queries are dropped even when
+    /// the call fails, since the server may have applied it anyway.
+    async fn write<B: Serialize + ?Sized>(&self, tool: &str, body: &B) -> Result<String> {
+        let result = self.client.call_tool(tool, body).await;
+        self.client.invalidate_cache();
+        result
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct McpGetTodoRequest {
-    todo_id: String,
-}
+    /// Call MCP server's add_todo_tool endpoint
+    async fn call_mcp_add_todo(&self, request_body: AddTodoRequest) -> Result<String> {
+        tracing::debug!("Calling MCP server add_todo_tool with: {:?}", request_body);
+
+        let response_text = self.write("add_todo_tool", &request_body).await?;
+        tracing::debug!("MCP server response: {}", response_text);
+
+        McpResponse::<Value>::parse(&response_text)?.into_message("Todo created")?;
+        tracing::info!("Successfully created todo via MCP server");
+        Ok(response_text)
+    }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct McpDeleteTodoRequest {
-    todo_id: String,
+    /// Call MCP server's query_todos_tool endpoint, through the client's cache
+    async fn call_mcp_query_todos(&self, filter: Option<String>, limit: Option<i32>) -> Result<Vec<TodoTask>> {
+        let request_body = QueryTodosRequest {
+            query_or_filter: filter,
+            fields_or_projection: None,
+            limit,
+        };
+
+        let response_text = self.client.call_tool_cached("query_todos_tool", &request_body).await?;
+        let data = McpResponse::<QueryTodosData>::parse(&response_text)?.into_data()?;
+        Ok(data.items.into_iter().map(TodoTask::from).collect())
+    }
+
+    /// Call MCP server's update_todo_tool endpoint
+    async fn call_mcp_update_todo(&self, todo_id: &str, updates: HashMap<String, Value>) -> Result<String> {
+        let request_body = UpdateTodoRequest {
+            todo_id: todo_id.to_string(),
+            updates,
+        };
+
+        let response_text = self.write("update_todo_tool", &request_body).await?;
+        McpResponse::<Value>::parse(&response_text)?.into_message("Todo updated successfully")
+    }
+
+    /// Call MCP server's mark_todo_complete_tool endpoint
+    async fn call_mcp_mark_complete(&self, todo_id: &str) -> Result<String> {
+        let request_body = TodoIdRequest { todo_id: todo_id.to_string() };
+
+        let response_text = self.write("mark_todo_complete_tool", &request_body).await?;
+        McpResponse::<Value>::parse(&response_text)?.into_message("Todo marked as complete")?;
+        Ok(response_text)
+    }
+
+    /// Call MCP server's get_todo_tool endpoint
+    async fn call_mcp_get_todo(&self, todo_id: &str) -> Result<TodoTask> {
+        let request_body = TodoIdRequest { todo_id: todo_id.to_string() };
+
+        let response_text = self.client.call_tool("get_todo_tool", &request_body).await?;
+        let todo = McpResponse::<OmnispindleTodo>::parse(&response_text)?.into_data()?;
+        Ok(todo.into())
+    }
+
+    /// Call MCP server's delete_todo_tool endpoint
+    async fn call_mcp_delete_todo(&self, todo_id: &str) -> Result<String> {
+        let request_body = TodoIdRequest { todo_id: todo_id.to_string() };
+
+        let response_text = self.write("delete_todo_tool", &request_body).await?;
+        McpResponse::<Value>::parse(&response_text)?.into_message("Todo deleted")?;
+        Ok(response_text)
+    }
 }
 
-// Logging structures to align with Omnispindle schema
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct ChangeEntry {
-    field: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    old_value: Option<serde_json::Value>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    new_value: Option<serde_json::Value>,
+#[async_trait]
+impl TodoStore for McpTodoStore {
+    fn name(&self) -> &'static str {
+        "mcp"
+    }
+
+    fn status_json(&self) -> Value {
+        self.client.status_json()
+    }
+
+    async fn add(&self, todo: NewTodo) -> Result<String> {
+        self.call_mcp_add_todo(AddTodoRequest {
+            description: todo.description,
+            project: todo.project,
+            priority: todo.priority,
+            target_agent: todo.target_agent,
+            metadata: todo.metadata,
+        }).await
+    }
+
+    async fn query(&self, query: TodoQuery) -> Result<Vec<TodoTask>> {
+        let mut filter = serde_json::Map::new();
+        if let Some(description) = query.description {
+            filter.insert("description".to_string(), Value::String(description));
+        }
+        if let Some(key) = query.idempotency_key {
+            filter.insert("metadata.idempotency_key".to_string(), Value::String(key));
+        }
+        let filter = (!filter.is_empty()).then(|| Value::Object(filter).to_string());
+        self.call_mcp_query_todos(filter, Some(query.limit.unwrap_or(100) as i32)).await
+    }
+
+    async fn update(&self, todo_id: &str, updates: HashMap<String, Value>) -> Result<String> {
+        self.call_mcp_update_todo(todo_id, updates).await
+    }
+
+    async fn complete(&self, todo_id: &str) -> Result<String> {
+        self.call_mcp_mark_complete(todo_id).await
+    }
+
+    async fn get(&self, todo_id: &str) -> Result<TodoTask> {
+        self.call_mcp_get_todo(todo_id).await
+    }
+
+    async fn delete(&self, todo_id: &str) -> Result<String> {
+        self.call_mcp_delete_todo(todo_id).await
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-struct LogEntry {
-    timestamp: chrono::DateTime<chrono::Utc>,
-    operation: String,
-    #[serde(rename = "todoId")]
-    todo_id: String,
-    description: String,
-    project: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    changes: Option<Vec<ChangeEntry>>,
-    #[serde(rename = "userAgent")]
-    user_agent: String,
+/// Build the todo store named by `TODO_BACKEND`: `mcp` (default) or `mongo`,
+/// with mutations recorded in the audit log
+pub async fn todo_store_from_env() -> Result<Arc<dyn TodoStore>> {
+    let backend = std::env::var("TODO_BACKEND").unwrap_or_else(|_| "mcp".to_string());
+    let audit = TodoAuditLog::from_env().await?;
+    match backend.trim().to_lowercase().as_str() {
+        "mcp" => Ok(Arc::new(AuditedTodoStore::new(McpTodoStore::from_env()?, audit))),
+        "mongo" | "mongodb" => Ok(Arc::new(AuditedTodoStore::new(MongoTodoStore::from_env().await?, audit))),
+        other => Err(anyhow!("Unknown TODO_BACKEND '{}', expected 'mcp' or 'mongo'", other)),
+    }
 }
 
 #[derive(Clone)]
 pub struct TodoTool {
-    http_client: reqwest::Client,
-    mcp_server_url: String,
+    store: Arc<dyn TodoStore>,
     ai_client: Arc<Box<dyn AiProvider + Send + Sync>>,
+    outbox: Arc<TodoOutbox>,
+    /// Set while writes are being buffered locally instead of reaching MCP
+    degraded: Arc<AtomicBool>,
+    operations_queued: Arc<AtomicU64>,
+    operations_replayed: Arc<AtomicU64>,
+    clock: Arc<dyn Clock>,
 }
 
 impl TodoTool {
     pub async fn new() -> Result<Self> {
-        let mcp_server_url = std::env::var("MCP_SERVER_URL")
-            .unwrap_or_else(|_| "http://localhost:8000".to_string());
-
-        let http_client = reqwest::Client::builder()
-            .timeout(Duration::from_secs(30))
-            .build()
-            .map_err(|e| anyhow!("Failed to create HTTP client: {}", e))?;
+        let store = todo_store_from_env().await?;
+        let outbox = TodoOutbox::from_env();
+        let degraded = !outbox.is_empty().await;
 
         Ok(Self {
-            http_client,
-            mcp_server_url,
+            store,
             ai_client: Arc::new(Box::new(DefaultAiClient::new())),
+            outbox: Arc::new(outbox),
+            degraded: Arc::new(AtomicBool::new(degraded)),
+            operations_queued: Arc::new(AtomicU64::new(0)),
+            operations_replayed: Arc::new(AtomicU64::new(0)),
+            clock: clock::system(),
         })
     }
 
-    pub fn with_ai_client<T: AiProvider + Send + Sync + 'static>(mut self, client: T) -> Self {
-        self.ai_client = Arc::new(Box::new(client));
+    pub fn with_store<S: TodoStore + 'static>(mut self, store: S) -> Self {
+        self.store = Arc::new(store);
         self
     }
 
-    // Normalize project name to align with Omnispindle validation logic
-    fn normalize_project_name(project: &str) -> String {
-        project
-            .trim()
-            .to_lowercase()
-            .replace(' ', "_")
-            .chars()
-            .filter(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
-            .collect()
+    /// Judge idempotency windows and stamp updates with `clock`
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
     }
 
-    /// Call MCP server's add_todo_tool endpoint
-    async fn call_mcp_add_todo(
-        &self,
-        description: String,
-        project: String,
-        priority: String,
-        target_agent: String,
-        metadata: Option<HashMap<String, serde_json::Value>>,
-    ) -> Result<String> {
-        let request_body = McpAddTodoRequest {
-            description,
-            project,
-            priority,
-            target_agent,
-            metadata,
-        };
-
-        tracing::debug!("Calling MCP server add_todo_tool with: {:?}", request_body);
+    /// Name of the backend todos are stored in
+    pub fn backend(&self) -> &'static str {
+        self.store.name()
+    }
 
-        let response = self.http_client
-            .post(&format!("{}/tools/add_todo_tool", self.mcp_server_url))
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|e| anyhow!("Failed to call MCP server: {}", e))?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow!("MCP server returned error {}: {}", status, error_text));
-        }
+    pub fn with_outbox(mut self, outbox: TodoOutbox) -> Self {
+        self.outbox = Arc::new(outbox);
+        self
+    }
 
-        let response_text = response.text().await
-            .map_err(|e| anyhow!("Failed to read MCP response: {}", e))?;
+    /// Whether writes are currently being buffered because MCP is unreachable
+    pub fn is_degraded(&self) -> bool {
+        self.degraded.load(Ordering::Relaxed)
+    }
 
-        tracing::debug!("MCP server response: {}", response_text);
+    /// Degraded-mode state and outbox counters, for metrics reporting
+    pub async fn status_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "backend": self.backend(),
+            "store": self.store.status_json(),
+            "degraded": self.is_degraded(),
+            "queued_operations": self.outbox.len().await,
+            "operations_queued": self.operations_queued.load(Ordering::Relaxed),
+            "operations_replayed": self.operations_replayed.load(Ordering::Relaxed),
+        })
+    }
 
-        // Parse as the actual MCP response format (JSON string)
-        let mcp_response: serde_json::Value = serde_json::from_str(&response_text)
-            .map_err(|e| anyhow!("Failed to parse MCP response as JSON: {}", e))?;
-
-        if let Some(success) = mcp_response.get("success").and_then(|v| v.as_bool()) {
-            if success {
-                tracing::info!("Successfully created todo via MCP server");
-                Ok(response_text)
-            } else {
-                let error_msg = mcp_response.get("message")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("Unknown MCP error");
-                Err(anyhow!("MCP server error: {}", error_msg))
+    /// Replay buffered writes against the MCP server. Returns how many were
+    /// delivered; leaves degraded mode once the outbox is empty.
+    pub async fn flush_outbox(&self) -> Result<usize> {
+        let delivered = self.outbox.replay(|operation| async move {
+            match self.apply(operation).await {
+                Ok(_) => Ok(()),
+                Err(e) if is_unavailable(&e) => Err(e),
+                // Rejected outright (e.g. the todo no longer exists); retrying won't help
+                Err(e) => {
+                    tracing::warn!("Dropping queued todo operation rejected by MCP server: {}", e);
+                    Ok(())
+                }
             }
-        } else {
-            // Assume success if no explicit success field
-            tracing::info!("Todo created via MCP server (assumed success)");
-            Ok(response_text)
+        }).await?;
+
+        if delivered > 0 {
+            tracing::info!("Replayed {} queued todo operations", delivered);
+            self.operations_replayed.fetch_add(delivered as u64, Ordering::Relaxed);
+        }
+        if self.outbox.is_empty().await {
+            self.degraded.store(false, Ordering::Relaxed);
         }
+        Ok(delivered)
     }
 
-    /// Call MCP server's query_todos_tool endpoint
-    async fn call_mcp_query_todos(&self, filter: Option<String>) -> Result<Vec<TodoTask>> {
-        let request_body = McpQueryRequest {
-            query_or_filter: filter,
-            fields_or_projection: None,
-            limit: Some(100),
-        };
-
-        let response = self.http_client
-            .post(&format!("{}/tools/query_todos_tool", self.mcp_server_url))
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|e| anyhow!("Failed to call MCP server: {}", e))?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow!("MCP server returned error {}: {}", status, error_text));
+    /// Send a write to MCP, or buffer it in the outbox if MCP is unreachable.
+    /// Buffered writes are replayed first so operations stay in order.
+    async fn write_through(&self, operation: PendingOperation) -> Result<String> {
+        if !self.outbox.is_empty().await {
+            if let Err(e) = self.flush_outbox().await {
+                tracing::warn!("Failed to replay todo outbox: {}", e);
+            }
         }
 
-        let response_text = response.text().await
-            .map_err(|e| anyhow!("Failed to read MCP response: {}", e))?;
-
-        // Parse the JSON response
-        let mcp_response: serde_json::Value = serde_json::from_str(&response_text)
-            .map_err(|e| anyhow!("Failed to parse MCP response: {}", e))?;
-
-        if let Some(success) = mcp_response.get("success").and_then(|v| v.as_bool()) {
-            if success {
-                if let Some(data) = mcp_response.get("data") {
-                    // Parse the todos from the response data
-                    if let Some(items) = data.get("items") {
-                        let todos: Vec<TodoTask> = serde_json::from_value(items.clone())
-                            .unwrap_or_else(|_| Vec::new());
-                        Ok(todos)
-                    } else {
-                        Ok(Vec::new())
-                    }
-                } else {
-                    Ok(Vec::new())
+        if self.outbox.is_empty().await {
+            match self.apply(operation.clone()).await {
+                Err(e) if is_unavailable(&e) => {
+                    tracing::warn!("MCP server unreachable, entering degraded mode: {}", e);
+                }
+                result => {
+                    self.degraded.store(false, Ordering::Relaxed);
+                    return result;
                 }
-            } else {
-                let error_msg = mcp_response.get("message")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("Unknown MCP error");
-                Err(anyhow!("MCP server error: {}", error_msg))
-            }
-        } else {
-            // Fallback: try to parse todos directly if no success field
-            if let Some(items) = mcp_response.get("items") {
-                let todos: Vec<TodoTask> = serde_json::from_value(items.clone())
-                    .unwrap_or_else(|_| Vec::new());
-                Ok(todos)
-            } else {
-                Ok(Vec::new())
             }
         }
-    }
 
-    /// Call MCP server's update_todo_tool endpoint
-    async fn call_mcp_update_todo(&self, todo_id: &str, updates: HashMap<String, serde_json::Value>) -> Result<String> {
-        let request_body = McpUpdateTodoRequest {
-            todo_id: todo_id.to_string(),
-            updates,
-        };
-
-        let response = self.http_client
-            .post(&format!("{}/tools/update_todo_tool", self.mcp_server_url))
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|e| anyhow!("Failed to call MCP server: {}", e))?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow!("MCP server returned error {}: {}", status, error_text));
-        }
+        let queued = self.outbox.push(operation).await?;
+        self.degraded.store(true, Ordering::Relaxed);
+        self.operations_queued.fetch_add(1, Ordering::Relaxed);
+        Ok(serde_json::json!({
+            "success": true,
+            "degraded": true,
+            "queued": true,
+            "operation_id": queued.id,
+            "queued_operations": self.outbox.len().await,
+            "message": "MCP server unreachable; operation queued for replay"
+        }).to_string())
+    }
 
-        let response_text = response.text().await
-            .map_err(|e| anyhow!("Failed to read MCP response: {}", e))?;
-
-        // Parse as JSON to check for success
-        let mcp_response: serde_json::Value = serde_json::from_str(&response_text)
-            .map_err(|e| anyhow!("Failed to parse MCP response: {}", e))?;
-
-        if let Some(success) = mcp_response.get("success").and_then(|v| v.as_bool()) {
-            if success {
-                Ok(mcp_response.get("message")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("Todo updated successfully").to_string())
-            } else {
-                let error_msg = mcp_response.get("message")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("Unknown MCP error");
-                Err(anyhow!("MCP server error: {}", error_msg))
+    /// Perform a write against the todo store
+    async fn apply(&self, operation: PendingOperation) -> Result<String> {
+        match operation {
+            PendingOperation::Add { description, project, priority, target_agent, metadata } => {
+                // The original call may have landed before the connection dropped
+                let key = metadata.as_ref()
+                    .and_then(|metadata| metadata.get("idempotency_key"))
+                    .and_then(|key| key.as_str());
+                if let Some(key) = key {
+                    if let Some(existing) = self.find_by_idempotency_key(key).await? {
+                        return Ok(serde_json::json!({
+                            "success": true,
+                            "replayed": true,
+                            "data": existing,
+                            "message": "Todo already exists"
+                        }).to_string());
+                    }
+                }
+                self.store.add(NewTodo { description, project, priority, target_agent, metadata }).await
+            }
+            PendingOperation::SetStatus { description, status } => {
+                self.update_todo_status(&description, status).await
             }
-        } else {
-            // Assume success if no explicit success field
-            Ok("Todo updated successfully".to_string())
         }
     }
 
-    /// Call MCP server's mark_todo_complete_tool endpoint
-    async fn call_mcp_mark_complete(&self, todo_id: &str) -> Result<String> {
-        let request_body = McpMarkCompleteRequest {
-            todo_id: todo_id.to_string(),
-        };
-
-        let response = self.http_client
-            .post(&format!("{}/tools/mark_todo_complete_tool", self.mcp_server_url))
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|e| anyhow!("Failed to call MCP server: {}", e))?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow!("MCP server returned error {}: {}", status, error_text));
-        }
-
-        let response_text = response.text().await
-            .map_err(|e| anyhow!("Failed to read MCP response: {}", e))?;
-
-        Ok(response_text)
+    pub fn with_ai_client<T: AiProvider + Send + Sync + 'static>(mut self, client: T) -> Self {
+        self.ai_client = Arc::new(Box::new(client));
+        self
     }
 
-    /// Call MCP server's get_todo_tool endpoint
-    async fn call_mcp_get_todo(&self, todo_id: &str) -> Result<TodoTask> {
-        let request_body = McpGetTodoRequest {
-            todo_id: todo_id.to_string(),
-        };
-
-        let response = self.http_client
-            .post(&format!("{}/tools/get_todo_tool", self.mcp_server_url))
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await
-            .map_err(|e| anyhow!("Failed to call MCP server: {}", e))?;
-
-        if !response.status().is_success() {
-            let status = response.status();
-            let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
-            return Err(anyhow!("MCP server returned error {}: {}", status, error_text));
-        }
-
-        let response_text = response.text().await
-            .map_err(|e| anyhow!("Failed to read MCP response: {}", e))?;
-
-        // Parse the JSON response
-        let mcp_response: serde_json::Value = serde_json::from_str(&response_text)
-            .map_err(|e| anyhow!("Failed to parse MCP response: {}", e))?;
-
-        if let Some(success) = mcp_response.get("success").and_then(|v| v.as_bool()) {
-            if success {
-                if let Some(data) = mcp_response.get("data") {
-                    let todo: TodoTask = serde_json::from_value(data.clone())
-                        .map_err(|e| anyhow!("Failed to parse todo from response: {}", e))?;
-                    Ok(todo)
-                } else {
-                    Err(anyhow!("No todo data in successful response"))
-                }
-            } else {
-                let error_msg = mcp_response.get("message")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("Todo not found");
-                Err(anyhow!("MCP server error: {}", error_msg))
-            }
-        } else {
-            Err(anyhow!("Invalid response format from MCP server"))
-        }
+    // Normalize project name to align with Omnispindle validation logic
+    fn normalize_project_name(project: &str) -> String {
+        project
+            .trim()
+            .to_lowercase()
+            .replace(' ', "_")
+            .chars()
+            .filter(|c| c.is_alphanumeric() || *c == '_' || *c == '-')
+            .collect()
     }
 
     async fn predict_project(&self, description: &str) -> Result<String> {
@@ -378,9 +357,41 @@ impl TodoTool {
         crate::ai::enhance_todo_description(description, self.ai_client.as_ref().as_ref()).await
     }
 
-    async fn add_todo(&self, description: &str, context: Option<&str>, target_agent: &str, project: Option<&str>) -> Result<String> {
+    /// Todo previously created with `key` inside the idempotency window, if any
+    async fn find_by_idempotency_key(&self, key: &str) -> Result<Option<TodoTask>> {
+        let window = idempotency_window_secs();
+        if window <= 0 {
+            return Ok(None);
+        }
+        let query = TodoQuery { idempotency_key: Some(key.to_string()), ..Default::default() };
+        let since = self.clock.timestamp() - window;
+        Ok(self.store.query(query).await?
+            .into_iter()
+            .filter(|todo| todo.created_at >= since)
+            .max_by_key(|todo| todo.created_at))
+    }
+
+    async fn add_todo(&self, description: &str, context: Option<&str>, target_agent: &str, project: Option<&str>, idempotency_key: Option<&str>, requested_priority: Option<TaskPriority>, extra_metadata: Option<serde_json::Map<String, serde_json::Value>>) -> Result<String> {
         tracing::debug!("Adding new todo - Description: {}, Context: {:?}, Target Agent: {}, Project: {:?}", description, context, target_agent, project);
 
+        // Retried requests get the todo they already created
+        let idempotency_key = idempotency_key
+            .map(|key| key.to_string())
+            .unwrap_or_else(|| derive_idempotency_key(description, project));
+        match self.find_by_idempotency_key(&idempotency_key).await {
+            Ok(Some(existing)) => {
+                tracing::info!("Idempotency key {} replayed, returning todo {}", idempotency_key, existing.id);
+                return Ok(serde_json::json!({
+                    "success": true,
+                    "replayed": true,
+                    "data": existing,
+                    "message": "Todo already exists"
+                }).to_string());
+            },
+            Ok(None) => {},
+            Err(e) => tracing::warn!("Failed to check idempotency key {}: {}", idempotency_key, e),
+        }
+
         // Try to enhance the description with AI, fallback to original if enhancement fails
         tracing::debug!("Attempting AI enhancement..");
         let (enhanced_description, priority, predicted_project) = match self.enhance_with_ai(description).await {
@@ -395,40 +406,38 @@ impl TodoTool {
             }
         };
 
+        // Publishers that name a priority know better than the model
+        let priority = requested_priority.unwrap_or(priority);
+
         // Use the provided project if available, otherwise use the predicted one
         let final_project = project.map(|p| p.to_string()).unwrap_or(predicted_project);
         let normalized_project = Self::normalize_project_name(&final_project);
 
-        // Convert priority to string for MCP call
-        let priority_str = match priority {
-            TaskPriority::Low => "Low",
-            TaskPriority::Medium => "Medium",
-            TaskPriority::High => "High",
-            TaskPriority::Critical => "High", // Map Critical to High for MCP
-            TaskPriority::Inital => "Medium", // Map Inital to Medium for MCP
-        };
 
         // Create metadata with source information
-        let mut metadata = HashMap::new();
+        let mut metadata: HashMap<String, serde_json::Value> = extra_metadata.unwrap_or_default().into_iter().collect();
         metadata.insert("source".to_string(), serde_json::Value::String("swarmonomicon_agent".to_string()));
         metadata.insert("created_via".to_string(), serde_json::Value::String("swarmonomicon_todo_tool".to_string()));
         if let Some(ctx) = context {
             metadata.insert("context".to_string(), serde_json::Value::String(ctx.to_string()));
         }
         metadata.insert("enhanced_description".to_string(), serde_json::Value::String(enhanced_description));
+        metadata.insert("idempotency_key".to_string(), serde_json::Value::String(idempotency_key));
+        // MCP has fewer levels; the exact one comes back from here
+        metadata.insert(MCP_PRIORITY_KEY.to_string(), serde_json::Value::String(priority.as_str().to_string()));
 
         tracing::debug!("Calling MCP server to add todo");
-        self.call_mcp_add_todo(
-            description.to_string(),
-            normalized_project,
-            priority_str.to_string(),
-            target_agent.to_string(),
-            Some(metadata)
-        ).await
+        self.write_through(PendingOperation::Add {
+            description: description.to_string(),
+            project: normalized_project,
+            priority: priority.mcp_level().to_string(),
+            target_agent: target_agent.to_string(),
+            metadata: Some(metadata),
+        }).await
     }
 
     async fn list_todos(&self) -> Result<String> {
-        let todos = self.call_mcp_query_todos(None).await?;
+        let todos = self.store.query(TodoQuery::default()).await?;
 
         if todos.is_empty() {
             return Ok("No todos found.".to_string());
@@ -443,11 +452,11 @@ impl TodoTool {
     }
 
     async fn update_todo_status(&self, description: &str, status: TaskStatus) -> Result<String> {
-        let now = Utc::now();
+        let now = self.clock.now();
 
         // First, find the todo by description using query_todos
-        let filter = format!(r#"{{"description": "{}"}}"#, description);
-        let todos = self.call_mcp_query_todos(Some(filter)).await?;
+        let query = TodoQuery { description: Some(description.to_string()), ..Default::default() };
+        let todos = self.store.query(query).await?;
 
         let todo = todos.into_iter().next()
             .ok_or_else(|| anyhow!("Todo with description '{}' not found", description))?;
@@ -455,7 +464,7 @@ impl TodoTool {
         // Handle completion separately using the mark_complete endpoint
         if status == TaskStatus::Completed {
             tracing::debug!("Marking todo as complete using mark_complete endpoint");
-            return self.call_mcp_mark_complete(&todo.id).await;
+            return self.store.complete(&todo.id).await;
         }
 
         // For other status changes, use the update endpoint
@@ -463,8 +472,7 @@ impl TodoTool {
         updates.insert("status".to_string(), serde_json::to_value(&status).unwrap_or(serde_json::Value::Null));
         updates.insert("updated_at".to_string(), serde_json::Value::Number(serde_json::Number::from(now.timestamp())));
 
-        // Call MCP server to update the todo
-        self.call_mcp_update_todo(&todo.id, updates).await
+        self.store.update(&todo.id, updates).await
     }
 }
 
@@ -481,8 +489,29 @@ impl ToolExecutor for TodoTool {
                 let default_agent = "user".to_string();
                 let target_agent = params.get("target_agent").unwrap_or(&default_agent);
                 let project = params.get("project").map(|s| s.as_str());
+                let idempotency_key = params.get("idempotency_key").map(|s| s.as_str());
+                let priority = params.get("priority")
+                    .map(|p| serde_json::from_value::<TaskPriority>(serde_json::Value::String(p.clone())))
+                    .transpose()
+                    .map_err(|e| anyhow!("Invalid priority: {}", e))?;
+                let mut metadata = params.get("metadata")
+                    .map(|m| serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(m))
+                    .transpose()
+                    .map_err(|e| anyhow!("Invalid metadata: {}", e))?;
+                // Subtasks name the todos they wait on (comma-separated ids) and their parent
+                let depends_on: Vec<&str> = params.get("depends_on")
+                    .map(|ids| ids.split(',').map(str::trim).filter(|id| !id.is_empty()).collect())
+                    .unwrap_or_default();
+                if !depends_on.is_empty() {
+                    metadata.get_or_insert_with(Default::default)
+                        .insert("depends_on".to_string(), serde_json::json!(depends_on));
+                }
+                if let Some(parent_id) = params.get("parent_id") {
+                    metadata.get_or_insert_with(Default::default)
+                        .insert("parent_id".to_string(), serde_json::Value::String(parent_id.clone()));
+                }
                 tracing::debug!("Adding todo - Description: {}, Context: {:?}, Target Agent: {}, Project: {:?}", description, context, target_agent, project);
-                self.add_todo(description, context, target_agent, project).await
+                self.add_todo(description, context, target_agent, project, idempotency_key, priority, metadata).await
             }
             "list" => {
                 tracing::debug!("Listing todos");
@@ -491,12 +520,18 @@ impl ToolExecutor for TodoTool {
             "complete" => {
                 let description = params.get("description").ok_or_else(|| anyhow!("Missing todo description"))?;
                 tracing::debug!("Marking todo as complete: {}", description);
-                self.update_todo_status(description, TaskStatus::Completed).await
+                self.write_through(PendingOperation::SetStatus {
+                    description: description.clone(),
+                    status: TaskStatus::Completed,
+                }).await
             }
             "fail" => {
                 let description = params.get("description").ok_or_else(|| anyhow!("Missing todo description"))?;
                 tracing::debug!("Marking todo as failed: {}", description);
-                self.update_todo_status(description, TaskStatus::Failed).await
+                self.write_through(PendingOperation::SetStatus {
+                    description: description.clone(),
+                    status: TaskStatus::Failed,
+                }).await
             }
             _ => {
                 tracing::error!("Unknown todo command: {}", command);
@@ -504,56 +539,97 @@ impl ToolExecutor for TodoTool {
             },
         }
     }
+
+    fn dry_run(&self, params: &HashMap<String, String>) -> Result<Option<String>> {
+        let command = params.get("command").ok_or_else(|| anyhow!("Missing command parameter"))?;
+        if command == "list" {
+            return Ok(None);
+        }
+        let description = params.get("description").ok_or_else(|| anyhow!("Missing todo description"))?;
+        let step = match command.as_str() {
+            "add" => format!(
+                "add todo {:?} for {}",
+                description,
+                params.get("target_agent").map(String::as_str).unwrap_or("user"),
+            ),
+            "complete" => format!("mark todo {:?} completed", description),
+            "fail" => format!("mark todo {:?} failed", description),
+            _ => return Err(anyhow!("Unknown todo command")),
+        };
+        Ok(Some(dry_run::plan([step])))
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::ai::DefaultAiClient;
+    use crate::clock::FakeClock;
+    use crate::mcp::McpClientConfig;
+    use crate::test_support::McpFake;
+
+    /// A model that is never reachable, so todos fall back to their own
+    /// description, Medium priority and the default project
+    struct NoAi;
+
+    #[async_trait]
+    impl AiProvider for NoAi {
+        async fn chat(&self, _system_prompt: &str, _messages: Vec<HashMap<String, String>>) -> Result<String> {
+            Err(anyhow!("no model in tests"))
+        }
+    }
+
+    /// A tool talking to `fake`, queueing into `dir`, at a time well after the
+    /// fixtures were recorded so none of them count as a retry
+    async fn hermetic_tool(fake: &McpFake, dir: &tempfile::TempDir) -> Result<TodoTool> {
+        Ok(TodoTool::new().await?
+            .with_ai_client(NoAi)
+            .with_clock(Arc::new(FakeClock::at(1_770_000_000)))
+            .with_store(McpTodoStore::new(fake.client()?))
+            .with_outbox(TodoOutbox::new(dir.path().join("outbox.jsonl"))))
+    }
 
     #[tokio::test]
     async fn test_todo_operations() -> Result<()> {
-        // Set up TodoTool with MCP server
-        let ai_client = DefaultAiClient::new();
-        let tool = TodoTool::new().await?
-            .with_ai_client(ai_client);
+        let fake = McpFake::with_fixtures().await;
+        let dir = tempfile::tempdir()?;
+        let tool = hermetic_tool(&fake, &dir).await?;
 
-        // Test adding a todo
         let mut params = HashMap::new();
         params.insert("command".to_string(), "add".to_string());
         params.insert("description".to_string(), "Test todo".to_string());
         params.insert("project".to_string(), "test_project".to_string());
 
-        let result = tool.execute(params).await;
-        // Note: This test will fail if MCP server is not running
-        // In a real test environment, you'd want to mock the HTTP calls
-        match result {
-            Ok(result) => {
-                assert!(result.contains("todo") || result.contains("success"));
-                tracing::info!("Add todo test passed: {}", result);
-            },
-            Err(e) => {
-                tracing::warn!("Add todo test failed (likely MCP server not running): {}", e);
-                // Don't fail the test if MCP server isn't available
-            }
-        }
+        let result = tool.execute(params).await?;
+        assert!(result.contains("Todo created successfully"));
+        let added = fake.requests("add_todo_tool").await;
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0]["description"], "Test todo");
+        assert_eq!(added[0]["metadata"]["enhanced_description"], "Test todo");
 
-        // Test listing todos
         let mut params = HashMap::new();
         params.insert("command".to_string(), "list".to_string());
 
-        let result = tool.execute(params).await;
-        match result {
-            Ok(result) => {
-                tracing::info!("List todos test passed: {}", result);
-                // Should contain either todos or "No todos found"
-                assert!(result.contains("todo") || result.contains("No todos found"));
-            },
-            Err(e) => {
-                tracing::warn!("List todos test failed (likely MCP server not running): {}", e);
-            }
-        }
+        let result = tool.execute(params).await?;
+        assert!(result.starts_with("Current todos:"));
+        assert!(result.contains("- Add retry metrics to the todo worker (Pending)"));
+        assert!(result.contains("- Update the README for the 0.1.3 release (Completed)"));
+        assert!(!tool.is_degraded());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_queries_are_cached_until_a_write() -> Result<()> {
+        let fake = McpFake::with_fixtures().await;
+        let store = McpTodoStore::new(fake.client()?);
+
+        let listed = store.query(TodoQuery::default()).await?;
+        assert_eq!(store.query(TodoQuery::default()).await?.len(), listed.len());
+        assert_eq!(fake.requests("query_todos_tool").await.len(), 1);
 
+        store.delete(&listed[0].id).await?;
+        store.query(TodoQuery::default()).await?;
+        assert_eq!(fake.requests("query_todos_tool").await.len(), 2);
         Ok(())
     }
 
@@ -639,26 +715,20 @@ mod tests {
 
     #[tokio::test]
     async fn test_project_field() -> Result<()> {
-        let ai_client = DefaultAiClient::new();
-        let tool = TodoTool::new().await?
-            .with_ai_client(ai_client);
+        let fake = McpFake::with_fixtures().await;
+        let dir = tempfile::tempdir()?;
+        let tool = hermetic_tool(&fake, &dir).await?;
 
-        // Test adding a todo with project
+        // A named project wins over the predicted one
         let mut params = HashMap::new();
         params.insert("command".to_string(), "add".to_string());
         params.insert("description".to_string(), "Project todo test".to_string());
-        params.insert("project".to_string(), "test_project".to_string());
-
-        match tool.execute(params).await {
-            Ok(result) => {
-                tracing::info!("Project field test passed: {}", result);
-                assert!(result.contains("todo") || result.contains("success"));
-            },
-            Err(e) => {
-                tracing::warn!("Project field test failed (likely MCP server not running): {}", e);
-            }
-        }
+        params.insert("project".to_string(), "Test Project".to_string());
 
+        tool.execute(params).await?;
+        let added = fake.requests("add_todo_tool").await;
+        assert_eq!(added[0]["project"], "test_project");
+        assert_eq!(added[0]["priority"], "Medium");
         Ok(())
     }
 
@@ -725,23 +795,49 @@ mod tests {
 
     #[tokio::test]
     async fn test_project_prediction_in_add_todo() -> Result<()> {
-        let ai_client = DefaultAiClient::new();
-        let tool = TodoTool::new().await?
-            .with_ai_client(ai_client);
+        let fake = McpFake::with_fixtures().await;
+        let dir = tempfile::tempdir()?;
+        let tool = hermetic_tool(&fake, &dir).await?;
 
-        // Test adding a todo without specifying a project
+        // Without a project or a model to predict one, the default is used
         let description = "Update the Swarmonomicon API documentation with new endpoints";
+        let result = tool.add_todo(description, None, "test_agent", None, None, None, None).await?;
+        assert!(result.contains("Todo created successfully"));
 
-        match tool.add_todo(description, None, "test_agent", None).await {
-            Ok(result) => {
-                tracing::info!("Add todo with project prediction test passed: {}", result);
-                assert!(result.contains("todo") || result.contains("success"));
-            },
-            Err(e) => {
-                tracing::warn!("Add todo with project prediction test failed (likely MCP server not running): {}", e);
-            }
-        }
+        let added = fake.requests("add_todo_tool").await;
+        assert_eq!(added[0]["project"], projects::get_default_project());
+        assert_eq!(added[0]["target_agent"], "test_agent");
+        assert_eq!(added[0]["metadata"]["idempotency_key"], derive_idempotency_key(description, None));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_server_queues_writes() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        // Nothing listens on the discard port
+        let tool = TodoTool::new().await?
+            .with_store(McpTodoStore::new(McpClient::new(McpClientConfig {
+                max_retries: 0,
+                ..McpClientConfig::new("http://127.0.0.1:9")
+            })?))
+            .with_outbox(TodoOutbox::new(dir.path().join("outbox.jsonl")));
+
+        let mut params = HashMap::new();
+        params.insert("command".to_string(), "complete".to_string());
+        params.insert("description".to_string(), "Calibrate the flux capacitor".to_string());
+
+        let response: serde_json::Value = serde_json::from_str(&tool.execute(params).await?)?;
+        assert_eq!(response["degraded"], true);
+        assert_eq!(response["queued"], true);
+        assert!(tool.is_degraded());
+
+        let status = tool.status_json().await;
+        assert_eq!(status["queued_operations"], 1);
+        assert_eq!(status["operations_queued"], 1);
 
+        // Still unreachable, so the operation stays queued
+        assert_eq!(tool.flush_outbox().await?, 0);
+        assert!(tool.is_degraded());
         Ok(())
     }
 }
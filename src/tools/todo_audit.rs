@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use async_trait::async_trait;
+use anyhow::Result;
+use chrono::Utc;
+use futures_util::TryStreamExt;
+use mongodb::{bson::doc, Collection};
+use crate::clients::Clients;
+use serde_json::Value;
+use crate::mcp::McpClient;
+use crate::mcp::schema::{ChangeEntry, LogEntry, LogOperation, McpResponse};
+use crate::tools::todo_store::{NewTodo, TodoQuery, TodoStore};
+use crate::types::TodoTask;
+
+/// `userAgent` recorded on every entry this crate writes
+pub const AUDIT_USER_AGENT: &str = "swarmonomicon";
+
+/// Records todo mutations in the local `todo_audit` collection and in
+/// Omnispindle's change log. Both sinks are best-effort: a failed audit write
+/// is logged and never fails the mutation itself.
+#[derive(Clone, Default)]
+pub struct TodoAuditLog {
+    collection: Option<Collection<LogEntry>>,
+    mcp: Option<McpClient>,
+    mcp_tool: String,
+}
+
+impl TodoAuditLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_collection(mut self, collection: Collection<LogEntry>) -> Self {
+        self.collection = Some(collection);
+        self
+    }
+
+    /// Also send entries to the MCP server's `tool` endpoint
+    pub fn with_mcp(mut self, client: McpClient, tool: impl Into<String>) -> Self {
+        self.mcp = Some(client);
+        self.mcp_tool = tool.into();
+        self
+    }
+
+    /// Uses `RTK_MONGO_URI`/`RTK_MONGO_DB` for the `todo_audit` collection when
+    /// set, and the shared MCP client with `MCP_AUDIT_TOOL` (default
+    /// `add_todo_log_tool`) unless that is set to an empty string.
+    pub async fn from_env() -> Result<Self> {
+        let mut audit = Self::new();
+
+        if let Ok(uri) = std::env::var("RTK_MONGO_URI") {
+            let db_name = std::env::var("RTK_MONGO_DB").unwrap_or_else(|_| "swarmonomicon".to_string());
+            let client = Clients::shared().mongo(&uri)?;
+            audit = audit.with_collection(client.database(&db_name).collection("todo_audit"));
+        }
+
+        let tool = std::env::var("MCP_AUDIT_TOOL").unwrap_or_else(|_| "add_todo_log_tool".to_string());
+        if !tool.is_empty() {
+            audit = audit.with_mcp(McpClient::shared()?, tool);
+        }
+        Ok(audit)
+    }
+
+    pub async fn record(&self, entry: LogEntry) {
+        if let Some(collection) = &self.collection {
+            if let Err(e) = collection.insert_one(&entry, None).await {
+                tracing::warn!("Failed to write audit entry for todo {}: {}", entry.todo_id, e);
+            }
+        }
+        if let Some(mcp) = &self.mcp {
+            let sent = match mcp.call_tool(&self.mcp_tool, &entry).await {
+                Ok(body) => McpResponse::<Value>::parse(&body).and_then(|r| r.into_message("")).map(|_| ()),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = sent {
+                tracing::warn!("Failed to send audit entry for todo {} to MCP: {}", entry.todo_id, e);
+            }
+        }
+    }
+
+    /// Every recorded mutation of a todo, oldest first
+    pub async fn history(&self, todo_id: &str) -> Result<Vec<LogEntry>> {
+        let Some(collection) = &self.collection else {
+            return Ok(Vec::new());
+        };
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "timestamp": 1 })
+            .build();
+        Ok(collection.find(doc! { "todoId": todo_id }, options).await?.try_collect().await?)
+    }
+}
+
+pub fn log_entry(operation: LogOperation, todo: &TodoTask, changes: Option<Vec<ChangeEntry>>) -> LogEntry {
+    LogEntry {
+        timestamp: Utc::now(),
+        operation,
+        todo_id: todo.id.clone(),
+        description: todo.description.clone(),
+        project: todo.project.clone().unwrap_or_default(),
+        changes,
+        user_agent: AUDIT_USER_AGENT.to_string(),
+    }
+}
+
+/// The fields `updates` actually changes on `before`
+pub fn diff(before: &TodoTask, updates: &HashMap<String, Value>) -> Vec<ChangeEntry> {
+    let before = serde_json::to_value(before).unwrap_or(Value::Null);
+    let mut changes: Vec<_> = updates.iter()
+        .filter_map(|(field, new_value)| {
+            let old_value = before.get(field).filter(|v| !v.is_null()).cloned();
+            (old_value.as_ref() != Some(new_value)).then(|| ChangeEntry {
+                field: field.clone(),
+                old_value,
+                new_value: Some(new_value.clone()),
+            })
+        })
+        .collect();
+    changes.sort_by(|a, b| a.field.cmp(&b.field));
+    changes
+}
+
+/// Id of the todo a store's add response created. MCP reports it as
+/// `data.todo_id`, the Mongo store as `data.id`.
+fn created_id(response: &str) -> Option<String> {
+    let response: Value = serde_json::from_str(response).ok()?;
+    let data = response.get("data")?;
+    data.get("todo_id").or_else(|| data.get("id"))?.as_str().map(|s| s.to_string())
+}
+
+/// Wraps a [`TodoStore`], recording every add, update, complete and delete
+pub struct AuditedTodoStore<S> {
+    inner: S,
+    audit: TodoAuditLog,
+}
+
+impl<S: TodoStore> AuditedTodoStore<S> {
+    pub fn new(inner: S, audit: TodoAuditLog) -> Self {
+        Self { inner, audit }
+    }
+
+    pub fn audit_log(&self) -> &TodoAuditLog {
+        &self.audit
+    }
+
+    /// The todo as it was before a mutation, for the entry's description and diff
+    async fn before(&self, todo_id: &str) -> Option<TodoTask> {
+        match self.inner.get(todo_id).await {
+            Ok(todo) => Some(todo),
+            Err(e) => {
+                tracing::debug!("Could not load todo {} for auditing: {}", todo_id, e);
+                None
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<S: TodoStore> TodoStore for AuditedTodoStore<S> {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn status_json(&self) -> Value {
+        self.inner.status_json()
+    }
+
+    async fn add(&self, todo: NewTodo) -> Result<String> {
+        let (description, project) = (todo.description.clone(), todo.project.clone());
+        let response = self.inner.add(todo).await?;
+
+        let entry = LogEntry {
+            timestamp: Utc::now(),
+            operation: LogOperation::Create,
+            todo_id: created_id(&response).unwrap_or_default(),
+            description,
+            project,
+            changes: None,
+            user_agent: AUDIT_USER_AGENT.to_string(),
+        };
+        self.audit.record(entry).await;
+        Ok(response)
+    }
+
+    async fn query(&self, query: TodoQuery) -> Result<Vec<TodoTask>> {
+        self.inner.query(query).await
+    }
+
+    async fn update(&self, todo_id: &str, updates: HashMap<String, Value>) -> Result<String> {
+        let before = self.before(todo_id).await;
+        let response = self.inner.update(todo_id, updates.clone()).await?;
+
+        if let Some(before) = before {
+            let changes = diff(&before, &updates);
+            self.audit.record(log_entry(LogOperation::Update, &before, Some(changes))).await;
+        }
+        Ok(response)
+    }
+
+    async fn complete(&self, todo_id: &str) -> Result<String> {
+        let before = self.before(todo_id).await;
+        let response = self.inner.complete(todo_id).await?;
+
+        if let Some(before) = before {
+            let status = HashMap::from([("status".to_string(), Value::String("completed".to_string()))]);
+            let changes = diff(&before, &status);
+            self.audit.record(log_entry(LogOperation::Complete, &before, Some(changes))).await;
+        }
+        Ok(response)
+    }
+
+    async fn get(&self, todo_id: &str) -> Result<TodoTask> {
+        self.inner.get(todo_id).await
+    }
+
+    async fn delete(&self, todo_id: &str) -> Result<String> {
+        let before = self.before(todo_id).await;
+        let response = self.inner.delete(todo_id).await?;
+
+        if let Some(before) = before {
+            self.audit.record(log_entry(LogOperation::Delete, &before, None)).await;
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task() -> TodoTask {
+        serde_json::from_value(serde_json::json!({
+            "id": "todo-1",
+            "description": "Merge the lease branch",
+            "enhanced_description": null,
+            "priority": "Medium",
+            "project": "swarmonomicon",
+            "source_agent": null,
+            "target_agent": "git",
+            "status": "review",
+            "created_at": 1,
+            "completed_at": null,
+            "due_date": null,
+            "duration_minutes": null,
+            "notes": null,
+            "ticket": null,
+            "last_modified": null
+        })).unwrap()
+    }
+
+    #[test]
+    fn test_diff_only_reports_changed_fields() {
+        let updates = HashMap::from([
+            ("status".to_string(), Value::String("completed".to_string())),
+            ("target_agent".to_string(), Value::String("git".to_string())),
+            ("notes".to_string(), Value::String("done".to_string())),
+        ]);
+        let changes = diff(&task(), &updates);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].field, "notes");
+        assert_eq!(changes[0].old_value, None);
+        assert_eq!(changes[1].field, "status");
+        assert_eq!(changes[1].old_value, Some(Value::String("review".to_string())));
+    }
+
+    #[test]
+    fn test_log_entry_and_created_id() {
+        let entry = log_entry(LogOperation::Delete, &task(), None);
+        assert_eq!(entry.todo_id, "todo-1");
+        assert_eq!(entry.project, "swarmonomicon");
+        assert_eq!(entry.user_agent, "swarmonomicon");
+
+        assert_eq!(created_id(r#"{"success": true, "data": {"todo_id": "a"}}"#).as_deref(), Some("a"));
+        assert_eq!(created_id(r#"{"success": true, "data": {"id": "b"}}"#).as_deref(), Some("b"));
+        assert_eq!(created_id(r#"{"success": true, "queued": true}"#), None);
+    }
+}
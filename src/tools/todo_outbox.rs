@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use crate::types::TaskStatus;
+
+/// A todo write that could not reach the MCP server
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum PendingOperation {
+    Add {
+        description: String,
+        project: String,
+        priority: String,
+        target_agent: String,
+        metadata: Option<HashMap<String, serde_json::Value>>,
+    },
+    /// Completing or failing a todo, which is looked up by description on replay
+    SetStatus {
+        description: String,
+        status: TaskStatus,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QueuedOperation {
+    pub id: String,
+    pub queued_at: i64,
+    #[serde(flatten)]
+    pub operation: PendingOperation,
+}
+
+/// Append-only JSON-lines log of todo writes waiting to be replayed against
+/// the MCP server. Entries survive restarts and are replayed oldest first.
+#[derive(Debug)]
+pub struct TodoOutbox {
+    path: PathBuf,
+    lock: Mutex<()>,
+}
+
+impl TodoOutbox {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into(), lock: Mutex::new(()) }
+    }
+
+    /// Uses `TODO_OUTBOX_PATH`, or a file under the system temp dir.
+    pub fn from_env() -> Self {
+        let path = std::env::var("TODO_OUTBOX_PATH")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| std::env::temp_dir().join("swarmonomicon").join("todo_outbox.jsonl"));
+        Self::new(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Durably append an operation to the log
+    pub async fn push(&self, operation: PendingOperation) -> Result<QueuedOperation> {
+        let _guard = self.lock.lock().await;
+        let queued = QueuedOperation {
+            id: uuid::Uuid::new_v4().to_string(),
+            queued_at: chrono::Utc::now().timestamp(),
+            operation,
+        };
+
+        if let Some(parent) = self.path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .map_err(|e| anyhow!("Failed to open todo outbox {}: {}", self.path.display(), e))?;
+        let mut line = serde_json::to_string(&queued)?;
+        line.push('\n');
+        file.write_all(line.as_bytes()).await?;
+        file.sync_data().await?;
+
+        Ok(queued)
+    }
+
+    pub async fn pending(&self) -> Result<Vec<QueuedOperation>> {
+        let _guard = self.lock.lock().await;
+        self.read().await
+    }
+
+    pub async fn len(&self) -> usize {
+        self.pending().await.map(|ops| ops.len()).unwrap_or(0)
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    /// Send queued operations in order until one fails. Delivered operations
+    /// are removed from the log; the rest stay for the next attempt. Returns
+    /// how many were delivered.
+    pub async fn replay<F, Fut>(&self, mut send: F) -> Result<usize>
+    where
+        F: FnMut(PendingOperation) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let _guard = self.lock.lock().await;
+        let pending = self.read().await?;
+
+        let mut delivered = 0;
+        for queued in &pending {
+            if let Err(e) = send(queued.operation.clone()).await {
+                tracing::warn!("Replay of queued todo operation {} failed: {}", queued.id, e);
+                break;
+            }
+            delivered += 1;
+        }
+
+        if delivered > 0 {
+            self.rewrite(&pending[delivered..]).await?;
+        }
+        Ok(delivered)
+    }
+
+    async fn read(&self) -> Result<Vec<QueuedOperation>> {
+        let contents = match tokio::fs::read_to_string(&self.path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        // A torn final line from a crash mid-write is skipped rather than blocking the queue
+        Ok(contents.lines()
+            .filter(|line| !line.trim().is_empty())
+            .filter_map(|line| match serde_json::from_str(line) {
+                Ok(queued) => Some(queued),
+                Err(e) => {
+                    tracing::warn!("Skipping unreadable todo outbox entry: {}", e);
+                    None
+                }
+            })
+            .collect())
+    }
+
+    /// Replace the log with `remaining`, atomically via a rename
+    async fn rewrite(&self, remaining: &[QueuedOperation]) -> Result<()> {
+        let mut contents = String::new();
+        for queued in remaining {
+            contents.push_str(&serde_json::to_string(queued)?);
+            contents.push('\n');
+        }
+        let tmp = self.path.with_extension("jsonl.tmp");
+        tokio::fs::write(&tmp, contents).await?;
+        tokio::fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn complete(description: &str) -> PendingOperation {
+        PendingOperation::SetStatus { description: description.to_string(), status: TaskStatus::Completed }
+    }
+
+    #[tokio::test]
+    async fn test_push_and_partial_replay() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let outbox = TodoOutbox::new(dir.path().join("outbox.jsonl"));
+        assert!(outbox.is_empty().await);
+
+        outbox.push(complete("first")).await?;
+        outbox.push(complete("second")).await?;
+        outbox.push(complete("third")).await?;
+        assert_eq!(outbox.len().await, 3);
+
+        // Deliver one, then fail on the second
+        let mut sent = Vec::new();
+        let delivered = outbox.replay(|op| {
+            let ok = sent.is_empty();
+            sent.push(op);
+            async move { if ok { Ok(()) } else { Err(anyhow!("still down")) } }
+        }).await?;
+        assert_eq!(delivered, 1);
+        assert_eq!(sent[0], complete("first"));
+
+        // Survives a restart, in order
+        let reopened = TodoOutbox::new(outbox.path());
+        let pending = reopened.pending().await?;
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].operation, complete("second"));
+
+        assert_eq!(reopened.replay(|_| async { Ok(()) }).await?, 2);
+        assert!(reopened.is_empty().await);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_torn_line_is_skipped() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let outbox = TodoOutbox::new(dir.path().join("outbox.jsonl"));
+        outbox.push(complete("kept")).await?;
+        tokio::fs::OpenOptions::new().append(true).open(outbox.path()).await?
+            .write_all(b"{\"op\":\"add\",\"descr").await?;
+
+        assert_eq!(outbox.pending().await?.len(), 1);
+        Ok(())
+    }
+}
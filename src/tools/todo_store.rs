@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use async_trait::async_trait;
+use anyhow::{Result, anyhow};
+use mongodb::bson::{self, doc, Document};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use crate::ai::TaskBudget;
+use crate::clock::{Clock, IdGenerator};
+use crate::types::{TodoList, TodoTask, TaskPriority, TaskStatus};
+
+/// A todo to be created, in the shape the MCP `add_todo_tool` expects
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NewTodo {
+    pub description: String,
+    pub project: String,
+    pub priority: String,
+    pub target_agent: String,
+    pub metadata: Option<HashMap<String, Value>>,
+}
+
+/// Which todos to return from [`TodoStore::query`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TodoQuery {
+    pub description: Option<String>,
+    pub idempotency_key: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// Where `TodoTool` keeps todos. Write methods return the backend's response
+/// body, which is passed back to the caller of the tool.
+#[async_trait]
+pub trait TodoStore: Send + Sync {
+    /// Short backend name for logs and metrics
+    fn name(&self) -> &'static str;
+    /// Backend health for metrics reporting
+    fn status_json(&self) -> Value {
+        Value::Null
+    }
+    async fn add(&self, todo: NewTodo) -> Result<String>;
+    async fn query(&self, query: TodoQuery) -> Result<Vec<TodoTask>>;
+    async fn update(&self, todo_id: &str, updates: HashMap<String, Value>) -> Result<String>;
+    async fn complete(&self, todo_id: &str) -> Result<String>;
+    async fn get(&self, todo_id: &str) -> Result<TodoTask>;
+    async fn delete(&self, todo_id: &str) -> Result<String>;
+}
+
+/// Stores todos directly in the `todos` collection used by the todo worker,
+/// for deployments that don't run an Omnispindle MCP server.
+#[derive(Debug, Clone)]
+pub struct MongoTodoStore {
+    todo_list: TodoList,
+}
+
+impl MongoTodoStore {
+    pub fn new(todo_list: TodoList) -> Self {
+        Self { todo_list }
+    }
+
+    /// Connects using `RTK_MONGO_URI` and `RTK_MONGO_DB`
+    pub async fn from_env() -> Result<Self> {
+        Ok(Self::new(TodoList::new().await?))
+    }
+}
+
+/// Build the task `add` inserts. Known metadata keys map onto task fields;
+/// the rest is kept in `notes`.
+fn task_from_new_todo(todo: NewTodo, clock: &dyn Clock, ids: &dyn IdGenerator) -> TodoTask {
+    let mut metadata = todo.metadata.unwrap_or_default();
+    let mut take = |key: &str| metadata.remove(key).and_then(|v| v.as_str().map(|s| s.to_string()));
+    let enhanced_description = take("enhanced_description");
+    let idempotency_key = take("idempotency_key");
+    let source_agent = take("source");
+    let parent_id = take("parent_id");
+    let depends_on = metadata.remove("depends_on")
+        .and_then(|v| serde_json::from_value::<Vec<String>>(v).ok())
+        .unwrap_or_default();
+    let budget = metadata.remove("budget")
+        .and_then(|v| serde_json::from_value::<TaskBudget>(v).ok());
+    let dry_run = metadata.remove("dry_run").and_then(|v| v.as_bool()).unwrap_or(false);
+    let notes = if metadata.is_empty() { None } else { Some(json!(metadata).to_string()) };
+
+    let now = clock.timestamp();
+    TodoTask {
+        id: ids.next_id(),
+        description: todo.description,
+        enhanced_description,
+        priority: serde_json::from_value(Value::String(todo.priority)).unwrap_or(TaskPriority::Medium),
+        project: Some(todo.project),
+        source_agent,
+        target_agent: todo.target_agent,
+        created_at: now,
+        notes,
+        last_modified: Some(now),
+        depends_on,
+        idempotency_key,
+        parent_id,
+        budget,
+        dry_run,
+        ..Default::default()
+    }
+}
+
+fn query_filter(query: &TodoQuery) -> Document {
+    let mut filter = Document::new();
+    if let Some(description) = &query.description {
+        filter.insert("description", description);
+    }
+    if let Some(key) = &query.idempotency_key {
+        filter.insert("idempotency_key", key);
+    }
+    filter
+}
+
+/// Translate MCP-style update fields to the task document, modified at `now`
+fn update_document(updates: HashMap<String, Value>, now: i64) -> Result<Document> {
+    let mut set = Document::new();
+    for (field, value) in updates {
+        let field = if field == "updated_at" { "last_modified".to_string() } else { field };
+        set.insert(field, bson::to_bson(&value)?);
+    }
+    set.insert("last_modified", now);
+    Ok(set)
+}
+
+fn ok_response(message: &str, data: Value) -> String {
+    json!({ "success": true, "message": message, "data": data }).to_string()
+}
+
+#[async_trait]
+impl TodoStore for MongoTodoStore {
+    fn name(&self) -> &'static str {
+        "mongo"
+    }
+
+    async fn add(&self, todo: NewTodo) -> Result<String> {
+        let task = task_from_new_todo(todo, self.todo_list.clock().as_ref(), self.todo_list.ids().as_ref());
+        self.todo_list.add_task(task.clone()).await?;
+        Ok(ok_response("Todo created", serde_json::to_value(&task)?))
+    }
+
+    async fn query(&self, query: TodoQuery) -> Result<Vec<TodoTask>> {
+        Ok(self.todo_list.find_tasks(query_filter(&query), query.limit.or(Some(100))).await?)
+    }
+
+    async fn update(&self, todo_id: &str, updates: HashMap<String, Value>) -> Result<String> {
+        let task = self.todo_list.update_task(todo_id, update_document(updates, self.todo_list.clock().timestamp())?).await?
+            .ok_or_else(|| anyhow!("Todo '{}' not found", todo_id))?;
+        Ok(ok_response("Todo updated successfully", serde_json::to_value(&task)?))
+    }
+
+    async fn complete(&self, todo_id: &str) -> Result<String> {
+        self.get(todo_id).await?;
+        if !self.todo_list.mark_task_completed(todo_id).await? {
+            return Err(anyhow!("Todo '{}' was cancelled and can't be completed", todo_id));
+        }
+        Ok(ok_response("Todo marked as complete", json!({ "id": todo_id })))
+    }
+
+    async fn get(&self, todo_id: &str) -> Result<TodoTask> {
+        self.todo_list.get_task(todo_id).await?
+            .ok_or_else(|| anyhow!("Todo '{}' not found", todo_id))
+    }
+
+    async fn delete(&self, todo_id: &str) -> Result<String> {
+        if !self.todo_list.delete_task(todo_id).await? {
+            return Err(anyhow!("Todo '{}' not found", todo_id));
+        }
+        Ok(ok_response("Todo deleted", json!({ "id": todo_id })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{FakeClock, SequentialIds};
+
+    #[test]
+    fn test_task_from_new_todo() {
+        let task = task_from_new_todo(NewTodo {
+            description: "Calibrate the flux capacitor".to_string(),
+            project: "madness_interactive".to_string(),
+            priority: "High".to_string(),
+            target_agent: "user".to_string(),
+            metadata: Some(HashMap::from([
+                ("enhanced_description".to_string(), json!("Calibrate the flux capacitor to 1.21 GW")),
+                ("idempotency_key".to_string(), json!("abc")),
+                ("context".to_string(), json!("mqtt_intake")),
+                ("parent_id".to_string(), json!("plan-1")),
+                ("depends_on".to_string(), json!(["step-1"])),
+                ("budget".to_string(), json!({"max_ai_calls": 3})),
+                ("dry_run".to_string(), json!(true)),
+            ])),
+        }, &FakeClock::at(1_700_000_000), &SequentialIds::new("todo"));
+
+        assert_eq!(task.id, "todo-1");
+        assert_eq!((task.created_at, task.last_modified), (1_700_000_000, Some(1_700_000_000)));
+        assert_eq!(task.priority, TaskPriority::High);
+        assert_eq!(task.status, TaskStatus::Pending);
+        assert_eq!(task.project.as_deref(), Some("madness_interactive"));
+        assert_eq!(task.idempotency_key.as_deref(), Some("abc"));
+        assert!(task.enhanced_description.unwrap().contains("1.21"));
+        assert_eq!(task.parent_id.as_deref(), Some("plan-1"));
+        assert_eq!(task.depends_on, vec!["step-1".to_string()]);
+        assert_eq!(task.budget.unwrap().max_ai_calls, Some(3));
+        assert!(task.dry_run);
+        let notes = task.notes.unwrap();
+        assert!(notes.contains("mqtt_intake"));
+        assert!(!notes.contains("plan-1"));
+    }
+
+    #[test]
+    fn test_update_document() {
+        let set = update_document(HashMap::from([
+            ("status".to_string(), json!("failed")),
+            ("updated_at".to_string(), json!(5)),
+        ]), 1_700_000_000).unwrap();
+        assert_eq!(set.get_str("status").unwrap(), "failed");
+        assert_eq!(set.get_i64("last_modified").unwrap(), 1_700_000_000);
+        assert!(!set.contains_key("updated_at"));
+    }
+
+    #[test]
+    fn test_query_filter() {
+        let filter = query_filter(&TodoQuery { idempotency_key: Some("abc".to_string()), ..Default::default() });
+        assert_eq!(filter, doc! { "idempotency_key": "abc" });
+    }
+}
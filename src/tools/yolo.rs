@@ -1,117 +1,66 @@
-#[cfg(feature = "yolo")]
-mod yolo {
-    use std::path::Path;
-    use std::collections::HashMap;
-    use async_trait::async_trait;
-    use crate::tools::ToolExecutor;
-    use anyhow::{Result, anyhow};
-    use serde::{Serialize, Deserialize};
-
-    #[derive(Debug, Serialize, Deserialize)]
-    pub struct Detection {
-        pub class: String,
-        pub confidence: f32,
-        pub bbox: [f32; 4], // [x, y, width, height]
-    }
-
-    pub struct YoloTool {
-        model_path: String,
-        config_path: String,
-    }
-
-    impl YoloTool {
-        pub fn new() -> Self {
-            Self {
-                model_path: "models/yolov3.weights".to_string(),
-                config_path: "models/yolov3.cfg".to_string(),
-            }
+//! ONNX Runtime backend for [`ObjectDetectionTool`](super::ObjectDetectionTool)
+
+use std::sync::Mutex;
+use anyhow::{Result, anyhow};
+use image::imageops::FilterType;
+use image::DynamicImage;
+use ndarray::Array4;
+use ort::execution_providers::CUDAExecutionProvider;
+use ort::session::builder::GraphOptimizationLevel;
+use ort::session::Session;
+use ort::value::Tensor;
+use super::object_detection::{decode_yolov8, Detection, DetectionConfig, Detector};
+
+/// A YOLOv8 model exported to ONNX, run with ONNX Runtime on the CPU or,
+/// with `use_gpu`, CUDA when available
+pub struct OnnxDetector {
+    session: Mutex<Session>,
+    config: DetectionConfig,
+}
+
+impl OnnxDetector {
+    pub fn load(config: DetectionConfig) -> Result<Self> {
+        if !config.model_path.exists() {
+            return Err(anyhow!("YOLO model not found at {}", config.model_path.display()));
         }
+        let mut builder = Session::builder()?
+            .with_optimization_level(GraphOptimizationLevel::Level3)?;
+        if config.use_gpu {
+            // Falls back to the CPU when CUDA isn't available
+            builder = builder.with_execution_providers([CUDAExecutionProvider::default().build()])?;
+        }
+        let session = builder.commit_from_file(&config.model_path)?;
+        tracing::info!("Loaded YOLO model {} (gpu: {})", config.model_path.display(), config.use_gpu);
+        Ok(Self { session: Mutex::new(session), config })
+    }
 
-        pub fn detect_objects<P: AsRef<Path>>(&self, image_path: P) -> Result<Vec<Detection>> {
-            // Check if image exists
-            if !image_path.as_ref().exists() {
-                return Err(anyhow!("Image file does not exist"));
-            }
-
-            // Check if model files exist
-            if !Path::new(&self.model_path).exists() || !Path::new(&self.config_path).exists() {
-                return Err(anyhow!("YOLO model files not found"));
-            }
-
-            // Load OpenCV DNN module
-            let net = opencv::dnn::read_net_from_darknet(
-                &self.config_path,
-                &self.model_path,
-            ).map_err(|e| anyhow!("Failed to load YOLO model: {}", e))?;
-
-            // Load and preprocess image
-            let image = opencv::imgcodecs::imread(
-                image_path.as_ref().to_str().unwrap(),
-                opencv::imgcodecs::IMREAD_COLOR,
-            ).map_err(|e| anyhow!("Failed to load image: {}", e))?;
-
-            // Create blob from image
-            let blob = opencv::dnn::blob_from_image(
-                &image,
-                1.0/255.0,
-                opencv::core::Size::new(416, 416),
-                opencv::core::Vector::from_slice(&[0f64, 0f64, 0f64]),
-                true,
-                false,
-                opencv::core::CV_32F,
-            ).map_err(|e| anyhow!("Failed to create blob: {}", e))?;
-
-            // Set input and forward pass
-            net.set_input(&blob, "", 1.0, opencv::core::Vector::default())
-                .map_err(|e| anyhow!("Failed to set network input: {}", e))?;
-
-            let mut detections = Vec::new();
-            let output_layers = net.get_unconnected_out_layers()
-                .map_err(|e| anyhow!("Failed to get output layers: {}", e))?;
-
-            for layer in output_layers.iter() {
-                let output = net.forward("", layer)
-                    .map_err(|e| anyhow!("Failed to perform forward pass: {}", e))?;
-
-                // Process detections
-                for i in 0..output.rows() {
-                    let confidence = output.at_2d::<f32>(i as i32, 4)
-                        .map_err(|e| anyhow!("Failed to get confidence: {}", e))?;
-
-                    if confidence > 0.5 {
-                        let x = output.at_2d::<f32>(i as i32, 0)
-                            .map_err(|e| anyhow!("Failed to get x: {}", e))?;
-                        let y = output.at_2d::<f32>(i as i32, 1)
-                            .map_err(|e| anyhow!("Failed to get y: {}", e))?;
-                        let w = output.at_2d::<f32>(i as i32, 2)
-                            .map_err(|e| anyhow!("Failed to get width: {}", e))?;
-                        let h = output.at_2d::<f32>(i as i32, 3)
-                            .map_err(|e| anyhow!("Failed to get height: {}", e))?;
-
-                        detections.push(Detection {
-                            class: "object".to_string(), // You can add class labels if needed
-                            confidence,
-                            bbox: [x, y, w, h],
-                        });
-                    }
-                }
+    /// RGB pixels scaled to 0..1, as a `[1, 3, size, size]` tensor
+    fn preprocess(&self, image: &DynamicImage) -> Array4<f32> {
+        let size = self.config.input_size;
+        let resized = image.resize_exact(size, size, FilterType::Triangle).to_rgb8();
+        let mut input = Array4::<f32>::zeros((1, 3, size as usize, size as usize));
+        for (x, y, pixel) in resized.enumerate_pixels() {
+            for channel in 0..3 {
+                input[[0, channel, y as usize, x as usize]] = pixel[channel] as f32 / 255.0;
             }
-
-            Ok(detections)
         }
+        input
     }
-
-    #[async_trait]
-    impl ToolExecutor for YoloTool {
-        async fn execute(&self, params: HashMap<String, String>) -> Result<String> {
-            let image_path = params.get("image")
-                .ok_or_else(|| anyhow!("Missing image parameter"))?;
-
-            let detections = self.detect_objects(image_path)?;
-            
-            // Convert detections to JSON string
-            serde_json::to_string(&detections)
-                .map_err(|e| anyhow!("Failed to serialize detections: {}", e))
+}
+
+impl Detector for OnnxDetector {
+    fn detect(&self, image: &DynamicImage) -> Result<Vec<Detection>> {
+        let input = Tensor::from_array(self.preprocess(image))?;
+        let mut session = self.session.lock().map_err(|_| anyhow!("YOLO session lock poisoned"))?;
+        let outputs = session.run(ort::inputs![input])?;
+        if outputs.len() == 0 {
+            return Err(anyhow!("YOLO model returned no outputs"));
         }
+        let (shape, values) = outputs[0].try_extract_tensor::<f32>()?;
+        let shape: Vec<usize> = shape.iter().map(|&dim| dim as usize).collect();
+
+        let size = self.config.input_size as f32;
+        let scale = (image.width() as f32 / size, image.height() as f32 / size);
+        decode_yolov8(values, &shape, scale, &self.config)
     }
-} 
+}
@@ -3,9 +3,10 @@ mod yolo_test {
     use super::*;
     use std::path::PathBuf;
     use tokio;
+    use crate::error::SwarmError;
 
     #[tokio::test]
-    async fn test_yolo_detection() -> Result<(), Box<dyn std::error::Error>> {
+    async fn test_yolo_detection() -> crate::Result<()> {
         // Create test image path
         let test_image = PathBuf::from("test_data/test_image.jpg");
         
@@ -16,7 +17,7 @@ mod yolo_test {
         if !test_image.exists() {
             // Create a simple test image using image crate
             let imgbuf = image::ImageBuffer::new(100, 100);
-            imgbuf.save(&test_image)?;
+            imgbuf.save(&test_image).map_err(|e| SwarmError::Tool(format!("Failed to write test image: {}", e)))?;
         }
 
         // Initialize yolo tool
@@ -36,7 +37,7 @@ mod yolo_test {
     }
 
     #[tokio::test]
-    async fn test_yolo_invalid_image() -> Result<(), Box<dyn std::error::Error>> {
+    async fn test_yolo_invalid_image() -> crate::Result<()> {
         let yolo = YoloTool::new();
         let invalid_path = PathBuf::from("nonexistent.jpg");
         
@@ -0,0 +1,171 @@
+use std::env;
+use std::path::PathBuf;
+use anyhow::{Result, anyhow};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+/// Uploads up to this many bytes are kept inline in the message or task
+pub const DEFAULT_INLINE_MAX_BYTES: usize = 256 * 1024;
+
+/// Where an attachment's bytes live
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AttachmentSource {
+    /// Base64-encoded content carried with the message
+    Inline { data: String },
+    /// Content in an object store, e.g. `file:///var/lib/swarm/attachments/<id>.png`
+    Reference { uri: String },
+}
+
+/// A file carried by a [`Message`](super::Message) or [`TodoTask`](super::TodoTask)
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Attachment {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub content_type: String,
+    #[serde(flatten)]
+    pub source: AttachmentSource,
+    #[serde(default)]
+    pub size: Option<u64>,
+}
+
+impl Attachment {
+    pub fn inline(name: Option<String>, content_type: impl Into<String>, bytes: &[u8]) -> Self {
+        Self {
+            name,
+            content_type: content_type.into(),
+            source: AttachmentSource::Inline { data: BASE64.encode(bytes) },
+            size: Some(bytes.len() as u64),
+        }
+    }
+
+    pub fn reference(name: Option<String>, content_type: impl Into<String>, uri: impl Into<String>) -> Self {
+        Self { name, content_type: content_type.into(), source: AttachmentSource::Reference { uri: uri.into() }, size: None }
+    }
+
+    pub fn is_image(&self) -> bool {
+        self.content_type.starts_with("image/")
+    }
+
+    /// The attachment's content, with references read through the
+    /// environment's [`AttachmentStore`]
+    pub async fn bytes(&self) -> Result<Vec<u8>> {
+        AttachmentStore::from_env().read(self).await
+    }
+}
+
+/// Keeps small uploads inline and writes larger ones to a directory,
+/// returning a `file://` reference
+#[derive(Debug, Clone)]
+pub struct AttachmentStore {
+    dir: PathBuf,
+    inline_max_bytes: usize,
+}
+
+impl AttachmentStore {
+    pub fn new(dir: impl Into<PathBuf>, inline_max_bytes: usize) -> Self {
+        Self { dir: dir.into(), inline_max_bytes }
+    }
+
+    /// Reads `ATTACHMENT_DIR` (default `$TMPDIR/swarmonomicon/attachments`) and
+    /// `ATTACHMENT_INLINE_MAX_BYTES`
+    pub fn from_env() -> Self {
+        let dir = env::var("ATTACHMENT_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| env::temp_dir().join("swarmonomicon").join("attachments"));
+        let inline_max_bytes = env::var("ATTACHMENT_INLINE_MAX_BYTES").ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_INLINE_MAX_BYTES);
+        Self::new(dir, inline_max_bytes)
+    }
+
+    pub async fn store(&self, name: Option<String>, content_type: impl Into<String>, bytes: &[u8]) -> Result<Attachment> {
+        let content_type = content_type.into();
+        if bytes.len() <= self.inline_max_bytes {
+            return Ok(Attachment::inline(name, content_type, bytes));
+        }
+        tokio::fs::create_dir_all(&self.dir).await?;
+        // Keep the extension so the file opens as the right type; never trust the rest of the name
+        let extension = name.as_deref()
+            .and_then(|n| n.rsplit_once('.').map(|(_, ext)| ext))
+            .filter(|ext| !ext.is_empty() && ext.chars().all(|c| c.is_ascii_alphanumeric()))
+            .map(|ext| format!(".{}", ext))
+            .unwrap_or_default();
+        let path = self.dir.join(format!("{}{}", uuid::Uuid::new_v4(), extension));
+        tokio::fs::write(&path, bytes).await?;
+        let mut attachment = Attachment::reference(name, content_type, format!("file://{}", path.display()));
+        attachment.size = Some(bytes.len() as u64);
+        Ok(attachment)
+    }
+
+    /// The content of `attachment`. Clients can send references too, so
+    /// only files in this store's directory are read, never an arbitrary
+    /// local path.
+    pub async fn read(&self, attachment: &Attachment) -> Result<Vec<u8>> {
+        let uri = match &attachment.source {
+            AttachmentSource::Inline { data } => {
+                return BASE64.decode(data).map_err(|e| anyhow!("Invalid base64 attachment: {}", e));
+            }
+            AttachmentSource::Reference { uri } => uri,
+        };
+        let path = uri.strip_prefix("file://").unwrap_or(uri);
+        if path.contains("://") {
+            return Err(anyhow!("Unsupported attachment store: {}", uri));
+        }
+        // Canonical, so `..` and symlinks can't lead out of the directory
+        let path = tokio::fs::canonicalize(path).await
+            .map_err(|e| anyhow!("Failed to read attachment {}: {}", uri, e))?;
+        let dir = tokio::fs::canonicalize(&self.dir).await
+            .map_err(|_| anyhow!("Attachment {} is not in the attachment store", uri))?;
+        if !path.starts_with(&dir) {
+            return Err(anyhow!("Attachment {} is not in the attachment store", uri));
+        }
+        tokio::fs::read(&path).await.map_err(|e| anyhow!("Failed to read attachment {}: {}", uri, e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_store_inlines_small_and_references_large() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = AttachmentStore::new(dir.path(), 4);
+
+        let small = store.store(Some("a.txt".to_string()), "text/plain", b"hi").await.unwrap();
+        assert!(matches!(small.source, AttachmentSource::Inline { .. }));
+        assert_eq!(store.read(&small).await.unwrap(), b"hi");
+
+        let large = store.store(Some("../../shot.png".to_string()), "image/png", b"0123456789").await.unwrap();
+        let AttachmentSource::Reference { uri } = &large.source else { panic!("expected a reference") };
+        assert!(uri.ends_with(".png") && uri.starts_with("file://"));
+        assert!(large.is_image());
+        assert_eq!(store.read(&large).await.unwrap(), b"0123456789");
+
+        let json = serde_json::to_value(&large).unwrap();
+        assert_eq!(json["kind"], "reference");
+        assert_eq!(serde_json::from_value::<Attachment>(json).unwrap(), large);
+    }
+
+    #[tokio::test]
+    async fn test_references_outside_the_store_are_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret.txt"), "nope").unwrap();
+        let store = AttachmentStore::new(dir.path().join("attachments"), 4);
+        let stored = store.store(Some("shot.png".to_string()), "image/png", b"0123456789").await.unwrap();
+
+        let sneaky = [
+            format!("file://{}", outside.path().join("secret.txt").display()),
+            format!("{}/../../{}", dir.path().join("attachments").display(), outside.path().join("secret.txt").display()),
+            "file:///etc/passwd".to_string(),
+        ];
+        for uri in sneaky {
+            let attachment = Attachment::reference(None, "text/plain", uri.clone());
+            assert!(store.read(&attachment).await.is_err(), "{}", uri);
+        }
+        assert_eq!(store.read(&stored).await.unwrap(), b"0123456789");
+    }
+}
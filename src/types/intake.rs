@@ -0,0 +1,226 @@
+use std::fmt;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use super::TaskPriority;
+
+/// Newest intake payload version this build understands
+pub const INTAKE_SCHEMA_VERSION: u32 = 1;
+/// Longest description accepted, in characters
+pub const MAX_DESCRIPTION_LEN: usize = 4000;
+
+/// A validated todo intake payload.
+///
+/// Version 1 (the default when `version` is omitted):
+///
+/// ```json
+/// {
+///   "version": 1,
+///   "description": "Fix the dashboard login",
+///   "project": "inventorium",
+///   "priority": "high",
+///   "source": "node-red",
+///   "metadata": {"ticket": "INV-12"},
+///   "idempotency_key": "inv-12-login",
+///   "request_id": "7b3e..."
+/// }
+/// ```
+///
+/// Only `description` is required. `project` skips classification,
+/// `priority` overrides the AI's guess.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IntakeRequest {
+    pub version: u32,
+    pub description: String,
+    pub project: Option<String>,
+    pub priority: Option<TaskPriority>,
+    pub source: Option<String>,
+    pub metadata: Map<String, Value>,
+    /// Lets publishers retry safely; replays within the window return the original todo
+    pub idempotency_key: Option<String>,
+    /// Echoed in error responses so publishers can match them up
+    pub request_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawIntakeRequest {
+    #[serde(default)]
+    version: Option<u32>,
+    description: String,
+    #[serde(default)]
+    project: Option<String>,
+    #[serde(default)]
+    priority: Option<String>,
+    #[serde(default)]
+    source: Option<String>,
+    #[serde(default)]
+    metadata: Option<Map<String, Value>>,
+    #[serde(default)]
+    idempotency_key: Option<String>,
+    #[serde(default)]
+    request_id: Option<String>,
+}
+
+/// Why a payload was turned away. Lists every problem found, not just the first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntakeError {
+    pub request_id: Option<String>,
+    pub errors: Vec<String>,
+}
+
+impl IntakeError {
+    fn new(request_id: Option<String>, error: impl Into<String>) -> Self {
+        Self { request_id, errors: vec![error.into()] }
+    }
+
+    /// Body of the error response sent to `mcp/error/<id>`
+    pub fn response_json(&self) -> Value {
+        json!({
+            "status": "error",
+            "request_id": self.request_id,
+            "errors": self.errors,
+            "schema_version": INTAKE_SCHEMA_VERSION,
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        })
+    }
+}
+
+impl fmt::Display for IntakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid intake payload: {}", self.errors.join("; "))
+    }
+}
+
+impl std::error::Error for IntakeError {}
+
+fn check_description(description: &str, errors: &mut Vec<String>) {
+    let length = description.chars().count();
+    if description.trim().is_empty() {
+        errors.push("description must not be empty".to_string());
+    } else if length > MAX_DESCRIPTION_LEN {
+        errors.push(format!("description is {} characters, the limit is {}", length, MAX_DESCRIPTION_LEN));
+    }
+}
+
+impl IntakeRequest {
+    /// Parse and validate a payload. JSON objects must match the schema; with
+    /// `accept_plain_text`, anything else is taken as a bare description, the
+    /// way legacy publishers send it.
+    pub fn parse(payload: &str, accept_plain_text: bool) -> Result<Self, IntakeError> {
+        let value = match serde_json::from_str::<Value>(payload) {
+            Ok(Value::Object(object)) => Value::Object(object),
+            _ if accept_plain_text => return Self::plain_text(payload),
+            Ok(_) => return Err(IntakeError::new(None, "payload must be a JSON object")),
+            Err(e) => return Err(IntakeError::new(None, format!("payload is not valid JSON: {}", e))),
+        };
+        let request_id = value.get("request_id").and_then(|id| id.as_str()).map(|id| id.to_string());
+
+        let raw: RawIntakeRequest = serde_json::from_value(value)
+            .map_err(|e| IntakeError::new(request_id.clone(), e.to_string()))?;
+
+        let mut errors = Vec::new();
+        let version = raw.version.unwrap_or(INTAKE_SCHEMA_VERSION);
+        if version == 0 || version > INTAKE_SCHEMA_VERSION {
+            errors.push(format!("unsupported version {}, this intake understands 1 to {}", version, INTAKE_SCHEMA_VERSION));
+        }
+        check_description(&raw.description, &mut errors);
+
+        let project = raw.project.map(|project| project.trim().to_lowercase());
+        if project.as_deref() == Some("") {
+            errors.push("project must not be empty when given".to_string());
+        }
+
+        let priority = match raw.priority.as_deref() {
+            Some(priority) => match TaskPriority::parse(priority) {
+                Some(priority) => Some(priority),
+                None => {
+                    errors.push(format!("unknown priority '{}', expected low, medium, high or critical", priority));
+                    None
+                }
+            },
+            None => None,
+        };
+
+        if !errors.is_empty() {
+            return Err(IntakeError { request_id, errors });
+        }
+        Ok(Self {
+            version,
+            description: raw.description.trim().to_string(),
+            project,
+            priority,
+            source: raw.source,
+            metadata: raw.metadata.unwrap_or_default(),
+            idempotency_key: raw.idempotency_key,
+            request_id: raw.request_id,
+        })
+    }
+
+    fn plain_text(payload: &str) -> Result<Self, IntakeError> {
+        let mut errors = Vec::new();
+        check_description(payload, &mut errors);
+        if !errors.is_empty() {
+            return Err(IntakeError { request_id: None, errors });
+        }
+        Ok(Self {
+            version: INTAKE_SCHEMA_VERSION,
+            description: payload.trim().to_string(),
+            project: None,
+            priority: None,
+            source: Some("plain_text".to_string()),
+            metadata: Map::new(),
+            idempotency_key: None,
+            request_id: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_full_payload() {
+        let request = IntakeRequest::parse(r#"{
+            "version": 1,
+            "description": "  Fix the dashboard login ",
+            "project": "Inventorium",
+            "priority": "HIGH",
+            "source": "node-red",
+            "metadata": {"ticket": "INV-12"},
+            "idempotency_key": "inv-12"
+        }"#, false).unwrap();
+        assert_eq!(request.description, "Fix the dashboard login");
+        assert_eq!(request.project.as_deref(), Some("inventorium"));
+        assert_eq!(request.priority, Some(TaskPriority::High));
+        assert_eq!(request.metadata["ticket"], "INV-12");
+        assert_eq!(request.idempotency_key.as_deref(), Some("inv-12"));
+    }
+
+    #[test]
+    fn test_legacy_payloads() {
+        // Unversioned objects are version 1, and the old `"Medium"` spelling still works
+        let request = IntakeRequest::parse(r#"{"description": "Water the plants", "priority": "Medium"}"#, true).unwrap();
+        assert_eq!(request.version, 1);
+        assert_eq!(request.priority, Some(TaskPriority::Medium));
+
+        let request = IntakeRequest::parse("Water the plants", true).unwrap();
+        assert_eq!(request.description, "Water the plants");
+        assert_eq!(request.source.as_deref(), Some("plain_text"));
+
+        assert!(IntakeRequest::parse("Water the plants", false).is_err());
+    }
+
+    #[test]
+    fn test_reports_every_problem() {
+        let error = IntakeRequest::parse(
+            r#"{"version": 9, "description": " ", "priority": "asap", "request_id": "abc"}"#,
+            true,
+        ).unwrap_err();
+        assert_eq!(error.request_id.as_deref(), Some("abc"));
+        assert_eq!(error.errors.len(), 3);
+
+        let error = IntakeRequest::parse(r#"{"priority": "low"}"#, true).unwrap_err();
+        assert!(error.errors[0].contains("description"));
+        assert_eq!(error.response_json()["status"], "error");
+    }
+}
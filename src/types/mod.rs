@@ -13,9 +13,17 @@ use std::error::Error as StdError;
 // Declare the modules that actually exist in the src/types directory
 pub mod todo;
 pub mod projects;
+pub mod scheduler;
+pub mod intake;
+pub mod rolling;
+pub mod attachment;
 
 // Re-export the types from the todo module that are used elsewhere
-pub use todo::{TodoList, TodoProcessor, TodoTask, TaskPriority, TaskStatus};
+pub use todo::{TodoList, TodoError, TodoProcessor, TodoTask, TaskPriority, TaskStatus, MCP_PRIORITY_KEY, Clarification, Recurrence, TaskSchedule, RetryPolicy, FailureOutcome, TaskLease, Heartbeat, derive_idempotency_key, idempotency_window_secs};
+pub use scheduler::{PriorityClass, SchedulerConfig, ScheduledTask, TaskScheduler, Throttled};
+pub use intake::{IntakeError, IntakeRequest, INTAKE_SCHEMA_VERSION};
+pub use rolling::{LatencySummary, RollingWindow};
+pub use attachment::{Attachment, AttachmentSource, AttachmentStore};
 
 // The rest of the file remains the same to avoid breaking other dependencies
 // (All the existing type definitions)
@@ -66,22 +74,62 @@ pub struct TranscriptItem {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
+    #[serde(default = "new_message_id")]
+    pub id: String,
     pub content: String,
     pub metadata: Option<MessageMetadata>,
     pub role: Option<String>,
     pub timestamp: Option<i64>,
+    /// Id of the message this one replies to, for threading
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
+}
+
+/// Metadata context key holding [`Message::session`]
+pub const SESSION_CONTEXT_KEY: &str = "session_id";
+/// Metadata context key holding [`Message::response_format`]
+pub const RESPONSE_FORMAT_CONTEXT_KEY: &str = "response_format";
+/// Metadata context key holding [`Message::task_id`]
+pub const TASK_CONTEXT_KEY: &str = "task_id";
+/// Metadata context key holding [`Message::is_dry_run`]
+pub const DRY_RUN_CONTEXT_KEY: &str = "dry_run";
+/// Metadata context key holding [`Message::questions`], one per line
+pub const NEEDS_INPUT_CONTEXT_KEY: &str = "needs_input";
+
+/// How the caller wants the reply: prose, or a structured JSON payload
+/// (see [`StructuredOutput`](crate::agents::structured::StructuredOutput))
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResponseFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+fn new_message_id() -> String {
+    uuid::Uuid::new_v4().to_string()
 }
 
 impl Message {
     pub fn new(content: String) -> Self {
         Self {
+            id: new_message_id(),
             content,
             metadata: None,
             role: Some("assistant".to_string()),
             timestamp: Some(chrono::Utc::now().timestamp()),
+            parent_id: None,
+            attachments: Vec::new(),
         }
     }
 
+    /// A new message threaded under this one
+    pub fn reply(&self, content: String) -> Self {
+        Self::new(content).with_parent(self.id.clone())
+    }
+
     pub fn with_metadata(mut self, metadata: MessageMetadata) -> Self {
         self.metadata = Some(metadata);
         self
@@ -96,6 +144,103 @@ impl Message {
         self.timestamp = timestamp;
         self
     }
+
+    pub fn with_parent(mut self, parent_id: String) -> Self {
+        self.parent_id = Some(parent_id);
+        self
+    }
+
+    pub fn with_attachments(mut self, attachments: Vec<Attachment>) -> Self {
+        self.attachments = attachments;
+        self
+    }
+
+    pub(crate) fn with_context_entry(mut self, key: &str, value: String) -> Self {
+        self.metadata
+            .get_or_insert_with(|| MessageMetadata::new(String::new()))
+            .context
+            .get_or_insert_with(HashMap::new)
+            .insert(key.to_string(), value);
+        self
+    }
+
+    pub(crate) fn context_entry(&self, key: &str) -> Option<&str> {
+        self.metadata.as_ref()?.context.as_ref()?.get(key).map(String::as_str)
+    }
+
+    /// Mark the message as part of `session`, e.g. a connection or a task
+    pub fn with_session(self, session: impl Into<String>) -> Self {
+        self.with_context_entry(SESSION_CONTEXT_KEY, session.into())
+    }
+
+    /// The session the message belongs to, for agents that keep state per caller
+    pub fn session(&self) -> Option<&str> {
+        self.context_entry(SESSION_CONTEXT_KEY)
+    }
+
+    /// Mark the message as the work of task `task_id`
+    pub fn with_task(self, task_id: impl Into<String>) -> Self {
+        self.with_context_entry(TASK_CONTEXT_KEY, task_id.into())
+    }
+
+    /// The task the message was built from, when it came off the todo queue
+    pub fn task_id(&self) -> Option<&str> {
+        self.context_entry(TASK_CONTEXT_KEY)
+    }
+
+    /// Have the agent describe what its tools would change instead of changing
+    /// it (see [`dry_run`](crate::tools::dry_run)); a no-op when `dry_run` is false
+    pub fn with_dry_run(self, dry_run: bool) -> Self {
+        if dry_run {
+            self.with_context_entry(DRY_RUN_CONTEXT_KEY, "true".to_string())
+        } else {
+            self
+        }
+    }
+
+    /// Whether the message asked for a dry run
+    pub fn is_dry_run(&self) -> bool {
+        self.context_entry(DRY_RUN_CONTEXT_KEY) == Some("true")
+    }
+
+    /// Ask the agent to reply in `format`
+    pub fn with_response_format(self, format: ResponseFormat) -> Self {
+        match format {
+            ResponseFormat::Text => self,
+            ResponseFormat::Json => self.with_context_entry(RESPONSE_FORMAT_CONTEXT_KEY, "json".to_string()),
+        }
+    }
+
+    /// The reply format the sender asked for
+    pub fn response_format(&self) -> ResponseFormat {
+        match self.context_entry(RESPONSE_FORMAT_CONTEXT_KEY) {
+            Some("json") => ResponseFormat::Json,
+            _ => ResponseFormat::Text,
+        }
+    }
+
+    /// A reply asking the sender to answer `questions` before the agent can
+    /// go on. Task processors park the task until an answer arrives.
+    pub fn needs_input<S: AsRef<str>>(questions: &[S]) -> Self {
+        let questions: Vec<&str> = questions.iter()
+            .map(|question| question.as_ref().trim())
+            .filter(|question| !question.is_empty())
+            .collect();
+        let mut content = String::from("I need more information before I can continue:");
+        for question in &questions {
+            content.push_str(&format!("\n- {}", question));
+        }
+        Self::new(content).with_context_entry(NEEDS_INPUT_CONTEXT_KEY, questions.join("\n"))
+    }
+
+    /// The questions the agent is waiting on, if this reply asks for input
+    pub fn questions(&self) -> Option<Vec<String>> {
+        let questions: Vec<String> = self.context_entry(NEEDS_INPUT_CONTEXT_KEY)?
+            .lines()
+            .map(str::to_string)
+            .collect();
+        (!questions.is_empty()).then_some(questions)
+    }
 }
 
 impl fmt::Display for Message {
@@ -168,9 +313,12 @@ pub struct StateMachine {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct State {
     pub name: String,
+    /// JSON schema that persisted `state_data` must satisfy in this state
     pub data: Option<String>,
     pub prompt: Option<String>,
+    /// Event name to target state
     pub transitions: Option<HashMap<String, String>>,
+    /// `[pattern, error message]` pairs a reply must match in this state
     pub validation: Option<Vec<String>>,
 }
 
@@ -196,19 +344,39 @@ pub struct ValidationRule {
 
 #[async_trait]
 pub trait Agent: Send + Sync {
-    async fn process_message(&self, message: Message) -> Result<Message>;
-    async fn transfer_to(&self, target_agent: String, message: Message) -> Result<Message>;
-    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> Result<String>;
-    async fn get_current_state(&self) -> Result<Option<State>>;
-    async fn get_config(&self) -> Result<AgentConfig>;
+    /// Slow, fallible set-up deferred from construction: fetching remote
+    /// lists, scheduling background work. The registry runs it once per
+    /// agent, at startup or before the first message.
+    async fn start(&self) -> crate::Result<()> {
+        Ok(())
+    }
+
+    async fn process_message(&self, message: Message) -> crate::Result<Message>;
+    async fn transfer_to(&self, target_agent: String, message: Message) -> crate::Result<Message>;
+    async fn call_tool(&self, tool: &Tool, params: HashMap<String, String>) -> crate::Result<String>;
+
+    /// Make several independent tool calls at once, returning each result in
+    /// call order. Agents backed by a [`ToolRegistry`](crate::tools::ToolRegistry)
+    /// should override this with its `execute_batch`.
+    async fn call_tools(&self, calls: Vec<(Tool, HashMap<String, String>)>) -> Vec<crate::Result<String>> {
+        use futures::StreamExt;
+        futures::stream::iter(calls)
+            .map(|(tool, params)| async move { self.call_tool(&tool, params).await })
+            .buffered(crate::tools::DEFAULT_TOOL_PARALLELISM)
+            .collect()
+            .await
+    }
+
+    async fn get_current_state(&self) -> crate::Result<Option<State>>;
+    async fn get_config(&self) -> crate::Result<AgentConfig>;
 
     fn get_todo_list(&self) -> Option<&TodoList> {
         None
     }
 
-    async fn delegate_task(&self, task: TodoTask, registry: &AgentRegistry) -> Result<()> {
+    async fn delegate_task(&self, task: TodoTask, registry: &AgentRegistry) -> crate::Result<()> {
         if let Some(target_agent) = registry.get(&task.target_agent) {
-            let todo_list = <AgentWrapper as TodoProcessor>::get_todo_list(target_agent);
+            let todo_list = <AgentWrapper as TodoProcessor>::get_todo_list(&*target_agent.read().await).clone();
             todo_list.add_task(task).await;
             Ok(())
         } else {
@@ -241,10 +409,17 @@ impl AgentStateManager {
         self
     }
 
+    /// Follows `event` from the current state, announcing the move on the shared event bus
     pub fn transition(&mut self, event: &str) -> Option<&State> {
         if let (Some(state_machine), Some(current_state)) = (&self.state_machine, &self.current_state) {
             if let Some(current) = state_machine.states.get(current_state) {
                 if let Some(next_state) = current.transitions.as_ref().and_then(|transitions| transitions.get(event)) {
+                    crate::events::EventBus::shared().publish(crate::events::Event::StateTransitioned {
+                        agent: self.agent_id.clone(),
+                        from: current_state.clone(),
+                        to: next_state.clone(),
+                        trigger: event.to_string(),
+                    });
                     self.current_state = Some(next_state.clone());
                     self.version += 1;
                     return state_machine.states.get(next_state);
@@ -291,4 +466,50 @@ pub struct AgentInfo {
     pub instructions: String,
     pub tools: Vec<Tool>,
     pub downstream_agents: Vec<String>,
+    pub readiness: Readiness,
+}
+
+/// Where an agent is in its warm-up, see [`Agent::start`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "state", content = "error", rename_all = "snake_case")]
+pub enum Readiness {
+    /// Not started yet; starts with its first message
+    Pending,
+    Starting,
+    Ready,
+    /// The last attempt failed; the next message tries again
+    Failed(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_threading() {
+        let question = Message::new("hello".to_string());
+        let answer = question.reply("hi there".to_string());
+
+        assert_ne!(question.id, answer.id);
+        assert_eq!(answer.parent_id.as_deref(), Some(question.id.as_str()));
+        assert!(answer.timestamp.is_some());
+    }
+
+    #[test]
+    fn test_message_without_id_deserializes() {
+        let message: Message = serde_json::from_str(
+            r#"{"content": "legacy", "metadata": null, "role": "user", "timestamp": 1}"#
+        ).unwrap();
+
+        assert!(!message.id.is_empty());
+        assert!(message.parent_id.is_none());
+    }
+
+    #[test]
+    fn test_needs_input() {
+        let reply = Message::needs_input(&["Which branch?", " ", "Squash the commits?"]);
+        assert_eq!(reply.questions(), Some(vec!["Which branch?".to_string(), "Squash the commits?".to_string()]));
+        assert!(reply.content.contains("- Which branch?"));
+        assert_eq!(Message::new("done".to_string()).questions(), None);
+    }
 }
@@ -0,0 +1,135 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+use serde::Serialize;
+
+/// Samples kept before the oldest are dropped, however recent
+const DEFAULT_CAPACITY: usize = 100_000;
+
+/// Timestamped samples from the last `retention`, for statistics over recent
+/// activity rather than the whole process lifetime
+#[derive(Debug, Clone)]
+pub struct RollingWindow<T> {
+    retention: Duration,
+    capacity: usize,
+    samples: VecDeque<(Instant, T)>,
+}
+
+impl<T> RollingWindow<T> {
+    pub fn new(retention: Duration) -> Self {
+        Self { retention, capacity: DEFAULT_CAPACITY, samples: VecDeque::new() }
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    pub fn push(&mut self, value: T) {
+        self.push_at(Instant::now(), value);
+    }
+
+    pub fn push_at(&mut self, now: Instant, value: T) {
+        self.expire(now);
+        if self.samples.len() >= self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((now, value));
+    }
+
+    /// Samples recorded within `span` of `now`, oldest first
+    pub fn since(&self, now: Instant, span: Duration) -> impl Iterator<Item = &T> {
+        self.samples.iter()
+            .filter(move |(at, _)| now.saturating_duration_since(*at) <= span)
+            .map(|(_, value)| value)
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while let Some((at, _)) = self.samples.front() {
+            if now.saturating_duration_since(*at) <= self.retention {
+                break;
+            }
+            self.samples.pop_front();
+        }
+    }
+}
+
+/// Nearest-rank percentile of already sorted values
+pub fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct LatencySummary {
+    pub count: usize,
+    pub p50_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+}
+
+impl LatencySummary {
+    pub fn from_durations<'a>(durations: impl IntoIterator<Item = &'a Duration>) -> Self {
+        let mut millis: Vec<f64> = durations.into_iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+        millis.sort_by(|a, b| a.total_cmp(b));
+        Self {
+            count: millis.len(),
+            p50_ms: percentile(&millis, 50.0),
+            p95_ms: percentile(&millis, 95.0),
+            p99_ms: percentile(&millis, 99.0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_window_expiry_and_spans() {
+        let mut window = RollingWindow::new(Duration::from_secs(3600));
+        let start = Instant::now();
+        window.push_at(start, 1);
+        window.push_at(start + Duration::from_secs(3000), 2);
+        window.push_at(start + Duration::from_secs(3500), 3);
+
+        let now = start + Duration::from_secs(3600);
+        assert_eq!(window.since(now, Duration::from_secs(300)).copied().collect::<Vec<_>>(), vec![3]);
+        assert_eq!(window.since(now, Duration::from_secs(3600)).count(), 3);
+
+        // Pushing past the retention drops the first sample
+        window.push_at(start + Duration::from_secs(3700), 4);
+        assert_eq!(window.len(), 3);
+    }
+
+    #[test]
+    fn test_capacity() {
+        let mut window = RollingWindow::new(Duration::from_secs(60)).with_capacity(2);
+        window.push(1);
+        window.push(2);
+        window.push(3);
+        assert_eq!(window.since(Instant::now(), Duration::from_secs(60)).copied().collect::<Vec<_>>(), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let durations: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        let summary = LatencySummary::from_durations(&durations);
+        assert_eq!(summary.count, 100);
+        assert_eq!(summary.p50_ms, Some(50.0));
+        assert_eq!(summary.p95_ms, Some(95.0));
+        assert_eq!(summary.p99_ms, Some(99.0));
+
+        assert_eq!(LatencySummary::from_durations(&[]), LatencySummary::default());
+    }
+}
@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+use std::env;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use crate::clock::{self, Clock};
+use super::todo::{TaskPriority, TodoTask};
+
+/// Scheduling lane a task runs in, derived from its (aged) priority
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PriorityClass {
+    /// High and critical tasks. They may use the reserved urgent permits.
+    Urgent,
+    Normal,
+    /// Low and initial tasks, throttled to a few concurrent slots
+    Low,
+}
+
+impl PriorityClass {
+    pub fn of(priority: &TaskPriority) -> Self {
+        match priority {
+            TaskPriority::Critical | TaskPriority::High => PriorityClass::Urgent,
+            TaskPriority::Medium => PriorityClass::Normal,
+            TaskPriority::Low | TaskPriority::Inital => PriorityClass::Low,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PriorityClass::Urgent => "urgent",
+            PriorityClass::Normal => "normal",
+            PriorityClass::Low => "low",
+        }
+    }
+}
+
+/// How many tasks may run at once and how the slots are split between classes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchedulerConfig {
+    /// Upper bound on concurrently running tasks
+    pub total_permits: usize,
+    /// Slots out of `total_permits` that only urgent tasks may use
+    pub urgent_permits: usize,
+    /// Maximum number of low-priority tasks running at once
+    pub low_permits: usize,
+    /// Seconds a task waits before its priority is bumped one level; 0 disables aging
+    pub aging_secs: i64,
+    /// Maximum concurrent tasks per project, for projects that need it
+    pub project_limits: HashMap<String, usize>,
+    /// Maximum concurrent tasks per target agent
+    pub agent_limits: HashMap<String, usize>,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            total_permits: 5,
+            urgent_permits: 2,
+            low_permits: 1,
+            aging_secs: 600,
+            project_limits: HashMap::new(),
+            agent_limits: HashMap::new(),
+        }
+    }
+}
+
+impl SchedulerConfig {
+    /// Reads `SCHED_TOTAL_PERMITS`, `SCHED_URGENT_PERMITS`, `SCHED_LOW_PERMITS`,
+    /// `SCHED_AGING_SECS`, `SCHED_PROJECT_LIMITS` and `SCHED_AGENT_LIMITS`
+    pub fn from_env() -> Self {
+        fn read<T: FromStr>(key: &str, default: T) -> T {
+            env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+        }
+
+        let default = Self::default();
+        Self {
+            total_permits: read("SCHED_TOTAL_PERMITS", default.total_permits),
+            urgent_permits: read("SCHED_URGENT_PERMITS", default.urgent_permits),
+            low_permits: read("SCHED_LOW_PERMITS", default.low_permits),
+            aging_secs: read("SCHED_AGING_SECS", default.aging_secs),
+            project_limits: parse_limits(&env::var("SCHED_PROJECT_LIMITS").unwrap_or_default()),
+            agent_limits: parse_limits(&env::var("SCHED_AGENT_LIMITS").unwrap_or_default()),
+        }
+    }
+}
+
+/// Parse `name=limit,name=limit`, skipping malformed entries
+pub fn parse_limits(spec: &str) -> HashMap<String, usize> {
+    spec.split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .filter_map(|(name, limit)| Some((name.trim().to_string(), limit.trim().parse().ok()?)))
+        .filter(|(name, _)| !name.is_empty())
+        .collect()
+}
+
+/// Why a task could not be started on this pass
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Throttled {
+    /// Its priority lane has no free slot
+    Lane(PriorityClass),
+    /// Its project is already running as many tasks as allowed
+    Project(String),
+    /// Its target agent is already running as many tasks as allowed
+    Agent(String),
+}
+
+/// A ready task together with the lane it was scheduled into
+#[derive(Debug, Clone)]
+pub struct ScheduledTask {
+    pub task: TodoTask,
+    pub priority: TaskPriority,
+    pub class: PriorityClass,
+    /// Whether aging moved the task into a more urgent class than its own priority
+    pub promoted: bool,
+}
+
+/// Held for as long as a task runs; dropping it frees the slot.
+#[derive(Debug)]
+pub struct SchedulerPermit {
+    _slot: OwnedSemaphorePermit,
+    _throttle: Option<OwnedSemaphorePermit>,
+    _limits: Vec<OwnedSemaphorePermit>,
+}
+
+/// Weighted admission for background task processing.
+///
+/// Urgent tasks are dispatched first and can fall back on a pool of reserved
+/// permits when the shared pool is full. Low tasks additionally need one of a
+/// small number of throttle permits. Projects and agents with a configured
+/// limit also need a slot of their own. Waiting tasks age into higher
+/// priorities so nothing starves.
+#[derive(Debug, Clone)]
+pub struct TaskScheduler {
+    config: SchedulerConfig,
+    reserved: Arc<Semaphore>,
+    shared: Arc<Semaphore>,
+    low: Arc<Semaphore>,
+    projects: HashMap<String, Arc<Semaphore>>,
+    agents: HashMap<String, Arc<Semaphore>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl TaskScheduler {
+    pub fn new(config: SchedulerConfig) -> Self {
+        let total = config.total_permits.max(1);
+        let reserved = config.urgent_permits.min(total - 1);
+        Self {
+            reserved: Arc::new(Semaphore::new(reserved)),
+            shared: Arc::new(Semaphore::new(total - reserved)),
+            low: Arc::new(Semaphore::new(config.low_permits.max(1))),
+            projects: limit_semaphores(&config.project_limits),
+            agents: limit_semaphores(&config.agent_limits),
+            clock: clock::system(),
+            config,
+        }
+    }
+
+    /// Age waiting tasks against `clock` instead of the real time
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// The current time in Unix seconds, as this scheduler sees it
+    pub fn now(&self) -> i64 {
+        self.clock.timestamp()
+    }
+
+    pub fn config(&self) -> &SchedulerConfig {
+        &self.config
+    }
+
+    /// Tasks currently holding a lane slot
+    pub fn in_flight(&self) -> usize {
+        let total = self.config.total_permits.max(1);
+        total.saturating_sub(self.shared.available_permits() + self.reserved.available_permits())
+    }
+
+    /// Priority of `task` after aging, as of `now`
+    pub fn effective_priority(&self, task: &TodoTask, now: i64) -> TaskPriority {
+        if self.config.aging_secs <= 0 {
+            return task.priority.clone();
+        }
+        let waited = (now - task.created_at).max(0);
+        let bumps = (waited / self.config.aging_secs).min(u8::MAX as i64) as u8;
+        TaskPriority::from_rank(task.priority.rank().saturating_add(bumps))
+    }
+
+    /// Order ready tasks for dispatch: most urgent (after aging) first, oldest
+    /// first within the same priority.
+    pub fn order(&self, tasks: Vec<TodoTask>, now: i64) -> Vec<ScheduledTask> {
+        let mut scheduled: Vec<ScheduledTask> = tasks.into_iter()
+            .map(|task| {
+                let priority = self.effective_priority(&task, now);
+                let class = PriorityClass::of(&priority);
+                let promoted = class != PriorityClass::of(&task.priority);
+                ScheduledTask { task, priority, class, promoted }
+            })
+            .collect();
+
+        scheduled.sort_by(|a, b| {
+            b.priority.rank().cmp(&a.priority.rank())
+                .then(a.task.created_at.cmp(&b.task.created_at))
+        });
+        scheduled
+    }
+
+    /// Take a slot for a task of `class`, or `None` if its lane is full
+    pub fn try_acquire(&self, class: PriorityClass) -> Option<SchedulerPermit> {
+        match class {
+            PriorityClass::Urgent => {
+                let slot = self.shared.clone().try_acquire_owned()
+                    .or_else(|_| self.reserved.clone().try_acquire_owned())
+                    .ok()?;
+                Some(SchedulerPermit { _slot: slot, _throttle: None, _limits: Vec::new() })
+            }
+            PriorityClass::Normal => {
+                let slot = self.shared.clone().try_acquire_owned().ok()?;
+                Some(SchedulerPermit { _slot: slot, _throttle: None, _limits: Vec::new() })
+            }
+            PriorityClass::Low => {
+                let throttle = self.low.clone().try_acquire_owned().ok()?;
+                let slot = self.shared.clone().try_acquire_owned().ok()?;
+                Some(SchedulerPermit { _slot: slot, _throttle: Some(throttle), _limits: Vec::new() })
+            }
+        }
+    }
+
+    /// Take every slot `scheduled` needs: its project's and agent's, if they
+    /// are limited, and one in its priority lane
+    pub fn try_acquire_for(&self, scheduled: &ScheduledTask) -> Result<SchedulerPermit, Throttled> {
+        let mut limits = Vec::new();
+        if let Some(project) = &scheduled.task.project {
+            if let Some(semaphore) = self.projects.get(project) {
+                limits.push(semaphore.clone().try_acquire_owned()
+                    .map_err(|_| Throttled::Project(project.clone()))?);
+            }
+        }
+        let agent = &scheduled.task.target_agent;
+        if let Some(semaphore) = self.agents.get(agent) {
+            limits.push(semaphore.clone().try_acquire_owned()
+                .map_err(|_| Throttled::Agent(agent.clone()))?);
+        }
+
+        let mut permit = self.try_acquire(scheduled.class).ok_or(Throttled::Lane(scheduled.class))?;
+        permit._limits = limits;
+        Ok(permit)
+    }
+}
+
+fn limit_semaphores(limits: &HashMap<String, usize>) -> HashMap<String, Arc<Semaphore>> {
+    limits.iter()
+        .map(|(name, limit)| (name.clone(), Arc::new(Semaphore::new(*limit))))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn task(id: &str, priority: TaskPriority, created_at: i64) -> TodoTask {
+        TodoTask {
+            id: id.to_string(),
+            description: id.to_string(),
+            priority,
+            target_agent: "worker".to_string(),
+            created_at,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_aging_follows_clock() {
+        let clock = Arc::new(clock::FakeClock::at(1_000));
+        let scheduler = TaskScheduler::new(SchedulerConfig { aging_secs: 100, ..Default::default() })
+            .with_clock(clock.clone());
+        let waiting = task("waiting", TaskPriority::Low, 1_000);
+        assert_eq!(scheduler.effective_priority(&waiting, scheduler.now()), TaskPriority::Low);
+
+        clock.advance(std::time::Duration::from_secs(200));
+        assert_eq!(scheduler.effective_priority(&waiting, scheduler.now()), TaskPriority::High);
+    }
+
+    #[test]
+    fn test_order_and_aging() {
+        let scheduler = TaskScheduler::new(SchedulerConfig { aging_secs: 100, ..Default::default() });
+        let now = 1_000;
+        let ordered = scheduler.order(vec![
+            task("medium", TaskPriority::Medium, 990),
+            task("critical", TaskPriority::Critical, 995),
+            task("old-low", TaskPriority::Low, 750),
+            task("new-low", TaskPriority::Low, 999),
+        ], now);
+
+        let ids: Vec<&str> = ordered.iter().map(|s| s.task.id.as_str()).collect();
+        assert_eq!(ids, vec!["critical", "old-low", "medium", "new-low"]);
+
+        let aged = &ordered[1];
+        assert_eq!(aged.priority, TaskPriority::High);
+        assert_eq!(aged.class, PriorityClass::Urgent);
+        assert!(aged.promoted);
+        assert!(!ordered[3].promoted);
+    }
+
+    #[test]
+    fn test_urgent_permits_are_reserved() {
+        let scheduler = TaskScheduler::new(SchedulerConfig {
+            total_permits: 3,
+            urgent_permits: 1,
+            low_permits: 1,
+            aging_secs: 0,
+            ..Default::default()
+        });
+
+        let _normal = [
+            scheduler.try_acquire(PriorityClass::Normal).unwrap(),
+            scheduler.try_acquire(PriorityClass::Normal).unwrap(),
+        ];
+        assert!(scheduler.try_acquire(PriorityClass::Normal).is_none());
+
+        let urgent = scheduler.try_acquire(PriorityClass::Urgent);
+        assert!(urgent.is_some());
+        assert!(scheduler.try_acquire(PriorityClass::Urgent).is_none());
+        assert_eq!(scheduler.in_flight(), 3);
+
+        drop(urgent);
+        assert_eq!(scheduler.in_flight(), 2);
+    }
+
+    #[test]
+    fn test_project_and_agent_limits() {
+        let scheduler = TaskScheduler::new(SchedulerConfig {
+            project_limits: parse_limits("regressiontestkit=1"),
+            agent_limits: parse_limits("git=1"),
+            aging_secs: 0,
+            ..Default::default()
+        });
+
+        let mut lab = task("lab", TaskPriority::High, 0);
+        lab.project = Some("regressiontestkit".to_string());
+        let ordered = scheduler.order(vec![lab.clone(), lab], 0);
+
+        let held = scheduler.try_acquire_for(&ordered[0]).unwrap();
+        assert_eq!(
+            scheduler.try_acquire_for(&ordered[1]).unwrap_err(),
+            Throttled::Project("regressiontestkit".to_string())
+        );
+        drop(held);
+        assert!(scheduler.try_acquire_for(&ordered[1]).is_ok());
+
+        let mut git = task("git", TaskPriority::Medium, 0);
+        git.target_agent = "git".to_string();
+        let ordered = scheduler.order(vec![git.clone(), git], 0);
+        let _held = scheduler.try_acquire_for(&ordered[0]).unwrap();
+        assert_eq!(scheduler.try_acquire_for(&ordered[1]).unwrap_err(), Throttled::Agent("git".to_string()));
+    }
+
+    #[test]
+    fn test_every_priority_has_a_class() {
+        let classes: Vec<_> = TaskPriority::ALL.iter().map(PriorityClass::of).collect();
+        assert_eq!(classes, [
+            PriorityClass::Low, PriorityClass::Low, PriorityClass::Normal,
+            PriorityClass::Urgent, PriorityClass::Urgent,
+        ]);
+    }
+
+    #[test]
+    fn test_parse_limits() {
+        let limits = parse_limits("regressiontestkit=1, omnispindle = 3,bogus,x=y,=2");
+        assert_eq!(limits.len(), 2);
+        assert_eq!(limits["regressiontestkit"], 1);
+        assert_eq!(limits["omnispindle"], 3);
+    }
+
+    #[test]
+    fn test_low_tasks_are_throttled() {
+        let scheduler = TaskScheduler::new(SchedulerConfig::default());
+
+        let low = scheduler.try_acquire(PriorityClass::Low).unwrap();
+        assert!(scheduler.try_acquire(PriorityClass::Low).is_none());
+        assert!(scheduler.try_acquire(PriorityClass::Normal).is_some());
+
+        drop(low);
+        assert!(scheduler.try_acquire(PriorityClass::Low).is_some());
+    }
+}
@@ -1,17 +1,20 @@
-use std::collections::VecDeque;
+use std::collections::{HashSet, VecDeque};
+use std::str::FromStr;
 use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 use std::sync::Arc;
-use super::Message;
-use mongodb::{Client, Collection, Database};
-use mongodb::bson::{doc, DateTime};
+use super::{Attachment, Message};
+use mongodb::{Collection, Database};
+use mongodb::bson::doc;
 use mongodb::error::Error as MongoError;
 use futures_util::TryStreamExt;
 use std::env;
-use uuid::Uuid;
 use std::collections::HashMap;
-use chrono::{Utc};
+use chrono::{Utc, TimeZone};
 use crate::ai::AiProvider;
+use crate::clients::Clients;
+use crate::clock::{self, Clock, IdGenerator};
+use crate::ai::budget::{BudgetExceeded, TaskBudget};
 use crate::types::projects::{get_default_project};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,8 +34,316 @@ pub struct TodoTask {
     pub notes: Option<String>,
     pub ticket: Option<String>,
     pub last_modified: Option<i64>,
+    /// Ids of tasks that must complete before this one is scheduled
+    #[serde(default)]
+    pub depends_on: Vec<String>,
+    /// When set, completing the task enqueues its next occurrence
+    #[serde(default)]
+    pub recurrence: Option<Recurrence>,
+    /// Id of the run this task was scheduled from, for recurring tasks
+    #[serde(default)]
+    pub previous_run_id: Option<String>,
+    /// Earliest time (unix seconds) the task may be picked up
+    #[serde(default)]
+    pub scheduled_for: Option<i64>,
+    /// Deadline (unix seconds); active tasks past it are escalated by the worker
+    #[serde(default)]
+    pub due_at: Option<i64>,
+    /// When the task was last escalated for being overdue
+    #[serde(default)]
+    pub escalated_at: Option<i64>,
+    /// Number of times processing has been attempted and failed
+    #[serde(default)]
+    pub attempts: u32,
+    /// Overrides the worker's default retry limit for this task
+    #[serde(default)]
+    pub max_attempts: Option<u32>,
+    /// One entry per failed attempt, oldest first
+    #[serde(default)]
+    pub error_history: Vec<TaskFailure>,
+    /// Worker currently holding the task while it is in progress
+    #[serde(default)]
+    pub claimed_by: Option<String>,
+    /// When the claim lapses unless the worker heartbeats (unix seconds)
+    #[serde(default)]
+    pub lease_expires_at: Option<i64>,
+    /// Key identifying the request that created the task, used to drop replays
+    #[serde(default)]
+    pub idempotency_key: Option<String>,
+    /// Files handed to the agent with the task
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+    /// Questions the agent asked about the task and the answers it got, oldest first
+    #[serde(default)]
+    pub clarifications: Vec<Clarification>,
+    /// Task this one was planned from, for subtasks created by the planner
+    #[serde(default)]
+    pub parent_id: Option<String>,
+    /// Limits on the AI calls, tokens and time spent processing the task;
+    /// `TASK_MAX_*` apply when absent
+    #[serde(default)]
+    pub budget: Option<TaskBudget>,
+    /// Process the task as a dry run, describing side effects instead of causing them
+    #[serde(default)]
+    pub dry_run: bool,
 }
 
+/// A pending, medium priority task with nothing else set. Literals fill in
+/// what they need and take the rest with `..Default::default()`.
+impl Default for TodoTask {
+    fn default() -> Self {
+        Self {
+            id: String::new(),
+            description: String::new(),
+            enhanced_description: None,
+            priority: TaskPriority::Medium,
+            project: None,
+            source_agent: None,
+            target_agent: String::new(),
+            status: TaskStatus::Pending,
+            created_at: 0,
+            completed_at: None,
+            due_date: None,
+            duration_minutes: None,
+            notes: None,
+            ticket: None,
+            last_modified: None,
+            depends_on: Vec::new(),
+            recurrence: None,
+            previous_run_id: None,
+            scheduled_for: None,
+            due_at: None,
+            escalated_at: None,
+            attempts: 0,
+            max_attempts: None,
+            error_history: Vec::new(),
+            claimed_by: None,
+            lease_expires_at: None,
+            idempotency_key: None,
+            attachments: Vec::new(),
+            clarifications: Vec::new(),
+            parent_id: None,
+            budget: None,
+            dry_run: false,
+        }
+    }
+}
+
+/// One round of an agent asking about a task it couldn't act on
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Clarification {
+    pub questions: Vec<String>,
+    pub answer: Option<String>,
+    pub asked_at: i64,
+    pub answered_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TaskFailure {
+    pub attempt: u32,
+    pub error: String,
+    pub timestamp: i64,
+}
+
+/// How many times a failing task is retried, and how long to wait in between.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_backoff_secs: u64,
+    pub max_backoff_secs: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_backoff_secs: 30,
+            max_backoff_secs: 3600,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Reads `TASK_MAX_ATTEMPTS`, `TASK_RETRY_BACKOFF_SECS` and `TASK_RETRY_MAX_BACKOFF_SECS`
+    pub fn from_env() -> Self {
+        fn read<T: FromStr>(key: &str, default: T) -> T {
+            env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+        }
+
+        let default = Self::default();
+        Self {
+            max_attempts: read("TASK_MAX_ATTEMPTS", default.max_attempts),
+            base_backoff_secs: read("TASK_RETRY_BACKOFF_SECS", default.base_backoff_secs),
+            max_backoff_secs: read("TASK_RETRY_MAX_BACKOFF_SECS", default.max_backoff_secs),
+        }
+    }
+
+    /// Exponential backoff before the attempt following failed attempt number `attempt`
+    pub fn backoff_for(&self, attempt: u32) -> u64 {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        self.base_backoff_secs.saturating_mul(factor).min(self.max_backoff_secs)
+    }
+}
+
+/// Identity of a worker claiming tasks, and how long its claims last without
+/// a heartbeat. A worker that crashes stops heartbeating, so its tasks are
+/// released once the lease runs out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TaskLease {
+    pub worker_id: String,
+    pub lease_secs: i64,
+    pub heartbeat_secs: u64,
+}
+
+impl TaskLease {
+    pub fn new(worker_id: impl Into<String>, lease_secs: i64) -> Self {
+        Self {
+            worker_id: worker_id.into(),
+            lease_secs,
+            heartbeat_secs: (lease_secs / 3).max(1) as u64,
+        }
+    }
+
+    /// Reads `TASK_LEASE_SECS` (default 120) and `TASK_HEARTBEAT_SECS` (default a third of the lease)
+    pub fn from_env(worker_id: impl Into<String>) -> Self {
+        let lease_secs = env::var("TASK_LEASE_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(120);
+        let mut lease = Self::new(worker_id, lease_secs);
+        if let Some(heartbeat) = env::var("TASK_HEARTBEAT_SECS").ok().and_then(|v| v.parse().ok()) {
+            lease.heartbeat_secs = heartbeat;
+        }
+        lease
+    }
+}
+
+/// Renews the lease on a claimed task until dropped, so a job that fails,
+/// panics or is aborted stops holding its task
+pub struct Heartbeat(tokio::task::JoinHandle<()>);
+
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// The `limit` most urgent of `candidates` whose dependencies are all in
+/// `completed`. Priority is stored as a name, so every due task is ranked here
+/// before the batch is cut, letting new urgent work past an older backlog.
+fn rank_ready(mut candidates: Vec<TodoTask>, completed: &HashSet<String>, limit: usize) -> Vec<TodoTask> {
+    candidates.sort_by(|a, b| b.priority.rank().cmp(&a.priority.rank()).then(a.created_at.cmp(&b.created_at)));
+    candidates.into_iter()
+        .filter(|task| task.depends_on.iter().all(|id| completed.contains(id)))
+        .take(limit)
+        .collect()
+}
+
+/// What happened to a task after a failed attempt was recorded
+#[derive(Debug, Clone)]
+pub enum FailureOutcome {
+    /// Re-queued; it will be picked up again at `retry_at`
+    Retrying { task: TodoTask, retry_at: i64 },
+    /// Out of attempts and moved to the dead-letter collection
+    DeadLettered(TodoTask),
+    /// The task no longer exists or was cancelled in the meantime
+    Discarded,
+}
+
+impl TodoTask {
+    /// Past its deadline and not yet finished
+    pub fn is_overdue(&self, now: i64) -> bool {
+        self.status.is_active() && self.due_at.map(|due| due < now).unwrap_or(false)
+    }
+
+    /// The description an agent should work from: the enhanced one if there
+    /// is one, followed by every answered clarification so far
+    pub fn clarified_description(&self) -> String {
+        let mut description = self.enhanced_description.clone()
+            .filter(|enhanced| !enhanced.is_empty())
+            .unwrap_or_else(|| self.description.clone());
+        for clarification in &self.clarifications {
+            if let Some(answer) = &clarification.answer {
+                description.push_str(&format!(
+                    "\n\nYou asked:\n{}\nAnswer: {}",
+                    clarification.questions.iter().map(|q| format!("- {}", q)).collect::<Vec<_>>().join("\n"),
+                    answer
+                ));
+            }
+        }
+        description
+    }
+}
+
+/// Scheduling and other optional settings for a new task
+#[derive(Debug, Clone, Default)]
+pub struct TaskSchedule {
+    pub depends_on: Vec<String>,
+    pub due_at: Option<i64>,
+    pub recurrence: Option<Recurrence>,
+    /// Caller-supplied idempotency key; derived from the description and
+    /// project when absent
+    pub idempotency_key: Option<String>,
+    pub attachments: Vec<Attachment>,
+    pub budget: Option<TaskBudget>,
+    pub dry_run: bool,
+}
+
+/// Stable key for a task request, used when the caller does not supply one
+pub fn derive_idempotency_key(description: &str, project: Option<&str>) -> String {
+    use sha2::{Digest, Sha256};
+
+    let mut hasher = Sha256::new();
+    hasher.update(project.unwrap_or_default().trim().to_lowercase().as_bytes());
+    hasher.update([0u8]);
+    hasher.update(description.trim().to_lowercase().as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// How long a replayed idempotency key returns the original task instead of
+/// creating a new one. Reads `TASK_IDEMPOTENCY_WINDOW_SECS`; 0 disables deduplication.
+pub fn idempotency_window_secs() -> i64 {
+    env::var("TASK_IDEMPOTENCY_WINDOW_SECS").ok().and_then(|v| v.parse().ok()).unwrap_or(300)
+}
+
+/// How often a recurring task repeats.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Recurrence {
+    Interval { seconds: u64 },
+    /// Standard five-field cron expression, or six/seven fields with seconds
+    Cron { expression: String },
+}
+
+impl Recurrence {
+    /// Next occurrence strictly after `after` (unix seconds)
+    pub fn next_after(&self, after: i64) -> Result<i64, String> {
+        match self {
+            Recurrence::Interval { seconds } => {
+                if *seconds == 0 {
+                    return Err("Recurrence interval must be greater than zero".to_string());
+                }
+                Ok(after + *seconds as i64)
+            }
+            Recurrence::Cron { expression } => {
+                // The cron crate expects a seconds field; accept the common five-field form too
+                let expression = if expression.split_whitespace().count() == 5 {
+                    format!("0 {}", expression)
+                } else {
+                    expression.clone()
+                };
+                let schedule = cron::Schedule::from_str(&expression)
+                    .map_err(|e| format!("Invalid cron expression '{}': {}", expression, e))?;
+                let after = Utc.timestamp_opt(after, 0).single()
+                    .ok_or_else(|| format!("Invalid timestamp {}", after))?;
+                schedule.after(&after).next()
+                    .map(|next| next.timestamp())
+                    .ok_or_else(|| format!("Cron expression '{}' has no upcoming runs", expression))
+            }
+        }
+    }
+}
+
+/// Metadata key holding a todo's exact priority on the MCP server
+pub const MCP_PRIORITY_KEY: &str = "priority";
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum TaskPriority {
     #[serde(rename = "Inital")]
@@ -47,40 +358,211 @@ pub enum TaskPriority {
     Critical,
 }
 
+impl TaskPriority {
+    /// Every priority, least urgent first. Code that counts, maps or labels
+    /// priorities iterates this rather than listing variants itself.
+    pub const ALL: [TaskPriority; 5] = [
+        TaskPriority::Inital,
+        TaskPriority::Low,
+        TaskPriority::Medium,
+        TaskPriority::High,
+        TaskPriority::Critical,
+    ];
+
+    /// Lower-case name, as used in logs and metrics keys
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskPriority::Inital => "inital",
+            TaskPriority::Low => "low",
+            TaskPriority::Medium => "medium",
+            TaskPriority::High => "high",
+            TaskPriority::Critical => "critical",
+        }
+    }
+
+    /// A priority by name in any case; `initial` is accepted for [`Inital`](TaskPriority::Inital)
+    pub fn parse(name: &str) -> Option<TaskPriority> {
+        match name.trim().to_lowercase().as_str() {
+            "initial" => Some(TaskPriority::Inital),
+            name => TaskPriority::ALL.into_iter().find(|priority| priority.as_str() == name),
+        }
+    }
+
+    /// The level Omnispindle stores. It only has Low, Medium and High, so
+    /// the exact priority also goes in the todo's metadata under
+    /// [`MCP_PRIORITY_KEY`] and is restored by [`from_mcp`](Self::from_mcp).
+    pub fn mcp_level(&self) -> &'static str {
+        match self {
+            TaskPriority::Inital | TaskPriority::Medium => "Medium",
+            TaskPriority::Low => "Low",
+            TaskPriority::High | TaskPriority::Critical => "High",
+        }
+    }
+
+    /// The priority of a todo Omnispindle returned as `level`, using the
+    /// exact one from its metadata unless the level was changed since
+    pub fn from_mcp(level: TaskPriority, exact: Option<&str>) -> TaskPriority {
+        exact.and_then(TaskPriority::parse)
+            .filter(|exact| exact.mcp_level() == level.mcp_level())
+            .unwrap_or(level)
+    }
+
+    /// Numeric urgency, higher is more urgent
+    pub fn rank(&self) -> u8 {
+        match self {
+            TaskPriority::Inital => 0,
+            TaskPriority::Low => 1,
+            TaskPriority::Medium => 2,
+            TaskPriority::High => 3,
+            TaskPriority::Critical => 4,
+        }
+    }
+
+    pub fn from_rank(rank: u8) -> TaskPriority {
+        match rank {
+            0 => TaskPriority::Inital,
+            1 => TaskPriority::Low,
+            2 => TaskPriority::Medium,
+            3 => TaskPriority::High,
+            _ => TaskPriority::Critical,
+        }
+    }
+
+    /// One level more urgent, saturating at critical
+    pub fn escalate(&self) -> TaskPriority {
+        match self {
+            TaskPriority::Inital | TaskPriority::Low => TaskPriority::Medium,
+            TaskPriority::Medium => TaskPriority::High,
+            TaskPriority::High | TaskPriority::Critical => TaskPriority::Critical,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum TaskStatus {
     #[serde(rename = "initial")]
     Initial,
     #[serde(rename = "pending")]
     Pending,
+    #[serde(rename = "in_progress")]
+    InProgress,
     #[serde(rename = "review")]
     Review,
+    /// Parked until someone answers the agent's questions
+    #[serde(rename = "waiting_for_input")]
+    WaitingForInput,
     #[serde(rename = "completed")]
     Completed,
     #[serde(rename = "failed")]
     Failed,
+    #[serde(rename = "cancelled")]
+    Cancelled,
+    /// Stopped for going over its budget; not retried automatically
+    #[serde(rename = "budget_exceeded")]
+    BudgetExceeded,
+}
+
+impl TaskStatus {
+    /// Statuses a task may be cancelled from. Cancelling a running task
+    /// signals the processor to drop the result instead of marking the task
+    /// completed.
+    pub fn can_cancel(&self) -> bool {
+        matches!(
+            self,
+            TaskStatus::Initial | TaskStatus::Pending | TaskStatus::InProgress | TaskStatus::Review | TaskStatus::WaitingForInput
+        )
+    }
+
+    /// Not yet finished, one way or another
+    pub fn is_active(&self) -> bool {
+        !matches!(self, TaskStatus::Completed | TaskStatus::Failed | TaskStatus::Cancelled | TaskStatus::BudgetExceeded)
+    }
+
+    /// Statuses a task may be re-queued from.
+    pub fn can_retry(&self) -> bool {
+        matches!(self, TaskStatus::Failed | TaskStatus::Cancelled | TaskStatus::BudgetExceeded)
+    }
+
+    fn as_bson(&self) -> mongodb::bson::Bson {
+        mongodb::bson::to_bson(self).unwrap_or(mongodb::bson::Bson::Null)
+    }
+}
+
+/// How many ready tasks `get_next_task` considers per call
+const READY_TASK_LIMIT: usize = 50;
+
+/// Why a [`TodoList`] write failed: the task itself was rejected, or the
+/// database call did
+#[derive(Debug, thiserror::Error)]
+pub enum TodoError {
+    #[error("{0}")]
+    Invalid(String),
+
+    #[error(transparent)]
+    Database(#[from] MongoError),
 }
 
 #[derive(Debug, Clone)]
 pub struct TodoList {
     collection: Collection<TodoTask>,
+    dead_letter: Collection<TodoTask>,
+    clock: Arc<dyn Clock>,
+    ids: Arc<dyn IdGenerator>,
 }
 
 impl TodoList {
     pub async fn new() -> Result<Self, MongoError> {
-        let uri = env::var("RTK_MONGO_URI")
-            .expect("RTK_MONGO_URI must be set");
-        let db_name = env::var("RTK_MONGO_DB")
-            .unwrap_or_else(|_| "swarmonomicon".to_string());
+        Self::from_env()
+    }
+
+    /// Like [`new`](Self::new), for constructors that can't await. Opens the
+    /// collections on the shared client for `RTK_MONGO_URI`, see
+    /// [`Clients::mongo`] for when that can block.
+    pub fn from_env() -> Result<Self, MongoError> {
+        let (uri, db_name) = connection_settings();
+        Ok(Self::open(Clients::shared().database(&uri, &db_name)?))
+    }
 
-        let client = Client::with_uri_str(&uri).await?;
-        let db = client.database(&db_name);
+    fn open(db: Database) -> Self {
         let collection = db.collection("todos");
+        let dead_letter = db.collection("todos_dead_letter");
+        Self { collection, dead_letter, clock: clock::system(), ids: clock::uuids() }
+    }
+
+    /// Stamp tasks and compute schedules with `clock` instead of the real time
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
 
-        Ok(Self { collection })
+    /// Give new tasks ids from `ids`
+    pub fn with_ids(mut self, ids: Arc<dyn IdGenerator>) -> Self {
+        self.ids = ids;
+        self
     }
 
-    pub async fn add_task(&self, task: TodoTask) -> Result<(), MongoError> {
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    pub fn ids(&self) -> &Arc<dyn IdGenerator> {
+        &self.ids
+    }
+
+    /// Insert a task. Fails with [`TodoError::Invalid`] if its recurrence
+    /// can't be scheduled or its `depends_on` would create a dependency cycle.
+    pub async fn add_task(&self, task: TodoTask) -> Result<(), TodoError> {
+        if let Some(recurrence) = &task.recurrence {
+            recurrence.next_after(task.created_at).map_err(TodoError::Invalid)?;
+        }
+
+        if !task.depends_on.is_empty() {
+            let graph = self.dependency_graph().await?;
+            if let Some(cycle) = find_dependency_cycle(&task.id, &task.depends_on, &graph) {
+                return Err(TodoError::Invalid(format!("Task dependency cycle: {}", cycle.join(" -> "))));
+            }
+        }
+
         if let Some(enhanced) = &task.enhanced_description {
             println!("Inserting enhanced description with length: {}", enhanced.len());
         }
@@ -88,53 +570,568 @@ impl TodoList {
         Ok(())
     }
 
-    pub async fn get_next_task(&self) -> Result<Option<TodoTask>, MongoError> {
+    /// Claim the highest-priority pending task whose dependencies have all
+    /// completed, moving it to in-progress under `lease`.
+    pub async fn get_next_task(&self, lease: &TaskLease) -> Result<Option<TodoTask>, MongoError> {
+        for candidate in self.get_ready_tasks(None, READY_TASK_LIMIT).await? {
+            // Another worker may have claimed it since we looked
+            if let Some(task) = self.claim_task(&candidate.id, lease).await? {
+                return Ok(Some(task));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Pending tasks that are due to run and whose dependencies have completed,
+    /// most urgent first, optionally restricted to one target agent.
+    pub async fn get_ready_tasks(&self, target_agent: Option<&str>, limit: usize) -> Result<Vec<TodoTask>, MongoError> {
+        let mut filter = doc! {
+            "status": TaskStatus::Pending.as_bson(),
+            "$or": [
+                { "scheduled_for": null },
+                { "scheduled_for": { "$lte": self.clock.timestamp() } }
+            ]
+        };
+        if let Some(agent) = target_agent {
+            filter.insert("target_agent", agent);
+        }
+        let mut cursor = self.collection.find(filter, None).await?;
+        let mut candidates = Vec::new();
+        while let Some(candidate) = cursor.try_next().await? {
+            candidates.push(candidate);
+        }
+        let completed = self.completed_ids(candidates.iter().flat_map(|task| &task.depends_on)).await?;
+        Ok(rank_ready(candidates, &completed, limit))
+    }
+
+    /// Atomically move a pending task to in-progress, held by `lease`.
+    /// Returns `None` if it was claimed by someone else first.
+    pub async fn claim_task(&self, task_id: &str, lease: &TaskLease) -> Result<Option<TodoTask>, MongoError> {
+        let now = self.clock.timestamp();
         let filter = doc! {
-            "status": "Pending"
+            "id": task_id,
+            "status": TaskStatus::Pending.as_bson()
         };
         let update = doc! {
             "$set": {
-                "status": "InProgress"
+                "status": TaskStatus::InProgress.as_bson(),
+                "claimed_by": &lease.worker_id,
+                "lease_expires_at": now + lease.lease_secs,
+                "last_modified": now
             }
         };
-        let options = mongodb::options::FindOneAndUpdateOptions::builder()
-            .sort(doc! { "priority": -1, "created_at": 1 })
-            .build();
+        self.update_and_return(filter, update).await
+    }
+
+    /// Extend the lease on a task this worker holds. Returns `false` if the
+    /// claim was lost, e.g. because the task was cancelled or released.
+    pub async fn renew_lease(&self, task_id: &str, lease: &TaskLease) -> Result<bool, MongoError> {
+        let filter = doc! {
+            "id": task_id,
+            "status": TaskStatus::InProgress.as_bson(),
+            "claimed_by": &lease.worker_id
+        };
+        let update = doc! {
+            "$set": {
+                "lease_expires_at": self.clock.timestamp() + lease.lease_secs
+            }
+        };
+        let result = self.collection.update_one(filter, update, None).await?;
+        Ok(result.matched_count > 0)
+    }
 
-        Ok(self.collection
-            .find_one_and_update(filter, update, options)
-            .await?)
+    /// Keep renewing the lease on `task_id` until the returned guard is dropped
+    pub fn spawn_heartbeat(&self, task_id: &str, lease: &TaskLease) -> Heartbeat {
+        let todo_list = self.clone();
+        let task_id = task_id.to_string();
+        let lease = lease.clone();
+        Heartbeat(tokio::spawn(async move {
+            let mut interval = tokio::time::interval(std::time::Duration::from_secs(lease.heartbeat_secs.max(1)));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match todo_list.renew_lease(&task_id, &lease).await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        tracing::warn!("Lost claim on task {}, stopping heartbeat", task_id);
+                        break;
+                    }
+                    Err(e) => tracing::warn!("Failed to renew lease on task {}: {}", task_id, e),
+                }
+            }
+        }))
     }
 
-    pub async fn mark_task_completed(&self, task_id: &str) -> Result<(), MongoError> {
+    /// Give a task this worker claimed back to the pending queue, e.g. when it
+    /// couldn't be started. Returns `false` if the claim was already lost.
+    pub async fn release_claim(&self, task_id: &str, lease: &TaskLease) -> Result<bool, MongoError> {
         let filter = doc! {
-            "id": task_id
+            "id": task_id,
+            "status": TaskStatus::InProgress.as_bson(),
+            "claimed_by": &lease.worker_id
+        };
+        let update = doc! {
+            "$set": {
+                "status": TaskStatus::Pending.as_bson(),
+                "claimed_by": mongodb::bson::Bson::Null,
+                "lease_expires_at": mongodb::bson::Bson::Null,
+                "last_modified": self.clock.timestamp()
+            }
+        };
+        let result = self.collection.update_one(filter, update, None).await?;
+        Ok(result.modified_count > 0)
+    }
+
+    /// Return in-progress tasks whose lease has run out to the pending queue,
+    /// so work held by a crashed worker is picked up again.
+    pub async fn release_expired_leases(&self, now: i64) -> Result<Vec<TodoTask>, MongoError> {
+        let expired = doc! {
+            "status": TaskStatus::InProgress.as_bson(),
+            "lease_expires_at": { "$lt": now }
+        };
+        let mut cursor = self.collection.find(expired.clone(), None).await?;
+        let mut candidates = Vec::new();
+        while let Some(task) = cursor.try_next().await? {
+            candidates.push(task.id);
+        }
+
+        let mut released = Vec::new();
+        for task_id in candidates {
+            let mut filter = expired.clone();
+            filter.insert("id", task_id);
+            let update = doc! {
+                "$set": {
+                    "status": TaskStatus::Pending.as_bson(),
+                    "claimed_by": mongodb::bson::Bson::Null,
+                    "lease_expires_at": mongodb::bson::Bson::Null,
+                    "last_modified": now
+                }
+            };
+            // A heartbeat may have landed since the find
+            if let Some(task) = self.update_and_return(filter, update).await? {
+                released.push(task);
+            }
+        }
+        Ok(released)
+    }
+
+    /// Which of `ids` belong to completed tasks, looked up in one query
+    async fn completed_ids<'a>(&self, ids: impl IntoIterator<Item = &'a String>) -> Result<HashSet<String>, MongoError> {
+        let ids: HashSet<&String> = ids.into_iter().collect();
+        let mut completed = HashSet::new();
+        if ids.is_empty() {
+            return Ok(completed);
+        }
+
+        let filter = doc! {
+            "id": { "$in": ids.into_iter().collect::<Vec<_>>() },
+            "status": TaskStatus::Completed.as_bson()
+        };
+        let mut cursor = self.collection.find(filter, None).await?;
+        while let Some(dependency) = cursor.try_next().await? {
+            completed.insert(dependency.id);
+        }
+        Ok(completed)
+    }
+
+    /// Dependencies of `task` that have not completed yet
+    pub async fn unmet_dependencies(&self, task: &TodoTask) -> Result<Vec<String>, MongoError> {
+        let completed = self.completed_ids(&task.depends_on).await?;
+        Ok(task.depends_on.iter()
+            .filter(|id| !completed.contains(*id))
+            .cloned()
+            .collect())
+    }
+
+    /// Active tasks whose deadline passed before `now` and that have not been
+    /// escalated since `escalated_before`
+    pub async fn get_overdue_tasks(&self, now: i64, escalated_before: i64) -> Result<Vec<TodoTask>, MongoError> {
+        let inactive: Vec<_> = [TaskStatus::Completed, TaskStatus::Failed, TaskStatus::Cancelled, TaskStatus::BudgetExceeded]
+            .iter()
+            .map(TaskStatus::as_bson)
+            .collect();
+        let filter = doc! {
+            "due_at": { "$lt": now },
+            "status": { "$nin": inactive },
+            "$or": [
+                { "escalated_at": null },
+                { "escalated_at": { "$lt": escalated_before } }
+            ]
+        };
+
+        let mut cursor = self.collection.find(filter, None).await?;
+        let mut tasks = Vec::new();
+        while let Some(task) = cursor.try_next().await? {
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+
+    /// Bump an overdue task's priority one level and record the escalation
+    pub async fn escalate_task(&self, task: &TodoTask) -> Result<Option<TodoTask>, MongoError> {
+        let priority = mongodb::bson::to_bson(&task.priority.escalate())
+            .unwrap_or(mongodb::bson::Bson::Null);
+        let now = self.clock.timestamp();
+        let filter = doc! {
+            "id": &task.id
+        };
+        let update = doc! {
+            "$set": {
+                "priority": priority,
+                "escalated_at": now,
+                "last_modified": now
+            }
+        };
+        self.update_and_return(filter, update).await
+    }
+
+    /// If `task` recurs, enqueue its next run as a new pending task linked back
+    /// to `task`. Returns the new task, or `None` for one-off tasks.
+    pub async fn enqueue_next_occurrence(&self, task: &TodoTask) -> Result<Option<TodoTask>, TodoError> {
+        let recurrence = match &task.recurrence {
+            Some(recurrence) => recurrence,
+            None => return Ok(None),
+        };
+
+        let now = self.clock.timestamp();
+        let next_run = recurrence.next_after(now).map_err(TodoError::Invalid)?;
+        let next = TodoTask {
+            id: self.ids.next_id(),
+            status: TaskStatus::Pending,
+            created_at: now,
+            completed_at: None,
+            last_modified: Some(now),
+            depends_on: Vec::new(),
+            previous_run_id: Some(task.id.clone()),
+            scheduled_for: Some(next_run),
+            due_at: task.due_at.map(|due| due + (next_run - task.scheduled_for.unwrap_or(task.created_at))),
+            escalated_at: None,
+            attempts: 0,
+            error_history: Vec::new(),
+            claimed_by: None,
+            lease_expires_at: None,
+            idempotency_key: None,
+            clarifications: Vec::new(),
+            ..task.clone()
+        };
+
+        self.add_task(next.clone()).await?;
+        Ok(Some(next))
+    }
+
+    async fn dependency_graph(&self) -> Result<HashMap<String, Vec<String>>, MongoError> {
+        let filter = doc! {
+            "depends_on.0": { "$exists": true }
+        };
+        let mut cursor = self.collection.find(filter, None).await?;
+        let mut graph = HashMap::new();
+        while let Some(task) = cursor.try_next().await? {
+            graph.insert(task.id, task.depends_on);
+        }
+        Ok(graph)
+    }
+
+    /// Most recent task created with `key` within the idempotency window, if any
+    pub async fn find_by_idempotency_key(&self, key: &str, window_secs: i64) -> Result<Option<TodoTask>, MongoError> {
+        if window_secs <= 0 {
+            return Ok(None);
+        }
+        let filter = doc! {
+            "idempotency_key": key,
+            "created_at": { "$gte": self.clock.timestamp() - window_secs }
+        };
+        let options = mongodb::options::FindOneOptions::builder()
+            .sort(doc! { "created_at": -1 })
+            .build();
+        self.collection.find_one(filter, options).await
+    }
+
+    /// Complete a task. Returns `false` if it is gone or was cancelled, in which
+    /// case nothing that follows a completion should happen either.
+    pub async fn mark_task_completed(&self, task_id: &str) -> Result<bool, MongoError> {
+        // Never overwrite a cancellation that arrived while the task was running
+        let filter = doc! {
+            "id": task_id,
+            "status": { "$ne": TaskStatus::Cancelled.as_bson() }
         };
         let update = doc! {
             "$set": {
                 "status": "completed",
-                "completed_at": DateTime::now(),
-                "last_modified": Utc::now().timestamp()
+                "completed_at": self.clock.timestamp(),
+                "last_modified": self.clock.timestamp()
             }
         };
-        self.collection.update_one(filter, update, None).await?;
-        Ok(())
+        let result = self.collection.update_one(filter, update, None).await?;
+        Ok(result.matched_count > 0)
     }
 
     pub async fn mark_task_failed(&self, task_id: &str) -> Result<(), MongoError> {
         let filter = doc! {
-            "id": task_id
+            "id": task_id,
+            "status": { "$ne": TaskStatus::Cancelled.as_bson() }
         };
         let update = doc! {
             "$set": {
                 "status": "failed",
-                "last_modified": Utc::now().timestamp()
+                "last_modified": self.clock.timestamp()
             }
         };
         self.collection.update_one(filter, update, None).await?;
         Ok(())
     }
 
+    /// Stop a task that went over its budget. Unlike other failures it is
+    /// not retried, since another attempt would run into the same limit.
+    /// Returns `None` if the task was cancelled meanwhile.
+    pub async fn mark_budget_exceeded(&self, task_id: &str, reason: &str) -> Result<Option<TodoTask>, MongoError> {
+        let now = self.clock.timestamp();
+        let attempt = self.get_task(task_id).await?.map_or(1, |task| task.attempts + 1);
+        let failure = mongodb::bson::to_bson(&TaskFailure {
+            attempt,
+            error: reason.to_string(),
+            timestamp: now,
+        }).unwrap_or(mongodb::bson::Bson::Null);
+        let filter = doc! {
+            "id": task_id,
+            "status": { "$ne": TaskStatus::Cancelled.as_bson() }
+        };
+        let update = doc! {
+            "$set": {
+                "status": TaskStatus::BudgetExceeded.as_bson(),
+                "attempts": attempt,
+                "claimed_by": mongodb::bson::Bson::Null,
+                "lease_expires_at": mongodb::bson::Bson::Null,
+                "last_modified": now
+            },
+            "$push": { "error_history": failure }
+        };
+        self.update_and_return(filter, update).await
+    }
+
+    /// Record a failed attempt. The task is re-queued with backoff while it has
+    /// attempts left, and moved to the dead-letter collection once it runs out.
+    pub async fn record_failure(&self, task_id: &str, error: &str, policy: &RetryPolicy) -> Result<FailureOutcome, MongoError> {
+        let task = match self.get_task(task_id).await? {
+            Some(task) if task.status != TaskStatus::Cancelled => task,
+            _ => return Ok(FailureOutcome::Discarded),
+        };
+
+        let now = self.clock.timestamp();
+        let attempt = task.attempts + 1;
+        let failure = mongodb::bson::to_bson(&TaskFailure {
+            attempt,
+            error: error.to_string(),
+            timestamp: now,
+        }).unwrap_or(mongodb::bson::Bson::Null);
+        let max_attempts = task.max_attempts.unwrap_or(policy.max_attempts);
+
+        let filter = doc! {
+            "id": task_id,
+            "status": { "$ne": TaskStatus::Cancelled.as_bson() }
+        };
+
+        // Either way the attempt is over, so its claim goes with it
+        if attempt < max_attempts {
+            let retry_at = now + policy.backoff_for(attempt) as i64;
+            let update = doc! {
+                "$set": {
+                    "status": TaskStatus::Pending.as_bson(),
+                    "attempts": attempt,
+                    "scheduled_for": retry_at,
+                    "claimed_by": mongodb::bson::Bson::Null,
+                    "lease_expires_at": mongodb::bson::Bson::Null,
+                    "last_modified": now
+                },
+                "$push": { "error_history": failure }
+            };
+            return Ok(match self.update_and_return(filter, update).await? {
+                Some(task) => FailureOutcome::Retrying { task, retry_at },
+                None => FailureOutcome::Discarded,
+            });
+        }
+
+        let update = doc! {
+            "$set": {
+                "status": TaskStatus::Failed.as_bson(),
+                "attempts": attempt,
+                "claimed_by": mongodb::bson::Bson::Null,
+                "lease_expires_at": mongodb::bson::Bson::Null,
+                "last_modified": now
+            },
+            "$push": { "error_history": failure }
+        };
+        match self.update_and_return(filter, update).await? {
+            Some(task) => {
+                self.dead_letter.insert_one(task.clone(), None).await?;
+                Ok(FailureOutcome::DeadLettered(task))
+            }
+            None => Ok(FailureOutcome::Discarded),
+        }
+    }
+
+    pub async fn get_dead_letters(&self) -> Result<Vec<TodoTask>, MongoError> {
+        let mut cursor = self.dead_letter.find(None, None).await?;
+        let mut tasks = Vec::new();
+        while let Some(task) = cursor.try_next().await? {
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+
+    /// Move every dead-lettered task back into the queue with a fresh attempt
+    /// count. Error history is kept. Returns the re-queued tasks.
+    pub async fn replay_dead_letters(&self) -> Result<Vec<TodoTask>, MongoError> {
+        let mut replayed = Vec::new();
+        for task in self.get_dead_letters().await? {
+            let filter = doc! {
+                "id": &task.id
+            };
+            let update = doc! {
+                "$set": {
+                    "status": TaskStatus::Pending.as_bson(),
+                    "attempts": 0,
+                    "scheduled_for": mongodb::bson::Bson::Null,
+                    "completed_at": mongodb::bson::Bson::Null,
+                    "last_modified": self.clock.timestamp()
+                }
+            };
+            if let Some(requeued) = self.update_and_return(filter.clone(), update).await? {
+                replayed.push(requeued);
+            }
+            self.dead_letter.delete_one(filter, None).await?;
+        }
+        Ok(replayed)
+    }
+
+    /// Cancel a task if its current status allows it. Returns the updated task,
+    /// or `None` if the task does not exist or can no longer be cancelled.
+    pub async fn cancel_task(&self, task_id: &str) -> Result<Option<TodoTask>, MongoError> {
+        let cancellable: Vec<_> = [
+            TaskStatus::Initial,
+            TaskStatus::Pending,
+            TaskStatus::InProgress,
+            TaskStatus::Review,
+            TaskStatus::WaitingForInput,
+        ]
+            .iter()
+            .map(TaskStatus::as_bson)
+            .collect();
+        let filter = doc! {
+            "id": task_id,
+            "status": { "$in": cancellable }
+        };
+        let update = doc! {
+            "$set": {
+                "status": TaskStatus::Cancelled.as_bson(),
+                "last_modified": self.clock.timestamp()
+            }
+        };
+        self.update_and_return(filter, update).await
+    }
+
+    /// Put a failed, cancelled or over-budget task back into the pending queue.
+    /// Returns the updated task, or `None` if the task does not exist or is not
+    /// retryable.
+    pub async fn requeue_task(&self, task_id: &str) -> Result<Option<TodoTask>, MongoError> {
+        let retryable: Vec<_> = [TaskStatus::Failed, TaskStatus::Cancelled, TaskStatus::BudgetExceeded]
+            .iter()
+            .map(TaskStatus::as_bson)
+            .collect();
+        let filter = doc! {
+            "id": task_id,
+            "status": { "$in": retryable }
+        };
+        let update = doc! {
+            "$set": {
+                "status": TaskStatus::Pending.as_bson(),
+                "completed_at": mongodb::bson::Bson::Null,
+                "last_modified": self.clock.timestamp()
+            }
+        };
+        self.update_and_return(filter, update).await
+    }
+
+    /// Park an in-progress task until `questions` are answered, releasing the
+    /// worker's claim. Returns `None` if the task was cancelled meanwhile.
+    pub async fn park_for_input(&self, task_id: &str, questions: &[String]) -> Result<Option<TodoTask>, MongoError> {
+        let now = self.clock.timestamp();
+        let clarification = mongodb::bson::to_bson(&Clarification {
+            questions: questions.to_vec(),
+            answer: None,
+            asked_at: now,
+            answered_at: None,
+        }).unwrap_or(mongodb::bson::Bson::Null);
+        let filter = doc! {
+            "id": task_id,
+            "status": TaskStatus::InProgress.as_bson()
+        };
+        let update = doc! {
+            "$set": {
+                "status": TaskStatus::WaitingForInput.as_bson(),
+                "claimed_by": mongodb::bson::Bson::Null,
+                "lease_expires_at": mongodb::bson::Bson::Null,
+                "last_modified": now
+            },
+            "$push": { "clarifications": clarification }
+        };
+        self.update_and_return(filter, update).await
+    }
+
+    /// Record `answer` to a parked task's open questions and put it back in
+    /// the pending queue. Returns `None` if the task does not exist or is not
+    /// waiting for input.
+    pub async fn answer_task(&self, task_id: &str, answer: &str) -> Result<Option<TodoTask>, MongoError> {
+        let now = self.clock.timestamp();
+        // The open questions are always the last clarification pushed
+        let filter = doc! {
+            "id": task_id,
+            "status": TaskStatus::WaitingForInput.as_bson(),
+            "clarifications.0": { "$exists": true }
+        };
+        let update = doc! {
+            "$set": {
+                "status": TaskStatus::Pending.as_bson(),
+                "clarifications.$[open].answer": answer,
+                "clarifications.$[open].answered_at": now,
+                "last_modified": now
+            }
+        };
+        let options = mongodb::options::FindOneAndUpdateOptions::builder()
+            .array_filters(vec![doc! { "open.answer": mongodb::bson::Bson::Null }])
+            .return_document(mongodb::options::ReturnDocument::After)
+            .build();
+        self.collection.find_one_and_update(filter, update, options).await
+    }
+
+    async fn update_and_return(
+        &self,
+        filter: mongodb::bson::Document,
+        update: mongodb::bson::Document,
+    ) -> Result<Option<TodoTask>, MongoError> {
+        let options = mongodb::options::FindOneAndUpdateOptions::builder()
+            .return_document(mongodb::options::ReturnDocument::After)
+            .build();
+
+        self.collection.find_one_and_update(filter, update, options).await
+    }
+
+    /// Tasks for `target_agent` parked until someone answers, oldest first
+    pub async fn get_waiting_tasks(&self, target_agent: &str) -> Result<Vec<TodoTask>, MongoError> {
+        let filter = doc! {
+            "target_agent": target_agent,
+            "status": TaskStatus::WaitingForInput.as_bson()
+        };
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "created_at": 1 })
+            .build();
+        let mut cursor = self.collection.find(filter, options).await?;
+        let mut tasks = Vec::new();
+        while let Some(task) = cursor.try_next().await? {
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+
     pub async fn get_all_tasks(&self) -> Result<Vec<TodoTask>, MongoError> {
         let mut cursor = self.collection.find(None, None).await?;
         let mut tasks = Vec::new();
@@ -144,6 +1141,31 @@ impl TodoList {
         Ok(tasks)
     }
 
+    /// Tasks matching `filter`, newest first
+    pub async fn find_tasks(&self, filter: mongodb::bson::Document, limit: Option<i64>) -> Result<Vec<TodoTask>, MongoError> {
+        let options = mongodb::options::FindOptions::builder()
+            .sort(doc! { "created_at": -1 })
+            .limit(limit)
+            .build();
+        let mut cursor = self.collection.find(filter, options).await?;
+        let mut tasks = Vec::new();
+        while let Some(task) = cursor.try_next().await? {
+            tasks.push(task);
+        }
+        Ok(tasks)
+    }
+
+    /// Apply `set` to a task. Returns the updated task, or `None` if it does not exist.
+    pub async fn update_task(&self, task_id: &str, set: mongodb::bson::Document) -> Result<Option<TodoTask>, MongoError> {
+        self.update_and_return(doc! { "id": task_id }, doc! { "$set": set }).await
+    }
+
+    /// Returns whether a task was deleted
+    pub async fn delete_task(&self, task_id: &str) -> Result<bool, MongoError> {
+        let result = self.collection.delete_one(doc! { "id": task_id }, None).await?;
+        Ok(result.deleted_count > 0)
+    }
+
     pub async fn get_task(&self, task_id: &str) -> Result<Option<TodoTask>, MongoError> {
         let filter = doc! {
             "id": task_id
@@ -166,24 +1188,28 @@ impl TodoList {
         source_agent: Option<String>,
         target_agent: String,
         project: Option<String>,
+        schedule: TaskSchedule,
         ai_client: Option<&dyn AiProvider>,
-    ) -> Result<TodoTask, MongoError> {
+    ) -> Result<TodoTask, TodoError> {
+        let idempotency_key = schedule.idempotency_key
+            .unwrap_or_else(|| derive_idempotency_key(&description, project.as_deref()));
         let mut task = TodoTask {
-            id: Uuid::new_v4().to_string(),
+            id: self.ids.next_id(),
             description: description.clone(),
-            enhanced_description: None,
             priority,
             project,
             source_agent,
             target_agent,
-            status: TaskStatus::Pending,
-            created_at: Utc::now().timestamp(),
-            completed_at: None,
-            due_date: None,
-            duration_minutes: None,
-            notes: None,
-            ticket: None,
-            last_modified: Some(Utc::now().timestamp()),
+            created_at: self.clock.timestamp(),
+            last_modified: Some(self.clock.timestamp()),
+            depends_on: schedule.depends_on,
+            recurrence: schedule.recurrence,
+            due_at: schedule.due_at,
+            idempotency_key: Some(idempotency_key),
+            attachments: schedule.attachments,
+            budget: schedule.budget,
+            dry_run: schedule.dry_run,
+            ..Default::default()
         };
 
         // Only attempt AI enhancement if a client is provided
@@ -216,6 +1242,54 @@ impl TodoList {
     }
 }
 
+/// `RTK_MONGO_URI` and `RTK_MONGO_DB` (default `swarmonomicon`)
+fn connection_settings() -> (String, String) {
+    let uri = env::var("RTK_MONGO_URI")
+        .expect("RTK_MONGO_URI must be set");
+    let db_name = env::var("RTK_MONGO_DB")
+        .unwrap_or_else(|_| "swarmonomicon".to_string());
+    (uri, db_name)
+}
+
+/// Walk the existing dependency `graph` from `depends_on` and return the path
+/// back to `task_id` if adding the task would close a cycle.
+pub fn find_dependency_cycle(
+    task_id: &str,
+    depends_on: &[String],
+    graph: &HashMap<String, Vec<String>>,
+) -> Option<Vec<String>> {
+    fn visit(
+        current: &str,
+        target: &str,
+        graph: &HashMap<String, Vec<String>>,
+        visited: &mut HashSet<String>,
+        path: &mut Vec<String>,
+    ) -> bool {
+        path.push(current.to_string());
+        if current == target {
+            return true;
+        }
+        if visited.insert(current.to_string()) {
+            for next in graph.get(current).into_iter().flatten() {
+                if visit(next, target, graph, visited, path) {
+                    return true;
+                }
+            }
+        }
+        path.pop();
+        false
+    }
+
+    let mut visited = HashSet::new();
+    for dependency in depends_on {
+        let mut path = vec![task_id.to_string()];
+        if visit(dependency, task_id, graph, &mut visited, &mut path) {
+            return Some(path);
+        }
+    }
+    None
+}
+
 #[async_trait::async_trait]
 pub trait TodoProcessor: Send + Sync {
     /// Process a single task from the todo list
@@ -227,20 +1301,293 @@ pub trait TodoProcessor: Send + Sync {
     /// Get the todo list for this processor
     fn get_todo_list(&self) -> &TodoList;
 
+    /// Cancel a task. Pending tasks are removed from the queue; a task that is
+    /// already running keeps running, but its result is discarded.
+    async fn cancel_task(&self, task_id: &str) -> super::Result<TodoTask> {
+        self.get_todo_list().cancel_task(task_id).await?
+            .ok_or_else(|| anyhow::anyhow!("Task '{}' not found or not cancellable", task_id))
+    }
+
+    /// Re-queue a failed or cancelled task so it is picked up again.
+    async fn retry_task(&self, task_id: &str) -> super::Result<TodoTask> {
+        self.get_todo_list().requeue_task(task_id).await?
+            .ok_or_else(|| anyhow::anyhow!("Task '{}' not found or not retryable", task_id))
+    }
+
+    /// Answer the questions a parked task is waiting on and re-queue it.
+    async fn answer_task(&self, task_id: &str, answer: &str) -> super::Result<TodoTask> {
+        self.get_todo_list().answer_task(task_id, answer).await?
+            .ok_or_else(|| anyhow::anyhow!("Task '{}' not found or not waiting for input", task_id))
+    }
+
     /// Start the task processing loop
     async fn start_processing(&self) -> super::Result<()> {
+        let lease = TaskLease::from_env(format!("processor-{}", self.get_todo_list().ids().next_id()));
         loop {
-            if let Some(task) = self.get_todo_list().get_next_task().await? {
-                match self.process_task(task.clone()).await {
-                    Ok(_) => {
-                        self.get_todo_list().mark_task_completed(&task.id).await?;
-                    }
-                    Err(_) => {
-                        self.get_todo_list().mark_task_failed(&task.id).await?;
-                    }
+            if let Some(task) = self.get_todo_list().get_next_task(&lease).await? {
+                let heartbeat = self.get_todo_list().spawn_heartbeat(&task.id, &lease);
+                let result = self.process_task(task.clone()).await;
+                drop(heartbeat);
+                match result {
+                    Ok(response) => match response.questions() {
+                        // Parked until someone answers; the task is picked up again after
+                        Some(questions) => {
+                            self.get_todo_list().park_for_input(&task.id, &questions).await?;
+                        }
+                        None => {
+                            // A task cancelled mid-run doesn't come back
+                            if self.get_todo_list().mark_task_completed(&task.id).await? {
+                                self.get_todo_list().enqueue_next_occurrence(&task).await?;
+                            }
+                        }
+                    },
+                    Err(e) => match BudgetExceeded::find(&e) {
+                        Some(exceeded) => {
+                            self.get_todo_list().mark_budget_exceeded(&task.id, &exceeded.to_string()).await?;
+                        }
+                        None => {
+                            self.get_todo_list()
+                                .record_failure(&task.id, &e.to_string(), &RetryPolicy::from_env())
+                                .await?;
+                        }
+                    },
                 }
             }
             tokio::time::sleep(self.get_check_interval()).await;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_priority_maps_everywhere() {
+        for (rank, priority) in TaskPriority::ALL.iter().enumerate() {
+            // Exhaustive, so a new variant fails to compile until it is added to ALL
+            let listed = match priority {
+                TaskPriority::Inital | TaskPriority::Low | TaskPriority::Medium
+                | TaskPriority::High | TaskPriority::Critical => TaskPriority::ALL.contains(priority),
+            };
+            assert!(listed);
+            assert_eq!(priority.rank() as usize, rank);
+            assert_eq!(&TaskPriority::from_rank(priority.rank()), priority);
+            assert_eq!(TaskPriority::parse(priority.as_str()).as_ref(), Some(priority));
+            let serialized = serde_json::to_value(priority).unwrap();
+            assert_eq!(TaskPriority::parse(serialized.as_str().unwrap()).as_ref(), Some(priority));
+
+            // MCP keeps fewer levels, but the exact one survives a round trip
+            let level: TaskPriority = serde_json::from_value(serde_json::json!(priority.mcp_level())).unwrap();
+            assert_eq!(&TaskPriority::from_mcp(level.clone(), Some(priority.as_str())), priority);
+            assert_eq!(level.mcp_level(), priority.mcp_level());
+        }
+        assert_eq!(TaskPriority::parse(" Initial "), Some(TaskPriority::Inital));
+        assert_eq!(TaskPriority::parse("urgent"), None);
+
+        // A level changed on the server since wins over stale metadata
+        assert_eq!(TaskPriority::from_mcp(TaskPriority::Low, Some("critical")), TaskPriority::Low);
+    }
+
+    #[test]
+    fn test_status_transitions() {
+        assert!(TaskStatus::Pending.can_cancel());
+        assert!(TaskStatus::Review.can_cancel());
+        assert!(TaskStatus::WaitingForInput.can_cancel());
+        assert!(TaskStatus::WaitingForInput.is_active());
+        assert!(!TaskStatus::Completed.can_cancel());
+        assert!(!TaskStatus::Cancelled.can_cancel());
+
+        assert!(TaskStatus::Failed.can_retry());
+        assert!(TaskStatus::Cancelled.can_retry());
+        assert!(TaskStatus::BudgetExceeded.can_retry());
+        assert!(!TaskStatus::BudgetExceeded.is_active());
+        assert!(!TaskStatus::Pending.can_retry());
+        assert!(!TaskStatus::Completed.can_retry());
+    }
+
+    #[test]
+    fn test_find_dependency_cycle() {
+        let graph = HashMap::from([
+            ("generate".to_string(), vec!["classify".to_string()]),
+            ("classify".to_string(), vec!["intake".to_string()]),
+        ]);
+
+        // intake -> generate -> classify -> intake
+        let cycle = find_dependency_cycle("intake", &["generate".to_string()], &graph);
+        assert_eq!(cycle, Some(vec![
+            "intake".to_string(),
+            "generate".to_string(),
+            "classify".to_string(),
+            "intake".to_string(),
+        ]));
+
+        assert!(find_dependency_cycle("deploy", &["generate".to_string()], &graph).is_none());
+        assert!(find_dependency_cycle("self", &["self".to_string()], &graph).is_some());
+    }
+
+    #[test]
+    fn test_overdue_and_escalation() {
+        let mut task: TodoTask = serde_json::from_value(serde_json::json!({
+            "id": "t1",
+            "description": "ship it",
+            "enhanced_description": null,
+            "priority": "Low",
+            "project": null,
+            "source_agent": null,
+            "target_agent": "user",
+            "status": "pending",
+            "created_at": 0,
+            "completed_at": null,
+            "due_date": null,
+            "duration_minutes": null,
+            "notes": null,
+            "ticket": null,
+            "last_modified": null,
+            "due_at": 100
+        })).unwrap();
+
+        assert!(!task.is_overdue(50));
+        assert!(task.is_overdue(150));
+        task.status = TaskStatus::Completed;
+        assert!(!task.is_overdue(150));
+
+        assert_eq!(TaskPriority::Low.escalate(), TaskPriority::Medium);
+        assert_eq!(TaskPriority::High.escalate(), TaskPriority::Critical);
+        assert_eq!(TaskPriority::Critical.escalate(), TaskPriority::Critical);
+    }
+
+    #[test]
+    fn test_clarified_description() {
+        let mut task: TodoTask = serde_json::from_value(serde_json::json!({
+            "id": "t2",
+            "description": "fix the build",
+            "enhanced_description": "",
+            "priority": "Medium",
+            "project": null,
+            "source_agent": null,
+            "target_agent": "git",
+            "status": "waiting_for_input",
+            "created_at": 0,
+            "completed_at": null,
+            "due_date": null,
+            "duration_minutes": null,
+            "notes": null,
+            "ticket": null,
+            "last_modified": null,
+            "clarifications": [
+                { "questions": ["Which target?"], "answer": null, "asked_at": 1, "answered_at": null }
+            ]
+        })).unwrap();
+        assert_eq!(task.status, TaskStatus::WaitingForInput);
+        // Unanswered questions add nothing
+        assert_eq!(task.clarified_description(), "fix the build");
+
+        task.clarifications[0].answer = Some("wasm32".to_string());
+        assert_eq!(task.clarified_description(), "fix the build\n\nYou asked:\n- Which target?\nAnswer: wasm32");
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        let policy = RetryPolicy { max_attempts: 5, base_backoff_secs: 10, max_backoff_secs: 60 };
+        assert_eq!(policy.backoff_for(1), 10);
+        assert_eq!(policy.backoff_for(2), 20);
+        assert_eq!(policy.backoff_for(3), 40);
+        assert_eq!(policy.backoff_for(4), 60);
+        assert_eq!(policy.backoff_for(40), 60);
+    }
+
+    #[test]
+    fn test_recurrence_next_after() {
+        let interval = Recurrence::Interval { seconds: 3600 };
+        assert_eq!(interval.next_after(1_000), Ok(4_600));
+        assert!(Recurrence::Interval { seconds: 0 }.next_after(1_000).is_err());
+
+        // 2025-03-01T12:00:00Z, next daily 03:00 run is the following morning
+        let noon = Utc.with_ymd_and_hms(2025, 3, 1, 12, 0, 0).unwrap().timestamp();
+        let daily = Recurrence::Cron { expression: "0 3 * * *".to_string() };
+        let next = Utc.with_ymd_and_hms(2025, 3, 2, 3, 0, 0).unwrap().timestamp();
+        assert_eq!(daily.next_after(noon), Ok(next));
+
+        assert!(Recurrence::Cron { expression: "not a cron".to_string() }.next_after(noon).is_err());
+    }
+
+    #[test]
+    fn test_missing_depends_on_defaults_to_empty() {
+        let task: TodoTask = serde_json::from_value(serde_json::json!({
+            "id": "t1",
+            "description": "legacy task",
+            "enhanced_description": null,
+            "priority": "Medium",
+            "project": null,
+            "source_agent": null,
+            "target_agent": "user",
+            "status": "pending",
+            "created_at": 0,
+            "completed_at": null,
+            "due_date": null,
+            "duration_minutes": null,
+            "notes": null,
+            "ticket": null,
+            "last_modified": null
+        })).unwrap();
+        assert!(task.depends_on.is_empty());
+        assert!(task.recurrence.is_none());
+        assert!(task.claimed_by.is_none());
+        assert!(task.lease_expires_at.is_none());
+    }
+
+    #[test]
+    fn test_derive_idempotency_key() {
+        let key = derive_idempotency_key("Fix the build", Some("swarmonomicon"));
+        assert_eq!(key, derive_idempotency_key("  fix the build ", Some("Swarmonomicon")));
+        assert_ne!(key, derive_idempotency_key("Fix the build", Some("omnispindle")));
+        assert_ne!(key, derive_idempotency_key("Fix the build", None));
+        assert_eq!(key.len(), 64);
+    }
+
+    #[test]
+    fn test_lease_heartbeat_interval() {
+        let lease = TaskLease::new("worker-1", 120);
+        assert_eq!(lease.worker_id, "worker-1");
+        assert_eq!(lease.heartbeat_secs, 40);
+        assert_eq!(TaskLease::new("worker-1", 2).heartbeat_secs, 1);
+    }
+
+    #[test]
+    fn test_rank_ready_looks_past_an_older_backlog() {
+        let task = |id: &str, priority: TaskPriority, created_at: i64, depends_on: &[&str]| TodoTask {
+            id: id.to_string(),
+            priority,
+            created_at,
+            depends_on: depends_on.iter().map(|id| id.to_string()).collect(),
+            ..Default::default()
+        };
+        let mut candidates: Vec<_> = (0..5).map(|n| task(&format!("low-{}", n), TaskPriority::Low, n, &[])).collect();
+        candidates.push(task("blocked", TaskPriority::Critical, 10, &["low-0"]));
+        candidates.push(task("urgent", TaskPriority::Critical, 20, &[]));
+        candidates.push(task("unblocked", TaskPriority::High, 30, &["done"]));
+        let completed = HashSet::from(["done".to_string()]);
+
+        let ready: Vec<_> = rank_ready(candidates, &completed, 3).into_iter().map(|task| task.id).collect();
+        assert_eq!(ready, vec!["urgent", "unblocked", "low-0"]);
+    }
+
+    #[tokio::test]
+    async fn test_dropping_a_heartbeat_stops_it() {
+        let (alive, mut stopped) = tokio::sync::mpsc::channel::<()>(1);
+        let heartbeat = Heartbeat(tokio::spawn(async move {
+            let _alive = alive;
+            std::future::pending::<()>().await;
+        }));
+        drop(heartbeat);
+        // The sender goes away with the aborted task
+        assert!(tokio::time::timeout(std::time::Duration::from_secs(1), stopped.recv()).await.unwrap().is_none());
+    }
+
+    #[test]
+    fn test_cancelled_status_serialization() {
+        assert_eq!(serde_json::to_string(&TaskStatus::Cancelled).unwrap(), "\"cancelled\"");
+        assert_eq!(TaskStatus::Cancelled.as_bson(), mongodb::bson::Bson::String("cancelled".to_string()));
+    }
+}
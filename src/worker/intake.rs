@@ -0,0 +1,651 @@
+//! MQTT intake: validates `mcp/<agent>` requests, has them classified into a
+//! project and enhanced, and files them as todos. The `mqtt_intake` binary
+//! and the swarm-e2e harness both run it.
+
+use std::time::Duration;
+use std::collections::HashMap;
+use crate::types::{IntakeRequest, derive_idempotency_key};
+use crate::tools::{ScreenshotDetectionTool, TodoTool, ToolExecutor, WatchConfig};
+use crate::agents::project::{
+    ClassificationResponder, ProjectAgent, ProjectClassificationRequest, ProjectClassificationResponse,
+    CLASSIFY_REQUEST_TOPIC, DEFAULT_PROJECT, classify_response_topic,
+};
+use crate::types::{Agent, AgentConfig};
+use crate::mqtt::QoS;
+use serde::{Deserialize, Serialize};
+use tokio::{task, time, sync::Semaphore};
+use std::error::Error as StdError;
+use anyhow::{Result, anyhow};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use serde_json::json;
+use std::time::Instant;
+use std::sync::atomic::{AtomicU64, Ordering};
+use uuid::Uuid;
+use tracing::Instrument;
+use crate::telemetry;
+use crate::mqtt::{BoundedQueue, DedupCache, MqttMessage, MqttService, PayloadFormat, Pushed};
+use crate::config::SwarmConfig;
+
+/// A validated request waiting for a queue worker
+struct QueuedRequest {
+    message: MqttMessage,
+    request: IntakeRequest,
+}
+
+// Maximum number of concurrent AI enhancements
+const MAX_CONCURRENT_AI: usize = 1;
+// Task metrics reporting interval
+const METRICS_REPORTING_INTERVAL: u64 = 300;
+// Project classification timeout
+const PROJECT_CLASSIFICATION_TIMEOUT: u64 = 30;
+
+// Simple metrics struct to track tasks
+struct TaskMetrics {
+    tasks_received: AtomicU64,
+    tasks_processed: AtomicU64,
+    tasks_failed: AtomicU64,
+    duplicates_suppressed: AtomicU64,
+    payloads_invalid: AtomicU64,
+    project_classifications_requested: AtomicU64,
+    project_classifications_successful: AtomicU64,
+    project_classifications_timed_out: AtomicU64,
+    /// Requests received but not yet picked up by a queue worker
+    request_queue: Option<BoundedQueue<QueuedRequest>>,
+    start_time: Instant,
+}
+
+impl TaskMetrics {
+    fn new() -> Self {
+        Self {
+            tasks_received: AtomicU64::new(0),
+            tasks_processed: AtomicU64::new(0),
+            tasks_failed: AtomicU64::new(0),
+            duplicates_suppressed: AtomicU64::new(0),
+            payloads_invalid: AtomicU64::new(0),
+            project_classifications_requested: AtomicU64::new(0),
+            project_classifications_successful: AtomicU64::new(0),
+            project_classifications_timed_out: AtomicU64::new(0),
+            request_queue: None,
+            start_time: Instant::now(),
+        }
+    }
+
+    fn with_request_queue(mut self, queue: BoundedQueue<QueuedRequest>) -> Self {
+        self.request_queue = Some(queue);
+        self
+    }
+
+    fn increment_received(&self) -> u64 {
+        self.tasks_received.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn increment_processed(&self) -> u64 {
+        self.tasks_processed.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn increment_failed(&self) -> u64 {
+        self.tasks_failed.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn increment_duplicates(&self) -> u64 {
+        self.duplicates_suppressed.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn increment_invalid(&self) -> u64 {
+        self.payloads_invalid.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn increment_classification_requested(&self) -> u64 {
+        self.project_classifications_requested.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn increment_classification_successful(&self) -> u64 {
+        self.project_classifications_successful.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn increment_classification_timed_out(&self) -> u64 {
+        self.project_classifications_timed_out.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+    fn as_json(&self) -> serde_json::Value {
+        let received = self.tasks_received.load(Ordering::SeqCst);
+        let processed = self.tasks_processed.load(Ordering::SeqCst);
+        let failed = self.tasks_failed.load(Ordering::SeqCst);
+        let class_requested = self.project_classifications_requested.load(Ordering::SeqCst);
+        let class_successful = self.project_classifications_successful.load(Ordering::SeqCst);
+        let uptime_secs = self.start_time.elapsed().as_secs();
+
+        json!({
+            "tasks_received": received,
+            "tasks_processed": processed,
+            "tasks_failed": failed,
+            "duplicates_suppressed": self.duplicates_suppressed.load(Ordering::SeqCst),
+            "payloads_invalid": self.payloads_invalid.load(Ordering::SeqCst),
+            "project_classifications_requested": class_requested,
+            "project_classifications_successful": class_successful,
+            "project_classifications_timed_out": self.project_classifications_timed_out.load(Ordering::SeqCst),
+            "classification_success_rate": if class_requested > 0 { (class_successful as f64 / class_requested as f64) * 100.0 } else { 0.0 },
+            "success_rate": if received > 0 { (processed as f64 / received as f64) * 100.0 } else { 0.0 },
+            "uptime_seconds": uptime_secs,
+            "request_queue": self.request_queue.as_ref().map(|queue| queue.stats()),
+            "tasks_per_minute": if uptime_secs > 0 { (received as f64 / uptime_secs as f64) * 60.0 } else { 0.0 }
+        })
+    }
+}
+
+/// Turn `mcp/<agent>` requests arriving on `client` into todos until shut down
+pub async fn run_intake(config: &SwarmConfig, client: MqttService, todo_tool: Arc<TodoTool>) -> Result<()> {
+    // Create semaphores for rate limiting
+    let ai_semaphore = Arc::new(Semaphore::new(MAX_CONCURRENT_AI));
+
+    // Requests wait here for a queue worker so a burst never stalls the MQTT event loop
+    let request_queue = config.worker.request_queue();
+    let queue_workers = config.worker.queue_workers;
+    tracing::info!("Request queue: {:?} with {} worker(s)", request_queue.stats(), queue_workers);
+
+    // Initialize metrics
+    let metrics = Arc::new(TaskMetrics::new().with_request_queue(request_queue.clone()));
+
+    // Legacy publishers send a bare description instead of an intake JSON object
+    let accept_plain_text = std::env::var("INTAKE_ACCEPT_PLAIN_TEXT").map(|v| v != "false").unwrap_or(true);
+
+    // Drops broker redeliveries and producer retries before they become todos
+    let dedup = DedupCache::from_env();
+    tracing::info!("Suppressing duplicate messages for {}s", dedup.window().as_secs());
+
+    client.log_topic_map(&[
+        "mcp/+",
+        "mcp_server/control",
+        "mcp/error/+",
+        "mqtt_intake/status",
+        CLASSIFY_REQUEST_TOPIC,
+        "project/classify/response/+",
+        "response/+/todo",
+        "response/+/error",
+        "response/mcp_server/status",
+        "metrics/response/mqtt_intake",
+    ]);
+    // Also on `/msgpack` and `/cbor` for publishers sending binary payloads
+    let mut requests = client.subscribe_all(&PayloadFormat::topic_filters("mcp/+"), client.config().task_qos).await?;
+    let mut control = client.subscribe("mcp_server/control", QoS::ExactlyOnce).await?;
+
+    // Answer our own classification requests unless a project_worker does it for us
+    let classifier = if std::env::var("INTAKE_LOCAL_CLASSIFIER").map(|v| v != "false").unwrap_or(true) {
+        match ProjectAgent::new(classifier_config()).await {
+            Ok(agent) => {
+                let agent = Arc::new(agent);
+                let warming = agent.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = warming.start().await {
+                        tracing::warn!("Local project classifier warm-up failed: {}", e);
+                    }
+                });
+                let responder = Arc::new(ClassificationResponder::new(agent, client.clone()));
+                let runner = responder.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = runner.run().await {
+                        tracing::error!("Classification responder stopped: {}", e);
+                    }
+                });
+                Some(responder)
+            }
+            Err(e) => {
+                tracing::warn!("Local project classifier unavailable, relying on project_worker: {}", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // Run detection on screenshots dropped into SCREENSHOT_WATCH_DIR
+    if let Some(watch) = WatchConfig::from_env() {
+        let mut screenshots = ScreenshotDetectionTool::new().with_mqtt(client.clone());
+        if watch.todo_classes.is_some() {
+            match crate::tools::todo::todo_store_from_env().await {
+                Ok(store) => screenshots = screenshots.with_todo_store(store),
+                Err(e) => tracing::warn!("Screenshot todos disabled, no todo store: {}", e),
+            }
+        }
+        tokio::spawn(screenshots.watcher(watch).run());
+    }
+
+    tracing::info!("MCP Todo Server started. Listening for new tasks...");
+
+    // Setup metrics reporting task
+    let metrics_client = client.clone();
+    let metrics_cloned = metrics.clone();
+    let metrics_todo_tool = todo_tool.clone();
+    let metrics_classifier = classifier.clone();
+    let metrics_reporter = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(METRICS_REPORTING_INTERVAL));
+        loop {
+            interval.tick().await;
+
+            // Retry buffered writes even when no new todos are arriving
+            if metrics_todo_tool.is_degraded() {
+                if let Err(e) = metrics_todo_tool.flush_outbox().await {
+                    tracing::warn!("Failed to replay todo outbox: {}", e);
+                }
+            }
+
+            // Report metrics
+            let mut metrics_json = metrics_cloned.as_json();
+            metrics_json["todo_tool"] = metrics_todo_tool.status_json().await;
+            if let Some(classifier) = &metrics_classifier {
+                metrics_json["classifier"] = classifier.stats_json();
+            }
+            let _ = metrics_client.publish_metrics("metrics/response/mqtt_intake", &metrics_json).await;
+        }
+    });
+
+    let mut request_workers: Vec<_> = (0..queue_workers)
+        .map(|_| {
+            let queue = request_queue.clone();
+            let client = client.clone();
+            let metrics = metrics.clone();
+            let todo_tool = todo_tool.clone();
+            let ai_semaphore = ai_semaphore.clone();
+            tokio::spawn(async move {
+                while let Some(queued) = queue.pop().await {
+                    process_request(queued, &client, &metrics, &todo_tool, &ai_semaphore).await;
+                }
+            })
+        })
+        .collect();
+
+    // Set up graceful shutdown channel
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::broadcast::channel::<()>(1);
+
+    // Set up ctrl-c handler
+    let shutdown_tx_ctrl_c = shutdown_tx.clone();
+    tokio::spawn(async move {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for ctrl-c: {}", e);
+            return;
+        }
+        tracing::info!("Received shutdown signal, initiating graceful shutdown...");
+        let _ = shutdown_tx_ctrl_c.send(());
+    });
+
+    // Main event loop
+    loop {
+        tokio::select! {
+            // Check for shutdown signal
+            result = shutdown_rx.recv() => {
+                if result.is_ok() {
+                    tracing::info!("Shutdown signal received, closing MQTT connection...");
+
+                    // Let the workers finish what was already accepted
+                    request_queue.close();
+                    for worker in request_workers.drain(..) {
+                        if let Err(e) = worker.await {
+                            tracing::error!("Request worker failed: {}", e);
+                        }
+                    }
+                    if let Some(classifier) = &classifier {
+                        classifier.shutdown(config.worker.shutdown_grace()).await;
+                    }
+
+                    // Publish final metrics and shutdown status
+                    let shutdown_payload = json!({
+                        "status": "shutdown",
+                        "timestamp": chrono::Utc::now().to_rfc3339(),
+                        "final_metrics": metrics.as_json()
+                    }).to_string();
+
+                    if let Err(e) = client.publish(
+                        "response/mcp_server/status",
+                        QoS::ExactlyOnce,
+                        false,
+                        shutdown_payload
+                    ).await {
+                        tracing::error!("Failed to publish shutdown status: {}", e);
+                    }
+
+                    // Disconnect from MQTT, clearing the retained online status first
+                    if let Err(e) = client.publish_offline().await {
+                        tracing::error!("Failed to publish offline status: {}", e);
+                    }
+                    if let Err(e) = client.disconnect().await {
+                        tracing::error!("Error disconnecting from MQTT: {}", e);
+                    }
+
+                    // Allow time for final messages to be sent
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    tracing::info!("Graceful shutdown complete");
+                    telemetry::shutdown_tracing();
+                    break;
+                }
+            }
+
+            // Handle control messages
+            Some(message) = control.recv() => {
+                let payload = message.payload_str();
+                if let Ok(control_json) = serde_json::from_str::<serde_json::Value>(&payload) {
+                    if let Some(command) = control_json.get("command").and_then(|c| c.as_str()) {
+                        if command == "shutdown" {
+                            tracing::info!("Received shutdown command, initiating graceful shutdown...");
+                            let _ = shutdown_tx.send(());
+                        } else if command == "status" {
+                            // Report current status
+                            let status_payload = json!({
+                                "status": "running",
+                                "timestamp": chrono::Utc::now().to_rfc3339(),
+                                "metrics": metrics.as_json()
+                            }).to_string();
+
+                            if let Err(e) = client.publish(
+                                "response/mcp_server/status",
+                                QoS::ExactlyOnce,
+                                false,
+                                status_payload
+                            ).await {
+                                tracing::error!("Failed to publish status: {}", e);
+                            }
+                        }
+                    }
+                }
+            }
+
+            // Handle MCP task requests
+            Some(message) = requests.recv() => {
+                let topic = message.topic.clone();
+                let payload = match message.json_text() {
+                    Ok(payload) => payload.into_owned(),
+                    Err(e) => {
+                        let invalid = metrics.increment_invalid();
+                        tracing::warn!("Undecodable {:?} payload on {} ({} so far): {}", message.format(), topic, invalid, e);
+                        continue;
+                    }
+                };
+                tracing::info!("Received payload on {}: {}", topic, payload);
+
+                // Increment the task received counter
+                let task_count = metrics.increment_received();
+                tracing::debug!("Task count: {}", task_count);
+
+                let request = match IntakeRequest::parse(&payload, accept_plain_text) {
+                    Ok(request) => request,
+                    Err(e) => {
+                        let invalid = metrics.increment_invalid();
+                        tracing::warn!("Rejected payload on {} ({} so far): {}", topic, invalid, e);
+                        let error_id = e.request_id.clone().unwrap_or_else(|| Uuid::new_v4().to_string());
+                        let mut error_payload = e.response_json();
+                        error_payload["request_id"] = json!(error_id);
+                        error_payload["topic"] = json!(&*topic);
+                        if let Err(e) = client.reply(&message, format!("mcp/error/{}", error_id), error_payload.to_string()).await {
+                            tracing::error!("Failed to publish validation error: {}", e);
+                        }
+                        continue;
+                    }
+                };
+
+                // Publishers' own idempotency keys identify retries; otherwise fall back to the content
+                let dedup_key = match &request.idempotency_key {
+                    Some(key) => format!("{}:{}", topic, key),
+                    None => DedupCache::message_key(&message),
+                };
+                if dedup.is_duplicate(&dedup_key) {
+                    let suppressed = metrics.increment_duplicates();
+                    tracing::info!("Suppressed duplicate message on {} ({} so far)", topic, suppressed);
+                    continue;
+                }
+
+                match request_queue.push(QueuedRequest { message, request }) {
+                    Pushed::Queued => {}
+                    Pushed::DroppedOldest(dropped) => {
+                        tracing::warn!("Request queue full, dropped oldest request on {}", dropped.message.topic);
+                        metrics.increment_failed();
+                        reject_request(&client, &dropped.message, "Dropped from a full request queue").await;
+                    }
+                    Pushed::Rejected(rejected) => {
+                        tracing::warn!("Request queue full, rejected request on {}", rejected.message.topic);
+                        metrics.increment_failed();
+                        reject_request(&client, &rejected.message, "Request queue full").await;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Classifies one queued `mcp/<agent>` request and adds it as a todo
+async fn process_request(
+    QueuedRequest { message, request }: QueuedRequest,
+    client: &MqttService,
+    metrics: &Arc<TaskMetrics>,
+    todo_tool: &Arc<TodoTool>,
+    ai_semaphore: &Arc<Semaphore>,
+) {
+    let topic = message.topic.clone();
+    // Already decoded once at intake, so this can't fail
+    let payload = message.json_text().unwrap_or_default().into_owned();
+
+    // Every intake starts (or continues) a trace for the todo's journey
+    let correlation_id = telemetry::extract_correlation_id(&payload)
+        .unwrap_or_else(telemetry::new_correlation_id);
+    let span = tracing::info_span!(
+        "intake.todo",
+        topic = %topic,
+        correlation_id = %correlation_id,
+    );
+
+    telemetry::with_correlation_id(correlation_id.clone(), async move {
+        let description = request.description.clone();
+        // Classification can differ between retries, so derive from the description alone
+        let idempotency_key = request.idempotency_key.clone()
+            .unwrap_or_else(|| derive_idempotency_key(&description, None));
+
+        let target_agent = topic.split('/').nth(1).unwrap_or("user");
+
+        // A project hint from the publisher skips classification
+        let project_name = match &request.project {
+            Some(project) => project.clone(),
+            None => match classify(client, metrics, &description, target_agent, &correlation_id).await {
+                Some(project) => project,
+                None => return,
+            },
+        };
+
+        // Acquire AI enhancement permit before processing
+        let _ai_permit = match ai_semaphore.acquire().await {
+            Ok(permit) => permit,
+            Err(e) => {
+                tracing::error!("Failed to acquire AI permit: {}", e);
+                metrics.increment_failed();
+                return;
+            }
+        };
+
+        // Use TodoTool to add the todo - it will handle MCP server calls internally
+        let mut params = HashMap::new();
+        params.insert("command".to_string(), "add".to_string());
+        params.insert("description".to_string(), description.clone());
+        params.insert("context".to_string(), "mqtt_intake".to_string());
+        params.insert("target_agent".to_string(), target_agent.to_string());
+        params.insert("project".to_string(), project_name.clone());
+        params.insert("idempotency_key".to_string(), idempotency_key.clone());
+        if let Some(priority) = request.priority.as_ref().and_then(|p| serde_json::to_value(p).ok()) {
+            params.insert("priority".to_string(), priority.as_str().unwrap_or_default().to_string());
+        }
+        let mut metadata = request.metadata.clone();
+        if let Some(source) = &request.source {
+            metadata.insert("intake_source".to_string(), json!(source));
+        }
+        metadata.insert("intake_schema_version".to_string(), json!(request.version));
+        params.insert("metadata".to_string(), serde_json::Value::Object(metadata).to_string());
+
+        match todo_tool.execute(params).await {
+            Ok(result) => {
+                tracing::info!("Successfully added todo: {} (project: {})", description, project_name);
+                metrics.increment_processed();
+
+                // Publish success response
+                let response_topic = format!("response/{}/todo", target_agent);
+                let mut response_payload = json!({
+                    "status": "success",
+                    "message": result,
+                    "project": project_name,
+                    "timestamp": chrono::Utc::now().to_rfc3339()
+                });
+                telemetry::inject_correlation_id(&mut response_payload);
+                let response_payload = response_payload.to_string();
+
+                if let Err(e) = client.reply(&message, response_topic, response_payload).await {
+                    tracing::error!("Failed to publish success response: {}", e);
+                }
+            },
+            Err(e) => {
+                tracing::error!("Failed to add todo: {}", e);
+                metrics.increment_failed();
+
+                // Publish error response
+                let error_topic = format!("response/{}/error", target_agent);
+                let mut error_payload = json!({
+                    "status": "error",
+                    "error": e.to_string(),
+                    "project": project_name,
+                    "timestamp": chrono::Utc::now().to_rfc3339()
+                });
+                telemetry::inject_correlation_id(&mut error_payload);
+                let error_payload = error_payload.to_string();
+
+                if let Err(e) = client.reply(&message, error_topic, error_payload).await {
+                    tracing::error!("Failed to publish error response: {}", e);
+                }
+            }
+        }
+    }).instrument(span).await
+}
+
+/// Ask the classifier which project `description` belongs to. `None` when
+/// the request couldn't even be sent.
+async fn classify(
+    client: &MqttService,
+    metrics: &Arc<TaskMetrics>,
+    description: &str,
+    target_agent: &str,
+    correlation_id: &str,
+) -> Option<String> {
+    // Request project classification from project worker
+    let request_id = Uuid::new_v4().to_string();
+    let classification_request = ProjectClassificationRequest {
+        description: description.to_string(),
+        request_id: Some(request_id.clone()),
+        context: Some({
+            let mut context = HashMap::new();
+            context.insert("source".to_string(), "mqtt_intake".to_string());
+            context.insert("target_agent".to_string(), target_agent.to_string());
+            context.insert("correlation_id".to_string(), correlation_id.to_string());
+            context
+        }),
+    };
+
+    metrics.increment_classification_requested();
+
+    // Subscribe to the classification response topic before asking, so the answer can't be missed
+    let response_topic = classify_response_topic(&request_id);
+    let responses = match client.subscribe(&response_topic, QoS::ExactlyOnce).await {
+        Ok(receiver) => receiver,
+        Err(e) => {
+            tracing::error!("Failed to subscribe to classification response topic: {}", e);
+            metrics.increment_failed();
+            return None;
+        }
+    };
+
+    // Publish classification request
+    let classification_payload = serde_json::to_string(&classification_request)
+        .unwrap_or_else(|_| description.to_string());
+
+    if let Err(e) = client.publish_request(
+        CLASSIFY_REQUEST_TOPIC,
+        classification_payload,
+        &response_topic,
+        request_id.clone()
+    ).await {
+        tracing::error!("Failed to publish classification request: {}", e);
+        metrics.increment_failed();
+        return None;
+    }
+
+    // Wait for project classification response with timeout
+    let project_name = match tokio::time::timeout(
+        Duration::from_secs(PROJECT_CLASSIFICATION_TIMEOUT),
+        wait_for_project_classification(responses, &request_id)
+    ).await {
+        Ok(Ok(response)) => {
+            // Responders answer with zero confidence when they fell back to the default
+            if response.confidence > 0.0 {
+                metrics.increment_classification_successful();
+            } else if let Some(reasoning) = &response.reasoning {
+                tracing::warn!("Responder used the default project: {}", reasoning);
+            }
+            tracing::info!("Received project classification: {} -> {}",
+                description, response.project_name);
+            response.project_name
+        },
+        Ok(Err(e)) => {
+            tracing::warn!("Project classification failed: {}. Using default.", e);
+            DEFAULT_PROJECT.to_string()
+        },
+        Err(_) => {
+            metrics.increment_classification_timed_out();
+            tracing::warn!("Project classification timed out. Using default.");
+            DEFAULT_PROJECT.to_string()
+        }
+    };
+    if let Err(e) = client.unsubscribe(&response_topic).await {
+        tracing::debug!("Failed to unsubscribe from {}: {}", response_topic, e);
+    }
+
+    Some(project_name)
+}
+
+/// Tells the sender of a request the queue had no room for
+async fn reject_request(client: &MqttService, message: &MqttMessage, reason: &str) {
+    let target_agent = message.topic.split('/').nth(1).unwrap_or("user");
+    let error_topic = format!("response/{}/error", target_agent);
+    let error_payload = json!({
+        "status": "error",
+        "error": reason,
+        "timestamp": chrono::Utc::now().to_rfc3339()
+    }).to_string();
+
+    if let Err(e) = client.reply(message, error_topic, error_payload).await {
+        tracing::error!("Failed to publish error response: {}", e);
+    }
+}
+
+/// Wait for the classification response to `request_id`
+async fn wait_for_project_classification(
+    mut responses: mpsc::Receiver<MqttMessage>,
+    request_id: &str
+) -> Result<ProjectClassificationResponse> {
+    while let Some(message) = responses.recv().await {
+        match message.json::<ProjectClassificationResponse>() {
+            // Verify this is our request
+            Ok(response) if response.request_id.is_none() || response.request_id.as_deref() == Some(request_id) => return Ok(response),
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Ignoring malformed classification response: {}", e),
+        }
+    }
+    Err(anyhow!("MQTT connection closed while waiting for classification response"))
+}
+
+/// Config for the in-process classifier; matches project_worker's
+fn classifier_config() -> AgentConfig {
+    AgentConfig {
+        name: "project-classifier".to_string(),
+        public_description: "AI-powered project classification agent".to_string(),
+        instructions: "Classify incoming tasks to determine which project they belong to and perform background project maintenance".to_string(),
+        tools: vec![],
+        downstream_agents: vec![],
+        personality: None,
+        state_machine: None,
+    }
+}
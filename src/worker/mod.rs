@@ -0,0 +1,11 @@
+//! The long-running loops behind the `todo_worker` and `mqtt_intake`
+//! binaries, kept in the library so the swarm-e2e harness can run them in
+//! one process against the in-memory broker.
+
+#[cfg(feature = "project-agent")]
+mod intake;
+mod todo;
+
+#[cfg(feature = "project-agent")]
+pub use intake::run_intake;
+pub use todo::{run_worker, WorkerContext, DEFAULT_CLIENT_ID};
@@ -0,0 +1,1984 @@
+//! The todo worker: claims tasks from the store, routes `agent/<name>/todo/*`
+//! requests to agents, and runs the overdue, lease and metrics loops around
+//! them. The `todo_worker` binary and the swarm-e2e harness both run it.
+
+use std::time::Duration;
+use crate::agents::{self, AgentRegistry, ApprovalInbox, OutputFilter};
+use crate::types::{TodoList, TodoTask, TaskStatus, TaskPriority, RetryPolicy, FailureOutcome, TaskLease};
+use crate::types::{PriorityClass, SchedulerConfig, TaskScheduler, Throttled};
+use crate::types::{LatencySummary, RollingWindow};
+use crate::types::TodoProcessor;
+use crate::mqtt::QoS;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::{RwLock, Mutex, watch};
+use crate::tools::{MetricsSnapshot, NotifyTool, WorkerMetricsStore};
+use anyhow::{Result, anyhow, Context};
+use std::env;
+use std::time::Instant;
+use serde_json::{self, json, Value};
+use chrono;
+use tracing::{info, error, warn, debug};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use tokio::sync::broadcast;
+use tracing::Instrument;
+use crate::telemetry;
+use crate::access::{tokens_match, with_principal, AccessDenied, AccessPolicy, API_KEY_FIELD, INBOX_DECIDE};
+use crate::events::{self, Event, EventBus};
+use crate::ai::BudgetExceeded;
+use crate::clock::{self, Clock};
+use crate::mqtt::{BoundedQueue, MqttMessage, MqttService, PayloadFormat, Pushed};
+use crate::config::{ConfigChanged, SwarmConfig};
+use crate::supervisor::TaskSupervisor;
+
+// Constants for configuration
+pub const DEFAULT_CLIENT_ID: &str = "todo_worker";
+const METRICS_REPORTING_INTERVAL: u64 = 10;
+const TASK_PROCESSING_TIMEOUT: u64 = 60;
+const HEALTHY_THRESHOLD_RATE: f64 = 90.0; // 90% success rate threshold
+const DEFAULT_OVERDUE_SWEEP_INTERVAL: u64 = 60;
+const DEFAULT_OVERDUE_REESCALATE_AFTER: i64 = 3600;
+const OVERDUE_TOPIC: &str = "todo/overdue";
+const DEAD_LETTER_TOPIC: &str = "todo/dead_letter";
+const DEFAULT_METRICS_SNAPSHOT_INTERVAL: u64 = 60;
+/// Recent windows reported alongside the lifetime counters, longest last
+const RECENT_WINDOWS: [(&str, Duration); 2] = [
+    ("5m", Duration::from_secs(300)),
+    ("1h", Duration::from_secs(3600)),
+];
+
+/// Scheduling counters for one project
+#[derive(Debug, Default, Clone, serde::Serialize)]
+struct ProjectQueueStats {
+    /// Ready tasks waiting at the most recent check
+    queued: u64,
+    scheduled: u64,
+    /// Times a task was held back by the project's own concurrency limit
+    throttled: u64,
+}
+
+// Metrics struct to track performance
+struct Metrics {
+    tasks_processed: AtomicU64,
+    tasks_succeeded: AtomicU64,
+    tasks_failed: AtomicU64,
+    tasks_timeout: AtomicU64,
+    tasks_escalated: AtomicU64,
+    tasks_reclaimed: AtomicU64,
+    inital_tasks_processed: AtomicU64,
+    low_tasks_processed: AtomicU64,
+    medium_tasks_processed: AtomicU64,
+    high_tasks_processed: AtomicU64,
+    critical_tasks_processed: AtomicU64,
+    urgent_tasks_scheduled: AtomicU64,
+    normal_tasks_scheduled: AtomicU64,
+    low_tasks_scheduled: AtomicU64,
+    tasks_throttled: AtomicU64,
+    tasks_aged: AtomicU64,
+    project_queues: Mutex<HashMap<String, ProjectQueueStats>>,
+    /// Requests received but not yet picked up by a queue worker
+    request_queue: Option<BoundedQueue<MqttMessage>>,
+    /// Success (`true`) or failure of each finished task
+    outcomes: std::sync::Mutex<RollingWindow<bool>>,
+    /// How long each finished task took
+    latencies: std::sync::Mutex<RollingWindow<Duration>>,
+    last_reset: std::sync::Mutex<Option<chrono::DateTime<chrono::Utc>>>,
+    start_time: Instant,
+    last_report_time: Mutex<Instant>,
+    clock: Arc<dyn Clock>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        Self::with_clock(clock::system())
+    }
+
+    fn with_clock(clock: Arc<dyn Clock>) -> Self {
+        let now = clock.instant();
+        Self {
+            tasks_processed: AtomicU64::new(0),
+            tasks_succeeded: AtomicU64::new(0),
+            tasks_failed: AtomicU64::new(0),
+            tasks_timeout: AtomicU64::new(0),
+            tasks_escalated: AtomicU64::new(0),
+            tasks_reclaimed: AtomicU64::new(0),
+            inital_tasks_processed: AtomicU64::new(0),
+            low_tasks_processed: AtomicU64::new(0),
+            medium_tasks_processed: AtomicU64::new(0),
+            high_tasks_processed: AtomicU64::new(0),
+            critical_tasks_processed: AtomicU64::new(0),
+            urgent_tasks_scheduled: AtomicU64::new(0),
+            normal_tasks_scheduled: AtomicU64::new(0),
+            low_tasks_scheduled: AtomicU64::new(0),
+            tasks_throttled: AtomicU64::new(0),
+            tasks_aged: AtomicU64::new(0),
+            project_queues: Mutex::new(HashMap::new()),
+            request_queue: None,
+            outcomes: std::sync::Mutex::new(RollingWindow::new(RECENT_WINDOWS[1].1)),
+            latencies: std::sync::Mutex::new(RollingWindow::new(RECENT_WINDOWS[1].1)),
+            last_reset: std::sync::Mutex::new(None),
+            start_time: now,
+            last_report_time: Mutex::new(now),
+            clock,
+        }
+    }
+
+    fn with_request_queue(mut self, queue: BoundedQueue<MqttMessage>) -> Self {
+        self.request_queue = Some(queue);
+        self
+    }
+
+    fn increment_processed(&self) -> u64 {
+        self.tasks_processed.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn increment_succeeded(&self) {
+        self.tasks_succeeded.fetch_add(1, Ordering::Relaxed);
+        self.outcomes.lock().unwrap().push_at(self.clock.instant(), true);
+    }
+
+    fn increment_failed(&self) {
+        self.tasks_failed.fetch_add(1, Ordering::Relaxed);
+        self.outcomes.lock().unwrap().push_at(self.clock.instant(), false);
+    }
+
+    /// Record how long a finished task took, successful or not
+    fn record_latency(&self, elapsed: Duration) {
+        self.latencies.lock().unwrap().push_at(self.clock.instant(), elapsed);
+    }
+
+    fn increment_timeout(&self) {
+        self.tasks_timeout.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_escalated(&self) {
+        self.tasks_escalated.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_reclaimed(&self, count: u64) {
+        self.tasks_reclaimed.fetch_add(count, Ordering::Relaxed);
+    }
+
+    fn priority_counter(&self, priority: &TaskPriority) -> &AtomicU64 {
+        match priority {
+            TaskPriority::Inital => &self.inital_tasks_processed,
+            TaskPriority::Low => &self.low_tasks_processed,
+            TaskPriority::Medium => &self.medium_tasks_processed,
+            TaskPriority::High => &self.high_tasks_processed,
+            TaskPriority::Critical => &self.critical_tasks_processed,
+        }
+    }
+
+    fn increment_priority_counter(&self, priority: &TaskPriority) {
+        self.priority_counter(priority).fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_scheduled(&self, class: PriorityClass) {
+        match class {
+            PriorityClass::Urgent => self.urgent_tasks_scheduled.fetch_add(1, Ordering::Relaxed),
+            PriorityClass::Normal => self.normal_tasks_scheduled.fetch_add(1, Ordering::Relaxed),
+            PriorityClass::Low => self.low_tasks_scheduled.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    fn increment_throttled(&self) {
+        self.tasks_throttled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn increment_aged(&self) {
+        self.tasks_aged.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Record how many ready tasks each project has waiting
+    async fn record_project_queues(&self, queued: &HashMap<String, u64>) {
+        let mut projects = self.project_queues.lock().await;
+        for stats in projects.values_mut() {
+            stats.queued = 0;
+        }
+        for (project, count) in queued {
+            projects.entry(project.clone()).or_default().queued = *count;
+        }
+    }
+
+    async fn record_project_scheduled(&self, project: &str) {
+        self.project_queues.lock().await.entry(project.to_string()).or_default().scheduled += 1;
+    }
+
+    async fn record_project_throttled(&self, project: &str) {
+        self.project_queues.lock().await.entry(project.to_string()).or_default().throttled += 1;
+    }
+
+    /// Success rate and latency percentiles over the last `span`
+    fn window_json(&self, now: Instant, span: Duration) -> serde_json::Value {
+        let outcomes = self.outcomes.lock().unwrap();
+        let (succeeded, failed) = outcomes.since(now, span)
+            .fold((0u64, 0u64), |(ok, err), success| if *success { (ok + 1, err) } else { (ok, err + 1) });
+        let latency = LatencySummary::from_durations(self.latencies.lock().unwrap().since(now, span));
+        json!({
+            "tasks_succeeded": succeeded,
+            "tasks_failed": failed,
+            "success_rate": if succeeded + failed > 0 { Some((succeeded as f64 / (succeeded + failed) as f64) * 100.0) } else { None },
+            "latency": latency,
+        })
+    }
+
+    /// Success rate over the shortest recent window, if anything finished in it
+    fn recent_success_rate(&self) -> Option<f64> {
+        let outcomes = self.outcomes.lock().unwrap();
+        let (succeeded, total) = outcomes.since(self.clock.instant(), RECENT_WINDOWS[0].1)
+            .fold((0u64, 0u64), |(ok, total), success| (ok + *success as u64, total + 1));
+        (total > 0).then(|| (succeeded as f64 / total as f64) * 100.0)
+    }
+
+    /// Zero every counter and window; uptime keeps counting from process start
+    async fn reset(&self) {
+        for counter in [
+            &self.tasks_processed, &self.tasks_succeeded, &self.tasks_failed, &self.tasks_timeout,
+            &self.tasks_escalated, &self.tasks_reclaimed, &self.inital_tasks_processed,
+            &self.low_tasks_processed, &self.medium_tasks_processed, &self.high_tasks_processed,
+            &self.critical_tasks_processed, &self.urgent_tasks_scheduled, &self.normal_tasks_scheduled,
+            &self.low_tasks_scheduled, &self.tasks_throttled, &self.tasks_aged,
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+        self.outcomes.lock().unwrap().clear();
+        self.latencies.lock().unwrap().clear();
+        self.project_queues.lock().await.clear();
+        *self.last_reset.lock().unwrap() = Some(self.clock.now());
+    }
+
+    fn get_success_rate(&self) -> f64 {
+        let processed = self.tasks_processed.load(Ordering::Relaxed);
+        if processed == 0 {
+            return 100.0;
+        }
+        let succeeded = self.tasks_succeeded.load(Ordering::Relaxed);
+        (succeeded as f64 / processed as f64) * 100.0
+    }
+
+    /// Judged on recent tasks when there are any, so old successes can't hide new failures
+    fn is_healthy(&self) -> bool {
+        self.recent_success_rate().unwrap_or_else(|| self.get_success_rate()) >= HEALTHY_THRESHOLD_RATE
+    }
+
+    async fn get_metrics_json(&self) -> serde_json::Value {
+        let now = self.clock.instant();
+        let uptime = now.duration_since(self.start_time);
+        
+        let tasks_processed = self.tasks_processed.load(Ordering::Relaxed);
+        let tasks_succeeded = self.tasks_succeeded.load(Ordering::Relaxed);
+        let tasks_failed = self.tasks_failed.load(Ordering::Relaxed);
+        let tasks_timeout = self.tasks_timeout.load(Ordering::Relaxed);
+        
+        let success_rate = if tasks_processed > 0 {
+            (tasks_succeeded as f64 / tasks_processed as f64) * 100.0
+        } else {
+            0.0
+        };
+
+        let mut report = json!({
+            "tasks_processed": tasks_processed,
+            "tasks_succeeded": tasks_succeeded,
+            "tasks_failed": tasks_failed,
+            "tasks_timeout": tasks_timeout,
+            "tasks_escalated": self.tasks_escalated.load(Ordering::Relaxed),
+            "tasks_reclaimed": self.tasks_reclaimed.load(Ordering::Relaxed),
+            "success_rate": success_rate,
+            "uptime_seconds": uptime.as_secs(),
+            "urgent_tasks_scheduled": self.urgent_tasks_scheduled.load(Ordering::Relaxed),
+            "normal_tasks_scheduled": self.normal_tasks_scheduled.load(Ordering::Relaxed),
+            "low_tasks_scheduled": self.low_tasks_scheduled.load(Ordering::Relaxed),
+            "tasks_throttled": self.tasks_throttled.load(Ordering::Relaxed),
+            "tasks_aged": self.tasks_aged.load(Ordering::Relaxed),
+            "projects": serde_json::to_value(&*self.project_queues.lock().await).unwrap_or_default(),
+            "request_queue": self.request_queue.as_ref().map(|queue| queue.stats()),
+            "windows": RECENT_WINDOWS.iter()
+                .map(|(name, span)| (name.to_string(), self.window_json(now, *span)))
+                .collect::<serde_json::Map<_, _>>(),
+            "last_reset": *self.last_reset.lock().unwrap(),
+            "healthy": self.is_healthy(),
+            "timestamp": chrono::Utc::now().to_rfc3339()
+        });
+        for priority in TaskPriority::ALL {
+            let count = self.priority_counter(&priority).load(Ordering::Relaxed);
+            report[format!("{}_tasks_processed", priority.as_str())] = json!(count);
+        }
+        report
+    }
+}
+
+/// Whether the worker takes on new tasks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RunState {
+    Running,
+    /// Not claiming tasks; set by `pause`, and by `drain` once in-flight work finishes
+    Paused,
+    /// Not claiming tasks, waiting for in-flight ones to finish
+    Draining,
+}
+
+impl RunState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            RunState::Running => "running",
+            RunState::Paused => "paused",
+            RunState::Draining => "draining",
+        }
+    }
+}
+
+/// Runtime switches operators flip over `todo_worker/control`
+struct WorkerControl {
+    state: watch::Sender<RunState>,
+    check_interval: watch::Sender<Duration>,
+    /// Queued requests being processed right now
+    busy_workers: AtomicUsize,
+}
+
+impl WorkerControl {
+    fn new(check_interval: Duration) -> Self {
+        Self {
+            state: watch::channel(RunState::Running).0,
+            check_interval: watch::channel(check_interval).0,
+            busy_workers: AtomicUsize::new(0),
+        }
+    }
+
+    fn state(&self) -> RunState {
+        *self.state.borrow()
+    }
+
+    fn set_state(&self, state: RunState) {
+        self.state.send_replace(state);
+    }
+
+    /// Wait until the worker is allowed to take on work
+    async fn wait_until_running(&self) {
+        let mut state = self.state.subscribe();
+        while *state.borrow_and_update() != RunState::Running {
+            if state.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    fn in_flight(&self, scheduler: &TaskScheduler) -> usize {
+        scheduler.in_flight() + self.busy_workers.load(Ordering::SeqCst)
+    }
+
+    fn status_json(&self, scheduler: &TaskScheduler) -> serde_json::Value {
+        json!({
+            "state": self.state().as_str(),
+            "in_flight": self.in_flight(scheduler),
+            "check_interval_secs": self.check_interval.borrow().as_secs(),
+        })
+    }
+}
+
+/// What a worker runs with: its connection, agents and counters, how it
+/// schedules and claims tasks, and how long it gives them at shutdown
+pub struct WorkerContext {
+    client: MqttService,
+    agent_registry: Arc<RwLock<AgentRegistry>>,
+    metrics: Arc<Metrics>,
+    scheduler: TaskScheduler,
+    lease: TaskLease,
+    /// How often to look for tasks until a control command or config edit changes it
+    check_interval: Duration,
+    request_queue: BoundedQueue<MqttMessage>,
+    queue_workers: usize,
+    supervisor: TaskSupervisor,
+    shutdown_grace: Duration,
+    /// Config file edits to apply without a restart
+    config_changes: Option<broadcast::Receiver<ConfigChanged>>,
+    /// Lets MQTT decisions on held operations through, as on the HTTP inbox routes
+    admin_token: Option<String>,
+}
+
+impl WorkerContext {
+    /// A worker on an already connected `client` with the queue, scheduling
+    /// and shutdown grace from `config`, claiming tasks as `worker_id`
+    pub fn new(client: MqttService, config: &SwarmConfig, worker_id: impl Into<String>) -> Self {
+        let request_queue = config.worker.request_queue();
+        // Metrics windows, the scheduler's aging and the sweeps all read this clock
+        let clock = clock::system();
+        Self {
+            client,
+            agent_registry: Arc::new(RwLock::new(AgentRegistry::new())),
+            metrics: Arc::new(Metrics::with_clock(clock.clone()).with_request_queue(request_queue.clone())),
+            // Shared across reconnects so in-flight tasks keep holding their slots
+            scheduler: TaskScheduler::new(SchedulerConfig::from_env()).with_clock(clock),
+            // Claims are tagged with this worker's id so other workers can take over if it dies
+            lease: TaskLease::from_env(worker_id),
+            check_interval: config.worker.check_interval(),
+            request_queue,
+            queue_workers: config.worker.queue_workers,
+            supervisor: TaskSupervisor::new("todo_worker", config.worker.max_jobs),
+            shutdown_grace: config.worker.shutdown_grace(),
+            config_changes: None,
+            admin_token: config.api.admin_token.clone(),
+        }
+    }
+
+    /// Check for tasks every `check_interval` instead of the configured period
+    pub fn with_check_interval(mut self, check_interval: Duration) -> Self {
+        self.check_interval = check_interval;
+        self
+    }
+
+    /// Apply edits to the config file as they arrive on `changes`
+    pub fn with_config_changes(mut self, changes: broadcast::Receiver<ConfigChanged>) -> Self {
+        self.config_changes = Some(changes);
+        self
+    }
+}
+
+/// Process todos arriving on the worker's MQTT topics and claimed from the
+/// task store until a shutdown signal, then drain what was accepted
+pub async fn run_worker(context: WorkerContext) -> Result<()> {
+    let WorkerContext {
+        client,
+        agent_registry,
+        metrics,
+        scheduler,
+        lease,
+        check_interval,
+        request_queue,
+        queue_workers,
+        supervisor,
+        shutdown_grace,
+        config_changes,
+        admin_token,
+    } = context;
+    info!("Request queue: {:?} with {} worker(s)", request_queue.stats(), queue_workers);
+    info!("Task scheduler: {:?}", scheduler.config());
+    info!("Claiming tasks as {} with a {}s lease", lease.worker_id, lease.lease_secs);
+
+    client.log_topic_map(&[
+        "agent/+/todo/process",
+        "agent/+/todo/answer",
+        "agent/+/todo/response",
+        "agent/+/todo/error",
+        "todo_worker/control",
+        "todo_worker/control/ack",
+        "todo_worker/status",
+        "todo_worker/error",
+        "metrics/todo_worker",
+        "health/todo_worker",
+        DEAD_LETTER_TOPIC,
+        OVERDUE_TOPIC,
+    ]);
+    // Also on `/msgpack` and `/cbor` for publishers sending binary payloads
+    let mut todo_requests = client.subscribe_all(&PayloadFormat::topic_filters("agent/+/todo/process"), client.config().task_qos).await?;
+    let mut control_messages = client.subscribe("todo_worker/control", QoS::ExactlyOnce).await?;
+    let mut answers = client.subscribe("agent/+/todo/answer", client.config().task_qos).await?;
+    let mut decisions = client.subscribe("user/inbox/+/decision", QoS::ExactlyOnce).await?;
+    
+    // Create default agents
+    if load_agents(&agent_registry).await.is_err() {
+        warn!("Failed to load default agents, will attempt to continue with empty registry");
+    }
+    
+    // Optionally publish a daily haiku about yesterday's busiest project
+    #[cfg(feature = "haiku-agent")]
+    let daily_haiku = match env::var("HAIKU_DAILY_HOUR").ok().and_then(|h| h.parse::<u32>().ok()) {
+        Some(hour) => match TodoList::new().await {
+            Ok(todo_list) => {
+                let haiku_config = agents::default_agents().into_iter()
+                    .find(|config| config.name == "haiku");
+                haiku_config.map(|config| {
+                    info!("Scheduling daily haiku at {:02}:00 UTC", hour);
+                    agents::haiku::spawn_daily_haiku_job(
+                        Arc::new(agents::HaikuAgent::new(config)),
+                        todo_list,
+                        client.clone(),
+                        hour,
+                    )
+                })
+            },
+            Err(e) => {
+                error!("Failed to open todo list for daily haiku: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    // Spawn the metrics reporting task
+    let metrics_client = client.clone();
+    let metrics_reporter = {
+        let metrics = metrics.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(METRICS_REPORTING_INTERVAL));
+            loop {
+                interval.tick().await;
+                if let Err(e) = report_metrics(&metrics, &metrics_client).await {
+                    error!("Failed to report metrics: {}", e);
+                }
+            }
+        })
+    };
+
+    // Record every agent invocation in the audit trail, when MongoDB is configured
+    match agents::AgentAuditLog::from_env().await {
+        Ok(log) => {
+            agents::AgentAuditLog::init_shared(log);
+        }
+        Err(e) => warn!("Agent audit trail unavailable: {}", e),
+    }
+
+    // Persist metrics snapshots for the dashboard's trend graphs, when MongoDB is configured
+    match WorkerMetricsStore::from_env().await {
+        Ok(store) => {
+            let metrics = metrics.clone();
+            let instance_id = lease.worker_id.clone();
+            let snapshot_interval = env::var("METRICS_SNAPSHOT_INTERVAL_SECS")
+                .ok()
+                .and_then(|s| s.parse::<u64>().ok())
+                .unwrap_or(DEFAULT_METRICS_SNAPSHOT_INTERVAL)
+                .max(1);
+            info!("Persisting metrics snapshots every {}s as {}", snapshot_interval, instance_id);
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(Duration::from_secs(snapshot_interval));
+                loop {
+                    interval.tick().await;
+                    let snapshot = MetricsSnapshot::new(DEFAULT_CLIENT_ID, instance_id.clone(), metrics.get_metrics_json().await);
+                    if let Err(e) = store.record(&snapshot).await {
+                        warn!("Failed to persist metrics snapshot: {}", e);
+                    }
+                }
+            });
+        }
+        Err(e) => info!("Metrics snapshots disabled: {}", e),
+    }
+    
+    // Task store used by the worker itself for sweeps and control commands
+    let worker_todo_list = match TodoList::new().await {
+        Ok(todo_list) => Some(todo_list),
+        Err(e) => {
+            error!("Failed to open todo list for worker maintenance: {}", e);
+            None
+        }
+    };
+
+    // Spawn the overdue task sweep
+    let overdue_sweeper = worker_todo_list.clone().map(|todo_list| {
+        let client = client.clone();
+        let metrics = metrics.clone();
+        let sweep_interval = env::var("OVERDUE_SWEEP_INTERVAL")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_OVERDUE_SWEEP_INTERVAL);
+        let reescalate_after = env::var("OVERDUE_REESCALATE_AFTER")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(DEFAULT_OVERDUE_REESCALATE_AFTER);
+        let project_topics = parse_project_topics(&env::var("OVERDUE_PROJECT_TOPICS").unwrap_or_default());
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(sweep_interval));
+            loop {
+                interval.tick().await;
+                if let Err(e) = sweep_overdue_tasks(&todo_list, &client, &metrics, &project_topics, reescalate_after).await {
+                    error!("Error sweeping overdue tasks: {}", e);
+                }
+            }
+        })
+    });
+
+    // Spawn the sweep that releases tasks held by workers that stopped heartbeating
+    let lease_sweeper = worker_todo_list.clone().map(|todo_list| {
+        let metrics = metrics.clone();
+        let sweep_interval = Duration::from_secs(lease.heartbeat_secs.max(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(sweep_interval);
+            loop {
+                interval.tick().await;
+                match todo_list.release_expired_leases(todo_list.clock().timestamp()).await {
+                    Ok(released) if released.is_empty() => {},
+                    Ok(released) => {
+                        metrics.increment_reclaimed(released.len() as u64);
+                        for task in released {
+                            warn!("Lease on task {} held by {:?} expired, returned it to the queue", task.id, task.claimed_by);
+                        }
+                    },
+                    Err(e) => error!("Error releasing expired task leases: {}", e),
+                }
+            }
+        })
+    });
+
+    // Spawn task checker background task
+    let control = Arc::new(WorkerControl::new(check_interval));
+    let task_checker = {
+        let registry = agent_registry.clone();
+        let client = client.clone();
+        let metrics = metrics.clone();
+        let lease = lease.clone();
+        let scheduler = scheduler.clone();
+        let supervisor = supervisor.clone();
+        let mut check_interval = control.check_interval.subscribe();
+        let control = control.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(*check_interval.borrow_and_update());
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    Ok(()) = check_interval.changed() => {
+                        let period = *check_interval.borrow_and_update();
+                        info!("Checking for tasks every {}s", period.as_secs());
+                        interval = tokio::time::interval(period);
+                        continue;
+                    }
+                }
+                // Paused and draining workers leave new tasks for others
+                if control.state() != RunState::Running {
+                    debug!("Worker is {}, not claiming tasks", control.state().as_str());
+                    continue;
+                }
+                if let Err(e) = check_agent_tasks(&registry, &client, &metrics, &scheduler, &lease, &supervisor).await {
+                    error!("Error checking agent tasks: {}", e);
+                }
+            }
+        })
+    };
+
+    // Apply config file edits that don't need a restart
+    if let Some(mut changes) = config_changes {
+        let control = control.clone();
+        tokio::spawn(async move {
+            loop {
+                match changes.recv().await {
+                    Ok(change) => {
+                        let interval = change.current.worker.check_interval();
+                        if interval != change.previous.worker.check_interval() {
+                            control.check_interval.send_replace(interval);
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    let mut request_workers: Vec<_> = (0..queue_workers)
+        .map(|_| {
+            let queue = request_queue.clone();
+            let registry = agent_registry.clone();
+            let client = client.clone();
+            let metrics = metrics.clone();
+            let lease = lease.clone();
+            let control = control.clone();
+            tokio::spawn(async move {
+                while let Some(message) = queue.pop().await {
+                    // Requests received while paused wait in the queue
+                    control.wait_until_running().await;
+                    control.busy_workers.fetch_add(1, Ordering::SeqCst);
+                    handle_todo_request(&registry, &message, &client, &metrics, &lease).await;
+                    control.busy_workers.fetch_sub(1, Ordering::SeqCst);
+                }
+            })
+        })
+        .collect();
+
+    // Main event loop with graceful shutdown support
+    let (shutdown_tx, mut shutdown_rx) = tokio::sync::broadcast::channel::<()>(1);
+    
+    // Set up graceful shutdown on ctrl-c
+    let shutdown_tx_ctrl_c = shutdown_tx.clone();
+    tokio::spawn(async move {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for ctrl-c: {}", e);
+            return;
+        }
+        info!("Received shutdown signal, initiating graceful shutdown...");
+        let _ = shutdown_tx_ctrl_c.send(());
+    });
+    
+    loop {
+        tokio::select! {
+            // Check for shutdown signal
+            result = shutdown_rx.recv() => {
+                if result.is_ok() {
+                    info!("Shutdown signal received, closing MQTT connection...");
+
+                    // Stop claiming; a task claimed but not yet started is
+                    // picked up by another worker once its lease expires
+                    task_checker.abort();
+                    metrics_reporter.abort();
+                    if let Some(lease_sweeper) = &lease_sweeper {
+                        lease_sweeper.abort();
+                    }
+                    if let Some(overdue_sweeper) = &overdue_sweeper {
+                        overdue_sweeper.abort();
+                    }
+                    #[cfg(feature = "haiku-agent")]
+                    if let Some(daily_haiku) = &daily_haiku {
+                        daily_haiku.abort();
+                    }
+
+                    // Let the workers finish what was already accepted
+                    request_queue.close();
+                    for worker in request_workers.drain(..) {
+                        if let Err(e) = worker.await {
+                            error!("Request worker failed: {}", e);
+                        }
+                    }
+
+                    // Then for tasks already being processed
+                    let aborted = supervisor.drain(shutdown_grace).await;
+                    if aborted > 0 {
+                        warn!("Aborted {} job(s) still running at shutdown", aborted);
+                    }
+                    
+                    // Report final metrics
+                    if let Err(e) = report_metrics(&metrics, &client).await {
+                        error!("Failed to report final metrics: {}", e);
+                    }
+                    
+                    // Publish shutdown status
+                    let shutdown_payload = json!({
+                        "status": "shutdown",
+                        "timestamp": chrono::Utc::now().to_rfc3339(),
+                        "final_metrics": metrics.get_metrics_json().await
+                    }).to_string();
+                    
+                    if let Err(e) = client.publish(
+                        "todo_worker/status", 
+                        QoS::ExactlyOnce, 
+                        false, 
+                        shutdown_payload
+                    ).await {
+                        error!("Failed to publish shutdown status: {}", e);
+                    }
+                    
+                    // Disconnect from MQTT, clearing the retained online status first
+                    if let Err(e) = client.publish_offline().await {
+                        error!("Failed to publish offline status: {}", e);
+                    }
+                    if let Err(e) = client.disconnect().await {
+                        error!("Error disconnecting from MQTT: {}", e);
+                    }
+                    
+                    // Allow time for final messages to be sent
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                    info!("Graceful shutdown complete");
+                    telemetry::shutdown_tracing();
+                    break Ok(());
+                }
+            }
+            
+            // Handle todo requests for agents
+            Some(message) = todo_requests.recv() => {
+                debug!("Queueing message on topic {}", message.topic);
+                match request_queue.push(message) {
+                    Pushed::Queued => {}
+                    Pushed::DroppedOldest(dropped) => {
+                        warn!("Request queue full, dropped oldest request on {}", dropped.topic);
+                        reject_todo_request(&client, &dropped, "Dropped from a full request queue").await;
+                    }
+                    Pushed::Rejected(rejected) => {
+                        warn!("Request queue full, rejected request on {}", rejected.topic);
+                        reject_todo_request(&client, &rejected, "Request queue full").await;
+                    }
+                }
+            }
+
+            // Resume tasks parked on a question
+            Some(message) = answers.recv() => {
+                debug!("Received message on topic {}: {}", message.topic, message.payload_str());
+                handle_answer_message(&message, &client, worker_todo_list.as_ref()).await;
+            }
+
+            // Approve or reject operations this worker is holding for approval
+            Some(message) = decisions.recv() => {
+                debug!("Received message on topic {}: {}", message.topic, message.payload_str());
+                handle_inbox_decision(&message, &client, admin_token.as_deref(), &AccessPolicy::shared()).await;
+            }
+
+            // Handle control commands
+            Some(message) = control_messages.recv() => {
+                debug!("Received message on topic {}: {}", message.topic, message.payload_str());
+                let context = ControlContext {
+                    client: &client,
+                    metrics: &metrics,
+                    todo_list: worker_todo_list.as_ref(),
+                    control: &control,
+                    scheduler: &scheduler,
+                    agent_registry: &agent_registry,
+                    supervisor: &supervisor,
+                };
+                if let Err(e) = handle_control_message(&message, &context).await {
+                    error!("Error handling control message: {}", e);
+                }
+            }
+        }
+    }
+}
+
+/// What control commands act on
+struct ControlContext<'a> {
+    client: &'a MqttService,
+    metrics: &'a Arc<Metrics>,
+    todo_list: Option<&'a TodoList>,
+    control: &'a Arc<WorkerControl>,
+    scheduler: &'a TaskScheduler,
+    agent_registry: &'a Arc<RwLock<AgentRegistry>>,
+    supervisor: &'a TaskSupervisor,
+}
+
+/// Acknowledge a control command on `todo_worker/control/ack` (or the
+/// request's response topic)
+async fn acknowledge(context: &ControlContext<'_>, request: &MqttMessage, command: &str, details: serde_json::Value) -> Result<()> {
+    let mut ack = json!({
+        "command": command,
+        "status": "ok",
+        "worker": context.control.status_json(context.scheduler),
+        "timestamp": chrono::Utc::now().to_rfc3339()
+    });
+    if let (Some(ack), serde_json::Value::Object(details)) = (ack.as_object_mut(), details) {
+        ack.extend(details);
+    }
+    Ok(context.client.reply(request, "todo_worker/control/ack", ack.to_string()).await?)
+}
+
+/// Once in-flight work finishes, move a draining worker to paused and say so
+async fn spawn_drain_watch(context: &ControlContext<'_>, request: &MqttMessage) -> Result<()> {
+    let control = context.control.clone();
+    let scheduler = context.scheduler.clone();
+    let client = context.client.clone();
+    let request = request.clone();
+    context.supervisor.spawn("drain watch", async move {
+        let mut poll = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            poll.tick().await;
+            if control.state() != RunState::Draining {
+                // Resumed or paused before the drain finished
+                return;
+            }
+            if control.in_flight(&scheduler) == 0 {
+                break;
+            }
+        }
+        control.set_state(RunState::Paused);
+        info!("Drained, worker is idle");
+        let ack = json!({
+            "command": "drain",
+            "status": "drained",
+            "worker": control.status_json(&scheduler),
+            "timestamp": chrono::Utc::now().to_rfc3339()
+        });
+        if let Err(e) = client.reply(&request, "todo_worker/control/ack", ack.to_string()).await {
+            error!("Failed to acknowledge drain: {}", e);
+        }
+    }).await
+}
+
+async fn handle_control_message(message: &MqttMessage, context: &ControlContext<'_>) -> Result<()> {
+    let (client, metrics, todo_list) = (context.client, context.metrics, context.todo_list);
+    match message.json::<serde_json::Value>() {
+        Ok(json) => {
+            if let Some(command) = json.get("command").and_then(|c| c.as_str()) {
+                match command {
+                    "status" => {
+                        // Publish current status
+                        let mut status = metrics.get_metrics_json().await;
+                        status["worker"] = context.control.status_json(context.scheduler);
+                        client.publish(
+                            "todo_worker/status",
+                            QoS::ExactlyOnce,
+                            false,
+                            status.to_string()
+                        ).await?;
+                        info!("Published status in response to request");
+                    },
+                    "reset_metrics" => {
+                        // Report the totals being discarded so they aren't lost
+                        let previous = metrics.get_metrics_json().await;
+                        metrics.reset().await;
+                        info!("Metrics reset");
+                        client.publish(
+                            "todo_worker/metrics_reset_response",
+                            QoS::ExactlyOnce,
+                            false,
+                            json!({
+                                "status": "reset",
+                                "previous_metrics": previous,
+                                "timestamp": chrono::Utc::now().to_rfc3339()
+                            }).to_string()
+                        ).await?;
+                    },
+                    "replay_dlq" => {
+                        // Re-queue every dead-lettered task with a fresh attempt count
+                        let todo_list = todo_list.ok_or_else(|| anyhow!("Todo list unavailable, cannot replay dead-letter queue"))?;
+                        let replayed = todo_list.replay_dead_letters().await?;
+                        info!("Replayed {} dead-lettered tasks", replayed.len());
+                        client.publish(
+                            "todo_worker/dlq_replay_response",
+                            QoS::ExactlyOnce,
+                            false,
+                            json!({
+                                "status": "replayed",
+                                "count": replayed.len(),
+                                "task_ids": replayed.iter().map(|task| task.id.clone()).collect::<Vec<_>>(),
+                                "timestamp": chrono::Utc::now().to_rfc3339()
+                            }).to_string()
+                        ).await?;
+                    },
+                    "pause" => {
+                        context.control.set_state(RunState::Paused);
+                        info!("Paused, no longer claiming tasks");
+                        acknowledge(context, message, command, json!({})).await?;
+                    },
+                    "resume" => {
+                        context.control.set_state(RunState::Running);
+                        info!("Resumed claiming tasks");
+                        acknowledge(context, message, command, json!({})).await?;
+                    },
+                    "drain" => {
+                        context.control.set_state(RunState::Draining);
+                        info!("Draining {} in-flight tasks", context.control.in_flight(context.scheduler));
+                        acknowledge(context, message, command, json!({ "status": "draining" })).await?;
+                        spawn_drain_watch(context, message).await?;
+                    },
+                    "set_check_interval" => {
+                        let secs = json.get("seconds")
+                            .and_then(|s| s.as_u64())
+                            .filter(|secs| *secs > 0)
+                            .ok_or_else(|| anyhow!("set_check_interval needs a positive \"seconds\""))?;
+                        context.control.check_interval.send_replace(Duration::from_secs(secs));
+                        acknowledge(context, message, command, json!({})).await?;
+                    },
+                    "reload_agents" => {
+                        load_agents(context.agent_registry).await?;
+                        let agents = context.agent_registry.read().await.names();
+                        info!("Reloaded {} agents", agents.len());
+                        acknowledge(context, message, command, json!({ "agents": agents })).await?;
+                    },
+                    unknown => {
+                        warn!("Unknown control command: {}", unknown);
+                        client.publish(
+                            "todo_worker/error",
+                            QoS::ExactlyOnce,
+                            false,
+                            json!({
+                                "error": format!("Unknown command: {}", unknown),
+                                "timestamp": chrono::Utc::now().to_rfc3339()
+                            }).to_string()
+                        ).await?;
+                    }
+                }
+            }
+        },
+        Err(e) => {
+            error!("Failed to parse control message: {}", e);
+            client.publish(
+                "todo_worker/error",
+                QoS::ExactlyOnce,
+                false,
+                json!({
+                    "error": format!("Invalid control message: {}", e),
+                    "timestamp": chrono::Utc::now().to_rfc3339()
+                }).to_string()
+            ).await?;
+        }
+    }
+    
+    Ok(())
+}
+
+async fn load_agents(agent_registry: &Arc<RwLock<AgentRegistry>>) -> Result<()> {
+    let config_agents = agents::default_agents();
+    for config in config_agents {
+        let agent_config = config.clone();
+        match agents::create_agent(agent_config).await {
+            Ok(agent) => {
+                let mut registry = agent_registry.write().await;
+                if let Err(e) = registry.register(config.name.clone(), agent).await {
+                    error!("Failed to register agent {}: {}", config.name, e);
+                } else {
+                    info!("Registered agent: {}", config.name);
+                }
+            },
+            Err(e) => {
+                error!("Failed to create agent {}: {}", config.name, e);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Runs one queued `agent/<name>/todo/process` request
+async fn handle_todo_request(
+    agent_registry: &Arc<RwLock<AgentRegistry>>,
+    message: &MqttMessage,
+    client: &MqttService,
+    metrics: &Arc<Metrics>,
+    lease: &TaskLease
+) {
+    let payload = match message.json_text() {
+        Ok(payload) => payload,
+        Err(e) => {
+            error!("Failed to decode {:?} payload: {}", message.format(), e);
+            return;
+        }
+    };
+    let payload = payload.as_ref();
+    debug!("Received message on topic {}: {}", message.topic, payload);
+
+    // Extract the agent name from the topic
+    if let Some(agent_name) = message.topic.split('/').nth(1) {
+        info!("Processing todo for agent: {}", agent_name);
+
+        let policy = AccessPolicy::shared();
+        if !policy.is_enabled() {
+            process_agent_message(agent_registry, agent_name, payload, message, client, metrics, lease).await;
+            return;
+        }
+        // Commands act for whoever the payload's key belongs to
+        let key = serde_json::from_str::<Value>(payload).ok()
+            .and_then(|value| value.get(API_KEY_FIELD).and_then(Value::as_str).map(str::to_string));
+        let Some(principal) = policy.resolve(key.as_deref()) else {
+            reject_todo_request(client, message, "Unknown API key").await;
+            return;
+        };
+        let checked = with_principal(principal, async {
+            policy.check_agent(agent_name)?;
+            process_agent_message(agent_registry, agent_name, payload, message, client, metrics, lease).await;
+            Ok::<_, AccessDenied>(())
+        }).await;
+        if let Err(denied) = checked {
+            warn!("Refusing todo for {}: {}", agent_name, denied);
+            reject_todo_request(client, message, &denied.to_string()).await;
+        }
+    }
+}
+
+/// Tells the sender of a request the queue had no room for
+async fn reject_todo_request(client: &MqttService, message: &MqttMessage, reason: &str) {
+    let agent_name = message.topic.split('/').nth(1).unwrap_or("unknown");
+    let error_topic = format!("agent/{}/todo/error", agent_name);
+    let error_payload = json!({
+        "error": reason,
+        "payload": message.payload_str(),
+        "timestamp": chrono::Utc::now().to_rfc3339()
+    }).to_string();
+
+    if let Err(e) = client.reply(message, error_topic, error_payload).await {
+        error!("Failed to publish error message: {}", e);
+    }
+}
+
+async fn process_agent_message(
+    agent_registry: &Arc<RwLock<AgentRegistry>>,
+    agent_name: &str,
+    payload: &str,
+    request: &MqttMessage,
+    client: &MqttService,
+    metrics: &Arc<Metrics>,
+    lease: &TaskLease
+) {
+    // First, check if this is a task that's already been processed by our background task system
+    // This avoids double-processing due to check_agent_tasks also publishing to the same topic
+    if is_background_dispatch(payload) {
+        debug!("Skipping already processed task for agent {}", agent_name);
+        return;
+    }
+    
+    let task_count = metrics.increment_processed();
+    
+    // Parse task from payload
+    let task: TodoTask = match serde_json::from_str::<TodoTask>(payload) {
+        Ok(task) => {
+            metrics.increment_priority_counter(&task.priority);
+            task
+        },
+        Err(e) => {
+            error!("Failed to parse task payload: {}", e);
+            metrics.increment_failed();
+            
+            // Publish error message to MQTT
+            let error_topic = format!("agent/{}/todo/error", agent_name);
+            let error_payload = json!({
+                "error": format!("Invalid task payload: {}", e),
+                "payload": payload,
+                "timestamp": chrono::Utc::now().to_rfc3339()
+            }).to_string();
+            
+            if let Err(e) = client.reply(request, error_topic, error_payload).await {
+                error!("Failed to publish error message: {}", e);
+            }
+            
+            return;
+        }
+    };
+    
+    // Get priority level as a string for logging
+    let priority_str = task.priority.as_str();
+    
+    // Tasks published directly to MQTT bypass get_next_task, so gate them on their dependencies here
+    if !task.depends_on.is_empty() {
+        if let Some(todo_list) = todo_list_for(agent_registry, agent_name).await {
+            match todo_list.unmet_dependencies(&task).await {
+                Ok(unmet) if !unmet.is_empty() => {
+                    // Stored as pending, the task is picked up from the store once they complete
+                    let stored = match todo_list.get_task(&task.id).await {
+                        Ok(Some(_)) => Ok(()),
+                        Ok(None) => todo_list.add_task(TodoTask {
+                            status: TaskStatus::Pending,
+                            target_agent: agent_name.to_string(),
+                            ..task.clone()
+                        }).await,
+                        Err(e) => Err(e.into()),
+                    };
+                    match stored {
+                        Ok(()) => info!("Deferring task {}: waiting on dependencies {:?}", task.id, unmet),
+                        Err(e) => {
+                            warn!("Failed to store task {} until its dependencies complete: {}", task.id, e);
+                            let reason = format!("Task {} waits on unmet dependencies {:?} and could not be stored: {}", task.id, unmet, e);
+                            reject_todo_request(client, request, &reason).await;
+                        }
+                    }
+                    return;
+                }
+                Ok(_) => {}
+                Err(e) => warn!("Failed to check dependencies for task {}: {}", task.id, e),
+            }
+        }
+    }
+
+    // Stored tasks must be claimed first so only one worker runs them; ad-hoc
+    // tasks that were never stored are processed as-is
+    let mut heartbeat = None;
+    if let Some(todo_list) = todo_list_for(agent_registry, agent_name).await {
+        match todo_list.claim_task(&task.id, lease).await {
+            Ok(Some(_)) => heartbeat = Some(todo_list.spawn_heartbeat(&task.id, lease)),
+            Ok(None) => match todo_list.get_task(&task.id).await {
+                Ok(Some(stored)) => {
+                    info!("Skipping task {}: already {:?} (claimed by {:?})", task.id, stored.status, stored.claimed_by);
+                    return;
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Failed to look up task {}: {}", task.id, e),
+            },
+            Err(e) => warn!("Failed to claim task {}: {}", task.id, e),
+        }
+    }
+
+    info!("Processing task {} with priority {} (count: {})", task.id, priority_str, task_count);
+    
+    // Continue the trace started upstream, or start one keyed on the task id
+    let correlation_id = telemetry::extract_correlation_id(payload).unwrap_or_else(|| task.id.clone());
+    let span = tracing::info_span!(
+        "todo.process",
+        task_id = %task.id,
+        agent = %agent_name,
+        correlation_id = %correlation_id,
+    );
+    
+    let started = Instant::now();
+    let processing_result = telemetry::with_correlation_id(
+        correlation_id,
+        tokio::time::timeout(
+            Duration::from_secs(TASK_PROCESSING_TIMEOUT),
+            process_todo_for_agent(agent_registry, agent_name, &task, client, Some(request))
+        )
+    ).instrument(span).await;
+    metrics.record_latency(started.elapsed());
+    drop(heartbeat);
+    
+    match processing_result {
+        Ok(Ok(_)) => {
+            info!("Successfully processed task {}", task.id);
+            metrics.increment_succeeded();
+            
+            // Report metrics if this is a multiple of 10
+            if task_count % 10 == 0 {
+                if let Err(e) = report_metrics(metrics, client).await {
+                    error!("Failed to report metrics: {}", e);
+                }
+            }
+        },
+        Ok(Err(e)) => {
+            error!("Failed to process task {}: {}", task.id, e);
+            metrics.increment_failed();
+            
+            // Publish error message to MQTT
+            let error_topic = format!("agent/{}/todo/error", agent_name);
+            let error_payload = json!({
+                "error": e.to_string(),
+                "task_id": task.id,
+                "timestamp": chrono::Utc::now().to_rfc3339()
+            }).to_string();
+            
+            if let Err(e) = client.reply(request, error_topic, error_payload).await {
+                error!("Failed to publish error message: {}", e);
+            }
+        },
+        Err(_) => {
+            // Task processing timed out
+            error!("Task processing timed out for task {}", task.id);
+            metrics.increment_timeout();
+            metrics.increment_failed();
+            
+            // Publish timeout error message
+            let error_topic = format!("agent/{}/todo/error", agent_name);
+            let error_payload = json!({
+                "error": format!("Task processing timed out after {} seconds", TASK_PROCESSING_TIMEOUT),
+                "task_id": task.id,
+                "timestamp": chrono::Utc::now().to_rfc3339()
+            }).to_string();
+            
+            if let Err(e) = client.reply(request, error_topic, error_payload).await {
+                error!("Failed to publish timeout error message: {}", e);
+            }
+            
+            // Retry the task later, or dead-letter it if it is out of attempts
+            if let Some(todo_list) = todo_list_for(agent_registry, agent_name).await {
+                let error = format!("Task processing timed out after {} seconds", TASK_PROCESSING_TIMEOUT);
+                record_task_failure(&todo_list, &task.id, &error, client).await;
+            }
+        }
+    }
+}
+
+/// `agent_name`'s todo list, looked up without keeping the registry locked
+async fn todo_list_for(agent_registry: &Arc<RwLock<AgentRegistry>>, agent_name: &str) -> Option<TodoList> {
+    let handle = agent_registry.read().await.get(agent_name)?;
+    let agent = handle.read().await;
+    Some(TodoProcessor::get_todo_list(&*agent).clone())
+}
+
+async fn process_todo_for_agent(
+    agent_registry: &Arc<RwLock<AgentRegistry>>,
+    agent_name: &str,
+    task: &TodoTask,
+    mqtt_client: &MqttService,
+    request: Option<&MqttMessage>,
+) -> Result<()> {
+    // Get agent to process the task; only this agent's lock is held while it works
+    let handle = agent_registry.read().await.get(agent_name)
+        .ok_or_else(|| anyhow!("Agent not found: {}", agent_name))?;
+    let agent = handle.read().await;
+    
+    // Track start time for performance measurement
+    let start_time = Instant::now();
+    
+    // Process the task; replies are checked before they go out over MQTT
+    let result = match agent.process_task(task.clone()).await {
+        Ok(response) => OutputFilter::shared().check(agent_name, response).await.map_err(anyhow::Error::from),
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(response) => {
+            let processing_time = start_time.elapsed().as_millis();
+            
+            // Publish response
+            let response_topic = format!("agent/{}/todo/response", agent_name);
+            let questions = response.questions();
+            let mut response_payload = json!({
+                "task_id": task.id,
+                "message": response.content,
+                "processing_time_ms": processing_time,
+                "timestamp": chrono::Utc::now().to_rfc3339()
+            });
+            if let Some(questions) = &questions {
+                response_payload["status"] = json!(TaskStatus::WaitingForInput);
+                response_payload["questions"] = json!(questions);
+            }
+            telemetry::inject_correlation_id(&mut response_payload);
+            let response_payload = response_payload.to_string();
+            
+            // Requests that named a response topic get their reply there
+            match request {
+                Some(request) => mqtt_client.reply(request, response_topic, response_payload).await,
+                None => mqtt_client.publish(response_topic, mqtt_client.config().task_qos, false, response_payload).await,
+            }.context("Failed to publish response")?;
+            
+            let todo_list = TodoProcessor::get_todo_list(&*agent);
+
+            // The agent can't go on without answers; park the task until they arrive
+            if let Some(questions) = questions {
+                match todo_list.park_for_input(&task.id, &questions).await
+                    .context("Failed to park task for input")?
+                {
+                    Some(parked) => {
+                        info!("Task {} is waiting for input: {:?}", task.id, questions);
+                        EventBus::shared().publish(Event::task_needs_input(agent_name, &parked, questions));
+                    }
+                    None => debug!("Task {} was not in progress, not parking it", task.id),
+                }
+                return Ok(());
+            }
+
+            // Mark task as completed, unless it was cancelled while it ran
+            let completed = todo_list.mark_task_completed(&task.id).await
+                .context("Failed to mark task as completed")?;
+            if !completed {
+                info!("Task {} was cancelled while running, not completing it", task.id);
+                return Ok(());
+            }
+            EventBus::shared().publish(Event::TaskCompleted {
+                agent: agent_name.to_string(),
+                task_id: task.id.clone(),
+                status: TaskStatus::Completed,
+            });
+            if let Err(e) = NotifyTool::from_env().with_mqtt(mqtt_client.clone()).notify_task_completed(task).await {
+                warn!("Failed to send completion notification for task {}: {}", task.id, e);
+            }
+
+            match todo_list.enqueue_next_occurrence(task).await {
+                Ok(Some(next)) => info!("Scheduled next run {} of recurring task {}", next.id, task.id),
+                Ok(None) => {}
+                Err(e) => error!("Failed to schedule next run of recurring task {}: {}", task.id, e),
+            }
+            
+            Ok(())
+        },
+        Err(e) => {
+            let todo_list = TodoProcessor::get_todo_list(&*agent);
+            match BudgetExceeded::find(&e) {
+                // Another attempt would hit the same limit, so it is not retried
+                Some(exceeded) => match todo_list.mark_budget_exceeded(&task.id, &exceeded.to_string()).await {
+                    Ok(Some(stopped)) => {
+                        warn!("Task {} stopped: {}", task.id, exceeded);
+                        EventBus::shared().publish(task_failed(&stopped, &exceeded.to_string(), false));
+                    }
+                    Ok(None) => debug!("Task {} was cancelled, not marking it over budget", task.id),
+                    Err(e) => error!("Failed to mark task {} over budget: {}", task.id, e),
+                },
+                // Retry the task later, or dead-letter it if it is out of attempts
+                None => record_task_failure(todo_list, &task.id, &e.to_string(), mqtt_client).await,
+            }
+            
+            Err(anyhow!("Failed to process task: {}", e))
+        }
+    }
+}
+
+/// Answer a parked task from an `agent/<name>/todo/answer` message
+/// (`{"task_id": ..., "answer": ...}`) and put it back in the queue
+async fn handle_answer_message(message: &MqttMessage, client: &MqttService, todo_list: Option<&TodoList>) {
+    let agent_name = message.topic.split('/').nth(1).unwrap_or("unknown");
+    let answered = async {
+        let todo_list = todo_list.ok_or_else(|| anyhow!("Task store unavailable"))?;
+        let payload: serde_json::Value = serde_json::from_slice(&message.payload)
+            .context("Invalid answer payload")?;
+        let task_id = payload["task_id"].as_str().ok_or_else(|| anyhow!("Answer is missing task_id"))?;
+        let answer = payload["answer"].as_str()
+            .map(str::trim)
+            .filter(|answer| !answer.is_empty())
+            .ok_or_else(|| anyhow!("Answer is missing answer"))?;
+        todo_list.answer_task(task_id, answer).await?
+            .ok_or_else(|| anyhow!("Task '{}' not found or not waiting for input", task_id))
+    }.await;
+
+    match answered {
+        Ok(task) => {
+            info!("Task {} answered, back in the queue", task.id);
+            EventBus::shared().publish(Event::task_requeued(agent_name, &task));
+        }
+        Err(e) => {
+            warn!("Failed to answer task for agent {}: {}", agent_name, e);
+            let error_payload = json!({
+                "error": e.to_string(),
+                "payload": message.payload_str(),
+                "timestamp": chrono::Utc::now().to_rfc3339()
+            }).to_string();
+            if let Err(e) = client.reply(message, format!("agent/{}/todo/error", agent_name), error_payload).await {
+                error!("Failed to publish error message: {}", e);
+            }
+        }
+    }
+}
+
+/// Decisions release held operations, so like the HTTP inbox routes they need
+/// the admin token in `token`, or an `api_key` whose principal may `inbox:decide`
+fn authorize_decision(payload: &Value, admin_token: Option<&str>, policy: &AccessPolicy) -> Result<()> {
+    if let (Some(given), Some(expected)) = (payload["token"].as_str(), admin_token) {
+        if tokens_match(given, expected) {
+            return Ok(());
+        }
+    }
+    let principal = payload.get(API_KEY_FIELD).and_then(Value::as_str)
+        .filter(|_| policy.is_enabled())
+        .and_then(|key| policy.resolve(Some(key)));
+    match principal {
+        Some(principal) if policy.allows(&principal, INBOX_DECIDE) => Ok(()),
+        _ => Err(anyhow!("Decisions need the admin token or an API key allowed {}", INBOX_DECIDE)),
+    }
+}
+
+/// Decide an operation held in this worker's approval inbox from a
+/// `user/inbox/<id>/decision` message (`{"decision": "approve" | "reject",
+/// "token": ..., "note": ..., "params": {...}}`); `params` edit the operation
+/// before it runs
+async fn handle_inbox_decision(message: &MqttMessage, client: &MqttService, admin_token: Option<&str>, policy: &AccessPolicy) {
+    let id = message.topic.split('/').nth(2).unwrap_or_default();
+    let decided = (|| {
+        let payload: serde_json::Value = serde_json::from_slice(&message.payload)
+            .context("Invalid decision payload")?;
+        authorize_decision(&payload, admin_token, policy)?;
+        let inbox = ApprovalInbox::shared();
+        let note = payload["note"].as_str().map(str::to_string);
+        if let Some(params) = payload.get("params") {
+            let params: HashMap<String, String> = serde_json::from_value(params.clone())
+                .context("Decision params must be an object of strings")?;
+            inbox.edit(id, params);
+        }
+        let approval = match payload["decision"].as_str() {
+            Some("approve") => inbox.approve(id, note),
+            Some("reject") => inbox.reject(id, note),
+            _ => return Err(anyhow!("Decision must be \"approve\" or \"reject\"")),
+        };
+        approval.ok_or_else(|| anyhow!("No pending approval '{}' in this worker", id))
+    })();
+
+    match decided {
+        Ok(approval) => info!("{} ({}) {:?} over MQTT", approval.summary(), approval.id, approval.status),
+        Err(e) => {
+            // Other workers may hold the approval; only malformed decisions are errors
+            if ApprovalInbox::shared().get(id).is_none() {
+                debug!("Ignoring decision for approval {}: {}", id, e);
+                return;
+            }
+            warn!("Failed to decide approval {}: {}", id, e);
+            let error_payload = json!({
+                "error": e.to_string(),
+                "payload": message.payload_str(),
+                "timestamp": chrono::Utc::now().to_rfc3339()
+            }).to_string();
+            if let Err(e) = client.reply(message, "user/inbox/error".to_string(), error_payload).await {
+                error!("Failed to publish error message: {}", e);
+            }
+        }
+    }
+}
+
+fn task_failed(task: &TodoTask, error: &str, dead_lettered: bool) -> Event {
+    Event::TaskFailed {
+        agent: task.target_agent.clone(),
+        task_id: task.id.clone(),
+        error: error.to_string(),
+        dead_lettered,
+    }
+}
+
+/// Apply the retry policy to a failed task and announce it on the dead-letter
+/// topic when it has no attempts left.
+async fn record_task_failure(todo_list: &TodoList, task_id: &str, error: &str, client: &MqttService) {
+    match todo_list.record_failure(task_id, error, &RetryPolicy::from_env()).await {
+        Ok(FailureOutcome::Retrying { task, retry_at }) => {
+            info!("Task {} failed attempt {}, retrying at {}", task_id, task.attempts, retry_at);
+            EventBus::shared().publish(task_failed(&task, error, false));
+        },
+        Ok(FailureOutcome::DeadLettered(task)) => {
+            warn!("Task {} exhausted {} attempts, moved to dead-letter queue", task_id, task.attempts);
+            EventBus::shared().publish(task_failed(&task, error, true));
+            if let Err(e) = NotifyTool::from_env().with_mqtt(client.clone()).notify_task_failed(&task, error, true).await {
+                warn!("Failed to send dead-letter notification for task {}: {}", task_id, e);
+            }
+            let payload = json!({
+                "task": task,
+                "timestamp": chrono::Utc::now().to_rfc3339()
+            }).to_string();
+            if let Err(e) = client.publish(DEAD_LETTER_TOPIC, QoS::AtLeastOnce, false, payload).await {
+                error!("Failed to publish dead-letter notification: {}", e);
+            }
+        },
+        Ok(FailureOutcome::Discarded) => {
+            debug!("Task {} was cancelled or removed, not retrying", task_id);
+        },
+        Err(e) => error!("Failed to record failure for task {}: {}", task_id, e),
+    }
+}
+
+/// Whether `payload` was published by `check_agent_tasks`, which has already
+/// claimed the task and is processing it
+fn is_background_dispatch(payload: &str) -> bool {
+    serde_json::from_str::<serde_json::Value>(payload)
+        .ok()
+        .and_then(|value| value.get("_processed_by_background").and_then(|flag| flag.as_bool()))
+        .unwrap_or(false)
+}
+
+/// Name a task's project is reported under in the worker metrics
+fn project_key(task: &TodoTask) -> String {
+    task.project.clone().unwrap_or_else(|| "unassigned".to_string())
+}
+
+/// Parse `project=topic,project=topic` into a per-project notification map
+fn parse_project_topics(spec: &str) -> HashMap<String, String> {
+    spec.split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(project, topic)| (project.trim().to_string(), topic.trim().to_string()))
+        .filter(|(project, topic)| !project.is_empty() && !topic.is_empty())
+        .collect()
+}
+
+/// Escalate active tasks that are past their deadline: bump their priority and
+/// announce them on `todo/overdue` plus any topic configured for their project.
+async fn sweep_overdue_tasks(
+    todo_list: &TodoList,
+    client: &MqttService,
+    metrics: &Arc<Metrics>,
+    project_topics: &HashMap<String, String>,
+    reescalate_after: i64,
+) -> Result<()> {
+    let now = todo_list.clock().timestamp();
+    let overdue = todo_list.get_overdue_tasks(now, now - reescalate_after).await?;
+
+    for task in overdue {
+        let escalated = match todo_list.escalate_task(&task).await? {
+            Some(escalated) => escalated,
+            None => continue,
+        };
+        metrics.increment_escalated();
+        warn!("Task {} is overdue, escalated from {:?} to {:?}", task.id, task.priority, escalated.priority);
+
+        let payload = json!({
+            "task_id": task.id,
+            "description": task.description,
+            "project": task.project,
+            "target_agent": task.target_agent,
+            "due_at": task.due_at,
+            "previous_priority": task.priority,
+            "priority": escalated.priority,
+            "timestamp": chrono::Utc::now().to_rfc3339()
+        }).to_string();
+
+        client.publish(OVERDUE_TOPIC, QoS::AtLeastOnce, false, payload.clone()).await?;
+        if let Some(topic) = task.project.as_ref().and_then(|project| project_topics.get(project)) {
+            client.publish(topic.clone(), QoS::AtLeastOnce, false, payload).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Announce a claimed task on its agent's process topic, flagged so this
+/// worker doesn't pick it up a second time
+async fn publish_claimed_task(mqtt_client: &MqttService, task: &TodoTask, correlation_id: &str) -> Result<()> {
+    let topic = format!("agent/{}/todo/process", task.target_agent);
+    let mut task_json_value = serde_json::to_value(task)?;
+    if let serde_json::Value::Object(ref mut obj) = task_json_value {
+        obj.insert("_processed_by_background".to_string(), serde_json::Value::Bool(true));
+        obj.insert(telemetry::CORRELATION_FIELD.to_string(), serde_json::Value::String(correlation_id.to_string()));
+    }
+    let task_json = serde_json::to_string(&task_json_value)?;
+    mqtt_client.publish(topic, mqtt_client.config().task_qos, false, task_json).await?;
+    Ok(())
+}
+
+/// Return a claimed task that never reached a job to the queue, rather than
+/// leaving it in progress until its lease runs out
+async fn release_unstarted(todo_list: &TodoList, task_id: &str, lease: &TaskLease) {
+    match todo_list.release_claim(task_id, lease).await {
+        Ok(true) => info!("Released claim on task {}, which could not be started", task_id),
+        Ok(false) => {}
+        Err(e) => error!("Failed to release claim on task {}: {}", task_id, e),
+    }
+}
+
+async fn check_agent_tasks(
+    agent_registry: &Arc<RwLock<AgentRegistry>>, 
+    mqtt_client: &MqttService,
+    metrics: &Arc<Metrics>,
+    scheduler: &TaskScheduler,
+    lease: &TaskLease,
+    supervisor: &TaskSupervisor,
+) -> Result<()> {
+    debug!("Checking for pending agent tasks");
+    
+    let agent_names = agent_registry.read().await.names();
+    
+    // Gather ready tasks across all agents so urgent work for one agent is not
+    // stuck behind low-priority work for another
+    let mut ready = Vec::new();
+    let mut todo_lists = HashMap::new();
+    for agent_name in agent_names {
+        if let Some(todo_list) = todo_list_for(agent_registry, &agent_name).await {
+            match todo_list.get_ready_tasks(Some(&agent_name), scheduler.config().total_permits * 4).await {
+                Ok(tasks) if tasks.is_empty() => {
+                    debug!("No pending tasks for agent {}", agent_name);
+                },
+                Ok(tasks) => ready.extend(tasks),
+                Err(e) => {
+                    error!("Failed to get ready tasks for agent {}: {}", agent_name, e);
+                }
+            }
+            todo_lists.insert(agent_name, todo_list);
+        }
+    }
+    
+    let mut queued: HashMap<String, u64> = HashMap::new();
+    for task in &ready {
+        *queued.entry(project_key(task)).or_insert(0) += 1;
+    }
+    metrics.record_project_queues(&queued).await;
+    
+    for scheduled in scheduler.order(ready, scheduler.now()) {
+        let project = project_key(&scheduled.task);
+        
+        // Acquire a slot before claiming so a full lane never strands a task in progress
+        let permit = match scheduler.try_acquire_for(&scheduled) {
+            Ok(permit) => permit,
+            Err(throttled) => {
+                metrics.increment_throttled();
+                if let Throttled::Project(_) = throttled {
+                    metrics.record_project_throttled(&project).await;
+                }
+                debug!("Task {} held back ({:?}), leaving it for the next check", scheduled.task.id, throttled);
+                continue;
+            }
+        };
+        
+        let agent_name = scheduled.task.target_agent.clone();
+        let Some(todo_list) = todo_lists.get(&agent_name) else { continue };
+        let task = match todo_list.claim_task(&scheduled.task.id, lease).await {
+            Ok(Some(task)) => task,
+            Ok(None) => {
+                debug!("Task {} was claimed elsewhere", scheduled.task.id);
+                continue;
+            },
+            Err(e) => {
+                error!("Failed to claim task {} for agent {}: {}", scheduled.task.id, agent_name, e);
+                continue;
+            }
+        };
+        
+        info!("Scheduling task {} ({:?}, {} lane) for agent {}", task.id, scheduled.priority, scheduled.class.as_str(), agent_name);
+        metrics.increment_scheduled(scheduled.class);
+        metrics.record_project_scheduled(&project).await;
+        if scheduled.promoted {
+            metrics.increment_aged();
+        }
+        
+        // Clone necessary values for task processing
+        let agent_registry_clone = agent_registry.clone();
+        let mqtt_client_clone = mqtt_client.clone();
+        let metrics_clone = metrics.clone();
+        let agent_name_clone = agent_name.clone();
+        let task_clone = task.clone();
+        let todo_list_clone = todo_list.clone();
+        let lease_clone = lease.clone();
+        let correlation_id = task.id.clone();
+        
+        // Nothing renews the claim until the job runs, so give it back if the task can't get there
+        if let Err(e) = publish_claimed_task(mqtt_client, &task, &correlation_id).await {
+            release_unstarted(todo_list, &task.id, lease).await;
+            return Err(e);
+        }
+        
+        // Process in the background, releasing the permit when done
+        let job = format!("task {} for {}", task.id, agent_name);
+        let span = tracing::info_span!(
+            "todo.process",
+            task_id = %task.id,
+            agent = %agent_name,
+            correlation_id = %correlation_id,
+        );
+        let spawned = supervisor.spawn(job, async move {
+            // Renews the claim until the job ends, however it ends
+            let heartbeat = todo_list_clone.spawn_heartbeat(&task_clone.id, &lease_clone);
+            
+            // Create a timeout for task processing
+            let started = Instant::now();
+            let processing_result = telemetry::with_correlation_id(
+                correlation_id,
+                tokio::time::timeout(
+                    Duration::from_secs(TASK_PROCESSING_TIMEOUT),
+                    process_todo_for_agent(
+                        &agent_registry_clone, 
+                        &agent_name_clone, 
+                        &task_clone, 
+                        &mqtt_client_clone,
+                        None
+                    )
+                )
+            ).await;
+            metrics_clone.record_latency(started.elapsed());
+            
+            match processing_result {
+                Ok(Ok(_)) => {
+                    metrics_clone.increment_succeeded();
+                    info!("Task {} processed successfully", task_clone.id);
+                },
+                Ok(Err(e)) => {
+                    metrics_clone.increment_failed();
+                    error!("Failed to process task {}: {}", task_clone.id, e);
+                },
+                Err(_) => {
+                    metrics_clone.increment_timeout();
+                    metrics_clone.increment_failed();
+                    error!("Task {} processing timed out", task_clone.id);
+                    if let Some(todo_list) = todo_list_for(&agent_registry_clone, &agent_name_clone).await {
+                        let error = format!("Task processing timed out after {} seconds", TASK_PROCESSING_TIMEOUT);
+                        record_task_failure(&todo_list, &task_clone.id, &error, &mqtt_client_clone).await;
+                    }
+                }
+            }
+            
+            // The permit is automatically dropped here, releasing the semaphore
+            drop(heartbeat);
+            drop(permit);
+        }.instrument(span)).await;
+        if let Err(e) = spawned {
+            release_unstarted(todo_list, &task.id, lease).await;
+            return Err(e);
+        }
+    }
+    
+    Ok(())
+}
+
+async fn report_metrics(
+    metrics: &Arc<Metrics>,
+    mqtt_client: &MqttService,
+) -> Result<()> {
+    let now = metrics.clock.instant();
+    
+    // Check if it's time to report metrics
+    {
+        let mut last_report = metrics.last_report_time.lock().await;
+        if now.duration_since(*last_report).as_secs() < METRICS_REPORTING_INTERVAL {
+            return Ok(());
+        }
+        *last_report = now;
+    }
+    
+    let metrics_json = metrics.get_metrics_json().await;
+    
+    let metrics_topic = "metrics/todo_worker";
+    mqtt_client.publish_metrics(metrics_topic, &metrics_json).await?;
+    info!("Published metrics: {}", metrics_json);
+    
+    // Also publish health status
+    let health_status = if metrics.is_healthy() { "healthy" } else { "unhealthy" };
+    let health_topic = "health/todo_worker";
+    mqtt_client.publish(health_topic, mqtt_client.config().metrics.qos, false, health_status).await?;
+    
+    Ok(())
+}
+
+// Add the tests at the end of the file
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::access::Principal;
+    
+    #[test]
+    fn test_decisions_need_a_credential() {
+        let policy = AccessPolicy::default()
+            .with_role("ops", &[INBOX_DECIDE])
+            .with_role("reader", &["todo:read"])
+            .with_key("k-ops", Principal::new("ops", &["ops"]))
+            .with_key("k-ci", Principal::new("ci", &["reader"]));
+        let decide = |credentials: Value| {
+            let mut payload = json!({"decision": "approve"});
+            payload.as_object_mut().unwrap().extend(credentials.as_object().unwrap().clone());
+            authorize_decision(&payload, Some("s3cret"), &policy).is_ok()
+        };
+
+        assert!(!decide(json!({})));
+        assert!(!decide(json!({"token": "guess"})));
+        assert!(decide(json!({"token": "s3cret"})));
+        assert!(decide(json!({"api_key": "k-ops"})));
+        assert!(!decide(json!({"api_key": "k-ci"})));
+        assert!(!decide(json!({"api_key": "stolen"})));
+        // Without a configured token, a token in the payload counts for nothing
+        assert!(authorize_decision(&json!({"token": ""}), None, &AccessPolicy::default()).is_err());
+    }
+
+    #[test]
+    fn test_metrics_counters() {
+        let metrics = Metrics::new();
+        
+        // Test processed counter
+        assert_eq!(metrics.increment_processed(), 1);
+        assert_eq!(metrics.increment_processed(), 2);
+        
+        // Test succeeded counter
+        metrics.increment_succeeded();
+        metrics.increment_succeeded();
+        assert_eq!(metrics.tasks_succeeded.load(Ordering::Relaxed), 2);
+        
+        // Test failed counter
+        metrics.increment_failed();
+        assert_eq!(metrics.tasks_failed.load(Ordering::Relaxed), 1);
+        
+        // Test timeout counter
+        metrics.increment_timeout();
+        assert_eq!(metrics.tasks_timeout.load(Ordering::Relaxed), 1);
+        
+        // Test priority counters
+        metrics.increment_priority_counter(&TaskPriority::Inital);
+        metrics.increment_priority_counter(&TaskPriority::Low);
+        metrics.increment_priority_counter(&TaskPriority::Medium);
+        metrics.increment_priority_counter(&TaskPriority::High);
+        metrics.increment_priority_counter(&TaskPriority::Critical);
+        
+        assert_eq!(metrics.inital_tasks_processed.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.low_tasks_processed.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.medium_tasks_processed.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.high_tasks_processed.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.critical_tasks_processed.load(Ordering::Relaxed), 1);
+        
+        // Test scheduler counters
+        metrics.increment_scheduled(PriorityClass::Urgent);
+        metrics.increment_scheduled(PriorityClass::Low);
+        metrics.increment_throttled();
+        metrics.increment_aged();
+        assert_eq!(metrics.urgent_tasks_scheduled.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.normal_tasks_scheduled.load(Ordering::Relaxed), 0);
+        assert_eq!(metrics.low_tasks_scheduled.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.tasks_throttled.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.tasks_aged.load(Ordering::Relaxed), 1);
+    }
+    
+    #[tokio::test]
+    async fn test_reset_and_recent_windows() {
+        let metrics = Metrics::new();
+        metrics.increment_processed();
+        metrics.increment_succeeded();
+        metrics.increment_failed();
+        metrics.record_latency(Duration::from_millis(120));
+
+        let json = metrics.get_metrics_json().await;
+        assert_eq!(json["windows"]["5m"]["success_rate"], 50.0);
+        assert_eq!(json["windows"]["1h"]["latency"]["count"], 1);
+        assert_eq!(json["windows"]["1h"]["latency"]["p99_ms"], 120.0);
+        assert!(!metrics.is_healthy());
+
+        metrics.reset().await;
+        let json = metrics.get_metrics_json().await;
+        assert_eq!(json["tasks_processed"], 0);
+        assert_eq!(json["tasks_failed"], 0);
+        assert!(json["windows"]["5m"]["success_rate"].is_null());
+        assert!(!json["last_reset"].is_null());
+        assert!(metrics.is_healthy());
+    }
+
+    #[tokio::test]
+    async fn test_recent_window_follows_clock() {
+        let clock = Arc::new(clock::FakeClock::at(1_700_000_000));
+        let metrics = Metrics::with_clock(clock.clone());
+        metrics.increment_failed();
+        assert!(!metrics.is_healthy());
+
+        // The failure ages out of the shortest window; only lifetime counts remain
+        clock.advance(RECENT_WINDOWS[0].1 + Duration::from_secs(1));
+        let json = metrics.get_metrics_json().await;
+        assert!(json["windows"]["5m"]["success_rate"].is_null());
+        assert_eq!(json["windows"]["1h"]["tasks_failed"], 1);
+        assert_eq!(json["uptime_seconds"], RECENT_WINDOWS[0].1.as_secs() + 1);
+    }
+
+    #[tokio::test]
+    async fn test_project_queue_metrics() {
+        let metrics = Metrics::new();
+        metrics.record_project_queues(&HashMap::from([("regressiontestkit".to_string(), 3)])).await;
+        metrics.record_project_scheduled("regressiontestkit").await;
+        metrics.record_project_throttled("regressiontestkit").await;
+        metrics.record_project_queues(&HashMap::new()).await;
+        
+        let json = metrics.get_metrics_json().await;
+        assert_eq!(json["projects"]["regressiontestkit"]["queued"], 0);
+        assert_eq!(json["projects"]["regressiontestkit"]["scheduled"], 1);
+        assert_eq!(json["projects"]["regressiontestkit"]["throttled"], 1);
+    }
+    
+    #[test]
+    fn test_is_background_dispatch() {
+        assert!(is_background_dispatch(r#"{"id":"t1","_processed_by_background":true}"#));
+        assert!(is_background_dispatch(r#"{"id": "t1", "_processed_by_background": true}"#));
+        assert!(!is_background_dispatch(r#"{"id":"t1"}"#));
+        assert!(!is_background_dispatch("not json"));
+    }
+    
+    #[test]
+    fn test_parse_project_topics() {
+        let topics = parse_project_topics("regressiontestkit=lab/alerts, omnispindle = todo/alerts,bogus,=x");
+        assert_eq!(topics.len(), 2);
+        assert_eq!(topics["regressiontestkit"], "lab/alerts");
+        assert_eq!(topics["omnispindle"], "todo/alerts");
+        assert!(parse_project_topics("").is_empty());
+    }
+    
+    #[test]
+    fn test_success_rate_calculation() {
+        let metrics = Metrics::new();
+        
+        // When no tasks processed, success rate should be 100%
+        assert_eq!(metrics.get_success_rate(), 100.0);
+        
+        // Process 4 tasks, 3 succeed, 1 fails
+        metrics.increment_processed();
+        metrics.increment_processed();
+        metrics.increment_processed();
+        metrics.increment_processed();
+        
+        metrics.increment_succeeded();
+        metrics.increment_succeeded();
+        metrics.increment_succeeded();
+        metrics.increment_failed();
+        
+        // Success rate should be 75%
+        assert_eq!(metrics.get_success_rate(), 75.0);
+    }
+    
+    #[test]
+    fn test_health_status() {
+        let metrics = Metrics::new();
+        
+        // When no tasks processed, should be healthy
+        assert!(metrics.is_healthy());
+        
+        // Process 10 tasks, 9 succeed, 1 fails (90% success rate)
+        for _ in 0..10 {
+            metrics.increment_processed();
+        }
+        
+        for _ in 0..9 {
+            metrics.increment_succeeded();
+        }
+        metrics.increment_failed();
+        
+        // At 90% success rate, should be just at the healthy threshold
+        assert!(metrics.is_healthy());
+        
+        // Process 1 more task that fails
+        metrics.increment_processed();
+        metrics.increment_failed();
+        
+        // Now at ~82% success rate (9/11), should be unhealthy
+        assert!(!metrics.is_healthy());
+    }
+    
+    #[tokio::test]
+    async fn test_metrics_json() {
+        let metrics = Metrics::new();
+        
+        metrics.increment_processed();
+        metrics.increment_processed();
+        metrics.increment_succeeded();
+        metrics.increment_failed();
+        metrics.increment_priority_counter(&TaskPriority::High);
+        metrics.increment_priority_counter(&TaskPriority::Low);
+        
+        let json = metrics.get_metrics_json().await;
+        
+        assert_eq!(json["tasks_processed"], 2);
+        assert_eq!(json["tasks_succeeded"], 1);
+        assert_eq!(json["tasks_failed"], 1);
+        assert_eq!(json["success_rate"], 50.0);
+        assert_eq!(json["high_tasks_processed"], 1);
+        assert_eq!(json["low_tasks_processed"], 1);
+        // Every priority is reported, counted or not
+        for priority in TaskPriority::ALL {
+            assert!(json[format!("{}_tasks_processed", priority.as_str())].is_u64(), "{:?}", priority);
+        }
+        assert_eq!(json["critical_tasks_processed"], 0);
+        assert_eq!(json["healthy"], false);
+    }
+}